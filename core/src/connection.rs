@@ -26,7 +26,7 @@ mod substream;
 pub(crate) mod manager;
 pub(crate) mod pool;
 
-pub use error::{ConnectionError, PendingConnectionError};
+pub use error::{CloseReason, ConnectionError, PendingConnectionError};
 pub use handler::{ConnectionHandler, ConnectionHandlerEvent, IntoConnectionHandler};
 pub use listeners::{ListenerId, ListenersStream, ListenersEvent};
 pub use manager::ConnectionId;
@@ -133,6 +133,16 @@ impl ConnectedPoint {
         }
     }
 
+    /// Returns the address of the remote stored in this struct.
+    ///
+    /// For `Dialer`, this returns `address`. For `Listener`, this returns `send_back_addr`.
+    pub fn get_remote_address(&self) -> &Multiaddr {
+        match self {
+            ConnectedPoint::Dialer { address } => address,
+            ConnectedPoint::Listener { send_back_addr, .. } => send_back_addr,
+        }
+    }
+
     /// Modifies the address of the remote stored in this struct.
     ///
     /// For `Dialer`, this modifies `address`. For `Listener`, this modifies `send_back_addr`.
@@ -169,6 +179,11 @@ pub trait ConnectionInfo {
 
     /// Returns the identity of the node we are connected to on this connection.
     fn peer_id(&self) -> &Self::PeerId;
+
+    /// Returns the name of the security protocol negotiated on this connection, if known.
+    fn security_protocol(&self) -> Option<&[u8]> {
+        None
+    }
 }
 
 impl ConnectionInfo for PeerId {
@@ -179,6 +194,40 @@ impl ConnectionInfo for PeerId {
     }
 }
 
+/// Information about an authenticated connection, comprising the identity
+/// of the remote and the name of the negotiated security protocol.
+///
+/// Obtained through the [`WithSecurityInfo`](crate::upgrade::WithSecurityInfo) upgrade.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecurityInfo {
+    peer_id: PeerId,
+    protocol: Vec<u8>,
+}
+
+impl SecurityInfo {
+    /// Creates a new `SecurityInfo` for a remote authenticated with the given protocol.
+    pub fn new(peer_id: PeerId, protocol: Vec<u8>) -> Self {
+        SecurityInfo { peer_id, protocol }
+    }
+
+    /// Returns the name of the negotiated security protocol, e.g. `/noise`.
+    pub fn security_protocol(&self) -> &[u8] {
+        &self.protocol
+    }
+}
+
+impl ConnectionInfo for SecurityInfo {
+    type PeerId = PeerId;
+
+    fn peer_id(&self) -> &PeerId {
+        &self.peer_id
+    }
+
+    fn security_protocol(&self) -> Option<&[u8]> {
+        Some(&self.protocol)
+    }
+}
+
 /// Event generated by a [`Connection`].
 #[derive(Debug, Clone)]
 pub enum Event<T> {
@@ -19,6 +19,7 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::connection::ConnectionLimit;
+use crate::Multiaddr;
 use crate::transport::TransportError;
 use std::{io, fmt};
 
@@ -31,6 +32,9 @@ pub enum ConnectionError<THandlerErr> {
 
     /// The connection handler produced an error.
     Handler(THandlerErr),
+
+    /// The connection was closed by the local node.
+    Closed(CloseReason),
 }
 
 impl<THandlerErr> fmt::Display
@@ -44,6 +48,8 @@ where
                 write!(f, "Connection error: I/O error: {}", err),
             ConnectionError::Handler(err) =>
                 write!(f, "Connection error: Handler error: {}", err),
+            ConnectionError::Closed(reason) =>
+                write!(f, "Connection error: Closed locally: {}", reason),
         }
     }
 }
@@ -57,6 +63,36 @@ where
         match self {
             ConnectionError::IO(err) => Some(err),
             ConnectionError::Handler(err) => Some(err),
+            ConnectionError::Closed(_) => None,
+        }
+    }
+}
+
+/// The reason for the local node closing a connection.
+///
+/// The reason is only reported locally. The remote merely observes the
+/// connection being closed, since none of the stream multiplexers
+/// currently supported is able to transmit a reason to the remote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CloseReason {
+    /// The connection was closed on request, without a specific reason.
+    Requested,
+    /// The connection was closed on request, for an application-provided reason.
+    Application(String),
+    /// The remote peer has been banned.
+    Banned,
+    /// The remote peer or its address is no longer permitted, e.g. because
+    /// the peer is not on the allow list or its IP address has been blocked.
+    Denied,
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CloseReason::Requested => write!(f, "Requested"),
+            CloseReason::Application(reason) => write!(f, "{}", reason),
+            CloseReason::Banned => write!(f, "Peer banned"),
+            CloseReason::Denied => write!(f, "Peer or address denied"),
         }
     }
 }
@@ -67,6 +103,10 @@ pub enum PendingConnectionError<TTransErr> {
     /// An error occurred while negotiating the transport protocol(s).
     Transport(TransportError<TTransErr>),
 
+    /// Dialing failed on all of the addresses that were dialed concurrently,
+    /// with the error encountered for each address.
+    ConcurrentDial(Vec<(Multiaddr, TransportError<TTransErr>)>),
+
     /// The peer identity obtained on the connection did not
     /// match the one that was expected or is otherwise invalid.
     InvalidPeerId,
@@ -78,6 +118,9 @@ pub enum PendingConnectionError<TTransErr> {
     /// An I/O error occurred on the connection.
     // TODO: Eventually this should also be a custom error?
     IO(io::Error),
+
+    /// The connection was not established within the configured timeout.
+    Timeout,
 }
 
 impl<TTransErr> fmt::Display
@@ -91,10 +134,19 @@ where
                 write!(f, "Pending connection: I/O error: {}", err),
             PendingConnectionError::Transport(err) =>
                 write!(f, "Pending connection: Transport error: {}", err),
+            PendingConnectionError::ConcurrentDial(errs) => {
+                write!(f, "Pending connection: Failed to dial all addresses:")?;
+                for (addr, err) in errs {
+                    write!(f, " {}: {};", addr, err)?;
+                }
+                Ok(())
+            }
             PendingConnectionError::InvalidPeerId =>
                 write!(f, "Pending connection: Invalid peer ID."),
             PendingConnectionError::ConnectionLimit(l) =>
                 write!(f, "Connection error: Connection limit: {}.", l),
+            PendingConnectionError::Timeout =>
+                write!(f, "Pending connection: Timeout."),
         }
     }
 }
@@ -108,8 +160,10 @@ where
         match self {
             PendingConnectionError::IO(err) => Some(err),
             PendingConnectionError::Transport(err) => Some(err),
+            PendingConnectionError::ConcurrentDial(_) => None,
             PendingConnectionError::InvalidPeerId => None,
             PendingConnectionError::ConnectionLimit(..) => None,
+            PendingConnectionError::Timeout => None,
         }
     }
 }
@@ -219,6 +219,11 @@ where
         }
     }
 
+    /// Removes all listeners.
+    pub fn clear(&mut self) {
+        self.listeners.clear();
+    }
+
     /// Returns the transport passed when building this object.
     pub fn transport(&self) -> &TTrans {
         &self.transport
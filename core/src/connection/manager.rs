@@ -21,11 +21,13 @@
 use crate::{
     Executor,
     muxing::StreamMuxer,
+    task_names,
 };
 use fnv::FnvHashMap;
+use tracing::Instrument;
 use futures::{
     prelude::*,
-    channel::mpsc,
+    channel::{mpsc, oneshot},
     stream::FuturesUnordered
 };
 use std::{
@@ -113,6 +115,10 @@ pub struct Manager<I, O, H, E, HE, C> {
     /// polled on the current thread when the manager is polled for new events.
     local_spawns: FuturesUnordered<Pin<Box<dyn Future<Output = ()> + Send>>>,
 
+    /// Resolves for each background task once it has terminated, including
+    /// the tasks no longer in `tasks`, e.g. those of closed connections.
+    running: FuturesUnordered<oneshot::Receiver<()>>,
+
     /// Sender distributed to managed tasks for reporting events back
     /// to the manager.
     events_tx: mpsc::Sender<task::Event<O, H, E, HE, C>>,
@@ -245,6 +251,7 @@ impl<I, O, H, TE, HE, C> Manager<I, O, H, TE, HE, C> {
             task_command_buffer_size: config.task_command_buffer_size,
             executor: config.executor,
             local_spawns: FuturesUnordered::new(),
+            running: FuturesUnordered::new(),
             events_tx: tx,
             events_rx: rx
         }
@@ -254,7 +261,7 @@ impl<I, O, H, TE, HE, C> Manager<I, O, H, TE, HE, C> {
     ///
     /// This method spawns a task dedicated to resolving this future and
     /// processing the node's events.
-    pub fn add_pending<F, M>(&mut self, future: F, handler: H) -> ConnectionId
+    pub fn add_pending<F, M>(&mut self, future: F, handler: H, span: tracing::Span) -> ConnectionId
     where
         I: Send + 'static,
         O: Send + 'static,
@@ -279,18 +286,15 @@ impl<I, O, H, TE, HE, C> Manager<I, O, H, TE, HE, C> {
         let (tx, rx) = mpsc::channel(self.task_command_buffer_size);
         self.tasks.insert(task_id, TaskInfo { sender: tx, state: TaskState::Pending });
 
-        let task = Box::pin(Task::pending(task_id, self.events_tx.clone(), rx, future, handler));
-        if let Some(executor) = &mut self.executor {
-            executor.exec(task);
-        } else {
-            self.local_spawns.push(task);
-        }
+        let task = Task::pending(task_id, self.events_tx.clone(), rx, future, handler);
+        self.spawn(task_names::PENDING_CONNECTION, task_id, span, task);
 
         ConnectionId(task_id)
     }
 
     /// Adds an existing connection to the manager.
-    pub fn add<M>(&mut self, conn: Connection<M, H::Handler>, info: Connected<C>) -> ConnectionId
+    pub fn add<M>(&mut self, conn: Connection<M, H::Handler>, info: Connected<C>, span: tracing::Span)
+        -> ConnectionId
     where
         H: IntoConnectionHandler<C> + Send + 'static,
         H::Handler: ConnectionHandler<
@@ -316,16 +320,55 @@ impl<I, O, H, TE, HE, C> Manager<I, O, H, TE, HE, C> {
             sender: tx, state: TaskState::Established(info)
         });
 
-        let task: Pin<Box<Task<Pin<Box<future::Pending<_>>>, _, _, _, _, _, _>>> =
-            Box::pin(Task::established(task_id, self.events_tx.clone(), rx, conn));
+        let task: Task<Pin<Box<future::Pending<_>>>, _, _, _, _, _, _> =
+            Task::established(task_id, self.events_tx.clone(), rx, conn);
+        self.spawn(task_names::CONNECTION, task_id, span, task);
 
+        ConnectionId(task_id)
+    }
+
+    /// Spawns the background task of a connection, either onto the
+    /// `executor`, as a task with the given name, or into `local_spawns`.
+    ///
+    /// The task is instrumented with the given span of the connection,
+    /// whose `id` field is set to the ID of the task.
+    fn spawn(
+        &mut self,
+        name: &'static str,
+        id: TaskId,
+        span: tracing::Span,
+        task: impl Future<Output = ()> + Send + 'static
+    ) {
+        span.record("id", &id.0);
+        // The sender is dropped once the task has terminated (or is
+        // dropped itself), which resolves the receiver in `running`.
+        let (terminated_tx, terminated_rx) = oneshot::channel();
+        self.running.push(terminated_rx);
+        let task = Box::pin(async move {
+            task.await;
+            drop(terminated_tx);
+        }.instrument(span));
         if let Some(executor) = &mut self.executor {
-            executor.exec(task);
+            executor.exec_named(name, task);
         } else {
             self.local_spawns.push(task);
         }
+    }
 
-        ConnectionId(task_id)
+    /// Polls for the termination of the background tasks of all connections,
+    /// including those that have been closed or aborted but may still be
+    /// shutting down gracefully.
+    ///
+    /// Returns `Poll::Ready` once no background task is running.
+    pub fn poll_terminated(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        while let Poll::Ready(Some(_)) = Stream::poll_next(Pin::new(&mut self.local_spawns), cx) {}
+        loop {
+            match Stream::poll_next(Pin::new(&mut self.running), cx) {
+                Poll::Ready(Some(_)) => {}
+                Poll::Ready(None) => return Poll::Ready(()),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
     }
 
     /// Gets an entry for a managed connection, if it exists.
@@ -350,6 +393,9 @@ impl<I, O, H, TE, HE, C> Manager<I, O, H, TE, HE, C> {
         // Advance the content of `local_spawns`.
         while let Poll::Ready(Some(_)) = Stream::poll_next(Pin::new(&mut self.local_spawns), cx) {}
 
+        // Forget about the tasks that have terminated.
+        while let Poll::Ready(Some(_)) = Stream::poll_next(Pin::new(&mut self.running), cx) {}
+
         // Poll for the first event for which the manager still has a registered task, if any.
         let event = loop {
             match Stream::poll_next(Pin::new(&mut self.events_rx), cx) {
@@ -20,6 +20,7 @@
 
 use crate::{
     ConnectedPoint,
+    Multiaddr,
     PeerId,
     connection::{
         self,
@@ -242,6 +243,10 @@ where
     {
         let endpoint = info.to_connected_point();
         self.limits.check_incoming(|| self.iter_pending_incoming().count())?;
+        let future = future.map_ok({
+            let endpoint = endpoint.clone();
+            move |output| (endpoint, output)
+        });
         Ok(self.add_pending(future, handler, endpoint, None))
     }
 
@@ -276,6 +281,47 @@ where
         TMuxer: StreamMuxer + Send + Sync + 'static,
         TMuxer::OutboundSubstream: Send + 'static,
         TPeerId: Clone + Send + 'static,
+    {
+        let address = info.address.clone();
+        self.add_outgoing_concurrent(future.map_ok(move |output| (address, output)), handler, info)
+    }
+
+    /// Adds a pending outgoing connection to the pool in the form of a `Future`
+    /// that establishes and negotiates the connection via one of possibly
+    /// several addresses, e.g. by dialing them concurrently, and resolves to
+    /// the address of the established connection.
+    ///
+    /// Until the connection is established, the address in `info` is
+    /// considered to be the address of the pending connection.
+    ///
+    /// Returns an error if the limit of pending outgoing connections
+    /// has been reached.
+    pub fn add_outgoing_concurrent<TFut, TMuxer>(
+        &mut self,
+        future: TFut,
+        handler: THandler,
+        info: OutgoingInfo<'_, TPeerId>,
+    ) -> Result<ConnectionId, ConnectionLimit>
+    where
+        TConnInfo: ConnectionInfo<PeerId = TPeerId> + Send + 'static,
+        TFut: Future<
+            Output = Result<(Multiaddr, (TConnInfo, TMuxer)), PendingConnectionError<TTransErr>>
+        > + Send + 'static,
+        THandler: IntoConnectionHandler<TConnInfo> + Send + 'static,
+        THandler::Handler: ConnectionHandler<
+            Substream = Substream<TMuxer>,
+            InEvent = TInEvent,
+            OutEvent = TOutEvent,
+            Error = THandlerErr
+        > + Send + 'static,
+        <THandler::Handler as ConnectionHandler>::OutboundOpenInfo: Send + 'static,
+        TTransErr: error::Error + Send + 'static,
+        THandlerErr: error::Error + Send + 'static,
+        TInEvent: Send + 'static,
+        TOutEvent: Send + 'static,
+        TMuxer: StreamMuxer + Send + Sync + 'static,
+        TMuxer::OutboundSubstream: Send + 'static,
+        TPeerId: Clone + Send + 'static,
     {
         self.limits.check_outgoing(|| self.iter_pending_outgoing().count())?;
 
@@ -284,11 +330,15 @@ where
         }
 
         let endpoint = info.to_connected_point();
+        let future = future.map_ok(|(address, output)| (ConnectedPoint::Dialer { address }, output));
         Ok(self.add_pending(future, handler, endpoint, info.peer_id.cloned()))
     }
 
     /// Adds a pending connection to the pool in the form of a
-    /// `Future` that establishes and negotiates the connection.
+    /// `Future` that establishes and negotiates the connection,
+    /// resolving to the endpoint of the established connection.
+    ///
+    /// The given `endpoint` is the endpoint of the pending connection.
     fn add_pending<TFut, TMuxer>(
         &mut self,
         future: TFut,
@@ -299,7 +349,7 @@ where
     where
         TConnInfo: ConnectionInfo<PeerId = TPeerId> + Send + 'static,
         TFut: Future<
-            Output = Result<(TConnInfo, TMuxer), PendingConnectionError<TTransErr>>
+            Output = Result<(ConnectedPoint, (TConnInfo, TMuxer)), PendingConnectionError<TTransErr>>
         > + Send + 'static,
         THandler: IntoConnectionHandler<TConnInfo> + Send + 'static,
         THandler::Handler: ConnectionHandler<
@@ -322,10 +372,9 @@ where
         // by the background task, which happens when this future resolves to an
         // "established" connection.
         let future = future.and_then({
-            let endpoint = endpoint.clone();
             let expected_peer = peer.clone();
             let local_id = self.local_id.clone();
-            move |(info, muxer)| {
+            move |(endpoint, (info, muxer))| {
                 if let Some(peer) = expected_peer {
                     if &peer != info.peer_id() {
                         return future::err(PendingConnectionError::InvalidPeerId)
@@ -341,7 +390,7 @@ where
             }
         });
 
-        let id = self.manager.add_pending(future, handler);
+        let id = self.manager.add_pending(future, handler, connection_span(&endpoint));
         self.pending.insert(id, (endpoint, peer));
         id
     }
@@ -372,13 +421,9 @@ where
         TPeerId: Clone,
         TConnInfo: ConnectionInfo<PeerId = TPeerId>,
     {
-        if let Some(limit) = self.limits.max_established_per_peer {
-            let current = self.num_peer_established(i.peer_id());
-            if limit >= current {
-                return Err(ConnectionLimit { limit, current })
-            }
-        }
-        let id = self.manager.add(c, i.clone());
+        self.limits.check_established(|| self.num_established())?;
+        self.limits.check_established_per_peer(|| self.num_peer_established(i.peer_id()))?;
+        let id = self.manager.add(c, i.clone(), connection_span(&i.endpoint));
         self.established.entry(i.peer_id().clone()).or_default().insert(id, i.endpoint);
         Ok(id)
     }
@@ -466,6 +511,32 @@ where
         }
     }
 
+    /// Closes all established connections and aborts all pending
+    /// connections, without emitting events for them.
+    pub fn disconnect_all(&mut self) {
+        for (_, conns) in self.established.drain() {
+            for id in conns.keys() {
+                if let Some(manager::Entry::Established(e)) = self.manager.entry(*id) {
+                    e.close();
+                }
+            }
+        }
+
+        for (id, _) in self.pending.drain() {
+            if let Some(manager::Entry::Pending(e)) = self.manager.entry(id) {
+                e.abort();
+            }
+        }
+    }
+
+    /// Polls for the termination of the background tasks of all
+    /// connections, including closed and aborted ones.
+    ///
+    /// See [`Manager::poll_terminated`].
+    pub fn poll_terminated(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.manager.poll_terminated(cx)
+    }
+
     /// Counts the number of established connections in the pool.
     pub fn num_established(&self) -> usize {
         self.established.iter().fold(0, |n, (_, conns)| n + conns.len())
@@ -604,12 +675,16 @@ where
                 },
                 manager::Event::ConnectionEstablished { entry } => {
                     let id = entry.id();
-                    if let Some((endpoint, peer)) = self.pending.remove(&id) {
-                        // Check connection limit.
+                    if let Some((_, peer)) = self.pending.remove(&id) {
+                        // Check connection limits.
                         let established = &self.established;
-                        let current = || established.get(entry.connected().peer_id())
+                        let current = || established.values().map(|conns| conns.len()).sum();
+                        let current_per_peer = || established.get(entry.connected().peer_id())
                                             .map_or(0, |conns| conns.len());
-                        if let Err(e) = self.limits.check_established(current) {
+                        let limits = &self.limits;
+                        let check = limits.check_established(current)
+                            .and_then(|()| limits.check_established_per_peer(current_per_peer));
+                        if let Err(e) = check {
                             let connected = entry.close();
                             return Poll::Ready(PoolEvent::PendingConnectionError {
                                 id,
@@ -636,7 +711,7 @@ where
                         let conns = self.established.entry(peer).or_default();
                         let num_established = NonZeroU32::new(u32::try_from(conns.len() + 1).unwrap())
                             .expect("n + 1 is always non-zero; qed");
-                        conns.insert(id, endpoint);
+                        conns.insert(id, entry.connected().endpoint.clone());
                         match self.get(id) {
                             Some(PoolConnection::Established(connection)) =>
                                 return Poll::Ready(PoolEvent::ConnectionEstablished {
@@ -879,12 +954,20 @@ where
 pub struct PoolLimits {
     pub max_outgoing: Option<usize>,
     pub max_incoming: Option<usize>,
+    pub max_established: Option<usize>,
     pub max_established_per_peer: Option<usize>,
     pub max_outgoing_per_peer: Option<usize>,
 }
 
 impl PoolLimits {
     fn check_established<F>(&self, current: F) -> Result<(), ConnectionLimit>
+    where
+        F: FnOnce() -> usize
+    {
+        Self::check(current, self.max_established)
+    }
+
+    fn check_established_per_peer<F>(&self, current: F) -> Result<(), ConnectionLimit>
     where
         F: FnOnce() -> usize
     {
@@ -925,3 +1008,15 @@ impl PoolLimits {
         Ok(())
     }
 }
+
+/// Creates the span of the background task of a connection, covering its
+/// establishment, including the upgrades of the transport, and its
+/// substreams. The `id` field is set once the task is spawned.
+fn connection_span(endpoint: &ConnectedPoint) -> tracing::Span {
+    tracing::debug_span!(
+        "connection",
+        id = tracing::field::Empty,
+        address = %endpoint.get_remote_address(),
+        dialer = endpoint.is_dialer(),
+    )
+}
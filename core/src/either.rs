@@ -477,4 +477,11 @@ where
             },
         }
     }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        match self {
+            EitherTransport::Left(a) => a.address_translation(listen, observed),
+            EitherTransport::Right(b) => b.address_translation(listen, observed),
+        }
+    }
 }
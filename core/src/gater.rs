@@ -0,0 +1,83 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::{ConnectedPoint, Multiaddr, PeerId};
+use std::{error, fmt};
+
+/// Decides at each stage of the establishment of a connection whether it
+/// may proceed, allowing to implement firewall-like policies.
+///
+/// Every method returns `true` to allow the connection and `false` to deny
+/// it. The default implementations allow all connections.
+///
+/// The stages are, in order:
+///
+///   1. Outgoing connections: [`intercept_peer_dial`](ConnectionGater::intercept_peer_dial)
+///      before dialing a peer and [`intercept_addr_dial`](ConnectionGater::intercept_addr_dial)
+///      before dialing each of its addresses.
+///   2. Incoming connections: [`intercept_accept`](ConnectionGater::intercept_accept)
+///      before the connection is upgraded.
+///   3. [`intercept_secured`](ConnectionGater::intercept_secured) once the
+///      remote is authenticated, before a multiplexer is negotiated.
+///   4. [`intercept_upgraded`](ConnectionGater::intercept_upgraded) once the
+///      connection is fully upgraded, before it is reported as established.
+///
+/// The `Swarm` of `libp2p-swarm` consults a gater configured on its builder
+/// at stages 1, 2 and 4. Stage 3 is consulted by transports upgraded with
+/// [`Builder::gate`](crate::transport::upgrade::Builder::gate), which should
+/// be given the same gater.
+pub trait ConnectionGater: Send + Sync + 'static {
+    /// Called before dialing a peer.
+    fn intercept_peer_dial(&self, _peer_id: &PeerId) -> bool {
+        true
+    }
+
+    /// Called before dialing an address, of the given peer if known.
+    fn intercept_addr_dial(&self, _peer_id: Option<&PeerId>, _addr: &Multiaddr) -> bool {
+        true
+    }
+
+    /// Called for an incoming connection on a listener, before it is upgraded.
+    fn intercept_accept(&self, _local_addr: &Multiaddr, _send_back_addr: &Multiaddr) -> bool {
+        true
+    }
+
+    /// Called once the security handshake revealed the identity of the remote.
+    fn intercept_secured(&self, _peer_id: &PeerId, _endpoint: &ConnectedPoint) -> bool {
+        true
+    }
+
+    /// Called once the connection is fully upgraded.
+    fn intercept_upgraded(&self, _peer_id: &PeerId, _endpoint: &ConnectedPoint) -> bool {
+        true
+    }
+}
+
+/// Error of a connection denied by a [`ConnectionGater`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ConnectionDenied;
+
+impl fmt::Display for ConnectionDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Connection denied by the connection gater")
+    }
+}
+
+impl error::Error for ConnectionDenied {}
@@ -20,6 +20,8 @@
 
 //! A node's network identity keys.
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod ecdsa;
 pub mod ed25519;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod rsa;
@@ -30,6 +32,8 @@ pub mod error;
 
 use self::error::*;
 use crate::{PeerId, keys_proto};
+use futures::future::{self, BoxFuture};
+use zeroize::Zeroize;
 
 /// Identity keypair of a node.
 ///
@@ -48,6 +52,22 @@ use crate::{PeerId, keys_proto};
 /// let keypair = Keypair::rsa_from_pkcs8(&mut bytes);
 /// ```
 ///
+/// # Example: Generating keys with `libp2p-keygen`
+///
+/// The `libp2p-keygen` tool generates keys of all types in the protobuf
+/// encoding of [`Keypair::to_protobuf_encoding`], printing their peer IDs:
+///
+/// ```text
+/// libp2p-keygen generate ed25519 --format protobuf --out private.key
+/// ```
+///
+/// Loading the keys:
+///
+/// ```text
+/// let bytes = std::fs::read("private.key").unwrap();
+/// let keypair = Keypair::from_protobuf_encoding(&bytes);
+/// ```
+///
 #[derive(Clone)]
 pub enum Keypair {
     /// An Ed25519 keypair.
@@ -57,7 +77,10 @@ pub enum Keypair {
     Rsa(rsa::Keypair),
     /// A Secp256k1 keypair.
     #[cfg(feature = "secp256k1")]
-    Secp256k1(secp256k1::Keypair)
+    Secp256k1(secp256k1::Keypair),
+    /// An ECDSA keypair on the P-256 curve.
+    #[cfg(not(target_arch = "wasm32"))]
+    Ecdsa(ecdsa::Keypair)
 }
 
 impl Keypair {
@@ -72,6 +95,12 @@ impl Keypair {
         Keypair::Secp256k1(secp256k1::Keypair::generate())
     }
 
+    /// Generate a new ECDSA keypair on the P-256 curve.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn generate_ecdsa() -> Keypair {
+        Keypair::Ecdsa(ecdsa::Keypair::generate())
+    }
+
     /// Decode an keypair from a DER-encoded secret key in PKCS#8 PrivateKeyInfo
     /// format (i.e. unencrypted) as defined in [RFC5208].
     ///
@@ -100,7 +129,9 @@ impl Keypair {
             #[cfg(not(target_arch = "wasm32"))]
             Rsa(ref pair) => pair.sign(msg),
             #[cfg(feature = "secp256k1")]
-            Secp256k1(ref pair) => pair.secret().sign(msg)
+            Secp256k1(ref pair) => pair.secret().sign(msg),
+            #[cfg(not(target_arch = "wasm32"))]
+            Ecdsa(ref pair) => pair.sign(msg)
         }
     }
 
@@ -113,8 +144,114 @@ impl Keypair {
             Rsa(pair) => PublicKey::Rsa(pair.public()),
             #[cfg(feature = "secp256k1")]
             Secp256k1(pair) => PublicKey::Secp256k1(pair.public().clone()),
+            #[cfg(not(target_arch = "wasm32"))]
+            Ecdsa(pair) => PublicKey::Ecdsa(pair.public()),
         }
     }
+
+    /// Encode the keypair into a protobuf structure for storage, in the
+    /// format used by other libp2p implementations.
+    ///
+    /// Encoding RSA keypairs is not supported.
+    pub fn to_protobuf_encoding(&self) -> Result<Vec<u8>, EncodingError> {
+        use prost::Message;
+
+        let private_key = match self {
+            Keypair::Ed25519(pair) =>
+                keys_proto::PrivateKey {
+                    r#type: keys_proto::KeyType::Ed25519 as i32,
+                    data: pair.encode().to_vec()
+                },
+            #[cfg(not(target_arch = "wasm32"))]
+            Keypair::Rsa(_) =>
+                return Err(EncodingError::new("encoding RSA keypairs is not supported")),
+            #[cfg(feature = "secp256k1")]
+            Keypair::Secp256k1(pair) =>
+                keys_proto::PrivateKey {
+                    r#type: keys_proto::KeyType::Secp256k1 as i32,
+                    data: pair.secret().to_bytes().to_vec()
+                },
+            #[cfg(not(target_arch = "wasm32"))]
+            Keypair::Ecdsa(pair) =>
+                keys_proto::PrivateKey {
+                    r#type: keys_proto::KeyType::Ecdsa as i32,
+                    data: pair.encode_sec1_der()
+                }
+        };
+
+        let mut buf = Vec::with_capacity(private_key.encoded_len());
+        private_key.encode(&mut buf).expect("Vec<u8> provides capacity as needed");
+        let mut data = private_key.data;
+        data.zeroize();
+        Ok(buf)
+    }
+
+    /// Decode a keypair from a protobuf structure, e.g. read from storage
+    /// or produced by another libp2p implementation.
+    ///
+    /// RSA keys are expected as DER-encoded PKCS#1 RSAPrivateKey structures.
+    pub fn from_protobuf_encoding(bytes: &[u8]) -> Result<Keypair, DecodingError> {
+        use prost::Message;
+
+        let mut private_key = keys_proto::PrivateKey::decode(bytes)
+            .map_err(|e| DecodingError::new("Protobuf").source(e))?;
+
+        let key_type = keys_proto::KeyType::from_i32(private_key.r#type)
+            .ok_or_else(|| DecodingError::new(format!("unknown key type: {}", private_key.r#type)))?;
+
+        let keypair = match key_type {
+            keys_proto::KeyType::Ed25519 => {
+                ed25519::Keypair::decode(&mut private_key.data).map(Keypair::Ed25519)
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            keys_proto::KeyType::Rsa => {
+                rsa::Keypair::from_pkcs1(&mut private_key.data).map(Keypair::Rsa)
+            }
+            #[cfg(feature = "secp256k1")]
+            keys_proto::KeyType::Secp256k1 => {
+                secp256k1::SecretKey::from_bytes(&mut private_key.data)
+                    .map(|sk| Keypair::Secp256k1(sk.into()))
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            keys_proto::KeyType::Ecdsa => {
+                ecdsa::Keypair::from_sec1_der(&mut private_key.data).map(Keypair::Ecdsa)
+            }
+            #[allow(unreachable_patterns)] // Due to conditional compilation.
+            _ => {
+                log::debug!("support for {:?} was disabled at compile-time", key_type);
+                Err(DecodingError::new("Unsupported"))
+            }
+        };
+
+        private_key.data.zeroize();
+        keypair
+    }
+}
+
+/// Signs messages with a node's identity key.
+///
+/// Security protocols authenticate the local node during their handshake by
+/// signing with its identity key. Implementing this trait allows the identity
+/// key to be held outside of the process, e.g. in a hardware security module
+/// or a remote key management service. [`Keypair`] implements it by signing
+/// with the local private key.
+pub trait Signer: Send + Sync {
+    /// Get the public key of the identity key.
+    fn public(&self) -> PublicKey;
+
+    /// Sign a message with the identity key, producing a signature that can
+    /// be verified with [`PublicKey::verify`].
+    fn sign(&self, msg: &[u8]) -> BoxFuture<'static, Result<Vec<u8>, SigningError>>;
+}
+
+impl Signer for Keypair {
+    fn public(&self) -> PublicKey {
+        Keypair::public(self)
+    }
+
+    fn sign(&self, msg: &[u8]) -> BoxFuture<'static, Result<Vec<u8>, SigningError>> {
+        Box::pin(future::ready(Keypair::sign(self, msg)))
+    }
 }
 
 /// The public key of a node's identity keypair.
@@ -127,7 +264,10 @@ pub enum PublicKey {
     Rsa(rsa::PublicKey),
     #[cfg(feature = "secp256k1")]
     /// A public Secp256k1 key.
-    Secp256k1(secp256k1::PublicKey)
+    Secp256k1(secp256k1::PublicKey),
+    #[cfg(not(target_arch = "wasm32"))]
+    /// A public ECDSA key on the P-256 curve.
+    Ecdsa(ecdsa::PublicKey)
 }
 
 impl PublicKey {
@@ -142,7 +282,9 @@ impl PublicKey {
             #[cfg(not(target_arch = "wasm32"))]
             Rsa(pk) => pk.verify(msg, sig),
             #[cfg(feature = "secp256k1")]
-            Secp256k1(pk) => pk.verify(msg, sig)
+            Secp256k1(pk) => pk.verify(msg, sig),
+            #[cfg(not(target_arch = "wasm32"))]
+            Ecdsa(pk) => pk.verify(msg, sig)
         }
     }
 
@@ -168,6 +310,12 @@ impl PublicKey {
                 keys_proto::PublicKey {
                     r#type: keys_proto::KeyType::Secp256k1 as i32,
                     data: key.encode().to_vec()
+                },
+            #[cfg(not(target_arch = "wasm32"))]
+            PublicKey::Ecdsa(key) =>
+                keys_proto::PublicKey {
+                    r#type: keys_proto::KeyType::Ecdsa as i32,
+                    data: key.encode_der()
                 }
         };
 
@@ -210,6 +358,15 @@ impl PublicKey {
                 log::debug!("support for secp256k1 was disabled at compile-time");
                 Err("Unsupported".to_string().into())
             }
+            #[cfg(not(target_arch = "wasm32"))]
+            keys_proto::KeyType::Ecdsa => {
+                ecdsa::PublicKey::decode_der(&pubkey.data).map(PublicKey::Ecdsa)
+            }
+            #[cfg(target_arch = "wasm32")]
+            keys_proto::KeyType::Ecdsa => {
+                log::debug!("support for ECDSA was disabled at compile-time");
+                Err(DecodingError::new("Unsupported"))
+            }
         }
     }
 
@@ -219,3 +376,60 @@ impl PublicKey {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(keypair: Keypair) {
+        let encoded = keypair.to_protobuf_encoding().unwrap();
+        let decoded = Keypair::from_protobuf_encoding(&encoded).unwrap();
+        assert_eq!(keypair.public(), decoded.public());
+
+        let sig = decoded.sign(b"hello").unwrap();
+        assert!(keypair.public().verify(b"hello", &sig));
+
+        let public = keypair.public();
+        assert_eq!(PublicKey::from_protobuf_encoding(&public.clone().into_protobuf_encoding()).unwrap(), public);
+    }
+
+    #[test]
+    fn keypair_protobuf_roundtrip() {
+        roundtrip(Keypair::generate_ed25519());
+        #[cfg(feature = "secp256k1")]
+        roundtrip(Keypair::generate_secp256k1());
+        #[cfg(not(target_arch = "wasm32"))]
+        roundtrip(Keypair::generate_ecdsa());
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn rsa_keypair_from_protobuf() {
+        use asn1_der::{DerObject, FromDerObject};
+        use prost::Message;
+
+        let pkcs8 = include_bytes!("identity/test/rsa-2048.pk8");
+        let keypair = Keypair::rsa_from_pkcs8(&mut pkcs8.to_vec()).unwrap();
+        assert!(keypair.to_protobuf_encoding().is_err());
+
+        // The PKCS#8 PrivateKeyInfo wraps the PKCS#1 RSAPrivateKey.
+        let info: Vec<DerObject> = FromDerObject::deserialize(pkcs8.iter()).unwrap();
+        let pkcs1 = info.into_iter().nth(2).unwrap().value.data;
+        let private_key = keys_proto::PrivateKey {
+            r#type: keys_proto::KeyType::Rsa as i32,
+            data: pkcs1
+        };
+        let mut encoded = Vec::new();
+        private_key.encode(&mut encoded).unwrap();
+
+        let decoded = Keypair::from_protobuf_encoding(&encoded).unwrap();
+        assert_eq!(keypair.public(), decoded.public());
+    }
+
+    #[test]
+    fn invalid_keypair_protobuf() {
+        assert!(Keypair::from_protobuf_encoding(&[0xff, 0x00]).is_err());
+        let public_key = Keypair::generate_ed25519().public().into_protobuf_encoding();
+        assert!(Keypair::from_protobuf_encoding(&public_key).is_err());
+    }
+}
@@ -0,0 +1,251 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! ECDSA keys on the NIST P-256 curve.
+
+use asn1_der::{FromDerObject, DerObject, DerTag};
+use super::error::*;
+use ring::rand::SystemRandom;
+use ring::signature::{self, EcdsaKeyPair, ECDSA_P256_SHA256_ASN1, ECDSA_P256_SHA256_ASN1_SIGNING};
+use ring::signature::KeyPair;
+use std::{fmt, sync::Arc};
+use zeroize::Zeroize;
+
+/// Length of an encoded P-256 secret scalar.
+const SECRET_KEY_LEN: usize = 32;
+
+/// Length of an uncompressed P-256 public point.
+const PUBLIC_KEY_LEN: usize = 65;
+
+/// DER encoding of the named curve OID of P-256 (1.2.840.10045.3.1.7).
+const P256_OID: [u8; 10] = [0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+
+/// DER prefix of a SubjectPublicKeyInfo holding an uncompressed P-256 point,
+/// i.e. the `id-ecPublicKey` algorithm with the P-256 curve, followed by the
+/// header of the BIT STRING containing the point.
+const SPKI_PREFIX: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01,
+    0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00
+];
+
+/// An ECDSA keypair.
+#[derive(Clone)]
+pub struct Keypair {
+    pair: Arc<EcdsaKeyPair>,
+    secret: Arc<SecretScalar>
+}
+
+impl Keypair {
+    /// Generate a new ECDSA keypair.
+    pub fn generate() -> Keypair {
+        let rng = SystemRandom::new();
+        let mut pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+            .expect("P-256 key generation with the system RNG does not fail")
+            .as_ref()
+            .to_vec();
+        // The PKCS#8 document produced by ring wraps an ECPrivateKey.
+        let mut sec1 = DerObject::deserialize(pkcs8.iter())
+            .and_then(Vec::<DerObject>::from_der_object)
+            .ok()
+            .and_then(|o| o.into_iter().nth(2))
+            .map(|o| o.value.data)
+            .expect("ring produces valid PKCS#8 documents");
+        pkcs8.zeroize();
+        Keypair::from_sec1_der(&mut sec1)
+            .expect("ring produces valid P-256 keys")
+    }
+
+    /// Decode an ECDSA P-256 keypair from a DER-encoded ECPrivateKey
+    /// structure as defined in [RFC5915], zeroing the input on success.
+    ///
+    /// The structure must contain the public key.
+    ///
+    /// [RFC5915]: https://tools.ietf.org/html/rfc5915
+    pub fn from_sec1_der(der: &mut [u8]) -> Result<Keypair, DecodingError> {
+        let obj: Vec<DerObject> = FromDerObject::deserialize(der.iter())
+            .map_err(|e| DecodingError::new("ECDSA DER ECPrivateKey").source(e))?;
+
+        let mut secret = None;
+        let mut public = None;
+        for (i, o) in obj.into_iter().enumerate() {
+            match (i, o.tag) {
+                (0, DerTag::Integer) if o.value.data == [1] => {}
+                (1, DerTag::OctetString) if o.value.data.len() == SECRET_KEY_LEN => {
+                    let mut s = SecretScalar([0; SECRET_KEY_LEN]);
+                    s.0.copy_from_slice(&o.value.data);
+                    let mut data = o.value.data;
+                    data.zeroize();
+                    secret = Some(s)
+                }
+                (_, DerTag::xa0) if o.value.data == P256_OID => {}
+                (_, DerTag::xa1) => {
+                    // A BIT STRING without unused bits, holding the public point.
+                    match o.value.data.split_at(3.min(o.value.data.len())) {
+                        ([0x03, 0x42, 0x00], point) => public = Some(PublicKey::from_point(point)?),
+                        _ => return Err(DecodingError::new("ECDSA public key in ECPrivateKey"))
+                    }
+                }
+                _ => return Err(DecodingError::new("ECDSA DER ECPrivateKey"))
+            }
+        }
+
+        let secret = secret.ok_or_else(|| DecodingError::new("missing ECDSA secret key"))?;
+        let public = public.ok_or_else(|| DecodingError::new("missing ECDSA public key"))?;
+        let pair = EcdsaKeyPair::from_private_key_and_public_key(
+            &ECDSA_P256_SHA256_ASN1_SIGNING, &secret.0, &public.0
+        ).map_err(|e| DecodingError::new("ECDSA keypair").source(e))?;
+        der.zeroize();
+        Ok(Keypair { pair: Arc::new(pair), secret: Arc::new(secret) })
+    }
+
+    /// Encode the keypair into a DER-encoded ECPrivateKey structure as defined
+    /// in [RFC5915], including the curve parameters and the public key.
+    ///
+    /// [RFC5915]: https://tools.ietf.org/html/rfc5915
+    pub fn encode_sec1_der(&self) -> Vec<u8> {
+        let mut der = Vec::with_capacity(121);
+        der.extend_from_slice(&[0x30, 0x77, 0x02, 0x01, 0x01, 0x04, 0x20]);
+        der.extend_from_slice(&self.secret.0);
+        der.extend_from_slice(&[0xa0, 0x0a]);
+        der.extend_from_slice(&P256_OID);
+        der.extend_from_slice(&[0xa1, 0x44, 0x03, 0x42, 0x00]);
+        der.extend_from_slice(self.pair.public_key().as_ref());
+        der
+    }
+
+    /// Get the public key of this keypair.
+    pub fn public(&self) -> PublicKey {
+        PublicKey(self.pair.public_key().as_ref().to_vec())
+    }
+
+    /// Sign a message with this keypair, producing a DER-encoded ECDSA
+    /// signature over its SHA-256 digest.
+    pub fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SigningError> {
+        let rng = SystemRandom::new();
+        self.pair.sign(&rng, msg)
+            .map(|s| s.as_ref().to_vec())
+            .map_err(|e| SigningError::new("ECDSA").source(e))
+    }
+}
+
+impl fmt::Debug for Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keypair").field("public", &self.public()).finish()
+    }
+}
+
+/// The secret scalar of a keypair, zeroed on drop.
+struct SecretScalar([u8; SECRET_KEY_LEN]);
+
+impl Drop for SecretScalar {
+    fn drop(&mut self) {
+        self.0.zeroize()
+    }
+}
+
+/// An ECDSA P-256 public key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PublicKey(Vec<u8>);
+
+impl PublicKey {
+    /// Verify a DER-encoded ECDSA signature on a message using the public key.
+    pub fn verify(&self, msg: &[u8], sig: &[u8]) -> bool {
+        let key = signature::UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, &self.0);
+        key.verify(msg, sig).is_ok()
+    }
+
+    /// Encode the public key as an uncompressed point.
+    pub fn encode_uncompressed(&self) -> [u8; PUBLIC_KEY_LEN] {
+        let mut point = [0; PUBLIC_KEY_LEN];
+        point.copy_from_slice(&self.0);
+        point
+    }
+
+    /// Encode the public key into a DER-encoded SubjectPublicKeyInfo
+    /// structure as defined in [RFC5480].
+    ///
+    /// [RFC5480]: https://tools.ietf.org/html/rfc5480#section-2
+    pub fn encode_der(&self) -> Vec<u8> {
+        let mut der = Vec::with_capacity(SPKI_PREFIX.len() + PUBLIC_KEY_LEN);
+        der.extend_from_slice(&SPKI_PREFIX);
+        der.extend_from_slice(&self.0);
+        der
+    }
+
+    /// Decode a public key from the format produced by `encode_der`.
+    pub fn decode_der(k: &[u8]) -> Result<PublicKey, DecodingError> {
+        if !k.starts_with(&SPKI_PREFIX) {
+            return Err(DecodingError::new("ECDSA DER SubjectPublicKeyInfo"))
+        }
+        PublicKey::from_point(&k[SPKI_PREFIX.len() ..])
+    }
+
+    /// Create a public key from an uncompressed point.
+    fn from_point(point: &[u8]) -> Result<PublicKey, DecodingError> {
+        if point.len() != PUBLIC_KEY_LEN || point[0] != 0x04 {
+            return Err(DecodingError::new("ECDSA public key must be an uncompressed P-256 point"))
+        }
+        Ok(PublicKey(point.to_vec()))
+    }
+}
+
+impl fmt::Debug for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PublicKey(PKIX): ")?;
+        for byte in self.encode_der() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+
+    #[test]
+    fn ecdsa_sign_verify() {
+        fn prop(msg: Vec<u8>) -> bool {
+            let kp = Keypair::generate();
+            let sig = kp.sign(&msg).unwrap();
+            kp.public().verify(&msg, &sig) && !kp.public().verify(&[msg, vec![1]].concat(), &sig)
+        }
+        QuickCheck::new().tests(10).quickcheck(prop as fn(_) -> _);
+    }
+
+    #[test]
+    fn ecdsa_sec1_encode_decode() {
+        let kp1 = Keypair::generate();
+        let mut der = kp1.encode_sec1_der();
+        let kp2 = Keypair::from_sec1_der(&mut der).unwrap();
+        assert!(der.iter().all(|b| *b == 0));
+        assert_eq!(kp1.public(), kp2.public());
+        let sig = kp2.sign(b"hello").unwrap();
+        assert!(kp1.public().verify(b"hello", &sig));
+    }
+
+    #[test]
+    fn ecdsa_der_encode_decode() {
+        let pk = Keypair::generate().public();
+        assert_eq!(PublicKey::decode_der(&pk.encode_der()).unwrap(), pk);
+        assert!(PublicKey::decode_der(&pk.encode_der()[1 ..]).is_err());
+    }
+}
@@ -52,6 +52,26 @@ impl Error for DecodingError {
     }
 }
 
+/// An error during encoding of key material.
+#[derive(Debug)]
+pub struct EncodingError {
+    msg: String
+}
+
+impl EncodingError {
+    pub(crate) fn new<S: ToString>(msg: S) -> Self {
+        Self { msg: msg.to_string() }
+    }
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Key encoding error: {}", self.msg)
+    }
+}
+
+impl Error for EncodingError {}
+
 /// An error during signing of a message.
 #[derive(Debug)]
 pub struct SigningError {
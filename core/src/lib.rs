@@ -43,6 +43,8 @@ mod keys_proto {
 pub use multiaddr;
 pub type Negotiated<T> = multistream_select::Negotiated<T>;
 
+mod gater;
+mod observer;
 mod peer_id;
 mod translation;
 
@@ -61,8 +63,10 @@ pub use identity::PublicKey;
 pub use transport::Transport;
 pub use translation::address_translation;
 pub use upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo, UpgradeError, ProtocolName};
-pub use connection::{Connected, Endpoint, ConnectedPoint, ConnectionInfo};
+pub use connection::{Connected, Endpoint, ConnectedPoint, ConnectionInfo, SecurityInfo};
 pub use network::Network;
+pub use gater::{ConnectionDenied, ConnectionGater};
+pub use observer::{HandshakeObserver, HandshakeStep};
 
 use std::{future::Future, pin::Pin};
 
@@ -73,25 +77,63 @@ use std::{future::Future, pin::Pin};
 /// >           optional, and that `FuturesUnordered` (or a similar struct) will automatically
 /// >           be used as fallback by libp2p. The `Executor` trait should therefore only be
 /// >           about running `Future`s in the background.
+///
+/// An `Executor` can spawn the futures onto any runtime, e.g. `tokio`,
+/// `async-std` or `wasm-bindgen-futures`, and can name or instrument the
+/// spawned tasks by implementing [`Executor::exec_named`].
 pub trait Executor {
     /// Run the given future in the background until it ends.
     fn exec(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>);
+
+    /// Run the given future in the background until it ends, as a task with
+    /// the given name.
+    ///
+    /// libp2p spawns its background tasks through this method, with one of
+    /// the names in [`task_names`]. The default implementation ignores the
+    /// name and calls [`Executor::exec`].
+    fn exec_named(&self, name: &'static str, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        let _ = name;
+        self.exec(future)
+    }
+}
+
+/// The names of the background tasks spawned by libp2p, as passed to
+/// [`Executor::exec_named`].
+pub mod task_names {
+    /// The task of a connection that is being established, which becomes
+    /// the task of the established connection.
+    pub const PENDING_CONNECTION: &str = "libp2p-pending-connection";
+    /// The task of a connection that has been established outside of the
+    /// task of a pending connection.
+    pub const CONNECTION: &str = "libp2p-connection";
 }
 
 impl<'a, T: ?Sized + Executor> Executor for &'a T {
     fn exec(&self, f: Pin<Box<dyn Future<Output = ()> + Send>>) {
         T::exec(&**self, f)
     }
+
+    fn exec_named(&self, name: &'static str, f: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        T::exec_named(&**self, name, f)
+    }
 }
 
 impl<'a, T: ?Sized + Executor> Executor for &'a mut T {
     fn exec(&self, f: Pin<Box<dyn Future<Output = ()> + Send>>) {
         T::exec(&**self, f)
     }
+
+    fn exec_named(&self, name: &'static str, f: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        T::exec_named(&**self, name, f)
+    }
 }
 
 impl<T: ?Sized + Executor> Executor for Box<T> {
     fn exec(&self, f: Pin<Box<dyn Future<Output = ()> + Send>>) {
         T::exec(&**self, f)
     }
+
+    fn exec_named(&self, name: &'static str, f: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        T::exec_named(&**self, name, f)
+    }
 }
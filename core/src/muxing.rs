@@ -165,6 +165,9 @@ pub trait StreamMuxer {
     /// `flush_substream`. If you want to make sure that the remote is immediately informed about
     /// the shutdown, use `flush_substream` or `flush_all`.
     ///
+    /// Only the writing side is shut down, i.e. the remote reads EOF, while the reading side
+    /// remains open, e.g. for receiving the response to a request.
+    ///
     /// After this method has been called, you should no longer attempt to write to this substream.
     ///
     /// An error can be generated if the connection has been closed, or if a protocol misbehaviour
@@ -173,6 +176,10 @@ pub trait StreamMuxer {
         -> Poll<Result<(), Self::Error>>;
 
     /// Destroys a substream.
+    ///
+    /// If the substream has not been shut down by both sides, the implementation should reset
+    /// it, informing the remote that no more data is read or written, as opposed to the graceful
+    /// shutdown of the writing side by `shutdown_substream`.
     fn destroy_substream(&self, s: Self::Substream);
 
     /// Returns `true` if the remote has shown any sign of activity after the muxer has been open.
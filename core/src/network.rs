@@ -18,18 +18,19 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+mod concurrent_dial;
 mod event;
 pub mod peer;
 
 pub use event::{NetworkEvent, IncomingConnectionEvent};
 pub use peer::Peer;
 
+use concurrent_dial::ConcurrentDial;
 use crate::{
     ConnectedPoint,
     Executor,
     Multiaddr,
     PeerId,
-    address_translation,
     connection::{
         ConnectionId,
         ConnectionLimit,
@@ -58,9 +59,11 @@ use std::{
     error,
     fmt,
     hash::Hash,
-    num::NonZeroUsize,
+    iter,
+    num::{NonZeroU8, NonZeroUsize},
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 
 /// Implementation of `Stream` that handles the nodes.
@@ -92,6 +95,12 @@ where
     /// > `Network` (see `dial_peer_impl` and `on_connection_failed`)
     /// > together with the implementation of `DialingAttempt::abort`.
     dialing: FnvHashMap<TPeerId, SmallVec<[peer::DialingState; 10]>>,
+
+    /// The number of addresses dialed concurrently by a dialing attempt.
+    dial_concurrency_factor: NonZeroU8,
+
+    /// The time within which an incoming connection must be upgraded.
+    incoming_timeout: Option<Duration>,
 }
 
 impl<TTrans, TInEvent, TOutEvent, THandler, TConnInfo, TPeerId> fmt::Debug for
@@ -158,6 +167,8 @@ where
             listeners: ListenersStream::new(transport),
             pool: Pool::new(pool_local_id, config.manager_config, config.pool_limits),
             dialing: Default::default(),
+            dial_concurrency_factor: config.dial_concurrency_factor,
+            incoming_timeout: config.incoming_timeout,
         }
     }
 
@@ -178,6 +189,11 @@ where
         self.listeners.remove_listener(id)
     }
 
+    /// Removes all listeners.
+    pub fn remove_all_listeners(&mut self) {
+        self.listeners.clear()
+    }
+
     /// Returns an iterator that produces the list of addresses we are listening on.
     pub fn listen_addrs(&self) -> impl Iterator<Item = &Multiaddr> {
         self.listeners.listen_addrs()
@@ -194,6 +210,9 @@ where
     /// observed address should contain our listening port. In case it differs from our listening
     /// port there might be a proxy along the path.
     ///
+    /// The translation itself is up to the transport, see [`Transport::address_translation`].
+    /// Every distinct address is produced only once.
+    ///
     /// # Arguments
     ///
     /// * `observed_addr` - should be an address a remote observes you as, which can be obtained for
@@ -205,7 +224,15 @@ where
         TMuxer: 'a,
         THandler: 'a,
     {
-        self.listen_addrs().flat_map(move |server| address_translation(server, observed_addr))
+        let mut addrs: Vec<Multiaddr> = Vec::new();
+        for server in self.listen_addrs() {
+            if let Some(addr) = self.transport().address_translation(server, observed_addr) {
+                if !addrs.contains(&addr) {
+                    addrs.push(addr);
+                }
+            }
+        }
+        addrs.into_iter()
     }
 
     /// Returns the peer id of the local node.
@@ -303,6 +330,42 @@ where
         self.dialing.keys()
     }
 
+    /// Returns the connection IDs of the current connection attempts of all
+    /// ongoing dialing attempts to a peer.
+    pub fn dialing_attempts<'a>(&'a self, peer: &TPeerId) -> impl Iterator<Item = ConnectionId> + 'a {
+        self.dialing.get(peer).into_iter().flatten().map(|s| s.current.0)
+    }
+
+    /// Aborts the pending outgoing connection with the given ID, dropping the
+    /// underlying connection and upgrade futures.
+    ///
+    /// If the connection belongs to a dialing attempt to a known peer, the
+    /// remaining addresses of the dialing attempt are not tried either.
+    ///
+    /// Returns the (expected) peer ID and the address of the aborted
+    /// connection, or `None` if there is no pending outgoing connection with
+    /// the given ID. No event is emitted for the aborted connection.
+    pub fn abort_dial(&mut self, id: ConnectionId) -> Option<(Option<TPeerId>, Multiaddr)> {
+        let connection = self.pool.get_outgoing(id)?;
+        let peer_id = connection.peer_id().clone();
+        let address = match connection.endpoint() {
+            ConnectedPoint::Dialer { address } => address.clone(),
+            ConnectedPoint::Listener { .. } => unreachable!("by definition of `Pool::get_outgoing`.")
+        };
+        connection.abort();
+
+        if let Some(peer) = &peer_id {
+            if let hash_map::Entry::Occupied(mut e) = self.dialing.entry(peer.clone()) {
+                e.get_mut().retain(|s| s.current.0 != id);
+                if e.get().is_empty() {
+                    e.remove();
+                }
+            }
+        }
+
+        Some((peer_id, address))
+    }
+
     /// Gets the configured limit on pending incoming connections,
     /// i.e. concurrent incoming connection attempts.
     pub fn incoming_limit(&self) -> Option<usize> {
@@ -319,6 +382,28 @@ where
         self.pool.num_pending()
     }
 
+    /// Closes all established connections and aborts all pending
+    /// connections and dialing attempts.
+    ///
+    /// No events are emitted for the closed and aborted connections.
+    /// Their background tasks may still be closing the connections
+    /// gracefully, see [`Network::poll_terminated`].
+    pub fn disconnect_all(&mut self) {
+        self.pool.disconnect_all();
+        self.dialing.clear();
+    }
+
+    /// Polls for the termination of the background tasks of all
+    /// connections, including those closed with [`Network::disconnect_all`]
+    /// or [`ConnectedPeer::disconnect`](peer::ConnectedPeer::disconnect).
+    ///
+    /// Returns `Poll::Ready` once no background task is running. With no
+    /// executor configured, the tasks only make progress while the
+    /// `Network` is polled.
+    pub fn poll_terminated(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.pool.poll_terminated(cx)
+    }
+
     /// Obtains a view of a [`Peer`] with the given ID in the network.
     pub fn peer(&mut self, peer_id: TPeerId)
         -> Peer<'_, TTrans, TInEvent, TOutEvent, THandler, TConnInfo, TPeerId>
@@ -358,6 +443,7 @@ where
                         upgrade,
                         local_addr,
                         send_back_addr,
+                        timeout: self.incoming_timeout,
                         pool: &mut self.pool,
                     }))
             }
@@ -399,7 +485,8 @@ where
                 let (next, event) = on_connection_failed(dialing, id, endpoint, error, handler);
                 if let Some(dial) = next {
                     let transport = self.listeners.transport().clone();
-                    if let Err(e) = dial_peer_impl(transport, pool, dialing, dial) {
+                    let concurrency = self.dial_concurrency_factor;
+                    if let Err(e) = dial_peer_impl(transport, pool, dialing, concurrency, dial) {
                         log::warn!("Dialing aborted: {:?}", e);
                     }
                 }
@@ -444,7 +531,8 @@ where
         TOutEvent: Send + 'static,
         TPeerId: Send + 'static,
     {
-        dial_peer_impl(self.transport().clone(), &mut self.pool, &mut self.dialing, opts)
+        let concurrency = self.dial_concurrency_factor;
+        dial_peer_impl(self.transport().clone(), &mut self.pool, &mut self.dialing, concurrency, opts)
     }
 }
 
@@ -463,7 +551,8 @@ fn dial_peer_impl<TMuxer, TInEvent, TOutEvent, THandler, TTrans, TConnInfo, TPee
     pool: &mut Pool<TInEvent, TOutEvent, THandler, TTrans::Error,
         <THandler::Handler as ConnectionHandler>::Error, TConnInfo, TPeerId>,
     dialing: &mut FnvHashMap<TPeerId, SmallVec<[peer::DialingState; 10]>>,
-    opts: DialingOpts<TPeerId, THandler>
+    concurrency: NonZeroU8,
+    mut opts: DialingOpts<TPeerId, THandler>
 ) -> Result<ConnectionId, ConnectionLimit>
 where
     THandler: IntoConnectionHandler<TConnInfo> + Send + 'static,
@@ -474,7 +563,7 @@ where
         InEvent = TInEvent,
         OutEvent = TOutEvent,
     > + Send + 'static,
-    TTrans: Transport<Output = (TConnInfo, TMuxer)>,
+    TTrans: Transport<Output = (TConnInfo, TMuxer)> + Clone,
     TTrans::Dial: Send + 'static,
     TTrans::Error: error::Error + Send + 'static,
     TMuxer: StreamMuxer + Send + Sync + 'static,
@@ -484,6 +573,26 @@ where
     TPeerId: Eq + Hash + Send + Clone + 'static,
     TConnInfo: ConnectionInfo<PeerId = TPeerId> + Send + 'static,
 {
+    // With a dial concurrency factor > 1, all addresses are dialed as part
+    // of the same pending connection. Addresses added to the dialing attempt
+    // in the meantime are only dialed if all of them fail.
+    if concurrency.get() > 1 && !opts.remaining.is_empty() {
+        let addresses = iter::once(opts.address.clone()).chain(opts.remaining.drain(..));
+        let dials = addresses.map(|a| (a.clone(), transport.clone().dial(a))).collect::<Vec<_>>();
+        let fut = ConcurrentDial::new(dials, usize::from(concurrency.get()));
+        let info = OutgoingInfo { address: &opts.address, peer_id: Some(&opts.peer) };
+        let result = pool.add_outgoing_concurrent(fut, opts.handler, info);
+        if let Ok(id) = &result {
+            dialing.entry(opts.peer).or_default().push(
+                peer::DialingState {
+                    current: (*id, opts.address),
+                    remaining: Vec::new(),
+                },
+            );
+        }
+        return result
+    }
+
     let result = match transport.dial(opts.address.clone()) {
         Ok(fut) => {
             let fut = fut.map_err(|e| PendingConnectionError::Transport(TransportError::Other(e)));
@@ -610,13 +719,25 @@ pub struct NetworkInfo {
 /// The default configuration specifies no dedicated task executor, no
 /// connection limits, a connection event buffer size of 32, and a
 /// `notify_handler` buffer size of 8.
-#[derive(Default)]
 pub struct NetworkConfig {
     /// Note that the `ManagerConfig`s task command buffer always provides
     /// one "free" slot per task. Thus the given total `notify_handler_buffer_size`
     /// exposed for configuration on the `Network` is reduced by one.
     manager_config: ManagerConfig,
     pool_limits: PoolLimits,
+    dial_concurrency_factor: NonZeroU8,
+    incoming_timeout: Option<Duration>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            manager_config: ManagerConfig::default(),
+            pool_limits: PoolLimits::default(),
+            dial_concurrency_factor: NonZeroU8::new(1).expect("1 > 0"),
+            incoming_timeout: None,
+        }
+    }
 }
 
 impl NetworkConfig {
@@ -664,16 +785,34 @@ impl NetworkConfig {
         self
     }
 
+    /// Sets the maximum number of incoming connections that are upgraded
+    /// concurrently. Incoming connections beyond the limit are dropped.
     pub fn set_incoming_limit(&mut self, n: usize) -> &mut Self {
         self.pool_limits.max_incoming = Some(n);
         self
     }
 
+    /// Sets the time within which the upgrade of an incoming connection,
+    /// i.e. the handshakes of the security and multiplexing protocols, must
+    /// complete. Incoming connections that take longer fail with
+    /// [`PendingConnectionError::Timeout`].
+    ///
+    /// By default, there is no timeout.
+    pub fn set_incoming_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.incoming_timeout = Some(timeout);
+        self
+    }
+
     pub fn set_outgoing_limit(&mut self, n: usize) -> &mut Self {
         self.pool_limits.max_outgoing = Some(n);
         self
     }
 
+    pub fn set_established_limit(&mut self, n: usize) -> &mut Self {
+        self.pool_limits.max_established = Some(n);
+        self
+    }
+
     pub fn set_established_per_peer_limit(&mut self, n: usize) -> &mut Self {
         self.pool_limits.max_established_per_peer = Some(n);
         self
@@ -683,4 +822,18 @@ impl NetworkConfig {
         self.pool_limits.max_outgoing_per_peer = Some(n);
         self
     }
+
+    /// Sets the number of addresses of a peer that are dialed concurrently
+    /// when dialing the peer with more than one address.
+    ///
+    /// The first connection established via any of the addresses is kept
+    /// and the other dials are cancelled. If all dials fail, the dialing
+    /// attempt fails with a [`PendingConnectionError::ConcurrentDial`]
+    /// error listing the error for each address.
+    ///
+    /// Defaults to 1, i.e. the addresses are dialed one after the other.
+    pub fn set_dial_concurrency_factor(&mut self, factor: NonZeroU8) -> &mut Self {
+        self.dial_concurrency_factor = factor;
+        self
+    }
 }
@@ -0,0 +1,148 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::{
+    Multiaddr,
+    connection::PendingConnectionError,
+    transport::TransportError,
+};
+use futures::{future, prelude::*, stream::FuturesUnordered};
+use std::{collections::VecDeque, pin::Pin, task::{Context, Poll}};
+
+/// A future that dials several addresses of a peer, with at most
+/// `concurrency` dials in progress at a time, resolving to the first
+/// successfully established connection together with its address.
+///
+/// Once a dial succeeds, the remaining dials are cancelled by dropping
+/// them. If all dials fail, the future resolves to a
+/// [`PendingConnectionError::ConcurrentDial`] with the error of every address.
+///
+/// > **Note**: The dials are all created upfront with `Transport::dial`,
+/// > but only polled once their turn has come.
+pub(crate) struct ConcurrentDial<TFut: Future, TErr> {
+    /// The dials in progress.
+    dials: FuturesUnordered<future::Join<future::Ready<Multiaddr>, TFut>>,
+    /// The dials not yet in progress.
+    pending: VecDeque<(Multiaddr, TFut)>,
+    /// The errors of the failed dials.
+    errors: Vec<(Multiaddr, TransportError<TErr>)>,
+    concurrency: usize,
+}
+
+// The pending dials are never pinned before being moved into `dials`,
+// which pins them on the heap.
+impl<TFut: Future, TErr> Unpin for ConcurrentDial<TFut, TErr> {}
+
+impl<TFut: Future, TErr> ConcurrentDial<TFut, TErr> {
+    /// Creates a new `ConcurrentDial` from the results of `Transport::dial`
+    /// for each address.
+    pub(crate) fn new(
+        dials: impl IntoIterator<Item = (Multiaddr, Result<TFut, TransportError<TErr>>)>,
+        concurrency: usize,
+    ) -> Self {
+        let mut pending = VecDeque::new();
+        let mut errors = Vec::new();
+        for (address, dial) in dials {
+            match dial {
+                Ok(dial) => pending.push_back((address, dial)),
+                Err(error) => errors.push((address, error)),
+            }
+        }
+        ConcurrentDial {
+            dials: FuturesUnordered::new(),
+            pending,
+            errors,
+            concurrency,
+        }
+    }
+}
+
+impl<TFut, TOut, TErr> Future for ConcurrentDial<TFut, TErr>
+where
+    TFut: Future<Output = Result<TOut, TErr>>,
+{
+    type Output = Result<(Multiaddr, TOut), PendingConnectionError<TErr>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        loop {
+            while this.dials.len() < this.concurrency {
+                match this.pending.pop_front() {
+                    Some((address, dial)) => this.dials.push(future::join(future::ready(address), dial)),
+                    None => break,
+                }
+            }
+
+            match this.dials.poll_next_unpin(cx) {
+                Poll::Ready(Some((address, Ok(output)))) => return Poll::Ready(Ok((address, output))),
+                Poll::Ready(Some((address, Err(error)))) => {
+                    log::debug!("Dialing {} failed, {} dials remaining.",
+                        address, this.dials.len() + this.pending.len());
+                    this.errors.push((address, TransportError::Other(error)));
+                }
+                Poll::Ready(None) => {
+                    let errors = std::mem::take(&mut this.errors);
+                    return Poll::Ready(Err(PendingConnectionError::ConcurrentDial(errors)))
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::io;
+
+    fn addr(i: u64) -> Multiaddr {
+        format!("/memory/{}", i).parse().unwrap()
+    }
+
+    #[test]
+    fn first_success_wins() {
+        let dials = vec![
+            (addr(1), Ok(future::err(io::Error::new(io::ErrorKind::Other, "refused")).boxed())),
+            (addr(2), Err(TransportError::MultiaddrNotSupported(addr(2)))),
+            (addr(3), Ok(future::pending().boxed())),
+            (addr(4), Ok(future::ok(4).boxed())),
+        ];
+        let (address, output) = block_on(ConcurrentDial::new(dials, 2)).unwrap();
+        assert_eq!((address, output), (addr(4), 4));
+    }
+
+    #[test]
+    fn all_errors_are_reported() {
+        let dials = vec![
+            (addr(1), Ok(future::err::<(), _>(io::Error::new(io::ErrorKind::Other, "refused")).boxed())),
+            (addr(2), Err(TransportError::MultiaddrNotSupported(addr(2)))),
+            (addr(3), Ok(future::err(io::Error::new(io::ErrorKind::Other, "refused")).boxed())),
+        ];
+        match block_on(ConcurrentDial::new(dials, 2)) {
+            Err(PendingConnectionError::ConcurrentDial(errors)) => {
+                let mut addrs = errors.into_iter().map(|(a, _)| a).collect::<Vec<_>>();
+                addrs.sort_by_key(|a| a.to_string());
+                assert_eq!(addrs, vec![addr(1), addr(2), addr(3)]);
+            }
+            _ => panic!("Unexpected result."),
+        }
+    }
+}
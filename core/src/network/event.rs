@@ -42,7 +42,8 @@ use crate::{
     transport::{Transport, TransportError},
 };
 use futures::prelude::*;
-use std::{error, fmt, hash::Hash, num::NonZeroU32};
+use futures_timer::Delay;
+use std::{error, fmt, hash::Hash, num::NonZeroU32, time::Duration};
 
 /// Event that can happen on the `Network`.
 pub enum NetworkEvent<'a, TTrans, TInEvent, TOutEvent, THandler, TConnInfo, TPeerId>
@@ -275,6 +276,8 @@ where
     pub(super) local_addr: Multiaddr,
     /// Address used to send back data to the remote.
     pub(super) send_back_addr: Multiaddr,
+    /// The time within which the upgrade must complete.
+    pub(super) timeout: Option<Duration>,
     /// Reference to the `peers` field of the `Network`.
     pub(super) pool: &'a mut Pool<
         TInEvent,
@@ -324,6 +327,19 @@ where
         let handler = builder(self.info());
         let upgrade = self.upgrade
             .map_err(|err| PendingConnectionError::Transport(TransportError::Other(err)));
+        let timer = self.timeout.map(Delay::new);
+        let upgrade = async move {
+            match timer {
+                Some(timer) => {
+                    futures::pin_mut!(upgrade);
+                    match future::select(upgrade, timer).await {
+                        future::Either::Left((result, _)) => result,
+                        future::Either::Right(_) => Err(PendingConnectionError::Timeout),
+                    }
+                }
+                None => upgrade.await,
+            }
+        };
         let info = IncomingInfo {
             local_addr: &self.local_addr,
             send_back_addr: &self.send_back_addr,
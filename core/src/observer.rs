@@ -0,0 +1,60 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::{ConnectedPoint, upgrade::NegotiationError};
+use std::time::Duration;
+
+/// The steps of establishing a connection observed by a [`HandshakeObserver`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum HandshakeStep {
+    /// Establishing the connection of the underlying transport, e.g. a TCP
+    /// connection, when dialing.
+    Connect,
+    /// Negotiating and performing the security handshake.
+    Security,
+    /// Negotiating and applying a multiplexer.
+    Multiplexing,
+}
+
+/// Observes the steps of establishing the connections of a transport, e.g.
+/// to record metrics.
+///
+/// The default implementations do nothing.
+///
+/// Transports report to an observer once upgraded with
+/// [`Builder::observe`](crate::transport::upgrade::Builder::observe).
+pub trait HandshakeObserver: Send + Sync + 'static {
+    /// Called when a step completed, successfully or not, with the time it took.
+    fn on_step(&self, _step: HandshakeStep, _endpoint: &ConnectedPoint, _duration: Duration, _success: bool) {
+    }
+
+    /// Called when no protocol could be negotiated for the security or the
+    /// multiplexing step, with the protocols proposed by the local node as
+    /// dialer, or accepted as listener, in order of preference.
+    fn on_negotiation_failure(
+        &self,
+        _step: HandshakeStep,
+        _endpoint: &ConnectedPoint,
+        _protocols: &[String],
+        _error: &NegotiationError
+    ) {
+    }
+}
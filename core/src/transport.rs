@@ -128,6 +128,25 @@ pub trait Transport {
     where
         Self: Sized;
 
+    /// Derives a plausible external address of the local node from a listen address
+    /// of this transport and an address a remote observed the local node at, e.g.
+    /// as reported by the identify protocol.
+    ///
+    /// For example, with TCP the observed address of an outbound connection contains
+    /// the port the connection was dialed from instead of the listen port, so the IP
+    /// address of the observed address is combined with the port of the listen address.
+    ///
+    /// Returns `None` if the listen address does not belong to this transport or no
+    /// address can be derived.
+    ///
+    /// The default implementation performs the generic [`address_translation`](crate::address_translation),
+    /// replacing the IP address of the listen address with the one of the observed
+    /// address. Transports may refine it, e.g. to take port reuse into account, while
+    /// transports wrapping another transport are expected to delegate to it.
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        crate::address_translation(listen, observed)
+    }
+
     /// Turns the transport into an abstract boxed (i.e. heap-allocated) transport.
     fn boxed(self) -> boxed::Boxed<Self::Output, Self::Error>
     where Self: Sized + Clone + Send + Sync + 'static,
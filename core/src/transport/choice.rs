@@ -74,4 +74,9 @@ where
 
         Err(TransportError::MultiaddrNotSupported(addr))
     }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.0.address_translation(listen, observed)
+            .or_else(|| self.1.address_translation(listen, observed))
+    }
 }
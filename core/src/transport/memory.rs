@@ -290,6 +290,19 @@ mod tests {
         assert_eq!(parse_memory_addr(&"/memory/1234567890".parse().unwrap()), Ok(1_234_567_890));
     }
 
+    #[test]
+    fn default_address_translation() {
+        let transport = MemoryTransport::default();
+        let listen: Multiaddr = "/ip4/127.0.0.1/tcp/4001".parse().unwrap();
+        let observed: Multiaddr = "/ip4/1.2.3.4/tcp/5678".parse().unwrap();
+        assert_eq!(
+            transport.address_translation(&listen, &observed),
+            Some("/ip4/1.2.3.4/tcp/4001".parse().unwrap())
+        );
+        let memory: Multiaddr = "/memory/5".parse().unwrap();
+        assert_eq!(transport.address_translation(&memory, &observed), None);
+    }
+
     #[test]
     fn listening_twice() {
         let transport = MemoryTransport::default();
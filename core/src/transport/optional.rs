@@ -74,4 +74,8 @@ where
             Err(TransportError::MultiaddrNotSupported(addr))
         }
     }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.0.as_ref().and_then(|inner| inner.address_translation(listen, observed))
+    }
 }
@@ -24,8 +24,14 @@ pub use crate::upgrade::Version;
 
 use crate::{
     ConnectedPoint,
+    ConnectionDenied,
+    ConnectionGater,
     ConnectionInfo,
+    HandshakeObserver,
+    HandshakeStep,
     Negotiated,
+    PeerId,
+    either::EitherError,
     transport::{
         Transport,
         TransportError,
@@ -39,6 +45,9 @@ use crate::{
         InboundUpgrade,
         apply_inbound,
         apply_outbound,
+        NegotiationError,
+        ProtocolName,
+        UpgradeInfo,
         UpgradeError,
         OutboundUpgradeApply,
         InboundUpgradeApply
@@ -46,14 +55,17 @@ use crate::{
 };
 use futures::{prelude::*, ready};
 use multiaddr::Multiaddr;
-use std::{error::Error, fmt, pin::Pin, task::Context, task::Poll};
+use std::{error::Error, fmt, pin::Pin, sync::Arc, task::Context, task::Poll};
+use wasm_timer::Instant;
 
 /// A `Builder` facilitates upgrading of a [`Transport`] for use with
 /// a [`Network`].
 ///
 /// The upgrade process is defined by the following stages:
 ///
-///    [`authenticate`](Builder::authenticate)`{1}`
+///    [`observe`](Builder::observe)`{0,1}`
+/// -> [`authenticate`](Builder::authenticate)`{1}`
+/// -> [`gate`](Builder::gate)`{*}`
 /// -> [`apply`](Builder::apply)`{*}`
 /// -> [`multiplex`](Builder::multiplex)`{1}`
 ///
@@ -72,6 +84,7 @@ use std::{error::Error, fmt, pin::Pin, task::Context, task::Poll};
 pub struct Builder<T> {
     inner: T,
     version: upgrade::Version,
+    observer: Option<Arc<dyn HandshakeObserver>>,
 }
 
 impl<T> Builder<T>
@@ -81,7 +94,23 @@ where
 {
     /// Creates a `Builder` over the given (base) `Transport`.
     pub fn new(inner: T, version: upgrade::Version) -> Builder<T> {
-        Builder { inner, version }
+        Builder { inner, version, observer: None }
+    }
+
+    /// Reports the steps of establishing every connection to the given
+    /// [`HandshakeObserver`], i.e. connecting the (base) transport when
+    /// dialing as well as the [authentication](Builder::authenticate) and
+    /// [multiplexing](Builder::multiplex) upgrades.
+    ///
+    /// ## Transitions
+    ///
+    ///   * Transport output: `C -> C`.
+    pub fn observe(self, observer: Arc<dyn HandshakeObserver>) -> Builder<Observe<T>> {
+        Builder {
+            inner: Observe { inner: self.inner, observer: observer.clone() },
+            version: self.version,
+            observer: Some(observer),
+        }
     }
 
     /// Upgrades the transport to perform authentication of the remote.
@@ -109,11 +138,19 @@ where
         E: Error + 'static,
     {
         let version = self.version;
-        Builder::new(self.inner.and_then(move |conn, endpoint| {
+        let observer = self.observer.clone();
+        let inner = self.inner.and_then(move |conn, endpoint| {
+            let span = tracing::debug_span!("security",
+                address = %endpoint.get_remote_address());
+            let observation = observer.map(|o|
+                Observation::new(o, HandshakeStep::Security, endpoint.clone(), protocol_names(&upgrade)));
             Authenticate {
-                inner: upgrade::apply(conn, upgrade, endpoint, version)
+                inner: span.in_scope(|| upgrade::apply(conn, upgrade, endpoint, version)),
+                span,
+                observation,
             }
-        }), version)
+        });
+        Builder { inner, version, observer: self.observer }
     }
 
     /// Applies an arbitrary upgrade on an authenticated, non-multiplexed
@@ -137,7 +174,34 @@ where
         U: OutboundUpgrade<Negotiated<C>, Output = D, Error = E> + Clone,
         E: Error + 'static,
     {
-        Builder::new(Upgrade::new(self.inner, upgrade), self.version)
+        Builder {
+            inner: Upgrade::new(self.inner, upgrade),
+            version: self.version,
+            observer: self.observer,
+        }
+    }
+
+    /// Consults the given [`ConnectionGater`] once the remote is
+    /// authenticated, failing the upgrade with [`ConnectionDenied`] if
+    /// [`ConnectionGater::intercept_secured`] denies the connection.
+    ///
+    /// ## Transitions
+    ///
+    ///   * Transport output: `(I, C) -> (I, C)`.
+    pub fn gate<C, I>(self, gater: Arc<dyn ConnectionGater>) -> Builder<
+        AndThen<T, impl FnOnce((I, C), ConnectedPoint) -> future::Ready<Result<(I, C), ConnectionDenied>> + Clone>
+    > where
+        T: Transport<Output = (I, C)>,
+        I: ConnectionInfo<PeerId = PeerId>,
+    {
+        let inner = self.inner.and_then(move |(i, c), endpoint| {
+            if gater.intercept_secured(i.peer_id(), &endpoint) {
+                future::ready(Ok((i, c)))
+            } else {
+                future::ready(Err(ConnectionDenied))
+            }
+        });
+        Builder { inner, version: self.version, observer: self.observer }
     }
 
     /// Upgrades the transport with a (sub)stream multiplexer.
@@ -158,14 +222,21 @@ where
         C: AsyncRead + AsyncWrite + Unpin,
         M: StreamMuxer,
         I: ConnectionInfo,
+        I::PeerId: fmt::Debug,
         U: InboundUpgrade<Negotiated<C>, Output = M, Error = E>,
         U: OutboundUpgrade<Negotiated<C>, Output = M, Error = E> + Clone,
         E: Error + 'static,
     {
         let version = self.version;
+        let observer = self.observer;
         self.inner.and_then(move |(i, c), endpoint| {
-            let upgrade = upgrade::apply(c, upgrade, endpoint, version);
-            Multiplex { info: Some(i), upgrade }
+            let span = tracing::debug_span!("multiplexing",
+                address = %endpoint.get_remote_address(),
+                peer = ?i.peer_id());
+            let observation = observer.map(|o|
+                Observation::new(o, HandshakeStep::Multiplexing, endpoint.clone(), protocol_names(&upgrade)));
+            let upgrade = span.in_scope(|| upgrade::apply(c, upgrade, endpoint, version));
+            Multiplex { info: Some(i), upgrade, span, observation }
         })
     }
 }
@@ -181,7 +252,9 @@ where
     U: InboundUpgrade<Negotiated<C>> + OutboundUpgrade<Negotiated<C>>
 {
     #[pin]
-    inner: EitherUpgrade<C, U>
+    inner: EitherUpgrade<C, U>,
+    span: tracing::Span,
+    observation: Option<Observation>,
 }
 
 impl<C, U> Future for Authenticate<C, U>
@@ -196,7 +269,16 @@ where
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.project();
-        Future::poll(this.inner, cx)
+        let _enter = this.span.enter();
+        let result = ready!(Future::poll(this.inner, cx));
+        if let Some(observation) = this.observation.take() {
+            observation.finish_upgrade(&result);
+        }
+        match &result {
+            Ok(_) => tracing::debug!("Security handshake complete"),
+            Err(_) => tracing::debug!("Security handshake failed"),
+        }
+        Poll::Ready(result)
     }
 }
 
@@ -213,6 +295,8 @@ where
     info: Option<I>,
     #[pin]
     upgrade: EitherUpgrade<C, U>,
+    span: tracing::Span,
+    observation: Option<Observation>,
 }
 
 impl<C, U, I, M, E> Future for Multiplex<C, U, I>
@@ -225,15 +309,68 @@ where
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.project();
-        let m = match ready!(Future::poll(this.upgrade, cx)) {
+        let _enter = this.span.enter();
+        let result = ready!(Future::poll(this.upgrade, cx));
+        if let Some(observation) = this.observation.take() {
+            observation.finish_upgrade(&result);
+        }
+        let m = match result {
             Ok(m) => m,
-            Err(err) => return Poll::Ready(Err(err)),
+            Err(err) => {
+                tracing::debug!("Multiplexer upgrade failed");
+                return Poll::Ready(Err(err))
+            }
         };
+        tracing::debug!("Multiplexer upgrade complete");
         let i = this.info.take().expect("Multiplex future polled after completion.");
         Poll::Ready(Ok((i, m)))
     }
 }
 
+/// The observation of a step of establishing a connection by a
+/// [`HandshakeObserver`].
+struct Observation {
+    observer: Arc<dyn HandshakeObserver>,
+    step: HandshakeStep,
+    endpoint: ConnectedPoint,
+    /// The protocols of the upgrade of the step, if any.
+    protocols: Vec<String>,
+    started: Instant,
+}
+
+impl Observation {
+    fn new(
+        observer: Arc<dyn HandshakeObserver>,
+        step: HandshakeStep,
+        endpoint: ConnectedPoint,
+        protocols: Vec<String>
+    ) -> Self {
+        Observation { observer, step, endpoint, protocols, started: Instant::now() }
+    }
+
+    /// Reports the completion of the step.
+    fn finish(self, success: bool) {
+        self.observer.on_step(self.step, &self.endpoint, self.started.elapsed(), success);
+    }
+
+    /// Reports the completion of the upgrade of the step, including a
+    /// failure to negotiate its protocol.
+    fn finish_upgrade<T, E>(self, result: &Result<T, UpgradeError<E>>) {
+        if let Err(UpgradeError::Select(err)) = result {
+            self.observer.on_negotiation_failure(self.step, &self.endpoint, &self.protocols, err);
+        }
+        self.finish(result.is_ok())
+    }
+}
+
+/// Returns the names of the protocols of an upgrade, in order of preference.
+fn protocol_names<U: UpgradeInfo>(upgrade: &U) -> Vec<String> {
+    upgrade.protocol_info()
+        .into_iter()
+        .map(|p| String::from_utf8_lossy(p.protocol_name()).into_owned())
+        .collect()
+}
+
 /// An inbound or outbound upgrade.
 type EitherUpgrade<C, U> = future::Either<InboundUpgradeApply<C, U>, OutboundUpgradeApply<C, U>>;
 
@@ -281,6 +418,70 @@ where
             upgrade: self.upgrade
         })
     }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.inner.address_translation(listen, observed)
+    }
+}
+
+/// A [`Transport`] reporting the time to connect when dialing to a
+/// [`HandshakeObserver`].
+///
+/// See [`Builder::observe`].
+#[derive(Clone)]
+pub struct Observe<T> {
+    inner: T,
+    observer: Arc<dyn HandshakeObserver>,
+}
+
+impl<T> Transport for Observe<T>
+where
+    T: Transport,
+{
+    type Output = T::Output;
+    type Error = T::Error;
+    type Listener = T::Listener;
+    type ListenerUpgrade = T::ListenerUpgrade;
+    type Dial = ObserveDial<T::Dial>;
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let endpoint = ConnectedPoint::Dialer { address: addr.clone() };
+        let future = self.inner.dial(addr)?;
+        let observation = Observation::new(self.observer, HandshakeStep::Connect, endpoint, Vec::new());
+        Ok(ObserveDial { future, observation: Some(observation) })
+    }
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        self.inner.listen_on(addr)
+    }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.inner.address_translation(listen, observed)
+    }
+}
+
+/// The [`Transport::Dial`] future of an [`Observe`]d transport.
+#[pin_project::pin_project]
+pub struct ObserveDial<F> {
+    #[pin]
+    future: F,
+    observation: Option<Observation>,
+}
+
+impl<F, O, E> Future for ObserveDial<F>
+where
+    F: Future<Output = Result<O, E>>,
+{
+    type Output = Result<O, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let result = ready!(this.future.poll(cx));
+        if let Some(observation) = this.observation.take() {
+            observation.finish(result.is_ok());
+        }
+        Poll::Ready(result)
+    }
 }
 
 /// Errors produced by a transport upgrade.
@@ -318,6 +519,77 @@ where
     }
 }
 
+/// The error of a transport obtained from [`Builder::multiplex`], identifying
+/// the stage of the upgrade process at which a connection failed.
+///
+/// Obtained from the error of such a transport through its [`From`] impl,
+/// e.g. with [`Transport::map_err`]. Errors of upgrades [applied](Builder::apply)
+/// between authentication and multiplexing are reported as [`StageError::Transport`].
+///
+/// The address of the connection is reported alongside the error by the
+/// [`Network`](crate::Network), whereas a remote that authenticates with an
+/// unexpected peer ID is reported as
+/// [`PendingConnectionError::InvalidPeerId`](crate::connection::PendingConnectionError::InvalidPeerId).
+#[derive(Debug)]
+pub enum StageError<T, A, M> {
+    /// Error in the underlying transport.
+    Transport(T),
+    /// Negotiating the authentication protocol failed.
+    SecurityNegotiation(NegotiationError),
+    /// The authentication handshake failed.
+    Security(A),
+    /// Negotiating the multiplexing protocol failed.
+    MuxerNegotiation(NegotiationError),
+    /// The multiplexing upgrade failed.
+    Muxer(M),
+}
+
+impl<T, A, M> From<EitherError<EitherError<T, UpgradeError<A>>, UpgradeError<M>>> for StageError<T, A, M> {
+    fn from(err: EitherError<EitherError<T, UpgradeError<A>>, UpgradeError<M>>) -> Self {
+        match err {
+            EitherError::A(EitherError::A(e)) => StageError::Transport(e),
+            EitherError::A(EitherError::B(UpgradeError::Select(e))) => StageError::SecurityNegotiation(e),
+            EitherError::A(EitherError::B(UpgradeError::Apply(e))) => StageError::Security(e),
+            EitherError::B(UpgradeError::Select(e)) => StageError::MuxerNegotiation(e),
+            EitherError::B(UpgradeError::Apply(e)) => StageError::Muxer(e),
+        }
+    }
+}
+
+impl<T, A, M> fmt::Display for StageError<T, A, M>
+where
+    T: fmt::Display,
+    A: fmt::Display,
+    M: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StageError::Transport(e) => write!(f, "Transport error: {}", e),
+            StageError::SecurityNegotiation(e) => write!(f, "Security protocol negotiation error: {}", e),
+            StageError::Security(e) => write!(f, "Security handshake error: {}", e),
+            StageError::MuxerNegotiation(e) => write!(f, "Multiplexer protocol negotiation error: {}", e),
+            StageError::Muxer(e) => write!(f, "Multiplexer upgrade error: {}", e),
+        }
+    }
+}
+
+impl<T, A, M> Error for StageError<T, A, M>
+where
+    T: Error + 'static,
+    A: Error + 'static,
+    M: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StageError::Transport(e) => Some(e),
+            StageError::SecurityNegotiation(e) => Some(e),
+            StageError::Security(e) => Some(e),
+            StageError::MuxerNegotiation(e) => Some(e),
+            StageError::Muxer(e) => Some(e),
+        }
+    }
+}
+
 /// The [`Transport::Dial`] future of an [`Upgrade`]d transport.
 pub struct DialUpgradeFuture<F, U, I, C>
 where
@@ -64,6 +64,7 @@ mod error;
 mod from_fn;
 mod map;
 mod optional;
+mod security_info;
 mod select;
 mod transfer;
 
@@ -74,11 +75,12 @@ pub use multistream_select::{Version, NegotiatedComplete, NegotiationError, Prot
 pub use self::{
     apply::{apply, apply_inbound, apply_outbound, InboundUpgradeApply, OutboundUpgradeApply},
     denied::DeniedUpgrade,
-    either::EitherUpgrade,
+    either::{EitherUpgrade, EitherSecurityUpgrade, EitherSecurityFuture},
     error::UpgradeError,
     from_fn::{from_fn, FromFnUpgrade},
     map::{MapInboundUpgrade, MapOutboundUpgrade, MapInboundUpgradeErr, MapOutboundUpgradeErr},
     optional::OptionalUpgrade,
+    security_info::{WithSecurityInfo, SecurityInfoFuture},
     select::SelectUpgrade,
     transfer::{write_one, write_with_len_prefix, write_varint, read_one, ReadOneError, read_varint},
 };
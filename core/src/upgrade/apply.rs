@@ -21,7 +21,6 @@
 use crate::{ConnectedPoint, Negotiated};
 use crate::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeError, ProtocolName};
 use futures::{future::Either, prelude::*};
-use log::debug;
 use multistream_select::{self, DialerSelectFuture, ListenerSelectFuture};
 use std::{iter, mem, pin::Pin, task::Context, task::Poll};
 
@@ -50,7 +49,8 @@ where
     let iter = up.protocol_info().into_iter().map(NameWrap as fn(_) -> NameWrap<_>);
     let future = multistream_select::listener_select_proto(conn, iter);
     InboundUpgradeApply {
-        inner: InboundUpgradeApplyState::Init { future, upgrade: up }
+        inner: InboundUpgradeApplyState::Init { future, upgrade: up },
+        span: tracing::debug_span!("inbound_upgrade", protocol = tracing::field::Empty),
     }
 }
 
@@ -63,7 +63,8 @@ where
     let iter = up.protocol_info().into_iter().map(NameWrap as fn(_) -> NameWrap<_>);
     let future = multistream_select::dialer_select_proto(conn, iter, v);
     OutboundUpgradeApply {
-        inner: OutboundUpgradeApplyState::Init { future, upgrade: up }
+        inner: OutboundUpgradeApplyState::Init { future, upgrade: up },
+        span: tracing::debug_span!("outbound_upgrade", protocol = tracing::field::Empty),
     }
 }
 
@@ -73,7 +74,10 @@ where
     C: AsyncRead + AsyncWrite + Unpin,
     U: InboundUpgrade<Negotiated<C>>
 {
-    inner: InboundUpgradeApplyState<C, U>
+    inner: InboundUpgradeApplyState<C, U>,
+    /// The span of the negotiation and the upgrade, recording the
+    /// negotiated protocol.
+    span: tracing::Span,
 }
 
 enum InboundUpgradeApplyState<C, U>
@@ -106,32 +110,35 @@ where
     type Output = Result<U::Output, UpgradeError<U::Error>>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let _enter = this.span.enter();
         loop {
-            match mem::replace(&mut self.inner, InboundUpgradeApplyState::Undefined) {
+            match mem::replace(&mut this.inner, InboundUpgradeApplyState::Undefined) {
                 InboundUpgradeApplyState::Init { mut future, upgrade } => {
                     let (info, io) = match Future::poll(Pin::new(&mut future), cx)? {
                         Poll::Ready(x) => x,
                         Poll::Pending => {
-                            self.inner = InboundUpgradeApplyState::Init { future, upgrade };
+                            this.inner = InboundUpgradeApplyState::Init { future, upgrade };
                             return Poll::Pending
                         }
                     };
-                    self.inner = InboundUpgradeApplyState::Upgrade {
+                    record_protocol(&this.span, &info.0);
+                    this.inner = InboundUpgradeApplyState::Upgrade {
                         future: Box::pin(upgrade.upgrade_inbound(io, info.0))
                     };
                 }
                 InboundUpgradeApplyState::Upgrade { mut future } => {
                     match Future::poll(Pin::new(&mut future), cx) {
                         Poll::Pending => {
-                            self.inner = InboundUpgradeApplyState::Upgrade { future };
+                            this.inner = InboundUpgradeApplyState::Upgrade { future };
                             return Poll::Pending
                         }
                         Poll::Ready(Ok(x)) => {
-                            debug!("Successfully applied negotiated protocol");
+                            tracing::debug!("Successfully applied negotiated protocol");
                             return Poll::Ready(Ok(x))
                         }
                         Poll::Ready(Err(e)) => {
-                            debug!("Failed to apply negotiated protocol");
+                            tracing::debug!("Failed to apply negotiated protocol");
                             return Poll::Ready(Err(UpgradeError::Apply(e)))
                         }
                     }
@@ -149,7 +156,10 @@ where
     C: AsyncRead + AsyncWrite + Unpin,
     U: OutboundUpgrade<Negotiated<C>>
 {
-    inner: OutboundUpgradeApplyState<C, U>
+    inner: OutboundUpgradeApplyState<C, U>,
+    /// The span of the negotiation and the upgrade, recording the
+    /// negotiated protocol.
+    span: tracing::Span,
 }
 
 enum OutboundUpgradeApplyState<C, U>
@@ -182,32 +192,35 @@ where
     type Output = Result<U::Output, UpgradeError<U::Error>>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let _enter = this.span.enter();
         loop {
-            match mem::replace(&mut self.inner, OutboundUpgradeApplyState::Undefined) {
+            match mem::replace(&mut this.inner, OutboundUpgradeApplyState::Undefined) {
                 OutboundUpgradeApplyState::Init { mut future, upgrade } => {
                     let (info, connection) = match Future::poll(Pin::new(&mut future), cx)? {
                         Poll::Ready(x) => x,
                         Poll::Pending => {
-                            self.inner = OutboundUpgradeApplyState::Init { future, upgrade };
+                            this.inner = OutboundUpgradeApplyState::Init { future, upgrade };
                             return Poll::Pending
                         }
                     };
-                    self.inner = OutboundUpgradeApplyState::Upgrade {
+                    record_protocol(&this.span, &info.0);
+                    this.inner = OutboundUpgradeApplyState::Upgrade {
                         future: Box::pin(upgrade.upgrade_outbound(connection, info.0))
                     };
                 }
                 OutboundUpgradeApplyState::Upgrade { mut future } => {
                     match Future::poll(Pin::new(&mut future), cx) {
                         Poll::Pending => {
-                            self.inner = OutboundUpgradeApplyState::Upgrade { future };
+                            this.inner = OutboundUpgradeApplyState::Upgrade { future };
                             return Poll::Pending
                         }
                         Poll::Ready(Ok(x)) => {
-                            debug!("Successfully applied negotiated protocol");
+                            tracing::debug!("Successfully applied negotiated protocol");
                             return Poll::Ready(Ok(x))
                         }
                         Poll::Ready(Err(e)) => {
-                            debug!("Failed to apply negotiated protocol");
+                            tracing::debug!("Failed to apply negotiated protocol");
                             return Poll::Ready(Err(UpgradeError::Apply(e)));
                         }
                     }
@@ -219,6 +232,14 @@ where
     }
 }
 
+/// Records the negotiated protocol in the span of an upgrade.
+fn record_protocol<N: ProtocolName>(span: &tracing::Span, protocol: &N) {
+    if !span.is_disabled() {
+        let protocol = String::from_utf8_lossy(protocol.protocol_name());
+        span.record("protocol", &tracing::field::display(protocol));
+    }
+}
+
 type NameWrapIter<I> = iter::Map<I, fn(<I as Iterator>::Item) -> NameWrap<<I as Iterator>::Item>>;
 
 /// Wrapper type to expose an `AsRef<[u8]>` impl for all types implementing `ProtocolName`.
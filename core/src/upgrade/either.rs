@@ -22,6 +22,8 @@ use crate::{
     either::{EitherOutput, EitherError, EitherFuture2, EitherName},
     upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo}
 };
+use futures::prelude::*;
+use std::{pin::Pin, task::Context, task::Poll};
 
 /// A type to represent two possible upgrade types (inbound or outbound).
 #[derive(Debug, Clone)]
@@ -82,6 +84,101 @@ where
     }
 }
 
+/// A type to represent two possible authentication upgrades, e.g. to choose
+/// the security protocol of a transport at runtime.
+///
+/// Contrary to [`EitherUpgrade`], the output of both upgrades must be a pair
+/// of the same connection information `I`, e.g. a `PeerId`, and an I/O
+/// resource. The output is `(I, EitherOutput<DA, DB>)`, which is suitable for
+/// [`Builder::authenticate`](crate::transport::upgrade::Builder::authenticate).
+#[derive(Debug, Clone)]
+pub enum EitherSecurityUpgrade<A, B> { A(A), B(B) }
+
+impl<A, B> UpgradeInfo for EitherSecurityUpgrade<A, B>
+where
+    A: UpgradeInfo,
+    B: UpgradeInfo
+{
+    type Info = EitherName<A::Info, B::Info>;
+    type InfoIter = EitherIter<
+        <A::InfoIter as IntoIterator>::IntoIter,
+        <B::InfoIter as IntoIterator>::IntoIter
+    >;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        match self {
+            EitherSecurityUpgrade::A(a) => EitherIter::A(a.protocol_info().into_iter()),
+            EitherSecurityUpgrade::B(b) => EitherIter::B(b.protocol_info().into_iter())
+        }
+    }
+}
+
+impl<C, A, B, I, DA, DB, EA, EB> InboundUpgrade<C> for EitherSecurityUpgrade<A, B>
+where
+    A: InboundUpgrade<C, Output = (I, DA), Error = EA>,
+    B: InboundUpgrade<C, Output = (I, DB), Error = EB>,
+{
+    type Output = (I, EitherOutput<DA, DB>);
+    type Error = EitherError<EA, EB>;
+    type Future = EitherSecurityFuture<A::Future, B::Future>;
+
+    fn upgrade_inbound(self, sock: C, info: Self::Info) -> Self::Future {
+        let inner = match (self, info) {
+            (EitherSecurityUpgrade::A(a), EitherName::A(info)) => EitherFuture2::A(a.upgrade_inbound(sock, info)),
+            (EitherSecurityUpgrade::B(b), EitherName::B(info)) => EitherFuture2::B(b.upgrade_inbound(sock, info)),
+            _ => panic!("Invalid invocation of EitherSecurityUpgrade::upgrade_inbound")
+        };
+        EitherSecurityFuture { inner }
+    }
+}
+
+impl<C, A, B, I, DA, DB, EA, EB> OutboundUpgrade<C> for EitherSecurityUpgrade<A, B>
+where
+    A: OutboundUpgrade<C, Output = (I, DA), Error = EA>,
+    B: OutboundUpgrade<C, Output = (I, DB), Error = EB>,
+{
+    type Output = (I, EitherOutput<DA, DB>);
+    type Error = EitherError<EA, EB>;
+    type Future = EitherSecurityFuture<A::Future, B::Future>;
+
+    fn upgrade_outbound(self, sock: C, info: Self::Info) -> Self::Future {
+        let inner = match (self, info) {
+            (EitherSecurityUpgrade::A(a), EitherName::A(info)) => EitherFuture2::A(a.upgrade_outbound(sock, info)),
+            (EitherSecurityUpgrade::B(b), EitherName::B(info)) => EitherFuture2::B(b.upgrade_outbound(sock, info)),
+            _ => panic!("Invalid invocation of EitherSecurityUpgrade::upgrade_outbound")
+        };
+        EitherSecurityFuture { inner }
+    }
+}
+
+/// The future of an [`EitherSecurityUpgrade`].
+#[pin_project::pin_project]
+#[must_use = "futures do nothing unless polled"]
+pub struct EitherSecurityFuture<A, B> {
+    #[pin]
+    inner: EitherFuture2<A, B>,
+}
+
+impl<A, B, I, DA, DB, EA, EB> Future for EitherSecurityFuture<A, B>
+where
+    A: TryFuture<Ok = (I, DA), Error = EA>,
+    B: TryFuture<Ok = (I, DB), Error = EB>,
+{
+    type Output = Result<(I, EitherOutput<DA, DB>), EitherError<EA, EB>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let out = match Future::poll(self.project().inner, cx) {
+            Poll::Ready(Ok(out)) => out,
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        };
+        Poll::Ready(Ok(match out {
+            EitherOutput::First((i, d)) => (i, EitherOutput::First(d)),
+            EitherOutput::Second((i, d)) => (i, EitherOutput::Second(d)),
+        }))
+    }
+}
+
 /// A type to represent two possible `Iterator` types.
 #[derive(Debug, Clone)]
 pub enum EitherIter<A, B> { A(A), B(B) }
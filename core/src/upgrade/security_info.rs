@@ -0,0 +1,113 @@
+// Copyright 2018 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::{PeerId, connection::SecurityInfo};
+use crate::upgrade::{InboundUpgrade, OutboundUpgrade, ProtocolName, UpgradeInfo};
+use futures::prelude::*;
+use std::{pin::Pin, task::Context, task::Poll};
+
+/// Wraps around an authentication upgrade yielding a `PeerId` and records the
+/// name of the negotiated protocol, yielding a [`SecurityInfo`] instead.
+///
+/// The output of this upgrade is suitable for
+/// [`Builder::authenticate`](crate::transport::upgrade::Builder::authenticate),
+/// making the security protocol available as the [`ConnectionInfo`](crate::ConnectionInfo)
+/// of established connections.
+#[derive(Debug, Clone)]
+pub struct WithSecurityInfo<U> { upgrade: U }
+
+impl<U> WithSecurityInfo<U> {
+    pub fn new(upgrade: U) -> Self {
+        WithSecurityInfo { upgrade }
+    }
+}
+
+impl<U> UpgradeInfo for WithSecurityInfo<U>
+where
+    U: UpgradeInfo
+{
+    type Info = U::Info;
+    type InfoIter = U::InfoIter;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.upgrade.protocol_info()
+    }
+}
+
+impl<C, U, D> InboundUpgrade<C> for WithSecurityInfo<U>
+where
+    U: InboundUpgrade<C, Output = (PeerId, D)>
+{
+    type Output = (SecurityInfo, D);
+    type Error = U::Error;
+    type Future = SecurityInfoFuture<U::Future>;
+
+    fn upgrade_inbound(self, sock: C, info: Self::Info) -> Self::Future {
+        let protocol = info.protocol_name().to_vec();
+        SecurityInfoFuture {
+            inner: self.upgrade.upgrade_inbound(sock, info),
+            protocol: Some(protocol)
+        }
+    }
+}
+
+impl<C, U, D> OutboundUpgrade<C> for WithSecurityInfo<U>
+where
+    U: OutboundUpgrade<C, Output = (PeerId, D)>
+{
+    type Output = (SecurityInfo, D);
+    type Error = U::Error;
+    type Future = SecurityInfoFuture<U::Future>;
+
+    fn upgrade_outbound(self, sock: C, info: Self::Info) -> Self::Future {
+        let protocol = info.protocol_name().to_vec();
+        SecurityInfoFuture {
+            inner: self.upgrade.upgrade_outbound(sock, info),
+            protocol: Some(protocol)
+        }
+    }
+}
+
+#[pin_project::pin_project]
+pub struct SecurityInfoFuture<TInnerFut> {
+    #[pin]
+    inner: TInnerFut,
+    protocol: Option<Vec<u8>>,
+}
+
+impl<TInnerFut, D> Future for SecurityInfoFuture<TInnerFut>
+where
+    TInnerFut: TryFuture<Ok = (PeerId, D)>,
+{
+    type Output = Result<(SecurityInfo, D), TInnerFut::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let (peer_id, io) = match TryFuture::try_poll(this.inner, cx) {
+            Poll::Ready(Ok(v)) => v,
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        };
+
+        let protocol = this.protocol.take().expect("Future has already finished");
+        Poll::Ready(Ok((SecurityInfo::new(peer_id, protocol), io)))
+    }
+}
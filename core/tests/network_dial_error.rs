@@ -24,18 +24,21 @@ use futures::prelude::*;
 use libp2p_core::identity;
 use libp2p_core::multiaddr::{multiaddr, Multiaddr};
 use libp2p_core::{
+    ConnectedPoint,
+    Executor,
     Network,
     PeerId,
     Transport,
     connection::PendingConnectionError,
     muxing::StreamMuxerBox,
     network::{NetworkEvent, NetworkConfig},
+    task_names,
     transport,
     upgrade,
 };
 use rand::Rng;
 use rand::seq::SliceRandom;
-use std::{io, error::Error, fmt, task::Poll};
+use std::{io, error::Error, fmt, num::NonZeroU8, pin::Pin, sync::{Arc, Mutex}, task::Poll};
 use util::TestHandler;
 
 type TestNetwork = Network<TestTransport, (), (), TestHandler>;
@@ -244,6 +247,107 @@ fn multiple_addresses_err() {
     })).unwrap();
 }
 
+#[test]
+fn multiple_addresses_concurrent_err() {
+    // Tries dialing multiple addresses concurrently, and makes sure there's a single
+    // dialing error listing the errors of all addresses.
+
+    let mut cfg = NetworkConfig::default();
+    cfg.set_dial_concurrency_factor(NonZeroU8::new(3).unwrap());
+    let mut swarm = new_network(cfg);
+
+    let mut addresses = Vec::new();
+    for _ in 0 .. 3 {
+        addresses.push(multiaddr![Ip4([0, 0, 0, 0]), Tcp(rand::random::<u16>())]);
+    }
+    for _ in 0 .. 5 {
+        addresses.push(multiaddr![Udp(rand::random::<u16>())]);
+    }
+    addresses.shuffle(&mut rand::thread_rng());
+
+    let first = addresses[0].clone();
+    let rest = addresses[1..].iter().cloned();
+
+    let target = PeerId::random();
+    swarm.peer(target.clone())
+        .dial(first.clone(), rest, TestHandler())
+        .unwrap();
+
+    async_std::task::block_on(future::poll_fn(|cx| -> Poll<Result<(), io::Error>> {
+        loop {
+            match swarm.poll(cx) {
+                Poll::Ready(NetworkEvent::DialError {
+                    attempts_remaining,
+                    peer_id,
+                    multiaddr,
+                    error: PendingConnectionError::ConcurrentDial(errors)
+                }) => {
+                    assert_eq!(peer_id, target);
+                    assert_eq!(multiaddr, first);
+                    assert_eq!(attempts_remaining, 0);
+                    let mut failed = errors.into_iter().map(|(a, _)| a).collect::<Vec<_>>();
+                    failed.sort_by_key(|a| a.to_string());
+                    addresses.sort_by_key(|a| a.to_string());
+                    assert_eq!(failed, addresses);
+                    return Poll::Ready(Ok(()));
+                },
+                Poll::Ready(_) => unreachable!(),
+                Poll::Pending => break Poll::Pending,
+            }
+        }
+    })).unwrap();
+}
+
+#[test]
+fn concurrent_dial_success() {
+    // Dials a peer via several addresses concurrently, only one of which is
+    // reachable, and checks that the connection is established via that address.
+
+    let mut listener = new_open_network(NetworkConfig::default());
+    let mut cfg = NetworkConfig::default();
+    cfg.set_dial_concurrency_factor(NonZeroU8::new(2).unwrap());
+    let mut dialer = new_open_network(cfg);
+
+    listener.listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+
+    let address = async_std::task::block_on(future::poll_fn(|cx| {
+        if let Poll::Ready(NetworkEvent::NewListenerAddress { listen_addr, .. }) = listener.poll(cx) {
+            Poll::Ready(listen_addr)
+        } else {
+            panic!("Was expecting the listen address to be reported")
+        }
+    }));
+
+    let addresses = [
+        multiaddr![Udp(rand::random::<u16>())],
+        multiaddr![Udp(rand::random::<u16>())],
+        address.clone(),
+    ];
+    dialer
+        .peer(listener.local_peer_id().clone())
+        .dial(addresses[0].clone(), addresses[1..].iter().cloned(), TestHandler())
+        .unwrap();
+
+    async_std::task::block_on(future::poll_fn(|cx| -> Poll<()> {
+        // The connection handlers continuously produce events, hence
+        // both networks are only polled once per iteration.
+        if let Poll::Ready(NetworkEvent::IncomingConnection(inc)) = listener.poll(cx) {
+            inc.accept(TestHandler()).unwrap();
+        }
+        match dialer.poll(cx) {
+            Poll::Ready(NetworkEvent::ConnectionEstablished { connection, .. }) => {
+                assert_eq!(connection.peer_id(), listener.local_peer_id());
+                assert_eq!(connection.endpoint(), &ConnectedPoint::Dialer { address: address.clone() });
+                return Poll::Ready(())
+            }
+            Poll::Ready(ev) => panic!("Unexpected event: {:?}", ev),
+            Poll::Pending => {}
+        }
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }));
+}
+
 #[test]
 fn connection_limit() {
     let outgoing_per_peer_limit = rand::thread_rng().gen_range(1, 10);
@@ -284,3 +388,101 @@ fn connection_limit() {
     assert_eq!(err.current, outgoing_limit);
     assert_eq!(err.limit, outgoing_limit);
 }
+
+#[test]
+fn established_connection_limit() {
+    // Checks that connections established beyond the limit are refused.
+
+    let mut cfg = NetworkConfig::default();
+    cfg.set_established_limit(1);
+    let mut listener = new_open_network(cfg);
+    let mut dialer1 = new_open_network(NetworkConfig::default());
+    let mut dialer2 = new_open_network(NetworkConfig::default());
+
+    listener.listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+
+    let address = async_std::task::block_on(future::poll_fn(|cx| {
+        if let Poll::Ready(NetworkEvent::NewListenerAddress { listen_addr, .. }) = listener.poll(cx) {
+            Poll::Ready(listen_addr)
+        } else {
+            panic!("Was expecting the listen address to be reported")
+        }
+    }));
+
+    for dialer in &mut [&mut dialer1, &mut dialer2] {
+        dialer
+            .peer(listener.local_peer_id().clone())
+            .dial(address.clone(), Vec::new(), TestHandler())
+            .unwrap();
+    }
+
+    let mut established = 0;
+    let mut refused = 0;
+    async_std::task::block_on(future::poll_fn(|cx| -> Poll<()> {
+        loop {
+            match listener.poll(cx) {
+                Poll::Ready(NetworkEvent::IncomingConnection(inc)) => {
+                    inc.accept(TestHandler()).unwrap();
+                }
+                Poll::Ready(NetworkEvent::ConnectionEstablished { .. }) => established += 1,
+                Poll::Ready(NetworkEvent::IncomingConnectionError {
+                    error: PendingConnectionError::ConnectionLimit(limit), ..
+                }) => {
+                    assert_eq!(limit.limit, 1);
+                    assert_eq!(limit.current, 1);
+                    refused += 1;
+                }
+                Poll::Ready(_) => {}
+                Poll::Pending => break,
+            }
+            if established == 1 && refused == 1 {
+                assert_eq!(listener.num_connections_established(), 1);
+                return Poll::Ready(());
+            }
+        }
+        // The dialers only need to make progress.
+        let _ = dialer1.poll(cx);
+        let _ = dialer2.poll(cx);
+        Poll::Pending
+    }));
+}
+
+/// Like `new_network`, but keeps connections open once established.
+fn new_open_network(cfg: NetworkConfig) -> TestNetwork {
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_public_key = local_key.public();
+    let transport: TestTransport = libp2p_tcp::TcpConfig::new()
+        .upgrade(upgrade::Version::V1)
+        .authenticate(libp2p_secio::SecioConfig::new(local_key))
+        .multiplex(libp2p_mplex::MplexConfig::new())
+        .map(|(conn_info, muxer), _| (conn_info, StreamMuxerBox::new(muxer)))
+        .map_err(|e| BoxError(Box::new(e)))
+        .boxed();
+    TestNetwork::new(transport, local_public_key.into(), cfg)
+}
+
+#[test]
+fn named_tasks() {
+    // Checks that the background tasks of connections are spawned with
+    // their names on the configured executor.
+
+    struct RecordNames(Arc<Mutex<Vec<&'static str>>>);
+
+    impl Executor for RecordNames {
+        fn exec(&self, _: Pin<Box<dyn Future<Output = ()> + Send>>) {
+            panic!("Unnamed task spawned")
+        }
+
+        fn exec_named(&self, name: &'static str, f: Pin<Box<dyn Future<Output = ()> + Send>>) {
+            self.0.lock().unwrap().push(name);
+            async_std::task::spawn(f);
+        }
+    }
+
+    let names = Arc::new(Mutex::new(Vec::new()));
+    let mut cfg = NetworkConfig::default();
+    cfg.set_executor(Box::new(RecordNames(names.clone())));
+    let mut network = new_network(cfg);
+    network.dial(&"/ip4/127.0.0.1/tcp/1".parse().unwrap(), TestHandler()).unwrap();
+    assert_eq!(*names.lock().unwrap(), vec![task_names::PENDING_CONNECTION]);
+}
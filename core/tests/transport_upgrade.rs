@@ -21,14 +21,16 @@
 mod util;
 
 use futures::prelude::*;
-use libp2p_core::identity;
-use libp2p_core::transport::{Transport, MemoryTransport};
-use libp2p_core::upgrade::{self, UpgradeInfo, InboundUpgrade, OutboundUpgrade};
+use libp2p_core::{identity, ConnectedPoint, ConnectionDenied, ConnectionGater, ConnectionInfo, PeerId};
+use libp2p_core::{HandshakeObserver, HandshakeStep};
+use libp2p_core::either::{EitherError, EitherTransport};
+use libp2p_core::transport::{Transport, MemoryTransport, memory::MemoryTransportError, upgrade::StageError};
+use libp2p_core::upgrade::{self, NegotiationError, UpgradeInfo, InboundUpgrade, OutboundUpgrade};
 use libp2p_mplex::MplexConfig;
 use libp2p_secio::SecioConfig;
 use multiaddr::{Multiaddr, Protocol};
 use rand::random;
-use std::{io, pin::Pin};
+use std::{fmt, io, pin::Pin, sync::{Arc, Mutex}, thread, time::Duration};
 
 #[derive(Clone)]
 struct HelloUpgrade {}
@@ -135,3 +137,362 @@ fn upgrade_pipeline() {
     async_std::task::block_on(client);
 }
 
+
+#[test]
+fn upgrade_stage_errors() {
+    fn dialer_transport() -> impl Transport<Error = StageError<
+        MemoryTransportError,
+        libp2p_secio::SecioError,
+        io::Error,
+    >> {
+        MemoryTransport::default()
+            .upgrade(upgrade::Version::V1)
+            .authenticate(SecioConfig::new(identity::Keypair::generate_ed25519()))
+            .multiplex(MplexConfig::default())
+            .map_err(StageError::from)
+    }
+
+    // A listener that closes every connection fails the negotiation
+    // of the security protocol.
+    let addr = Multiaddr::from(Protocol::Memory(random::<u64>()));
+    let mut listener = MemoryTransport::default().listen_on(addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        while let Some(event) = listener.next().await {
+            if let Some((upgrade, _)) = event.unwrap().into_upgrade() {
+                drop(upgrade.await);
+            }
+        }
+    });
+    match async_std::task::block_on(dialer_transport().dial(addr).unwrap()) {
+        Err(StageError::SecurityNegotiation(_)) => {}
+        Err(e) => panic!("Unexpected error: {:?}", e),
+        Ok(_) => panic!("Unexpected success"),
+    }
+
+    // A listener that does not support the multiplexer fails the
+    // negotiation of the multiplexing protocol.
+    let addr = Multiaddr::from(Protocol::Memory(random::<u64>()));
+    let mut listener = MemoryTransport::default().listen_on(addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        while let Some(event) = listener.next().await {
+            if let Some((upgrade, _)) = event.unwrap().into_upgrade() {
+                let keys = identity::Keypair::generate_ed25519();
+                let socket = upgrade.await.unwrap();
+                let (_, socket) = upgrade::apply_inbound(socket, SecioConfig::new(keys)).await.unwrap();
+                let _ = upgrade::apply_inbound(socket, HelloUpgrade {}).await;
+            }
+        }
+    });
+    match async_std::task::block_on(dialer_transport().dial(addr).unwrap()) {
+        Err(StageError::MuxerNegotiation(NegotiationError::Failed)) => {}
+        Err(e) => panic!("Unexpected error: {:?}", e),
+        Ok(_) => panic!("Unexpected success"),
+    }
+}
+
+#[test]
+fn upgrade_gate() {
+    struct DenyPeer(PeerId);
+
+    impl ConnectionGater for DenyPeer {
+        fn intercept_secured(&self, peer_id: &PeerId, _: &ConnectedPoint) -> bool {
+            *peer_id != self.0
+        }
+    }
+
+    let listener_keys = identity::Keypair::generate_ed25519();
+    let listener_id = listener_keys.public().into_peer_id();
+    let listener_transport = MemoryTransport::default()
+        .upgrade(upgrade::Version::V1)
+        .authenticate(SecioConfig::new(listener_keys))
+        .multiplex(MplexConfig::default())
+        .and_then(|(peer, mplex), _| {
+            util::CloseMuxer::new(mplex).map_ok(move |mplex| (peer, mplex))
+        });
+
+    let addr = Multiaddr::from(Protocol::Memory(random::<u64>()));
+    let mut listener = listener_transport.listen_on(addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        while let Some(event) = listener.next().await {
+            if let Some((upgrade, _)) = event.unwrap().into_upgrade() {
+                async_std::task::spawn(async move { drop(upgrade.await) });
+            }
+        }
+    });
+
+    let dial = |gater: DenyPeer| {
+        let transport = MemoryTransport::default()
+            .upgrade(upgrade::Version::V1)
+            .authenticate(SecioConfig::new(identity::Keypair::generate_ed25519()))
+            .gate(Arc::new(gater))
+            .multiplex(MplexConfig::default());
+        async_std::task::block_on(transport.dial(addr.clone()).unwrap())
+    };
+
+    match dial(DenyPeer(listener_id.clone())) {
+        Err(EitherError::A(EitherError::B(ConnectionDenied))) => {}
+        Err(e) => panic!("Unexpected error: {:?}", e),
+        Ok(_) => panic!("Unexpected success"),
+    }
+
+    let (peer, _mplex) = dial(DenyPeer(PeerId::random())).unwrap();
+    assert_eq!(peer, listener_id);
+}
+
+#[test]
+fn upgrade_observer() {
+    #[derive(Default)]
+    struct Observed {
+        steps: Mutex<Vec<(HandshakeStep, bool)>>,
+        failures: Mutex<Vec<(HandshakeStep, Vec<String>)>>,
+    }
+
+    impl HandshakeObserver for Observed {
+        fn on_step(&self, step: HandshakeStep, endpoint: &ConnectedPoint, _: Duration, success: bool) {
+            assert!(endpoint.is_dialer());
+            self.steps.lock().unwrap().push((step, success));
+        }
+
+        fn on_negotiation_failure(
+            &self,
+            step: HandshakeStep,
+            _: &ConnectedPoint,
+            protocols: &[String],
+            error: &NegotiationError
+        ) {
+            assert!(matches!(error, NegotiationError::Failed));
+            self.failures.lock().unwrap().push((step, protocols.to_vec()));
+        }
+    }
+
+    // A listener that does not support the multiplexer.
+    let addr = Multiaddr::from(Protocol::Memory(random::<u64>()));
+    let mut listener = MemoryTransport.listen_on(addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        while let Some(event) = listener.next().await {
+            if let Some((upgrade, _)) = event.unwrap().into_upgrade() {
+                let keys = identity::Keypair::generate_ed25519();
+                let socket = upgrade.await.unwrap();
+                let (_, socket) = upgrade::apply_inbound(socket, SecioConfig::new(keys)).await.unwrap();
+                let _ = upgrade::apply_inbound(socket, HelloUpgrade {}).await;
+            }
+        }
+    });
+
+    let observed = Arc::new(Observed::default());
+    let dialer_transport = MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .observe(observed.clone())
+        .authenticate(SecioConfig::new(identity::Keypair::generate_ed25519()))
+        .multiplex(MplexConfig::default());
+    assert!(async_std::task::block_on(dialer_transport.dial(addr).unwrap()).is_err());
+
+    assert_eq!(*observed.steps.lock().unwrap(), vec![
+        (HandshakeStep::Connect, true),
+        (HandshakeStep::Security, true),
+        (HandshakeStep::Multiplexing, false),
+    ]);
+    assert_eq!(*observed.failures.lock().unwrap(), vec![
+        (HandshakeStep::Multiplexing, vec!["/mplex/6.7.0".to_string()]),
+    ]);
+}
+
+#[test]
+fn upgrade_security_info() {
+    let listener_keys = identity::Keypair::generate_ed25519();
+    let listener_id = listener_keys.public().into_peer_id();
+    let listener_transport = MemoryTransport::default()
+        .upgrade(upgrade::Version::V1)
+        .authenticate(upgrade::WithSecurityInfo::new(SecioConfig::new(listener_keys)))
+        .multiplex(MplexConfig::default())
+        .and_then(|(info, mplex), _| {
+            util::CloseMuxer::new(mplex).map_ok(move |mplex| (info, mplex))
+        });
+
+    let dialer_keys = identity::Keypair::generate_ed25519();
+    let dialer_id = dialer_keys.public().into_peer_id();
+    let dialer_transport = MemoryTransport::default()
+        .upgrade(upgrade::Version::V1)
+        .authenticate(upgrade::WithSecurityInfo::new(SecioConfig::new(dialer_keys)))
+        .multiplex(MplexConfig::default());
+
+    let addr = Multiaddr::from(Protocol::Memory(random::<u64>()));
+    let mut listener = listener_transport.listen_on(addr.clone()).unwrap();
+
+    let server = async move {
+        loop {
+            let (upgrade, _remote_addr) =
+                match listener.next().await.unwrap().unwrap().into_upgrade() {
+                    Some(u) => u,
+                    None => continue
+                };
+            let (info, _mplex) = upgrade.await.unwrap();
+            assert_eq!(info.peer_id(), &dialer_id);
+            assert_eq!(info.security_protocol(), b"/secio/1.0.0");
+        }
+    };
+
+    let client = async move {
+        let (info, _mplex) = dialer_transport.dial(addr).unwrap().await.unwrap();
+        assert_eq!(info.peer_id(), &listener_id);
+        assert_eq!(info.security_protocol(), b"/secio/1.0.0");
+    };
+
+    async_std::task::spawn(server);
+    async_std::task::block_on(client);
+}
+
+#[test]
+fn upgrade_either() {
+    // The transport, security and multiplexing protocols are chosen at runtime
+    // behind a single type, with different choices on the listener and the dialer.
+    fn choose(keys: identity::Keypair, first: bool) -> (
+        EitherTransport<MemoryTransport, MemoryTransport>,
+        upgrade::EitherSecurityUpgrade<SecioConfig, SecioConfig>,
+        upgrade::EitherUpgrade<MplexConfig, MplexConfig>,
+    ) {
+        if first {
+            (
+                EitherTransport::Left(MemoryTransport::default()),
+                upgrade::EitherSecurityUpgrade::A(SecioConfig::new(keys)),
+                upgrade::EitherUpgrade::A(MplexConfig::default()),
+            )
+        } else {
+            (
+                EitherTransport::Right(MemoryTransport::default()),
+                upgrade::EitherSecurityUpgrade::B(SecioConfig::new(keys)),
+                upgrade::EitherUpgrade::B(MplexConfig::default()),
+            )
+        }
+    }
+
+    let listener_keys = identity::Keypair::generate_ed25519();
+    let listener_id = listener_keys.public().into_peer_id();
+    let (transport, security, muxer) = choose(listener_keys, true);
+    let listener_transport = transport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(security)
+        .multiplex(muxer)
+        .and_then(|(peer, muxer), _| {
+            util::CloseMuxer::new(muxer).map_ok(move |muxer| (peer, muxer))
+        });
+
+    let dialer_keys = identity::Keypair::generate_ed25519();
+    let dialer_id = dialer_keys.public().into_peer_id();
+    let (transport, security, muxer) = choose(dialer_keys, false);
+    let dialer_transport = transport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(security)
+        .multiplex(muxer);
+
+    let addr = Multiaddr::from(Protocol::Memory(random::<u64>()));
+    let mut listener = listener_transport.listen_on(addr.clone()).unwrap();
+
+    let server = async move {
+        loop {
+            let (upgrade, _remote_addr) =
+                match listener.next().await.unwrap().unwrap().into_upgrade() {
+                    Some(u) => u,
+                    None => continue
+                };
+            let (peer, _muxer) = upgrade.await.unwrap();
+            assert_eq!(peer, dialer_id);
+        }
+    };
+
+    let client = async move {
+        let (peer, _muxer) = dialer_transport.dial(addr).unwrap().await.unwrap();
+        assert_eq!(peer, listener_id);
+    };
+
+    async_std::task::spawn(server);
+    async_std::task::block_on(client);
+}
+
+#[test]
+fn upgrade_spans() {
+    // Records the names of the spans created on the thread of the dialer,
+    // together with the recorded negotiated protocols.
+    struct RecordSpans {
+        thread: thread::ThreadId,
+        spans: Arc<Mutex<Vec<(&'static str, Vec<String>)>>>,
+    }
+
+    struct RecordProtocol<'a>(&'a mut Vec<String>);
+
+    impl tracing::field::Visit for RecordProtocol<'_> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+            if field.name() == "protocol" {
+                self.0.push(format!("{:?}", value));
+            }
+        }
+    }
+
+    impl tracing::Subscriber for RecordSpans {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool { true }
+
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            if thread::current().id() != self.thread {
+                return tracing::span::Id::from_u64(u64::MAX)
+            }
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((span.metadata().name(), Vec::new()));
+            tracing::span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, id: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+            let mut spans = self.spans.lock().unwrap();
+            if let Some((_, protocols)) = spans.get_mut(id.into_u64() as usize - 1) {
+                values.record(&mut RecordProtocol(protocols));
+            }
+        }
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+        fn event(&self, _: &tracing::Event<'_>) {}
+        fn enter(&self, _: &tracing::span::Id) {}
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    let spans = Arc::new(Mutex::new(Vec::new()));
+    tracing::subscriber::set_global_default(RecordSpans {
+        thread: thread::current().id(),
+        spans: spans.clone(),
+    }).unwrap();
+
+    let listener_keys = identity::Keypair::generate_ed25519();
+    let listener_transport = MemoryTransport::default()
+        .upgrade(upgrade::Version::V1)
+        .authenticate(SecioConfig::new(listener_keys))
+        .multiplex(MplexConfig::default())
+        .and_then(|(peer, mplex), _| {
+            // Gracefully close the connection to allow protocol
+            // negotiation to complete.
+            util::CloseMuxer::new(mplex).map_ok(move |mplex| (peer, mplex))
+        });
+
+    let dialer_keys = identity::Keypair::generate_ed25519();
+    let dialer_transport = MemoryTransport::default()
+        .upgrade(upgrade::Version::V1)
+        .authenticate(SecioConfig::new(dialer_keys))
+        .multiplex(MplexConfig::default());
+
+    let listen_addr = Multiaddr::from(Protocol::Memory(random::<u64>()));
+    let mut listener = listener_transport.listen_on(listen_addr.clone()).unwrap();
+
+    async_std::task::spawn(async move {
+        loop {
+            if let Some((upgrade, _)) = listener.next().await.unwrap().unwrap().into_upgrade() {
+                let _ = upgrade.await;
+            }
+        }
+    });
+
+    async_std::task::block_on(dialer_transport.dial(listen_addr).unwrap()).unwrap();
+
+    assert_eq!(*spans.lock().unwrap(), vec![
+        ("security", vec![]),
+        ("outbound_upgrade", vec!["/secio/1.0.0".to_string()]),
+        ("multiplexing", vec![]),
+        ("outbound_upgrade", vec!["/mplex/6.7.0".to_string()]),
+    ]);
+}
@@ -121,7 +121,11 @@ fn main() -> Result<(), Box<dyn Error>> {
     task::block_on(future::poll_fn(move |cx: &mut Context<'_>| {
         loop {
             match stdin.try_poll_next_unpin(cx)? {
-                Poll::Ready(Some(line)) => swarm.publish(&topic, line.as_bytes()),
+                Poll::Ready(Some(line)) => {
+                    if let Err(e) = swarm.publish(&topic, line.as_bytes()) {
+                        println!("Publish error: {}", e);
+                    }
+                }
                 Poll::Ready(None) => panic!("Stdin closed"),
                 Poll::Pending => break,
             };
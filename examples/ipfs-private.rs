@@ -282,7 +282,9 @@ fn main() -> Result<(), Box<dyn Error>> {
         loop {
             match stdin.try_poll_next_unpin(cx)? {
                 Poll::Ready(Some(line)) => {
-                    swarm.gossipsub.publish(&gossipsub_topic, line.as_bytes());
+                    if let Err(e) = swarm.gossipsub.publish(&gossipsub_topic, line.as_bytes()) {
+                        println!("Publish error: {}", e);
+                    }
                 }
                 Poll::Ready(None) => panic!("Stdin closed"),
                 Poll::Pending => break,
@@ -0,0 +1,88 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_core::upgrade::DeniedUpgrade;
+use libp2p_swarm::{
+    KeepAlive,
+    SubstreamProtocol,
+    ProtocolsHandler,
+    ProtocolsHandlerUpgrErr,
+    ProtocolsHandlerEvent
+};
+use std::{task::{Context, Poll}, time::Duration};
+use void::Void;
+use wasm_timer::Instant;
+
+/// The time a new connection is kept alive until the [`Bootstrap`](crate::Bootstrap)
+/// behaviour decided whether it is a connection to a bootstrap peer.
+const INITIAL_KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+/// Protocol handler that keeps connections to bootstrap peers alive.
+///
+/// The handler supports no protocols. It receives `true` from the behaviour
+/// if the connection is to be kept alive and `false` otherwise.
+pub struct BootstrapHandler {
+    keep_alive: KeepAlive,
+}
+
+impl Default for BootstrapHandler {
+    fn default() -> Self {
+        BootstrapHandler {
+            keep_alive: KeepAlive::Until(Instant::now() + INITIAL_KEEP_ALIVE),
+        }
+    }
+}
+
+impl ProtocolsHandler for BootstrapHandler {
+    type InEvent = bool;
+    type OutEvent = Void;
+    type Error = Void;
+    type InboundProtocol = DeniedUpgrade;
+    type OutboundProtocol = DeniedUpgrade;
+    type OutboundOpenInfo = Void;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<DeniedUpgrade> {
+        SubstreamProtocol::new(DeniedUpgrade)
+    }
+
+    fn inject_fully_negotiated_inbound(&mut self, protocol: Void) {
+        void::unreachable(protocol)
+    }
+
+    fn inject_fully_negotiated_outbound(&mut self, protocol: Void, _: Void) {
+        void::unreachable(protocol)
+    }
+
+    fn inject_event(&mut self, keep_alive: bool) {
+        self.keep_alive = if keep_alive { KeepAlive::Yes } else { KeepAlive::No };
+    }
+
+    fn inject_dial_upgrade_error(&mut self, info: Void, _: ProtocolsHandlerUpgrErr<Void>) {
+        void::unreachable(info)
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        self.keep_alive
+    }
+
+    fn poll(&mut self, _: &mut Context<'_>) -> Poll<ProtocolsHandlerEvent<DeniedUpgrade, Void, Void, Void>> {
+        Poll::Pending
+    }
+}
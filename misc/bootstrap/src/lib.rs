@@ -0,0 +1,473 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Maintains connections to a set of bootstrap peers.
+//!
+//! The [`Bootstrap`] behaviour dials the configured bootstrap addresses as
+//! soon as it is polled for the first time. Whenever a dialing attempt fails
+//! or an established connection to a bootstrap address is closed, the address
+//! is redialed after a backoff that doubles with every consecutive failure,
+//! up to a configurable maximum.
+//!
+//! Connections established by dialing a bootstrap address are kept alive,
+//! even if no other behaviour uses them.
+//!
+//! The behaviour reports a [`BootstrapEvent`] whenever the number of connected
+//! bootstrap peers reaches or drops below the configured minimum, so that the
+//! application knows whether the node is (still) connected to the network.
+
+mod handler;
+
+pub use handler::BootstrapHandler;
+
+use libp2p_core::{
+    ConnectedPoint,
+    Multiaddr,
+    PeerId,
+    connection::ConnectionId,
+};
+use libp2p_swarm::{
+    NetworkBehaviour,
+    NetworkBehaviourAction,
+    NotifyHandler,
+    PollParameters,
+    ProtocolsHandler,
+};
+use futures::prelude::*;
+use std::{
+    collections::{HashSet, VecDeque},
+    error,
+    task::{Context, Poll},
+    time::Duration,
+};
+use wasm_timer::Delay;
+
+/// The configuration of a [`Bootstrap`] behaviour.
+#[derive(Debug, Clone)]
+pub struct BootstrapConfig {
+    min_connections: usize,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    dial_timeout: Duration,
+}
+
+impl Default for BootstrapConfig {
+    fn default() -> Self {
+        BootstrapConfig {
+            min_connections: 1,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5 * 60),
+            dial_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl BootstrapConfig {
+    /// Sets the minimum number of bootstrap peers the node should be
+    /// connected to.
+    ///
+    /// Defaults to 1.
+    pub fn set_min_connections(&mut self, min: usize) -> &mut Self {
+        self.min_connections = min;
+        self
+    }
+
+    /// Sets the backoff before redialing an address after its connection
+    /// was closed or after the first failed dialing attempt. The backoff
+    /// doubles with every consecutive failed attempt.
+    ///
+    /// Defaults to 1 second.
+    pub fn set_initial_backoff(&mut self, backoff: Duration) -> &mut Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Sets the maximum backoff between dialing attempts to an address.
+    ///
+    /// Defaults to 5 minutes.
+    pub fn set_max_backoff(&mut self, backoff: Duration) -> &mut Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Sets the time after which a dialing attempt that has been reported
+    /// neither as successful nor as failed is considered failed.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn set_dial_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.dial_timeout = timeout;
+        self
+    }
+
+    /// Returns the backoff after the given number of consecutive failures.
+    fn backoff(&self, failures: u32) -> Duration {
+        self.initial_backoff
+            .checked_mul(2u32.saturating_pow(failures))
+            .map_or(self.max_backoff, |b| b.min(self.max_backoff))
+    }
+}
+
+/// Event generated by the [`Bootstrap`] behaviour.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BootstrapEvent {
+    /// The number of connected bootstrap peers reached the configured
+    /// minimum.
+    MinimumReached {
+        /// The number of connected bootstrap peers.
+        connected: usize,
+    },
+    /// The number of connected bootstrap peers dropped below the
+    /// configured minimum.
+    BelowMinimum {
+        /// The number of connected bootstrap peers.
+        connected: usize,
+    },
+}
+
+/// The state of a single bootstrap address.
+#[derive(Debug)]
+enum State {
+    /// The address is to be dialed on the next poll.
+    Pending,
+    /// A dialing attempt is in progress.
+    Dialing(Delay),
+    /// A connection has been established by dialing the address.
+    Connected(PeerId, ConnectionId),
+    /// The address is redialed once the delay expires.
+    Backoff(Delay),
+}
+
+#[derive(Debug)]
+struct BootstrapAddress {
+    address: Multiaddr,
+    state: State,
+    /// The number of consecutive failed dialing attempts.
+    failures: u32,
+}
+
+/// A [`NetworkBehaviour`] maintaining connections to a set of bootstrap
+/// addresses.
+pub struct Bootstrap {
+    config: BootstrapConfig,
+    addresses: Vec<BootstrapAddress>,
+    /// Whether the minimum number of connections was met when the
+    /// connected bootstrap peers last changed.
+    minimum_met: bool,
+    /// Queued events to return when the behaviour is being polled.
+    queued_events: VecDeque<NetworkBehaviourAction<bool, BootstrapEvent>>,
+}
+
+impl Bootstrap {
+    /// Creates a `Bootstrap` behaviour that dials the given addresses.
+    pub fn new(config: BootstrapConfig, addresses: impl IntoIterator<Item = Multiaddr>) -> Self {
+        let mut bootstrap = Bootstrap {
+            config,
+            addresses: Vec::new(),
+            minimum_met: false,
+            queued_events: VecDeque::new(),
+        };
+        for address in addresses {
+            bootstrap.add_address(address);
+        }
+        bootstrap
+    }
+
+    /// Adds a bootstrap address, which is dialed on the next poll.
+    ///
+    /// Returns `false` if the address is already known.
+    pub fn add_address(&mut self, address: Multiaddr) -> bool {
+        if self.addresses.iter().any(|a| a.address == address) {
+            return false
+        }
+        self.addresses.push(BootstrapAddress { address, state: State::Pending, failures: 0 });
+        true
+    }
+
+    /// Removes a bootstrap address, which is no longer redialed. An existing
+    /// connection established by dialing the address is no longer kept alive.
+    ///
+    /// Returns `true` if the address was known.
+    pub fn remove_address(&mut self, address: &Multiaddr) -> bool {
+        let pos = match self.addresses.iter().position(|a| &a.address == address) {
+            Some(pos) => pos,
+            None => return false,
+        };
+        if let State::Connected(peer_id, conn) = self.addresses.remove(pos).state {
+            self.queued_events.push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id,
+                handler: NotifyHandler::One(conn),
+                event: false,
+            });
+            self.update_minimum();
+        }
+        true
+    }
+
+    /// Returns an iterator over the bootstrap addresses.
+    pub fn addresses(&self) -> impl Iterator<Item = &Multiaddr> {
+        self.addresses.iter().map(|a| &a.address)
+    }
+
+    /// Returns the number of bootstrap peers we are connected to.
+    pub fn connected(&self) -> usize {
+        self.addresses.iter()
+            .filter_map(|a| match &a.state {
+                State::Connected(peer_id, _) => Some(peer_id),
+                _ => None,
+            })
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Returns whether we are connected to at least the configured minimum
+    /// number of bootstrap peers.
+    pub fn is_minimum_met(&self) -> bool {
+        self.connected() >= self.config.min_connections
+    }
+
+    /// Reports an event if the minimum number of connections was reached or
+    /// is no longer met.
+    fn update_minimum(&mut self) {
+        let connected = self.connected();
+        let minimum_met = connected >= self.config.min_connections;
+        if minimum_met != self.minimum_met {
+            self.minimum_met = minimum_met;
+            let event = if minimum_met {
+                BootstrapEvent::MinimumReached { connected }
+            } else {
+                BootstrapEvent::BelowMinimum { connected }
+            };
+            self.queued_events.push_back(NetworkBehaviourAction::GenerateEvent(event));
+        }
+    }
+}
+
+impl BootstrapAddress {
+    /// Schedules the address to be redialed after a backoff depending on the
+    /// number of consecutive failures.
+    fn backoff(&mut self, config: &BootstrapConfig) {
+        let backoff = config.backoff(self.failures);
+        log::debug!("Redialing bootstrap address {} in {:?}.", self.address, backoff);
+        self.state = State::Backoff(Delay::new(backoff));
+    }
+
+    /// Records a failed dialing attempt and schedules the next one.
+    fn dial_failed(&mut self, config: &BootstrapConfig) {
+        self.backoff(config);
+        self.failures = self.failures.saturating_add(1);
+    }
+}
+
+impl NetworkBehaviour for Bootstrap {
+    type ProtocolsHandler = BootstrapHandler;
+    type OutEvent = BootstrapEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        BootstrapHandler::default()
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, _: &PeerId) {}
+
+    fn inject_disconnected(&mut self, _: &PeerId) {}
+
+    fn inject_connection_established(&mut self, peer_id: &PeerId, conn: &ConnectionId, endpoint: &ConnectedPoint) {
+        if let ConnectedPoint::Dialer { address } = endpoint {
+            let entry = self.addresses.iter_mut()
+                .find(|a| &a.address == address && !matches!(a.state, State::Connected(..)));
+            if let Some(a) = entry {
+                a.state = State::Connected(peer_id.clone(), *conn);
+                a.failures = 0;
+                self.queued_events.push_back(NetworkBehaviourAction::NotifyHandler {
+                    peer_id: peer_id.clone(),
+                    handler: NotifyHandler::One(*conn),
+                    event: true,
+                });
+                self.update_minimum();
+            }
+        }
+    }
+
+    fn inject_connection_closed(&mut self, peer_id: &PeerId, conn: &ConnectionId, _: &ConnectedPoint) {
+        let config = &self.config;
+        let entry = self.addresses.iter_mut().find(|a| match &a.state {
+            State::Connected(p, c) => p == peer_id && c == conn,
+            _ => false,
+        });
+        if let Some(a) = entry {
+            a.backoff(config);
+            self.update_minimum();
+        }
+    }
+
+    fn inject_addr_reach_failure(&mut self, _: Option<&PeerId>, addr: &Multiaddr, _: &dyn error::Error) {
+        let config = &self.config;
+        let entry = self.addresses.iter_mut()
+            .find(|a| &a.address == addr && matches!(a.state, State::Dialing(_)));
+        if let Some(a) = entry {
+            a.dial_failed(config);
+        }
+    }
+
+    fn inject_event(&mut self, _: PeerId, _: ConnectionId,
+        ev: <Self::ProtocolsHandler as ProtocolsHandler>::OutEvent)
+    {
+        void::unreachable(ev)
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>, _: &mut impl PollParameters) ->
+        Poll<NetworkBehaviourAction<<Self::ProtocolsHandler as ProtocolsHandler>::InEvent, Self::OutEvent>>
+    {
+        if let Some(event) = self.queued_events.pop_front() {
+            return Poll::Ready(event)
+        }
+
+        let config = &self.config;
+        for a in &mut self.addresses {
+            if let State::Dialing(timeout) = &mut a.state {
+                if timeout.poll_unpin(cx).is_ready() {
+                    log::debug!("Dialing bootstrap address {} timed out.", a.address);
+                    a.dial_failed(config);
+                }
+            }
+            if let State::Backoff(delay) = &mut a.state {
+                if delay.poll_unpin(cx).is_ready() {
+                    a.state = State::Pending;
+                }
+            }
+            if let State::Pending = a.state {
+                a.state = State::Dialing(Delay::new(config.dial_timeout));
+                return Poll::Ready(NetworkBehaviourAction::DialAddress { address: a.address.clone() })
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> Multiaddr {
+        s.parse().unwrap()
+    }
+
+    fn generated_events(bootstrap: &mut Bootstrap) -> Vec<BootstrapEvent> {
+        bootstrap.queued_events.drain(..)
+            .filter_map(|e| match e {
+                NetworkBehaviourAction::GenerateEvent(e) => Some(e),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn dialing(bootstrap: &mut Bootstrap) {
+        for a in &mut bootstrap.addresses {
+            a.state = State::Dialing(Delay::new(Duration::from_secs(30)));
+        }
+    }
+
+    #[test]
+    fn backoff_is_exponential_and_capped() {
+        let mut config = BootstrapConfig::default();
+        config.set_initial_backoff(Duration::from_secs(1)).set_max_backoff(Duration::from_secs(10));
+        assert_eq!(config.backoff(0), Duration::from_secs(1));
+        assert_eq!(config.backoff(1), Duration::from_secs(2));
+        assert_eq!(config.backoff(3), Duration::from_secs(8));
+        assert_eq!(config.backoff(4), Duration::from_secs(10));
+        assert_eq!(config.backoff(u32::MAX), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn failures_reset_on_connection() {
+        let mut bootstrap = Bootstrap::new(BootstrapConfig::default(), vec![addr("/memory/1")]);
+        dialing(&mut bootstrap);
+        let error = std::io::Error::new(std::io::ErrorKind::Other, "unreachable");
+        bootstrap.inject_addr_reach_failure(None, &addr("/memory/1"), &error);
+        assert_eq!(bootstrap.addresses[0].failures, 1);
+        assert!(matches!(bootstrap.addresses[0].state, State::Backoff(_)));
+
+        // Failures of addresses that are not being dialed are ignored.
+        bootstrap.inject_addr_reach_failure(None, &addr("/memory/1"), &error);
+        assert_eq!(bootstrap.addresses[0].failures, 1);
+
+        dialing(&mut bootstrap);
+        let endpoint = ConnectedPoint::Dialer { address: addr("/memory/1") };
+        bootstrap.inject_connection_established(&PeerId::random(), &ConnectionId::new(0), &endpoint);
+        assert_eq!(bootstrap.addresses[0].failures, 0);
+    }
+
+    #[test]
+    fn keep_bootstrap_connections_alive() {
+        let mut bootstrap = Bootstrap::new(BootstrapConfig::default(), vec![addr("/memory/1")]);
+        dialing(&mut bootstrap);
+        let peer = PeerId::random();
+        let endpoint = ConnectedPoint::Dialer { address: addr("/memory/1") };
+        bootstrap.inject_connection_established(&peer, &ConnectionId::new(0), &endpoint);
+        match bootstrap.queued_events.pop_front() {
+            Some(NetworkBehaviourAction::NotifyHandler { peer_id, event: true, .. }) => assert_eq!(peer_id, peer),
+            _ => panic!("Expected the handler to be notified."),
+        }
+
+        bootstrap.queued_events.clear();
+        assert!(bootstrap.remove_address(&addr("/memory/1")));
+        assert!(matches!(bootstrap.queued_events.pop_front(),
+            Some(NetworkBehaviourAction::NotifyHandler { event: false, .. })));
+        assert!(!bootstrap.remove_address(&addr("/memory/1")));
+    }
+
+    #[test]
+    fn minimum_connections() {
+        let mut config = BootstrapConfig::default();
+        config.set_min_connections(2);
+        let addresses = vec![addr("/memory/1"), addr("/memory/2"), addr("/memory/3")];
+        let mut bootstrap = Bootstrap::new(config, addresses);
+        dialing(&mut bootstrap);
+
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+        let dialer = |a: &str| ConnectedPoint::Dialer { address: addr(a) };
+        let listener = ConnectedPoint::Listener {
+            local_addr: addr("/memory/4"),
+            send_back_addr: addr("/memory/5"),
+        };
+
+        bootstrap.inject_connection_established(&alice, &ConnectionId::new(0), &dialer("/memory/1"));
+        // Connections to other addresses and a second connection to the same
+        // peer do not count.
+        bootstrap.inject_connection_established(&bob, &ConnectionId::new(1), &listener);
+        bootstrap.inject_connection_established(&alice, &ConnectionId::new(2), &dialer("/memory/2"));
+        assert_eq!(bootstrap.connected(), 1);
+        assert!(generated_events(&mut bootstrap).is_empty());
+
+        bootstrap.inject_connection_established(&bob, &ConnectionId::new(3), &dialer("/memory/3"));
+        assert!(bootstrap.is_minimum_met());
+        assert_eq!(generated_events(&mut bootstrap), vec![BootstrapEvent::MinimumReached { connected: 2 }]);
+
+        bootstrap.inject_connection_closed(&bob, &ConnectionId::new(1), &listener);
+        assert!(generated_events(&mut bootstrap).is_empty());
+        bootstrap.inject_connection_closed(&bob, &ConnectionId::new(3), &dialer("/memory/3"));
+        assert!(matches!(bootstrap.addresses[2].state, State::Backoff(_)));
+        assert_eq!(generated_events(&mut bootstrap), vec![BootstrapEvent::BelowMinimum { connected: 1 }]);
+    }
+}
@@ -0,0 +1,94 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+use futures::executor::block_on;
+use libp2p_bootstrap::{Bootstrap, BootstrapConfig, BootstrapEvent};
+use libp2p_core::{
+    identity,
+    multiaddr::{Multiaddr, Protocol},
+    muxing::StreamMuxerBox,
+    transport::{boxed::Boxed, MemoryTransport, Transport},
+    upgrade,
+    PeerId,
+};
+use libp2p_plaintext::PlainText2Config;
+use libp2p_swarm::{Swarm, SwarmEvent};
+use libp2p_yamux as yamux;
+use std::{io, time::Duration};
+
+#[test]
+fn redial_bootstrap_peer() {
+    let (bob_id, trans) = mk_transport();
+    let mut bob = Swarm::new(trans, Bootstrap::new(BootstrapConfig::default(), Vec::new()), bob_id.clone());
+    let bob_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    Swarm::listen_on(&mut bob, bob_addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        // Bob closes the first connection from Alice.
+        let mut closed = false;
+        loop {
+            if let SwarmEvent::ConnectionEstablished { peer_id, .. } = bob.next_event().await {
+                if !closed {
+                    closed = true;
+                    Swarm::ban_peer_id(&mut bob, peer_id.clone());
+                    Swarm::unban_peer_id(&mut bob, peer_id);
+                }
+            }
+        }
+    });
+
+    let mut config = BootstrapConfig::default();
+    config.set_initial_backoff(Duration::from_millis(100));
+    let unreachable: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    let (alice_id, trans) = mk_transport();
+    let mut alice = Swarm::new(trans, Bootstrap::new(config, vec![unreachable, bob_addr]), alice_id);
+
+    block_on(async {
+        assert_eq!(next_bootstrap_event(&mut alice).await, BootstrapEvent::MinimumReached { connected: 1 });
+        assert_eq!(next_bootstrap_event(&mut alice).await, BootstrapEvent::BelowMinimum { connected: 0 });
+
+        // Bob is redialed after the connection has been closed and the
+        // new connection is kept alive.
+        assert_eq!(next_bootstrap_event(&mut alice).await, BootstrapEvent::MinimumReached { connected: 1 });
+        let idle = async_std::future::timeout(Duration::from_secs(1), next_bootstrap_event(&mut alice)).await;
+        assert!(idle.is_err());
+        let peers = Swarm::connections(&mut alice).into_iter().map(|(_, _, p)| p).collect::<Vec<_>>();
+        assert_eq!(peers, vec![bob_id]);
+    });
+}
+
+async fn next_bootstrap_event(swarm: &mut Swarm<Bootstrap>) -> BootstrapEvent {
+    loop {
+        if let SwarmEvent::Behaviour(event) = swarm.next_event().await {
+            return event
+        }
+    }
+}
+
+fn mk_transport() -> (PeerId, Boxed<(PeerId, StreamMuxerBox), io::Error>) {
+    let id_keys = identity::Keypair::generate_ed25519();
+    let peer_id = id_keys.public().into_peer_id();
+    let transport = MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(PlainText2Config { local_public_key: id_keys.public() })
+        .multiplex(yamux::Config::default())
+        .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+        .boxed();
+    (peer_id, transport)
+}
@@ -450,8 +450,20 @@ fn build_struct(ast: &DeriveInput, data_struct: &DataStruct) -> TokenStream {
                             event: #wrapped_event,
                         });
                     }
-                    std::task::Poll::Ready(#network_behaviour_action::ReportObservedAddr { address }) => {
-                        return std::task::Poll::Ready(#network_behaviour_action::ReportObservedAddr { address });
+                    std::task::Poll::Ready(#network_behaviour_action::ReportObservedAddr { address, peer_id }) => {
+                        return std::task::Poll::Ready(#network_behaviour_action::ReportObservedAddr { address, peer_id });
+                    }
+                    std::task::Poll::Ready(#network_behaviour_action::AddExternalAddr { address }) => {
+                        return std::task::Poll::Ready(#network_behaviour_action::AddExternalAddr { address });
+                    }
+                    std::task::Poll::Ready(#network_behaviour_action::ConfirmExternalAddr { address }) => {
+                        return std::task::Poll::Ready(#network_behaviour_action::ConfirmExternalAddr { address });
+                    }
+                    std::task::Poll::Ready(#network_behaviour_action::RemoveExternalAddr { address }) => {
+                        return std::task::Poll::Ready(#network_behaviour_action::RemoveExternalAddr { address });
+                    }
+                    std::task::Poll::Ready(#network_behaviour_action::UpdateSupportedProtocols) => {
+                        return std::task::Poll::Ready(#network_behaviour_action::UpdateSupportedProtocols);
                     }
                     std::task::Poll::Pending => break,
                 }
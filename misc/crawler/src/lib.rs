@@ -0,0 +1,633 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! A crawler of the Kademlia DHT.
+//!
+//! The [`Crawler`] is a `NetworkBehaviour` that walks the DHT starting from
+//! the peers in its routing table, by looking up the closest peers to random
+//! keys and to every peer it discovers. Every discovered peer is dialed and
+//! identified with the identify protocol.
+//!
+//! Each crawled peer is reported through a [`CrawlerEvent::PeerCrawled`]
+//! event. Once no lookups are left and every discovered peer has been
+//! identified or found unreachable, the crawl ends with a
+//! [`CrawlerEvent::Finished`] event carrying a [`CrawlReport`] of the agent
+//! versions, protocols and addresses of all peers.
+//!
+//! # Usage
+//!
+//! Add the addresses of some peers with [`Crawler::add_address`], e.g. the
+//! bootstrap nodes of the network, then start a crawl with
+//! [`Crawler::start`] and poll the `Swarm` until the crawl is finished.
+
+use libp2p_core::{Multiaddr, PeerId, PublicKey, connection::{ConnectionId, ListenerId}, ConnectedPoint, either::EitherOutput};
+use libp2p_identify::{Identify, IdentifyEvent, IdentifyInfo};
+use libp2p_kad::{
+    GetClosestPeersError,
+    GetClosestPeersOk,
+    Kademlia,
+    KademliaConfig,
+    KademliaEvent,
+    QueryId,
+    QueryResult,
+    store::MemoryStore,
+};
+use libp2p_swarm::{
+    DialPeerCondition,
+    IntoProtocolsHandler,
+    IntoProtocolsHandlerSelect,
+    NetworkBehaviour,
+    NetworkBehaviourAction,
+    PollParameters,
+    ProtocolsHandler,
+};
+use std::{collections::{BTreeMap, HashMap, HashSet, VecDeque}, error, io, task::{Context, Poll}};
+
+/// The configuration of a [`Crawler`].
+#[derive(Debug, Clone)]
+pub struct CrawlerConfig {
+    kademlia: KademliaConfig,
+    protocol_version: String,
+    agent_version: String,
+    random_walks: usize,
+    max_concurrent_lookups: usize,
+    max_concurrent_dials: usize,
+}
+
+impl Default for CrawlerConfig {
+    fn default() -> Self {
+        CrawlerConfig {
+            kademlia: KademliaConfig::default(),
+            protocol_version: "ipfs/0.1.0".to_string(),
+            agent_version: format!("rust-libp2p-crawler/{}", env!("CARGO_PKG_VERSION")),
+            random_walks: 1,
+            max_concurrent_lookups: 4,
+            max_concurrent_dials: 16,
+        }
+    }
+}
+
+impl CrawlerConfig {
+    /// Sets the configuration of the Kademlia behaviour used for the crawl.
+    pub fn set_kademlia_config(&mut self, config: KademliaConfig) -> &mut Self {
+        self.kademlia = config;
+        self
+    }
+
+    /// Sets the protocol version announced to the crawled peers through
+    /// the identify protocol.
+    pub fn set_protocol_version(&mut self, version: String) -> &mut Self {
+        self.protocol_version = version;
+        self
+    }
+
+    /// Sets the agent version announced to the crawled peers through
+    /// the identify protocol.
+    pub fn set_agent_version(&mut self, version: String) -> &mut Self {
+        self.agent_version = version;
+        self
+    }
+
+    /// Sets the number of lookups of random keys a crawl starts with,
+    /// in addition to the lookups of the discovered peers.
+    pub fn set_random_walks(&mut self, n: usize) -> &mut Self {
+        self.random_walks = n;
+        self
+    }
+
+    /// Sets the maximum number of lookups running at the same time.
+    pub fn set_max_concurrent_lookups(&mut self, n: usize) -> &mut Self {
+        self.max_concurrent_lookups = n;
+        self
+    }
+
+    /// Sets the maximum number of peers dialed by the crawler at the
+    /// same time.
+    pub fn set_max_concurrent_dials(&mut self, n: usize) -> &mut Self {
+        self.max_concurrent_dials = n;
+        self
+    }
+}
+
+/// The outcome of crawling a peer.
+#[derive(Debug, Clone)]
+pub enum PeerOutcome {
+    /// The peer has been identified.
+    Identified(Box<IdentifyInfo>),
+    /// The peer has been connected to, but could not be identified.
+    Unidentified,
+    /// The peer could not be connected to.
+    Unreachable,
+}
+
+/// The report on a crawled peer.
+#[derive(Debug, Clone)]
+pub struct PeerReport {
+    /// The ID of the peer.
+    pub peer_id: PeerId,
+    /// The addresses of the peer, i.e. its listen addresses if identified,
+    /// the addresses found in the DHT otherwise.
+    pub addresses: Vec<Multiaddr>,
+    /// The outcome of crawling the peer.
+    pub outcome: PeerOutcome,
+}
+
+/// The report on a crawl, listing all peers crawled.
+#[derive(Debug, Clone, Default)]
+pub struct CrawlReport {
+    /// The crawled peers, ordered by their base58-encoded IDs.
+    pub peers: Vec<PeerReport>,
+}
+
+impl CrawlReport {
+    /// Returns the number of identified peers per agent version.
+    pub fn agent_versions(&self) -> BTreeMap<String, usize> {
+        let mut versions = BTreeMap::new();
+        for info in self.identified() {
+            *versions.entry(info.agent_version.clone()).or_default() += 1;
+        }
+        versions
+    }
+
+    /// Returns the number of identified peers supporting each protocol.
+    pub fn protocols(&self) -> BTreeMap<String, usize> {
+        let mut protocols = BTreeMap::new();
+        for info in self.identified() {
+            for protocol in &info.protocols {
+                *protocols.entry(protocol.clone()).or_default() += 1;
+            }
+        }
+        protocols
+    }
+
+    /// Returns the number of peers that could be connected to.
+    pub fn num_reachable(&self) -> usize {
+        self.peers.iter()
+            .filter(|p| !matches!(p.outcome, PeerOutcome::Unreachable))
+            .count()
+    }
+
+    fn identified(&self) -> impl Iterator<Item = &IdentifyInfo> {
+        self.peers.iter().filter_map(|p| match &p.outcome {
+            PeerOutcome::Identified(info) => Some(&**info),
+            _ => None,
+        })
+    }
+}
+
+/// The events emitted by the [`Crawler`].
+#[derive(Debug)]
+pub enum CrawlerEvent {
+    /// A peer has been crawled.
+    PeerCrawled(PeerReport),
+    /// The crawl is finished.
+    Finished(CrawlReport),
+}
+
+/// The state of a peer discovered during a crawl.
+#[derive(Debug)]
+enum CrawlState {
+    /// The peer is yet to be dialed.
+    Discovered,
+    /// The peer is being dialed.
+    Dialing,
+    /// The peer is connected and yet to be identified.
+    Connected,
+    /// The peer has been crawled.
+    Done(PeerOutcome),
+}
+
+/// A `NetworkBehaviour` crawling the Kademlia DHT.
+///
+/// See the [crate documentation](crate) for details.
+pub struct Crawler {
+    config: CrawlerConfig,
+    local_peer_id: PeerId,
+    kademlia: Kademlia<MemoryStore>,
+    identify: Identify,
+    /// The peers discovered by the current or last crawl.
+    peers: HashMap<PeerId, CrawlState>,
+    /// The discovered peers that are not crawled yet.
+    pending: HashSet<PeerId>,
+    /// The addresses known for each peer.
+    addresses: HashMap<PeerId, Vec<Multiaddr>>,
+    /// The most recent identifying information received from each peer.
+    identified: HashMap<PeerId, IdentifyInfo>,
+    /// The currently connected peers.
+    connected: HashSet<PeerId>,
+    /// The keys of the lookups yet to be started.
+    lookups: VecDeque<PeerId>,
+    /// The running lookups started by the crawler.
+    queries: HashSet<QueryId>,
+    /// The peers yet to be dialed.
+    dials: VecDeque<PeerId>,
+    /// The number of peers in [`CrawlState::Dialing`].
+    num_dialing: usize,
+    /// Whether a crawl is in progress.
+    crawling: bool,
+    events: VecDeque<CrawlerEvent>,
+}
+
+impl Crawler {
+    /// Creates a new `Crawler` with the given configuration.
+    pub fn new(local_public_key: PublicKey, config: CrawlerConfig) -> Self {
+        let local_peer_id = local_public_key.clone().into_peer_id();
+        let store = MemoryStore::new(local_peer_id.clone());
+        let kademlia = Kademlia::with_config(local_peer_id.clone(), store, config.kademlia.clone());
+        let identify = Identify::new(
+            config.protocol_version.clone(),
+            config.agent_version.clone(),
+            local_public_key,
+        );
+        Crawler {
+            config,
+            local_peer_id,
+            kademlia,
+            identify,
+            peers: HashMap::new(),
+            pending: HashSet::new(),
+            addresses: HashMap::new(),
+            identified: HashMap::new(),
+            connected: HashSet::new(),
+            lookups: VecDeque::new(),
+            queries: HashSet::new(),
+            dials: VecDeque::new(),
+            num_dialing: 0,
+            crawling: false,
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Adds a known address of a peer, e.g. of a bootstrap node, to the
+    /// routing table that a crawl starts from.
+    pub fn add_address(&mut self, peer: &PeerId, address: Multiaddr) {
+        self.kademlia.add_address(peer, address.clone());
+        add_address(self.addresses.entry(peer.clone()).or_default(), address);
+    }
+
+    /// Returns the Kademlia behaviour used for the crawl.
+    pub fn kademlia(&mut self) -> &mut Kademlia<MemoryStore> {
+        &mut self.kademlia
+    }
+
+    /// Starts a crawl, aborting the crawl in progress, if any.
+    ///
+    /// The crawl starts from the peers in the routing table. If there are
+    /// none, it finishes with an empty report.
+    pub fn start(&mut self) {
+        self.peers.clear();
+        self.pending.clear();
+        self.lookups.clear();
+        self.queries.clear();
+        self.dials.clear();
+        self.num_dialing = 0;
+        self.crawling = true;
+
+        let known = self.kademlia.kbuckets()
+            .flat_map(|bucket| bucket.iter()
+                .map(|entry| (entry.node.key.preimage().clone(), entry.node.value.iter().cloned().collect::<Vec<_>>()))
+                .collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        for (peer, addresses) in known {
+            self.discover(peer, addresses);
+        }
+        for _ in 0 .. self.config.random_walks {
+            self.lookups.push_back(PeerId::random());
+        }
+    }
+
+    /// Returns the report on the peers crawled so far by the current or
+    /// last crawl.
+    pub fn report(&self) -> CrawlReport {
+        let mut peers = self.peers.iter()
+            .filter_map(|(peer, state)| match state {
+                CrawlState::Done(outcome) => Some(self.peer_report(peer, outcome.clone())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        peers.sort_by_cached_key(|p| p.peer_id.to_base58());
+        CrawlReport { peers }
+    }
+
+    fn peer_report(&self, peer: &PeerId, outcome: PeerOutcome) -> PeerReport {
+        let addresses = match &outcome {
+            PeerOutcome::Identified(info) if !info.listen_addrs.is_empty() => info.listen_addrs.clone(),
+            _ => self.addresses.get(peer).cloned().unwrap_or_default(),
+        };
+        PeerReport { peer_id: peer.clone(), addresses, outcome }
+    }
+
+    /// Records a peer found in the DHT, scheduling it to be crawled if it
+    /// is new to the current crawl.
+    fn discover(&mut self, peer: PeerId, addresses: impl IntoIterator<Item = Multiaddr>) {
+        let known = self.addresses.entry(peer.clone()).or_default();
+        for address in addresses {
+            add_address(known, address);
+        }
+        if !self.crawling || peer == self.local_peer_id || self.peers.contains_key(&peer) {
+            return
+        }
+        self.peers.insert(peer.clone(), CrawlState::Discovered);
+        self.pending.insert(peer.clone());
+        self.dials.push_back(peer.clone());
+        self.lookups.push_back(peer);
+    }
+
+    /// Ends crawling a peer.
+    fn finish(&mut self, peer: &PeerId, outcome: PeerOutcome) {
+        if let Some(state) = self.peers.get_mut(peer) {
+            if let CrawlState::Dialing = state {
+                self.num_dialing -= 1;
+            }
+            *state = CrawlState::Done(outcome.clone());
+            self.pending.remove(peer);
+            let report = self.peer_report(peer, outcome);
+            self.events.push_back(CrawlerEvent::PeerCrawled(report));
+        }
+    }
+
+    /// Returns the next peer to dial, if the limit of concurrent dials
+    /// permits. Connected peers are not dialed, but wait to be identified.
+    fn next_dial(&mut self) -> Option<PeerId> {
+        while self.num_dialing < self.config.max_concurrent_dials {
+            let peer = self.dials.pop_front()?;
+            if !matches!(self.peers.get(&peer), Some(CrawlState::Discovered)) {
+                continue
+            }
+            if self.connected.contains(&peer) {
+                match self.identified.get(&peer).cloned() {
+                    Some(info) => self.finish(&peer, PeerOutcome::Identified(Box::new(info))),
+                    None => { self.peers.insert(peer, CrawlState::Connected); }
+                }
+                continue
+            }
+            self.peers.insert(peer.clone(), CrawlState::Dialing);
+            self.num_dialing += 1;
+            return Some(peer)
+        }
+        None
+    }
+
+    /// Starts lookups up to the limit of concurrent lookups, returning
+    /// whether any lookup has been started.
+    fn start_lookups(&mut self) -> bool {
+        let mut started = false;
+        while self.queries.len() < self.config.max_concurrent_lookups {
+            match self.lookups.pop_front() {
+                Some(key) => {
+                    let id = self.kademlia.get_closest_peers(key);
+                    self.queries.insert(id);
+                    started = true;
+                }
+                None => break,
+            }
+        }
+        started
+    }
+
+    fn on_kademlia_event(&mut self, event: KademliaEvent) {
+        match event {
+            KademliaEvent::QueryResult { id, result: QueryResult::GetClosestPeers(result), .. } => {
+                if !self.queries.remove(&id) {
+                    return
+                }
+                let peers = match result {
+                    Ok(GetClosestPeersOk { peers, .. }) => peers,
+                    Err(GetClosestPeersError::Timeout { peers, .. }) => peers,
+                };
+                for peer in peers {
+                    self.discover(peer, None);
+                }
+            }
+            KademliaEvent::RoutingUpdated { peer, addresses, .. } => {
+                self.discover(peer, addresses.into_vec());
+            }
+            KademliaEvent::RoutablePeer { peer, address } |
+            KademliaEvent::PendingRoutablePeer { peer, address } => {
+                self.discover(peer, Some(address));
+            }
+            KademliaEvent::UnroutablePeer { peer } => {
+                self.discover(peer, None);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_identify_event(&mut self, event: IdentifyEvent) {
+        match event {
+            IdentifyEvent::Received { peer_id, info, .. } => {
+                self.identified.insert(peer_id.clone(), info.clone());
+                if !matches!(self.peers.get(&peer_id), None | Some(CrawlState::Done(_))) {
+                    self.finish(&peer_id, PeerOutcome::Identified(Box::new(info)));
+                }
+            }
+            IdentifyEvent::Error { peer_id, .. } => {
+                if !matches!(self.peers.get(&peer_id), None | Some(CrawlState::Done(_))) {
+                    self.finish(&peer_id, PeerOutcome::Unidentified);
+                }
+            }
+            IdentifyEvent::Sent { .. } | IdentifyEvent::Pushed { .. } => {}
+        }
+    }
+}
+
+/// Adds an address to a list of addresses, unless already present.
+fn add_address(addresses: &mut Vec<Multiaddr>, address: Multiaddr) {
+    if !addresses.contains(&address) {
+        addresses.push(address);
+    }
+}
+
+/// Maps the handler event of an action of an inner behaviour, returning the
+/// event generated by the behaviour as an error.
+fn map_action<TInEvent, TOutEvent, T>(
+    action: NetworkBehaviourAction<TInEvent, TOutEvent>,
+    map: impl FnOnce(TInEvent) -> T,
+) -> Result<NetworkBehaviourAction<T, CrawlerEvent>, TOutEvent> {
+    Ok(match action {
+        NetworkBehaviourAction::GenerateEvent(event) => return Err(event),
+        NetworkBehaviourAction::DialAddress { address } =>
+            NetworkBehaviourAction::DialAddress { address },
+        NetworkBehaviourAction::DialPeer { peer_id, condition } =>
+            NetworkBehaviourAction::DialPeer { peer_id, condition },
+        NetworkBehaviourAction::NotifyHandler { peer_id, handler, event } =>
+            NetworkBehaviourAction::NotifyHandler { peer_id, handler, event: map(event) },
+        NetworkBehaviourAction::ReportObservedAddr { address, peer_id } =>
+            NetworkBehaviourAction::ReportObservedAddr { address, peer_id },
+        NetworkBehaviourAction::AddExternalAddr { address } =>
+            NetworkBehaviourAction::AddExternalAddr { address },
+        NetworkBehaviourAction::ConfirmExternalAddr { address } =>
+            NetworkBehaviourAction::ConfirmExternalAddr { address },
+        NetworkBehaviourAction::RemoveExternalAddr { address } =>
+            NetworkBehaviourAction::RemoveExternalAddr { address },
+        NetworkBehaviourAction::UpdateSupportedProtocols =>
+            NetworkBehaviourAction::UpdateSupportedProtocols,
+    })
+}
+
+type KademliaHandler = <Kademlia<MemoryStore> as NetworkBehaviour>::ProtocolsHandler;
+type IdentifyHandler = <Identify as NetworkBehaviour>::ProtocolsHandler;
+
+impl NetworkBehaviour for Crawler {
+    type ProtocolsHandler = IntoProtocolsHandlerSelect<KademliaHandler, IdentifyHandler>;
+    type OutEvent = CrawlerEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        IntoProtocolsHandler::select(self.kademlia.new_handler(), self.identify.new_handler())
+    }
+
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        let mut addresses = self.addresses.get(peer_id).cloned().unwrap_or_default();
+        let inner = self.kademlia.addresses_of_peer(peer_id).into_iter()
+            .chain(self.identify.addresses_of_peer(peer_id));
+        for address in inner {
+            add_address(&mut addresses, address);
+        }
+        addresses
+    }
+
+    fn inject_connected(&mut self, peer_id: &PeerId) {
+        self.kademlia.inject_connected(peer_id);
+        self.identify.inject_connected(peer_id);
+        self.connected.insert(peer_id.clone());
+        if let Some(state @ CrawlState::Dialing) = self.peers.get_mut(peer_id) {
+            *state = CrawlState::Connected;
+            self.num_dialing -= 1;
+        }
+    }
+
+    fn inject_disconnected(&mut self, peer_id: &PeerId) {
+        self.kademlia.inject_disconnected(peer_id);
+        self.identify.inject_disconnected(peer_id);
+        self.connected.remove(peer_id);
+        if let Some(CrawlState::Connected) = self.peers.get(peer_id) {
+            self.finish(peer_id, PeerOutcome::Unidentified);
+        }
+    }
+
+    fn inject_connection_established(&mut self, peer_id: &PeerId, id: &ConnectionId, endpoint: &ConnectedPoint) {
+        self.kademlia.inject_connection_established(peer_id, id, endpoint);
+        self.identify.inject_connection_established(peer_id, id, endpoint);
+    }
+
+    fn inject_connection_closed(&mut self, peer_id: &PeerId, id: &ConnectionId, endpoint: &ConnectedPoint) {
+        self.kademlia.inject_connection_closed(peer_id, id, endpoint);
+        self.identify.inject_connection_closed(peer_id, id, endpoint);
+    }
+
+    fn inject_address_change(&mut self, peer_id: &PeerId, id: &ConnectionId, old: &ConnectedPoint, new: &ConnectedPoint) {
+        self.kademlia.inject_address_change(peer_id, id, old, new);
+        self.identify.inject_address_change(peer_id, id, old, new);
+    }
+
+    fn inject_event(
+        &mut self,
+        peer_id: PeerId,
+        connection: ConnectionId,
+        event: <<Self::ProtocolsHandler as IntoProtocolsHandler>::Handler as ProtocolsHandler>::OutEvent
+    ) {
+        match event {
+            EitherOutput::First(event) => self.kademlia.inject_event(peer_id, connection, event),
+            EitherOutput::Second(event) => self.identify.inject_event(peer_id, connection, event),
+        }
+    }
+
+    fn inject_addr_reach_failure(&mut self, peer_id: Option<&PeerId>, addr: &Multiaddr, error: &dyn error::Error) {
+        self.kademlia.inject_addr_reach_failure(peer_id, addr, error);
+        self.identify.inject_addr_reach_failure(peer_id, addr, error);
+    }
+
+    fn inject_dial_failure(&mut self, peer_id: &PeerId) {
+        self.kademlia.inject_dial_failure(peer_id);
+        self.identify.inject_dial_failure(peer_id);
+        if let Some(CrawlState::Dialing) = self.peers.get(peer_id) {
+            self.finish(peer_id, PeerOutcome::Unreachable);
+        }
+    }
+
+    fn inject_new_listen_addr(&mut self, addr: &Multiaddr) {
+        self.kademlia.inject_new_listen_addr(addr);
+        self.identify.inject_new_listen_addr(addr);
+    }
+
+    fn inject_expired_listen_addr(&mut self, addr: &Multiaddr) {
+        self.kademlia.inject_expired_listen_addr(addr);
+        self.identify.inject_expired_listen_addr(addr);
+    }
+
+    fn inject_new_external_addr(&mut self, addr: &Multiaddr) {
+        self.kademlia.inject_new_external_addr(addr);
+        self.identify.inject_new_external_addr(addr);
+    }
+
+    fn inject_listener_error(&mut self, id: ListenerId, err: &(dyn error::Error + 'static)) {
+        self.kademlia.inject_listener_error(id, err);
+        self.identify.inject_listener_error(id, err);
+    }
+
+    fn inject_listener_closed(&mut self, id: ListenerId, reason: Result<(), &io::Error>) {
+        self.kademlia.inject_listener_closed(id, reason);
+        self.identify.inject_listener_closed(id, reason);
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>, params: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<<<Self::ProtocolsHandler as IntoProtocolsHandler>::Handler as ProtocolsHandler>::InEvent, Self::OutEvent>>
+    {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event))
+            }
+
+            if let Some(peer_id) = self.next_dial() {
+                return Poll::Ready(NetworkBehaviourAction::DialPeer {
+                    peer_id,
+                    condition: DialPeerCondition::Disconnected,
+                })
+            }
+
+            let started = self.start_lookups();
+
+            if self.crawling && self.lookups.is_empty() && self.queries.is_empty() && self.pending.is_empty() {
+                self.crawling = false;
+                let report = self.report();
+                self.events.push_back(CrawlerEvent::Finished(report));
+                continue
+            }
+
+            if let Poll::Ready(action) = self.kademlia.poll(cx, params) {
+                match map_action(action, EitherOutput::First) {
+                    Ok(action) => return Poll::Ready(action),
+                    Err(event) => { self.on_kademlia_event(event); continue }
+                }
+            }
+
+            if let Poll::Ready(action) = self.identify.poll(cx, params) {
+                match map_action(action, EitherOutput::Second) {
+                    Ok(action) => return Poll::Ready(action),
+                    Err(event) => { self.on_identify_event(event); continue }
+                }
+            }
+
+            if !started && self.events.is_empty() {
+                return Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_core::{
+    Multiaddr,
+    PeerId,
+    Transport,
+    identity,
+    multiaddr::Protocol,
+    muxing::StreamMuxerBox,
+    transport::{MemoryTransport, boxed::Boxed},
+    upgrade,
+};
+use libp2p_crawler::{Crawler, CrawlerConfig, CrawlerEvent, PeerOutcome};
+use libp2p_plaintext::PlainText2Config;
+use libp2p_swarm::Swarm;
+use libp2p_yamux as yamux;
+use std::io;
+
+type TestSwarm = Swarm<Crawler>;
+
+fn build_transport(keys: &identity::Keypair) -> Boxed<(PeerId, StreamMuxerBox), io::Error> {
+    MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(PlainText2Config { local_public_key: keys.public() })
+        .multiplex(yamux::Config::default())
+        .map(|(p, m), _| (p, StreamMuxerBox::new(m)))
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+        .boxed()
+}
+
+fn build_swarm(agent_version: &str) -> (PeerId, Multiaddr, TestSwarm) {
+    let keys = identity::Keypair::generate_ed25519();
+    let peer_id = keys.public().into_peer_id();
+    let mut config = CrawlerConfig::default();
+    config.set_agent_version(agent_version.to_string());
+    let crawler = Crawler::new(keys.public(), config);
+    let mut swarm = Swarm::new(build_transport(&keys), crawler, peer_id.clone());
+    let addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    Swarm::listen_on(&mut swarm, addr.clone()).unwrap();
+    (peer_id, addr, swarm)
+}
+
+#[test]
+fn crawl() {
+    let (_, _, mut swarm1) = build_swarm("crawler");
+    let (peer2, addr2, mut swarm2) = build_swarm("node/2");
+    let (peer3, addr3, swarm3) = build_swarm("node/3");
+
+    // The crawler only knows the second node, which knows the third.
+    swarm2.add_address(&peer3, addr3.clone());
+    swarm1.add_address(&peer2, addr2.clone());
+
+    for mut swarm in [swarm2, swarm3] {
+        async_std::task::spawn(async move {
+            loop {
+                swarm.next_event().await;
+            }
+        });
+    }
+
+    swarm1.start();
+    let report = async_std::task::block_on(async {
+        let mut crawled = Vec::new();
+        loop {
+            match swarm1.next().await {
+                CrawlerEvent::PeerCrawled(peer) => crawled.push(peer.peer_id),
+                CrawlerEvent::Finished(report) => {
+                    assert_eq!(crawled.len(), 2);
+                    break report
+                }
+            }
+        }
+    });
+
+    assert_eq!(report.peers.len(), 2);
+    assert_eq!(report.num_reachable(), 2);
+    for peer in &report.peers {
+        assert!(peer.peer_id == peer2 || peer.peer_id == peer3);
+        assert!(matches!(peer.outcome, PeerOutcome::Identified(_)), "{:?}", peer);
+    }
+    let peer3_report = report.peers.iter().find(|p| p.peer_id == peer3).unwrap();
+    assert_eq!(peer3_report.addresses, vec![addr3]);
+    assert_eq!(report.agent_versions().into_iter().collect::<Vec<_>>(),
+        vec![("node/2".to_string(), 1), ("node/3".to_string(), 1)]);
+    assert!(report.protocols().contains_key("/ipfs/kad/1.0.0"));
+}
@@ -0,0 +1,10 @@
+module github.com/libp2p/rust-libp2p/misc/interop-tests/go-peer
+
+go 1.14
+
+require (
+	github.com/libp2p/go-libp2p v0.10.2
+	github.com/libp2p/go-libp2p-kad-dht v0.8.3
+	github.com/libp2p/go-libp2p-noise v0.1.1
+	github.com/libp2p/go-libp2p-secio v0.2.2
+)
@@ -0,0 +1,50 @@
+// A go-libp2p peer for the interoperability tests of rust-libp2p.
+//
+// The peer listens on a random local TCP port, serves ping, identify and
+// the Kademlia DHT, and prints its listen addresses to stdout.
+package main
+
+import (
+	"context"
+	"flag"
+	"fmt"
+	"log"
+
+	"github.com/libp2p/go-libp2p"
+	dht "github.com/libp2p/go-libp2p-kad-dht"
+	noise "github.com/libp2p/go-libp2p-noise"
+	secio "github.com/libp2p/go-libp2p-secio"
+)
+
+func main() {
+	security := flag.String("security", "noise", "security protocol to use (noise or secio)")
+	flag.Parse()
+
+	opts := []libp2p.Option{
+		libp2p.ListenAddrStrings("/ip4/127.0.0.1/tcp/0"),
+		libp2p.UserAgent("go-libp2p-interop"),
+		libp2p.Ping(true),
+	}
+	switch *security {
+	case "noise":
+		opts = append(opts, libp2p.Security(noise.ID, noise.New))
+	case "secio":
+		opts = append(opts, libp2p.Security(secio.ID, secio.New))
+	default:
+		log.Fatalf("unknown security protocol: %s", *security)
+	}
+
+	ctx := context.Background()
+	host, err := libp2p.New(ctx, opts...)
+	if err != nil {
+		log.Fatal(err)
+	}
+	if _, err := dht.New(ctx, host, dht.Mode(dht.ModeServer)); err != nil {
+		log.Fatal(err)
+	}
+
+	for _, addr := range host.Addrs() {
+		fmt.Printf("%s/p2p/%s\n", addr, host.ID())
+	}
+	select {}
+}
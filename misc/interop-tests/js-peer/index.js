@@ -0,0 +1,45 @@
+// A js-libp2p peer for the interoperability tests of rust-libp2p.
+//
+// The peer listens on a random local TCP port, serves ping, identify and
+// the Kademlia DHT, and prints its listen addresses to stdout.
+'use strict'
+
+const Libp2p = require('libp2p')
+const TCP = require('libp2p-tcp')
+const MPLEX = require('libp2p-mplex')
+const { NOISE } = require('libp2p-noise')
+const SECIO = require('libp2p-secio')
+const KadDHT = require('libp2p-kad-dht')
+
+const securityProtocols = { noise: NOISE, secio: SECIO }
+
+async function main () {
+  const flag = process.argv.indexOf('--security')
+  const security = flag === -1 ? 'noise' : process.argv[flag + 1]
+  if (!securityProtocols[security]) {
+    throw new Error(`unknown security protocol: ${security}`)
+  }
+
+  const node = await Libp2p.create({
+    addresses: { listen: ['/ip4/127.0.0.1/tcp/0'] },
+    modules: {
+      transport: [TCP],
+      streamMuxer: [MPLEX],
+      connEncryption: [securityProtocols[security]],
+      dht: KadDHT
+    },
+    config: {
+      dht: { enabled: true }
+    }
+  })
+  await node.start()
+
+  for (const addr of node.multiaddrs) {
+    console.log(`${addr}/p2p/${node.peerId.toB58String()}`)
+  }
+}
+
+main().catch((err) => {
+  console.error(err)
+  process.exit(1)
+})
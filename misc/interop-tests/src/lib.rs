@@ -0,0 +1,267 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Interoperability tests against go-libp2p and js-libp2p.
+//!
+//! The tests in this crate run the peers of the other implementations in
+//! docker containers and connect to them with rust-libp2p, in order to catch
+//! regressions of the wire format before they are released. The go and js
+//! peers live in the `go-peer` and `js-peer` directories. They listen on a
+//! random local TCP port and serve the ping, identify and Kademlia protocols.
+//!
+//! As the tests require a running docker daemon and network access to build
+//! the images, they are ignored by default and have to be run explicitly:
+//!
+//! ```text
+//! cargo test -p interop-tests -- --ignored
+//! ```
+//!
+//! The images are built on first use in a test run, such that changes to the
+//! peers are always picked up.
+
+use libp2p::{
+    Multiaddr,
+    PeerId,
+    Transport,
+    core::{muxing::StreamMuxerBox, transport::boxed::Boxed, upgrade},
+    identity,
+    mplex,
+    multiaddr::Protocol,
+    noise,
+    secio,
+    tcp::TcpConfig,
+    yamux,
+};
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    process::{Child, ChildStdout, Command, Stdio},
+    sync::{Mutex, atomic::{AtomicUsize, Ordering}},
+    time::Duration,
+};
+
+/// The libp2p implementation running on the remote side of a test.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Implementation {
+    /// A go-libp2p peer, see `go-peer/main.go`.
+    Go,
+    /// A js-libp2p peer, see `js-peer/index.js`.
+    Js,
+}
+
+impl Implementation {
+    /// The name of the docker image of the peer.
+    fn image(self) -> &'static str {
+        match self {
+            Implementation::Go => "rust-libp2p-interop-go",
+            Implementation::Js => "rust-libp2p-interop-js",
+        }
+    }
+
+    /// The directory containing the `Dockerfile` of the peer.
+    fn directory(self) -> &'static str {
+        match self {
+            Implementation::Go => "go-peer",
+            Implementation::Js => "js-peer",
+        }
+    }
+}
+
+/// The security protocol used on the connections of a test.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Security {
+    Noise,
+    Secio,
+}
+
+impl fmt::Display for Security {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Security::Noise => f.write_str("noise"),
+            Security::Secio => f.write_str("secio"),
+        }
+    }
+}
+
+/// The images that have been built during this test run.
+static BUILT_IMAGES: Mutex<Vec<Implementation>> = Mutex::new(Vec::new());
+
+/// The number of containers started by this process, used for their names.
+static CONTAINERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Builds the docker image of the given implementation, unless it has
+/// already been built during this test run.
+fn build_image(implementation: Implementation) -> io::Result<()> {
+    let mut built = BUILT_IMAGES.lock().unwrap_or_else(|e| e.into_inner());
+    if built.contains(&implementation) {
+        return Ok(())
+    }
+
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join(implementation.directory());
+    let status = Command::new("docker")
+        .arg("build")
+        .arg("--tag").arg(implementation.image())
+        .arg(&directory)
+        .stdout(Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::new(io::ErrorKind::Other, format!("building {} failed: {}", implementation.image(), status)))
+    }
+
+    built.push(implementation);
+    Ok(())
+}
+
+/// A peer of another implementation running in a docker container.
+///
+/// The container is removed when the `RemotePeer` is dropped.
+pub struct RemotePeer {
+    name: String,
+    child: Child,
+    /// Kept open, such that the peer doesn't fail writing to its stdout.
+    _stdout: BufReader<ChildStdout>,
+    peer_id: PeerId,
+    addr: Multiaddr,
+}
+
+impl RemotePeer {
+    /// Builds the image of the implementation if necessary and starts a
+    /// peer using the given security protocol.
+    ///
+    /// Returns once the peer has reported its listen address.
+    pub fn start(implementation: Implementation, security: Security) -> io::Result<Self> {
+        build_image(implementation)?;
+
+        let name = format!(
+            "{}-{}-{}",
+            implementation.image(),
+            std::process::id(),
+            CONTAINERS.fetch_add(1, Ordering::Relaxed)
+        );
+        let mut child = Command::new("docker")
+            .arg("run")
+            .arg("--rm")
+            .arg("--network").arg("host")
+            .arg("--name").arg(&name)
+            .arg(implementation.image())
+            .arg("--security").arg(security.to_string())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+        let mut peer = None;
+        let mut line = String::new();
+        while peer.is_none() {
+            line.clear();
+            if stdout.read_line(&mut line)? == 0 {
+                let _ = child.wait();
+                return Err(io::Error::new(io::ErrorKind::Other, format!("{} exited before listening", name)))
+            }
+            peer = parse_listen_addr(line.trim());
+        }
+        let (peer_id, addr) = peer.expect("loop exits once a peer is found");
+
+        Ok(RemotePeer { name, child, _stdout: stdout, peer_id, addr })
+    }
+
+    /// The peer ID of the remote peer.
+    pub fn peer_id(&self) -> &PeerId {
+        &self.peer_id
+    }
+
+    /// The address the remote peer listens on, without the `/p2p` suffix.
+    pub fn addr(&self) -> &Multiaddr {
+        &self.addr
+    }
+}
+
+impl Drop for RemotePeer {
+    fn drop(&mut self) {
+        let _ = Command::new("docker")
+            .arg("rm").arg("--force").arg(&self.name)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        let _ = self.child.wait();
+    }
+}
+
+/// Parses a line of the form `/ip4/127.0.0.1/tcp/<port>/p2p/<peer-id>`,
+/// as printed by the peers, into the peer ID and the TCP address.
+fn parse_listen_addr(line: &str) -> Option<(PeerId, Multiaddr)> {
+    let mut addr = line.parse::<Multiaddr>().ok()?;
+    match addr.pop()? {
+        Protocol::P2p(hash) => PeerId::from_multihash(hash).ok().map(|id| (id, addr)),
+        _ => None,
+    }
+}
+
+/// Builds a TCP transport securing connections with the given protocol and
+/// negotiating protocols with the given version of multistream-select.
+///
+/// Both yamux and mplex are offered, as js-libp2p only supports the latter.
+pub fn build_transport(
+    keypair: &identity::Keypair,
+    security: Security,
+    version: upgrade::Version,
+) -> Boxed<(PeerId, StreamMuxerBox), io::Error> {
+    let tcp = TcpConfig::new().nodelay(true).upgrade(version);
+    let muxer = upgrade::SelectUpgrade::new(yamux::Config::default(), mplex::MplexConfig::new());
+
+    match security {
+        Security::Noise => {
+            let noise_keys = noise::Keypair::<noise::X25519Spec>::new()
+                .into_authentic(keypair)
+                .expect("signing the libp2p-noise static keypair");
+            tcp.authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
+                .multiplex(muxer)
+                .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+                .timeout(Duration::from_secs(20))
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+                .boxed()
+        }
+        Security::Secio => {
+            tcp.authenticate(secio::SecioConfig::new(keypair.clone()))
+                .multiplex(muxer)
+                .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+                .timeout(Duration::from_secs(20))
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+                .boxed()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_listen_addr() {
+        let peer_id = PeerId::random();
+        let line = format!("/ip4/127.0.0.1/tcp/4001/p2p/{}", peer_id);
+        let (id, addr) = parse_listen_addr(&line).unwrap();
+        assert_eq!(id, peer_id);
+        assert_eq!(addr, "/ip4/127.0.0.1/tcp/4001".parse::<Multiaddr>().unwrap());
+
+        assert!(parse_listen_addr("/ip4/127.0.0.1/tcp/4001").is_none());
+        assert!(parse_listen_addr("listening").is_none());
+    }
+}
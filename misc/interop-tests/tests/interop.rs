@@ -0,0 +1,190 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! End-to-end tests against peers of other implementations.
+//!
+//! Every test starts a fresh remote peer, connects to it from a rust-libp2p
+//! swarm and runs a single protocol over the connection. Establishing the
+//! connection exercises the security and multiplexing upgrades as well as
+//! multistream-select. See the crate documentation on how to run them.
+
+use async_std::future::timeout;
+use interop_tests::{Implementation, RemotePeer, Security, build_transport};
+use libp2p::{
+    PeerId,
+    Swarm,
+    core::upgrade,
+    identify::{Identify, IdentifyEvent},
+    identity,
+    kad::{Kademlia, KademliaEvent, QueryResult, store::MemoryStore},
+    ping::{Ping, PingConfig, PingEvent, PingSuccess},
+};
+use std::time::Duration;
+
+/// The maximum duration of a single test once the remote peer is running.
+const TEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn local_keys() -> (identity::Keypair, PeerId) {
+    let keypair = identity::Keypair::generate_ed25519();
+    let peer_id = keypair.public().into_peer_id();
+    (keypair, peer_id)
+}
+
+fn ping(implementation: Implementation, security: Security, version: upgrade::Version) {
+    let remote = RemotePeer::start(implementation, security).unwrap();
+    let (keypair, peer_id) = local_keys();
+    let ping = Ping::new(PingConfig::new().with_keep_alive(true));
+    let mut swarm = Swarm::new(build_transport(&keypair, security, version), ping, peer_id);
+    Swarm::dial_addr(&mut swarm, remote.addr().clone()).unwrap();
+
+    let result = async_std::task::block_on(timeout(TEST_TIMEOUT, async {
+        loop {
+            match swarm.next().await {
+                PingEvent { peer, result: Ok(PingSuccess::Ping { .. }) } => {
+                    assert_eq!(&peer, remote.peer_id());
+                    return
+                }
+                PingEvent { result: Ok(PingSuccess::Pong), .. } => {}
+                PingEvent { result: Err(e), .. } => panic!("ping failed: {:?}", e),
+            }
+        }
+    }));
+    result.expect("ping timed out");
+}
+
+fn identify(implementation: Implementation, security: Security, agent_version: &str) {
+    let remote = RemotePeer::start(implementation, security).unwrap();
+    let (keypair, peer_id) = local_keys();
+    let identify = Identify::new("ipfs/0.1.0".into(), "rust-libp2p-interop".into(), keypair.public());
+    let transport = build_transport(&keypair, security, upgrade::Version::V1);
+    let mut swarm = Swarm::new(transport, identify, peer_id);
+    Swarm::dial_addr(&mut swarm, remote.addr().clone()).unwrap();
+
+    let result = async_std::task::block_on(timeout(TEST_TIMEOUT, async {
+        let (mut received, mut sent) = (false, false);
+        while !received || !sent {
+            match swarm.next().await {
+                IdentifyEvent::Received { peer_id, info, .. } => {
+                    assert_eq!(&peer_id, remote.peer_id());
+                    assert_eq!(&info.public_key.into_peer_id(), remote.peer_id());
+                    assert!(
+                        info.agent_version.starts_with(agent_version),
+                        "unexpected agent version {}", info.agent_version
+                    );
+                    for protocol in &["/ipfs/id/1.0.0", "/ipfs/ping/1.0.0", "/ipfs/kad/1.0.0"] {
+                        assert!(
+                            info.protocols.iter().any(|p| p == protocol),
+                            "{} not in {:?}", protocol, info.protocols
+                        );
+                    }
+                    received = true;
+                }
+                IdentifyEvent::Sent { peer_id } => {
+                    assert_eq!(&peer_id, remote.peer_id());
+                    sent = true;
+                }
+                IdentifyEvent::Pushed { .. } => {}
+                IdentifyEvent::Error { error, .. } => panic!("identify failed: {:?}", error),
+            }
+        }
+    }));
+    result.expect("identify timed out");
+}
+
+fn kad(implementation: Implementation, security: Security) {
+    let remote = RemotePeer::start(implementation, security).unwrap();
+    let (keypair, peer_id) = local_keys();
+    let kademlia = Kademlia::new(peer_id.clone(), MemoryStore::new(peer_id.clone()));
+    let transport = build_transport(&keypair, security, upgrade::Version::V1);
+    let mut swarm = Swarm::new(transport, kademlia, peer_id);
+    swarm.add_address(remote.peer_id(), remote.addr().clone());
+    swarm.get_closest_peers(PeerId::random());
+
+    let result = async_std::task::block_on(timeout(TEST_TIMEOUT, async {
+        loop {
+            if let KademliaEvent::QueryResult { result: QueryResult::GetClosestPeers(result), .. } = swarm.next().await {
+                let ok = result.expect("query succeeds");
+                assert!(ok.peers.contains(remote.peer_id()), "{:?} not in {:?}", remote.peer_id(), ok.peers);
+                return
+            }
+        }
+    }));
+    result.expect("kad query timed out");
+}
+
+#[test]
+#[ignore]
+fn go_noise_ping() {
+    ping(Implementation::Go, Security::Noise, upgrade::Version::V1);
+}
+
+#[test]
+#[ignore]
+fn go_secio_ping() {
+    ping(Implementation::Go, Security::Secio, upgrade::Version::V1);
+}
+
+#[test]
+#[ignore]
+fn go_lazy_negotiation() {
+    ping(Implementation::Go, Security::Noise, upgrade::Version::V1Lazy);
+}
+
+#[test]
+#[ignore]
+fn go_identify() {
+    identify(Implementation::Go, Security::Noise, "go-libp2p-interop");
+}
+
+#[test]
+#[ignore]
+fn go_kad() {
+    kad(Implementation::Go, Security::Noise);
+}
+
+#[test]
+#[ignore]
+fn js_noise_ping() {
+    ping(Implementation::Js, Security::Noise, upgrade::Version::V1);
+}
+
+#[test]
+#[ignore]
+fn js_secio_ping() {
+    ping(Implementation::Js, Security::Secio, upgrade::Version::V1);
+}
+
+#[test]
+#[ignore]
+fn js_lazy_negotiation() {
+    ping(Implementation::Js, Security::Noise, upgrade::Version::V1Lazy);
+}
+
+#[test]
+#[ignore]
+fn js_identify() {
+    identify(Implementation::Js, Security::Noise, "js-libp2p");
+}
+
+#[test]
+#[ignore]
+fn js_kad() {
+    kad(Implementation::Js, Security::Noise);
+}
@@ -0,0 +1,244 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Snapshots of the state of a libp2p node for debugging.
+//!
+//! A [`NodeState`] is taken from the [`SwarmState`] of a `Swarm`, obtained
+//! from `Swarm::state`, and optionally extended with the contents of a
+//! [`PeerStore`] and the routing table of [`Kademlia`], e.g. for displaying
+//! them in a debugging UI.
+//!
+//! [`NodeState::encode`] encodes a snapshot in the protobuf format of the
+//! `State` message of the libp2p introspection protocol, of which only the
+//! connections and the DHT are populated. The substreams of the connections
+//! and the peer store are only part of [`NodeState`].
+
+use libp2p_core::{ConnectedPoint, Multiaddr, PeerId};
+use libp2p_kad::{Kademlia, kbucket::NodeStatus, record::store::RecordStore};
+use libp2p_peer_store::{Backend, PeerStore};
+use libp2p_swarm::SwarmState;
+use prost::Message;
+use std::convert::TryFrom;
+use wasm_timer::{SystemTime, UNIX_EPOCH};
+
+mod structs_proto {
+    include!(concat!(env!("OUT_DIR"), "/pb.rs"));
+}
+
+/// A snapshot of the state of a libp2p node.
+#[derive(Debug, Clone)]
+pub struct NodeState {
+    /// The time at which the snapshot was taken.
+    pub timestamp: SystemTime,
+    /// The state of the `Swarm`.
+    pub swarm: SwarmState,
+    /// The peers in the peer store, if added with [`NodeState::with_peer_store`].
+    pub peers: Vec<PeerState>,
+    /// The routing table, if added with [`NodeState::with_kademlia`].
+    pub dht: Option<DhtState>,
+}
+
+/// The record of a peer in a [`PeerStore`].
+#[derive(Debug, Clone)]
+pub struct PeerState {
+    pub peer_id: PeerId,
+    /// The known addresses of the peer.
+    pub addresses: Vec<Multiaddr>,
+    /// Whether the public key of the peer is known.
+    pub has_public_key: bool,
+    /// The number of successful connections to the peer.
+    pub successes: u32,
+    /// The number of failed attempts to connect to the peer.
+    pub failures: u32,
+}
+
+/// The routing table of [`Kademlia`].
+#[derive(Debug, Clone)]
+pub struct DhtState {
+    /// The name of the Kademlia protocol.
+    pub protocol: String,
+    /// The non-empty buckets, in order of increasing distance from the local key.
+    pub buckets: Vec<BucketState>,
+}
+
+/// A bucket of the routing table of [`Kademlia`].
+#[derive(Debug, Clone)]
+pub struct BucketState {
+    /// The length of the prefix the keys of the bucket share with the local key.
+    pub cpl: u32,
+    pub peers: Vec<DhtPeer>,
+}
+
+/// An entry in a bucket of the routing table of [`Kademlia`].
+#[derive(Debug, Clone)]
+pub struct DhtPeer {
+    pub peer_id: PeerId,
+    /// Whether the peer is connected.
+    pub connected: bool,
+    pub addresses: Vec<Multiaddr>,
+}
+
+impl NodeState {
+    /// Takes a snapshot with the given state of the `Swarm`.
+    pub fn new(swarm: SwarmState) -> Self {
+        NodeState { timestamp: SystemTime::now(), swarm, peers: Vec::new(), dht: None }
+    }
+
+    /// Adds the peers of a peer store to the snapshot.
+    pub fn with_peer_store<TBackend: Backend>(mut self, store: &PeerStore<TBackend>) -> Self {
+        self.peers = store.peers()
+            .map(|(peer_id, record)| PeerState {
+                peer_id: peer_id.clone(),
+                addresses: record.addresses().map(|a| a.address().clone()).collect(),
+                has_public_key: record.public_key().is_some(),
+                successes: record.successes(),
+                failures: record.failures(),
+            })
+            .collect();
+        self
+    }
+
+    /// Adds the routing table of Kademlia to the snapshot.
+    pub fn with_kademlia<TStore>(mut self, kademlia: &mut Kademlia<TStore>) -> Self
+    where
+        for<'a> TStore: RecordStore<'a>
+    {
+        let protocol = String::from_utf8_lossy(kademlia.protocol_name()).into_owned();
+        let mut buckets = kademlia.kbuckets()
+            .map(|bucket| BucketState {
+                cpl: u32::try_from(255 - bucket.index()).unwrap_or_default(),
+                peers: bucket.iter()
+                    .map(|entry| DhtPeer {
+                        peer_id: entry.node.key.preimage().clone(),
+                        connected: matches!(entry.status, NodeStatus::Connected),
+                        addresses: entry.node.value.iter().cloned().collect(),
+                    })
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+        buckets.sort_by_key(|b| std::cmp::Reverse(b.cpl));
+        self.dht = Some(DhtState { protocol, buckets });
+        self
+    }
+
+    /// Encodes the snapshot as a `State` message of the libp2p
+    /// introspection protocol.
+    pub fn encode(&self) -> Vec<u8> {
+        let connections = self.swarm.connections.iter()
+            .map(|c| {
+                let (src, dst, role) = match &c.endpoint {
+                    ConnectedPoint::Dialer { address } =>
+                        (String::new(), address.to_string(), structs_proto::Role::Initiator),
+                    ConnectedPoint::Listener { local_addr, send_back_addr } =>
+                        (local_addr.to_string(), send_back_addr.to_string(), structs_proto::Role::Responder),
+                };
+                structs_proto::Connection {
+                    id: format!("{:?}", c.id).into_bytes(),
+                    peer_id: c.peer_id.to_base58(),
+                    status: structs_proto::Status::Active as i32,
+                    endpoints: Some(structs_proto::EndpointPair { src_multiaddr: src, dst_multiaddr: dst }),
+                    role: role as i32,
+                }
+            })
+            .collect();
+        let dht = self.dht.as_ref().map(|dht| structs_proto::Dht {
+            protocol: dht.protocol.clone(),
+            enabled: true,
+            buckets: dht.buckets.iter()
+                .map(|b| structs_proto::dht::Bucket {
+                    cpl: b.cpl,
+                    peers: b.peers.iter()
+                        .map(|p| {
+                            let status = if p.connected {
+                                structs_proto::dht::peer_in_dht::Status::Active
+                            } else {
+                                structs_proto::dht::peer_in_dht::Status::Missing
+                            };
+                            structs_proto::dht::PeerInDht {
+                                peer_id: p.peer_id.to_base58(),
+                                status: status as i32,
+                            }
+                        })
+                        .collect(),
+                })
+                .collect(),
+        });
+        let instant_ts = self.timestamp.duration_since(UNIX_EPOCH)
+            .map(|d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+            .unwrap_or_default();
+        let state = structs_proto::State {
+            subsystems: Some(structs_proto::Subsystems { connections, dht }),
+            instant_ts,
+        };
+        let mut buf = Vec::with_capacity(state.encoded_len());
+        state.encode(&mut buf).expect("Vec<u8> provides capacity as needed");
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_core::{connection::ConnectionId, network::NetworkInfo};
+    use libp2p_swarm::ConnectionState;
+
+    #[test]
+    fn encode_state() {
+        let peer_id = PeerId::random();
+        let address: Multiaddr = "/memory/1234".parse().unwrap();
+        let swarm = SwarmState {
+            local_peer_id: PeerId::random(),
+            listeners: Vec::new(),
+            external_addresses: Vec::new(),
+            network_info: NetworkInfo {
+                num_peers: 1,
+                num_connections: 1,
+                num_connections_pending: 0,
+                num_connections_established: 1,
+            },
+            connections: vec![ConnectionState {
+                id: ConnectionId::new(1),
+                peer_id: peer_id.clone(),
+                endpoint: ConnectedPoint::Dialer { address: address.clone() },
+                substreams: Vec::new(),
+            }],
+        };
+        let mut state = NodeState::new(swarm);
+        state.dht = Some(DhtState {
+            protocol: "/ipfs/kad/1.0.0".to_string(),
+            buckets: vec![BucketState {
+                cpl: 3,
+                peers: vec![DhtPeer { peer_id: peer_id.clone(), connected: false, addresses: Vec::new() }],
+            }],
+        });
+
+        let decoded = structs_proto::State::decode(&state.encode()[..]).unwrap();
+        assert!(decoded.instant_ts > 0);
+        let subsystems = decoded.subsystems.unwrap();
+        let connection = &subsystems.connections[0];
+        assert_eq!(connection.peer_id, peer_id.to_base58());
+        assert_eq!(connection.role, structs_proto::Role::Initiator as i32);
+        assert_eq!(connection.endpoints.as_ref().unwrap().dst_multiaddr, address.to_string());
+        let dht = subsystems.dht.unwrap();
+        assert_eq!(dht.protocol, "/ipfs/kad/1.0.0");
+        assert_eq!(dht.buckets[0].cpl, 3);
+        assert_eq!(dht.buckets[0].peers[0].status, structs_proto::dht::peer_in_dht::Status::Missing as i32);
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_core::{
+    Multiaddr,
+    PeerId,
+    Transport,
+    identity,
+    multiaddr::Protocol,
+    muxing::StreamMuxerBox,
+    transport::{MemoryTransport, boxed::Boxed},
+    upgrade,
+};
+use libp2p_introspection::NodeState;
+use libp2p_kad::{Kademlia, KademliaEvent, QueryResult, record::store::MemoryStore};
+use libp2p_plaintext::PlainText2Config;
+use libp2p_swarm::Swarm;
+use libp2p_yamux as yamux;
+use std::io;
+
+type TestSwarm = Swarm<Kademlia<MemoryStore>>;
+
+fn build_transport(keys: &identity::Keypair) -> Boxed<(PeerId, StreamMuxerBox), io::Error> {
+    MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(PlainText2Config { local_public_key: keys.public() })
+        .multiplex(yamux::Config::default())
+        .map(|(p, m), _| (p, StreamMuxerBox::new(m)))
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+        .boxed()
+}
+
+fn build_swarm() -> (PeerId, TestSwarm) {
+    let keys = identity::Keypair::generate_ed25519();
+    let peer_id = keys.public().into_peer_id();
+    let kademlia = Kademlia::new(peer_id.clone(), MemoryStore::new(peer_id.clone()));
+    (peer_id.clone(), Swarm::new(build_transport(&keys), kademlia, peer_id))
+}
+
+#[test]
+fn node_state() {
+    let (peer1, mut swarm1) = build_swarm();
+    let (peer2, mut swarm2) = build_swarm();
+
+    let addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    Swarm::listen_on(&mut swarm1, addr.clone()).unwrap();
+    swarm2.add_address(&peer1, addr.clone());
+    swarm2.bootstrap().unwrap();
+
+    async_std::task::spawn(async move {
+        loop {
+            swarm1.next_event().await;
+        }
+    });
+    async_std::task::block_on(async {
+        loop {
+            if let KademliaEvent::QueryResult { result: QueryResult::Bootstrap(_), .. } = swarm2.next().await {
+                break
+            }
+        }
+    });
+
+    let state = NodeState::new(Swarm::state(&mut swarm2)).with_kademlia(&mut swarm2);
+    assert_eq!(state.swarm.local_peer_id, peer2);
+    assert_eq!(state.swarm.connections.len(), 1);
+    let connection = &state.swarm.connections[0];
+    assert_eq!(connection.peer_id, peer1);
+    assert!(connection.endpoint.is_dialer());
+    assert!(connection.substreams.iter().any(|(p, n)| p == "/ipfs/kad/1.0.0" && *n >= 1),
+        "{:?}", connection.substreams);
+
+    let dht = state.dht.as_ref().unwrap();
+    assert_eq!(dht.protocol, "/ipfs/kad/1.0.0");
+    let peers = dht.buckets.iter().flat_map(|b| &b.peers).collect::<Vec<_>>();
+    assert_eq!(peers.len(), 1);
+    assert_eq!(peers[0].peer_id, peer1);
+    assert!(peers[0].connected);
+    assert_eq!(peers[0].addresses, vec![addr]);
+
+    assert!(!state.encode().is_empty());
+}
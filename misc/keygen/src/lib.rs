@@ -0,0 +1,429 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Generation, inspection and conversion of identity keys.
+//!
+//! A [`Key`] is the private key of a node identity, which can be decoded
+//! from and encoded to the following [`Format`]s:
+//!
+//!   - [`Format::Pkcs8`]: a DER-encoded PKCS#8 `PrivateKeyInfo`, as written
+//!     e.g. by `openssl genpkey -outform DER`.
+//!   - [`Format::Protobuf`]: the protobuf encoding of libp2p, as returned by
+//!     [`Keypair::to_protobuf_encoding`].
+//!   - [`Format::Base64`]: the base64 encoding of the protobuf encoding, as
+//!     found e.g. in the configuration of go-ipfs.
+//!
+//! The `libp2p-keygen` binary of this crate generates keys, prints their
+//! peer IDs and converts them between these formats, such that keys no
+//! longer need to be generated with OpenSSL and checked in as PKCS#8 files:
+//!
+//! ```text
+//! libp2p-keygen generate ed25519 --out identity.key
+//! libp2p-keygen inspect identity.key
+//! libp2p-keygen convert identity.key --format pkcs8 --out identity.pk8
+//! ```
+
+use libp2p_core::{PeerId, identity::{self, Keypair, error::DecodingError}};
+use prost::Message;
+use std::{error, fmt, str::FromStr};
+use yasna::models::ObjectIdentifier;
+use zeroize::Zeroizing;
+
+mod keys_proto {
+    include!(concat!(env!("OUT_DIR"), "/keys_proto.rs"));
+}
+
+/// The OID of RSA keys, `rsaEncryption`.
+const RSA_OID: &[u64] = &[1, 2, 840, 113549, 1, 1, 1];
+/// The OID of Ed25519 keys, `id-Ed25519`.
+const ED25519_OID: &[u64] = &[1, 3, 101, 112];
+/// The OID of elliptic curve keys, `id-ecPublicKey`.
+const EC_OID: &[u64] = &[1, 2, 840, 10045, 2, 1];
+/// The OID of the secp256k1 curve, the parameter of elliptic curve keys.
+const SECP256K1_OID: &[u64] = &[1, 3, 132, 0, 10];
+
+/// The type of a [`Key`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyType {
+    /// An RSA key.
+    Rsa,
+    /// An Ed25519 key.
+    Ed25519,
+    /// A secp256k1 key.
+    Secp256k1,
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyType::Rsa => f.write_str("rsa"),
+            KeyType::Ed25519 => f.write_str("ed25519"),
+            KeyType::Secp256k1 => f.write_str("secp256k1"),
+        }
+    }
+}
+
+impl FromStr for KeyType {
+    type Err = KeygenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "rsa" => Ok(KeyType::Rsa),
+            "ed25519" => Ok(KeyType::Ed25519),
+            "secp256k1" => Ok(KeyType::Secp256k1),
+            _ => Err(KeygenError::UnknownKeyType(s.to_owned())),
+        }
+    }
+}
+
+/// An encoding of a [`Key`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    /// A DER-encoded PKCS#8 `PrivateKeyInfo` as defined in [RFC5208].
+    ///
+    /// [RFC5208]: https://tools.ietf.org/html/rfc5208#section-5
+    Pkcs8,
+    /// The protobuf encoding of libp2p.
+    Protobuf,
+    /// The base64 encoding of the protobuf encoding.
+    Base64,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Pkcs8 => f.write_str("pkcs8"),
+            Format::Protobuf => f.write_str("protobuf"),
+            Format::Base64 => f.write_str("base64"),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = KeygenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "pkcs8" => Ok(Format::Pkcs8),
+            "protobuf" => Ok(Format::Protobuf),
+            "base64" => Ok(Format::Base64),
+            _ => Err(KeygenError::UnknownFormat(s.to_owned())),
+        }
+    }
+}
+
+/// The private key of a node identity.
+#[derive(Clone)]
+pub struct Key {
+    key_type: KeyType,
+    /// The key in the `Data` field of the protobuf encoding, i.e. the
+    /// Ed25519 keypair, the secp256k1 secret key or the PKCS#1 encoding
+    /// of the RSA key.
+    data: Zeroizing<Vec<u8>>,
+}
+
+impl Key {
+    /// Generates a new key of the given type.
+    ///
+    /// Generating RSA keys is not supported, RSA keys can be generated with
+    /// OpenSSL and decoded from [`Format::Pkcs8`] instead.
+    pub fn generate(key_type: KeyType) -> Result<Key, KeygenError> {
+        let data = match key_type {
+            KeyType::Ed25519 => identity::ed25519::Keypair::generate().encode().to_vec(),
+            KeyType::Secp256k1 => identity::secp256k1::Keypair::generate().secret().to_bytes().to_vec(),
+            KeyType::Rsa => return Err(KeygenError::Unsupported("generating RSA keys")),
+        };
+        Ok(Key { key_type, data: Zeroizing::new(data) })
+    }
+
+    /// Decodes a key in the given format.
+    pub fn decode(bytes: &[u8], format: Format) -> Result<Key, KeygenError> {
+        let key = match format {
+            Format::Pkcs8 => Key::decode_pkcs8(bytes)?,
+            Format::Protobuf => Key::decode_protobuf(bytes)?,
+            Format::Base64 => {
+                let text = std::str::from_utf8(bytes)
+                    .map_err(|_| KeygenError::InvalidFormat("base64 key is not valid UTF-8"))?;
+                let protobuf = Zeroizing::new(base64::decode(text.trim())
+                    .map_err(|_| KeygenError::InvalidFormat("invalid base64"))?);
+                Key::decode_protobuf(&protobuf)?
+            }
+        };
+        // Ensure that the key can be used as a keypair.
+        key.try_keypair()?;
+        Ok(key)
+    }
+
+    /// Decodes a key in any of the supported formats, returning the key
+    /// together with its format.
+    pub fn decode_any(bytes: &[u8]) -> Result<(Key, Format), KeygenError> {
+        for &format in &[Format::Pkcs8, Format::Protobuf, Format::Base64] {
+            if let Ok(key) = Key::decode(bytes, format) {
+                return Ok((key, format))
+            }
+        }
+        Err(KeygenError::InvalidFormat("not a key in any supported format"))
+    }
+
+    /// Encodes the key in the given format.
+    pub fn encode(&self, format: Format) -> Vec<u8> {
+        match format {
+            Format::Pkcs8 => self.encode_pkcs8(),
+            Format::Protobuf => self.encode_protobuf(),
+            Format::Base64 => base64::encode(&*Zeroizing::new(self.encode_protobuf())).into_bytes(),
+        }
+    }
+
+    /// Returns the type of the key.
+    pub fn key_type(&self) -> KeyType {
+        self.key_type
+    }
+
+    /// Returns the keypair of the key.
+    pub fn keypair(&self) -> Keypair {
+        self.try_keypair().expect("the key has been validated when decoded")
+    }
+
+    /// Returns the ID of the peer identified by the key.
+    pub fn peer_id(&self) -> PeerId {
+        self.keypair().public().into_peer_id()
+    }
+
+    fn try_keypair(&self) -> Result<Keypair, DecodingError> {
+        let mut data = self.data.clone();
+        match self.key_type {
+            KeyType::Rsa => identity::rsa::Keypair::from_pkcs1(&mut data).map(Keypair::Rsa),
+            KeyType::Ed25519 => identity::ed25519::Keypair::decode(&mut data).map(Keypair::Ed25519),
+            KeyType::Secp256k1 => identity::secp256k1::SecretKey::from_bytes(&mut *data)
+                .map(|sk| Keypair::Secp256k1(sk.into())),
+        }
+    }
+
+    fn decode_protobuf(bytes: &[u8]) -> Result<Key, KeygenError> {
+        let private_key = keys_proto::PrivateKey::decode(bytes)
+            .map_err(|_| KeygenError::InvalidFormat("invalid protobuf encoding"))?;
+        let data = Zeroizing::new(private_key.data);
+        let key_type = match keys_proto::KeyType::from_i32(private_key.r#type) {
+            Some(keys_proto::KeyType::Rsa) => KeyType::Rsa,
+            Some(keys_proto::KeyType::Ed25519) => KeyType::Ed25519,
+            Some(keys_proto::KeyType::Secp256k1) => KeyType::Secp256k1,
+            Some(keys_proto::KeyType::Ecdsa) => return Err(KeygenError::Unsupported("ECDSA keys")),
+            None => return Err(KeygenError::InvalidFormat("unknown key type")),
+        };
+        Ok(Key { key_type, data })
+    }
+
+    fn encode_protobuf(&self) -> Vec<u8> {
+        let key_type = match self.key_type {
+            KeyType::Rsa => keys_proto::KeyType::Rsa,
+            KeyType::Ed25519 => keys_proto::KeyType::Ed25519,
+            KeyType::Secp256k1 => keys_proto::KeyType::Secp256k1,
+        };
+        let private_key = keys_proto::PrivateKey {
+            r#type: key_type as i32,
+            data: self.data.to_vec(),
+        };
+        let mut buf = Vec::with_capacity(private_key.encoded_len());
+        private_key.encode(&mut buf).expect("Vec<u8> provides capacity as needed");
+        drop(Zeroizing::new(private_key.data));
+        buf
+    }
+
+    fn decode_pkcs8(bytes: &[u8]) -> Result<Key, KeygenError> {
+        let (algorithm, parameters, private_key) = yasna::parse_der(bytes, |r| r.read_sequence(|r| {
+            let version = r.next().read_u8()?;
+            if version > 1 {
+                return Err(yasna::ASN1Error::new(yasna::ASN1ErrorKind::Invalid))
+            }
+            let (algorithm, parameters) = r.next().read_sequence(|r| {
+                let algorithm = r.next().read_oid()?;
+                let parameters = r.read_optional(|r| r.read_der())?;
+                Ok((algorithm, parameters))
+            })?;
+            let private_key = Zeroizing::new(r.next().read_bytes()?);
+            // The optional attributes and, in version 1, the public key.
+            r.read_optional(|r| r.read_der())?;
+            r.read_optional(|r| r.read_der())?;
+            Ok((algorithm, parameters, private_key))
+        })).map_err(|_| KeygenError::InvalidFormat("invalid PKCS#8 encoding"))?;
+
+        let (key_type, data) = if algorithm.components() == RSA_OID {
+            (KeyType::Rsa, private_key)
+        } else if algorithm.components() == ED25519_OID {
+            let secret = Zeroizing::new(yasna::parse_der(&private_key, |r| r.read_bytes())
+                .map_err(|_| KeygenError::InvalidFormat("invalid Ed25519 private key"))?);
+            let secret = identity::ed25519::SecretKey::from_bytes(secret.to_vec())
+                .map_err(KeygenError::Decoding)?;
+            let keypair = identity::ed25519::Keypair::from(secret);
+            (KeyType::Ed25519, Zeroizing::new(keypair.encode().to_vec()))
+        } else if algorithm.components() == EC_OID {
+            if parameters.as_deref() != Some(&oid_der(SECP256K1_OID)[..]) {
+                return Err(KeygenError::Unsupported("elliptic curves other than secp256k1"))
+            }
+            let secret = identity::secp256k1::SecretKey::from_der(private_key.to_vec())
+                .map_err(KeygenError::Decoding)?;
+            (KeyType::Secp256k1, Zeroizing::new(secret.to_bytes().to_vec()))
+        } else {
+            return Err(KeygenError::Unsupported("PKCS#8 key algorithm"))
+        };
+        Ok(Key { key_type, data })
+    }
+
+    fn encode_pkcs8(&self) -> Vec<u8> {
+        let (algorithm, parameters, private_key) = match self.key_type {
+            KeyType::Rsa => (RSA_OID, Some(yasna::construct_der(|w| w.write_null())), self.data.clone()),
+            KeyType::Ed25519 => {
+                // An Ed25519 keypair starts with the 32 bytes of the secret key.
+                let private_key = yasna::construct_der(|w| w.write_bytes(&self.data[.. 32]));
+                (ED25519_OID, None, Zeroizing::new(private_key))
+            }
+            KeyType::Secp256k1 => {
+                // An ECPrivateKey as defined in RFC5915, with the curve given
+                // by the parameters of the algorithm.
+                let private_key = yasna::construct_der(|w| w.write_sequence(|w| {
+                    w.next().write_u8(1);
+                    w.next().write_bytes(&self.data);
+                }));
+                (EC_OID, Some(oid_der(SECP256K1_OID)), Zeroizing::new(private_key))
+            }
+        };
+        yasna::construct_der(|w| w.write_sequence(|w| {
+            w.next().write_u8(0);
+            w.next().write_sequence(|w| {
+                w.next().write_oid(&ObjectIdentifier::from_slice(algorithm));
+                if let Some(parameters) = &parameters {
+                    w.next().write_der(parameters);
+                }
+            });
+            w.next().write_bytes(&private_key);
+        }))
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Key")
+            .field("key_type", &self.key_type)
+            .field("peer_id", &self.peer_id())
+            .finish()
+    }
+}
+
+/// Returns the DER encoding of an OID.
+fn oid_der(oid: &[u64]) -> Vec<u8> {
+    yasna::construct_der(|w| w.write_oid(&ObjectIdentifier::from_slice(oid)))
+}
+
+/// An error generating, decoding or encoding a [`Key`].
+#[derive(Debug)]
+pub enum KeygenError {
+    /// The key type is not one of `rsa`, `ed25519` and `secp256k1`.
+    UnknownKeyType(String),
+    /// The format is not one of `pkcs8`, `protobuf` and `base64`.
+    UnknownFormat(String),
+    /// A key is malformed.
+    InvalidFormat(&'static str),
+    /// A key, or an operation on a key, is not supported.
+    Unsupported(&'static str),
+    /// A key could not be decoded into a keypair.
+    Decoding(DecodingError),
+}
+
+impl fmt::Display for KeygenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeygenError::UnknownKeyType(t) => write!(f, "Unknown key type: {:?}", t),
+            KeygenError::UnknownFormat(t) => write!(f, "Unknown key format: {:?}", t),
+            KeygenError::InvalidFormat(msg) => write!(f, "Invalid key: {}", msg),
+            KeygenError::Unsupported(what) => write!(f, "Not supported: {}", what),
+            KeygenError::Decoding(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for KeygenError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            KeygenError::Decoding(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<DecodingError> for KeygenError {
+    fn from(e: DecodingError) -> Self {
+        KeygenError::Decoding(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The Ed25519 private key of the example in RFC8410, section 10.3.
+    const ED25519_PKCS8: &str = "MC4CAQAwBQYDK2VwBCIEINTuctv5E1hK1bbY8fdp+K06/nwoy/HU++CXqI9EdVhC";
+
+    #[test]
+    fn roundtrip() {
+        for &key_type in &[KeyType::Ed25519, KeyType::Secp256k1] {
+            let key = Key::generate(key_type).unwrap();
+            for &format in &[Format::Pkcs8, Format::Protobuf, Format::Base64] {
+                let encoded = key.encode(format);
+                let decoded = Key::decode(&encoded, format).unwrap();
+                assert_eq!(decoded.key_type(), key_type);
+                assert_eq!(decoded.peer_id(), key.peer_id());
+                let (detected, detected_format) = Key::decode_any(&encoded).unwrap();
+                assert_eq!(detected_format, format);
+                assert_eq!(detected.peer_id(), key.peer_id());
+            }
+        }
+    }
+
+    #[test]
+    fn protobuf_encoding_of_keypair() {
+        let key = Key::generate(KeyType::Ed25519).unwrap();
+        let keypair = Keypair::from_protobuf_encoding(&key.encode(Format::Protobuf)).unwrap();
+        assert_eq!(keypair.public(), key.keypair().public());
+        assert_eq!(keypair.to_protobuf_encoding().unwrap(), key.encode(Format::Protobuf));
+    }
+
+    #[test]
+    fn ed25519_pkcs8() {
+        let pkcs8 = base64::decode(ED25519_PKCS8).unwrap();
+        let key = Key::decode(&pkcs8, Format::Pkcs8).unwrap();
+        assert_eq!(key.key_type(), KeyType::Ed25519);
+        assert_eq!(key.encode(Format::Pkcs8), pkcs8);
+    }
+
+    #[test]
+    fn rsa_pkcs8() {
+        let pkcs8 = include_bytes!("../../../core/src/identity/test/rsa-2048.pk8");
+        let key = Key::decode(pkcs8, Format::Pkcs8).unwrap();
+        assert_eq!(key.key_type(), KeyType::Rsa);
+        assert_eq!(key.encode(Format::Pkcs8), pkcs8.to_vec());
+        let keypair = Keypair::rsa_from_pkcs8(&mut pkcs8.to_vec()).unwrap();
+        assert_eq!(key.peer_id(), keypair.public().into_peer_id());
+
+        let protobuf = key.encode(Format::Protobuf);
+        let decoded = Keypair::from_protobuf_encoding(&protobuf).unwrap();
+        assert_eq!(decoded.public(), keypair.public());
+        assert!(Key::generate(KeyType::Rsa).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_keygen::{Format, Key, KeyType};
+use std::{env, error, fs, io::{self, Read, Write}, process};
+use zeroize::Zeroizing;
+
+const USAGE: &str = "\
+Usage:
+    libp2p-keygen generate <rsa|ed25519|secp256k1> [--bits <bits>] [--format <format>] [--out <file>]
+    libp2p-keygen inspect <file>
+    libp2p-keygen convert <file> --format <format> [--out <file>]
+
+Generates identity keys, prints their peer IDs and converts them between
+formats. The format is one of pkcs8, protobuf and base64, the latter being
+the default. Keys are read from standard input if <file> is `-` and written
+to standard output if no --out file is given.
+
+RSA keys are generated with `openssl genpkey`, of --bits bits (default 2048).";
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let (command, positional, options) = match parse(&args) {
+        Some(args) => args,
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(command, positional, options) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(command: &str, positional: &str, options: Options<'_>) -> Result<(), Box<dyn error::Error>> {
+    let format = options.format.map(|f| f.parse::<Format>()).transpose()?;
+
+    match command {
+        "generate" => {
+            let key_type = positional.parse::<KeyType>()?;
+            let key = match key_type {
+                KeyType::Rsa => generate_rsa(options.bits.unwrap_or("2048"))?,
+                _ => Key::generate(key_type)?,
+            };
+            eprintln!("Generated {} key of peer {}", key_type, key.peer_id());
+            write_key(&key, format.unwrap_or(Format::Base64), options.out)
+        }
+        "inspect" => {
+            let (key, format) = Key::decode_any(&read_file(positional)?)?;
+            println!("Format:     {}", format);
+            println!("Key type:   {}", key.key_type());
+            println!("Peer ID:    {}", key.peer_id());
+            println!("Public key: {}", base64::encode(&key.keypair().public().into_protobuf_encoding()));
+            Ok(())
+        }
+        "convert" => {
+            let format = format.ok_or("missing --format")?;
+            let (key, _) = Key::decode_any(&read_file(positional)?)?;
+            write_key(&key, format, options.out)
+        }
+        _ => unreachable!("commands are checked by `parse`"),
+    }
+}
+
+/// The options given on the command line.
+#[derive(Default)]
+struct Options<'a> {
+    bits: Option<&'a str>,
+    format: Option<&'a str>,
+    out: Option<&'a str>,
+}
+
+/// Parses the command, the positional argument and the options.
+fn parse(args: &[String]) -> Option<(&str, &str, Options<'_>)> {
+    let mut args = args.iter().map(String::as_str);
+    let command = args.next().filter(|c| ["generate", "inspect", "convert"].contains(c))?;
+    let mut positional = None;
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg {
+            "--bits" => options.bits = Some(args.next()?),
+            "--format" => options.format = Some(args.next()?),
+            "--out" => options.out = Some(args.next()?),
+            _ if positional.is_none() && (arg == "-" || !arg.starts_with('-')) => positional = Some(arg),
+            _ => return None,
+        }
+    }
+    Some((command, positional?, options))
+}
+
+/// Generates an RSA key with OpenSSL, as RSA keys can not be generated
+/// with the cryptographic libraries used by libp2p.
+fn generate_rsa(bits: &str) -> Result<Key, Box<dyn error::Error>> {
+    let bits = bits.parse::<u32>().map_err(|_| "invalid --bits")?;
+    let output = process::Command::new("openssl")
+        .args(["genpkey", "-algorithm", "RSA"])
+        .arg("-pkeyopt").arg(format!("rsa_keygen_bits:{}", bits))
+        .stderr(process::Stdio::inherit())
+        .output()
+        .map_err(|e| format!("failed to run openssl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("openssl failed: {}", output.status).into())
+    }
+    // The key is written as PEM-encoded PKCS#8, as newer versions of OpenSSL
+    // write RSA keys in DER format as PKCS#1.
+    let pem = Zeroizing::new(output.stdout);
+    let base64 = Zeroizing::new(String::from_utf8_lossy(&pem)
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect::<String>());
+    let pkcs8 = Zeroizing::new(base64::decode(&*base64).map_err(|_| "invalid openssl output")?);
+    Ok(Key::decode(&pkcs8, Format::Pkcs8)?)
+}
+
+fn read_file(file: &str) -> io::Result<Zeroizing<Vec<u8>>> {
+    if file == "-" {
+        let mut bytes = Zeroizing::new(Vec::new());
+        io::stdin().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    } else {
+        fs::read(file).map(Zeroizing::new)
+    }
+}
+
+fn write_key(key: &Key, format: Format, out: Option<&str>) -> Result<(), Box<dyn error::Error>> {
+    let mut bytes = Zeroizing::new(key.encode(format));
+    if format == Format::Base64 {
+        bytes.push(b'\n');
+    }
+    match out {
+        Some(file) => fs::write(file, &*bytes)?,
+        None => io::stdout().write_all(&bytes)?,
+    }
+    Ok(())
+}
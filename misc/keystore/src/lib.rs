@@ -0,0 +1,511 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Passphrase-encrypted storage of identity keys on disk.
+//!
+//! A [`Keystore`] is a directory holding one file per named keypair. Each
+//! keypair is stored in the protobuf encoding of
+//! [`Keypair::to_protobuf_encoding`], encrypted with AES-256-GCM under a key
+//! that is derived from the passphrase of the keystore with scrypt.
+//!
+//! The node identity is stored under the name [`IDENTITY`]. The first call to
+//! [`Keystore::identity`] generates an Ed25519 identity and stores it, later
+//! calls load it:
+//!
+//! ```no_run
+//! use libp2p_keystore::Keystore;
+//!
+//! let keystore = Keystore::new("/var/lib/my-node/keys", "correct horse battery staple");
+//! let local_key = keystore.identity().expect("identity can be loaded");
+//! let local_peer_id = local_key.public().into_peer_id();
+//! ```
+
+use libp2p_core::identity::{Keypair, error::{DecodingError, EncodingError}};
+use ring::{aead, rand::{SecureRandom, SystemRandom}};
+use scrypt::ScryptParams;
+use std::{error, fmt, fs, io::{self, Write}, path::PathBuf};
+use zeroize::Zeroize;
+
+/// The name under which [`Keystore::identity`] stores the node identity.
+pub const IDENTITY: &str = "identity";
+
+/// Leading bytes of every key file.
+const MAGIC: &[u8] = b"libp2p-keystore";
+
+/// Version of the key file format.
+const VERSION: u8 = 1;
+
+/// Length of the random salt passed to scrypt.
+const SALT_LEN: usize = 16;
+
+/// Length of the header of a key file, which is authenticated together with
+/// the encrypted keypair:
+/// magic, version, scrypt `log_n`, `r` and `p`, salt and nonce.
+const HEADER_LEN: usize = MAGIC.len() + 1 + 1 + 4 + 4 + SALT_LEN + aead::NONCE_LEN;
+
+/// Upper bound of the memory used by scrypt when loading a key file, such
+/// that a manipulated file can not make us allocate arbitrary amounts of memory.
+const MAX_SCRYPT_MEMORY: u64 = 1 << 31;
+
+/// Upper bound of the scrypt parallelization `p` when loading a key file.
+const MAX_SCRYPT_P: u32 = 16;
+
+/// Upper bound of `log_n * p` when loading a key file, such that a
+/// manipulated file can not make us spend arbitrary amounts of CPU time.
+const MAX_SCRYPT_LOG_N_P: u32 = 128;
+
+/// File extension of key files.
+const EXTENSION: &str = "key";
+
+/// A directory of passphrase-encrypted keypairs.
+pub struct Keystore {
+    dir: PathBuf,
+    passphrase: Passphrase,
+    log_n: u8,
+    r: u32,
+    p: u32,
+}
+
+impl Keystore {
+    /// Creates a keystore in the given directory, encrypting keypairs with the
+    /// given passphrase.
+    ///
+    /// The directory is created when the first keypair is stored.
+    pub fn new(dir: impl Into<PathBuf>, passphrase: impl Into<String>) -> Self {
+        Keystore {
+            dir: dir.into(),
+            passphrase: Passphrase(passphrase.into()),
+            log_n: 15,
+            r: 8,
+            p: 1,
+        }
+    }
+
+    /// Sets the scrypt parameters used when storing keypairs, i.e. the
+    /// CPU/memory cost `2^log_n`, the block size `r` and the parallelization `p`.
+    ///
+    /// Defaults to `log_n = 15`, `r = 8` and `p = 1`, i.e. 32 MiB of memory.
+    /// Loading uses the parameters stored with each keypair.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `r` or `p` is zero, if `p` exceeds 16, if `log_n * p`
+    /// exceeds 128, or if the parameters require more than 2 GiB of memory.
+    pub fn with_scrypt_params(mut self, log_n: u8, r: u32, p: u32) -> Self {
+        assert!(scrypt_params_valid(log_n, r, p), "invalid scrypt parameters");
+        self.log_n = log_n;
+        self.r = r;
+        self.p = p;
+        self
+    }
+
+    /// Returns the directory of this keystore.
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    /// Loads the node identity, generating and storing a new Ed25519 keypair
+    /// if there is none yet.
+    pub fn identity(&self) -> Result<Keypair, KeystoreError> {
+        self.load_or_generate(IDENTITY, Keypair::generate_ed25519)
+    }
+
+    /// Loads the keypair with the given name, generating and storing a new
+    /// one with `generate` if there is none yet.
+    pub fn load_or_generate<F>(&self, name: &str, generate: F) -> Result<Keypair, KeystoreError>
+    where
+        F: FnOnce() -> Keypair
+    {
+        if let Some(keypair) = self.load(name)? {
+            return Ok(keypair)
+        }
+        let keypair = generate();
+        self.store(name, &keypair)?;
+        log::debug!("Generated keypair {:?} in {}", name, self.dir.display());
+        Ok(keypair)
+    }
+
+    /// Loads the keypair with the given name, returning `None` if there is none.
+    pub fn load(&self, name: &str) -> Result<Option<Keypair>, KeystoreError> {
+        let mut file = match fs::read(self.path(name)?) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(KeystoreError::Io(e))
+        };
+        let result = self.decrypt(&mut file);
+        file.zeroize();
+        result.map(Some)
+    }
+
+    /// Stores a keypair under the given name, replacing any keypair of that name.
+    ///
+    /// On Unix, the key file is only accessible by its owner.
+    pub fn store(&self, name: &str, keypair: &Keypair) -> Result<(), KeystoreError> {
+        let path = self.path(name)?;
+        let mut file = self.encrypt(keypair)?;
+
+        fs::create_dir_all(&self.dir)?;
+        let tmp_path = path.with_extension("tmp");
+        let result = write_private(&tmp_path, &file).and_then(|()| fs::rename(&tmp_path, &path));
+        file.zeroize();
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        Ok(result?)
+    }
+
+    /// Removes the keypair with the given name, returning whether it existed.
+    pub fn remove(&self, name: &str) -> Result<bool, KeystoreError> {
+        match fs::remove_file(self.path(name)?) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(KeystoreError::Io(e))
+        }
+    }
+
+    /// Returns the names of all stored keypairs, in lexicographic order.
+    pub fn names(&self) -> Result<Vec<String>, KeystoreError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(KeystoreError::Io(e))
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == EXTENSION) {
+                if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                    if name_valid(name) {
+                        names.push(name.to_owned())
+                    }
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Returns the path of the key file of the given name.
+    fn path(&self, name: &str) -> Result<PathBuf, KeystoreError> {
+        if !name_valid(name) {
+            return Err(KeystoreError::InvalidName(name.to_owned()))
+        }
+        Ok(self.dir.join(name).with_extension(EXTENSION))
+    }
+
+    /// Encrypts a keypair into the contents of a key file.
+    fn encrypt(&self, keypair: &Keypair) -> Result<Vec<u8>, KeystoreError> {
+        let rng = SystemRandom::new();
+        let mut salt = [0; SALT_LEN];
+        let mut nonce = [0; aead::NONCE_LEN];
+        rng.fill(&mut salt).map_err(|_| KeystoreError::Random)?;
+        rng.fill(&mut nonce).map_err(|_| KeystoreError::Random)?;
+
+        let mut file = Vec::with_capacity(HEADER_LEN + 128);
+        file.extend_from_slice(MAGIC);
+        file.push(VERSION);
+        file.push(self.log_n);
+        file.extend_from_slice(&self.r.to_be_bytes());
+        file.extend_from_slice(&self.p.to_be_bytes());
+        file.extend_from_slice(&salt);
+        file.extend_from_slice(&nonce);
+        debug_assert_eq!(file.len(), HEADER_LEN);
+
+        let key = self.derive_key(&salt, self.log_n, self.r, self.p);
+        let mut data = keypair.to_protobuf_encoding().map_err(KeystoreError::Encoding)?;
+        let result = key.seal_in_place_append_tag(
+            aead::Nonce::assume_unique_for_key(nonce),
+            aead::Aad::from(&file[..]),
+            &mut data
+        );
+        if result.is_err() {
+            data.zeroize();
+            return Err(KeystoreError::Encryption)
+        }
+        file.extend_from_slice(&data);
+        data.zeroize();
+        Ok(file)
+    }
+
+    /// Decrypts the contents of a key file into a keypair.
+    fn decrypt(&self, file: &mut [u8]) -> Result<Keypair, KeystoreError> {
+        if file.len() < HEADER_LEN || !file.starts_with(MAGIC) {
+            return Err(KeystoreError::InvalidFormat("not a key file"))
+        }
+        let (header, data) = file.split_at_mut(HEADER_LEN);
+        let (version, params) = header[MAGIC.len() ..].split_at(1);
+        if version[0] != VERSION {
+            return Err(KeystoreError::InvalidFormat("unsupported version"))
+        }
+        let log_n = params[0];
+        let r = u32::from_be_bytes([params[1], params[2], params[3], params[4]]);
+        let p = u32::from_be_bytes([params[5], params[6], params[7], params[8]]);
+        if !scrypt_params_valid(log_n, r, p) {
+            return Err(KeystoreError::InvalidFormat("invalid scrypt parameters"))
+        }
+        let (salt, nonce) = params[9 ..].split_at(SALT_LEN);
+        let nonce = aead::Nonce::try_assume_unique_for_key(nonce).expect("nonce has NONCE_LEN bytes");
+
+        let key = self.derive_key(salt, log_n, r, p);
+        let plaintext = key.open_in_place(nonce, aead::Aad::from(&*header), data)
+            .map_err(|_| KeystoreError::Decryption)?;
+        Keypair::from_protobuf_encoding(plaintext).map_err(KeystoreError::Decoding)
+    }
+
+    /// Derives the AES-256-GCM key from the passphrase.
+    fn derive_key(&self, salt: &[u8], log_n: u8, r: u32, p: u32) -> aead::LessSafeKey {
+        let params = ScryptParams::new(log_n, r, p).expect("scrypt parameters have been validated");
+        let mut key = [0; 32];
+        scrypt::scrypt(self.passphrase.0.as_bytes(), salt, &params, &mut key)
+            .expect("32 bytes are a valid scrypt output length");
+        let unbound = aead::UnboundKey::new(&aead::AES_256_GCM, &key).expect("key has 32 bytes");
+        key.zeroize();
+        aead::LessSafeKey::new(unbound)
+    }
+}
+
+impl fmt::Debug for Keystore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keystore")
+            .field("dir", &self.dir)
+            .field("log_n", &self.log_n)
+            .field("r", &self.r)
+            .field("p", &self.p)
+            .finish()
+    }
+}
+
+/// The passphrase of a keystore, zeroed on drop.
+struct Passphrase(String);
+
+impl Drop for Passphrase {
+    fn drop(&mut self) {
+        self.0.zeroize()
+    }
+}
+
+/// Whether the name is a valid keypair name, i.e. a non-empty sequence of
+/// at most 64 ASCII alphanumeric characters, `-` and `_`.
+fn name_valid(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Whether the scrypt parameters are valid and within our memory and CPU bounds.
+fn scrypt_params_valid(log_n: u8, r: u32, p: u32) -> bool {
+    // RFC7914 requires N < 2^(128 * r / 8).
+    if log_n == 0 || log_n >= 32 || r == 0 || p == 0 || u64::from(log_n) >= 16 * u64::from(r) {
+        return false
+    }
+    if p > MAX_SCRYPT_P || u32::from(log_n) * p > MAX_SCRYPT_LOG_N_P {
+        return false
+    }
+    let memory = u64::from(r).saturating_mul(128).saturating_mul((1 << log_n) + u64::from(p));
+    memory <= MAX_SCRYPT_MEMORY
+}
+
+/// Writes a file which is only accessible by its owner on Unix.
+fn write_private(path: &PathBuf, contents: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// An error accessing a [`Keystore`].
+#[derive(Debug)]
+pub enum KeystoreError {
+    /// An I/O error accessing the keystore directory.
+    Io(io::Error),
+    /// The keypair name is not a non-empty sequence of at most 64 ASCII
+    /// alphanumeric characters, `-` and `_`.
+    InvalidName(String),
+    /// A key file is malformed.
+    InvalidFormat(&'static str),
+    /// A key file could not be decrypted, i.e. the passphrase is wrong or
+    /// the file has been tampered with.
+    Decryption,
+    /// A keypair could not be encrypted.
+    Encryption,
+    /// The system random number generator failed.
+    Random,
+    /// A keypair could not be encoded, e.g. because it is an RSA keypair.
+    Encoding(EncodingError),
+    /// A decrypted keypair could not be decoded.
+    Decoding(DecodingError),
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeystoreError::Io(e) => write!(f, "I/O error: {}", e),
+            KeystoreError::InvalidName(name) => write!(f, "Invalid keypair name: {:?}", name),
+            KeystoreError::InvalidFormat(msg) => write!(f, "Invalid key file: {}", msg),
+            KeystoreError::Decryption => f.write_str("Failed to decrypt key file, wrong passphrase?"),
+            KeystoreError::Encryption => f.write_str("Failed to encrypt keypair"),
+            KeystoreError::Random => f.write_str("Failed to generate random bytes"),
+            KeystoreError::Encoding(e) => write!(f, "{}", e),
+            KeystoreError::Decoding(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for KeystoreError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            KeystoreError::Io(e) => Some(e),
+            KeystoreError::Encoding(e) => Some(e),
+            KeystoreError::Decoding(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for KeystoreError {
+    fn from(e: io::Error) -> Self {
+        KeystoreError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keystore(dir: &tempfile::TempDir, passphrase: &str) -> Keystore {
+        Keystore::new(dir.path().join("keys"), passphrase).with_scrypt_params(4, 1, 1)
+    }
+
+    #[test]
+    fn identity_is_generated_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let id1 = keystore(&dir, "secret").identity().unwrap();
+        let id2 = keystore(&dir, "secret").identity().unwrap();
+        assert_eq!(id1.public(), id2.public());
+        assert_eq!(keystore(&dir, "secret").names().unwrap(), vec![IDENTITY.to_owned()]);
+    }
+
+    #[test]
+    fn named_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = keystore(&dir, "secret");
+        assert!(keystore.names().unwrap().is_empty());
+        assert!(keystore.load("a").unwrap().is_none());
+
+        let a = Keypair::generate_ed25519();
+        let b = Keypair::generate_ecdsa();
+        keystore.store("a", &a).unwrap();
+        keystore.store("b", &b).unwrap();
+        assert_eq!(keystore.names().unwrap(), vec!["a".to_owned(), "b".to_owned()]);
+        assert_eq!(keystore.load("a").unwrap().unwrap().public(), a.public());
+        assert_eq!(keystore.load("b").unwrap().unwrap().public(), b.public());
+
+        assert!(keystore.remove("a").unwrap());
+        assert!(!keystore.remove("a").unwrap());
+        assert_eq!(keystore.names().unwrap(), vec!["b".to_owned()]);
+    }
+
+    #[test]
+    fn wrong_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        keystore(&dir, "secret").identity().unwrap();
+        match keystore(&dir, "guess").identity() {
+            Err(KeystoreError::Decryption) => {}
+            other => panic!("unexpected result: {:?}", other.map(|k| k.public()))
+        }
+    }
+
+    #[test]
+    fn tampered_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = keystore(&dir, "secret");
+        keystore.identity().unwrap();
+        let path = keystore.path(IDENTITY).unwrap();
+
+        let file = fs::read(&path).unwrap();
+        for i in MAGIC.len() .. file.len() {
+            let mut tampered = file.clone();
+            tampered[i] ^= 1;
+            fs::write(&path, &tampered).unwrap();
+            assert!(keystore.load(IDENTITY).is_err(), "flipped bit in byte {} not detected", i);
+        }
+    }
+
+    #[test]
+    fn invalid_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = keystore(&dir, "secret");
+        for name in &["", "..", "../identity", "a/b", "a.key"] {
+            match keystore.load(name) {
+                Err(KeystoreError::InvalidName(_)) => {}
+                other => panic!("name {:?} not rejected: {:?}", name, other.map(|_| ()))
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn file_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let keystore = keystore(&dir, "secret");
+        keystore.identity().unwrap();
+        let mode = fs::metadata(keystore.path(IDENTITY).unwrap()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn scrypt_params() {
+        assert!(scrypt_params_valid(15, 8, 1));
+        assert!(!scrypt_params_valid(0, 8, 1));
+        assert!(!scrypt_params_valid(15, 0, 1));
+        assert!(!scrypt_params_valid(15, 8, 0));
+        assert!(!scrypt_params_valid(24, 8, 1));
+        assert!(!scrypt_params_valid(16, 1, 1));
+        assert!(!scrypt_params_valid(31, u32::MAX, u32::MAX));
+        assert!(scrypt_params_valid(8, 8, 16));
+        assert!(!scrypt_params_valid(4, 8, 17));
+        assert!(!scrypt_params_valid(9, 8, 16));
+    }
+
+    // Test vectors from RFC7914, section 12.
+    #[test]
+    fn rfc7914_test_vectors() {
+        fn hex(bytes: &[u8]) -> String {
+            bytes.iter().map(|b| format!("{:02x}", b)).collect()
+        }
+
+        let mut out = [0; 64];
+
+        scrypt::scrypt(b"", b"", &ScryptParams::new(4, 1, 1).unwrap(), &mut out).unwrap();
+        assert_eq!(hex(&out),
+            "77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442\
+             fcd0069ded0948f8326a753a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906");
+
+        scrypt::scrypt(b"password", b"NaCl", &ScryptParams::new(10, 8, 16).unwrap(), &mut out).unwrap();
+        assert_eq!(hex(&out),
+            "fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b373162\
+             2eaf30d92e22a3886ff109279d9830dac727afb94a83ee6d8360cbdfa2cc0640");
+    }
+}
@@ -0,0 +1,76 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use crate::{Metrics, endpoint_transport, role};
+use libp2p_core::{
+    ConnectedPoint,
+    HandshakeObserver,
+    HandshakeStep,
+    upgrade::{NegotiationError, ProtocolError},
+};
+use std::time::Duration;
+
+impl HandshakeObserver for Metrics {
+    fn on_step(&self, step: HandshakeStep, endpoint: &ConnectedPoint, duration: Duration, success: bool) {
+        let transport = endpoint_transport(endpoint);
+        let outcome = if success { "success" } else { "failure" };
+        self.families.handshake_step_duration
+            .get(&[("step", step_label(step)), ("role", role(endpoint)), ("transport", &transport), ("outcome", outcome)])
+            .observe(duration.as_secs_f64());
+    }
+
+    fn on_negotiation_failure(
+        &self,
+        step: HandshakeStep,
+        endpoint: &ConnectedPoint,
+        protocols: &[String],
+        error: &NegotiationError
+    ) {
+        self.families.negotiation_failures
+            .get(&[
+                ("step", step_label(step)),
+                ("role", role(endpoint)),
+                ("reason", reason(error)),
+                ("protocols", &protocols.join(",")),
+            ])
+            .inc();
+    }
+}
+
+/// Returns the label of a step of establishing a connection.
+fn step_label(step: HandshakeStep) -> &'static str {
+    match step {
+        HandshakeStep::Connect => "connect",
+        HandshakeStep::Security => "security",
+        HandshakeStep::Multiplexing => "multiplexing",
+    }
+}
+
+/// Returns the label of the reason of a failed protocol negotiation.
+fn reason(error: &NegotiationError) -> &'static str {
+    match error {
+        NegotiationError::Failed => "unsupported",
+        NegotiationError::ProtocolError(ProtocolError::IoError(_)) => "io",
+        NegotiationError::ProtocolError(ProtocolError::InvalidMessage) => "invalid_message",
+        NegotiationError::ProtocolError(ProtocolError::InvalidProtocol) => "invalid_protocol",
+        NegotiationError::ProtocolError(ProtocolError::TooManyProtocols) => "too_many_protocols",
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Metrics of a libp2p node in the Prometheus text format.
+//!
+//! [`Metrics`] collects counters and histograms about the connections of
+//! a node from three sources:
+//!
+//!   * The events of a `Swarm`, passed to [`Recorder::record`]: established
+//!     and closed connections by role and transport, and failed dialing
+//!     attempts and incoming connections by cause.
+//!   * A [`MetricsTransport`] wrapping the transport of the `Swarm`, obtained
+//!     from [`Metrics::transport`]: the durations of the connection
+//!     handshakes, the number of open substreams and the bytes sent and
+//!     received on substreams.
+//!   * The [`HandshakeObserver`] of a transport upgraded with
+//!     [`Builder::observe`](libp2p_core::transport::upgrade::Builder::observe),
+//!     obtained from [`Metrics::handshake_observer`]: the durations of
+//!     connecting, the security handshake and the negotiation of the
+//!     multiplexer, and the failed negotiations of their protocols by reason
+//!     and proposed protocols.
+//!   * With the `relay` feature, the events of a `Relay` of `libp2p-relay`,
+//!     passed to [`Recorder::record`]: the reservation and circuit requests
+//!     by outcome and the number of relayed circuits.
+//!
+//! [`Metrics::encode`] renders all metrics in the
+//! [Prometheus text format](https://prometheus.io/docs/instrumenting/exposition_formats/),
+//! e.g. for serving them to a Prometheus server over HTTP.
+//!
+//! The transport of a connection is identified by the protocols of its
+//! remote address without the IP addresses, DNS names and peer IDs, e.g.
+//! `tcp` or `tcp/ws`.
+
+mod handshake;
+mod metric;
+#[cfg(feature = "relay")]
+mod relay;
+mod swarm;
+mod transport;
+
+pub use transport::{MetricsFuture, MetricsListener, MetricsMuxer, MetricsSubstream, MetricsTransport};
+
+use libp2p_core::{ConnectedPoint, HandshakeObserver, Multiaddr, multiaddr::Protocol};
+use metric::{Counter, Family, Gauge, Histogram};
+use std::sync::Arc;
+
+/// Records the metrics of an event.
+pub trait Recorder<TEvent> {
+    /// Records the metrics of the given event.
+    fn record(&self, event: &TEvent);
+}
+
+/// The metrics of a libp2p node.
+///
+/// Cloning `Metrics` is cheap and yields a handle to the same metrics.
+#[derive(Clone)]
+pub struct Metrics {
+    families: Arc<Families>,
+}
+
+struct Families {
+    connections_established: Family<Counter>,
+    connections_closed: Family<Counter>,
+    connections_incoming: Family<Counter>,
+    connections_incoming_error: Family<Counter>,
+    dial_failures: Family<Counter>,
+    handshake_duration: Family<Histogram>,
+    handshake_step_duration: Family<Histogram>,
+    negotiation_failures: Family<Counter>,
+    substreams_open: Family<Gauge>,
+    substream_bytes: Family<Counter>,
+    #[cfg(feature = "relay")]
+    relay_events: Family<Counter>,
+    #[cfg(feature = "relay")]
+    relay_circuits: Family<Gauge>,
+}
+
+impl Metrics {
+    /// Creates a new set of metrics, all of them initially empty.
+    pub fn new() -> Self {
+        let families = Families {
+            connections_established: Family::new(
+                "libp2p_swarm_connections_established_total",
+                "Number of connections established, by role and transport."),
+            connections_closed: Family::new(
+                "libp2p_swarm_connections_closed_total",
+                "Number of connections closed, by role and transport."),
+            connections_incoming: Family::new(
+                "libp2p_swarm_connections_incoming_total",
+                "Number of incoming connections, by transport."),
+            connections_incoming_error: Family::new(
+                "libp2p_swarm_connections_incoming_error_total",
+                "Number of incoming connections that failed before being established, by cause."),
+            dial_failures: Family::new(
+                "libp2p_swarm_dial_failures_total",
+                "Number of addresses that failed to be dialed, by cause."),
+            handshake_duration: Family::new(
+                "libp2p_transport_handshake_duration_seconds",
+                "Duration of establishing a connection including its upgrades, by role and transport."),
+            handshake_step_duration: Family::new(
+                "libp2p_transport_handshake_step_duration_seconds",
+                "Duration of the steps of establishing a connection, by step, role, transport and outcome."),
+            negotiation_failures: Family::new(
+                "libp2p_transport_negotiation_failures_total",
+                "Number of failed negotiations of the security or multiplexing protocol, by step, role, reason and proposed protocols."),
+            substreams_open: Family::new(
+                "libp2p_transport_substreams_open",
+                "Number of open substreams, by direction."),
+            substream_bytes: Family::new(
+                "libp2p_transport_substream_bytes_total",
+                "Number of bytes sent and received on substreams, by direction and transport."),
+            #[cfg(feature = "relay")]
+            relay_events: Family::new(
+                "libp2p_relay_events_total",
+                "Number of reservation and circuit requests handled by the relay, by outcome."),
+            #[cfg(feature = "relay")]
+            relay_circuits: Family::new(
+                "libp2p_relay_circuits",
+                "Number of circuits currently relayed."),
+        };
+        Metrics { families: Arc::new(families) }
+    }
+
+    /// Wraps a transport to measure its connections.
+    ///
+    /// The transport is usually the final transport passed to the `Swarm`,
+    /// i.e. one that is authenticated and multiplexed.
+    pub fn transport<T>(&self, transport: T) -> MetricsTransport<T> {
+        MetricsTransport::new(transport, self.clone())
+    }
+
+    /// Returns a [`HandshakeObserver`] measuring the steps of establishing
+    /// connections, to be passed to
+    /// [`Builder::observe`](libp2p_core::transport::upgrade::Builder::observe).
+    pub fn handshake_observer(&self) -> Arc<dyn HandshakeObserver> {
+        Arc::new(self.clone())
+    }
+
+    /// Encodes all metrics in the Prometheus text format.
+    pub fn encode(&self) -> String {
+        let f = &self.families;
+        let mut out = String::new();
+        f.connections_established.encode(&mut out);
+        f.connections_closed.encode(&mut out);
+        f.connections_incoming.encode(&mut out);
+        f.connections_incoming_error.encode(&mut out);
+        f.dial_failures.encode(&mut out);
+        f.handshake_duration.encode(&mut out);
+        f.handshake_step_duration.encode(&mut out);
+        f.negotiation_failures.encode(&mut out);
+        f.substreams_open.encode(&mut out);
+        f.substream_bytes.encode(&mut out);
+        #[cfg(feature = "relay")]
+        {
+            f.relay_events.encode(&mut out);
+            f.relay_circuits.encode(&mut out);
+        }
+        out
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+/// Returns the label of the role of the local node for a connection.
+fn role(endpoint: &ConnectedPoint) -> &'static str {
+    match endpoint {
+        ConnectedPoint::Dialer { .. } => "dialer",
+        ConnectedPoint::Listener { .. } => "listener",
+    }
+}
+
+/// Returns the label of the transport of a connection with the given endpoint.
+fn endpoint_transport(endpoint: &ConnectedPoint) -> String {
+    match endpoint {
+        ConnectedPoint::Dialer { address } => transport(address),
+        ConnectedPoint::Listener { send_back_addr, .. } => transport(send_back_addr),
+    }
+}
+
+/// Returns the label of the transport of a connection with the given address.
+fn transport(addr: &Multiaddr) -> String {
+    let protocols = addr.iter()
+        .filter(|p| !matches!(p,
+            Protocol::Ip4(_) | Protocol::Ip6(_) | Protocol::Dns(_) |
+            Protocol::Dns4(_) | Protocol::Dns6(_) | Protocol::Dnsaddr(_) | Protocol::P2p(_)))
+        .map(|p| p.to_string().split('/').nth(1).unwrap_or_default().to_string())
+        .collect::<Vec<_>>();
+    if protocols.is_empty() {
+        "unknown".to_string()
+    } else {
+        protocols.join("/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transport_label() {
+        let label = |a: &str| transport(&a.parse().unwrap());
+        assert_eq!(label("/ip4/127.0.0.1/tcp/4001"), "tcp");
+        assert_eq!(label("/dns4/example.com/tcp/443/wss/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC"), "tcp/wss");
+        assert_eq!(label("/memory/1234"), "memory");
+        assert_eq!(label("/ip6/::1"), "unknown");
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Metric types and their encoding in the Prometheus text format.
+
+use parking_lot::Mutex;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    sync::{Arc, atomic::{AtomicI64, AtomicU64, Ordering}},
+};
+
+/// The label names and values identifying a metric within a [`Family`].
+type Labels = Vec<(&'static str, String)>;
+
+/// A metric that can be encoded in the Prometheus text format.
+pub(crate) trait Metric: Clone + Default {
+    /// The Prometheus type of the metric.
+    const TYPE: &'static str;
+
+    /// Appends the samples of the metric to `out`.
+    fn encode(&self, name: &str, labels: &Labels, out: &mut String);
+}
+
+/// A monotonically increasing counter.
+#[derive(Clone, Default)]
+pub(crate) struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    pub(crate) fn inc(&self) {
+        self.inc_by(1)
+    }
+
+    pub(crate) fn inc_by(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+}
+
+impl Metric for Counter {
+    const TYPE: &'static str = "counter";
+
+    fn encode(&self, name: &str, labels: &Labels, out: &mut String) {
+        sample(out, name, "", labels, None, self.0.load(Ordering::Relaxed));
+    }
+}
+
+/// A value that can go up and down.
+#[derive(Clone, Default)]
+pub(crate) struct Gauge(Arc<AtomicI64>);
+
+impl Gauge {
+    pub(crate) fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metric for Gauge {
+    const TYPE: &'static str = "gauge";
+
+    fn encode(&self, name: &str, labels: &Labels, out: &mut String) {
+        sample(out, name, "", labels, None, self.0.load(Ordering::Relaxed));
+    }
+}
+
+/// The upper bounds of the buckets of a [`Histogram`], in seconds.
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Counts observed durations in buckets.
+#[derive(Clone, Default)]
+pub(crate) struct Histogram(Arc<Mutex<HistogramState>>);
+
+#[derive(Default)]
+struct HistogramState {
+    /// The number of observations per bucket, not cumulative.
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub(crate) fn observe(&self, value: f64) {
+        let mut state = self.0.lock();
+        if let Some(i) = BUCKETS.iter().position(|b| value <= *b) {
+            state.buckets[i] += 1;
+        }
+        state.sum += value;
+        state.count += 1;
+    }
+}
+
+impl Metric for Histogram {
+    const TYPE: &'static str = "histogram";
+
+    fn encode(&self, name: &str, labels: &Labels, out: &mut String) {
+        let state = self.0.lock();
+        let mut cumulative = 0;
+        for (bound, n) in BUCKETS.iter().zip(state.buckets.iter()) {
+            cumulative += n;
+            sample(out, name, "_bucket", labels, Some(&bound.to_string()), cumulative);
+        }
+        sample(out, name, "_bucket", labels, Some("+Inf"), state.count);
+        sample(out, name, "_sum", labels, None, state.sum);
+        sample(out, name, "_count", labels, None, state.count);
+    }
+}
+
+/// A set of metrics of the same name, distinguished by their labels.
+pub(crate) struct Family<M> {
+    name: &'static str,
+    help: &'static str,
+    metrics: Mutex<BTreeMap<Labels, M>>,
+}
+
+impl<M: Metric> Family<M> {
+    pub(crate) fn new(name: &'static str, help: &'static str) -> Self {
+        Family { name, help, metrics: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// Returns the metric with the given labels, creating it if necessary.
+    pub(crate) fn get(&self, labels: &[(&'static str, &str)]) -> M {
+        let labels = labels.iter().map(|(k, v)| (*k, v.to_string())).collect();
+        self.metrics.lock().entry(labels).or_default().clone()
+    }
+
+    /// Appends the help text, the type and the samples of all metrics to `out`.
+    pub(crate) fn encode(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} {}", self.name, M::TYPE);
+        for (labels, metric) in self.metrics.lock().iter() {
+            metric.encode(self.name, labels, out);
+        }
+    }
+}
+
+/// Appends a single sample line to `out`.
+fn sample(
+    out: &mut String,
+    name: &str,
+    suffix: &str,
+    labels: &Labels,
+    le: Option<&str>,
+    value: impl std::fmt::Display
+) {
+    let _ = write!(out, "{}{}", name, suffix);
+    let le = le.map(|le| ("le", le));
+    let mut labels = labels.iter().map(|(k, v)| (*k, v.as_str())).chain(le).peekable();
+    if labels.peek().is_some() {
+        out.push('{');
+        for (i, (k, v)) in labels.enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{}=\"", k);
+            for c in v.chars() {
+                match c {
+                    '\\' => out.push_str("\\\\"),
+                    '"' => out.push_str("\\\""),
+                    '\n' => out.push_str("\\n"),
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+        }
+        out.push('}');
+    }
+    let _ = writeln!(out, " {}", value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_counter() {
+        let family = Family::<Counter>::new("requests_total", "Number of requests.");
+        family.get(&[("method", "get")]).inc();
+        family.get(&[("method", "get")]).inc_by(2);
+        family.get(&[("method", "a\"b")]).inc();
+        let mut out = String::new();
+        family.encode(&mut out);
+        assert_eq!(out, "\
+            # HELP requests_total Number of requests.\n\
+            # TYPE requests_total counter\n\
+            requests_total{method=\"a\\\"b\"} 1\n\
+            requests_total{method=\"get\"} 3\n");
+    }
+
+    #[test]
+    fn encode_histogram() {
+        let family = Family::<Histogram>::new("duration_seconds", "Durations.");
+        let histogram = family.get(&[]);
+        histogram.observe(0.25);
+        histogram.observe(0.5);
+        histogram.observe(20.0);
+        let mut out = String::new();
+        family.encode(&mut out);
+        assert!(out.contains("duration_seconds_bucket{le=\"0.1\"} 0\n"));
+        assert!(out.contains("duration_seconds_bucket{le=\"0.25\"} 1\n"));
+        assert!(out.contains("duration_seconds_bucket{le=\"0.5\"} 2\n"));
+        assert!(out.contains("duration_seconds_bucket{le=\"10\"} 2\n"));
+        assert!(out.contains("duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(out.contains("duration_seconds_sum 20.75\n"));
+        assert!(out.contains("duration_seconds_count 3\n"));
+    }
+}
@@ -0,0 +1,73 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::{Metrics, Recorder};
+use libp2p_relay::RelayEvent;
+
+impl Recorder<RelayEvent> for Metrics {
+    fn record(&self, event: &RelayEvent) {
+        let f = &self.families;
+        let label = match event {
+            RelayEvent::ReservationReqAccepted { renewed: false, .. } => "reservation_accepted",
+            RelayEvent::ReservationReqAccepted { renewed: true, .. } => "reservation_renewed",
+            RelayEvent::ReservationReqAcceptFailed { .. } => "reservation_accept_failed",
+            RelayEvent::ReservationReqDenied { .. } => "reservation_denied",
+            RelayEvent::ReservationReqDenyFailed { .. } => "reservation_deny_failed",
+            RelayEvent::ReservationTimedOut { .. } => "reservation_timed_out",
+            RelayEvent::CircuitReqDenied { .. } => "circuit_denied",
+            RelayEvent::CircuitReqOutboundConnectFailed { .. } => "circuit_connect_failed",
+            RelayEvent::CircuitReqAccepted { .. } => {
+                f.relay_circuits.get(&[]).inc();
+                "circuit_accepted"
+            }
+            RelayEvent::CircuitClosed { .. } => {
+                f.relay_circuits.get(&[]).dec();
+                "circuit_closed"
+            }
+        };
+        f.relay_events.get(&[("event", label)]).inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_core::PeerId;
+
+    #[test]
+    fn relay_metrics() {
+        let metrics = Metrics::new();
+        let (src_peer_id, dst_peer_id) = (PeerId::random(), PeerId::random());
+        metrics.record(&RelayEvent::ReservationReqAccepted { src_peer_id: dst_peer_id.clone(), renewed: false });
+        metrics.record(&RelayEvent::CircuitReqAccepted { src_peer_id: src_peer_id.clone(), dst_peer_id: dst_peer_id.clone() });
+        metrics.record(&RelayEvent::CircuitReqAccepted { src_peer_id: src_peer_id.clone(), dst_peer_id: dst_peer_id.clone() });
+        metrics.record(&RelayEvent::CircuitClosed { src_peer_id, dst_peer_id, error: None });
+
+        let encoded = metrics.encode();
+        for line in &[
+            "libp2p_relay_events_total{event=\"reservation_accepted\"} 1",
+            "libp2p_relay_events_total{event=\"circuit_accepted\"} 2",
+            "libp2p_relay_events_total{event=\"circuit_closed\"} 1",
+            "libp2p_relay_circuits 1",
+        ] {
+            assert!(encoded.lines().any(|l| &l == line), "{} not in {}", line, encoded);
+        }
+    }
+}
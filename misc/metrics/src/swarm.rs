@@ -0,0 +1,93 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::{Metrics, Recorder, endpoint_transport, role, transport};
+use libp2p_core::{connection::PendingConnectionError, transport::TransportError};
+use libp2p_swarm::SwarmEvent;
+
+impl<TBvEv, THandleErr> Recorder<SwarmEvent<TBvEv, THandleErr>> for Metrics {
+    fn record(&self, event: &SwarmEvent<TBvEv, THandleErr>) {
+        let f = &self.families;
+        match event {
+            SwarmEvent::ConnectionEstablished { endpoint, .. } => {
+                let transport = endpoint_transport(endpoint);
+                f.connections_established
+                    .get(&[("role", role(endpoint)), ("transport", &transport)])
+                    .inc();
+            }
+            SwarmEvent::ConnectionClosed { endpoint, .. } => {
+                let transport = endpoint_transport(endpoint);
+                f.connections_closed
+                    .get(&[("role", role(endpoint)), ("transport", &transport)])
+                    .inc();
+            }
+            SwarmEvent::IncomingConnection { send_back_addr, .. } => {
+                f.connections_incoming
+                    .get(&[("transport", &transport(send_back_addr))])
+                    .inc();
+            }
+            SwarmEvent::IncomingConnectionError { error, .. } => {
+                f.connections_incoming_error
+                    .get(&[("cause", cause(error))])
+                    .inc();
+            }
+            SwarmEvent::BlockedIncomingConnection { .. } => {
+                f.connections_incoming_error
+                    .get(&[("cause", "blocked")])
+                    .inc();
+            }
+            SwarmEvent::UnreachableAddr { error, .. } |
+            SwarmEvent::UnknownPeerUnreachableAddr { error, .. } => {
+                if let PendingConnectionError::ConcurrentDial(errors) = error {
+                    for (_, error) in errors {
+                        f.dial_failures
+                            .get(&[("cause", transport_cause(error))])
+                            .inc();
+                    }
+                } else {
+                    f.dial_failures
+                        .get(&[("cause", cause(error))])
+                        .inc();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns the label of the cause of a failed pending connection.
+fn cause<TTransErr>(error: &PendingConnectionError<TTransErr>) -> &'static str {
+    match error {
+        PendingConnectionError::Transport(error) => transport_cause(error),
+        PendingConnectionError::ConcurrentDial(_) => "transport",
+        PendingConnectionError::InvalidPeerId => "invalid_peer_id",
+        PendingConnectionError::ConnectionLimit(_) => "connection_limit",
+        PendingConnectionError::IO(_) => "io",
+        PendingConnectionError::Timeout => "timeout",
+    }
+}
+
+/// Returns the label of the cause of a transport error.
+fn transport_cause<TErr>(error: &TransportError<TErr>) -> &'static str {
+    match error {
+        TransportError::MultiaddrNotSupported(_) => "multiaddr_not_supported",
+        TransportError::Other(_) => "transport",
+    }
+}
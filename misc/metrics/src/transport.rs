@@ -0,0 +1,259 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::{Metrics, metric::{Counter, Gauge}, transport};
+use futures::{prelude::*, ready};
+use libp2p_core::{
+    Multiaddr,
+    Transport,
+    muxing::{StreamMuxer, StreamMuxerEvent},
+    transport::{ListenerEvent, TransportError},
+};
+use std::{convert::TryFrom as _, pin::Pin, task::{Context, Poll}};
+use wasm_timer::Instant;
+
+/// Wraps around a `Transport` producing multiplexed connections and measures
+/// the handshake duration, the open substreams and the bytes transferred on
+/// substreams of all connections.
+///
+/// Created with [`Metrics::transport`].
+#[derive(Clone)]
+pub struct MetricsTransport<TInner> {
+    inner: TInner,
+    metrics: Metrics,
+}
+
+impl<TInner> MetricsTransport<TInner> {
+    pub(crate) fn new(inner: TInner, metrics: Metrics) -> Self {
+        MetricsTransport { inner, metrics }
+    }
+}
+
+impl<TInner, TInfo, TMuxer> Transport for MetricsTransport<TInner>
+where
+    TInner: Transport<Output = (TInfo, TMuxer)>,
+    TMuxer: StreamMuxer,
+{
+    type Output = (TInfo, MetricsMuxer<TMuxer>);
+    type Error = TInner::Error;
+    type Listener = MetricsListener<TInner::Listener>;
+    type ListenerUpgrade = MetricsFuture<TInner::ListenerUpgrade>;
+    type Dial = MetricsFuture<TInner::Dial>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let metrics = self.metrics;
+        self.inner
+            .listen_on(addr)
+            .map(move |inner| MetricsListener { inner, metrics })
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let metrics = self.metrics;
+        let transport = transport(&addr);
+        self.inner
+            .dial(addr)
+            .map(move |inner| MetricsFuture::new(inner, metrics, "dialer", transport))
+    }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.inner.address_translation(listen, observed)
+    }
+}
+
+/// Wraps around a `Stream` that produces connections. Wraps each connection
+/// upgrade into a [`MetricsFuture`].
+#[pin_project::pin_project]
+pub struct MetricsListener<TInner> {
+    #[pin]
+    inner: TInner,
+    metrics: Metrics,
+}
+
+impl<TInner, TUpgrade, TErr> Stream for MetricsListener<TInner>
+where
+    TInner: TryStream<Ok = ListenerEvent<TUpgrade, TErr>, Error = TErr>
+{
+    type Item = Result<ListenerEvent<MetricsFuture<TUpgrade>, TErr>, TErr>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let event =
+            if let Some(event) = ready!(this.inner.try_poll_next(cx)?) {
+                event
+            } else {
+                return Poll::Ready(None)
+            };
+
+        let event = match event {
+            ListenerEvent::Upgrade { upgrade, local_addr, remote_addr } => {
+                let transport = transport(&remote_addr);
+                let upgrade = MetricsFuture::new(upgrade, this.metrics.clone(), "listener", transport);
+                ListenerEvent::Upgrade { upgrade, local_addr, remote_addr }
+            }
+            ListenerEvent::NewAddress(addr) => ListenerEvent::NewAddress(addr),
+            ListenerEvent::AddressExpired(addr) => ListenerEvent::AddressExpired(addr),
+            ListenerEvent::Error(error) => ListenerEvent::Error(error),
+        };
+
+        Poll::Ready(Some(Ok(event)))
+    }
+}
+
+/// Wraps around a `Future` that produces a multiplexed connection. Measures
+/// the time until the connection is established and wraps its multiplexer
+/// into a [`MetricsMuxer`].
+#[pin_project::pin_project]
+pub struct MetricsFuture<TInner> {
+    #[pin]
+    inner: TInner,
+    metrics: Metrics,
+    role: &'static str,
+    transport: String,
+    started: Instant,
+}
+
+impl<TInner> MetricsFuture<TInner> {
+    fn new(inner: TInner, metrics: Metrics, role: &'static str, transport: String) -> Self {
+        MetricsFuture { inner, metrics, role, transport, started: Instant::now() }
+    }
+}
+
+impl<TInner, TInfo, TMuxer> Future for MetricsFuture<TInner>
+where
+    TInner: TryFuture<Ok = (TInfo, TMuxer)>,
+{
+    type Output = Result<(TInfo, MetricsMuxer<TMuxer>), TInner::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let (info, inner) = ready!(this.inner.try_poll(cx)?);
+
+        let f = &this.metrics.families;
+        f.handshake_duration
+            .get(&[("role", this.role), ("transport", this.transport)])
+            .observe(this.started.elapsed().as_secs_f64());
+        let muxer = MetricsMuxer {
+            inner,
+            inbound: f.substreams_open.get(&[("direction", "inbound")]),
+            outbound: f.substreams_open.get(&[("direction", "outbound")]),
+            received: f.substream_bytes.get(&[("direction", "inbound"), ("transport", this.transport)]),
+            sent: f.substream_bytes.get(&[("direction", "outbound"), ("transport", this.transport)]),
+        };
+
+        Poll::Ready(Ok((info, muxer)))
+    }
+}
+
+/// Wraps around a `StreamMuxer` and counts its open substreams and the bytes
+/// read from and written to them.
+pub struct MetricsMuxer<TInner> {
+    inner: TInner,
+    inbound: Gauge,
+    outbound: Gauge,
+    received: Counter,
+    sent: Counter,
+}
+
+/// A substream of a [`MetricsMuxer`].
+pub struct MetricsSubstream<TInner> {
+    inner: TInner,
+    open: Gauge,
+}
+
+impl<TInner> StreamMuxer for MetricsMuxer<TInner>
+where
+    TInner: StreamMuxer,
+{
+    type Substream = MetricsSubstream<TInner::Substream>;
+    type OutboundSubstream = TInner::OutboundSubstream;
+    type Error = TInner::Error;
+
+    fn poll_event(&self, cx: &mut Context<'_>) -> Poll<Result<StreamMuxerEvent<Self::Substream>, Self::Error>> {
+        let event = match ready!(self.inner.poll_event(cx)) {
+            Ok(StreamMuxerEvent::InboundSubstream(inner)) => {
+                self.inbound.inc();
+                let open = self.inbound.clone();
+                StreamMuxerEvent::InboundSubstream(MetricsSubstream { inner, open })
+            }
+            Ok(StreamMuxerEvent::AddressChange(addr)) => StreamMuxerEvent::AddressChange(addr),
+            Err(err) => return Poll::Ready(Err(err)),
+        };
+        Poll::Ready(Ok(event))
+    }
+
+    fn open_outbound(&self) -> Self::OutboundSubstream {
+        self.inner.open_outbound()
+    }
+
+    fn poll_outbound(&self, cx: &mut Context<'_>, s: &mut Self::OutboundSubstream)
+        -> Poll<Result<Self::Substream, Self::Error>>
+    {
+        let inner = ready!(self.inner.poll_outbound(cx, s))?;
+        self.outbound.inc();
+        let open = self.outbound.clone();
+        Poll::Ready(Ok(MetricsSubstream { inner, open }))
+    }
+
+    fn destroy_outbound(&self, s: Self::OutboundSubstream) {
+        self.inner.destroy_outbound(s)
+    }
+
+    fn read_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream, buf: &mut [u8])
+        -> Poll<Result<usize, Self::Error>>
+    {
+        let num_bytes = ready!(self.inner.read_substream(cx, &mut s.inner, buf))?;
+        self.received.inc_by(u64::try_from(num_bytes).unwrap_or(u64::MAX));
+        Poll::Ready(Ok(num_bytes))
+    }
+
+    fn write_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream, buf: &[u8])
+        -> Poll<Result<usize, Self::Error>>
+    {
+        let num_bytes = ready!(self.inner.write_substream(cx, &mut s.inner, buf))?;
+        self.sent.inc_by(u64::try_from(num_bytes).unwrap_or(u64::MAX));
+        Poll::Ready(Ok(num_bytes))
+    }
+
+    fn flush_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream)
+        -> Poll<Result<(), Self::Error>>
+    {
+        self.inner.flush_substream(cx, &mut s.inner)
+    }
+
+    fn shutdown_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream)
+        -> Poll<Result<(), Self::Error>>
+    {
+        self.inner.shutdown_substream(cx, &mut s.inner)
+    }
+
+    fn destroy_substream(&self, s: Self::Substream) {
+        s.open.dec();
+        self.inner.destroy_substream(s.inner)
+    }
+
+    fn close(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.close(cx)
+    }
+
+    fn flush_all(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.flush_all(cx)
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::prelude::*;
+use libp2p_core::{
+    Multiaddr,
+    Transport,
+    identity,
+    multiaddr::Protocol,
+    upgrade,
+    transport::MemoryTransport,
+};
+use libp2p_metrics::{Metrics, Recorder};
+use libp2p_mplex::MplexConfig;
+use libp2p_ping::{Ping, PingConfig, PingEvent, PingSuccess};
+use libp2p_plaintext::PlainText2Config;
+use libp2p_swarm::{Swarm, SwarmEvent};
+
+fn new_swarm(metrics: &Metrics) -> Swarm<Ping> {
+    let local_public_key = identity::Keypair::generate_ed25519().public();
+    let local_peer_id = local_public_key.clone().into_peer_id();
+    let transport = MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .observe(metrics.handshake_observer())
+        .authenticate(PlainText2Config { local_public_key })
+        .multiplex(MplexConfig::new());
+    let ping = Ping::new(PingConfig::new().with_keep_alive(true));
+    Swarm::new(metrics.transport(transport), ping, local_peer_id)
+}
+
+/// Returns the value of the sample with the given name and labels.
+fn sample(metrics: &Metrics, sample: &str) -> Option<f64> {
+    metrics.encode()
+        .lines()
+        .find_map(|line| line.strip_prefix(sample)?.strip_prefix(' ')?.parse().ok())
+}
+
+#[test]
+fn connection_metrics() {
+    let metrics1 = Metrics::new();
+    let metrics2 = Metrics::new();
+    let mut swarm1 = new_swarm(&metrics1);
+    let mut swarm2 = new_swarm(&metrics2);
+
+    let addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    Swarm::listen_on(&mut swarm1, addr.clone()).unwrap();
+    Swarm::dial_addr(&mut swarm2, addr).unwrap();
+
+    fn pinged(event: &SwarmEvent<PingEvent, impl std::fmt::Debug>) -> bool {
+        matches!(event, SwarmEvent::Behaviour(PingEvent { result: Ok(PingSuccess::Ping { .. }), .. }))
+    }
+
+    let peer1 = {
+        let metrics1 = metrics1.clone();
+        async move {
+            loop {
+                let event = swarm1.next_event().await;
+                metrics1.record(&event);
+            }
+        }
+    };
+    let peer2 = async move {
+        loop {
+            let event = swarm2.next_event().await;
+            metrics2.record(&event);
+            if pinged(&event) {
+                return metrics2
+            }
+        }
+    };
+
+    let (metrics2, _) = async_std::task::block_on(
+        future::select(Box::pin(peer1), Box::pin(peer2))
+    ).factor_first();
+
+    assert_eq!(sample(&metrics2,
+        "libp2p_swarm_connections_established_total{role=\"dialer\",transport=\"memory\"}"), Some(1.0));
+    assert_eq!(sample(&metrics2,
+        "libp2p_transport_handshake_duration_seconds_count{role=\"dialer\",transport=\"memory\"}"), Some(1.0));
+    for step in &["connect", "security", "multiplexing"] {
+        assert_eq!(sample(&metrics2, &format!(
+            "libp2p_transport_handshake_step_duration_seconds_count{{step=\"{}\",role=\"dialer\",\
+            transport=\"memory\",outcome=\"success\"}}", step)), Some(1.0));
+    }
+    // The substream of a ping is closed once the ping succeeded.
+    assert_eq!(sample(&metrics2,
+        "libp2p_transport_substreams_open{direction=\"outbound\"}"), Some(0.0));
+    let sent = sample(&metrics2,
+        "libp2p_transport_substream_bytes_total{direction=\"outbound\",transport=\"memory\"}").unwrap();
+    assert!(sent >= 32.0, "{} bytes sent", sent);
+
+    assert_eq!(sample(&metrics1,
+        "libp2p_swarm_connections_incoming_total{transport=\"memory\"}"), Some(1.0));
+    assert_eq!(sample(&metrics1,
+        "libp2p_swarm_connections_established_total{role=\"listener\",transport=\"memory\"}"), Some(1.0));
+}
+
+#[test]
+fn dial_failure_metrics() {
+    let metrics = Metrics::new();
+    let mut swarm = new_swarm(&metrics);
+
+    let addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    Swarm::dial_addr(&mut swarm, addr).unwrap();
+    async_std::task::block_on(async {
+        loop {
+            let event = swarm.next_event().await;
+            metrics.record(&event);
+            if let SwarmEvent::UnknownPeerUnreachableAddr { .. } = event {
+                break
+            }
+        }
+    });
+
+    assert_eq!(sample(&metrics, "libp2p_swarm_dial_failures_total{cause=\"transport\"}"), Some(1.0));
+}
+
+#[test]
+fn negotiation_failure_metrics() {
+    let metrics = Metrics::new();
+    let local_public_key = identity::Keypair::generate_ed25519().public();
+    let transport = MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .observe(metrics.handshake_observer())
+        .authenticate(PlainText2Config { local_public_key })
+        .multiplex(MplexConfig::new());
+
+    // A listener that offers the security protocol as the only multiplexer.
+    let addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    let mut listener = MemoryTransport.listen_on(addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        while let Some(event) = listener.next().await {
+            if let Some((upgrade, _)) = event.unwrap().into_upgrade() {
+                let local_public_key = identity::Keypair::generate_ed25519().public();
+                let config = PlainText2Config { local_public_key };
+                let socket = upgrade.await.unwrap();
+                let (_, socket) = upgrade::apply_inbound(socket, config.clone()).await.unwrap();
+                let _ = upgrade::apply_inbound(socket, config).await;
+            }
+        }
+    });
+    assert!(async_std::task::block_on(transport.dial(addr).unwrap()).is_err());
+
+    assert_eq!(sample(&metrics,
+        "libp2p_transport_negotiation_failures_total{step=\"multiplexing\",role=\"dialer\",\
+        reason=\"unsupported\",protocols=\"/mplex/6.7.0\"}"), Some(1.0));
+    assert_eq!(sample(&metrics,
+        "libp2p_transport_handshake_step_duration_seconds_count{step=\"multiplexing\",role=\"dialer\",\
+        transport=\"memory\",outcome=\"failure\"}"), Some(1.0));
+}
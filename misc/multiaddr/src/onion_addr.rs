@@ -1,6 +1,6 @@
 use std::borrow::Cow;
 use std::fmt::Debug;
-use serde::export::Formatter;
+use std::fmt::Formatter;
 use std::fmt;
 
 /// Represents an Onion v3 address
@@ -198,7 +198,7 @@ where
                     if let Err(err) = Pin::new(&mut io).start_send(Message::Protocol(p.clone())) {
                         return Poll::Ready(Err(From::from(err)));
                     }
-                    log::debug!("Dialer: Proposed protocol: {}", p);
+                    tracing::debug!("Dialer: Proposed protocol: {}", p);
 
                     if this.protocols.peek().is_some() {
                         *this.state = SeqState::FlushProtocol { io, protocol }
@@ -206,7 +206,7 @@ where
                         match this.version {
                             Version::V1 => *this.state = SeqState::FlushProtocol { io, protocol },
                             Version::V1Lazy => {
-                                log::debug!("Dialer: Expecting proposed protocol: {}", p);
+                                tracing::debug!("Dialer: Expecting proposed protocol: {}", p);
                                 let io = Negotiated::expecting(io.into_reader(), p, *this.version);
                                 return Poll::Ready(Ok((protocol, io)))
                             }
@@ -241,13 +241,13 @@ where
                             *this.state = SeqState::AwaitProtocol { io, protocol };
                         }
                         Message::Protocol(ref p) if p.as_ref() == protocol.as_ref() => {
-                            log::debug!("Dialer: Received confirmation for protocol: {}", p);
+                            tracing::debug!("Dialer: Received confirmation for protocol: {}", p);
                             let (io, remaining) = io.into_inner();
                             let io = Negotiated::completed(io, remaining);
                             return Poll::Ready(Ok((protocol, io)));
                         }
                         Message::NotAvailable => {
-                            log::debug!("Dialer: Received rejection of protocol: {}",
+                            tracing::debug!("Dialer: Received rejection of protocol: {}",
                                 String::from_utf8_lossy(protocol.as_ref()));
                             let protocol = this.protocols.next().ok_or(NegotiationError::Failed)?;
                             *this.state = SeqState::SendProtocol { io, protocol }
@@ -334,7 +334,7 @@ where
                         return Poll::Ready(Err(From::from(err)));
                     }
 
-                    log::debug!("Dialer: Requested supported protocols.");
+                    tracing::debug!("Dialer: Requested supported protocols.");
                     *this.state = ParState::Flush { io }
                 }
 
@@ -369,7 +369,7 @@ where
                                 .find(|p| supported.iter().any(|s|
                                     s.as_ref() == p.as_ref()))
                                 .ok_or(NegotiationError::Failed)?;
-                            log::debug!("Dialer: Found supported protocol: {}",
+                            tracing::debug!("Dialer: Found supported protocol: {}",
                                 String::from_utf8_lossy(protocol.as_ref()));
                             *this.state = ParState::SendProtocol { io, protocol };
                         }
@@ -390,7 +390,7 @@ where
                     if let Err(err) = Pin::new(&mut io).start_send(Message::Protocol(p.clone())) {
                         return Poll::Ready(Err(From::from(err)));
                     }
-                    log::debug!("Dialer: Expecting proposed protocol: {}", p);
+                    tracing::debug!("Dialer: Expecting proposed protocol: {}", p);
 
                     let io = Negotiated::expecting(io.into_reader(), p, *this.version);
                     return Poll::Ready(Ok((protocol, io)))
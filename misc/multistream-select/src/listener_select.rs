@@ -48,7 +48,7 @@ where
         match Protocol::try_from(n.as_ref()) {
             Ok(p) => Some((n, p)),
             Err(e) => {
-                log::warn!("Listener: Ignoring invalid protocol: {} due to {}",
+                tracing::warn!("Listener: Ignoring invalid protocol: {} due to {}",
                       String::from_utf8_lossy(n.as_ref()), e);
                 None
             }
@@ -176,10 +176,10 @@ where
                             });
 
                             let message = if protocol.is_some() {
-                                log::debug!("Listener: confirming protocol: {}", p);
+                                tracing::debug!("Listener: confirming protocol: {}", p);
                                 Message::Protocol(p.clone())
                             } else {
-                                log::debug!("Listener: rejecting protocol: {}",
+                                tracing::debug!("Listener: rejecting protocol: {}",
                                     String::from_utf8_lossy(p.as_ref()));
                                 Message::NotAvailable
                             };
@@ -209,7 +209,7 @@ where
                     // message.
                     *this.state = match protocol {
                         Some(protocol) => {
-                            log::debug!("Listener: sent confirmed protocol: {}",
+                            tracing::debug!("Listener: sent confirmed protocol: {}",
                                 String::from_utf8_lossy(protocol.as_ref()));
                             let (io, remaining) = io.into_inner();
                             let io = Negotiated::completed(io, remaining);
@@ -138,7 +138,7 @@ impl<TInner> Negotiated<TInner> {
 
                     if let Message::Protocol(p) = &msg {
                         if p.as_ref() == protocol.as_ref() {
-                            log::debug!("Negotiated: Received confirmation for protocol: {}", p);
+                            tracing::debug!("Negotiated: Received confirmation for protocol: {}", p);
                             let (io, remaining) = io.into_inner();
                             *this.state = State::Completed { io, remaining };
                             return Poll::Ready(Ok(()));
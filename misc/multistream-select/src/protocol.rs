@@ -36,15 +36,6 @@ use unsigned_varint as uvi;
 const MAX_PROTOCOLS: usize = 1000;
 
 /// The maximum length (in bytes) of a protocol name.
-///
-/// This limit is necessary in order to be able to unambiguously parse
-/// response messages without knowledge of the corresponding request.
-/// 140 comes about from 3 * 47 = 141, where 47 is the ascii/utf8
-/// encoding of the `/` character and an encoded protocol name is
-/// at least 3 bytes long (uvi-length followed by `/` and `\n`).
-/// Hence a protocol list response message with 47 protocols is at least
-/// 141 bytes long and thus such a response cannot be mistaken for a
-/// single protocol response. See `Message::decode`.
 const MAX_PROTOCOL_LEN: usize = 140;
 
 /// The encoded form of a multistream-select 1.0.0 header message.
@@ -199,13 +190,17 @@ impl Message {
                 Ok(())
             }
             Message::Protocols(ps) => {
+                // The protocols are length-delimited and terminated by a
+                // line feed, followed by a final line feed, as in other
+                // implementations of multistream-select.
                 let mut buf = uvi::encode::usize_buffer();
-                let mut out_msg = Vec::from(uvi::encode::usize(ps.len(), &mut buf));
+                let mut out_msg = Vec::new();
                 for p in ps {
                     out_msg.extend(uvi::encode::usize(p.0.as_ref().len() + 1, &mut buf)); // +1 for '\n'
                     out_msg.extend_from_slice(p.0.as_ref());
                     out_msg.push(b'\n')
                 }
+                out_msg.push(b'\n');
                 dest.reserve(out_msg.len());
                 dest.put(out_msg.as_ref());
                 Ok(())
@@ -228,7 +223,11 @@ impl Message {
             return Ok(Message::Header(Version::V1))
         }
 
-        if msg.get(0) == Some(&b'/') && msg.last() == Some(&b'\n') && msg.len() <= MAX_PROTOCOL_LEN {
+        // A protocol message has no line feed other than the trailing one,
+        // whereas a protocol list response has at least two.
+        if msg.first() == Some(&b'/') && msg.last() == Some(&b'\n')
+            && !msg[.. msg.len() - 1].contains(&b'\n')
+        {
             let p = Protocol::try_from(msg.split_to(msg.len() - 1))?;
             return Ok(Message::Protocol(p));
         }
@@ -241,14 +240,17 @@ impl Message {
             return Ok(Message::ListProtocols)
         }
 
-        // At this point, it must be a varint number of protocols, i.e.
-        // a `Protocols` message.
-        let (num_protocols, mut remaining) = uvi::decode::usize(&msg)?;
-        if num_protocols > MAX_PROTOCOLS {
-            return Err(ProtocolError::TooManyProtocols)
-        }
-        let mut protocols = Vec::with_capacity(num_protocols);
-        for _ in 0 .. num_protocols {
+        // At this point, it must be a list of length-delimited protocols
+        // terminated by a line feed, i.e. a `Protocols` message.
+        let mut protocols = Vec::new();
+        let mut remaining: &[u8] = &msg;
+        loop {
+            if remaining == b"\n" {
+                break
+            }
+            if protocols.len() == MAX_PROTOCOLS {
+                return Err(ProtocolError::TooManyProtocols)
+            }
             let (len, rem) = uvi::decode::usize(remaining)?;
             if len == 0 || len > rem.len() || rem[len - 1] != b'\n' {
                 return Err(ProtocolError::InvalidMessage)
@@ -427,7 +429,7 @@ where
         return Poll::Ready(None)
     };
 
-    log::trace!("Received message: {:?}", msg);
+    tracing::trace!("Received message: {:?}", msg);
 
     Poll::Ready(Some(Ok(msg)))
 }
@@ -537,5 +539,32 @@ mod tests {
         }
         quickcheck(prop as fn(_))
     }
+
+    #[test]
+    fn protocols_wire_format() {
+        let protocols = vec![
+            Protocol::try_from(&b"/a"[..]).unwrap(),
+            Protocol::try_from(&b"/bc/1.0.0"[..]).unwrap(),
+        ];
+        let encoded = &b"\x03/a\n\x0a/bc/1.0.0\n\n"[..];
+
+        let mut buf = BytesMut::new();
+        Message::Protocols(protocols.clone()).encode(&mut buf).unwrap();
+        assert_eq!(&buf[..], encoded);
+        assert_eq!(Message::decode(Bytes::from(encoded)).unwrap(), Message::Protocols(protocols));
+        assert_eq!(Message::decode(Bytes::from(&b"\n"[..])).unwrap(), Message::Protocols(Vec::new()));
+    }
+
+    #[test]
+    fn protocols_not_mistaken_for_protocol() {
+        // A list whose first protocol is 46 bytes long starts with the
+        // encoded length 47, i.e. `/`.
+        let name = format!("/{}", "a".repeat(45));
+        let protocols = vec![Protocol::try_from(Bytes::from(name)).unwrap()];
+        let mut buf = BytesMut::new();
+        Message::Protocols(protocols.clone()).encode(&mut buf).unwrap();
+        assert_eq!(buf[0], b'/');
+        assert_eq!(Message::decode(buf.freeze()).unwrap(), Message::Protocols(protocols));
+    }
 }
 
@@ -0,0 +1,181 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Storage backends of a [`PeerStore`](crate::PeerStore).
+
+use crate::record::{AddressRecord, PeerRecord};
+use libp2p_core::{Multiaddr, PeerId, PublicKey};
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use wasm_timer::Instant;
+
+/// A storage backend of a [`PeerStore`](crate::PeerStore).
+///
+/// The peer store loads the stored peers from its backend when it is created
+/// and saves all peers to its backend periodically, if they changed.
+pub trait Backend {
+    /// Loads all stored peers. Addresses that expired while stored are omitted.
+    fn load(&mut self) -> io::Result<Vec<(PeerId, PeerRecord)>>;
+
+    /// Replaces all stored peers with the given peers.
+    fn save<'a>(&mut self, peers: &mut dyn Iterator<Item = (&'a PeerId, &'a PeerRecord)>) -> io::Result<()>;
+}
+
+/// A backend that does not store anything, such that the peer store only
+/// lives in memory.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryBackend;
+
+impl Backend for MemoryBackend {
+    fn load(&mut self) -> io::Result<Vec<(PeerId, PeerRecord)>> {
+        Ok(Vec::new())
+    }
+
+    fn save<'a>(&mut self, _: &mut dyn Iterator<Item = (&'a PeerId, &'a PeerRecord)>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A backend storing the peers in a file.
+///
+/// The file contains one line per peer, each followed by one line per address
+/// of that peer:
+///
+/// ```text
+/// peer <peer ID> <successes> <failures> <base58 public key or "-">
+/// addr <multiaddr> <expiry in seconds since the UNIX epoch or "-"> <successes> <failures>
+/// ```
+///
+/// The file is replaced atomically on every save.
+#[derive(Debug, Clone)]
+pub struct FileBackend {
+    path: PathBuf,
+}
+
+impl FileBackend {
+    /// Creates a backend storing the peers in the file at the given path.
+    ///
+    /// The file is created on the first save, if it does not exist.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileBackend { path: path.into() }
+    }
+}
+
+impl Backend for FileBackend {
+    fn load(&mut self) -> io::Result<Vec<(PeerId, PeerRecord)>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut peers = Vec::<(PeerId, PeerRecord)>::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            match fields.as_slice() {
+                ["peer", peer_id, successes, failures, public_key] => {
+                    let peer_id = peer_id.parse::<PeerId>().map_err(|_| invalid_data("peer ID"))?;
+                    let public_key = match *public_key {
+                        "-" => None,
+                        key => {
+                            let bytes = bs58::decode(key).into_vec().map_err(|_| invalid_data("public key"))?;
+                            Some(PublicKey::from_protobuf_encoding(&bytes).map_err(|_| invalid_data("public key"))?)
+                        }
+                    };
+                    let record = PeerRecord::new(
+                        Vec::new(),
+                        public_key,
+                        parse_counter(successes)?,
+                        parse_counter(failures)?,
+                    );
+                    peers.push((peer_id, record));
+                }
+                ["addr", address, expires, successes, failures] => {
+                    let (_, record) = peers.last_mut().ok_or_else(|| invalid_data("address without peer"))?;
+                    let address = address.parse::<Multiaddr>().map_err(|_| invalid_data("address"))?;
+                    let expires = match *expires {
+                        "-" => None,
+                        secs => {
+                            let secs = secs.parse::<u64>().map_err(|_| invalid_data("expiry"))?;
+                            let expires = UNIX_EPOCH + Duration::from_secs(secs);
+                            match expires.duration_since(SystemTime::now()) {
+                                Ok(remaining) => Instant::now().checked_add(remaining),
+                                Err(_) => continue,
+                            }
+                        }
+                    };
+                    record.addresses.push(AddressRecord::new(
+                        address,
+                        expires,
+                        parse_counter(successes)?,
+                        parse_counter(failures)?,
+                    ));
+                }
+                [] => {}
+                _ => return Err(invalid_data("line")),
+            }
+        }
+
+        Ok(peers)
+    }
+
+    fn save<'a>(&mut self, peers: &mut dyn Iterator<Item = (&'a PeerId, &'a PeerRecord)>) -> io::Result<()> {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let mut file = BufWriter::new(fs::File::create(&tmp_path)?);
+
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+        for (peer_id, record) in peers {
+            let public_key = match &record.public_key {
+                Some(key) => bs58::encode(key.clone().into_protobuf_encoding()).into_string(),
+                None => "-".to_owned(),
+            };
+            writeln!(file, "peer {} {} {} {}", peer_id, record.successes, record.failures, public_key)?;
+            for address in record.addresses() {
+                let expires = match address.expires {
+                    Some(e) => {
+                        let expires = system_now + e.duration_since(now);
+                        let secs = expires.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                        secs.to_string()
+                    }
+                    None => "-".to_owned(),
+                };
+                writeln!(file, "addr {} {} {} {}", address.address, expires, address.successes, address.failures)?;
+            }
+        }
+
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp_path, &self.path)
+    }
+}
+
+fn parse_counter(s: &str) -> io::Result<u32> {
+    s.parse().map_err(|_| invalid_data("counter"))
+}
+
+fn invalid_data(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid {} in peer store file.", what))
+}
@@ -0,0 +1,466 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! An address book of known peers.
+//!
+//! The [`PeerStore`] remembers the addresses of peers together with the
+//! point in time at which they expire, counts the successful and failed
+//! connection attempts per peer and per address, and keeps the public keys
+//! of peers. As a [`NetworkBehaviour`] it reports the known addresses of a
+//! peer to the `Swarm`, such that dialing a peer by its `PeerId` uses the
+//! addresses from the store as candidates.
+//!
+//! The peers can be persisted with a [`Backend`], such as the [`FileBackend`],
+//! so that a restarted node can immediately dial previously known peers.
+//!
+//! The store only learns about addresses through [`PeerStore::add_address`],
+//! usually fed with the addresses discovered by other behaviours such as
+//! `libp2p-identify` or `libp2p-kad`, and through successfully dialed
+//! addresses.
+//!
+//! An address that could not be dialed is not reported to the `Swarm` for a
+//! backoff that doubles with every consecutive failure, such that peers
+//! that are no longer reachable are not dialed over and over again.
+
+mod backend;
+mod record;
+
+pub use backend::{Backend, FileBackend, MemoryBackend};
+pub use record::{AddressRecord, PeerRecord};
+
+use libp2p_core::{
+    ConnectedPoint,
+    Multiaddr,
+    PeerId,
+    PublicKey,
+    connection::ConnectionId,
+};
+use libp2p_swarm::{
+    NetworkBehaviour,
+    NetworkBehaviourAction,
+    PollParameters,
+    ProtocolsHandler,
+    protocols_handler::DummyProtocolsHandler,
+};
+use futures::prelude::*;
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    error,
+    io,
+    task::{Context, Poll},
+    time::Duration,
+};
+use wasm_timer::{Instant, Interval};
+
+/// The configuration of a [`PeerStore`].
+#[derive(Debug, Clone)]
+pub struct PeerStoreConfig {
+    connected_ttl: Duration,
+    max_addresses_per_peer: usize,
+    save_interval: Duration,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for PeerStoreConfig {
+    fn default() -> Self {
+        PeerStoreConfig {
+            connected_ttl: Duration::from_secs(60 * 60),
+            max_addresses_per_peer: 32,
+            save_interval: Duration::from_secs(60),
+            initial_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+impl PeerStoreConfig {
+    /// Sets the TTL of an address after a connection has successfully
+    /// been established by dialing it.
+    ///
+    /// Defaults to 1 hour.
+    pub fn set_connected_ttl(&mut self, ttl: Duration) -> &mut Self {
+        self.connected_ttl = ttl;
+        self
+    }
+
+    /// Sets the maximum number of addresses kept per peer. If the limit is
+    /// reached, the address that expires first is replaced.
+    ///
+    /// Defaults to 32.
+    pub fn set_max_addresses_per_peer(&mut self, max: usize) -> &mut Self {
+        self.max_addresses_per_peer = max;
+        self
+    }
+
+    /// Sets the interval at which the peers are saved to the backend, if
+    /// they changed.
+    ///
+    /// Defaults to 1 minute.
+    pub fn set_save_interval(&mut self, interval: Duration) -> &mut Self {
+        self.save_interval = interval;
+        self
+    }
+
+    /// Sets the time during which an address is not reported as a dialing
+    /// candidate after a failed dialing attempt. The backoff doubles with
+    /// every consecutive failure and is reset by a successful connection.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn set_initial_backoff(&mut self, backoff: Duration) -> &mut Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Sets the maximum backoff of an address after failed dialing attempts.
+    ///
+    /// Defaults to 30 minutes.
+    pub fn set_max_backoff(&mut self, backoff: Duration) -> &mut Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Returns the backoff after the given number of consecutive failures.
+    fn backoff(&self, failures: u32) -> Duration {
+        self.initial_backoff
+            .checked_mul(2u32.saturating_pow(failures))
+            .map_or(self.max_backoff, |b| b.min(self.max_backoff))
+    }
+}
+
+/// A [`NetworkBehaviour`] keeping the addresses, connection statistics and
+/// public keys of known peers.
+pub struct PeerStore<TBackend = MemoryBackend> {
+    config: PeerStoreConfig,
+    peers: HashMap<PeerId, PeerRecord>,
+    backend: TBackend,
+    /// Whether the peers changed since they were last saved.
+    dirty: bool,
+    save_interval: Interval,
+}
+
+impl PeerStore<MemoryBackend> {
+    /// Creates a new, empty `PeerStore` that only lives in memory.
+    pub fn new(config: PeerStoreConfig) -> Self {
+        let save_interval = Interval::new(config.save_interval);
+        PeerStore {
+            config,
+            peers: HashMap::new(),
+            backend: MemoryBackend,
+            dirty: false,
+            save_interval,
+        }
+    }
+}
+
+impl<TBackend> PeerStore<TBackend>
+where
+    TBackend: Backend,
+{
+    /// Creates a `PeerStore` with the peers loaded from the given backend,
+    /// to which the peers are saved periodically.
+    pub fn with_backend(config: PeerStoreConfig, mut backend: TBackend) -> io::Result<Self> {
+        let peers = backend.load()?.into_iter().collect();
+        let save_interval = Interval::new(config.save_interval);
+        Ok(PeerStore {
+            config,
+            peers,
+            backend,
+            dirty: false,
+            save_interval,
+        })
+    }
+
+    /// Saves the peers to the backend.
+    ///
+    /// The peers are saved periodically, but should also be saved explicitly
+    /// before shutting down, so that no changes are lost.
+    pub fn save(&mut self) -> io::Result<()> {
+        self.backend.save(&mut self.peers.iter())?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Adds an address of a peer that expires after `ttl`.
+    ///
+    /// If the address is already known, its expiry is extended if necessary.
+    /// An address whose expiry cannot be represented never expires.
+    pub fn add_address(&mut self, peer_id: PeerId, address: Multiaddr, ttl: Duration) {
+        let expires = Instant::now().checked_add(ttl);
+        let max = self.config.max_addresses_per_peer;
+        self.peers.entry(peer_id).or_default().insert_address(address, expires, max);
+        self.dirty = true;
+    }
+
+    /// Removes an address of a peer.
+    ///
+    /// Returns `true` if the address was known.
+    pub fn remove_address(&mut self, peer_id: &PeerId, address: &Multiaddr) -> bool {
+        if let Some(record) = self.peers.get_mut(peer_id) {
+            let len = record.addresses.len();
+            record.addresses.retain(|a| &a.address != address);
+            self.dirty = true;
+            return record.addresses.len() != len
+        }
+        false
+    }
+
+    /// Adds the public key of a peer, whose ID is derived from the key.
+    pub fn add_public_key(&mut self, public_key: PublicKey) {
+        let peer_id = public_key.clone().into_peer_id();
+        self.peers.entry(peer_id).or_default().public_key = Some(public_key);
+        self.dirty = true;
+    }
+
+    /// Removes everything known about a peer.
+    pub fn remove_peer(&mut self, peer_id: &PeerId) -> Option<PeerRecord> {
+        self.dirty = true;
+        self.peers.remove(peer_id)
+    }
+
+    /// Returns the record of a peer.
+    pub fn peer(&self, peer_id: &PeerId) -> Option<&PeerRecord> {
+        self.peers.get(peer_id)
+    }
+
+    /// Returns an iterator over all known peers.
+    pub fn peers(&self) -> impl Iterator<Item = (&PeerId, &PeerRecord)> {
+        self.peers.iter()
+    }
+
+    /// Returns the addresses of a peer that have not expired, ordered by
+    /// the number of successful minus failed connection attempts.
+    pub fn addresses(&self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.sorted_addresses(peer_id).into_iter().map(|a| a.address.clone()).collect()
+    }
+
+    /// Returns the addresses of a peer like [`PeerStore::addresses`], except
+    /// for those that are backing off after failed dialing attempts.
+    pub fn dialable_addresses(&self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        let now = Instant::now();
+        self.sorted_addresses(peer_id).into_iter()
+            .filter(|a| !a.is_backed_off(now))
+            .map(|a| a.address.clone())
+            .collect()
+    }
+
+    fn sorted_addresses(&self, peer_id: &PeerId) -> Vec<&AddressRecord> {
+        let mut addrs = match self.peers.get(peer_id) {
+            Some(record) => record.addresses().collect::<Vec<_>>(),
+            None => return Vec::new(),
+        };
+        addrs.sort_by_key(|a| i64::from(a.failures) - i64::from(a.successes));
+        addrs
+    }
+
+    /// Returns the public key of a peer, if known.
+    pub fn public_key(&self, peer_id: &PeerId) -> Option<&PublicKey> {
+        self.peers.get(peer_id).and_then(|r| r.public_key())
+    }
+
+    /// Removes the expired addresses of all peers.
+    pub fn remove_expired(&mut self) {
+        let now = Instant::now();
+        for record in self.peers.values_mut() {
+            record.remove_expired(now);
+        }
+    }
+}
+
+impl<TBackend> NetworkBehaviour for PeerStore<TBackend>
+where
+    TBackend: Backend + Send + 'static,
+{
+    type ProtocolsHandler = DummyProtocolsHandler;
+    type OutEvent = void::Void;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        DummyProtocolsHandler::default()
+    }
+
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        if let Entry::Occupied(mut e) = self.peers.entry(peer_id.clone()) {
+            e.get_mut().remove_expired(Instant::now());
+        }
+        self.dialable_addresses(peer_id)
+    }
+
+    fn inject_connected(&mut self, _: &PeerId) {}
+
+    fn inject_disconnected(&mut self, _: &PeerId) {}
+
+    fn inject_connection_established(&mut self, peer_id: &PeerId, _: &ConnectionId, endpoint: &ConnectedPoint) {
+        self.dirty = true;
+        let record = self.peers.entry(peer_id.clone()).or_default();
+        record.successes = record.successes.saturating_add(1);
+        if let ConnectedPoint::Dialer { address } = endpoint {
+            let expires = Instant::now().checked_add(self.config.connected_ttl);
+            record.insert_address(address.clone(), expires, self.config.max_addresses_per_peer);
+            if let Some(a) = record.address_mut(address) {
+                a.record_success();
+            }
+        }
+    }
+
+    fn inject_addr_reach_failure(&mut self, peer_id: Option<&PeerId>, addr: &Multiaddr, _: &dyn error::Error) {
+        let peers = &mut self.peers;
+        if let Some(a) = peer_id.and_then(|p| peers.get_mut(p)).and_then(|r| r.address_mut(addr)) {
+            let backoff = self.config.backoff(a.consecutive_failures);
+            log::debug!("Not dialing {} for {:?} after {} consecutive failures.",
+                addr, backoff, a.consecutive_failures + 1);
+            a.record_failure(backoff);
+            self.dirty = true;
+        }
+    }
+
+    fn inject_dial_failure(&mut self, peer_id: &PeerId) {
+        if let Some(record) = self.peers.get_mut(peer_id) {
+            record.failures = record.failures.saturating_add(1);
+            self.dirty = true;
+        }
+    }
+
+    fn inject_event(&mut self, _: PeerId, _: ConnectionId,
+        ev: <Self::ProtocolsHandler as ProtocolsHandler>::OutEvent)
+    {
+        void::unreachable(ev)
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>, _: &mut impl PollParameters) ->
+        Poll<NetworkBehaviourAction<<Self::ProtocolsHandler as ProtocolsHandler>::InEvent, Self::OutEvent>>
+    {
+        while let Poll::Ready(Some(_)) = self.save_interval.poll_next_unpin(cx) {
+            if self.dirty {
+                self.remove_expired();
+                if let Err(e) = self.save() {
+                    log::warn!("Failed to save the peer store: {:?}", e);
+                }
+            }
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_core::identity;
+
+    fn addr(s: &str) -> Multiaddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn addresses_expire() {
+        let mut store = PeerStore::new(PeerStoreConfig::default());
+        let peer = PeerId::random();
+        store.add_address(peer.clone(), addr("/memory/1"), Duration::from_secs(0));
+        store.add_address(peer.clone(), addr("/memory/2"), Duration::from_secs(60));
+        assert_eq!(store.addresses(&peer), vec![addr("/memory/2")]);
+        assert_eq!(store.addresses_of_peer(&peer), vec![addr("/memory/2")]);
+        assert_eq!(store.peer(&peer).unwrap().addresses.len(), 1);
+
+        // Re-adding an address never shortens its expiry.
+        store.add_address(peer.clone(), addr("/memory/2"), Duration::from_secs(0));
+        assert_eq!(store.addresses(&peer), vec![addr("/memory/2")]);
+    }
+
+    #[test]
+    fn max_addresses_per_peer() {
+        let mut config = PeerStoreConfig::default();
+        config.set_max_addresses_per_peer(2);
+        let mut store = PeerStore::new(config);
+        let peer = PeerId::random();
+        store.add_address(peer.clone(), addr("/memory/1"), Duration::from_secs(20));
+        store.add_address(peer.clone(), addr("/memory/2"), Duration::from_secs(10));
+        store.add_address(peer.clone(), addr("/memory/3"), Duration::from_secs(30));
+        let mut addrs = store.addresses(&peer);
+        addrs.sort_by_key(|a| a.to_string());
+        assert_eq!(addrs, vec![addr("/memory/1"), addr("/memory/3")]);
+    }
+
+    #[test]
+    fn addresses_ordered_by_success() {
+        let mut store = PeerStore::new(PeerStoreConfig::default());
+        let peer = PeerId::random();
+        let ttl = Duration::from_secs(60);
+        store.add_address(peer.clone(), addr("/memory/1"), ttl);
+        store.add_address(peer.clone(), addr("/memory/2"), ttl);
+        let error = std::io::Error::new(std::io::ErrorKind::Other, "unreachable");
+        store.inject_addr_reach_failure(Some(&peer), &addr("/memory/1"), &error);
+        store.inject_dial_failure(&peer);
+        assert_eq!(store.addresses(&peer), vec![addr("/memory/2"), addr("/memory/1")]);
+
+        let endpoint = ConnectedPoint::Dialer { address: addr("/memory/1") };
+        store.inject_connection_established(&peer, &ConnectionId::new(0), &endpoint);
+        store.inject_connection_established(&peer, &ConnectionId::new(1), &endpoint);
+        assert_eq!(store.addresses(&peer), vec![addr("/memory/1"), addr("/memory/2")]);
+
+        let record = store.peer(&peer).unwrap();
+        assert_eq!((record.successes(), record.failures()), (2, 1));
+    }
+
+    #[test]
+    fn addresses_back_off_after_failure() {
+        let mut config = PeerStoreConfig::default();
+        config.set_initial_backoff(Duration::from_secs(60)).set_max_backoff(Duration::from_secs(90));
+        let mut store = PeerStore::new(config);
+        let peer = PeerId::random();
+        let ttl = Duration::from_secs(60 * 60);
+        store.add_address(peer.clone(), addr("/memory/1"), ttl);
+        store.add_address(peer.clone(), addr("/memory/2"), ttl);
+
+        let error = std::io::Error::new(std::io::ErrorKind::Other, "unreachable");
+        let before = Instant::now();
+        store.inject_addr_reach_failure(Some(&peer), &addr("/memory/1"), &error);
+        assert_eq!(store.addresses_of_peer(&peer), vec![addr("/memory/2")]);
+        assert_eq!(store.addresses(&peer), vec![addr("/memory/2"), addr("/memory/1")]);
+        let until = store.peer(&peer).unwrap().addresses().find(|a| a.address() == &addr("/memory/1"))
+            .and_then(|a| a.backoff_until())
+            .unwrap();
+        assert!(until >= before + Duration::from_secs(60));
+        assert!(until < before + Duration::from_secs(90));
+
+        // The backoff doubles up to the maximum.
+        store.inject_addr_reach_failure(Some(&peer), &addr("/memory/1"), &error);
+        let until = store.peer(&peer).unwrap().addresses().find(|a| a.address() == &addr("/memory/1"))
+            .and_then(|a| a.backoff_until())
+            .unwrap();
+        assert!(until >= before + Duration::from_secs(90));
+
+        // A successful connection resets the backoff.
+        let endpoint = ConnectedPoint::Dialer { address: addr("/memory/1") };
+        store.inject_connection_established(&peer, &ConnectionId::new(0), &endpoint);
+        assert_eq!(store.addresses_of_peer(&peer).len(), 2);
+        let record = store.peer(&peer).unwrap();
+        assert!(record.addresses().all(|a| a.backoff_until().is_none()));
+    }
+
+    #[test]
+    fn public_keys() {
+        let mut store = PeerStore::new(PeerStoreConfig::default());
+        let key = identity::Keypair::generate_ed25519().public();
+        let peer = key.clone().into_peer_id();
+        store.add_public_key(key.clone());
+        assert_eq!(store.public_key(&peer), Some(&key));
+        assert!(store.remove_peer(&peer).is_some());
+        assert_eq!(store.public_key(&peer), None);
+    }
+}
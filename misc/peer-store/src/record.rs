@@ -0,0 +1,175 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The records kept by the [`PeerStore`](crate::PeerStore) for every known peer.
+
+use libp2p_core::{Multiaddr, PublicKey};
+use std::time::Duration;
+use wasm_timer::Instant;
+
+/// The information known about a peer.
+#[derive(Debug, Clone, Default)]
+pub struct PeerRecord {
+    pub(crate) addresses: Vec<AddressRecord>,
+    pub(crate) public_key: Option<PublicKey>,
+    pub(crate) successes: u32,
+    pub(crate) failures: u32,
+}
+
+impl PeerRecord {
+    /// Creates a record, e.g. when loading it in a [`Backend`](crate::Backend).
+    pub fn new(addresses: Vec<AddressRecord>, public_key: Option<PublicKey>, successes: u32, failures: u32) -> Self {
+        PeerRecord { addresses, public_key, successes, failures }
+    }
+
+    /// Returns the addresses of the peer that have not expired.
+    pub fn addresses(&self) -> impl Iterator<Item = &AddressRecord> {
+        let now = Instant::now();
+        self.addresses.iter().filter(move |a| !a.is_expired(now))
+    }
+
+    /// Returns the public key of the peer, if known.
+    pub fn public_key(&self) -> Option<&PublicKey> {
+        self.public_key.as_ref()
+    }
+
+    /// Returns the number of connections established with the peer.
+    pub fn successes(&self) -> u32 {
+        self.successes
+    }
+
+    /// Returns the number of dialing attempts to the peer that failed on
+    /// all addresses.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Inserts the address or extends its expiry, such that it expires no
+    /// sooner than `expires`.
+    pub(crate) fn insert_address(&mut self, address: Multiaddr, expires: Option<Instant>, max: usize) {
+        if let Some(record) = self.addresses.iter_mut().find(|a| a.address == address) {
+            record.expires = match (record.expires, expires) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                _ => None,
+            };
+            return
+        }
+
+        if self.addresses.len() >= max {
+            // Evict the address that expires first.
+            let evict = self.addresses.iter()
+                .enumerate()
+                .filter_map(|(i, a)| a.expires.map(|e| (i, e)))
+                .min_by_key(|(_, e)| *e)
+                .map(|(i, _)| i);
+            match evict {
+                Some(i) => { self.addresses.remove(i); }
+                None => return,
+            }
+        }
+
+        self.addresses.push(AddressRecord::new(address, expires, 0, 0));
+    }
+
+    /// Removes all addresses that have expired.
+    pub(crate) fn remove_expired(&mut self, now: Instant) {
+        self.addresses.retain(|a| !a.is_expired(now));
+    }
+
+    pub(crate) fn address_mut(&mut self, address: &Multiaddr) -> Option<&mut AddressRecord> {
+        self.addresses.iter_mut().find(|a| &a.address == address)
+    }
+}
+
+/// An address of a peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressRecord {
+    pub(crate) address: Multiaddr,
+    pub(crate) expires: Option<Instant>,
+    pub(crate) successes: u32,
+    pub(crate) failures: u32,
+    /// The number of failed dialing attempts since the last success.
+    pub(crate) consecutive_failures: u32,
+    /// The point in time before which the address is not dialed again.
+    pub(crate) backoff_until: Option<Instant>,
+}
+
+impl AddressRecord {
+    /// Creates a record, e.g. when loading it in a [`Backend`](crate::Backend).
+    ///
+    /// The backoff after failed dialing attempts is not persisted, hence a
+    /// loaded address can be dialed immediately.
+    pub fn new(address: Multiaddr, expires: Option<Instant>, successes: u32, failures: u32) -> Self {
+        AddressRecord { address, expires, successes, failures, consecutive_failures: 0, backoff_until: None }
+    }
+
+    /// Returns the address.
+    pub fn address(&self) -> &Multiaddr {
+        &self.address
+    }
+
+    /// Returns the point in time at which the address expires, or `None`
+    /// if it never expires.
+    pub fn expires(&self) -> Option<Instant> {
+        self.expires
+    }
+
+    /// Returns the number of connections established by dialing the address.
+    pub fn successes(&self) -> u32 {
+        self.successes
+    }
+
+    /// Returns the number of failed attempts to dial the address.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Returns the point in time before which the address is not dialed
+    /// again after a failed dialing attempt, if any.
+    pub fn backoff_until(&self) -> Option<Instant> {
+        self.backoff_until
+    }
+
+    /// Records a failed attempt to dial the address, which is not dialed
+    /// again for the given backoff.
+    pub(crate) fn record_failure(&mut self, backoff: Duration) {
+        self.failures = self.failures.saturating_add(1);
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        self.backoff_until = Instant::now().checked_add(backoff);
+    }
+
+    /// Records a connection established by dialing the address.
+    pub(crate) fn record_success(&mut self) {
+        self.successes = self.successes.saturating_add(1);
+        self.consecutive_failures = 0;
+        self.backoff_until = None;
+    }
+
+    pub(crate) fn is_backed_off(&self, now: Instant) -> bool {
+        match self.backoff_until {
+            Some(until) => now < until,
+            None => false,
+        }
+    }
+
+    pub(crate) fn is_expired(&self, now: Instant) -> bool {
+        self.expires.is_some_and(|e| e <= now)
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::executor::block_on;
+use libp2p_core::{
+    identity,
+    multiaddr::{Multiaddr, Protocol},
+    muxing::StreamMuxerBox,
+    transport::{boxed::Boxed, MemoryTransport, Transport},
+    upgrade,
+    PeerId,
+};
+use libp2p_peer_store::{FileBackend, PeerStore, PeerStoreConfig};
+use libp2p_plaintext::PlainText2Config;
+use libp2p_swarm::{Swarm, SwarmEvent};
+use libp2p_yamux as yamux;
+use std::{io, time::Duration};
+
+#[test]
+fn dial_with_addresses_from_store() {
+    let (bob_id, trans) = mk_transport();
+    let mut bob = Swarm::new(trans, PeerStore::new(PeerStoreConfig::default()), bob_id.clone());
+    let bob_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    Swarm::listen_on(&mut bob, bob_addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        loop { bob.next_event().await; }
+    });
+
+    let (alice_id, trans) = mk_transport();
+    let mut alice = Swarm::new(trans, PeerStore::new(PeerStoreConfig::default()), alice_id);
+    let unreachable: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    alice.add_address(bob_id.clone(), unreachable.clone(), Duration::from_secs(60));
+    alice.add_address(bob_id.clone(), bob_addr.clone(), Duration::from_secs(60));
+    Swarm::dial(&mut alice, &bob_id).unwrap();
+
+    block_on(async {
+        loop {
+            match alice.next_event().await {
+                SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                    assert_eq!(peer_id, bob_id);
+                    break
+                }
+                SwarmEvent::UnknownPeerUnreachableAddr { .. } |
+                SwarmEvent::UnreachableAddr { .. } |
+                SwarmEvent::Dialing(_) => {}
+                e => panic!("Unexpected event: {:?}", e),
+            }
+        }
+    });
+
+    let record = alice.peer(&bob_id).unwrap();
+    assert_eq!(record.successes(), 1);
+    for address in record.addresses() {
+        if address.address() == &bob_addr {
+            assert_eq!((address.successes(), address.failures()), (1, 0));
+        } else {
+            assert_eq!((address.successes(), address.failures()), (0, 1));
+        }
+    }
+    assert_eq!(alice.addresses(&bob_id), vec![bob_addr, unreachable]);
+}
+
+#[test]
+fn peers_survive_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("peers");
+
+    let key = identity::Keypair::generate_ed25519().public();
+    let peer = key.clone().into_peer_id();
+    let addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    let expired: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    let permanent: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+
+    let mut store = PeerStore::with_backend(PeerStoreConfig::default(), FileBackend::new(&path)).unwrap();
+    assert!(store.peers().next().is_none());
+    store.add_public_key(key.clone());
+    store.add_address(peer.clone(), addr.clone(), Duration::from_secs(60 * 60));
+    store.add_address(peer.clone(), expired, Duration::from_secs(0));
+    store.add_address(peer.clone(), permanent.clone(), Duration::from_secs(u64::MAX));
+    store.save().unwrap();
+    drop(store);
+
+    let store = PeerStore::with_backend(PeerStoreConfig::default(), FileBackend::new(&path)).unwrap();
+    assert_eq!(store.public_key(&peer), Some(&key));
+    assert_eq!(store.addresses(&peer), vec![addr, permanent.clone()]);
+    let record = store.peer(&peer).unwrap();
+    assert!(record.addresses().any(|a| a.address() == &permanent && a.expires().is_none()));
+}
+
+fn mk_transport() -> (PeerId, Boxed<(PeerId, StreamMuxerBox), io::Error>) {
+    let id_keys = identity::Keypair::generate_ed25519();
+    let peer_id = id_keys.public().into_peer_id();
+    let transport = MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(PlainText2Config { local_public_key: id_keys.public() })
+        .multiplex(yamux::Config::default())
+        .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+        .boxed();
+    (peer_id, transport)
+}
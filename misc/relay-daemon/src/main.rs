@@ -0,0 +1,279 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! A standalone circuit relay v2 server.
+//!
+//! The daemon runs a public relay that peers behind a NAT can make
+//! reservations on, such that other peers can reach them through the relay.
+//! It serves the identify and ping protocols next to the relay, so that its
+//! clients learn their observed addresses and keep their connections alive.
+//!
+//! ```text
+//! RELAY_DAEMON_PASSPHRASE=... libp2p-relay-daemon \
+//!     --identity /var/lib/relay/keys \
+//!     --listen /ip4/0.0.0.0/tcp/4001 \
+//!     --external-address /ip4/203.0.113.1/tcp/4001 \
+//!     --metrics 127.0.0.1:9090
+//! ```
+//!
+//! The identity of the relay is stored in a keystore in the `--identity`
+//! directory, encrypted with the passphrase in the `RELAY_DAEMON_PASSPHRASE`
+//! environment variable, such that the relay keeps its peer ID across
+//! restarts. If the metrics address is given, the metrics of the relay are
+//! served in the Prometheus text format over HTTP.
+
+use async_std::{net::TcpListener, task};
+use futures::prelude::*;
+use libp2p::{
+    Multiaddr,
+    NetworkBehaviour,
+    PeerId,
+    Swarm,
+    Transport,
+    core::{muxing::StreamMuxerBox, upgrade},
+    dns::DnsConfig,
+    identify::{Identify, IdentifyEvent},
+    identity::Keypair,
+    keystore::Keystore,
+    metrics::{Metrics, Recorder},
+    mplex::MplexConfig,
+    noise::{self, NoiseConfig, X25519Spec},
+    ping::{Ping, PingConfig, PingEvent},
+    relay::{Relay, RelayConfig, RelayEvent},
+    swarm::{NetworkBehaviourEventProcess, SwarmEvent},
+    tcp::TcpConfig,
+    yamux,
+};
+use std::{env, error::Error, net::SocketAddr, process, time::Duration};
+
+const USAGE: &str = "\
+Usage: libp2p-relay-daemon [options]
+
+Options:
+    --listen <multiaddr>               Address to listen on, repeatable
+                                       (default: /ip4/0.0.0.0/tcp/4001).
+    --external-address <multiaddr>     Publicly reachable address of the relay,
+                                       announced to clients, repeatable.
+    --identity <dir>                   Keystore directory to persist the identity
+                                       in, encrypted with $RELAY_DAEMON_PASSPHRASE.
+                                       A new identity is generated on every start
+                                       if not given.
+    --metrics <ip:port>                Address to serve Prometheus metrics on.
+    --max-reservations <n>             Maximum number of reservations.
+    --max-reservations-per-peer <n>    Maximum number of reservations per peer.
+    --reservation-duration <secs>      Duration after which reservations expire.
+    --max-circuits <n>                 Maximum number of relayed circuits.
+    --max-circuits-per-peer <n>        Maximum number of circuits per peer.
+    --max-circuit-duration <secs>      Maximum duration of a circuit.
+    --max-circuit-bytes <n>            Maximum number of bytes relayed per circuit.";
+
+/// The configuration of the daemon, parsed from the command line.
+struct Config {
+    listen_addrs: Vec<Multiaddr>,
+    external_addrs: Vec<Multiaddr>,
+    identity: Option<String>,
+    metrics_addr: Option<SocketAddr>,
+    relay: RelayConfig,
+}
+
+/// The network behaviour of the relay.
+#[derive(NetworkBehaviour)]
+struct Behaviour {
+    relay: Relay,
+    identify: Identify,
+    ping: Ping,
+    #[behaviour(ignore)]
+    metrics: Metrics,
+}
+
+impl NetworkBehaviourEventProcess<RelayEvent> for Behaviour {
+    fn inject_event(&mut self, event: RelayEvent) {
+        log::debug!("{:?}", event);
+        self.metrics.record(&event);
+    }
+}
+
+impl NetworkBehaviourEventProcess<IdentifyEvent> for Behaviour {
+    fn inject_event(&mut self, _: IdentifyEvent) {}
+}
+
+impl NetworkBehaviourEventProcess<PingEvent> for Behaviour {
+    fn inject_event(&mut self, _: PingEvent) {}
+}
+
+fn main() {
+    env_logger::init();
+
+    let config = match parse(env::args().skip(1)) {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return
+        }
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = task::block_on(run(config)) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+async fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    let local_key = match &config.identity {
+        Some(dir) => {
+            let passphrase = env::var("RELAY_DAEMON_PASSPHRASE")
+                .map_err(|_| "RELAY_DAEMON_PASSPHRASE must be set to use --identity")?;
+            Keystore::new(dir, passphrase).identity()?
+        }
+        None => {
+            log::warn!("No --identity given, the peer ID changes on every start.");
+            Keypair::generate_ed25519()
+        }
+    };
+    let local_peer_id = local_key.public().into_peer_id();
+    log::info!("Local peer ID: {}", local_peer_id);
+
+    let metrics = Metrics::new();
+    let mut swarm = build_swarm(local_key, local_peer_id.clone(), config.relay, &metrics)?;
+    for addr in config.listen_addrs {
+        Swarm::listen_on(&mut swarm, addr)?;
+    }
+    for addr in config.external_addrs {
+        Swarm::add_external_address(&mut swarm, addr);
+    }
+
+    if let Some(addr) = config.metrics_addr {
+        let listener = TcpListener::bind(addr).await?;
+        log::info!("Serving metrics on http://{}/metrics", addr);
+        task::spawn(serve_metrics(listener, metrics.clone()));
+    }
+
+    loop {
+        let event = swarm.next_event().await;
+        metrics.record(&event);
+        match event {
+            SwarmEvent::NewListenAddr(addr) =>
+                log::info!("Listening on {}/p2p/{}", addr, local_peer_id),
+            SwarmEvent::ListenerError { error } =>
+                log::warn!("Listener error: {}", error),
+            _ => {}
+        }
+    }
+}
+
+fn build_swarm(local_key: Keypair, local_peer_id: PeerId, relay_config: RelayConfig, metrics: &Metrics)
+    -> Result<Swarm<Behaviour>, Box<dyn Error>>
+{
+    let noise_keys = noise::Keypair::<X25519Spec>::new().into_authentic(&local_key)?;
+    let transport = DnsConfig::new(TcpConfig::new().nodelay(true))?
+        .upgrade(upgrade::Version::V1)
+        .observe(metrics.handshake_observer())
+        .authenticate(NoiseConfig::xx(noise_keys).into_authenticated())
+        .multiplex(upgrade::SelectUpgrade::new(yamux::Config::default(), MplexConfig::new()))
+        .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+        .timeout(Duration::from_secs(20));
+
+    let behaviour = Behaviour {
+        relay: Relay::new(local_peer_id.clone(), relay_config),
+        identify: Identify::new(
+            "ipfs/0.1.0".to_string(),
+            format!("libp2p-relay-daemon/{}", env!("CARGO_PKG_VERSION")),
+            local_key.public(),
+        ),
+        ping: Ping::new(PingConfig::new()),
+        metrics: metrics.clone(),
+    };
+
+    Ok(Swarm::new(metrics.transport(transport), behaviour, local_peer_id))
+}
+
+/// Answers every HTTP request on the listener with the encoded metrics.
+async fn serve_metrics(listener: TcpListener, metrics: Metrics) {
+    let mut incoming = listener.incoming();
+    while let Some(stream) = incoming.next().await {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::warn!("Failed to accept metrics connection: {}", e);
+                continue
+            }
+        };
+        let metrics = metrics.clone();
+        task::spawn(async move {
+            // The request is not inspected, but read up to the end of its
+            // header, which is all a Prometheus scrape consists of.
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") && request.len() < 8192 {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => request.extend_from_slice(&buf[.. n]),
+                }
+            }
+            let body = metrics.encode();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\n\
+                 Content-Type: text/plain; version=0.0.4\r\n\
+                 Content-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                body.len(), body);
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+/// Parses the command line, returning `None` if help is requested.
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Config>, Box<dyn Error>> {
+    let mut config = Config {
+        listen_addrs: Vec::new(),
+        external_addrs: Vec::new(),
+        identity: None,
+        metrics_addr: None,
+        relay: RelayConfig::default(),
+    };
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            return Ok(None)
+        }
+        let value = args.next().ok_or_else(|| format!("missing value of {}", arg))?;
+        match arg.as_str() {
+            "--listen" => config.listen_addrs.push(value.parse()?),
+            "--external-address" => config.external_addrs.push(value.parse()?),
+            "--identity" => config.identity = Some(value),
+            "--metrics" => config.metrics_addr = Some(value.parse()?),
+            "--max-reservations" => { config.relay.set_max_reservations(value.parse()?); }
+            "--max-reservations-per-peer" => { config.relay.set_max_reservations_per_peer(value.parse()?); }
+            "--reservation-duration" => { config.relay.set_reservation_duration(Duration::from_secs(value.parse()?)); }
+            "--max-circuits" => { config.relay.set_max_circuits(value.parse()?); }
+            "--max-circuits-per-peer" => { config.relay.set_max_circuits_per_peer(value.parse()?); }
+            "--max-circuit-duration" => { config.relay.set_max_circuit_duration(Duration::from_secs(value.parse()?)); }
+            "--max-circuit-bytes" => { config.relay.set_max_circuit_bytes(value.parse()?); }
+            _ => return Err(format!("unknown option {}", arg).into()),
+        }
+    }
+    if config.listen_addrs.is_empty() {
+        config.listen_addrs.push("/ip4/0.0.0.0/tcp/4001".parse()?);
+    }
+    Ok(Some(config))
+}
@@ -0,0 +1,346 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Deterministic simulation of libp2p networks.
+//!
+//! A [`Simulation`] connects many swarms in a single process over a
+//! simulated [`Network`] with a virtual clock. Every link of the network has
+//! a configurable latency, jitter, packet loss and connection loss, see
+//! [`LinkConfig`], and the virtual clock only advances when all swarms are
+//! idle, to the next point in time at which something is delivered. Hence a
+//! simulation of minutes of network traffic runs in milliseconds, and
+//! protocols can be tested for convergence under adverse conditions.
+//!
+//! The swarms and their connection tasks are polled on a single thread in a
+//! fixed order, and the
+//! behaviour of the links is drawn from a generator seeded on creation, as
+//! are the identities of the nodes. A simulation is thereby reproducible as
+//! far as the simulated behaviours are deterministic themselves.
+//!
+//! Note that only the network runs on virtual time. Timers of behaviours and
+//! protocols handlers, e.g. heartbeats or query timeouts, run on the real
+//! clock, and are waited for when nothing is in flight in the network.
+//!
+//! # Usage
+//!
+//! ```
+//! use libp2p_ping::{Ping, PingConfig, PingEvent};
+//! use libp2p_simulation::{LinkConfig, Simulation};
+//! use std::time::Duration;
+//!
+//! let mut link = LinkConfig::default();
+//! link.set_latency(Duration::from_millis(100)).set_packet_loss(0.1);
+//!
+//! let mut simulation = Simulation::new(42, link);
+//! let a = simulation.add_node(|_| Ping::new(PingConfig::new().with_keep_alive(true)));
+//! let b = simulation.add_node(|_| Ping::new(PingConfig::new().with_keep_alive(true)));
+//! simulation.connect(a, b);
+//!
+//! let pinged = simulation.run_until(Duration::from_secs(10), |_, event: PingEvent| {
+//!     event.result.is_ok()
+//! });
+//! assert!(pinged);
+//! assert!(simulation.network().now() >= Duration::from_millis(200));
+//! ```
+
+mod network;
+mod transport;
+
+pub use network::{Delay, LinkConfig, Network};
+pub use transport::{Connection, DialFuture, Listener, SimError, SimTransport};
+
+use futures::{prelude::*, stream::FuturesUnordered, task::{ArcWake, waker_ref}};
+use libp2p_core::{
+    Executor,
+    Multiaddr,
+    PeerId,
+    Transport,
+    identity,
+    multiaddr::Protocol,
+    muxing::StreamMuxerBox,
+    upgrade,
+};
+use libp2p_plaintext::PlainText2Config;
+use libp2p_swarm::{IntoProtocolsHandler, NetworkBehaviour, ProtocolsHandler, Swarm, SwarmBuilder};
+use parking_lot::Mutex;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::{
+    collections::BTreeSet,
+    io,
+    mem,
+    pin::Pin,
+    sync::{Arc, atomic::{AtomicBool, Ordering}},
+    task::{Context, Poll},
+    thread,
+    time::{Duration, Instant},
+};
+
+type HandlerInEvent<B> = <<<B as NetworkBehaviour>::ProtocolsHandler as IntoProtocolsHandler>::Handler as ProtocolsHandler>::InEvent;
+type HandlerOutEvent<B> = <<<B as NetworkBehaviour>::ProtocolsHandler as IntoProtocolsHandler>::Handler as ProtocolsHandler>::OutEvent;
+
+type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// The interval at which idle swarms are polled while waiting for timers.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Many swarms connected over a simulated [`Network`].
+///
+/// Nodes are identified by their index, in the order they have been added.
+/// Every node runs a `Swarm` of the same behaviour over a transport of the
+/// network, authenticated with plaintext and multiplexed with yamux.
+pub struct Simulation<B: NetworkBehaviour> {
+    network: Network,
+    nodes: Vec<Node<B>>,
+    /// Generates the identities of the nodes.
+    rng: StdRng,
+    /// Tasks spawned by the swarms since the last poll.
+    spawned: TaskQueue,
+    /// The connection tasks of all swarms.
+    tasks: FuturesUnordered<Task>,
+    notifier: Arc<Notifier>,
+    idle_timeout: Duration,
+}
+
+struct Node<B: NetworkBehaviour> {
+    peer_id: PeerId,
+    addr: Multiaddr,
+    swarm: Swarm<B>,
+    waker: Arc<NodeWaker>,
+}
+
+/// Executor of the swarms, queueing their tasks to be polled by the
+/// simulation instead of running them on a thread pool.
+#[derive(Clone, Default)]
+struct TaskQueue(Arc<Mutex<Vec<Task>>>);
+
+impl Executor for TaskQueue {
+    fn exec(&self, future: Task) {
+        self.0.lock().push(future);
+    }
+}
+
+/// Wakes the thread running the simulation.
+struct Notifier {
+    woken: AtomicBool,
+    /// The nodes whose swarm has been woken, polled in order.
+    ready: Mutex<BTreeSet<usize>>,
+    thread: Mutex<thread::Thread>,
+}
+
+impl ArcWake for Notifier {
+    fn wake_by_ref(this: &Arc<Self>) {
+        this.woken.store(true, Ordering::SeqCst);
+        this.thread.lock().unpark();
+    }
+}
+
+/// Wakes the swarm of a node, such that only swarms that can make progress
+/// are polled.
+struct NodeWaker {
+    index: usize,
+    notifier: Arc<Notifier>,
+}
+
+impl ArcWake for NodeWaker {
+    fn wake_by_ref(this: &Arc<Self>) {
+        this.notifier.ready.lock().insert(this.index);
+        ArcWake::wake_by_ref(&this.notifier);
+    }
+}
+
+impl<B> Simulation<B>
+where
+    B: NetworkBehaviour,
+    B::ProtocolsHandler: Send + 'static,
+    HandlerInEvent<B>: Clone + Send + 'static,
+    HandlerOutEvent<B>: Send + 'static,
+{
+    /// Creates an empty simulation whose links all behave according to
+    /// `default_link`, seeding its generators with `seed`.
+    pub fn new(seed: u64, default_link: LinkConfig) -> Self {
+        Simulation {
+            network: Network::new(seed, default_link),
+            nodes: Vec::new(),
+            rng: StdRng::seed_from_u64(seed),
+            spawned: TaskQueue::default(),
+            tasks: FuturesUnordered::new(),
+            notifier: Arc::new(Notifier {
+                woken: AtomicBool::new(false),
+                ready: Mutex::new(BTreeSet::new()),
+                thread: Mutex::new(thread::current()),
+            }),
+            idle_timeout: Duration::from_secs(2),
+        }
+    }
+
+    /// Sets how long to wait in real time for timers of the swarms when
+    /// nothing is in flight in the network, before the simulation is
+    /// considered to have come to a halt.
+    ///
+    /// Defaults to 2 seconds.
+    pub fn set_idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Adds a node running the behaviour created by the given closure from
+    /// the identity of the node, and returns its index.
+    ///
+    /// The node immediately listens on its address.
+    pub fn add_node(&mut self, behaviour: impl FnOnce(&identity::Keypair) -> B) -> usize {
+        let index = self.nodes.len();
+        let node = index as u64 + 1;
+
+        let mut secret = [0u8; 32];
+        self.rng.fill(&mut secret);
+        let secret = identity::ed25519::SecretKey::from_bytes(&mut secret)
+            .expect("32 bytes are a valid ed25519 secret key");
+        let keypair = identity::Keypair::Ed25519(secret.into());
+        let peer_id = keypair.public().into_peer_id();
+
+        let transport = self.network.transport(node)
+            .upgrade(upgrade::Version::V1)
+            .authenticate(PlainText2Config { local_public_key: keypair.public() })
+            .multiplex(libp2p_yamux::Config::default())
+            .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+            .boxed();
+        let mut swarm = SwarmBuilder::new(transport, behaviour(&keypair), peer_id.clone())
+            .executor(Box::new(self.spawned.clone()))
+            .build();
+        let addr: Multiaddr = Protocol::Memory(node).into();
+        Swarm::listen_on(&mut swarm, addr.clone()).expect("nodes can listen on their address");
+
+        let waker = Arc::new(NodeWaker { index, notifier: self.notifier.clone() });
+        self.nodes.push(Node { peer_id, addr, swarm, waker });
+        index
+    }
+
+    /// Returns the number of nodes.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns `true` if no node has been added.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the network connecting the nodes.
+    ///
+    /// Node `i` is the node `i + 1` of the network.
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    /// Returns the peer ID of a node.
+    pub fn peer_id(&self, index: usize) -> &PeerId {
+        &self.nodes[index].peer_id
+    }
+
+    /// Returns the address of a node.
+    pub fn addr(&self, index: usize) -> &Multiaddr {
+        &self.nodes[index].addr
+    }
+
+    /// Returns the swarm of a node.
+    pub fn swarm(&mut self, index: usize) -> &mut Swarm<B> {
+        &mut self.nodes[index].swarm
+    }
+
+    /// Makes node `a` dial node `b`.
+    pub fn connect(&mut self, a: usize, b: usize) {
+        let addr = self.nodes[b].addr.clone();
+        Swarm::dial_addr(&mut self.nodes[a].swarm, addr).expect("dialing a node succeeds");
+    }
+
+    /// Runs the simulation for at most `duration` of virtual time, passing
+    /// every event of a node together with its index to `f` until it returns
+    /// `true`.
+    ///
+    /// Returns `true` if `f` returned `true`, and `false` if the virtual time
+    /// has run out or the simulation came to a halt, see
+    /// [`Simulation::set_idle_timeout`].
+    pub fn run_until<F>(&mut self, duration: Duration, mut f: F) -> bool
+    where
+        F: FnMut(usize, B::OutEvent) -> bool,
+    {
+        let deadline = self.network.now() + duration;
+        *self.notifier.thread.lock() = thread::current();
+        let notifier = self.notifier.clone();
+        let waker = waker_ref(&notifier);
+        let mut cx = Context::from_waker(&waker);
+        let mut idle_since = Instant::now();
+
+        // The swarms may have been used since the last run.
+        self.notifier.ready.lock().extend(0 .. self.nodes.len());
+
+        loop {
+            // Poll the woken swarms and tasks until none of them is woken
+            // anymore.
+            loop {
+                self.notifier.woken.store(false, Ordering::SeqCst);
+                let ready = mem::take(&mut *self.notifier.ready.lock());
+                for index in ready {
+                    let node = &mut self.nodes[index];
+                    let waker = waker_ref(&node.waker);
+                    let mut cx = Context::from_waker(&waker);
+                    while let Poll::Ready(event) = node.swarm.poll_next_unpin(&mut cx) {
+                        if f(index, event.expect("swarms never terminate")) {
+                            return true
+                        }
+                    }
+                }
+                self.tasks.extend(self.spawned.0.lock().drain(..));
+                while let Poll::Ready(Some(())) = self.tasks.poll_next_unpin(&mut cx) {}
+                if !self.notifier.woken.load(Ordering::SeqCst) {
+                    break
+                }
+                idle_since = Instant::now();
+            }
+
+            match self.network.next_event_at() {
+                Some(at) if at <= deadline => {
+                    self.network.advance_to(at);
+                    idle_since = Instant::now();
+                    continue
+                }
+                Some(_) => {
+                    self.network.advance_to(deadline);
+                    return false
+                }
+                None => {}
+            }
+
+            // Nothing is in flight, wait for the timers of the swarms.
+            if idle_since.elapsed() >= self.idle_timeout {
+                self.network.advance_to(deadline);
+                return false
+            }
+            thread::park_timeout(IDLE_POLL_INTERVAL);
+        }
+    }
+
+    /// Runs the simulation for `duration` of virtual time, discarding the
+    /// events of the nodes.
+    pub fn run_for(&mut self, duration: Duration) {
+        self.run_until(duration, |_, _| false);
+    }
+}
@@ -0,0 +1,553 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::transport::{Connection, Pipe, SimError, SimTransport};
+use futures::channel::mpsc;
+use parking_lot::Mutex;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::{
+    cmp,
+    collections::{BinaryHeap, HashMap, HashSet},
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Weak},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+/// Parameters of the links between the nodes of a [`Network`].
+#[derive(Debug, Clone)]
+pub struct LinkConfig {
+    latency: Duration,
+    jitter: Duration,
+    packet_loss: f64,
+    retransmission_timeout: Duration,
+    connection_loss: f64,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        LinkConfig {
+            latency: Duration::from_millis(10),
+            jitter: Duration::from_secs(0),
+            packet_loss: 0.0,
+            retransmission_timeout: Duration::from_millis(200),
+            connection_loss: 0.0,
+        }
+    }
+}
+
+impl LinkConfig {
+    /// Sets the one-way latency of the link.
+    ///
+    /// Defaults to 10 milliseconds.
+    pub fn set_latency(&mut self, latency: Duration) -> &mut Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Sets the maximum jitter added to the latency of every transmission.
+    ///
+    /// The jitter of a transmission is drawn uniformly from `[0, jitter)`.
+    /// Connections are ordered streams, hence jitter never reorders the data
+    /// sent over a connection. Defaults to zero.
+    pub fn set_jitter(&mut self, jitter: Duration) -> &mut Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the probability with which a transmission is lost.
+    ///
+    /// Like TCP, connections retransmit lost data after the retransmission
+    /// timeout, such that a loss delays the data and everything sent after it
+    /// on the same connection. Defaults to zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the probability is not within `[0, 1)`.
+    pub fn set_packet_loss(&mut self, probability: f64) -> &mut Self {
+        assert!((0.0..1.0).contains(&probability), "packet loss must be within [0, 1)");
+        self.packet_loss = probability;
+        self
+    }
+
+    /// Sets the delay after which lost data is retransmitted.
+    ///
+    /// Defaults to 200 milliseconds.
+    pub fn set_retransmission_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.retransmission_timeout = timeout;
+        self
+    }
+
+    /// Sets the probability with which a connection attempt over the link
+    /// fails as if the remote was unreachable.
+    ///
+    /// Defaults to zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the probability is not within `[0, 1]`.
+    pub fn set_connection_loss(&mut self, probability: f64) -> &mut Self {
+        assert!((0.0..=1.0).contains(&probability), "connection loss must be within [0, 1]");
+        self.connection_loss = probability;
+        self
+    }
+}
+
+/// A simulated network connecting nodes identified by a non-zero `u64`.
+///
+/// Node `n` is reachable at `/memory/n` through the transport returned by
+/// [`Network::transport`]. Everything sent between two nodes is delivered
+/// according to the [`LinkConfig`] of their link, measured on a virtual clock
+/// that only advances through [`Network::advance_to`]. The latency, jitter
+/// and losses of the links are drawn from a generator seeded on creation, so
+/// that the same sequence of operations always results in the same schedule.
+#[derive(Clone)]
+pub struct Network {
+    inner: Arc<Mutex<Inner>>,
+}
+
+pub(crate) struct Inner {
+    /// The current virtual time, relative to the creation of the network.
+    now: Duration,
+    rng: StdRng,
+    default_link: LinkConfig,
+    /// Links deviating from the default, keyed by the ordered pair of nodes.
+    links: HashMap<(u64, u64), LinkConfig>,
+    /// Pairs of nodes that can't reach each other, in the same order.
+    partitions: HashSet<(u64, u64)>,
+    pub(crate) listeners: HashMap<u64, mpsc::UnboundedSender<Connection>>,
+    /// The pipes of the open connections, used to reset them.
+    connections: Vec<(u64, u64, ConnectionPipes)>,
+    /// The scheduled events, ordered by the time they occur at.
+    events: BinaryHeap<Event>,
+    next_sequence: u64,
+}
+
+/// The pipes of a connection in both directions.
+type ConnectionPipes = [Weak<Mutex<Pipe>>; 2];
+
+struct Event {
+    at: Duration,
+    /// Breaks ties between events occurring at the same time, such that they
+    /// are processed in the order they have been scheduled in.
+    sequence: u64,
+    action: Action,
+}
+
+enum Action {
+    Deliver { pipe: Arc<Mutex<Pipe>>, data: Vec<u8> },
+    Close { pipe: Arc<Mutex<Pipe>> },
+    Connect { dialer: u64, listener: u64, lost: bool, slot: Arc<Mutex<Slot<Result<Connection, SimError>>>> },
+    Wake { slot: Arc<Mutex<Slot<()>>> },
+}
+
+impl PartialEq for Event {
+    fn eq(&self, other: &Self) -> bool {
+        (self.at, self.sequence) == (other.at, other.sequence)
+    }
+}
+
+impl Eq for Event {}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Event {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        // Reversed, as `BinaryHeap` is a max-heap.
+        (other.at, other.sequence).cmp(&(self.at, self.sequence))
+    }
+}
+
+/// Orders a pair of nodes, as links are symmetric.
+fn link_key(a: u64, b: u64) -> (u64, u64) {
+    (cmp::min(a, b), cmp::max(a, b))
+}
+
+impl Inner {
+    fn link(&self, a: u64, b: u64) -> &LinkConfig {
+        self.links.get(&link_key(a, b)).unwrap_or(&self.default_link)
+    }
+
+    fn is_partitioned(&self, a: u64, b: u64) -> bool {
+        self.partitions.contains(&link_key(a, b))
+    }
+
+    /// Draws the delay of a transmission from `a` to `b`.
+    fn transmission_delay(&mut self, a: u64, b: u64) -> Duration {
+        let link = self.link(a, b).clone();
+        let mut delay = link.latency + link.jitter.mul_f64(self.rng.gen::<f64>());
+        while link.packet_loss > 0.0 && self.rng.gen_bool(link.packet_loss) {
+            delay += link.retransmission_timeout;
+        }
+        delay
+    }
+
+    fn schedule(&mut self, at: Duration, action: Action) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.events.push(Event { at, sequence, action });
+    }
+
+    /// Schedules an action on a pipe after all the data previously sent
+    /// through it.
+    fn transmit(&mut self, from: u64, to: u64, pipe: &Arc<Mutex<Pipe>>, action: Action) {
+        let delay = self.transmission_delay(from, to);
+        let mut pipe = pipe.lock();
+        let at = cmp::max(self.now + delay, pipe.last_delivery);
+        pipe.last_delivery = at;
+        drop(pipe);
+        self.schedule(at, action);
+    }
+
+    /// Resets all connections between `a` and `b`.
+    fn reset_connections(&mut self, a: u64, b: u64) {
+        self.connections.retain(|(x, y, pipes)| {
+            if link_key(*x, *y) != link_key(a, b) {
+                return true
+            }
+            for pipe in pipes.iter().filter_map(|p| p.upgrade()) {
+                pipe.lock().reset();
+            }
+            false
+        });
+    }
+}
+
+impl Network {
+    /// Creates a network whose links all behave according to `default_link`,
+    /// drawing from a generator seeded with `seed`.
+    pub fn new(seed: u64, default_link: LinkConfig) -> Self {
+        Network {
+            inner: Arc::new(Mutex::new(Inner {
+                now: Duration::from_secs(0),
+                rng: StdRng::seed_from_u64(seed),
+                default_link,
+                links: HashMap::new(),
+                partitions: HashSet::new(),
+                listeners: HashMap::new(),
+                connections: Vec::new(),
+                events: BinaryHeap::new(),
+                next_sequence: 0,
+            }))
+        }
+    }
+
+    /// Returns the transport of the given node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` is zero.
+    pub fn transport(&self, node: u64) -> SimTransport {
+        SimTransport::new(self.clone(), node)
+    }
+
+    /// Replaces the configuration of all links that have not been
+    /// overridden with [`Network::set_link`].
+    ///
+    /// Data already in flight is not affected.
+    pub fn set_default_link(&self, config: LinkConfig) {
+        self.inner.lock().default_link = config;
+    }
+
+    /// Overrides the configuration of the link between `a` and `b`.
+    pub fn set_link(&self, a: u64, b: u64, config: LinkConfig) {
+        self.inner.lock().links.insert(link_key(a, b), config);
+    }
+
+    /// Partitions `a` and `b` from each other, or heals the partition.
+    ///
+    /// Partitioning resets all open connections between the two nodes and
+    /// makes subsequent connection attempts fail.
+    pub fn set_partitioned(&self, a: u64, b: u64, partitioned: bool) {
+        let mut inner = self.inner.lock();
+        if partitioned {
+            inner.partitions.insert(link_key(a, b));
+            inner.reset_connections(a, b);
+        } else {
+            inner.partitions.remove(&link_key(a, b));
+        }
+    }
+
+    /// Resets all open connections between `a` and `b`.
+    pub fn disconnect(&self, a: u64, b: u64) {
+        self.inner.lock().reset_connections(a, b);
+    }
+
+    /// Returns the current virtual time, relative to the creation of the
+    /// network.
+    pub fn now(&self) -> Duration {
+        self.inner.lock().now
+    }
+
+    /// Returns the virtual time of the next scheduled event, if any.
+    pub fn next_event_at(&self) -> Option<Duration> {
+        self.inner.lock().events.peek().map(|e| e.at)
+    }
+
+    /// Advances the virtual clock to `time`, processing all events scheduled
+    /// up to then in order.
+    ///
+    /// Does nothing if `time` lies in the past.
+    pub fn advance_to(&self, time: Duration) {
+        loop {
+            let action = {
+                let mut inner = self.inner.lock();
+                match inner.events.peek() {
+                    Some(event) if event.at <= time => {}
+                    _ => {
+                        inner.now = cmp::max(inner.now, time);
+                        return
+                    }
+                }
+                let event = inner.events.pop().expect("an event has been peeked");
+                inner.now = cmp::max(inner.now, event.at);
+                event.action
+            };
+            // Executed without holding the lock, as dropping a connection
+            // locks the network.
+            self.execute(action);
+        }
+    }
+
+    /// Returns a future that completes once the virtual clock has advanced
+    /// by `duration`.
+    pub fn delay(&self, duration: Duration) -> Delay {
+        let slot = Arc::new(Mutex::new(Slot::default()));
+        let mut inner = self.inner.lock();
+        let at = inner.now + duration;
+        inner.schedule(at, Action::Wake { slot: slot.clone() });
+        Delay { slot }
+    }
+
+    pub(crate) fn downgrade(&self) -> Weak<Mutex<Inner>> {
+        Arc::downgrade(&self.inner)
+    }
+
+    pub(crate) fn upgrade(inner: &Weak<Mutex<Inner>>) -> Option<Network> {
+        inner.upgrade().map(|inner| Network { inner })
+    }
+
+    pub(crate) fn lock(&self) -> parking_lot::MutexGuard<'_, Inner> {
+        self.inner.lock()
+    }
+
+    /// Starts a connection attempt from `dialer` to `listener`, which
+    /// completes after the latency of their link.
+    pub(crate) fn connect(&self, dialer: u64, listener: u64) -> Arc<Mutex<Slot<Result<Connection, SimError>>>> {
+        let slot = Arc::new(Mutex::new(Slot::default()));
+        let mut inner = self.inner.lock();
+        let connection_loss = inner.link(dialer, listener).connection_loss;
+        let lost = connection_loss > 0.0 && inner.rng.gen_bool(connection_loss);
+        let at = inner.now + inner.transmission_delay(dialer, listener);
+        inner.schedule(at, Action::Connect { dialer, listener, lost, slot: slot.clone() });
+        slot
+    }
+
+    /// Sends data through a pipe from `from` to `to`.
+    pub(crate) fn send(&self, from: u64, to: u64, pipe: &Arc<Mutex<Pipe>>, data: Vec<u8>) {
+        self.inner.lock().transmit(from, to, pipe, Action::Deliver { pipe: pipe.clone(), data });
+    }
+
+    /// Closes a pipe from `from` to `to` after the data sent through it.
+    pub(crate) fn send_close(&self, from: u64, to: u64, pipe: &Arc<Mutex<Pipe>>) {
+        self.inner.lock().transmit(from, to, pipe, Action::Close { pipe: pipe.clone() });
+    }
+
+    fn execute(&self, action: Action) {
+        match action {
+            Action::Deliver { pipe, data } => pipe.lock().deliver(data),
+            Action::Close { pipe } => pipe.lock().close(),
+            Action::Connect { dialer, listener, lost, slot } => {
+                let result = if lost { Err(SimError::Unreachable) } else { self.accept(dialer, listener) };
+                slot.lock().complete(result);
+            }
+            Action::Wake { slot } => slot.lock().complete(()),
+        }
+    }
+
+    /// Hands a new connection to the listener of `listener`.
+    fn accept(&self, dialer: u64, listener: u64) -> Result<Connection, SimError> {
+        let sender = {
+            let inner = self.inner.lock();
+            if inner.is_partitioned(dialer, listener) {
+                return Err(SimError::Unreachable)
+            }
+            inner.listeners.get(&listener).cloned().ok_or(SimError::Unreachable)?
+        };
+        let (dialer_end, listener_end) = Connection::pair(self, dialer, listener);
+        sender.unbounded_send(listener_end).map_err(|_| SimError::Unreachable)?;
+        Ok(dialer_end)
+    }
+
+    /// Registers the pipes of a new connection, such that it can be reset.
+    pub(crate) fn register(&self, a: u64, b: u64, one: &Arc<Mutex<Pipe>>, two: &Arc<Mutex<Pipe>>) {
+        let mut inner = self.inner.lock();
+        inner.connections.retain(|(_, _, pipes)| pipes.iter().any(|p| p.strong_count() > 0));
+        inner.connections.push((a, b, [Arc::downgrade(one), Arc::downgrade(two)]));
+    }
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.lock();
+        f.debug_struct("Network")
+            .field("now", &inner.now)
+            .field("events", &inner.events.len())
+            .finish()
+    }
+}
+
+/// A value that is completed by the network at some virtual time.
+pub(crate) struct Slot<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+}
+
+impl<T> Default for Slot<T> {
+    fn default() -> Self {
+        Slot { value: None, waker: None }
+    }
+}
+
+impl<T> Slot<T> {
+    fn complete(&mut self, value: T) {
+        self.value = Some(value);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    pub(crate) fn poll_take(&mut self, cx: &mut Context<'_>) -> Poll<T> {
+        match self.value.take() {
+            Some(value) => Poll::Ready(value),
+            None => {
+                self.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// A future completing at a point in virtual time, see [`Network::delay`].
+pub struct Delay {
+    slot: Arc<Mutex<Slot<()>>>,
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.slot.lock().poll_take(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, prelude::*};
+    use libp2p_core::{Transport, multiaddr::Protocol};
+
+    /// Sends ten messages from node 1 to node 2 and returns the virtual time
+    /// at which each of them arrived.
+    fn arrival_times(seed: u64) -> Vec<Duration> {
+        let mut link = LinkConfig::default();
+        link.set_latency(Duration::from_millis(50))
+            .set_jitter(Duration::from_millis(20))
+            .set_packet_loss(0.2);
+        let network = Network::new(seed, link);
+
+        let mut listener = network.transport(2).listen_on(Protocol::Memory(2).into()).unwrap();
+        let mut dial = network.transport(1).dial(Protocol::Memory(2).into()).unwrap();
+        network.advance_to(network.next_event_at().unwrap());
+        let mut dialer = block_on(&mut dial).unwrap();
+        let mut accepted = block_on(async {
+            loop {
+                if let Some(upgrade) = listener.next().await.unwrap().unwrap().into_upgrade() {
+                    break upgrade.0.await.unwrap()
+                }
+            }
+        });
+
+        let mut times = Vec::new();
+        for i in 0 .. 10u8 {
+            block_on(dialer.write_all(&[i])).unwrap();
+        }
+        while let Some(at) = network.next_event_at() {
+            network.advance_to(at);
+            let mut buf = [0; 1];
+            while let Some(Ok(1)) = accepted.read(&mut buf).now_or_never() {
+                assert_eq!(buf[0] as usize, times.len(), "data is delivered in order");
+                times.push(network.now());
+            }
+        }
+        times
+    }
+
+    #[test]
+    fn delivery_is_delayed_and_reproducible() {
+        let times = arrival_times(1);
+        assert_eq!(times.len(), 10);
+        assert!(times.iter().all(|t| *t >= Duration::from_millis(100)));
+        assert_eq!(times, arrival_times(1));
+    }
+
+    #[test]
+    fn partition_resets_connections() {
+        let network = Network::new(0, LinkConfig::default());
+        let _listener = network.transport(2).listen_on(Protocol::Memory(2).into()).unwrap();
+        let mut dial = network.transport(1).dial(Protocol::Memory(2).into()).unwrap();
+        network.advance_to(Duration::from_secs(1));
+        let mut connection = block_on(&mut dial).unwrap();
+
+        network.set_partitioned(1, 2, true);
+        assert!(block_on(connection.write_all(b"hello")).is_err());
+
+        let mut dial = network.transport(1).dial(Protocol::Memory(2).into()).unwrap();
+        network.advance_to(Duration::from_secs(2));
+        assert!(block_on(&mut dial).is_err());
+    }
+
+    #[test]
+    fn lost_connection_attempts_fail() {
+        let mut link = LinkConfig::default();
+        link.set_connection_loss(1.0);
+        let network = Network::new(0, link);
+        let _listener = network.transport(2).listen_on(Protocol::Memory(2).into()).unwrap();
+        let mut dial = network.transport(1).dial(Protocol::Memory(2).into()).unwrap();
+        network.advance_to(Duration::from_secs(1));
+        assert!(block_on(&mut dial).is_err());
+    }
+
+    #[test]
+    fn delay_completes_at_virtual_time() {
+        let network = Network::new(0, LinkConfig::default());
+        let mut delay = network.delay(Duration::from_secs(5));
+        network.advance_to(Duration::from_secs(4));
+        assert!((&mut delay).now_or_never().is_none());
+        network.advance_to(Duration::from_secs(5));
+        assert!(delay.now_or_never().is_some());
+    }
+}
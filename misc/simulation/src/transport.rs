@@ -0,0 +1,339 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::network::{Inner, Network, Slot};
+use futures::{channel::mpsc, future::{self, Ready}, prelude::*};
+use libp2p_core::{
+    Transport,
+    multiaddr::{Multiaddr, Protocol},
+    transport::{ListenerEvent, TransportError},
+};
+use parking_lot::Mutex;
+use std::{
+    collections::{VecDeque, hash_map::Entry},
+    error,
+    fmt,
+    io,
+    pin::Pin,
+    sync::{Arc, Weak},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+/// Transport of a node of a simulated [`Network`], supporting `/memory/N`
+/// multiaddresses.
+///
+/// A node can only listen on its own address, or on `/memory/0` which is
+/// equivalent.
+#[derive(Debug, Clone)]
+pub struct SimTransport {
+    network: Network,
+    node: u64,
+}
+
+impl SimTransport {
+    pub(crate) fn new(network: Network, node: u64) -> Self {
+        assert_ne!(node, 0, "node 0 is not a valid address");
+        SimTransport { network, node }
+    }
+}
+
+impl Transport for SimTransport {
+    type Output = Connection;
+    type Error = SimError;
+    type Listener = Listener;
+    type ListenerUpgrade = Ready<Result<Self::Output, Self::Error>>;
+    type Dial = DialFuture;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        match parse_memory_addr(&addr) {
+            Some(port) if port == 0 || port == self.node => {}
+            _ => return Err(TransportError::MultiaddrNotSupported(addr)),
+        }
+
+        let (tx, rx) = mpsc::unbounded();
+        match self.network.lock().listeners.entry(self.node) {
+            Entry::Occupied(_) => return Err(TransportError::Other(SimError::AlreadyInUse)),
+            Entry::Vacant(e) => e.insert(tx),
+        };
+
+        Ok(Listener {
+            addr: Protocol::Memory(self.node).into(),
+            network: self.network,
+            node: self.node,
+            receiver: rx,
+            tell_listen_addr: true,
+        })
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<DialFuture, TransportError<Self::Error>> {
+        let listener = match parse_memory_addr(&addr) {
+            Some(0) => return Err(TransportError::Other(SimError::Unreachable)),
+            Some(port) => port,
+            None => return Err(TransportError::MultiaddrNotSupported(addr)),
+        };
+        Ok(DialFuture { slot: self.network.connect(self.node, listener) })
+    }
+}
+
+/// If the address is `/memory/n`, returns the value of `n`.
+fn parse_memory_addr(a: &Multiaddr) -> Option<u64> {
+    let mut iter = a.iter();
+    match (iter.next(), iter.next()) {
+        (Some(Protocol::Memory(port)), None) => Some(port),
+        _ => None,
+    }
+}
+
+/// Error that can be produced from the `SimTransport`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SimError {
+    /// There's no listener on the given node, the nodes are partitioned or
+    /// the connection attempt has been lost.
+    Unreachable,
+    /// The node is already listening.
+    AlreadyInUse,
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimError::Unreachable => write!(f, "Node unreachable."),
+            SimError::AlreadyInUse => write!(f, "Node already listening."),
+        }
+    }
+}
+
+impl error::Error for SimError {}
+
+/// Connection to a node of a simulated network currently being opened.
+pub struct DialFuture {
+    slot: Arc<Mutex<Slot<Result<Connection, SimError>>>>,
+}
+
+impl Future for DialFuture {
+    type Output = Result<Connection, SimError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.slot.lock().poll_take(cx)
+    }
+}
+
+/// Listener for connections of a simulated network.
+pub struct Listener {
+    network: Network,
+    node: u64,
+    addr: Multiaddr,
+    receiver: mpsc::UnboundedReceiver<Connection>,
+    /// Generate `ListenerEvent::NewAddress` to inform about our listen address.
+    tell_listen_addr: bool,
+}
+
+impl Stream for Listener {
+    type Item = Result<ListenerEvent<Ready<Result<Connection, SimError>>, SimError>, SimError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.tell_listen_addr {
+            self.tell_listen_addr = false;
+            return Poll::Ready(Some(Ok(ListenerEvent::NewAddress(self.addr.clone()))))
+        }
+
+        let connection = match Stream::poll_next(Pin::new(&mut self.receiver), cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(None) => panic!("Alive listeners always have a sender."),
+            Poll::Ready(Some(c)) => c,
+        };
+
+        let event = ListenerEvent::Upgrade {
+            local_addr: self.addr.clone(),
+            remote_addr: Protocol::Memory(connection.remote).into(),
+            upgrade: future::ready(Ok(connection)),
+        };
+
+        Poll::Ready(Some(Ok(event)))
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.network.lock().listeners.remove(&self.node);
+    }
+}
+
+/// One direction of a connection.
+pub(crate) struct Pipe {
+    buffer: VecDeque<u8>,
+    /// The writing side has closed the pipe, reads return EOF once the
+    /// buffer is drained.
+    closed: bool,
+    /// The connection has been reset, reads and writes fail.
+    reset: bool,
+    reader: Option<Waker>,
+    /// The virtual time of the last delivery scheduled on this pipe.
+    pub(crate) last_delivery: Duration,
+}
+
+impl Pipe {
+    fn new() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Pipe {
+            buffer: VecDeque::new(),
+            closed: false,
+            reset: false,
+            reader: None,
+            last_delivery: Duration::from_secs(0),
+        }))
+    }
+
+    fn wake_reader(&mut self) {
+        if let Some(waker) = self.reader.take() {
+            waker.wake();
+        }
+    }
+
+    pub(crate) fn deliver(&mut self, data: Vec<u8>) {
+        if !self.reset {
+            self.buffer.extend(data);
+            self.wake_reader();
+        }
+    }
+
+    pub(crate) fn close(&mut self) {
+        self.closed = true;
+        self.wake_reader();
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.reset = true;
+        self.buffer.clear();
+        self.wake_reader();
+    }
+}
+
+/// An established connection between two nodes of a simulated network.
+///
+/// Implements `AsyncRead` and `AsyncWrite`. Writes never block, the written
+/// data is delivered to the remote once the virtual clock has advanced by the
+/// delay of the link.
+pub struct Connection {
+    network: Weak<Mutex<Inner>>,
+    local: u64,
+    remote: u64,
+    incoming: Arc<Mutex<Pipe>>,
+    outgoing: Arc<Mutex<Pipe>>,
+    write_closed: bool,
+}
+
+impl Connection {
+    /// Creates both ends of a connection between `a` and `b`.
+    pub(crate) fn pair(network: &Network, a: u64, b: u64) -> (Connection, Connection) {
+        let (a_to_b, b_to_a) = (Pipe::new(), Pipe::new());
+        network.register(a, b, &a_to_b, &b_to_a);
+        let a_end = Connection {
+            network: network.downgrade(),
+            local: a,
+            remote: b,
+            incoming: b_to_a.clone(),
+            outgoing: a_to_b.clone(),
+            write_closed: false,
+        };
+        let b_end = Connection {
+            network: network.downgrade(),
+            local: b,
+            remote: a,
+            incoming: a_to_b,
+            outgoing: b_to_a,
+            write_closed: false,
+        };
+        (a_end, b_end)
+    }
+
+    /// Returns the network of the connection, or an error if it is gone.
+    fn network(&self) -> io::Result<Network> {
+        Network::upgrade(&self.network)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "network dropped"))
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let mut pipe = self.incoming.lock();
+        if pipe.reset {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()))
+        }
+        if pipe.buffer.is_empty() {
+            if pipe.closed {
+                return Poll::Ready(Ok(0))
+            }
+            pipe.reader = Some(cx.waker().clone());
+            return Poll::Pending
+        }
+        let len = std::cmp::min(buf.len(), pipe.buffer.len());
+        for (dst, src) in buf.iter_mut().zip(pipe.buffer.drain(.. len)) {
+            *dst = src;
+        }
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if self.write_closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
+        }
+        if self.outgoing.lock().reset {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()))
+        }
+        self.network()?.send(self.local, self.remote, &self.outgoing, buf.to_vec());
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if !self.write_closed {
+            self.write_closed = true;
+            self.network()?.send_close(self.local, self.remote, &self.outgoing);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if !self.write_closed {
+            if let Ok(network) = self.network() {
+                network.send_close(self.local, self.remote, &self.outgoing);
+            }
+        }
+        // Nobody reads anymore, hence writes of the remote fail.
+        self.incoming.lock().reset();
+    }
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("local", &self.local)
+            .field("remote", &self.remote)
+            .finish()
+    }
+}
@@ -20,7 +20,7 @@
 
 mod codec;
 
-use std::{cmp, iter, mem, pin::Pin, task::Context, task::Poll};
+use std::{cmp, collections::VecDeque, iter, mem, pin::Pin, task::Context, task::Poll};
 use std::io::{Error as IoError, ErrorKind as IoErrorKind};
 use std::sync::Arc;
 use std::task::Waker;
@@ -45,6 +45,8 @@ pub struct MplexConfig {
     max_substreams: usize,
     /// Maximum number of elements in the internal buffer.
     max_buffer_len: usize,
+    /// Maximum number of elements in the internal buffer belonging to a single substream.
+    max_substream_buffer_len: usize,
     /// Behaviour when the buffer size limit is reached.
     max_buffer_behaviour: MaxBufferBehaviour,
     /// When sending data, split it into frames whose maximum size is this value
@@ -58,8 +60,10 @@ impl MplexConfig {
         Default::default()
     }
 
-    /// Sets the maximum number of simultaneously opened substreams, after which an error is
-    /// generated and the connection closes.
+    /// Sets the maximum number of simultaneously opened substreams.
+    ///
+    /// Once reached, an inbound substream generates an error and the connection closes,
+    /// whereas opening an outbound substream fails.
     ///
     /// A limit is necessary in order to avoid DoS attacks.
     pub fn max_substreams(&mut self, max: usize) -> &mut Self {
@@ -75,6 +79,16 @@ impl MplexConfig {
         self
     }
 
+    /// Sets the maximum number of pending incoming messages of a single substream.
+    ///
+    /// This prevents a single slow substream from occupying the whole buffer
+    /// shared by all substreams. The limit defaults to the maximum length of
+    /// the whole buffer, i.e. it is disabled.
+    pub fn max_substream_buffer_len(&mut self, max: usize) -> &mut Self {
+        self.max_substream_buffer_len = max;
+        self
+    }
+
     /// Sets the behaviour when the maximum buffer length has been reached.
     ///
     /// See the documentation of `MaxBufferBehaviour`.
@@ -103,6 +117,9 @@ impl MplexConfig {
                 config: self,
                 buffer: Vec::with_capacity(cmp::min(max_buffer_len, 512)),
                 opened_substreams: Default::default(),
+                reset_substreams: Default::default(),
+                blocked_substream: None,
+                pending_frames: VecDeque::new(),
                 next_outbound_stream_id: 0,
                 notifier_read: Arc::new(Notifier {
                     to_wake: Mutex::new(Default::default()),
@@ -121,13 +138,15 @@ impl Default for MplexConfig {
         MplexConfig {
             max_substreams: 128,
             max_buffer_len: 4096,
+            max_substream_buffer_len: 4096,
             max_buffer_behaviour: MaxBufferBehaviour::CloseAll,
             split_send_size: 1024,
         }
     }
 }
 
-/// Behaviour when the maximum length of the buffer is reached.
+/// Behaviour when the maximum length of the buffer, or of the buffer of a
+/// single substream, is reached.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum MaxBufferBehaviour {
     /// Produce an error on all the substreams.
@@ -137,6 +156,10 @@ pub enum MaxBufferBehaviour {
     /// This can potentially introduce a deadlock if you are waiting for a message from a substream
     /// before processing the messages received on another substream.
     Block,
+    /// Reset the substream whose message does not fit into the buffer, discarding
+    /// its buffered messages. Reading from or writing to a reset substream
+    /// produces an error of kind `ConnectionReset`.
+    ResetStream,
 }
 
 impl UpgradeInfo for MplexConfig {
@@ -197,6 +220,13 @@ struct MultiplexInner<C> {
     // The `Endpoint` value denotes who initiated the substream from our point of view
     // (see note [StreamId]).
     opened_substreams: FnvHashSet<(u32, Endpoint)>,
+    // List of Ids of substreams that have been reset locally because their buffer was full,
+    // but whose `Substream` has not been destroyed yet.
+    reset_substreams: FnvHashSet<(u32, Endpoint)>,
+    // Substream whose buffer exceeds the maximum length, if the behaviour is `Block`.
+    blocked_substream: Option<(u32, Endpoint)>,
+    // Frames generated internally, e.g. `Reset`, that still need to be sent.
+    pending_frames: VecDeque<codec::Elem>,
     // Id of the next outgoing substream.
     next_outbound_stream_id: u32,
     /// List of wakers to wake when a read event happens on the underlying stream.
@@ -257,8 +287,9 @@ where C: AsyncRead + AsyncWrite + Unpin,
     if let Some((offset, out)) = inner.buffer.iter().enumerate().filter_map(|(n, v)| filter(v).map(|v| (n, v))).next() {
         // Found a matching entry in the existing buffer!
 
-        // The buffer was full and no longer is, so let's notify everything.
-        if inner.buffer.len() == inner.config.max_buffer_len {
+        // The buffer was full and no longer is, or a substream may no longer
+        // block reading, so let's notify everything.
+        if inner.buffer.len() == inner.config.max_buffer_len || inner.blocked_substream.is_some() {
             ArcWake::wake_by_ref(&inner.notifier_read);
         }
 
@@ -267,6 +298,20 @@ where C: AsyncRead + AsyncWrite + Unpin,
     }
 
     loop {
+        // Send the frames generated internally, if any.
+        if let Err(err) = send_pending_frames(inner) {
+            return Poll::Ready(Err(err));
+        }
+
+        // Check if the buffer of a blocked substream has been drained.
+        if let Some(blocked) = inner.blocked_substream {
+            if buffered_len(inner, blocked) > inner.config.max_substream_buffer_len {
+                inner.notifier_read.insert(cx.waker());
+                return Poll::Pending
+            }
+            inner.blocked_substream = None;
+        }
+
         // Check if we reached max buffer length first.
         debug_assert!(inner.buffer.len() <= inner.config.max_buffer_len);
         if inner.buffer.len() == inner.config.max_buffer_len {
@@ -280,6 +325,8 @@ where C: AsyncRead + AsyncWrite + Unpin,
                     inner.notifier_read.insert(cx.waker());
                     return Poll::Pending
                 },
+                // Substreams are reset when a message does not fit into the buffer.
+                MaxBufferBehaviour::ResetStream => {},
             }
         }
 
@@ -304,9 +351,15 @@ where C: AsyncRead + AsyncWrite + Unpin,
                     debug!("Received open message for substream {} which was already open", substream_id)
                 }
             }
-            codec::Elem::Close { substream_id, endpoint, .. } | codec::Elem::Reset { substream_id, endpoint, .. } => {
+            codec::Elem::Close { substream_id, endpoint, .. } => {
                 inner.opened_substreams.remove(&(substream_id, !endpoint));
             }
+            codec::Elem::Reset { substream_id, endpoint, .. } => {
+                if inner.opened_substreams.contains(&(substream_id, !endpoint)) {
+                    debug!("Substream {:?} has been reset by the remote", (substream_id, !endpoint));
+                    discard_substream(inner, (substream_id, !endpoint), false);
+                }
+            }
             _ => ()
         }
 
@@ -315,7 +368,28 @@ where C: AsyncRead + AsyncWrite + Unpin,
         } else {
             let endpoint = elem.endpoint().unwrap_or(Endpoint::Dialer);
             if inner.opened_substreams.contains(&(elem.substream_id(), !endpoint)) || elem.is_open_msg() {
-                inner.buffer.push(elem);
+                // See note [StreamId].
+                let id = (elem.substream_id(), elem.endpoint().map_or(Endpoint::Listener, |e| !e));
+                if inner.buffer.len() == inner.config.max_buffer_len {
+                    debug_assert_eq!(inner.config.max_buffer_behaviour, MaxBufferBehaviour::ResetStream);
+                    debug!("Resetting substream {:?}; reached maximum buffer length", id);
+                    reset_substream(inner, id, elem.is_open_msg());
+                } else if buffered_len(inner, id) >= inner.config.max_substream_buffer_len {
+                    debug!("Reached mplex maximum buffer length of substream {:?}", id);
+                    match inner.config.max_buffer_behaviour {
+                        MaxBufferBehaviour::CloseAll => {
+                            inner.error = Err(IoError::new(IoErrorKind::Other, "reached maximum substream buffer length"));
+                            return Poll::Ready(Err(IoError::new(IoErrorKind::Other, "reached maximum substream buffer length")));
+                        },
+                        MaxBufferBehaviour::Block => {
+                            inner.buffer.push(elem);
+                            inner.blocked_substream = Some(id);
+                        },
+                        MaxBufferBehaviour::ResetStream => reset_substream(inner, id, elem.is_open_msg()),
+                    }
+                } else {
+                    inner.buffer.push(elem);
+                }
             } else if !elem.is_close_or_reset_msg() {
                 debug!("Ignored message {:?} because the substream wasn't open", elem);
             }
@@ -323,11 +397,77 @@ where C: AsyncRead + AsyncWrite + Unpin,
     }
 }
 
+/// Returns the number of buffered messages belonging to the given substream.
+fn buffered_len<C>(inner: &MultiplexInner<C>, (num, endpoint): (u32, Endpoint)) -> usize {
+    inner.buffer.iter()
+        .filter(|elem| elem.substream_id() == num && elem.endpoint().map_or(Endpoint::Listener, |e| !e) == endpoint)
+        .count()
+}
+
+/// Resets a substream whose messages do not fit into the buffer, discarding its
+/// buffered messages and scheduling a `Reset` frame to be sent to the remote.
+///
+/// `opening` denotes whether the message that does not fit is the `Open` message of the substream.
+fn reset_substream<C>(inner: &mut MultiplexInner<C>, (num, endpoint): (u32, Endpoint), opening: bool) {
+    discard_substream(inner, (num, endpoint), opening);
+    inner.pending_frames.push_back(codec::Elem::Reset { substream_id: num, endpoint });
+}
+
+/// Discards the buffered messages of a substream that has been reset, such that
+/// further attempts to read from or write to the substream produce an error.
+fn discard_substream<C>(inner: &mut MultiplexInner<C>, (num, endpoint): (u32, Endpoint), opening: bool) {
+    // The substream has not been accepted yet if its `Open` message is still buffered.
+    let accepted = !opening && (endpoint == Endpoint::Dialer
+        || !inner.buffer.iter().any(|elem| elem.is_open_msg() && elem.substream_id() == num));
+    inner.buffer.retain(|elem| {
+        elem.substream_id() != num || elem.endpoint() == Some(endpoint)
+    });
+    inner.opened_substreams.remove(&(num, endpoint));
+    if accepted {
+        inner.reset_substreams.insert((num, endpoint));
+    }
+    ArcWake::wake_by_ref(&inner.notifier_read);
+}
+
+/// Sends as many of the internally generated frames as possible without blocking.
+fn send_pending_frames<C>(inner: &mut MultiplexInner<C>) -> Result<(), IoError>
+where C: AsyncRead + AsyncWrite + Unpin
+{
+    if inner.pending_frames.is_empty() || inner.is_shutdown {
+        return Ok(())
+    }
+
+    let waker = waker_ref(&inner.notifier_write);
+    let mut cx = Context::from_waker(&waker);
+    while !inner.pending_frames.is_empty() {
+        match Sink::poll_ready(Pin::new(&mut inner.inner), &mut cx) {
+            Poll::Ready(Ok(())) => {
+                let elem = inner.pending_frames.pop_front().expect("not empty");
+                Sink::start_send(Pin::new(&mut inner.inner), elem)?;
+            },
+            Poll::Pending => return Ok(()),
+            Poll::Ready(Err(err)) => {
+                inner.error = Err(IoError::new(err.kind(), err.to_string()));
+                return Err(err)
+            }
+        }
+    }
+
+    match Sink::poll_flush(Pin::new(&mut inner.inner), &mut cx) {
+        Poll::Ready(Err(err)) => {
+            inner.error = Err(IoError::new(err.kind(), err.to_string()));
+            Err(err)
+        },
+        _ => Ok(())
+    }
+}
+
 // Small convenience function that tries to write `elem` to the stream.
 fn poll_send<C>(inner: &mut MultiplexInner<C>, cx: &mut Context<'_>, elem: codec::Elem) -> Poll<Result<(), IoError>>
 where C: AsyncRead + AsyncWrite + Unpin
 {
     ensure_no_error_no_close(inner)?;
+    send_pending_frames(inner)?;
 
     inner.notifier_write.insert(cx.waker());
 
@@ -422,6 +562,15 @@ where C: AsyncRead + AsyncWrite + Unpin
 
             let polling = match substream.state {
                 OutboundSubstreamState::SendElem(ref elem) => {
+                    // The substream has already been inserted by `open_outbound`.
+                    if inner.opened_substreams.len() > inner.config.max_substreams {
+                        debug!("Refused outbound substream; reached maximum number of substreams {}",
+                            inner.config.max_substreams);
+                        inner.opened_substreams.remove(&(substream.num, Endpoint::Dialer));
+                        substream.state = OutboundSubstreamState::Done;
+                        return Poll::Ready(Err(IoError::new(IoErrorKind::ConnectionRefused,
+                            "exceeded maximum number of open substreams")));
+                    }
                     poll_send(&mut inner, cx, elem.clone())
                 },
                 OutboundSubstreamState::Flush => {
@@ -491,6 +640,9 @@ where C: AsyncRead + AsyncWrite + Unpin
 
             // Try to find a packet of data in the buffer.
             let mut inner = self.inner.lock();
+            if inner.reset_substreams.contains(&(substream.num, substream.endpoint)) {
+                return Poll::Ready(Err(IoErrorKind::ConnectionReset.into()));
+            }
             let next_data_poll = next_match(&mut inner, cx, |elem| {
                 match elem {
                     codec::Elem::Data { substream_id, endpoint, data, .. }
@@ -518,8 +670,10 @@ where C: AsyncRead + AsyncWrite + Unpin
                 },
                 Poll::Pending => {
                     // There was no data packet in the buffer about this substream; maybe it's
-                    // because it has been closed.
-                    if inner.opened_substreams.contains(&(substream.num, substream.endpoint)) {
+                    // because it has been closed or reset.
+                    if inner.reset_substreams.contains(&(substream.num, substream.endpoint)) {
+                        return Poll::Ready(Err(IoErrorKind::ConnectionReset.into()))
+                    } else if inner.opened_substreams.contains(&(substream.num, substream.endpoint)) {
                         return Poll::Pending
                     } else {
                         return Poll::Ready(Ok(0))
@@ -535,6 +689,9 @@ where C: AsyncRead + AsyncWrite + Unpin
         }
 
         let mut inner = self.inner.lock();
+        if inner.reset_substreams.contains(&(substream.num, substream.endpoint)) {
+            return Poll::Ready(Err(IoErrorKind::ConnectionReset.into()));
+        }
 
         let to_write = cmp::min(buf.len(), inner.config.split_send_size);
 
@@ -582,9 +739,22 @@ where C: AsyncRead + AsyncWrite + Unpin
     }
 
     fn destroy_substream(&self, sub: Self::Substream) {
-        self.inner.lock().buffer.retain(|elem| {
+        let mut inner = self.inner.lock();
+        let was_reset = inner.reset_substreams.remove(&(sub.num, sub.endpoint));
+        inner.buffer.retain(|elem| {
             elem.substream_id() != sub.num || elem.endpoint() == Some(sub.endpoint)
-        })
+        });
+        // A substream that is dropped before both sides have been closed is reset,
+        // informing the remote that no more data is read or written.
+        if !was_reset && (sub.local_open || sub.remote_open) && inner.error.is_ok() {
+            debug!("Resetting substream {} on drop", sub.num);
+            inner.opened_substreams.remove(&(sub.num, sub.endpoint));
+            inner.pending_frames.push_back(codec::Elem::Reset { substream_id: sub.num, endpoint: sub.endpoint });
+            let _ = send_pending_frames(&mut inner);
+        }
+        if inner.blocked_substream == Some((sub.num, sub.endpoint)) {
+            ArcWake::wake_by_ref(&inner.notifier_read);
+        }
     }
 
     fn close(&self, cx: &mut Context<'_>) -> Poll<Result<(), IoError>> {
@@ -0,0 +1,148 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use libp2p_core::{muxing, upgrade, StreamMuxer, Transport};
+use libp2p_mplex::{MaxBufferBehaviour, MplexConfig};
+use libp2p_tcp::TcpConfig;
+use futures::{channel::oneshot, future, prelude::*};
+use std::{io, sync::Arc};
+
+#[test]
+fn reset_substream_exceeding_buffer() {
+    // A substream that is not read from is reset once its buffer is full,
+    // while other substreams remain usable.
+
+    let (tx, rx) = oneshot::channel();
+
+    let bg_thread = async_std::task::spawn(async move {
+        let mut mplex = MplexConfig::new();
+        mplex.max_substream_buffer_len(2)
+            .max_buffer_len_behaviour(MaxBufferBehaviour::ResetStream);
+
+        let transport = TcpConfig::new().and_then(move |c, e|
+            upgrade::apply(c, mplex, e, upgrade::Version::V1));
+
+        let mut listener = transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let addr = listener.next().await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        tx.send(addr).unwrap();
+
+        let client = Arc::new(listener
+            .next().await
+            .unwrap()
+            .unwrap()
+            .into_upgrade().unwrap().0.await.unwrap());
+
+        let mut inbound = Vec::new();
+        while inbound.len() < 2 {
+            if let Some(s) = muxing::event_from_ref_and_wrap(client.clone()).await.unwrap()
+                .into_inbound_substream() {
+                inbound.push(s);
+            }
+        }
+        let mut slow = inbound.remove(0);
+        let mut fast = inbound.remove(0);
+
+        let mut buf = Vec::new();
+        fast.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hello world");
+
+        let err = slow.read(&mut [0; 16]).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+    });
+
+    async_std::task::block_on(async {
+        let mut mplex = MplexConfig::new();
+        mplex.split_send_size(1);
+        let transport = TcpConfig::new().and_then(move |c, e|
+            upgrade::apply(c, mplex, e, upgrade::Version::V1));
+
+        let client = Arc::new(transport.dial(rx.await.unwrap()).unwrap().await.unwrap());
+
+        let mut slow = muxing::outbound_from_ref_and_wrap(client.clone()).await.unwrap();
+        slow.write_all(b"hello").await.unwrap();
+        slow.flush().await.unwrap();
+
+        let mut fast = muxing::outbound_from_ref_and_wrap(client.clone()).await.unwrap();
+        fast.write_all(b"hello world").await.unwrap();
+        fast.close().await.unwrap();
+
+        bg_thread.await;
+    });
+}
+
+#[test]
+fn max_outbound_substreams() {
+    let (tx, rx) = oneshot::channel();
+    let (done_tx, done_rx) = oneshot::channel::<()>();
+
+    let bg_thread = async_std::task::spawn(async move {
+        let transport = TcpConfig::new().and_then(move |c, e|
+            upgrade::apply(c, MplexConfig::new(), e, upgrade::Version::V1));
+
+        let mut listener = transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let addr = listener.next().await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        tx.send(addr).unwrap();
+
+        let client = listener
+            .next().await
+            .unwrap()
+            .unwrap()
+            .into_upgrade().unwrap().0.await.unwrap();
+
+        // Flush the confirmation of the protocol negotiation.
+        future::poll_fn(|cx| client.flush_all(cx)).await.unwrap();
+
+        let _ = done_rx.await;
+    });
+
+    async_std::task::block_on(async {
+        let mut mplex = MplexConfig::new();
+        mplex.max_substreams(2);
+        let transport = TcpConfig::new().and_then(move |c, e|
+            upgrade::apply(c, mplex, e, upgrade::Version::V1));
+
+        let client = Arc::new(transport.dial(rx.await.unwrap()).unwrap().await.unwrap());
+
+        let _s1 = muxing::outbound_from_ref_and_wrap(client.clone()).await.unwrap();
+        let _s2 = muxing::outbound_from_ref_and_wrap(client.clone()).await.unwrap();
+        let err = muxing::outbound_from_ref_and_wrap(client.clone()).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+
+        done_tx.send(()).unwrap();
+        bg_thread.await;
+    });
+}
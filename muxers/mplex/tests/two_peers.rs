@@ -134,3 +134,124 @@ fn client_to_server_inbound() {
         bg_thread.await;
     });
 }
+
+#[test]
+fn half_close() {
+    // The writing side of a substream can be closed while still reading the reply.
+
+    let (tx, rx) = oneshot::channel();
+
+    let bg_thread = async_std::task::spawn(async move {
+        let mplex = libp2p_mplex::MplexConfig::new();
+
+        let transport = TcpConfig::new().and_then(move |c, e|
+            upgrade::apply(c, mplex, e, upgrade::Version::V1));
+
+        let mut listener = transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let addr = listener.next().await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        tx.send(addr).unwrap();
+
+        let client = Arc::new(listener
+            .next().await
+            .unwrap()
+            .unwrap()
+            .into_upgrade().unwrap().0.await.unwrap());
+
+        let mut inbound = loop {
+            if let Some(s) = muxing::event_from_ref_and_wrap(client.clone()).await.unwrap()
+                .into_inbound_substream() {
+                break s;
+            }
+        };
+
+        let mut buf = Vec::new();
+        inbound.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"ping");
+        inbound.write_all(b"pong").await.unwrap();
+        inbound.close().await.unwrap();
+    });
+
+    async_std::task::block_on(async {
+        let mplex = libp2p_mplex::MplexConfig::new();
+        let transport = TcpConfig::new().and_then(move |c, e|
+            upgrade::apply(c, mplex, e, upgrade::Version::V1));
+
+        let client = Arc::new(transport.dial(rx.await.unwrap()).unwrap().await.unwrap());
+        let mut outbound = muxing::outbound_from_ref_and_wrap(client).await.unwrap();
+        outbound.write_all(b"ping").await.unwrap();
+        outbound.close().await.unwrap();
+
+        let mut buf = Vec::new();
+        outbound.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"pong");
+
+        bg_thread.await;
+    });
+}
+
+#[test]
+fn reset_on_drop() {
+    // Dropping a substream that is not closed resets it.
+
+    let (tx, rx) = oneshot::channel();
+
+    let bg_thread = async_std::task::spawn(async move {
+        let mplex = libp2p_mplex::MplexConfig::new();
+
+        let transport = TcpConfig::new().and_then(move |c, e|
+            upgrade::apply(c, mplex, e, upgrade::Version::V1));
+
+        let mut listener = transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let addr = listener.next().await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        tx.send(addr).unwrap();
+
+        let client = Arc::new(listener
+            .next().await
+            .unwrap()
+            .unwrap()
+            .into_upgrade().unwrap().0.await.unwrap());
+
+        let mut inbound = loop {
+            if let Some(s) = muxing::event_from_ref_and_wrap(client.clone()).await.unwrap()
+                .into_inbound_substream() {
+                break s;
+            }
+        };
+
+        let mut buf = [0; 4];
+        inbound.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        let err = inbound.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::ConnectionReset);
+    });
+
+    async_std::task::block_on(async {
+        let mplex = libp2p_mplex::MplexConfig::new();
+        let transport = TcpConfig::new().and_then(move |c, e|
+            upgrade::apply(c, mplex, e, upgrade::Version::V1));
+
+        let client = Arc::new(transport.dial(rx.await.unwrap()).unwrap().await.unwrap());
+        let mut outbound = muxing::outbound_from_ref_and_wrap(client.clone()).await.unwrap();
+        outbound.write_all(b"ping").await.unwrap();
+        outbound.flush().await.unwrap();
+        drop(outbound);
+
+        bg_thread.await;
+    });
+}
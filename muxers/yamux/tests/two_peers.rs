@@ -0,0 +1,100 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::{channel::{mpsc, oneshot}, prelude::*};
+use libp2p_core::{muxing::{self, StreamMuxer, StreamMuxerEvent, SubstreamRef}, upgrade, Transport};
+use libp2p_tcp::TcpConfig;
+use std::sync::Arc;
+
+/// Drives the connection of `muxer` in the background and returns the inbound substreams.
+fn drive<M>(muxer: Arc<M>) -> mpsc::UnboundedReceiver<SubstreamRef<Arc<M>>>
+where
+    M: StreamMuxer + Send + Sync + 'static,
+    M::Substream: Send,
+    M::OutboundSubstream: Send,
+{
+    let (tx, rx) = mpsc::unbounded();
+    async_std::task::spawn(async move {
+        while let Ok(event) = muxing::event_from_ref_and_wrap(muxer.clone()).await {
+            if let StreamMuxerEvent::InboundSubstream(substream) = event {
+                let _ = tx.unbounded_send(substream);
+            }
+        }
+    });
+    rx
+}
+
+#[test]
+fn half_close() {
+    // The writing side of a substream can be closed while still reading the reply.
+
+    let (tx, rx) = oneshot::channel();
+
+    let bg_thread = async_std::task::spawn(async move {
+        let yamux = libp2p_yamux::Config::default();
+
+        let transport = TcpConfig::new().and_then(move |c, e|
+            upgrade::apply(c, yamux, e, upgrade::Version::V1));
+
+        let mut listener = transport
+            .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+
+        let addr = listener.next().await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        tx.send(addr).unwrap();
+
+        let client = Arc::new(listener
+            .next().await
+            .unwrap()
+            .unwrap()
+            .into_upgrade().unwrap().0.await.unwrap());
+
+        let mut inbound = drive(client).next().await.unwrap();
+
+        let mut buf = Vec::new();
+        inbound.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"ping");
+        inbound.write_all(b"pong").await.unwrap();
+        inbound.close().await.unwrap();
+    });
+
+    async_std::task::block_on(async {
+        let yamux = libp2p_yamux::Config::default();
+        let transport = TcpConfig::new().and_then(move |c, e|
+            upgrade::apply(c, yamux, e, upgrade::Version::V1));
+
+        let client = Arc::new(transport.dial(rx.await.unwrap()).unwrap().await.unwrap());
+        let _inbound = drive(client.clone());
+        let mut outbound = muxing::outbound_from_ref_and_wrap(client).await.unwrap();
+        outbound.write_all(b"ping").await.unwrap();
+        outbound.close().await.unwrap();
+
+        let mut buf = Vec::new();
+        outbound.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"pong");
+
+        bg_thread.await;
+    });
+}
@@ -0,0 +1,733 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::protocol::{AutoNatCodec, AutoNatProtocol, DialRequest, DialResponse, ResponseError};
+use futures::prelude::*;
+use libp2p_core::{
+    connection::{ConnectedPoint, ConnectionId},
+    multiaddr::Protocol,
+    Multiaddr,
+    PeerId,
+};
+use libp2p_request_response::{
+    handler::RequestResponseHandler,
+    OutboundFailure,
+    ProtocolSupport,
+    RequestId,
+    RequestResponse,
+    RequestResponseConfig,
+    RequestResponseEvent,
+    RequestResponseMessage,
+    ResponseChannel,
+};
+use libp2p_swarm::{
+    DialPeerCondition,
+    NetworkBehaviour,
+    NetworkBehaviourAction,
+    PollParameters,
+    ProtocolsHandler,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    error,
+    fmt,
+    iter,
+    mem,
+    net::IpAddr,
+    task::{Context, Poll},
+    time::Duration,
+};
+use wasm_timer::{Delay, Instant};
+
+/// The configuration for an [`AutoNat`] behaviour.
+#[derive(Debug, Clone)]
+pub struct AutoNatConfig {
+    timeout: Duration,
+    boot_delay: Duration,
+    retry_interval: Duration,
+    refresh_interval: Duration,
+    throttle_server_period: Duration,
+    use_connected: bool,
+    confidence_max: usize,
+    max_peer_addresses: usize,
+    throttle_clients_global_max: usize,
+    throttle_clients_peer_max: usize,
+    throttle_clients_period: Duration,
+}
+
+impl Default for AutoNatConfig {
+    fn default() -> Self {
+        AutoNatConfig {
+            timeout: Duration::from_secs(30),
+            boot_delay: Duration::from_secs(15),
+            retry_interval: Duration::from_secs(90),
+            refresh_interval: Duration::from_secs(15 * 60),
+            throttle_server_period: Duration::from_secs(90),
+            use_connected: true,
+            confidence_max: 3,
+            max_peer_addresses: 16,
+            throttle_clients_global_max: 30,
+            throttle_clients_peer_max: 3,
+            throttle_clients_period: Duration::from_secs(1),
+        }
+    }
+}
+
+impl AutoNatConfig {
+    /// Sets the timeout for a dial-back request, covering both the dial-back
+    /// of the server and the transmission of the response.
+    pub fn set_timeout(&mut self, v: Duration) -> &mut Self {
+        self.timeout = v;
+        self
+    }
+
+    /// Sets the delay before the first probe.
+    pub fn set_boot_delay(&mut self, v: Duration) -> &mut Self {
+        self.boot_delay = v;
+        self
+    }
+
+    /// Sets the interval between probes while the NAT status is not yet
+    /// confirmed with maximum confidence.
+    pub fn set_retry_interval(&mut self, v: Duration) -> &mut Self {
+        self.retry_interval = v;
+        self
+    }
+
+    /// Sets the interval between probes once the NAT status is confirmed with
+    /// maximum confidence.
+    pub fn set_refresh_interval(&mut self, v: Duration) -> &mut Self {
+        self.refresh_interval = v;
+        self
+    }
+
+    /// Sets the minimum period before the same server is probed again.
+    pub fn set_throttle_server_period(&mut self, v: Duration) -> &mut Self {
+        self.throttle_server_period = v;
+        self
+    }
+
+    /// Sets whether connected peers are used as servers, in addition to
+    /// the ones added via [`AutoNat::add_server`].
+    pub fn set_use_connected(&mut self, v: bool) -> &mut Self {
+        self.use_connected = v;
+        self
+    }
+
+    /// Sets the maximum confidence in the current NAT status.
+    ///
+    /// Each probe confirming the current status increases the confidence by
+    /// one, up to this maximum, while each probe contradicting it decreases
+    /// the confidence. The status only flips once a contradicting probe is
+    /// received at a confidence of zero.
+    pub fn set_confidence_max(&mut self, v: usize) -> &mut Self {
+        self.confidence_max = v;
+        self
+    }
+
+    /// Sets the maximum number of addresses of a client that are dialed back.
+    pub fn set_max_peer_addresses(&mut self, v: usize) -> &mut Self {
+        self.max_peer_addresses = v;
+        self
+    }
+
+    /// Sets the maximum number of dial-back requests served within a
+    /// throttle period, across all clients.
+    pub fn set_throttle_clients_global_max(&mut self, v: usize) -> &mut Self {
+        self.throttle_clients_global_max = v;
+        self
+    }
+
+    /// Sets the maximum number of dial-back requests served within a
+    /// throttle period for a single client.
+    pub fn set_throttle_clients_peer_max(&mut self, v: usize) -> &mut Self {
+        self.throttle_clients_peer_max = v;
+        self
+    }
+
+    /// Sets the throttle period for dial-back requests of clients.
+    pub fn set_throttle_clients_period(&mut self, v: Duration) -> &mut Self {
+        self.throttle_clients_period = v;
+        self
+    }
+}
+
+/// The NAT status of the local node, as inferred from the probes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NatStatus {
+    /// The local node is publicly reachable on the given address.
+    Public(Multiaddr),
+    /// The local node is not publicly reachable.
+    Private,
+    /// Not enough probes have succeeded yet to tell.
+    Unknown,
+}
+
+impl NatStatus {
+    /// Returns `true` if the local node is publicly reachable.
+    pub fn is_public(&self) -> bool {
+        matches!(self, NatStatus::Public(_))
+    }
+}
+
+/// The events emitted by the [`AutoNat`] behaviour.
+#[derive(Debug)]
+pub enum AutoNatEvent {
+    /// A dial-back request of a remote peer has been served.
+    InboundProbe {
+        /// The peer that requested to be dialed back.
+        peer: PeerId,
+        /// The address on which the peer has been dialed back successfully.
+        result: Result<Multiaddr, InboundProbeError>,
+    },
+    /// A dial-back request sent to a server has been answered.
+    OutboundProbe {
+        /// The server the request has been sent to.
+        peer: PeerId,
+        /// The address on which the server dialed us back successfully.
+        result: Result<Multiaddr, OutboundProbeError>,
+    },
+    /// The inferred NAT status of the local node changed.
+    StatusChanged {
+        /// The former status.
+        old: NatStatus,
+        /// The new status.
+        new: NatStatus,
+    },
+}
+
+/// The reasons for which a dial-back request of a remote peer failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InboundProbeError {
+    /// The request has been refused because of throttling.
+    Throttled,
+    /// The request announced a peer ID other than the one of the requesting
+    /// peer, or did not contain any address we are willing to dial.
+    InvalidRequest,
+    /// Dialing the requested addresses failed.
+    Dial,
+}
+
+impl fmt::Display for InboundProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InboundProbeError::Throttled => write!(f, "Dial-back request throttled"),
+            InboundProbeError::InvalidRequest => write!(f, "Invalid dial-back request"),
+            InboundProbeError::Dial => write!(f, "Failed to dial back"),
+        }
+    }
+}
+
+impl error::Error for InboundProbeError {}
+
+/// The reasons for which a dial-back request sent to a server failed.
+#[derive(Debug)]
+pub enum OutboundProbeError {
+    /// The request could not be sent or no response has been received.
+    OutboundFailure(OutboundFailure),
+    /// The server answered with an error.
+    Response(ResponseError),
+}
+
+impl fmt::Display for OutboundProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutboundProbeError::OutboundFailure(e) => write!(f, "Request failed: {:?}", e),
+            OutboundProbeError::Response(e) => write!(f, "Server responded with error: {}", e),
+        }
+    }
+}
+
+impl error::Error for OutboundProbeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            OutboundProbeError::OutboundFailure(_) => None,
+            OutboundProbeError::Response(e) => Some(e),
+        }
+    }
+}
+
+/// A dial-back of a client that is in progress.
+struct DialBack {
+    addresses: Vec<Multiaddr>,
+    channel: ResponseChannel<DialResponse>,
+}
+
+type InEvent = <RequestResponseHandler<AutoNatCodec> as ProtocolsHandler>::InEvent;
+
+/// `NetworkBehaviour` implementing the AutoNAT protocol.
+///
+/// As a client, the behaviour periodically asks a server, i.e. one of the
+/// peers added via [`AutoNat::add_server`] or, if configured, any connected
+/// peer, to dial it back on its listen addresses and its confirmed and candidate
+/// external addresses. The results of these probes determine the [`NatStatus`]
+/// of the local node. An address the local node is found to be publicly
+/// reachable on is confirmed as an external address of the `Swarm`.
+///
+/// As a server, the behaviour dials back clients on the addresses they ask
+/// for, within the limits of the [`AutoNatConfig`].
+pub struct AutoNat {
+    /// The underlying request-response protocol.
+    inner: RequestResponse<AutoNatCodec>,
+    config: AutoNatConfig,
+    local_peer_id: PeerId,
+    /// The currently inferred NAT status.
+    nat_status: NatStatus,
+    /// The confidence in `nat_status`.
+    confidence: usize,
+    /// The servers added via [`AutoNat::add_server`].
+    servers: Vec<PeerId>,
+    /// The established connections per connected peer.
+    connected: HashMap<PeerId, HashMap<ConnectionId, ConnectedPoint>>,
+    /// The time at which each server has last been probed.
+    last_probes: HashMap<PeerId, Instant>,
+    /// Fires when the next probe is due.
+    next_probe: Delay,
+    /// The probe in progress, if any.
+    ongoing_outbound: Option<(RequestId, PeerId)>,
+    /// The dial-backs in progress, per client.
+    ongoing_inbound: HashMap<PeerId, DialBack>,
+    /// The dial-back requests served within the current throttle period.
+    throttled_clients: Vec<(PeerId, Instant)>,
+    /// Queue of actions to return when polled.
+    pending_actions: VecDeque<NetworkBehaviourAction<InEvent, AutoNatEvent>>,
+}
+
+impl AutoNat {
+    /// Creates a new `AutoNat` behaviour with the given configuration.
+    pub fn new(local_peer_id: PeerId, config: AutoNatConfig) -> Self {
+        let mut cfg = RequestResponseConfig::default();
+        cfg.set_request_timeout(config.timeout);
+        let protocols = iter::once((AutoNatProtocol, ProtocolSupport::Full));
+        let inner = RequestResponse::new(AutoNatCodec, protocols, cfg);
+        AutoNat {
+            inner,
+            next_probe: Delay::new(config.boot_delay),
+            config,
+            local_peer_id,
+            nat_status: NatStatus::Unknown,
+            confidence: 0,
+            servers: Vec::new(),
+            connected: HashMap::new(),
+            last_probes: HashMap::new(),
+            ongoing_outbound: None,
+            ongoing_inbound: HashMap::new(),
+            throttled_clients: Vec::new(),
+            pending_actions: VecDeque::new(),
+        }
+    }
+
+    /// Returns the currently inferred NAT status of the local node.
+    pub fn nat_status(&self) -> &NatStatus {
+        &self.nat_status
+    }
+
+    /// Returns the public address of the local node, if it is publicly
+    /// reachable.
+    pub fn public_address(&self) -> Option<&Multiaddr> {
+        match &self.nat_status {
+            NatStatus::Public(address) => Some(address),
+            _ => None,
+        }
+    }
+
+    /// Returns the confidence in the current NAT status.
+    pub fn confidence(&self) -> usize {
+        self.confidence
+    }
+
+    /// Adds a peer to be used as server for probes, optionally with an
+    /// address on which it can be reached.
+    pub fn add_server(&mut self, peer: PeerId, address: Option<Multiaddr>) {
+        if let Some(address) = address {
+            self.inner.add_address(&peer, address);
+        }
+        if !self.servers.contains(&peer) {
+            self.servers.push(peer);
+        }
+    }
+
+    /// Removes a peer previously added via [`AutoNat::add_server`].
+    pub fn remove_server(&mut self, peer: &PeerId) {
+        self.servers.retain(|p| p != peer);
+    }
+
+    /// Sends a dial-back request to a server, unless a probe is already in
+    /// progress or no server or candidate address is available.
+    fn start_probe(&mut self, params: &mut impl PollParameters) {
+        if self.ongoing_outbound.is_some() {
+            return;
+        }
+
+        let mut addresses: Vec<Multiaddr> = Vec::new();
+        let candidates = params.external_address_candidates()
+            .chain(params.external_addresses())
+            .chain(params.listened_addresses());
+        for address in candidates {
+            if !is_relayed(&address) && !addresses.contains(&address) {
+                addresses.push(address);
+            }
+        }
+        if addresses.is_empty() {
+            log::debug!("No candidate addresses to probe.");
+            return;
+        }
+
+        let server = match self.select_server() {
+            Some(server) => server,
+            None => {
+                log::debug!("No AutoNAT server available to probe.");
+                return;
+            }
+        };
+
+        let request = DialRequest { peer_id: self.local_peer_id.clone(), addresses };
+        let request_id = self.inner.send_request(&server, request);
+        self.last_probes.insert(server.clone(), Instant::now());
+        self.ongoing_outbound = Some((request_id, server));
+    }
+
+    /// Selects the server that has not been probed for the longest time,
+    /// ignoring those probed within the throttle period.
+    fn select_server(&self) -> Option<PeerId> {
+        let now = Instant::now();
+        let connected = self.connected.keys().filter(|_| self.config.use_connected);
+        let mut candidates = self.servers.iter().chain(connected)
+            .filter(|peer| match self.last_probes.get(*peer) {
+                Some(last) => *last + self.config.throttle_server_period <= now,
+                None => true,
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|peer| self.last_probes.get(*peer));
+        candidates.first().map(|peer| (*peer).clone())
+    }
+
+    /// Handles the result of a probe and schedules the next one.
+    fn handle_outbound_result(&mut self, peer: PeerId, result: Result<Multiaddr, OutboundProbeError>) {
+        self.ongoing_outbound = None;
+        match &result {
+            Ok(address) => self.handle_reported_status(NatStatus::Public(address.clone())),
+            Err(OutboundProbeError::Response(ResponseError::DialError)) =>
+                self.handle_reported_status(NatStatus::Private),
+            Err(_) => {}
+        }
+        let delay = if self.confidence == self.config.confidence_max {
+            self.config.refresh_interval
+        } else {
+            self.config.retry_interval
+        };
+        self.next_probe.reset(delay);
+        self.pending_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+            AutoNatEvent::OutboundProbe { peer, result }));
+    }
+
+    /// Updates the NAT status and the confidence in it with a status
+    /// reported by a probe.
+    fn handle_reported_status(&mut self, reported: NatStatus) {
+        let confirmed = matches!(
+            (&self.nat_status, &reported),
+            (NatStatus::Public(_), NatStatus::Public(_)) | (NatStatus::Private, NatStatus::Private)
+        );
+
+        if confirmed {
+            if self.confidence < self.config.confidence_max {
+                self.confidence += 1;
+            }
+            // A public node may be confirmed on another of its addresses.
+            if self.nat_status == reported {
+                return;
+            }
+        } else if self.confidence > 0 {
+            self.confidence -= 1;
+            return;
+        }
+
+        let old = mem::replace(&mut self.nat_status, reported.clone());
+        match (&old, &reported) {
+            (_, NatStatus::Public(address)) => {
+                self.pending_actions.push_back(NetworkBehaviourAction::ConfirmExternalAddr {
+                    address: address.clone(),
+                });
+            }
+            // The address the node was found to be public on is no longer
+            // reachable and must not be advertised anymore.
+            (NatStatus::Public(address), NatStatus::Private) => {
+                self.pending_actions.push_back(NetworkBehaviourAction::RemoveExternalAddr {
+                    address: address.clone(),
+                });
+            }
+            _ => {}
+        }
+        self.pending_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+            AutoNatEvent::StatusChanged { old, new: reported }));
+    }
+
+    /// Serves a dial-back request of a client.
+    fn handle_dial_request(
+        &mut self,
+        peer: PeerId,
+        request: DialRequest,
+        channel: ResponseChannel<DialResponse>,
+    ) {
+        let now = Instant::now();
+        let period = self.config.throttle_clients_period;
+        self.throttled_clients.retain(|(_, at)| *at + period > now);
+
+        let result = if request.peer_id != peer {
+            Err(InboundProbeError::InvalidRequest)
+        } else if self.ongoing_inbound.contains_key(&peer)
+            || self.throttled_clients.len() >= self.config.throttle_clients_global_max
+            || self.throttled_clients.iter().filter(|(p, _)| p == &peer).count()
+                >= self.config.throttle_clients_peer_max
+        {
+            Err(InboundProbeError::Throttled)
+        } else {
+            let addresses = self.filter_addresses(&peer, request.addresses);
+            if addresses.is_empty() {
+                Err(InboundProbeError::InvalidRequest)
+            } else {
+                Ok(addresses)
+            }
+        };
+
+        match result {
+            Ok(addresses) => {
+                log::debug!("Dialing back {:?} on {:?}.", peer, addresses);
+                self.throttled_clients.push((peer.clone(), now));
+                self.ongoing_inbound.insert(peer.clone(), DialBack { addresses, channel });
+                self.pending_actions.push_back(NetworkBehaviourAction::DialPeer {
+                    peer_id: peer,
+                    condition: DialPeerCondition::Always,
+                });
+            }
+            Err(error) => {
+                let response_error = match error {
+                    InboundProbeError::Throttled => ResponseError::DialRefused,
+                    InboundProbeError::InvalidRequest => ResponseError::BadRequest,
+                    InboundProbeError::Dial => ResponseError::DialError,
+                };
+                self.inner.send_response(channel, DialResponse {
+                    result: Err(response_error),
+                    status_text: Some(error.to_string()),
+                });
+                self.pending_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    AutoNatEvent::InboundProbe { peer, result: Err(error) }));
+            }
+        }
+    }
+
+    /// Filters the addresses a client asked to be dialed back on.
+    ///
+    /// Relayed addresses are never dialed. If the IP address of the client
+    /// has been observed on any of its connections, only addresses with that
+    /// IP address are dialed, so that a server cannot be abused to dial
+    /// arbitrary third parties.
+    fn filter_addresses(&self, peer: &PeerId, addresses: Vec<Multiaddr>) -> Vec<Multiaddr> {
+        let observed_ips = self.connected.get(peer)
+            .into_iter()
+            .flat_map(|connections| connections.values())
+            .filter_map(|endpoint| match endpoint {
+                ConnectedPoint::Dialer { address } => ip_of(address),
+                ConnectedPoint::Listener { send_back_addr, .. } => ip_of(send_back_addr),
+            })
+            .collect::<Vec<_>>();
+
+        let mut filtered = Vec::new();
+        for address in addresses {
+            if is_relayed(&address) || filtered.contains(&address) {
+                continue;
+            }
+            if !observed_ips.is_empty() && !ip_of(&address).is_some_and(|ip| observed_ips.contains(&ip)) {
+                continue;
+            }
+            filtered.push(address);
+        }
+        filtered.truncate(self.config.max_peer_addresses);
+        filtered
+    }
+
+    /// Responds to a dial-back request and reports the result.
+    fn finish_dial_back(&mut self, peer: PeerId, dial_back: DialBack, result: Result<Multiaddr, InboundProbeError>) {
+        let response = match &result {
+            Ok(address) => DialResponse { result: Ok(address.clone()), status_text: None },
+            Err(error) => DialResponse {
+                result: Err(ResponseError::DialError),
+                status_text: Some(error.to_string()),
+            },
+        };
+        self.inner.send_response(dial_back.channel, response);
+        self.pending_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+            AutoNatEvent::InboundProbe { peer, result }));
+    }
+
+    fn handle_inner_event(&mut self, event: RequestResponseEvent<DialRequest, DialResponse>) {
+        match event {
+            RequestResponseEvent::Message { peer, message: RequestResponseMessage::Request { request, channel } } =>
+                self.handle_dial_request(peer, request, channel),
+            RequestResponseEvent::Message { peer, message: RequestResponseMessage::Response { request_id, response } } => {
+                if self.ongoing_outbound.as_ref().map(|(id, _)| id) == Some(&request_id) {
+                    let result = response.result.map_err(OutboundProbeError::Response);
+                    self.handle_outbound_result(peer, result);
+                }
+            }
+            // Streaming responses are not enabled for AutoNAT.
+            RequestResponseEvent::Message { message: RequestResponseMessage::ResponseStream { .. }, .. } => {}
+            RequestResponseEvent::OutboundFailure { peer, request_id, error } => {
+                if self.ongoing_outbound.as_ref().map(|(id, _)| id) == Some(&request_id) {
+                    self.handle_outbound_result(peer, Err(OutboundProbeError::OutboundFailure(error)));
+                }
+            }
+            RequestResponseEvent::InboundFailure { peer, error } => {
+                log::debug!("Inbound AutoNAT request of {:?} failed: {:?}", peer, error);
+            }
+        }
+    }
+}
+
+impl NetworkBehaviour for AutoNat {
+    type ProtocolsHandler = RequestResponseHandler<AutoNatCodec>;
+    type OutEvent = AutoNatEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        self.inner.new_handler()
+    }
+
+    fn addresses_of_peer(&mut self, peer: &PeerId) -> Vec<Multiaddr> {
+        // A dial-back must only succeed on one of the requested addresses.
+        if let Some(dial_back) = self.ongoing_inbound.get(peer) {
+            return dial_back.addresses.clone();
+        }
+        self.inner.addresses_of_peer(peer)
+    }
+
+    fn inject_connected(&mut self, peer: &PeerId) {
+        self.inner.inject_connected(peer)
+    }
+
+    fn inject_disconnected(&mut self, peer: &PeerId) {
+        self.connected.remove(peer);
+        self.inner.inject_disconnected(peer)
+    }
+
+    fn inject_connection_established(&mut self, peer: &PeerId, conn: &ConnectionId, endpoint: &ConnectedPoint) {
+        self.connected.entry(peer.clone()).or_default().insert(*conn, endpoint.clone());
+        self.inner.inject_connection_established(peer, conn, endpoint);
+
+        if let ConnectedPoint::Dialer { address } = endpoint {
+            let is_dial_back = self.ongoing_inbound.get(peer)
+                .is_some_and(|dial_back| dial_back.addresses.contains(address));
+            if is_dial_back {
+                let dial_back = self.ongoing_inbound.remove(peer).expect("Checked above.");
+                self.finish_dial_back(peer.clone(), dial_back, Ok(address.clone()));
+            }
+        }
+    }
+
+    fn inject_connection_closed(&mut self, peer: &PeerId, conn: &ConnectionId, endpoint: &ConnectedPoint) {
+        if let Some(connections) = self.connected.get_mut(peer) {
+            connections.remove(conn);
+        }
+        self.inner.inject_connection_closed(peer, conn, endpoint)
+    }
+
+    fn inject_address_change(&mut self, peer: &PeerId, conn: &ConnectionId, old: &ConnectedPoint, new: &ConnectedPoint) {
+        if let Some(endpoint) = self.connected.get_mut(peer).and_then(|c| c.get_mut(conn)) {
+            *endpoint = new.clone();
+        }
+        self.inner.inject_address_change(peer, conn, old, new)
+    }
+
+    fn inject_dial_failure(&mut self, peer: &PeerId) {
+        if let Some(dial_back) = self.ongoing_inbound.remove(peer) {
+            self.finish_dial_back(peer.clone(), dial_back, Err(InboundProbeError::Dial));
+        }
+        self.inner.inject_dial_failure(peer)
+    }
+
+    fn inject_event(
+        &mut self,
+        peer: PeerId,
+        conn: ConnectionId,
+        event: <Self::ProtocolsHandler as ProtocolsHandler>::OutEvent,
+    ) {
+        self.inner.inject_event(peer, conn, event)
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>, params: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<InEvent, AutoNatEvent>>
+    {
+        // Forget about dial-backs whose requests timed out in the meantime.
+        self.ongoing_inbound.retain(|_, dial_back| dial_back.channel.is_open());
+
+        loop {
+            if let Some(action) = self.pending_actions.pop_front() {
+                return Poll::Ready(action);
+            }
+
+            match self.inner.poll(cx, params) {
+                Poll::Ready(NetworkBehaviourAction::GenerateEvent(event)) => {
+                    self.handle_inner_event(event);
+                    continue;
+                }
+                Poll::Ready(NetworkBehaviourAction::DialAddress { address }) =>
+                    return Poll::Ready(NetworkBehaviourAction::DialAddress { address }),
+                Poll::Ready(NetworkBehaviourAction::DialPeer { peer_id, condition }) =>
+                    return Poll::Ready(NetworkBehaviourAction::DialPeer { peer_id, condition }),
+                Poll::Ready(NetworkBehaviourAction::NotifyHandler { peer_id, handler, event }) =>
+                    return Poll::Ready(NetworkBehaviourAction::NotifyHandler { peer_id, handler, event }),
+                Poll::Ready(NetworkBehaviourAction::ReportObservedAddr { address, peer_id }) =>
+                    return Poll::Ready(NetworkBehaviourAction::ReportObservedAddr { address, peer_id }),
+                Poll::Ready(NetworkBehaviourAction::AddExternalAddr { address }) =>
+                    return Poll::Ready(NetworkBehaviourAction::AddExternalAddr { address }),
+                Poll::Ready(NetworkBehaviourAction::ConfirmExternalAddr { address }) =>
+                    return Poll::Ready(NetworkBehaviourAction::ConfirmExternalAddr { address }),
+                Poll::Ready(NetworkBehaviourAction::RemoveExternalAddr { address }) =>
+                    return Poll::Ready(NetworkBehaviourAction::RemoveExternalAddr { address }),
+                Poll::Ready(NetworkBehaviourAction::UpdateSupportedProtocols) =>
+                    return Poll::Ready(NetworkBehaviourAction::UpdateSupportedProtocols),
+                Poll::Pending => {}
+            }
+
+            if self.next_probe.poll_unpin(cx).is_ready() {
+                // Retried after the retry interval unless a result arrives
+                // in the meantime and reschedules the probe.
+                self.next_probe.reset(self.config.retry_interval);
+                self.start_probe(params);
+                continue;
+            }
+
+            return Poll::Pending;
+        }
+    }
+}
+
+/// Returns the IP address of a multiaddress, if any.
+fn ip_of(address: &Multiaddr) -> Option<IpAddr> {
+    address.iter().find_map(|p| match p {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+/// Returns `true` if the given multiaddress is a relayed address.
+fn is_relayed(address: &Multiaddr) -> bool {
+    address.iter().any(|p| p == Protocol::P2pCircuit)
+}
@@ -0,0 +1,53 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Implementation of the [AutoNAT](https://github.com/libp2p/specs/blob/master/autonat/README.md)
+//! protocol.
+//!
+//! AutoNAT lets a node determine whether it is publicly reachable, i.e. not
+//! hidden behind a NAT or firewall, by asking other peers to dial it back on
+//! its addresses.
+//!
+//! The [`AutoNat`] network behaviour plays both roles of the protocol:
+//!
+//! - As a client, it periodically sends dial-back requests to servers and
+//!   infers the [`NatStatus`] of the local node from the responses. A status
+//!   only flips after enough contradicting probes, according to the
+//!   confidence configured in the [`AutoNatConfig`]. Changes are reported via
+//!   [`AutoNatEvent::StatusChanged`].
+//! - As a server, it dials back the clients asking for it and reports the
+//!   result. Dial-back requests are throttled, both per client and overall.
+
+mod behaviour;
+mod protocol;
+
+mod message_proto {
+    include!(concat!(env!("OUT_DIR"), "/autonat.pb.rs"));
+}
+
+pub use behaviour::{
+    AutoNat,
+    AutoNatConfig,
+    AutoNatEvent,
+    InboundProbeError,
+    NatStatus,
+    OutboundProbeError,
+};
+pub use protocol::{DialRequest, DialResponse, ResponseError, PROTOCOL_NAME};
@@ -0,0 +1,290 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The AutoNAT wire protocol, carried over a `libp2p-request-response` codec.
+
+use crate::message_proto;
+use async_trait::async_trait;
+use futures::prelude::*;
+use libp2p_core::{
+    upgrade::{self, ReadOneError},
+    Multiaddr,
+    PeerId,
+    ProtocolName,
+};
+use libp2p_request_response::RequestResponseCodec;
+use prost::Message;
+use std::{convert::TryFrom, error, fmt, io};
+
+/// The protocol name used for negotiating AutoNAT substreams.
+pub const PROTOCOL_NAME: &[u8] = b"/libp2p/autonat/1.0.0";
+
+/// Maximum size of an AutoNAT message.
+const MAX_MESSAGE_SIZE: usize = 4096;
+
+/// The AutoNAT protocol, as negotiated by `libp2p-request-response`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AutoNatProtocol;
+
+impl ProtocolName for AutoNatProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        PROTOCOL_NAME
+    }
+}
+
+/// The codec reading and writing AutoNAT dial requests and responses.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AutoNatCodec;
+
+#[async_trait]
+impl RequestResponseCodec for AutoNatCodec {
+    type Protocol = AutoNatProtocol;
+    type Request = DialRequest;
+    type Response = DialResponse;
+
+    async fn read_request<T>(&mut self, _: &AutoNatProtocol, io: &mut T)
+        -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send
+    {
+        DialRequest::from_bytes(&recv(io).await?)
+    }
+
+    async fn read_response<T>(&mut self, _: &AutoNatProtocol, io: &mut T)
+        -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send
+    {
+        DialResponse::from_bytes(&recv(io).await?)
+    }
+
+    async fn write_request<T>(&mut self, _: &AutoNatProtocol, io: &mut T, req: DialRequest)
+        -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send
+    {
+        upgrade::write_one(io, req.into_bytes()).await
+    }
+
+    async fn write_response<T>(&mut self, _: &AutoNatProtocol, io: &mut T, res: DialResponse)
+        -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send
+    {
+        upgrade::write_one(io, res.into_bytes()).await
+    }
+}
+
+/// A request of a peer to be dialed back on the given addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DialRequest {
+    /// The peer ID of the requesting peer.
+    pub peer_id: PeerId,
+    /// The addresses on which the requesting peer wants to be dialed back.
+    pub addresses: Vec<Multiaddr>,
+}
+
+impl DialRequest {
+    fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let msg = message_proto::Message::decode(bytes).map_err(invalid_data)?;
+        if msg.r#type != Some(message_proto::message::MessageType::Dial as i32) {
+            return Err(invalid_data("Expected a DIAL message"));
+        }
+        let peer = msg.dial
+            .and_then(|dial| dial.peer)
+            .ok_or_else(|| invalid_data("DIAL message without peer"))?;
+        let peer_id = peer.id
+            .ok_or_else(|| invalid_data("DIAL message without peer ID"))
+            .and_then(|id| PeerId::from_bytes(id).map_err(|_| invalid_data("Invalid peer ID")))?;
+        // Addresses that fail to parse are skipped rather than failing the
+        // whole request, so that a remote announcing newer address types can
+        // still be dialed on the ones we understand.
+        let addresses = peer.addrs.into_iter()
+            .filter_map(|a| Multiaddr::try_from(a).ok())
+            .collect();
+        Ok(DialRequest { peer_id, addresses })
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        let msg = message_proto::Message {
+            r#type: Some(message_proto::message::MessageType::Dial as i32),
+            dial: Some(message_proto::message::Dial {
+                peer: Some(message_proto::message::PeerInfo {
+                    id: Some(self.peer_id.into_bytes()),
+                    addrs: self.addresses.into_iter().map(|a| a.to_vec()).collect(),
+                }),
+            }),
+            dial_response: None,
+        };
+        let mut bytes = Vec::with_capacity(msg.encoded_len());
+        msg.encode(&mut bytes).expect("Vec<u8> provides capacity as needed");
+        bytes
+    }
+}
+
+/// The reasons a dial-back request may fail, as reported by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseError {
+    /// The server failed to dial any of the requested addresses.
+    DialError,
+    /// The server refused to dial back, e.g. because of throttling.
+    DialRefused,
+    /// The request was malformed or contained no dialable address.
+    BadRequest,
+    /// The server failed for internal reasons.
+    InternalError,
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResponseError::DialError => write!(f, "Dial error"),
+            ResponseError::DialRefused => write!(f, "Dial refused"),
+            ResponseError::BadRequest => write!(f, "Bad request"),
+            ResponseError::InternalError => write!(f, "Internal error"),
+        }
+    }
+}
+
+impl error::Error for ResponseError {}
+
+/// The response to a [`DialRequest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DialResponse {
+    /// The address on which the server successfully dialed back, or the
+    /// reason for failure.
+    pub result: Result<Multiaddr, ResponseError>,
+    /// An optional human readable description of the result.
+    pub status_text: Option<String>,
+}
+
+impl DialResponse {
+    fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        use message_proto::message::ResponseStatus;
+
+        let msg = message_proto::Message::decode(bytes).map_err(invalid_data)?;
+        if msg.r#type != Some(message_proto::message::MessageType::DialResponse as i32) {
+            return Err(invalid_data("Expected a DIAL_RESPONSE message"));
+        }
+        let response = msg.dial_response
+            .ok_or_else(|| invalid_data("DIAL_RESPONSE message without response"))?;
+        let status = response.status
+            .and_then(ResponseStatus::from_i32)
+            .ok_or_else(|| invalid_data("DIAL_RESPONSE message without valid status"))?;
+        let result = match status {
+            ResponseStatus::Ok => {
+                let addr = response.addr
+                    .ok_or_else(|| invalid_data("DIAL_RESPONSE message without address"))?;
+                Ok(Multiaddr::try_from(addr).map_err(invalid_data)?)
+            }
+            ResponseStatus::EDialError => Err(ResponseError::DialError),
+            ResponseStatus::EDialRefused => Err(ResponseError::DialRefused),
+            ResponseStatus::EBadRequest => Err(ResponseError::BadRequest),
+            ResponseStatus::EInternalError => Err(ResponseError::InternalError),
+        };
+        Ok(DialResponse { result, status_text: response.status_text })
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        use message_proto::message::ResponseStatus;
+
+        let (status, addr) = match self.result {
+            Ok(addr) => (ResponseStatus::Ok, Some(addr.to_vec())),
+            Err(ResponseError::DialError) => (ResponseStatus::EDialError, None),
+            Err(ResponseError::DialRefused) => (ResponseStatus::EDialRefused, None),
+            Err(ResponseError::BadRequest) => (ResponseStatus::EBadRequest, None),
+            Err(ResponseError::InternalError) => (ResponseStatus::EInternalError, None),
+        };
+        let msg = message_proto::Message {
+            r#type: Some(message_proto::message::MessageType::DialResponse as i32),
+            dial: None,
+            dial_response: Some(message_proto::message::DialResponse {
+                status: Some(status as i32),
+                status_text: self.status_text,
+                addr,
+            }),
+        };
+        let mut bytes = Vec::with_capacity(msg.encoded_len());
+        msg.encode(&mut bytes).expect("Vec<u8> provides capacity as needed");
+        bytes
+    }
+}
+
+/// Reads a single length-prefixed message.
+async fn recv<T: AsyncRead + Unpin>(io: &mut T) -> io::Result<Vec<u8>> {
+    let buf = upgrade::read_one(io, MAX_MESSAGE_SIZE).await
+        .map_err(|e| match e {
+            ReadOneError::Io(e) => e,
+            e => invalid_data(e),
+        })?;
+    if buf.is_empty() {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+
+fn invalid_data<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dial_request_roundtrip() {
+        let request = DialRequest {
+            peer_id: PeerId::random(),
+            addresses: vec![
+                "/ip4/8.8.8.8/tcp/30333".parse().unwrap(),
+                "/memory/1234".parse().unwrap(),
+            ],
+        };
+        let decoded = DialRequest::from_bytes(&request.clone().into_bytes()).unwrap();
+        assert_eq!(decoded, request);
+    }
+
+    #[test]
+    fn dial_response_roundtrip() {
+        let responses = vec![
+            DialResponse {
+                result: Ok("/ip4/8.8.8.8/tcp/30333".parse().unwrap()),
+                status_text: None,
+            },
+            DialResponse {
+                result: Err(ResponseError::DialRefused),
+                status_text: Some("Too many requests".into()),
+            },
+        ];
+        for response in responses {
+            let decoded = DialResponse::from_bytes(&response.clone().into_bytes()).unwrap();
+            assert_eq!(decoded, response);
+        }
+    }
+
+    #[test]
+    fn request_is_not_a_response() {
+        let request = DialRequest { peer_id: PeerId::random(), addresses: Vec::new() };
+        assert!(DialResponse::from_bytes(&request.into_bytes()).is_err());
+    }
+}
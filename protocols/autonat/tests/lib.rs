@@ -0,0 +1,173 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Integration tests for the AutoNAT client and server roles.
+
+use futures::executor::block_on;
+use libp2p_autonat::{
+    AutoNat,
+    AutoNatConfig,
+    AutoNatEvent,
+    NatStatus,
+    OutboundProbeError,
+    ResponseError,
+};
+use libp2p_core::{
+    identity,
+    multiaddr::{Multiaddr, Protocol},
+    muxing::StreamMuxerBox,
+    transport::{boxed::Boxed, MemoryTransport, Transport},
+    upgrade,
+    PeerId,
+};
+use libp2p_plaintext::PlainText2Config;
+use libp2p_swarm::Swarm;
+use libp2p_yamux as yamux;
+use std::{io, time::Duration};
+
+#[test]
+fn reachable_client_becomes_public() {
+    let _ = env_logger::try_init();
+
+    let (server_id, server_addr) = spawn_server(AutoNatConfig::default());
+
+    let mut client = build_swarm(client_config());
+    let client_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    Swarm::listen_on(&mut client, client_addr.clone()).unwrap();
+    client.add_server(server_id.clone(), Some(server_addr));
+
+    block_on(async {
+        loop {
+            match client.next().await {
+                AutoNatEvent::OutboundProbe { peer, result } => {
+                    assert_eq!(peer, server_id);
+                    assert_eq!(result.unwrap(), client_addr);
+                }
+                AutoNatEvent::StatusChanged { old, new } => {
+                    assert_eq!(old, NatStatus::Unknown);
+                    assert_eq!(new, NatStatus::Public(client_addr.clone()));
+                    break;
+                }
+                e => panic!("Unexpected event: {:?}", e),
+            }
+        }
+    });
+
+    assert_eq!(client.public_address(), Some(&client_addr));
+    assert_eq!(client.confidence(), 0);
+}
+
+#[test]
+fn unreachable_client_becomes_private() {
+    let _ = env_logger::try_init();
+
+    let (server_id, server_addr) = spawn_server(AutoNatConfig::default());
+
+    // The client claims an address it does not listen on.
+    let mut client = build_swarm(client_config());
+    Swarm::add_external_address(&mut client, Protocol::Memory(rand::random::<u64>()).into());
+    client.add_server(server_id.clone(), Some(server_addr));
+
+    block_on(async {
+        loop {
+            match client.next().await {
+                AutoNatEvent::OutboundProbe { peer, result } => {
+                    assert_eq!(peer, server_id);
+                    match result {
+                        Err(OutboundProbeError::Response(ResponseError::DialError)) => {}
+                        r => panic!("Unexpected result: {:?}", r),
+                    }
+                }
+                AutoNatEvent::StatusChanged { old, new } => {
+                    assert_eq!(old, NatStatus::Unknown);
+                    assert_eq!(new, NatStatus::Private);
+                    break;
+                }
+                e => panic!("Unexpected event: {:?}", e),
+            }
+        }
+    });
+}
+
+#[test]
+fn throttled_server_refuses_to_dial_back() {
+    let _ = env_logger::try_init();
+
+    let mut server_config = AutoNatConfig::default();
+    server_config.set_throttle_clients_global_max(0);
+    let (server_id, server_addr) = spawn_server(server_config);
+
+    let mut client = build_swarm(client_config());
+    let client_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    Swarm::listen_on(&mut client, client_addr).unwrap();
+    client.add_server(server_id.clone(), Some(server_addr));
+
+    block_on(async {
+        match client.next().await {
+            AutoNatEvent::OutboundProbe { peer, result } => {
+                assert_eq!(peer, server_id);
+                match result {
+                    Err(OutboundProbeError::Response(ResponseError::DialRefused)) => {}
+                    r => panic!("Unexpected result: {:?}", r),
+                }
+            }
+            e => panic!("Unexpected event: {:?}", e),
+        }
+    });
+
+    assert_eq!(client.nat_status(), &NatStatus::Unknown);
+}
+
+fn client_config() -> AutoNatConfig {
+    let mut config = AutoNatConfig::default();
+    config.set_boot_delay(Duration::from_millis(100));
+    config
+}
+
+/// Spawns a server swarm in the background, returning its peer ID and
+/// listen address.
+fn spawn_server(config: AutoNatConfig) -> (PeerId, Multiaddr) {
+    let mut server = build_swarm(config);
+    let server_id = Swarm::local_peer_id(&server).clone();
+    let server_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    Swarm::listen_on(&mut server, server_addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        loop {
+            server.next().await;
+        }
+    });
+    (server_id, server_addr)
+}
+
+fn build_swarm(config: AutoNatConfig) -> Swarm<AutoNat> {
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_public_key = local_key.public();
+    let local_peer_id = local_public_key.clone().into_peer_id();
+
+    let transport: Boxed<(PeerId, StreamMuxerBox), io::Error> = MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(PlainText2Config { local_public_key })
+        .multiplex(yamux::Config::default())
+        .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+        .boxed();
+
+    Swarm::new(transport, AutoNat::new(local_peer_id.clone(), config), local_peer_id)
+}
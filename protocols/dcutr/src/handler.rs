@@ -0,0 +1,134 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::protocol;
+use libp2p_core::Multiaddr;
+use libp2p_swarm::{
+    KeepAlive,
+    ProtocolsHandler,
+    ProtocolsHandlerEvent,
+    ProtocolsHandlerUpgrErr,
+    SubstreamProtocol,
+};
+use std::{collections::VecDeque, io, task::{Context, Poll}, time::Duration};
+use wasm_timer::Instant;
+
+/// Time a connection is kept alive for the hole punch to be carried out.
+const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Event sent from the [`Dcutr`](crate::Dcutr) behaviour to a [`Handler`].
+#[derive(Debug, Clone)]
+pub enum In {
+    /// Initiate a hole punch with the remote.
+    Connect,
+}
+
+/// Event produced by a [`Handler`].
+#[derive(Debug)]
+pub enum Event {
+    /// The remote initiated a hole punch, its addresses are to be dialed now.
+    InboundConnectNegotiated(Vec<Multiaddr>),
+    /// The hole punch we initiated has been negotiated, the addresses of the
+    /// remote are to be dialed now.
+    OutboundConnectNegotiated(Vec<Multiaddr>),
+    /// The hole punch we initiated could not be negotiated.
+    OutboundNegotiationFailed { error: ProtocolsHandlerUpgrErr<io::Error> },
+}
+
+/// Protocol handler for DCUtR, to be used on relayed connections.
+pub struct Handler {
+    /// Our addresses, sent to the remote.
+    obs_addrs: Vec<Multiaddr>,
+    queued_events: VecDeque<ProtocolsHandlerEvent<protocol::OutboundUpgrade, (), Event, io::Error>>,
+    keep_alive: KeepAlive,
+}
+
+impl Handler {
+    pub fn new(obs_addrs: Vec<Multiaddr>) -> Self {
+        Handler {
+            obs_addrs,
+            queued_events: VecDeque::new(),
+            keep_alive: KeepAlive::Until(Instant::now() + KEEP_ALIVE_TIMEOUT),
+        }
+    }
+}
+
+impl ProtocolsHandler for Handler {
+    type InEvent = In;
+    type OutEvent = Event;
+    type Error = io::Error;
+    type InboundProtocol = protocol::InboundUpgrade;
+    type OutboundProtocol = protocol::OutboundUpgrade;
+    type OutboundOpenInfo = ();
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol> {
+        SubstreamProtocol::new(protocol::InboundUpgrade { obs_addrs: self.obs_addrs.clone() })
+    }
+
+    fn inject_fully_negotiated_inbound(&mut self, remote_addrs: Vec<Multiaddr>) {
+        self.queued_events.push_back(ProtocolsHandlerEvent::Custom(
+            Event::InboundConnectNegotiated(remote_addrs)
+        ));
+    }
+
+    fn inject_fully_negotiated_outbound(&mut self, remote_addrs: Vec<Multiaddr>, _: ()) {
+        self.queued_events.push_back(ProtocolsHandlerEvent::Custom(
+            Event::OutboundConnectNegotiated(remote_addrs)
+        ));
+    }
+
+    fn inject_event(&mut self, event: In) {
+        match event {
+            In::Connect => {
+                self.keep_alive = KeepAlive::Until(Instant::now() + KEEP_ALIVE_TIMEOUT);
+                self.queued_events.push_back(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                    protocol: SubstreamProtocol::new(protocol::OutboundUpgrade {
+                        obs_addrs: self.obs_addrs.clone(),
+                    }),
+                    info: (),
+                });
+            }
+        }
+    }
+
+    fn inject_dial_upgrade_error(&mut self, _: (), error: ProtocolsHandlerUpgrErr<io::Error>) {
+        self.queued_events.push_back(ProtocolsHandlerEvent::Custom(
+            Event::OutboundNegotiationFailed { error }
+        ));
+    }
+
+    fn inject_listen_upgrade_error(&mut self, error: ProtocolsHandlerUpgrErr<io::Error>) {
+        log::debug!("Inbound hole punch failed: {:?}", error);
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        self.keep_alive
+    }
+
+    fn poll(&mut self, _: &mut Context<'_>) -> Poll<
+        ProtocolsHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::OutEvent, Self::Error>
+    > {
+        if let Some(event) = self.queued_events.pop_front() {
+            return Poll::Ready(event);
+        }
+
+        Poll::Pending
+    }
+}
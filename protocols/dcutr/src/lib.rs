@@ -0,0 +1,251 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Implementation of the [Direct Connection Upgrade through Relay
+//! (DCUtR)](https://github.com/libp2p/specs/blob/master/relay/DCUtR.md) protocol.
+//!
+//! Two peers connected through a relay, e.g. via `libp2p-relay`, use the relayed
+//! connection to exchange their addresses and synchronise a simultaneous dial
+//! of each other. If both are behind NATs allowing it, the dials punch holes
+//! through the NATs and a direct connection is established.
+//!
+//! The peer that accepted the relayed connection initiates the hole punch. It
+//! measures the round-trip time of the relayed connection while exchanging
+//! addresses, so that both sides start dialing at the same time. Failed hole
+//! punches are retried a few times before giving up.
+//!
+//! The addresses announced to the remote are the addresses the
+//! [`Swarm`](libp2p_swarm::Swarm) listens on and the external addresses
+//! reported to it, excluding relayed addresses.
+
+pub mod handler;
+mod protocol;
+
+mod message_proto {
+    include!(concat!(env!("OUT_DIR"), "/holepunch.pb.rs"));
+}
+
+pub use protocol::PROTOCOL_NAME;
+
+use handler::Handler;
+use libp2p_core::{
+    connection::ConnectionId,
+    multiaddr::Protocol,
+    ConnectedPoint,
+    Multiaddr,
+    PeerId,
+};
+use libp2p_swarm::{
+    DialPeerCondition,
+    NetworkBehaviour,
+    NetworkBehaviourAction,
+    NotifyHandler,
+    PollParameters,
+    ProtocolsHandlerUpgrErr,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    task::{Context, Poll},
+};
+
+/// Number of hole punches attempted before giving up.
+const MAX_NUMBER_OF_UPGRADE_ATTEMPTS: u8 = 3;
+
+/// Event emitted by [`Dcutr`].
+#[derive(Debug)]
+pub enum DcutrEvent {
+    /// We initiated a hole punch with a peer we are connected to via a relay.
+    InitiatedDirectConnectionUpgrade { remote_peer_id: PeerId },
+    /// A peer we are connected to via a relay initiated a hole punch.
+    RemoteInitiatedDirectConnectionUpgrade { remote_peer_id: PeerId },
+    /// A direct connection to a peer has been established by hole punching.
+    DirectConnectionUpgradeSucceeded { remote_peer_id: PeerId },
+    /// Hole punching to a peer failed.
+    DirectConnectionUpgradeFailed { remote_peer_id: PeerId, error: UpgradeError },
+}
+
+/// Reason a hole punch failed.
+#[derive(Debug)]
+pub enum UpgradeError {
+    /// None of the addresses of the remote could be dialed.
+    Dial,
+    /// The hole punch could not be negotiated over the relayed connection.
+    Handler(ProtocolsHandlerUpgrErr<io::Error>),
+}
+
+/// `NetworkBehaviour` upgrading relayed connections to direct connections.
+pub struct Dcutr {
+    /// Our non-relayed addresses, announced to the remote.
+    local_addrs: Vec<Multiaddr>,
+    /// Addresses of peers we are hole punching to.
+    direct_addrs: HashMap<PeerId, Vec<Multiaddr>>,
+    /// Relayed connection and number of attempts made, for the hole punches we
+    /// initiated.
+    attempts: HashMap<PeerId, (ConnectionId, u8)>,
+    queued_actions: VecDeque<NetworkBehaviourAction<handler::In, DcutrEvent>>,
+}
+
+impl Dcutr {
+    /// Creates a new `Dcutr` behaviour.
+    pub fn new() -> Self {
+        Dcutr {
+            local_addrs: Vec::new(),
+            direct_addrs: HashMap::new(),
+            attempts: HashMap::new(),
+            queued_actions: VecDeque::new(),
+        }
+    }
+
+    fn add_local_addr(&mut self, addr: &Multiaddr) {
+        if !is_relayed(addr) && !self.local_addrs.contains(addr) {
+            self.local_addrs.push(addr.clone());
+        }
+    }
+
+    fn dial(&mut self, peer: PeerId, remote_addrs: Vec<Multiaddr>) {
+        self.direct_addrs.insert(peer.clone(), remote_addrs);
+        self.queued_actions.push_back(NetworkBehaviourAction::DialPeer {
+            peer_id: peer,
+            condition: DialPeerCondition::Always,
+        });
+    }
+
+    fn upgrade_failed(&mut self, peer: PeerId, error: UpgradeError) {
+        self.attempts.remove(&peer);
+        self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+            DcutrEvent::DirectConnectionUpgradeFailed { remote_peer_id: peer, error }
+        ));
+    }
+}
+
+impl Default for Dcutr {
+    fn default() -> Self {
+        Dcutr::new()
+    }
+}
+
+impl NetworkBehaviour for Dcutr {
+    type ProtocolsHandler = Handler;
+    type OutEvent = DcutrEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        Handler::new(self.local_addrs.clone())
+    }
+
+    fn addresses_of_peer(&mut self, peer: &PeerId) -> Vec<Multiaddr> {
+        self.direct_addrs.get(peer).cloned().unwrap_or_default()
+    }
+
+    fn inject_connected(&mut self, _: &PeerId) {}
+
+    fn inject_disconnected(&mut self, peer: &PeerId) {
+        self.direct_addrs.remove(peer);
+        self.attempts.remove(peer);
+    }
+
+    fn inject_connection_established(&mut self, peer: &PeerId, connection: &ConnectionId, endpoint: &ConnectedPoint) {
+        match endpoint {
+            ConnectedPoint::Listener { local_addr, .. } if is_relayed(local_addr) => {
+                // The remote reached us through a relay, try to upgrade to a
+                // direct connection.
+                self.attempts.insert(peer.clone(), (*connection, 1));
+                self.queued_actions.push_back(NetworkBehaviourAction::NotifyHandler {
+                    peer_id: peer.clone(),
+                    handler: NotifyHandler::One(*connection),
+                    event: handler::In::Connect,
+                });
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    DcutrEvent::InitiatedDirectConnectionUpgrade { remote_peer_id: peer.clone() }
+                ));
+            }
+            ConnectedPoint::Dialer { address } if is_relayed(address) => {}
+            _ => {
+                if self.direct_addrs.remove(peer).is_some() {
+                    self.attempts.remove(peer);
+                    self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                        DcutrEvent::DirectConnectionUpgradeSucceeded { remote_peer_id: peer.clone() }
+                    ));
+                }
+            }
+        }
+    }
+
+    fn inject_dial_failure(&mut self, peer: &PeerId) {
+        if self.direct_addrs.remove(peer).is_none() {
+            return;
+        }
+
+        match self.attempts.get_mut(peer) {
+            Some((connection, attempts)) if *attempts < MAX_NUMBER_OF_UPGRADE_ATTEMPTS => {
+                *attempts += 1;
+                self.queued_actions.push_back(NetworkBehaviourAction::NotifyHandler {
+                    peer_id: peer.clone(),
+                    handler: NotifyHandler::One(*connection),
+                    event: handler::In::Connect,
+                });
+            }
+            _ => self.upgrade_failed(peer.clone(), UpgradeError::Dial),
+        }
+    }
+
+    fn inject_new_listen_addr(&mut self, addr: &Multiaddr) {
+        self.add_local_addr(addr);
+    }
+
+    fn inject_expired_listen_addr(&mut self, addr: &Multiaddr) {
+        self.local_addrs.retain(|a| a != addr);
+    }
+
+    fn inject_new_external_addr(&mut self, addr: &Multiaddr) {
+        self.add_local_addr(addr);
+    }
+
+    fn inject_event(&mut self, peer: PeerId, _: ConnectionId, event: handler::Event) {
+        match event {
+            handler::Event::InboundConnectNegotiated(remote_addrs) => {
+                self.dial(peer.clone(), remote_addrs);
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    DcutrEvent::RemoteInitiatedDirectConnectionUpgrade { remote_peer_id: peer }
+                ));
+            }
+            handler::Event::OutboundConnectNegotiated(remote_addrs) => {
+                self.dial(peer, remote_addrs);
+            }
+            handler::Event::OutboundNegotiationFailed { error } => {
+                self.upgrade_failed(peer, UpgradeError::Handler(error));
+            }
+        }
+    }
+
+    fn poll(&mut self, _: &mut Context<'_>, _: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<handler::In, Self::OutEvent>>
+    {
+        if let Some(action) = self.queued_actions.pop_front() {
+            return Poll::Ready(action);
+        }
+
+        Poll::Pending
+    }
+}
+
+fn is_relayed(addr: &Multiaddr) -> bool {
+    addr.iter().any(|p| p == Protocol::P2pCircuit)
+}
@@ -0,0 +1,163 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::message_proto::{hole_punch, HolePunch};
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::{
+    multiaddr::{Multiaddr, Protocol},
+    upgrade::{self, ReadOneError},
+};
+use libp2p_swarm::NegotiatedSubstream;
+use prost::Message;
+use std::{convert::TryFrom, error, io, iter};
+use wasm_timer::{Delay, Instant};
+
+/// Protocol name of DCUtR.
+pub const PROTOCOL_NAME: &[u8] = b"/libp2p/dcutr";
+
+/// Maximum size of a single protocol message.
+const MAX_MESSAGE_SIZE: usize = 4096;
+
+/// Upgrade of the peer that accepted the relayed connection, initiating the
+/// hole punch.
+///
+/// Sends our addresses with a `CONNECT`, measures the round-trip time until
+/// the remote answers with its addresses and sends a `SYNC`. The upgrade only
+/// completes half a round-trip time later, i.e. when the remote receives the
+/// `SYNC`, so that both sides start dialing at the same time.
+#[derive(Debug, Clone)]
+pub struct OutboundUpgrade {
+    pub obs_addrs: Vec<Multiaddr>,
+}
+
+impl upgrade::UpgradeInfo for OutboundUpgrade {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(PROTOCOL_NAME)
+    }
+}
+
+impl upgrade::OutboundUpgrade<NegotiatedSubstream> for OutboundUpgrade {
+    type Output = Vec<Multiaddr>;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, mut substream: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+        async move {
+            send(&mut substream, hole_punch::Type::Connect, &self.obs_addrs).await?;
+            let sent_at = Instant::now();
+
+            let remote_addrs = recv(&mut substream, hole_punch::Type::Connect).await?;
+            let rtt = sent_at.elapsed();
+            log::debug!("Measured round-trip time of {:?} over relayed connection.", rtt);
+
+            send(&mut substream, hole_punch::Type::Sync, &[]).await?;
+            substream.close().await?;
+
+            Delay::new(rtt / 2).await?;
+
+            Ok(remote_addrs)
+        }.boxed()
+    }
+}
+
+/// Upgrade of the peer that dialed the relayed connection, answering the
+/// hole punch of the remote.
+///
+/// Completes as soon as the `SYNC` of the remote is received.
+#[derive(Debug, Clone)]
+pub struct InboundUpgrade {
+    pub obs_addrs: Vec<Multiaddr>,
+}
+
+impl upgrade::UpgradeInfo for InboundUpgrade {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(PROTOCOL_NAME)
+    }
+}
+
+impl upgrade::InboundUpgrade<NegotiatedSubstream> for InboundUpgrade {
+    type Output = Vec<Multiaddr>;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, mut substream: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+        async move {
+            let remote_addrs = recv(&mut substream, hole_punch::Type::Connect).await?;
+            send(&mut substream, hole_punch::Type::Connect, &self.obs_addrs).await?;
+            recv(&mut substream, hole_punch::Type::Sync).await?;
+
+            Ok(remote_addrs)
+        }.boxed()
+    }
+}
+
+async fn send(substream: &mut NegotiatedSubstream, ty: hole_punch::Type, addrs: &[Multiaddr]) -> io::Result<()> {
+    let message = HolePunch {
+        r#type: ty.into(),
+        obs_addrs: addrs.iter().map(|a| a.to_vec()).collect(),
+    };
+
+    let mut buf = Vec::with_capacity(message.encoded_len());
+    message.encode(&mut buf).expect("Vec<u8> provides capacity as needed");
+    upgrade::write_with_len_prefix(substream, buf).await
+}
+
+/// Reads a message of the given type, returning the addresses it carries.
+///
+/// Relayed addresses are discarded, as they are of no use for a direct
+/// connection.
+async fn recv(substream: &mut NegotiatedSubstream, ty: hole_punch::Type) -> io::Result<Vec<Multiaddr>> {
+    let buf = upgrade::read_one(substream, MAX_MESSAGE_SIZE).await
+        .map_err(|e| match e {
+            ReadOneError::Io(e) => e,
+            e => invalid_data(e),
+        })?;
+    if buf.is_empty() {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    let HolePunch { r#type, obs_addrs } = HolePunch::decode(&buf[..]).map_err(invalid_data)?;
+    if hole_punch::Type::from_i32(r#type) != Some(ty) {
+        return Err(invalid_data(format!("Expected {:?} message", ty)));
+    }
+
+    let addrs = obs_addrs.into_iter()
+        .filter_map(|a| match Multiaddr::try_from(a) {
+            Ok(a) => Some(a),
+            Err(e) => {
+                log::debug!("Ignoring invalid address: {:?}", e);
+                None
+            }
+        })
+        .filter(|a| !a.iter().any(|p| p == Protocol::P2pCircuit))
+        .collect();
+
+    Ok(addrs)
+}
+
+fn invalid_data(e: impl Into<Box<dyn error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
@@ -0,0 +1,177 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Integration tests for upgrading relayed connections to direct connections.
+
+use futures::{executor::block_on, prelude::*};
+use libp2p::NetworkBehaviour;
+use libp2p_core::{
+    identity,
+    multiaddr::{Multiaddr, Protocol},
+    muxing::StreamMuxerBox,
+    transport::{boxed::Boxed, MemoryTransport, Transport},
+    upgrade,
+    PeerId,
+};
+use libp2p_dcutr::{Dcutr, DcutrEvent};
+use libp2p_plaintext::PlainText2Config;
+use libp2p_relay::{client, Relay, RelayConfig};
+use libp2p_swarm::{Swarm, SwarmEvent};
+use libp2p_yamux as yamux;
+use std::io;
+
+#[test]
+fn connect_directly_after_relayed_connection() {
+    let _ = env_logger::try_init();
+
+    let relay_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    let mut relay = build_relay();
+    let relay_peer_id = Swarm::local_peer_id(&relay).clone();
+    Swarm::listen_on(&mut relay, relay_addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        loop {
+            relay.next().await;
+        }
+    });
+
+    let mut dst = build_client();
+    let dst_peer_id = Swarm::local_peer_id(&dst).clone();
+    let dst_relayed_addr = relay_addr
+        .with(Protocol::P2p(relay_peer_id.into()))
+        .with(Protocol::P2pCircuit);
+    block_on(listen(&mut dst, Protocol::Memory(rand::random::<u64>()).into()));
+    block_on(listen(&mut dst, dst_relayed_addr.clone()));
+
+    let mut src = build_client();
+    let src_peer_id = Swarm::local_peer_id(&src).clone();
+    block_on(listen(&mut src, Protocol::Memory(rand::random::<u64>()).into()));
+
+    Swarm::dial_addr(&mut src, dst_relayed_addr.with(Protocol::P2p(dst_peer_id.clone().into()))).unwrap();
+
+    block_on(async {
+        let src_events = wait_for_upgrade(&mut src, dst_peer_id.clone());
+        let dst_events = wait_for_upgrade(&mut dst, src_peer_id.clone());
+        let (src_events, dst_events) = future::join(src_events, dst_events).await;
+
+        assert_eq!(src_events, vec!["remote initiated", "succeeded"]);
+        assert_eq!(dst_events, vec!["initiated", "succeeded"]);
+    });
+}
+
+async fn listen(swarm: &mut Swarm<Client>, addr: Multiaddr) {
+    Swarm::listen_on(swarm, addr.clone()).unwrap();
+    loop {
+        match swarm.next_event().await {
+            SwarmEvent::NewListenAddr(a) if a == addr => return,
+            SwarmEvent::Behaviour(ClientEvent::Dcutr(e)) => panic!("Unexpected event: {:?}", e),
+            _ => {}
+        }
+    }
+}
+
+/// Drives the swarm until the hole punch to `remote` succeeded, returning the
+/// DCUtR events emitted in the meantime.
+async fn wait_for_upgrade(swarm: &mut Swarm<Client>, remote: PeerId) -> Vec<&'static str> {
+    let mut events = Vec::new();
+    loop {
+        if let SwarmEvent::Behaviour(ClientEvent::Dcutr(event)) = swarm.next_event().await {
+            match event {
+                DcutrEvent::InitiatedDirectConnectionUpgrade { remote_peer_id } => {
+                    assert_eq!(remote_peer_id, remote);
+                    events.push("initiated");
+                }
+                DcutrEvent::RemoteInitiatedDirectConnectionUpgrade { remote_peer_id } => {
+                    assert_eq!(remote_peer_id, remote);
+                    events.push("remote initiated");
+                }
+                DcutrEvent::DirectConnectionUpgradeSucceeded { remote_peer_id } => {
+                    assert_eq!(remote_peer_id, remote);
+                    events.push("succeeded");
+                    return events;
+                }
+                e => panic!("Unexpected event: {:?}", e),
+            }
+        }
+    }
+}
+
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "ClientEvent", event_process = false)]
+struct Client {
+    relay: client::Client,
+    dcutr: Dcutr,
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+enum ClientEvent {
+    Relay(client::ClientEvent),
+    Dcutr(DcutrEvent),
+}
+
+impl From<client::ClientEvent> for ClientEvent {
+    fn from(event: client::ClientEvent) -> Self {
+        ClientEvent::Relay(event)
+    }
+}
+
+impl From<DcutrEvent> for ClientEvent {
+    fn from(event: DcutrEvent) -> Self {
+        ClientEvent::Dcutr(event)
+    }
+}
+
+fn build_relay() -> Swarm<Relay> {
+    let local_public_key = identity::Keypair::generate_ed25519().public();
+    let local_peer_id = local_public_key.clone().into_peer_id();
+    let transport = upgrade_transport(MemoryTransport, local_public_key);
+
+    Swarm::new(transport, Relay::new(local_peer_id.clone(), RelayConfig::default()), local_peer_id)
+}
+
+fn build_client() -> Swarm<Client> {
+    let local_public_key = identity::Keypair::generate_ed25519().public();
+    let local_peer_id = local_public_key.clone().into_peer_id();
+
+    let (relay_transport, relay_behaviour) = client::Client::new_transport_and_behaviour();
+    let transport = upgrade_transport(relay_transport.or_transport(MemoryTransport), local_public_key);
+    let behaviour = Client { relay: relay_behaviour, dcutr: Dcutr::new() };
+
+    Swarm::new(transport, behaviour, local_peer_id)
+}
+
+fn upgrade_transport<T>(transport: T, local_public_key: identity::PublicKey)
+    -> Boxed<(PeerId, StreamMuxerBox), io::Error>
+where
+    T: Transport + Clone + Send + Sync + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Error: Send + Sync + 'static,
+    T::Dial: Send + 'static,
+    T::Listener: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+{
+    transport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(PlainText2Config { local_public_key })
+        .multiplex(yamux::Config::default())
+        .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+        .boxed()
+}
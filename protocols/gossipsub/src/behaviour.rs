@@ -18,16 +18,22 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::config::GossipsubConfig;
+use crate::config::{GossipsubConfig, IDENTITY_SOURCE};
 use crate::handler::GossipsubHandler;
 use crate::mcache::MessageCache;
+use crate::peer_score::{PeerScore, PeerScoreParams, PeerScoreThresholds, TopicScoreParams};
 use crate::protocol::{
     GossipsubControlAction, GossipsubMessage, GossipsubSubscription, GossipsubSubscriptionAction,
     MessageId,
 };
 use crate::topic::{Topic, TopicHash};
 use futures::prelude::*;
-use libp2p_core::{Multiaddr, PeerId, connection::ConnectionId};
+use libp2p_core::{
+    connection::ConnectionId,
+    identity::{error::SigningError, Keypair},
+    multiaddr::Protocol,
+    ConnectedPoint, Multiaddr, PeerId,
+};
 use libp2p_swarm::{
     NetworkBehaviour,
     NetworkBehaviourAction,
@@ -43,9 +49,12 @@ use std::{
     collections::hash_map::HashMap,
     collections::HashSet,
     collections::VecDeque,
+    error, fmt,
     iter,
+    net::IpAddr,
     sync::Arc,
     task::{Context, Poll},
+    time::Duration,
 };
 use wasm_timer::{Instant, Interval};
 
@@ -63,8 +72,8 @@ pub struct Gossipsub {
     /// Pools non-urgent control messages between heartbeats.
     control_pool: HashMap<PeerId, Vec<GossipsubControlAction>>,
 
-    /// Peer id of the local node. Used for the source of the messages that we publish.
-    local_peer_id: PeerId,
+    /// How the messages that we publish are authored and signed.
+    authenticity: MessageAuthenticity,
 
     /// A map of all connected peers - A map of topic hash to a list of gossipsub peer Ids.
     topic_peers: HashMap<TopicHash, Vec<PeerId>>,
@@ -84,28 +93,52 @@ pub struct Gossipsub {
     /// Message cache for the last few heartbeats.
     mcache: MessageCache,
 
-    // We keep track of the messages we received (in the format `string(source ID, seq_no)`) so that
-    // we don't dispatch the same message twice if we receive it twice on the network.
-    received: LruCache<MessageId, ()>,
+    // We keep track of the messages we received, together with the instant they were first
+    // received, so that we don't dispatch the same message twice if we receive it twice on the
+    // network.
+    received: LruCache<MessageId, Instant>,
 
     /// Heartbeat interval stream.
     heartbeat: Interval,
+
+    /// Number of heartbeats since the beginning of time; this allows us to amortize some resource
+    /// clean up (e.g. opportunistic grafting).
+    heartbeat_ticks: u64,
+
+    /// Peers that must not be grafted into the mesh of a topic before the associated instant,
+    /// as the result of a PRUNE.
+    backoffs: HashMap<TopicHash, HashMap<PeerId, Instant>>,
+
+    /// The peer scoring state together with its thresholds and the interval at which scores
+    /// are decayed. `None` if peer scoring is disabled.
+    peer_score: Option<(PeerScore, PeerScoreThresholds, Interval)>,
 }
 
 impl Gossipsub {
     /// Creates a `Gossipsub` struct given a set of parameters specified by `gs_config`.
+    ///
+    /// Published messages carry `local_peer_id` as their source, but are not signed.
     pub fn new(local_peer_id: PeerId, gs_config: GossipsubConfig) -> Self {
-        let local_peer_id = if gs_config.no_source_id {
-            PeerId::from_bytes(crate::config::IDENTITY_SOURCE.to_vec()).expect("Valid peer id")
-        } else {
-            local_peer_id
+        Self::with_authenticity(MessageAuthenticity::Author(local_peer_id), gs_config)
+    }
+
+    /// Creates a `Gossipsub` struct given a set of parameters specified by `gs_config`, with
+    /// published messages authored and signed according to `authenticity`.
+    pub fn with_authenticity(authenticity: MessageAuthenticity, gs_config: GossipsubConfig) -> Self {
+        let authenticity = match authenticity {
+            MessageAuthenticity::Author(_) if gs_config.no_source_id => {
+                MessageAuthenticity::Author(
+                    PeerId::from_bytes(IDENTITY_SOURCE.to_vec()).expect("Valid peer id"),
+                )
+            }
+            authenticity => authenticity,
         };
 
         Gossipsub {
             config: gs_config.clone(),
             events: VecDeque::new(),
             control_pool: HashMap::new(),
-            local_peer_id,
+            authenticity,
             topic_peers: HashMap::new(),
             peer_topics: HashMap::new(),
             mesh: HashMap::new(),
@@ -116,11 +149,68 @@ impl Gossipsub {
                 gs_config.history_length,
                 gs_config.message_id_fn,
             ),
-            received: LruCache::new(256), // keep track of the last 256 messages
+            received: LruCache::new(gs_config.duplicate_cache_size),
             heartbeat: Interval::new_at(
                 Instant::now() + gs_config.heartbeat_initial_delay,
                 gs_config.heartbeat_interval,
             ),
+            heartbeat_ticks: 0,
+            backoffs: HashMap::new(),
+            peer_score: None,
+        }
+    }
+
+    /// Activates the peer scoring system with the given parameters. This will reset all scores
+    /// if there was already another peer scoring system activated. Returns an error if the
+    /// params are not valid.
+    pub fn with_peer_score(
+        &mut self,
+        params: PeerScoreParams,
+        threshold: PeerScoreThresholds,
+    ) -> Result<(), String> {
+        params.validate()?;
+        threshold.validate()?;
+
+        let interval = Interval::new(params.decay_interval);
+        let mut peer_score = PeerScore::new(params);
+        for peer_id in self.peer_topics.keys() {
+            peer_score.add_peer(peer_id.clone());
+        }
+        self.peer_score = Some((peer_score, threshold, interval));
+        Ok(())
+    }
+
+    /// Returns the score of a peer, if peer scoring is enabled.
+    pub fn peer_score(&self, peer_id: &PeerId) -> Option<f64> {
+        self.peer_score
+            .as_ref()
+            .map(|(peer_score, ..)| peer_score.score(peer_id))
+    }
+
+    /// Sets the application specific score for a peer. Returns true if scoring is active and
+    /// the peer is connected or if the score of the peer is not yet expired, false otherwise.
+    pub fn set_application_score(&mut self, peer_id: &PeerId, new_score: f64) -> bool {
+        if let Some((peer_score, ..)) = &mut self.peer_score {
+            peer_score.set_application_score(peer_id, new_score)
+        } else {
+            false
+        }
+    }
+
+    /// Sets the scoring parameters for a topic. Returns an error if peer scoring is disabled or
+    /// if the parameters are not valid.
+    pub fn set_topic_params(
+        &mut self,
+        topic: Topic,
+        params: TopicScoreParams,
+    ) -> Result<(), &'static str> {
+        params.validate()?;
+        let topic_hash = self.topic_hash(topic);
+        if let Some((peer_score, ..)) = &mut self.peer_score {
+            peer_score.set_topic_params(topic_hash, params);
+            Ok(())
+        } else {
+            Err("Peer score must be initialised with `with_peer_score()`")
         }
     }
 
@@ -215,25 +305,52 @@ impl Gossipsub {
         true
     }
 
-    /// Publishes a message to the network.
-    pub fn publish(&mut self, topic: &Topic, data: impl Into<Vec<u8>>) {
+    /// Publishes a message to the network. Returns the id of the message, or an error if it
+    /// could not be published.
+    pub fn publish(
+        &mut self,
+        topic: &Topic,
+        data: impl Into<Vec<u8>>,
+    ) -> Result<MessageId, PublishError> {
         self.publish_many(iter::once(topic.clone()), data)
     }
 
-    /// Publishes a message with multiple topics to the network.
+    /// Publishes a message with multiple topics to the network. Returns the id of the message, or
+    /// an error if it could not be published.
     pub fn publish_many(
         &mut self,
         topic: impl IntoIterator<Item = Topic>,
         data: impl Into<Vec<u8>>,
-    ) {
-        let message = GossipsubMessage {
-            source: self.local_peer_id.clone(),
+    ) -> Result<MessageId, PublishError> {
+        let (source, sequence_number) = match &self.authenticity {
+            MessageAuthenticity::Signed(keypair) => {
+                (Some(keypair.public().into_peer_id()), Some(rand::random()))
+            }
+            // To be interoperable with the go-implementation the sequence number is treated as a
+            // 64-bit big-endian uint.
+            MessageAuthenticity::Author(peer_id) => (Some(peer_id.clone()), Some(rand::random())),
+            MessageAuthenticity::Anonymous => (None, None),
+        };
+        let mut message = GossipsubMessage {
+            source,
             data: data.into(),
-            // To be interoperable with the go-implementation this is treated as a 64-bit
-            // big-endian uint.
-            sequence_number: rand::random(),
+            sequence_number,
             topics: topic.into_iter().map(|t| self.topic_hash(t)).collect(),
+            signature: None,
+            key: None,
         };
+        if let MessageAuthenticity::Signed(keypair) = &self.authenticity {
+            message.sign(keypair).map_err(PublishError::SigningError)?;
+        }
+
+        // peers would not accept a message that does not fit in a frame
+        let size = message.encoded_len();
+        if size > self.config.max_transmit_size {
+            return Err(PublishError::MessageTooLarge {
+                size,
+                max_size: self.config.max_transmit_size,
+            });
+        }
 
         debug!(
             "Publishing message: {:?}",
@@ -241,8 +358,7 @@ impl Gossipsub {
         );
 
         // forward the message to mesh peers
-        let local_peer_id = self.local_peer_id.clone();
-        self.forward_msg(message.clone(), &local_peer_id);
+        self.forward_msg(message.clone(), None);
 
         let mut recipient_peers = HashSet::new();
         for topic_hash in &message.topics {
@@ -258,9 +374,10 @@ impl Gossipsub {
                 } else {
                     // we have no fanout peers, select mesh_n of them and add them to the fanout
                     let mesh_n = self.config.mesh_n;
+                    let peer_score = &self.peer_score;
                     let new_peers =
                         Self::get_random_peers(&self.topic_peers, &topic_hash, mesh_n, {
-                            |_| true
+                            |p| !Self::score_below_threshold(peer_score, p, |t| t.publish_threshold)
                         });
                     // add the new peers to the fanout and recipient peers
                     self.fanout.insert(topic_hash.clone(), new_peers.clone());
@@ -278,7 +395,7 @@ impl Gossipsub {
         // add published message to our received caches
         let msg_id = (self.config.message_id_fn)(&message);
         self.mcache.put(message.clone());
-        self.received.put(msg_id.clone(), ());
+        self.received.put(msg_id.clone(), Instant::now());
 
         info!("Published message: {:?}", msg_id);
 
@@ -296,31 +413,72 @@ impl Gossipsub {
                 handler: NotifyHandler::Any,
             });
         }
+        Ok(msg_id)
     }
 
     /// This function should be called when `config.manual_propagation` is `true` in order to
     /// propagate messages. Messages are stored in the ['Memcache'] and validation is expected to be
-    /// fast enough that the messages should still exist in the cache.
+    /// fast enough that the messages should still exist in the cache. Until then, they are neither
+    /// forwarded, gossiped about nor served to peers requesting them.
     ///
     /// Calling this function will propagate a message stored in the cache, if it still exists.
-    /// If the message still exists in the cache, it will be forwarded and this function will return true,
-    /// otherwise it will return false.
+    /// If the message still exists in the cache and is awaiting validation, it will be forwarded
+    /// and this function will return true, otherwise it will return false.
     pub fn propagate_message(
         &mut self,
         message_id: &MessageId,
         propagation_source: &PeerId,
     ) -> bool {
-        let message = match self.mcache.get(message_id) {
+        let message = match self.mcache.validate(message_id) {
             Some(message) => message.clone(),
             None => {
                 warn!(
-                    "Message not in cache. Ignoring forwarding. Message Id: {}",
+                    "Message not in cache or already validated. Ignoring forwarding. Message Id: {}",
                     message_id.0
                 );
                 return false;
             }
         };
-        self.forward_msg(message, propagation_source);
+        if let Some((peer_score, ..)) = &mut self.peer_score {
+            peer_score.deliver_message(propagation_source, message_id, &message.topics);
+        }
+        self.forward_msg(message, Some(propagation_source));
+        true
+    }
+
+    /// This function should be called when `config.manual_propagation` is `true` to report the
+    /// outcome of the validation of a message to the peer scoring system.
+    ///
+    /// An accepted message is propagated, exactly as with `propagate_message()`. A rejected
+    /// message penalises the peers that delivered it, while an ignored one is dropped without
+    /// affecting any score. Neither of them are forwarded or gossiped about.
+    ///
+    /// Returns true if the message was still in the cache and awaiting validation.
+    pub fn report_message_validation_result(
+        &mut self,
+        message_id: &MessageId,
+        propagation_source: &PeerId,
+        acceptance: MessageAcceptance,
+    ) -> bool {
+        let message = match acceptance {
+            MessageAcceptance::Accept => {
+                return self.propagate_message(message_id, propagation_source)
+            }
+            MessageAcceptance::Reject | MessageAcceptance::Ignore => {
+                match self.mcache.remove_pending(message_id) {
+                    Some(message) => message,
+                    None => return false,
+                }
+            }
+        };
+
+        if let Some((peer_score, ..)) = &mut self.peer_score {
+            if let MessageAcceptance::Reject = acceptance {
+                peer_score.reject_message(propagation_source, message_id, &message.topics);
+            } else {
+                peer_score.ignore_message(message_id);
+            }
+        }
         true
     }
 
@@ -360,11 +518,17 @@ impl Gossipsub {
         // check if we need to get more peers, which we randomly select
         if added_peers.len() < self.config.mesh_n {
             // get the peers
+            let backoffs = &self.backoffs;
+            let peer_score = &self.peer_score;
             let new_peers = Self::get_random_peers(
                 &self.topic_peers,
                 topic_hash,
                 self.config.mesh_n - added_peers.len(),
-                |_| true,
+                |peer| {
+                    !added_peers.contains(peer)
+                        && !Self::is_backing_off(backoffs, topic_hash, peer)
+                        && Self::score_of(peer_score, peer) >= 0.0
+                },
             );
             added_peers.extend_from_slice(&new_peers);
             // add them to the mesh
@@ -380,6 +544,9 @@ impl Gossipsub {
         }
 
         for peer_id in added_peers {
+            if let Some((peer_score, ..)) = &mut self.peer_score {
+                peer_score.graft(&peer_id, topic_hash.clone());
+            }
             // Send a GRAFT control message
             info!("JOIN: Sending Graft message to peer: {:?}", peer_id);
             Self::control_pool_add(
@@ -402,13 +569,8 @@ impl Gossipsub {
             for peer in peers {
                 // Send a PRUNE control message
                 info!("LEAVE: Sending PRUNE to peer: {:?}", peer);
-                Self::control_pool_add(
-                    &mut self.control_pool,
-                    peer.clone(),
-                    GossipsubControlAction::Prune {
-                        topic_hash: topic_hash.clone(),
-                    },
-                );
+                let prune = self.make_prune(topic_hash, &peer);
+                Self::control_pool_add(&mut self.control_pool, peer.clone(), prune);
             }
         }
         debug!("Completed LEAVE for topic: {:?}", topic_hash);
@@ -418,6 +580,13 @@ impl Gossipsub {
     /// requests it with an IWANT control message.
     fn handle_ihave(&mut self, peer_id: &PeerId, ihave_msgs: Vec<(TopicHash, Vec<MessageId>)>) {
         debug!("Handling IHAVE for peer: {:?}", peer_id);
+        if Self::score_below_threshold(&self.peer_score, peer_id, |t| t.gossip_threshold) {
+            debug!(
+                "IHAVE: ignoring peer {:?} with score below threshold",
+                peer_id
+            );
+            return;
+        }
         // use a hashset to avoid duplicates efficiently
         let mut iwant_ids = HashSet::new();
 
@@ -432,7 +601,7 @@ impl Gossipsub {
             }
 
             for id in ids {
-                if !self.received.contains(&id) {
+                if !self.is_duplicate(&id) {
                     // have not seen this message, request it
                     iwant_ids.insert(id);
                 }
@@ -457,29 +626,52 @@ impl Gossipsub {
     /// forwarded to the requesting peer.
     fn handle_iwant(&mut self, peer_id: &PeerId, iwant_msgs: Vec<MessageId>) {
         debug!("Handling IWANT for peer: {:?}", peer_id);
+        if Self::score_below_threshold(&self.peer_score, peer_id, |t| t.gossip_threshold) {
+            debug!(
+                "IWANT: ignoring peer {:?} with score below threshold",
+                peer_id
+            );
+            return;
+        }
         // build a hashmap of available messages
         let mut cached_messages = HashMap::new();
 
         for id in iwant_msgs {
             // if we have it, add it do the cached_messages mapping
-            if let Some(msg) = self.mcache.get(&id) {
+            if let Some(msg) = self.mcache.get_validated(&id) {
                 cached_messages.insert(id.clone(), msg.clone());
             }
         }
 
         if !cached_messages.is_empty() {
             debug!("IWANT: Sending cached messages to peer: {:?}", peer_id);
-            // Send the messages to the peer
-            let message_list = cached_messages.into_iter().map(|entry| entry.1).collect();
-            self.events.push_back(NetworkBehaviourAction::NotifyHandler {
-                peer_id: peer_id.clone(),
-                handler: NotifyHandler::Any,
-                event: Arc::new(GossipsubRpc {
-                    subscriptions: Vec::new(),
-                    messages: message_list,
-                    control_msgs: Vec::new(),
-                }),
-            });
+            // Send the messages to the peer, split over as many RPCs as needed for each of them
+            // to fit within the maximum transmission size
+            let mut message_lists = vec![Vec::new()];
+            let mut list_size = 0;
+            for (_, message) in cached_messages {
+                let size = message.encoded_len();
+                if list_size + size > self.config.max_transmit_size && list_size > 0 {
+                    message_lists.push(Vec::new());
+                    list_size = 0;
+                }
+                list_size += size;
+                message_lists
+                    .last_mut()
+                    .expect("There is always a message list")
+                    .push(message);
+            }
+            for message_list in message_lists {
+                self.events.push_back(NetworkBehaviourAction::NotifyHandler {
+                    peer_id: peer_id.clone(),
+                    handler: NotifyHandler::Any,
+                    event: Arc::new(GossipsubRpc {
+                        subscriptions: Vec::new(),
+                        messages: message_list,
+                        control_msgs: Vec::new(),
+                    }),
+                });
+            }
         }
         debug!("Completed IWANT handling for peer: {:?}", peer_id);
     }
@@ -490,16 +682,57 @@ impl Gossipsub {
         debug!("Handling GRAFT message for peer: {:?}", peer_id);
 
         let mut to_prune_topics = HashSet::new();
+        let now = Instant::now();
+        let score = Self::score_of(&self.peer_score, peer_id);
         for topic_hash in topics {
             if let Some(peers) = self.mesh.get_mut(&topic_hash) {
-                // if we are subscribed, add peer to the mesh, if not already added
+                // ensure peer is not already added
+                if peers.contains(peer_id) {
+                    continue;
+                }
+
+                // the peer is not allowed to GRAFT again before its backoff expired
+                let backoff = self
+                    .backoffs
+                    .get(&topic_hash)
+                    .and_then(|peers| peers.get(peer_id))
+                    .cloned();
+                if let Some(backoff) = backoff.filter(|backoff| *backoff > now) {
+                    debug!(
+                        "GRAFT: Received GRAFT for peer {:?} that is backing off in topic {:?}",
+                        peer_id, topic_hash
+                    );
+                    if let Some((peer_score, ..)) = &mut self.peer_score {
+                        peer_score.add_penalty(peer_id, 1);
+                        // a GRAFT that follows our PRUNE too closely is flooding
+                        if now + self.config.prune_backoff
+                            < backoff + self.config.graft_flood_threshold
+                        {
+                            peer_score.add_penalty(peer_id, 1);
+                        }
+                    }
+                    to_prune_topics.insert(topic_hash.clone());
+                    continue;
+                }
+
+                // peers with a negative score are not accepted in the mesh
+                if score < 0.0 {
+                    debug!(
+                        "GRAFT: Ignoring peer {:?} with negative score: {}",
+                        peer_id, score
+                    );
+                    to_prune_topics.insert(topic_hash.clone());
+                    continue;
+                }
+
+                // if we are subscribed, add peer to the mesh
                 info!(
                     "GRAFT: Mesh link added for peer: {:?} in topic: {:?}",
                     peer_id, topic_hash
                 );
-                // ensure peer is not already added
-                if !peers.contains(peer_id) {
-                    peers.push(peer_id.clone());
+                peers.push(peer_id.clone());
+                if let Some((peer_score, ..)) = &mut self.peer_score {
+                    peer_score.graft(peer_id, topic_hash);
                 }
             } else {
                 to_prune_topics.insert(topic_hash.clone());
@@ -510,9 +743,7 @@ impl Gossipsub {
             // build the prune messages to send
             let prune_messages = to_prune_topics
                 .iter()
-                .map(|t| GossipsubControlAction::Prune {
-                    topic_hash: t.clone(),
-                })
+                .map(|t| self.make_prune(t, peer_id))
                 .collect();
             // Send the prune messages to the peer
             info!(
@@ -533,9 +764,9 @@ impl Gossipsub {
     }
 
     /// Handles PRUNE control messages. Removes peer from the mesh.
-    fn handle_prune(&mut self, peer_id: &PeerId, topics: Vec<TopicHash>) {
+    fn handle_prune(&mut self, peer_id: &PeerId, prunes: Vec<(TopicHash, Option<u64>)>) {
         debug!("Handling PRUNE message for peer: {:?}", peer_id);
-        for topic_hash in topics {
+        for (topic_hash, backoff) in prunes {
             if let Some(peers) = self.mesh.get_mut(&topic_hash) {
                 // remove the peer if it exists in the mesh
                 info!(
@@ -543,7 +774,16 @@ impl Gossipsub {
                     peer_id, topic_hash
                 );
                 peers.retain(|p| p != peer_id);
+                if let Some((peer_score, ..)) = &mut self.peer_score {
+                    peer_score.prune(peer_id, topic_hash.clone());
+                }
             }
+
+            // we must not GRAFT the remote again before the backoff it asked for is over
+            let backoff = backoff
+                .map(Duration::from_secs)
+                .unwrap_or(self.config.prune_backoff);
+            Self::add_backoff(&mut self.backoffs, &topic_hash, peer_id, backoff);
         }
         debug!("Completed PRUNE handling for peer: {:?}", peer_id);
     }
@@ -556,13 +796,44 @@ impl Gossipsub {
             "Handling message: {:?} from peer: {:?}",
             msg_id, propagation_source
         );
-        if self.received.put(msg_id.clone(), ()).is_some() {
+
+        // drop messages whose source, sequence number or signature don't pass validation, before
+        // they are cached, delivered or propagated
+        if let Err(reason) = msg.validate(self.config.validation_mode) {
+            warn!(
+                "Invalid message from peer: {:?}, dropping it: {}",
+                propagation_source, reason
+            );
+            if let Some((peer_score, ..)) = &mut self.peer_score {
+                peer_score.reject_invalid_message(propagation_source, &msg.topics);
+            }
+            return;
+        }
+
+        if self.is_duplicate(&msg_id) {
             debug!("Message already received, ignoring. Message: {:?}", msg_id);
+            if let Some((peer_score, ..)) = &mut self.peer_score {
+                peer_score.duplicated_message(propagation_source, &msg_id, &msg.topics);
+            }
             return;
         }
+        self.received.put(msg_id.clone(), Instant::now());
 
-        // add to the memcache
-        self.mcache.put(msg.clone());
+        // add to the memcache, withholding the message from gossip until it is validated
+        if self.config.manual_propagation {
+            self.mcache.put_pending(msg.clone());
+        } else {
+            self.mcache.put(msg.clone());
+        }
+
+        // the message is only delivered, as far as scoring is concerned, once it is validated
+        if let Some((peer_score, ..)) = &mut self.peer_score {
+            if self.config.manual_propagation {
+                peer_score.validate_message(propagation_source, &msg_id, &msg.topics);
+            } else {
+                peer_score.deliver_message(propagation_source, &msg_id, &msg.topics);
+            }
+        }
 
         // dispatch the message to the user
         if self.mesh.keys().any(|t| msg.topics.iter().any(|u| t == u)) {
@@ -575,11 +846,20 @@ impl Gossipsub {
         // forward the message to mesh peers, if no validation is required
         if !self.config.manual_propagation {
             let message_id = (self.config.message_id_fn)(&msg);
-            self.forward_msg(msg, propagation_source);
+            self.forward_msg(msg, Some(propagation_source));
             debug!("Completed message handling for message: {:?}", message_id);
         }
     }
 
+    /// Returns true if the message with the given id was first received less than
+    /// `duplicate_cache_ttl` ago.
+    fn is_duplicate(&self, msg_id: &MessageId) -> bool {
+        match self.received.peek(msg_id) {
+            Some(first_seen) => first_seen.elapsed() < self.config.duplicate_cache_ttl,
+            None => false,
+        }
+    }
+
     /// Handles received subscriptions.
     fn handle_received_subscriptions(
         &mut self,
@@ -682,11 +962,44 @@ impl Gossipsub {
     fn heartbeat(&mut self) {
         debug!("Starting heartbeat");
 
+        self.heartbeat_ticks += 1;
+
         let mut to_graft = HashMap::new();
         let mut to_prune = HashMap::new();
 
+        // clean up expired backoffs
+        self.clear_backoffs();
+
+        // compute the scores of the peers at most once per heartbeat
+        let mut scores = HashMap::new();
+        let peer_score = &self.peer_score;
+        let mut score = |p: &PeerId| {
+            *scores
+                .entry(p.clone())
+                .or_insert_with(|| Self::score_of(peer_score, p))
+        };
+
         // maintain the mesh for each topic
+        let backoffs = &self.backoffs;
+        let topic_peers = &self.topic_peers;
         for (topic_hash, peers) in self.mesh.iter_mut() {
+            // drop all peers with negative score
+            peers.retain(|peer| {
+                let peer_score = score(peer);
+                if peer_score < 0.0 {
+                    debug!(
+                        "HEARTBEAT: Prune peer {:?} with negative score [score = {}, topic = {}]",
+                        peer, peer_score, topic_hash
+                    );
+                    to_prune
+                        .entry(peer.clone())
+                        .or_insert_with(Vec::new)
+                        .push(topic_hash.clone());
+                    return false;
+                }
+                true
+            });
+
             // too little peers - add some
             if peers.len() < self.config.mesh_n_low {
                 debug!(
@@ -698,8 +1011,10 @@ impl Gossipsub {
                 // not enough peers - get mesh_n - current_length more
                 let desired_peers = self.config.mesh_n - peers.len();
                 let peer_list =
-                    Self::get_random_peers(&self.topic_peers, topic_hash, desired_peers, {
-                        |peer| !peers.contains(peer)
+                    Self::get_random_peers(topic_peers, topic_hash, desired_peers, |peer| {
+                        !peers.contains(peer)
+                            && !Self::is_backing_off(backoffs, topic_hash, peer)
+                            && score(peer) >= 0.0
                     });
                 for peer in &peer_list {
                     let current_topic = to_graft.entry(peer.clone()).or_insert_with(|| vec![]);
@@ -719,10 +1034,19 @@ impl Gossipsub {
                     self.config.mesh_n_high
                 );
                 let excess_peer_no = peers.len() - self.config.mesh_n;
-                // shuffle the peers
+                // shuffle the peers, then sort them by score while keeping the shuffled order of
+                // equally scored peers
                 let mut rng = thread_rng();
                 peers.shuffle(&mut rng);
-                // remove the first excess_peer_no peers adding them to to_prune
+                peers.sort_by(|p1, p2| {
+                    score(p2)
+                        .partial_cmp(&score(p1))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+                // keep the best `retain_scores` peers and shuffle the others
+                let retain_scores = std::cmp::min(self.config.retain_scores, peers.len());
+                peers[retain_scores..].shuffle(&mut rng);
+                // remove the last excess_peer_no peers adding them to to_prune
                 for _ in 0..excess_peer_no {
                     let peer = peers
                         .pop()
@@ -731,6 +1055,45 @@ impl Gossipsub {
                     current_topic.push(topic_hash.clone());
                 }
             }
+
+            // opportunistic grafting: if the median score of the mesh is too low, graft some
+            // peers with a score above the median
+            if let Some((_, thresholds, _)) = peer_score {
+                if self
+                    .heartbeat_ticks
+                    .is_multiple_of(self.config.opportunistic_graft_ticks)
+                    && peers.len() > 1
+                {
+                    let mut peer_scores: Vec<f64> = peers.iter().map(&mut score).collect();
+                    peer_scores.sort_by(|s1, s2| {
+                        s1.partial_cmp(s2).unwrap_or(std::cmp::Ordering::Equal)
+                    });
+                    let median = peer_scores[peers.len() / 2];
+                    if median < thresholds.opportunistic_graft_threshold {
+                        let peer_list = Self::get_random_peers(
+                            topic_peers,
+                            topic_hash,
+                            self.config.opportunistic_graft_peers,
+                            |peer| {
+                                !peers.contains(peer)
+                                    && !Self::is_backing_off(backoffs, topic_hash, peer)
+                                    && score(peer) > median
+                            },
+                        );
+                        debug!(
+                            "HEARTBEAT: Opportunistically graft {:?} in topic {:?}",
+                            peer_list, topic_hash
+                        );
+                        for peer in &peer_list {
+                            to_graft
+                                .entry(peer.clone())
+                                .or_insert_with(Vec::new)
+                                .push(topic_hash.clone());
+                        }
+                        peers.extend(peer_list);
+                    }
+                }
+            }
         }
 
         // remove expired fanout topics
@@ -751,14 +1114,20 @@ impl Gossipsub {
         }
 
         // maintain fanout
-        // check if our peers are still a part of the topic
+        // check if our peers are still a part of the topic and are still allowed to receive
+        // our messages
+        let peer_score = &self.peer_score;
         for (topic_hash, peers) in self.fanout.iter_mut() {
             let mut to_remove_peers = Vec::new();
             for peer in peers.iter() {
                 // is the peer still subscribed to the topic?
                 match self.peer_topics.get(peer) {
                     Some(topics) => {
-                        if !topics.contains(&topic_hash) {
+                        if !topics.contains(&topic_hash)
+                            || Self::score_below_threshold(peer_score, peer, |t| {
+                                t.publish_threshold
+                            })
+                        {
                             debug!(
                                 "HEARTBEAT: Peer removed from fanout for topic: {:?}",
                                 topic_hash
@@ -772,7 +1141,7 @@ impl Gossipsub {
                     }
                 }
             }
-            peers.retain(|peer| to_remove_peers.contains(&peer));
+            peers.retain(|peer| !to_remove_peers.contains(&peer));
 
             // not enough peers
             if peers.len() < self.config.mesh_n {
@@ -785,11 +1154,23 @@ impl Gossipsub {
                 let new_peers =
                     Self::get_random_peers(&self.topic_peers, topic_hash, needed_peers, |peer| {
                         !peers.contains(peer)
+                            && !Self::score_below_threshold(peer_score, peer, |t| {
+                                t.publish_threshold
+                            })
                     });
                 peers.extend(new_peers);
             }
         }
 
+        // update the scores and backoffs of the grafted and pruned peers
+        if let Some((peer_score, ..)) = &mut self.peer_score {
+            for (peer, topics) in to_graft.iter() {
+                for topic_hash in topics {
+                    peer_score.graft(peer, topic_hash.clone());
+                }
+            }
+        }
+
         self.emit_gossip();
 
         // send graft/prunes
@@ -809,6 +1190,7 @@ impl Gossipsub {
     /// and fanout peers
     fn emit_gossip(&mut self) {
         debug!("Started gossip");
+        let peer_score = &self.peer_score;
         for (topic_hash, peers) in self.mesh.iter().chain(self.fanout.iter()) {
             let message_ids = self.mcache.get_gossip_ids(&topic_hash);
             if message_ids.is_empty() {
@@ -820,7 +1202,12 @@ impl Gossipsub {
                 &self.topic_peers,
                 &topic_hash,
                 self.config.gossip_lazy,
-                |peer| !peers.contains(peer),
+                |peer| {
+                    !peers.contains(peer)
+                        && !Self::score_below_threshold(peer_score, peer, |t| {
+                            t.gossip_threshold
+                        })
+                },
             );
             for peer in to_msg_peers {
                 // send an IHAVE message
@@ -856,9 +1243,7 @@ impl Gossipsub {
                 .remove(peer)
                 .unwrap_or_else(|| vec![])
                 .iter()
-                .map(|topic_hash| GossipsubControlAction::Prune {
-                    topic_hash: topic_hash.clone(),
-                })
+                .map(|topic_hash| self.make_prune(topic_hash, peer))
                 .collect();
             grafts.append(&mut prunes);
 
@@ -878,9 +1263,7 @@ impl Gossipsub {
         for (peer, topics) in to_prune.iter() {
             let remaining_prunes = topics
                 .iter()
-                .map(|topic_hash| GossipsubControlAction::Prune {
-                    topic_hash: topic_hash.clone(),
-                })
+                .map(|topic_hash| self.make_prune(topic_hash, peer))
                 .collect();
             self.events.push_back(NetworkBehaviourAction::NotifyHandler {
                 peer_id: peer.clone(),
@@ -895,7 +1278,7 @@ impl Gossipsub {
     }
 
     /// Helper function which forwards a message to mesh\[topic\] peers.
-    fn forward_msg(&mut self, message: GossipsubMessage, source: &PeerId) {
+    fn forward_msg(&mut self, message: GossipsubMessage, source: Option<&PeerId>) {
         let msg_id = (self.config.message_id_fn)(&message);
         debug!("Forwarding message: {:?}", msg_id);
         let mut recipient_peers = HashSet::new();
@@ -905,7 +1288,7 @@ impl Gossipsub {
             // mesh
             if let Some(mesh_peers) = self.mesh.get(&topic) {
                 for peer_id in mesh_peers {
-                    if peer_id != source {
+                    if Some(peer_id) != source {
                         recipient_peers.insert(peer_id.clone());
                     }
                 }
@@ -973,6 +1356,88 @@ impl Gossipsub {
             .push(control);
     }
 
+    /// Creates a PRUNE control message for `peer_id` in `topic_hash` and makes sure that we don't
+    /// GRAFT the peer again before the backoff expired.
+    fn make_prune(&mut self, topic_hash: &TopicHash, peer_id: &PeerId) -> GossipsubControlAction {
+        if let Some((peer_score, ..)) = &mut self.peer_score {
+            peer_score.prune(peer_id, topic_hash.clone());
+        }
+        Self::add_backoff(
+            &mut self.backoffs,
+            topic_hash,
+            peer_id,
+            self.config.prune_backoff,
+        );
+        GossipsubControlAction::Prune {
+            topic_hash: topic_hash.clone(),
+            backoff: Some(self.config.prune_backoff.as_secs()),
+        }
+    }
+
+    /// Prevents `peer_id` from being grafted into the mesh of `topic_hash` for `backoff`, unless
+    /// it is already backing off for longer.
+    fn add_backoff(
+        backoffs: &mut HashMap<TopicHash, HashMap<PeerId, Instant>>,
+        topic_hash: &TopicHash,
+        peer_id: &PeerId,
+        backoff: Duration,
+    ) {
+        let until = Instant::now() + backoff;
+        let entry = backoffs
+            .entry(topic_hash.clone())
+            .or_default()
+            .entry(peer_id.clone())
+            .or_insert(until);
+        if *entry < until {
+            *entry = until;
+        }
+    }
+
+    /// Returns true if `peer_id` must not be grafted into the mesh of `topic_hash` yet.
+    fn is_backing_off(
+        backoffs: &HashMap<TopicHash, HashMap<PeerId, Instant>>,
+        topic_hash: &TopicHash,
+        peer_id: &PeerId,
+    ) -> bool {
+        backoffs
+            .get(topic_hash)
+            .and_then(|peers| peers.get(peer_id))
+            .is_some_and(|until| *until > Instant::now())
+    }
+
+    /// Removes the expired backoffs.
+    fn clear_backoffs(&mut self) {
+        let now = Instant::now();
+        self.backoffs.retain(|_, peers| {
+            peers.retain(|_, until| *until > now);
+            !peers.is_empty()
+        });
+    }
+
+    /// Returns the score of a peer, or 0 if peer scoring is disabled.
+    fn score_of(
+        peer_score: &Option<(PeerScore, PeerScoreThresholds, Interval)>,
+        peer_id: &PeerId,
+    ) -> f64 {
+        peer_score
+            .as_ref()
+            .map_or(0.0, |(peer_score, ..)| peer_score.score(peer_id))
+    }
+
+    /// Returns true if peer scoring is enabled and the score of `peer_id` is below the threshold
+    /// selected by `threshold`.
+    fn score_below_threshold(
+        peer_score: &Option<(PeerScore, PeerScoreThresholds, Interval)>,
+        peer_id: &PeerId,
+        threshold: impl Fn(&PeerScoreThresholds) -> f64,
+    ) -> bool {
+        peer_score
+            .as_ref()
+            .is_some_and(|(peer_score, thresholds, _)| {
+                peer_score.score(peer_id) < threshold(thresholds)
+            })
+    }
+
     /// Produces a `TopicHash` for a topic given the gossipsub configuration.
     fn topic_hash(&self, topic: Topic) -> TopicHash {
         if self.config.hash_topics {
@@ -1039,6 +1504,10 @@ impl NetworkBehaviour for Gossipsub {
 
         // For the time being assume all gossipsub peers
         self.peer_topics.insert(id.clone(), Vec::new());
+
+        if let Some((peer_score, ..)) = &mut self.peer_score {
+            peer_score.add_peer(id.clone());
+        }
     }
 
     fn inject_disconnected(&mut self, id: &PeerId) {
@@ -1089,9 +1558,56 @@ impl NetworkBehaviour for Gossipsub {
         // remove peer from peer_topics
         let was_in = self.peer_topics.remove(id);
         debug_assert!(was_in.is_some());
+
+        if let Some((peer_score, ..)) = &mut self.peer_score {
+            peer_score.remove_peer(id);
+        }
+    }
+
+    fn inject_connection_established(
+        &mut self,
+        peer_id: &PeerId,
+        _: &ConnectionId,
+        endpoint: &ConnectedPoint,
+    ) {
+        if let Some((peer_score, ..)) = &mut self.peer_score {
+            if let Some(ip) = get_ip_addr(endpoint) {
+                peer_score.add_ip(peer_id, ip);
+            } else {
+                trace!(
+                    "Couldn't extract ip from endpoint of peer {} with endpoint {:?}",
+                    peer_id,
+                    endpoint
+                );
+            }
+        }
+    }
+
+    fn inject_connection_closed(
+        &mut self,
+        peer_id: &PeerId,
+        _: &ConnectionId,
+        endpoint: &ConnectedPoint,
+    ) {
+        if let Some((peer_score, ..)) = &mut self.peer_score {
+            if let Some(ip) = get_ip_addr(endpoint) {
+                peer_score.remove_ip(peer_id, &ip);
+            }
+        }
     }
 
     fn inject_event(&mut self, propagation_source: PeerId, _: ConnectionId, event: GossipsubRpc) {
+        // ignore all RPCs of graylisted peers
+        if Self::score_below_threshold(&self.peer_score, &propagation_source, |t| {
+            t.graylist_threshold
+        }) {
+            debug!(
+                "RPC from graylisted peer {:?} ignored",
+                propagation_source
+            );
+            return;
+        }
+
         // Handle subscriptions
         // Update connected peers topics
         self.handle_received_subscriptions(&event.subscriptions, &propagation_source);
@@ -1118,7 +1634,10 @@ impl NetworkBehaviour for Gossipsub {
                     self.handle_iwant(&propagation_source, message_ids)
                 }
                 GossipsubControlAction::Graft { topic_hash } => graft_msgs.push(topic_hash),
-                GossipsubControlAction::Prune { topic_hash } => prune_msgs.push(topic_hash),
+                GossipsubControlAction::Prune {
+                    topic_hash,
+                    backoff,
+                } => prune_msgs.push((topic_hash, backoff)),
             }
         }
         if !ihave_msgs.is_empty() {
@@ -1168,8 +1687,20 @@ impl NetworkBehaviour for Gossipsub {
                 NetworkBehaviourAction::DialPeer { peer_id, condition } => {
                     return Poll::Ready(NetworkBehaviourAction::DialPeer { peer_id, condition });
                 }
-                NetworkBehaviourAction::ReportObservedAddr { address } => {
-                    return Poll::Ready(NetworkBehaviourAction::ReportObservedAddr { address });
+                NetworkBehaviourAction::ReportObservedAddr { address, peer_id } => {
+                    return Poll::Ready(NetworkBehaviourAction::ReportObservedAddr { address, peer_id });
+                }
+                NetworkBehaviourAction::AddExternalAddr { address } => {
+                    return Poll::Ready(NetworkBehaviourAction::AddExternalAddr { address });
+                }
+                NetworkBehaviourAction::ConfirmExternalAddr { address } => {
+                    return Poll::Ready(NetworkBehaviourAction::ConfirmExternalAddr { address });
+                }
+                NetworkBehaviourAction::RemoveExternalAddr { address } => {
+                    return Poll::Ready(NetworkBehaviourAction::RemoveExternalAddr { address });
+                }
+                NetworkBehaviourAction::UpdateSupportedProtocols => {
+                    return Poll::Ready(NetworkBehaviourAction::UpdateSupportedProtocols);
                 }
             }
         }
@@ -1178,10 +1709,106 @@ impl NetworkBehaviour for Gossipsub {
             self.heartbeat();
         }
 
+        if let Some((peer_score, _, interval)) = &mut self.peer_score {
+            while let Poll::Ready(Some(())) = interval.poll_next_unpin(cx) {
+                peer_score.refresh_scores();
+            }
+        }
+
         Poll::Pending
     }
 }
 
+/// Extracts the IP address of a peer from the endpoint of one of its connections.
+fn get_ip_addr(endpoint: &ConnectedPoint) -> Option<IpAddr> {
+    let addr = match endpoint {
+        ConnectedPoint::Dialer { address } => address,
+        ConnectedPoint::Listener { send_back_addr, .. } => send_back_addr,
+    };
+    addr.iter().find_map(|p| match p {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+/// Error returned when a message could not be published.
+#[derive(Debug)]
+pub enum PublishError {
+    /// The message, once encoded, exceeds the maximum transmission size.
+    MessageTooLarge {
+        /// The size of the encoded message.
+        size: usize,
+        /// The maximum transmission size.
+        max_size: usize,
+    },
+    /// The message could not be signed.
+    SigningError(SigningError),
+}
+
+impl fmt::Display for PublishError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublishError::MessageTooLarge { size, max_size } => write!(
+                f,
+                "Message of {} bytes exceeds the maximum transmission size of {} bytes",
+                size, max_size
+            ),
+            PublishError::SigningError(err) => write!(f, "Failed to sign message: {}", err),
+        }
+    }
+}
+
+impl error::Error for PublishError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            PublishError::MessageTooLarge { .. } => None,
+            PublishError::SigningError(err) => Some(err),
+        }
+    }
+}
+
+/// Determines how the messages published by the local node are authored and authenticated.
+#[derive(Clone)]
+pub enum MessageAuthenticity {
+    /// Messages carry the peer id of the keypair as source and a sequence number, and are signed
+    /// with the keypair.
+    Signed(Keypair),
+    /// Messages carry the given peer id as source and a sequence number, but are not signed.
+    Author(PeerId),
+    /// Messages carry neither a source, a sequence number nor a signature. As the default
+    /// `message_id_fn` is derived from the source and sequence number, a custom one must be
+    /// configured, and peers must not use `ValidationMode::Strict`.
+    Anonymous,
+}
+
+impl fmt::Debug for MessageAuthenticity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageAuthenticity::Signed(keypair) => f
+                .debug_tuple("Signed")
+                .field(&keypair.public().into_peer_id())
+                .finish(),
+            MessageAuthenticity::Author(peer_id) => f.debug_tuple("Author").field(peer_id).finish(),
+            MessageAuthenticity::Anonymous => f.write_str("Anonymous"),
+        }
+    }
+}
+
+/// The result of the validation of a message, reported by the application with
+/// [`Gossipsub::report_message_validation_result`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageAcceptance {
+    /// The message is valid; it is propagated and the peers that delivered it are rewarded.
+    Accept,
+    /// The message is invalid; it is not propagated and the peers that delivered it are
+    /// penalised.
+    Reject,
+    /// The message is neither propagated nor penalised, for instance because it is valid but
+    /// no longer relevant.
+    Ignore,
+}
+
 /// An RPC received/sent.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct GossipsubRpc {
@@ -23,6 +23,7 @@
 #[cfg(test)]
 mod tests {
     use super::super::*;
+    use crate::config::{GossipsubConfigBuilder, ValidationMode};
 
     // helper functions for testing
 
@@ -217,6 +218,8 @@ mod tests {
             gs.unsubscribe(topics[1].clone()),
             "should be able to unsubscribe successfully"
         );
+        // the peers pruned by LEAVE are backing off, forget about it to test JOIN on its own
+        gs.backoffs.clear();
 
         // re-subscribe - there should be peers associated with the topic
         assert!(
@@ -317,7 +320,7 @@ mod tests {
 
         // publish on topic
         let publish_data = vec![0; 42];
-        gs.publish(&Topic::new(publish_topic), publish_data);
+        gs.publish(&Topic::new(publish_topic), publish_data).unwrap();
 
         // Collect all publish messages
         let publishes = gs
@@ -342,7 +345,7 @@ mod tests {
         );
 
         assert!(
-            gs.mcache.get(&msg_id).is_some(),
+            gs.mcache.get_validated(&msg_id).is_some(),
             "Message cache should contain published message"
         );
         assert!(
@@ -374,7 +377,7 @@ mod tests {
 
         // Publish on unsubscribed topic
         let publish_data = vec![0; 42];
-        gs.publish(&Topic::new(fanout_topic.clone()), publish_data);
+        gs.publish(&Topic::new(fanout_topic.clone()), publish_data).unwrap();
 
         assert_eq!(
             gs.fanout
@@ -409,7 +412,7 @@ mod tests {
         );
 
         assert!(
-            gs.mcache.get(&msg_id).is_some(),
+            gs.mcache.get_validated(&msg_id).is_some(),
             "Message cache should contain published message"
         );
         assert!(
@@ -603,10 +606,12 @@ mod tests {
         let id = gs.config.message_id_fn;
 
         let message = GossipsubMessage {
-            source: peers[11].clone(),
+            source: Some(peers[11].clone()),
             data: vec![1, 2, 3, 4],
-            sequence_number: 1u64,
+            sequence_number: Some(1u64),
             topics: Vec::new(),
+            signature: None,
+            key: None,
         };
         let msg_id = id(&message);
         gs.mcache.put(message.clone());
@@ -642,10 +647,12 @@ mod tests {
         // perform 10 memshifts and check that it leaves the cache
         for shift in 1..10 {
             let message = GossipsubMessage {
-                source: peers[11].clone(),
+                source: Some(peers[11].clone()),
                 data: vec![1, 2, 3, 4],
-                sequence_number: shift,
+                sequence_number: Some(shift),
                 topics: Vec::new(),
+                signature: None,
+                key: None,
             };
             let msg_id = id(&message);
             gs.mcache.put(message.clone());
@@ -731,7 +738,7 @@ mod tests {
             build_and_inject_nodes(20, vec![String::from("topic1")], true);
 
         let msg_id = MessageId(String::from("known id"));
-        gs.received.put(msg_id.clone(), ());
+        gs.received.put(msg_id.clone(), Instant::now());
 
         let events_before = gs.events.len();
         gs.handle_ihave(&peers[7], vec![(topic_hashes[0].clone(), vec![msg_id])]);
@@ -842,10 +849,538 @@ mod tests {
             "Expected peer to be in mesh"
         );
 
-        gs.handle_prune(&peers[7], topic_hashes.clone());
+        gs.handle_prune(
+            &peers[7],
+            topic_hashes.iter().map(|t| (t.clone(), None)).collect(),
+        );
         assert!(
             !gs.mesh.get(&topic_hashes[0]).unwrap().contains(&peers[7]),
             "Expected peer to be removed from mesh"
         );
     }
+
+    // helper function that enables peer scoring with the default parameters, scoring `topics`
+    // with the default topic parameters
+    fn enable_peer_score(gs: &mut Gossipsub, topics: &[TopicHash]) {
+        let mut params = PeerScoreParams::default();
+        for topic_hash in topics {
+            params
+                .topics
+                .insert(topic_hash.clone(), TopicScoreParams::default());
+        }
+        gs.with_peer_score(params, PeerScoreThresholds::default())
+            .expect("Valid peer score parameters");
+    }
+
+    // helper function that returns the PRUNE control messages sent to `peer_id`
+    fn prunes_sent_to(gs: &Gossipsub, peer_id: &PeerId) -> Vec<(TopicHash, Option<u64>)> {
+        gs.events
+            .iter()
+            .filter_map(|e| match e {
+                NetworkBehaviourAction::NotifyHandler { peer_id: p, event, .. } if p == peer_id => {
+                    Some(event.control_msgs.clone())
+                }
+                _ => None,
+            })
+            .flatten()
+            .chain(gs.control_pool.get(peer_id).cloned().unwrap_or_default())
+            .filter_map(|c| match c {
+                GossipsubControlAction::Prune { topic_hash, backoff } => Some((topic_hash, backoff)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    // tests that a pruned peer is not grafted again before its backoff expired
+    fn test_prune_backoff() {
+        let (mut gs, peers, topic_hashes) =
+            build_and_inject_nodes(20, vec![String::from("topic1")], true);
+
+        gs.mesh.insert(topic_hashes[0].clone(), peers[..3].to_vec());
+        gs.handle_prune(&peers[0], vec![(topic_hashes[0].clone(), Some(100))]);
+
+        // the mesh is low, but peers[0] must not be grafted back
+        gs.heartbeat();
+        let mesh = gs.mesh.get(&topic_hashes[0]).unwrap();
+        assert_eq!(mesh.len(), gs.config.mesh_n);
+        assert!(!mesh.contains(&peers[0]), "Expected peer to be backing off");
+
+        // a GRAFT of a backing off peer is answered with a PRUNE
+        gs.handle_graft(&peers[0], topic_hashes.clone());
+        assert!(!gs.mesh.get(&topic_hashes[0]).unwrap().contains(&peers[0]));
+        assert_eq!(
+            prunes_sent_to(&gs, &peers[0]),
+            vec![(
+                topic_hashes[0].clone(),
+                Some(gs.config.prune_backoff.as_secs())
+            )]
+        );
+    }
+
+    #[test]
+    // tests that the peers we prune receive a backoff
+    fn test_leave_sends_prune_with_backoff() {
+        let (mut gs, peers, topic_hashes) =
+            build_and_inject_nodes(20, vec![String::from("topic1")], true);
+
+        gs.mesh.insert(topic_hashes[0].clone(), vec![peers[0].clone()]);
+        gs.leave(&topic_hashes[0]);
+
+        assert_eq!(
+            prunes_sent_to(&gs, &peers[0]),
+            vec![(
+                topic_hashes[0].clone(),
+                Some(gs.config.prune_backoff.as_secs())
+            )]
+        );
+        assert!(Gossipsub::is_backing_off(
+            &gs.backoffs,
+            &topic_hashes[0],
+            &peers[0]
+        ));
+    }
+
+    #[test]
+    // tests that peers with a negative score can't join the mesh and are pruned from it
+    fn test_negative_score_peers_are_not_in_mesh() {
+        let (mut gs, peers, topic_hashes) =
+            build_and_inject_nodes(20, vec![String::from("topic1")], true);
+        enable_peer_score(&mut gs, &topic_hashes);
+
+        gs.mesh.insert(topic_hashes[0].clone(), vec![peers[0].clone()]);
+        assert!(gs.set_application_score(&peers[0], -1.0));
+        assert!(gs.set_application_score(&peers[1], -1.0));
+        assert!(gs.peer_score(&peers[0]).unwrap() < 0.0);
+
+        gs.handle_graft(&peers[1], topic_hashes.clone());
+        assert!(!gs.mesh.get(&topic_hashes[0]).unwrap().contains(&peers[1]));
+        assert_eq!(prunes_sent_to(&gs, &peers[1]).len(), 1);
+
+        gs.heartbeat();
+        let mesh = gs.mesh.get(&topic_hashes[0]).unwrap();
+        assert!(!mesh.contains(&peers[0]), "Expected peer to be pruned");
+        assert!(!mesh.contains(&peers[1]), "Expected peer not to be grafted");
+        assert_eq!(mesh.len(), gs.config.mesh_n);
+    }
+
+    #[test]
+    // tests that the RPCs of graylisted peers are ignored
+    fn test_ignore_rpc_from_graylisted_peer() {
+        let (mut gs, peers, topic_hashes) =
+            build_and_inject_nodes(20, vec![String::from("topic1")], true);
+        enable_peer_score(&mut gs, &topic_hashes);
+
+        // default graylist threshold is -80, the default application weight is 10
+        gs.set_application_score(&peers[0], -10.0);
+
+        let message = GossipsubMessage {
+            source: Some(peers[1].clone()),
+            data: vec![1, 2, 3],
+            sequence_number: Some(1),
+            topics: topic_hashes.clone(),
+            signature: None,
+            key: None,
+        };
+        gs.events.clear();
+        gs.inject_event(
+            peers[0].clone(),
+            ConnectionId::new(0),
+            GossipsubRpc {
+                messages: vec![message.clone()],
+                subscriptions: Vec::new(),
+                control_msgs: Vec::new(),
+            },
+        );
+        assert!(gs.events.is_empty(), "Expected the RPC to be ignored");
+
+        gs.inject_event(
+            peers[2].clone(),
+            ConnectionId::new(0),
+            GossipsubRpc {
+                messages: vec![message],
+                subscriptions: Vec::new(),
+                control_msgs: Vec::new(),
+            },
+        );
+        assert!(gs.events.iter().any(|e| matches!(
+            e,
+            NetworkBehaviourAction::GenerateEvent(GossipsubEvent::Message(..))
+        )));
+    }
+
+    #[test]
+    // tests that rejecting a message penalises the peers that delivered it
+    fn test_reject_message_penalises_peers() {
+        let gs_config = crate::GossipsubConfigBuilder::new()
+            .manual_propagation()
+            .build();
+        let mut gs = Gossipsub::new(PeerId::random(), gs_config);
+        let topic = Topic::new("topic1".into());
+        gs.subscribe(topic.clone());
+        let topic_hash = topic.no_hash();
+        enable_peer_score(&mut gs, std::slice::from_ref(&topic_hash));
+
+        let peers: Vec<PeerId> = (0..2).map(|_| PeerId::random()).collect();
+        for peer in &peers {
+            <Gossipsub as NetworkBehaviour>::inject_connected(&mut gs, peer);
+        }
+
+        let message = GossipsubMessage {
+            source: Some(peers[0].clone()),
+            data: vec![1, 2, 3],
+            sequence_number: Some(1),
+            topics: vec![topic_hash],
+            signature: None,
+            key: None,
+        };
+        let message_id = (gs.config.message_id_fn)(&message);
+        gs.handle_received_message(message.clone(), &peers[0]);
+        gs.handle_received_message(message, &peers[1]);
+
+        assert!(gs.report_message_validation_result(
+            &message_id,
+            &peers[0],
+            MessageAcceptance::Reject
+        ));
+        for peer in &peers {
+            assert!(gs.peer_score(peer).unwrap() < 0.0);
+        }
+        assert!(gs.mcache.get_validated(&message_id).is_none());
+        assert!(!gs.report_message_validation_result(
+            &message_id,
+            &peers[0],
+            MessageAcceptance::Accept
+        ));
+    }
+
+    #[test]
+    // tests that messages awaiting validation are neither forwarded, gossiped about nor served
+    fn test_unvalidated_messages_are_withheld() {
+        let gs_config = crate::GossipsubConfigBuilder::new()
+            .manual_propagation()
+            .build();
+        let mut gs = Gossipsub::new(PeerId::random(), gs_config);
+        let topic = Topic::new("topic1".into());
+        gs.subscribe(topic.clone());
+        let topic_hash = topic.no_hash();
+
+        let peers: Vec<PeerId> = (0..2).map(|_| PeerId::random()).collect();
+        for peer in &peers {
+            <Gossipsub as NetworkBehaviour>::inject_connected(&mut gs, peer);
+        }
+
+        let message = GossipsubMessage {
+            source: Some(peers[0].clone()),
+            data: vec![1, 2, 3],
+            sequence_number: Some(1),
+            topics: vec![topic_hash.clone()],
+            signature: None,
+            key: None,
+        };
+        let message_id = (gs.config.message_id_fn)(&message);
+        gs.handle_received_message(message, &peers[0]);
+
+        let sent_messages = |gs: &Gossipsub| {
+            gs.events
+                .iter()
+                .filter(|e| match e {
+                    NetworkBehaviourAction::NotifyHandler { event, .. } => {
+                        !event.messages.is_empty()
+                    }
+                    _ => false,
+                })
+                .count()
+        };
+
+        gs.handle_iwant(&peers[1], vec![message_id.clone()]);
+        assert_eq!(sent_messages(&gs), 0, "Unvalidated messages must not be served");
+        assert!(gs.mcache.get_gossip_ids(&topic_hash).is_empty());
+
+        assert!(gs.report_message_validation_result(
+            &message_id,
+            &peers[0],
+            MessageAcceptance::Accept
+        ));
+        gs.handle_iwant(&peers[1], vec![message_id.clone()]);
+        assert_eq!(sent_messages(&gs), 1, "Validated messages are served");
+        assert_eq!(gs.mcache.get_gossip_ids(&topic_hash), vec![message_id.clone()]);
+
+        assert!(
+            !gs.report_message_validation_result(&message_id, &peers[0], MessageAcceptance::Reject),
+            "A message can only be validated once"
+        );
+    }
+
+    // publishes a message on a fresh node with the given authenticity and returns it
+    fn publish_with_authenticity(authenticity: MessageAuthenticity) -> GossipsubMessage {
+        let mut gs = Gossipsub::with_authenticity(authenticity, GossipsubConfig::default());
+        let topic = Topic::new(String::from("test_authenticity"));
+        gs.subscribe(topic.clone());
+        let peer = PeerId::random();
+        <Gossipsub as NetworkBehaviour>::inject_connected(&mut gs, &peer);
+        gs.handle_received_subscriptions(
+            &[GossipsubSubscription {
+                action: GossipsubSubscriptionAction::Subscribe,
+                topic_hash: topic.no_hash(),
+            }],
+            &peer,
+        );
+        gs.events.clear();
+        gs.publish(&topic, vec![1, 2, 3]).unwrap();
+
+        gs.events
+            .iter()
+            .find_map(|e| match e {
+                NetworkBehaviourAction::NotifyHandler { event, .. } => {
+                    event.messages.first().cloned()
+                }
+                _ => None,
+            })
+            .expect("Should publish the message")
+    }
+
+    #[test]
+    /// Test that signed messages carry a valid signature, which is invalidated by tampering.
+    fn test_signed_messages() {
+        let keypair = Keypair::generate_ed25519();
+        let message = publish_with_authenticity(MessageAuthenticity::Signed(keypair.clone()));
+
+        assert_eq!(message.source, Some(keypair.public().into_peer_id()));
+        assert!(message.sequence_number.is_some());
+        assert!(message.signature.is_some());
+        assert!(message.key.is_none(), "Ed25519 keys are inlined in the peer id");
+        assert_eq!(message.validate(ValidationMode::Strict), Ok(()));
+
+        let mut tampered = message.clone();
+        tampered.data = vec![4, 5, 6];
+        assert!(tampered.validate(ValidationMode::Strict).is_err());
+        assert!(tampered.validate(ValidationMode::Permissive).is_err());
+        assert_eq!(tampered.validate(ValidationMode::None), Ok(()));
+
+        let mut forged = message;
+        forged.source = Some(PeerId::random());
+        assert!(forged.validate(ValidationMode::Permissive).is_err());
+    }
+
+    #[test]
+    /// Test that anonymous messages carry neither source, sequence number nor signature.
+    fn test_anonymous_messages() {
+        let message = publish_with_authenticity(MessageAuthenticity::Anonymous);
+
+        assert!(message.source.is_none());
+        assert!(message.sequence_number.is_none());
+        assert!(message.signature.is_none());
+        assert!(message.validate(ValidationMode::Strict).is_err());
+        assert_eq!(message.validate(ValidationMode::Permissive), Ok(()));
+    }
+
+    #[test]
+    /// Test that different anonymous messages are not mistaken for duplicates of each other.
+    fn test_receive_anonymous_messages() {
+        let (mut gs, peers, topic_hashes) =
+            build_and_inject_nodes(1, vec![String::from("topic1")], true);
+
+        let message = |data: Vec<u8>| GossipsubMessage {
+            source: None,
+            data,
+            sequence_number: None,
+            topics: topic_hashes.clone(),
+            signature: None,
+            key: None,
+        };
+        assert_ne!(
+            (gs.config.message_id_fn)(&message(vec![1, 2, 3])),
+            (gs.config.message_id_fn)(&message(vec![4, 5, 6]))
+        );
+
+        // The same bytes split differently into topics yield different ids.
+        let with_topics = |topics: &[&str]| GossipsubMessage {
+            topics: topics.iter().map(|t| TopicHash::from_raw(*t)).collect(),
+            ..message(vec![1, 2, 3])
+        };
+        assert_ne!(
+            (gs.config.message_id_fn)(&with_topics(&["ab", "c"])),
+            (gs.config.message_id_fn)(&with_topics(&["a", "bc"]))
+        );
+
+        gs.handle_received_message(message(vec![1, 2, 3]), &peers[0]);
+        gs.handle_received_message(message(vec![4, 5, 6]), &peers[0]);
+        gs.handle_received_message(message(vec![1, 2, 3]), &peers[0]);
+        assert_eq!(delivered_messages(&gs), 2, "Only the duplicate should be dropped");
+    }
+
+    #[test]
+    /// Test that messages failing strict validation are neither delivered nor propagated, and
+    /// penalise the peer that sent them.
+    fn test_strict_validation_drops_invalid_messages() {
+        let config = GossipsubConfigBuilder::new()
+            .validation_mode(ValidationMode::Strict)
+            .build();
+        let mut gs = Gossipsub::new(PeerId::random(), config);
+        let topic = Topic::new(String::from("test_strict"));
+        gs.subscribe(topic.clone());
+
+        let topic_params = TopicScoreParams {
+            topic_weight: 1.0,
+            invalid_message_deliveries_weight: -1.0,
+            ..TopicScoreParams::default()
+        };
+        let mut params = PeerScoreParams::default();
+        params.topics.insert(topic.no_hash(), topic_params);
+        gs.with_peer_score(params, PeerScoreThresholds::default())
+            .unwrap();
+
+        let peers = (0..2).map(|_| PeerId::random()).collect::<Vec<_>>();
+        for peer in &peers {
+            <Gossipsub as NetworkBehaviour>::inject_connected(&mut gs, peer);
+            gs.handle_received_subscriptions(
+                &[GossipsubSubscription {
+                    action: GossipsubSubscriptionAction::Subscribe,
+                    topic_hash: topic.no_hash(),
+                }],
+                peer,
+            );
+        }
+        gs.events.clear();
+
+        let message = GossipsubMessage {
+            source: Some(peers[1].clone()),
+            data: vec![1, 2, 3],
+            sequence_number: Some(1),
+            topics: vec![topic.no_hash()],
+            signature: None,
+            key: None,
+        };
+        let message_id = (gs.config.message_id_fn)(&message);
+        gs.handle_received_message(message, &peers[0]);
+
+        assert!(gs.events.is_empty(), "Invalid message should be dropped");
+        assert!(gs.mcache.get_validated(&message_id).is_none());
+        assert!(gs.mcache.remove_pending(&message_id).is_none());
+        assert!(gs.peer_score(&peers[0]).unwrap() < 0.0);
+    }
+
+    // counts the messages delivered to the user
+    fn delivered_messages(gs: &Gossipsub) -> usize {
+        gs.events
+            .iter()
+            .filter(|e| {
+                matches!(
+                    e,
+                    NetworkBehaviourAction::GenerateEvent(GossipsubEvent::Message(..))
+                )
+            })
+            .count()
+    }
+
+    #[test]
+    /// Test that a content based message id deduplicates messages of distinct sources.
+    fn test_content_based_message_id() {
+        let config = GossipsubConfigBuilder::new()
+            .message_id_fn(|message| MessageId(hex_fmt::HexFmt(&message.data).to_string()))
+            .build();
+        let mut gs = Gossipsub::new(PeerId::random(), config);
+        let topic = Topic::new(String::from("test_content_id"));
+        gs.subscribe(topic.clone());
+
+        let peers = (0..2).map(|_| PeerId::random()).collect::<Vec<_>>();
+        for (seq, peer) in peers.iter().enumerate() {
+            let message = GossipsubMessage {
+                source: Some(peer.clone()),
+                data: vec![1, 2, 3],
+                sequence_number: Some(seq as u64),
+                topics: vec![topic.no_hash()],
+                signature: None,
+                key: None,
+            };
+            gs.handle_received_message(message, peer);
+        }
+
+        assert_eq!(delivered_messages(&gs), 1);
+    }
+
+    #[test]
+    /// Test that message ids are forgotten by the duplicate cache after its ttl.
+    fn test_duplicate_cache_ttl() {
+        let config = GossipsubConfigBuilder::new()
+            .duplicate_cache_ttl(Duration::from_millis(50))
+            .build();
+        let mut gs = Gossipsub::new(PeerId::random(), config);
+        let topic = Topic::new(String::from("test_duplicate_ttl"));
+        gs.subscribe(topic.clone());
+
+        let peer = PeerId::random();
+        let message = GossipsubMessage {
+            source: Some(peer.clone()),
+            data: vec![1, 2, 3],
+            sequence_number: Some(1),
+            topics: vec![topic.no_hash()],
+            signature: None,
+            key: None,
+        };
+        gs.handle_received_message(message.clone(), &peer);
+        gs.handle_received_message(message.clone(), &peer);
+        assert_eq!(delivered_messages(&gs), 1);
+
+        std::thread::sleep(Duration::from_millis(100));
+        gs.handle_received_message(message, &peer);
+        assert_eq!(delivered_messages(&gs), 2);
+    }
+
+    #[test]
+    /// Test that messages exceeding the maximum transmission size are not published.
+    fn test_publish_message_too_large() {
+        let (mut gs, _, _) = build_and_inject_nodes(20, vec![String::from("topic1")], true);
+        gs.events.clear();
+
+        let max_size = gs.config.max_transmit_size;
+        match gs.publish(&Topic::new(String::from("topic1")), vec![0; max_size]) {
+            Err(PublishError::MessageTooLarge { size, .. }) => assert!(size > max_size),
+            res => panic!("Unexpected publish result: {:?}", res),
+        }
+        assert!(gs.events.is_empty(), "Nothing should be sent");
+    }
+
+    #[test]
+    /// Test that IWANT responses are split over several RPCs when needed to fit within the
+    /// maximum transmission size.
+    fn test_handle_iwant_splits_large_responses() {
+        let (mut gs, peers, _) = build_and_inject_nodes(20, Vec::new(), true);
+        gs.events.clear();
+
+        let max_size = gs.config.max_transmit_size;
+        let message_ids = (0..4)
+            .map(|seq| {
+                let message = GossipsubMessage {
+                    source: Some(peers[11].clone()),
+                    data: vec![0; max_size / 3],
+                    sequence_number: Some(seq),
+                    topics: Vec::new(),
+                    signature: None,
+                    key: None,
+                };
+                let msg_id = (gs.config.message_id_fn)(&message);
+                gs.mcache.put(message);
+                msg_id
+            })
+            .collect::<Vec<_>>();
+
+        gs.handle_iwant(&peers[7], message_ids);
+
+        let rpcs = gs
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                NetworkBehaviourAction::NotifyHandler { event, .. } => Some(event),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rpcs.len(), 2);
+        assert_eq!(rpcs.iter().map(|rpc| rpc.messages.len()).sum::<usize>(), 4);
+        for rpc in rpcs {
+            let size = rpc.messages.iter().map(|m| m.encoded_len()).sum::<usize>();
+            assert!(size <= max_size);
+        }
+    }
 }
@@ -18,7 +18,8 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::protocol::{GossipsubMessage, MessageId};
+use crate::protocol::{GossipsubMessage, MessageId, GOSSIPSUB_1_1_0_PROTOCOL};
+use sha2::{Digest, Sha256};
 use std::borrow::Cow;
 use std::time::Duration;
 
@@ -26,10 +27,24 @@ use std::time::Duration;
 /// packet.
 pub const IDENTITY_SOURCE: [u8; 3] = [0, 1, 0];
 
+/// The checks performed on the source, sequence number and signature of received messages before
+/// they are delivered or propagated. Messages failing them are dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationMode {
+    /// Messages must have a source, a sequence number and a valid signature by their source.
+    Strict,
+    /// Unsigned and anonymous messages are accepted, but the signature of signed messages must be
+    /// valid.
+    Permissive,
+    /// No checks are performed.
+    None,
+}
+
 /// Configuration parameters that define the performance of the gossipsub network.
 #[derive(Clone)]
 pub struct GossipsubConfig {
-    /// The protocol id to negotiate this protocol (default is `/meshsub/1.0.0`).
+    /// The protocol id to negotiate this protocol (default is `/meshsub/1.1.0`, which also accepts
+    /// `/meshsub/1.0.0` peers).
     pub protocol_id: Cow<'static, [u8]>,
 
     // Overlay network parameters.
@@ -61,7 +76,8 @@ pub struct GossipsubConfig {
     /// Time to live for fanout peers (default is 60 seconds).
     pub fanout_ttl: Duration,
 
-    /// The maximum byte size for each gossip (default is 2048 bytes).
+    /// The maximum byte size for each gossip, enforced both when publishing and receiving messages
+    /// (default is 2048 bytes).
     pub max_transmit_size: usize,
 
     /// Flag determining if gossipsub topics are hashed or sent as plain strings (default is false).
@@ -73,11 +89,17 @@ pub struct GossipsubConfig {
     /// When set to `true`, prevents automatic forwarding of all received messages. This setting
     /// allows a user to validate the messages before propagating them to their peers. If set to
     /// true, the user must manually call `propagate_message()` on the behaviour to forward message
-    /// once validated (default is false).
+    /// once validated, and the message is not gossiped about before (default is false).
     pub manual_propagation: bool,
 
+    /// The checks performed on the source, sequence number and signature of received messages
+    /// (default is `ValidationMode::Permissive`).
+    pub validation_mode: ValidationMode,
+
     /// A user-defined function allowing the user to specify the message id of a gossipsub message.
-    /// The default value is to concatenate the source peer id with a sequence number. Setting this
+    /// The default value is to concatenate the source peer id with a sequence number. Messages
+    /// lacking either, such as anonymous messages, are identified by the base64 encoded SHA256
+    /// hash of their content and topics instead. Setting this
     /// parameter allows the user to address packets arbitrarily. One example is content based
     /// addressing, where this function may be set to `hash(message)`. This would prevent messages
     /// of the same content from being duplicated.
@@ -85,12 +107,39 @@ pub struct GossipsubConfig {
     /// The function takes a `GossipsubMessage` as input and outputs a String to be interpreted as
     /// the message id.
     pub message_id_fn: fn(&GossipsubMessage) -> MessageId,
+
+    /// Maximum number of message ids remembered to detect duplicate messages, which are neither
+    /// delivered nor propagated again (default is 256).
+    pub duplicate_cache_size: usize,
+
+    /// Time after which a message id is forgotten by the duplicate cache, even if the cache is not
+    /// full (default is 2 minutes).
+    pub duplicate_cache_ttl: Duration,
+
+    /// The backoff time a pruned peer has to wait before it may GRAFT again (default is 60
+    /// seconds).
+    pub prune_backoff: Duration,
+
+    /// A GRAFT received less than `graft_flood_threshold` after our PRUNE incurs an extra
+    /// behaviour penalty when peer scoring is enabled (default is 10 seconds).
+    pub graft_flood_threshold: Duration,
+
+    /// Number of heartbeats between two attempts of opportunistic grafting (default is 60).
+    pub opportunistic_graft_ticks: u64,
+
+    /// Number of peers to opportunistically graft when the median score of the mesh is below
+    /// the opportunistic graft threshold (default is 2).
+    pub opportunistic_graft_peers: usize,
+
+    /// Number of the highest scoring peers kept in the mesh when pruning an oversubscribed mesh
+    /// (default is 4).
+    pub retain_scores: usize,
 }
 
 impl Default for GossipsubConfig {
     fn default() -> GossipsubConfig {
         GossipsubConfig {
-            protocol_id: Cow::Borrowed(b"/meshsub/1.0.0"),
+            protocol_id: Cow::Borrowed(GOSSIPSUB_1_1_0_PROTOCOL),
             history_length: 5,
             history_gossip: 3,
             mesh_n: 6,
@@ -104,12 +153,37 @@ impl Default for GossipsubConfig {
             hash_topics: false, // default compatibility with floodsub
             no_source_id: false,
             manual_propagation: false,
-            message_id_fn: |message| {
-                // default message id is: source + sequence number
-                let mut source_string = message.source.to_base58();
-                source_string.push_str(&message.sequence_number.to_string());
-                MessageId(source_string)
-            },
+            validation_mode: ValidationMode::Permissive,
+            message_id_fn: default_message_id,
+            duplicate_cache_size: 256,
+            duplicate_cache_ttl: Duration::from_secs(120),
+            prune_backoff: Duration::from_secs(60),
+            graft_flood_threshold: Duration::from_secs(10),
+            opportunistic_graft_ticks: 60,
+            opportunistic_graft_peers: 2,
+            retain_scores: 4,
+        }
+    }
+}
+
+/// The default message id: the source peer id concatenated with the sequence number, or the
+/// hash of the content and topics of messages lacking either.
+fn default_message_id(message: &GossipsubMessage) -> MessageId {
+    match (&message.source, message.sequence_number) {
+        (Some(source), Some(sequence_number)) => {
+            MessageId(source.to_base58() + &sequence_number.to_string())
+        }
+        _ => {
+            // Every field is prefixed with its length, such that different
+            // splits of the same bytes into data and topics differ.
+            let mut hasher = Sha256::new();
+            hasher.input(&(message.data.len() as u64).to_be_bytes());
+            hasher.input(&message.data);
+            for topic in &message.topics {
+                hasher.input(&(topic.as_str().len() as u64).to_be_bytes());
+                hasher.input(topic.as_str().as_bytes());
+            }
+            MessageId(base64::encode(hasher.result().as_slice()))
         }
     }
 }
@@ -219,11 +293,63 @@ impl GossipsubConfigBuilder {
         self
     }
 
+    pub fn validation_mode(&mut self, validation_mode: ValidationMode) -> &mut Self {
+        self.config.validation_mode = validation_mode;
+        self
+    }
+
     pub fn message_id_fn(&mut self, id_fn: fn(&GossipsubMessage) -> MessageId) -> &mut Self {
         self.config.message_id_fn = id_fn;
         self
     }
 
+    pub fn duplicate_cache_size(&mut self, duplicate_cache_size: usize) -> &mut Self {
+        assert!(
+            duplicate_cache_size > 0,
+            "The duplicate_cache_size must be greater than zero"
+        );
+        self.config.duplicate_cache_size = duplicate_cache_size;
+        self
+    }
+
+    pub fn duplicate_cache_ttl(&mut self, duplicate_cache_ttl: Duration) -> &mut Self {
+        self.config.duplicate_cache_ttl = duplicate_cache_ttl;
+        self
+    }
+
+    pub fn prune_backoff(&mut self, prune_backoff: Duration) -> &mut Self {
+        self.config.prune_backoff = prune_backoff;
+        self
+    }
+
+    pub fn graft_flood_threshold(&mut self, graft_flood_threshold: Duration) -> &mut Self {
+        self.config.graft_flood_threshold = graft_flood_threshold;
+        self
+    }
+
+    pub fn opportunistic_graft_ticks(&mut self, opportunistic_graft_ticks: u64) -> &mut Self {
+        assert!(
+            opportunistic_graft_ticks > 0,
+            "The opportunistic_graft_ticks must be greater than zero"
+        );
+        self.config.opportunistic_graft_ticks = opportunistic_graft_ticks;
+        self
+    }
+
+    pub fn opportunistic_graft_peers(&mut self, opportunistic_graft_peers: usize) -> &mut Self {
+        self.config.opportunistic_graft_peers = opportunistic_graft_peers;
+        self
+    }
+
+    pub fn retain_scores(&mut self, retain_scores: usize) -> &mut Self {
+        assert!(
+            retain_scores <= self.config.mesh_n,
+            "The retain_scores must be smaller than or equal to mesh_n"
+        );
+        self.config.retain_scores = retain_scores;
+        self
+    }
+
     pub fn build(&self) -> GossipsubConfig {
         self.config.clone()
     }
@@ -250,6 +376,14 @@ impl std::fmt::Debug for GossipsubConfig {
         let _ = builder.field("hash_topics", &self.hash_topics);
         let _ = builder.field("no_source_id", &self.no_source_id);
         let _ = builder.field("manual_propagation", &self.manual_propagation);
+        let _ = builder.field("validation_mode", &self.validation_mode);
+        let _ = builder.field("duplicate_cache_size", &self.duplicate_cache_size);
+        let _ = builder.field("duplicate_cache_ttl", &self.duplicate_cache_ttl);
+        let _ = builder.field("prune_backoff", &self.prune_backoff);
+        let _ = builder.field("graft_flood_threshold", &self.graft_flood_threshold);
+        let _ = builder.field("opportunistic_graft_ticks", &self.opportunistic_graft_ticks);
+        let _ = builder.field("opportunistic_graft_peers", &self.opportunistic_graft_peers);
+        let _ = builder.field("retain_scores", &self.retain_scores);
         builder.finish()
     }
 }
@@ -198,7 +198,11 @@ impl ProtocolsHandler for GossipsubHandler {
                             return Poll::Ready(ProtocolsHandlerEvent::Custom(message));
                         }
                         Poll::Ready(Some(Err(e))) => {
-                            debug!("Inbound substream error while awaiting input: {:?}", e);
+                            if let io::ErrorKind::PermissionDenied = e.kind() {
+                                warn!("Inbound message over the maximum transmission size, closing substream.");
+                            } else {
+                                debug!("Inbound substream error while awaiting input: {:?}", e);
+                            }
                             self.inbound_substream =
                                 Some(InboundSubstreamState::Closing(substream));
                         }
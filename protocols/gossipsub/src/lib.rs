@@ -60,7 +60,8 @@
 //!
 //! [`GossipsubConfig`]: struct.GossipsubConfig.html
 //!
-//! - `protocol_id` - The protocol id that this implementation will accept connections on.
+//! - `protocol_id` - The protocol id that this implementation will accept connections on
+//! (default: `/meshsub/1.1.0`, also accepting `/meshsub/1.0.0`).
 //! - `history_length` - The number of heartbeats which past messages are kept in cache (default: 5).
 //! - `history_gossip` - The number of past heartbeats that the node will send gossip metadata
 //! about (default: 3).
@@ -76,11 +77,31 @@
 //! - `fanout_ttl` - The fanout time to live time period. The timeout required before removing peers from the fanout
 //! for a given topic (default: 1 minute).
 //! - `max_transmit_size` - This sets the maximum transmission size for total gossipsub messages on the network.
+//! Publishing a larger message fails with `PublishError::MessageTooLarge`, and larger frames sent by
+//! remote peers are rejected before being buffered.
 //! - `hash_topics` - Whether to hash the topics using base64(SHA256(topic)) or to leave as plain utf-8 strings.
 //! - `manual_propagation` - Whether gossipsub should immediately forward received messages on the
-//! network. For applications requiring message validation, this should be set to false, then the
+//! network. For applications requiring message validation, this should be set to true, then the
 //! application should call `propagate_message(message_id, propagation_source)` once validated, to
-//! propagate the message to peers.
+//! propagate the message to peers. Until then, the message is neither gossiped about nor served to
+//! peers requesting it. With peer scoring enabled,
+//! `report_message_validation_result(message_id, propagation_source, acceptance)` should be used
+//! instead, so that rejected messages penalise the peers that sent them.
+//! - `message_id_fn` - The function computing the id of a message, used to detect duplicates
+//! (default: the source peer id followed by the sequence number). Content-addressed networks can
+//! for instance use a hash of the message data instead.
+//! - `duplicate_cache_size` - The number of message ids remembered to detect duplicates (default:
+//! 256).
+//! - `duplicate_cache_ttl` - The time after which a message id is forgotten by the duplicate cache
+//! (default: 2 minutes).
+//! - `prune_backoff` - The time a pruned peer must wait before grafting again (default: 1 minute).
+//! - `graft_flood_threshold` - GRAFTs received sooner than this after a PRUNE are penalised
+//! (default: 10 seconds).
+//! - `opportunistic_graft_ticks` - The number of heartbeats between two opportunistic grafting
+//! attempts (default: 60).
+//! - `opportunistic_graft_peers` - The number of peers to opportunistically graft (default: 2).
+//! - `retain_scores` - The number of the best scoring peers kept when pruning an oversubscribed
+//! mesh (default: 4).
 //!
 //! This struct implements the `Default` trait and can be initialised via
 //! `GossipsubConfig::default()`.
@@ -93,6 +114,20 @@
 //! [`GossipsubConfig`].
 //!
 //! [`Gossipsub`]: struct.Gossipsub.html
+//!
+//! ## Peer scoring
+//!
+//! Gossipsub v1.1 peer scoring is enabled with `Gossipsub::with_peer_score`, given a set of
+//! [`PeerScoreParams`] and [`PeerScoreThresholds`]. Each peer is then scored from its behaviour
+//! in the mesh of the topics configured with [`TopicScoreParams`], from an application specific
+//! score, from the number of peers sharing its IP address and from protocol violations such as
+//! GRAFTing during a backoff. Peers with a negative score are pruned from the mesh, and peers
+//! below the respective thresholds don't receive gossip, published messages or have their RPCs
+//! ignored altogether.
+//!
+//! [`PeerScoreParams`]: struct.PeerScoreParams.html
+//! [`PeerScoreThresholds`]: struct.PeerScoreThresholds.html
+//! [`TopicScoreParams`]: struct.TopicScoreParams.html
 
 //! ## Example
 //!
@@ -141,13 +176,21 @@ mod behaviour;
 mod config;
 mod handler;
 mod mcache;
+mod peer_score;
 mod topic;
 
 mod rpc_proto {
     include!(concat!(env!("OUT_DIR"), "/gossipsub.pb.rs"));
 }
 
-pub use self::behaviour::{Gossipsub, GossipsubEvent, GossipsubRpc};
-pub use self::config::{GossipsubConfig, GossipsubConfigBuilder};
+pub use self::behaviour::{
+    Gossipsub, GossipsubEvent, GossipsubRpc, MessageAcceptance, MessageAuthenticity,
+    PublishError,
+};
+pub use self::config::{GossipsubConfig, GossipsubConfigBuilder, ValidationMode};
+pub use self::peer_score::{
+    score_parameter_decay, score_parameter_decay_with_base, PeerScoreParams, PeerScoreThresholds,
+    TopicScoreParams,
+};
 pub use self::protocol::{GossipsubMessage, MessageId};
 pub use self::topic::{Topic, TopicHash};
@@ -20,9 +20,13 @@
 
 
 
+use crate::config::GossipsubConfig;
 use crate::protocol::{GossipsubMessage, MessageId};
 use crate::topic::TopicHash;
-use std::{collections::HashMap, fmt};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
 /// CacheEntry stored in the history.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -35,6 +39,9 @@ pub struct CacheEntry {
 #[derive(Clone)]
 pub struct MessageCache {
     msgs: HashMap<MessageId, GossipsubMessage>,
+    /// Messages awaiting validation by the application, which are neither gossiped about nor
+    /// served until then.
+    pending: HashSet<MessageId>,
     history: Vec<Vec<CacheEntry>>,
     gossip: usize,
     msg_id: fn(&GossipsubMessage) -> MessageId,
@@ -44,6 +51,7 @@ impl fmt::Debug for MessageCache {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("MessageCache")
             .field("msgs", &self.msgs)
+            .field("pending", &self.pending)
             .field("history", &self.history)
             .field("gossip", &self.gossip)
             .finish()
@@ -60,6 +68,7 @@ impl MessageCache {
         MessageCache {
             gossip,
             msgs: HashMap::default(),
+            pending: HashSet::default(),
             history: vec![Vec::new(); history_capacity],
             msg_id,
         }
@@ -68,18 +77,11 @@ impl MessageCache {
     /// Creates a `MessageCache` with a default message id function.
     #[allow(dead_code)]
     pub fn new_default(gossip: usize, history_capacity: usize) -> MessageCache {
-        let default_id = |message: &GossipsubMessage| {
-            // default message id is: source + sequence number
-            let mut source_string = message.source.to_base58();
-            source_string.push_str(&message.sequence_number.to_string());
-            MessageId(source_string)
-        };
-        MessageCache {
+        MessageCache::new(
             gossip,
-            msgs: HashMap::default(),
-            history: vec![Vec::new(); history_capacity],
-            msg_id: default_id,
-        }
+            history_capacity,
+            GossipsubConfig::default().message_id_fn,
+        )
     }
 
     /// Put a message into the memory cache
@@ -95,9 +97,29 @@ impl MessageCache {
         self.history[0].push(cache_entry);
     }
 
-    /// Get a message with `message_id`
-    pub fn get(&self, message_id: &MessageId) -> Option<&GossipsubMessage> {
-        self.msgs.get(message_id)
+    /// Put a message awaiting validation into the memory cache. It is neither gossiped about nor
+    /// returned by `get_validated` until `validate` is called.
+    pub fn put_pending(&mut self, msg: GossipsubMessage) {
+        self.pending.insert((self.msg_id)(&msg));
+        self.put(msg);
+    }
+
+    /// Marks a message awaiting validation as validated. Returns the message if it was pending.
+    pub fn validate(&mut self, message_id: &MessageId) -> Option<&GossipsubMessage> {
+        if self.pending.remove(message_id) {
+            self.msgs.get(message_id)
+        } else {
+            None
+        }
+    }
+
+    /// Get a message with `message_id`, unless it is awaiting validation.
+    pub fn get_validated(&self, message_id: &MessageId) -> Option<&GossipsubMessage> {
+        if self.pending.contains(message_id) {
+            None
+        } else {
+            self.msgs.get(message_id)
+        }
     }
 
     /// Get a list of GossipIds for a given topic
@@ -109,7 +131,10 @@ impl MessageCache {
                 let mut found_entries: Vec<MessageId> = entries
                     .iter()
                     .filter_map(|entry| {
-                        if entry.topics.iter().any(|t| t == topic) {
+                        if entry.topics.iter().any(|t| t == topic)
+                            && self.msgs.contains_key(&entry.mid)
+                            && !self.pending.contains(&entry.mid)
+                        {
                             Some(entry.mid.clone())
                         } else {
                             None
@@ -123,10 +148,28 @@ impl MessageCache {
             })
     }
 
+    /// Removes a message from the cache, so that it is neither gossiped about nor served
+    /// anymore. Returns the message if it was present.
+    pub fn remove(&mut self, message_id: &MessageId) -> Option<GossipsubMessage> {
+        self.pending.remove(message_id);
+        self.msgs.remove(message_id)
+    }
+
+    /// Removes a message from the cache if it is awaiting validation. Returns the message if it
+    /// was pending.
+    pub fn remove_pending(&mut self, message_id: &MessageId) -> Option<GossipsubMessage> {
+        if self.pending.contains(message_id) {
+            self.remove(message_id)
+        } else {
+            None
+        }
+    }
+
     /// Shift the history array down one and delete messages associated with the
     /// last entry
     pub fn shift(&mut self) {
         for entry in self.history.pop().expect("history is always > 1") {
+            self.pending.remove(&entry.mid);
             self.msgs.remove(&entry.mid);
         }
 
@@ -148,10 +191,12 @@ mod tests {
         let sequence_number = x;
 
         let m = GossipsubMessage {
-            source,
+            source: Some(source),
             data,
-            sequence_number,
+            sequence_number: Some(sequence_number),
             topics,
+            signature: None,
+            key: None,
         };
         m
     }
@@ -161,8 +206,8 @@ mod tests {
     fn test_new_cache() {
         let default_id = |message: &GossipsubMessage| {
             // default message id is: source + sequence number
-            let mut source_string = message.source.to_base58();
-            source_string.push_str(&message.sequence_number.to_string());
+            let mut source_string = message.source.as_ref().unwrap().to_base58();
+            source_string.push_str(&message.sequence_number.unwrap().to_string());
             MessageId(source_string)
         };
         let x: usize = 3;
@@ -185,7 +230,7 @@ mod tests {
 
         assert!(mc.history[0].len() == 1);
 
-        let fetched = mc.get(&(mc.msg_id)(&m));
+        let fetched = mc.get_validated(&(mc.msg_id)(&m));
 
         assert_eq!(fetched.is_none(), false);
         assert_eq!(fetched.is_some(), true);
@@ -211,7 +256,7 @@ mod tests {
 
         // Try to get an incorrect ID
         let wrong_id = MessageId(String::from("wrongid"));
-        let fetched = mc.get(&wrong_id);
+        let fetched = mc.get_validated(&wrong_id);
         assert_eq!(fetched.is_none(), true);
     }
 
@@ -222,7 +267,7 @@ mod tests {
 
         // Try to get an incorrect ID
         let wrong_string = MessageId(String::from("imempty"));
-        let fetched = mc.get(&wrong_string);
+        let fetched = mc.get_validated(&wrong_string);
         assert_eq!(fetched.is_none(), true);
     }
 
@@ -235,7 +280,7 @@ mod tests {
         let m = gen_testm(1, vec![]);
         mc.put(m.clone());
 
-        let fetched = mc.get(&(mc.msg_id)(&m));
+        let fetched = mc.get_validated(&(mc.msg_id)(&m));
 
         // Make sure it is the same fetched message
         match fetched {
@@ -321,4 +366,31 @@ mod tests {
         assert_eq!(mc.history[0].len(), 0);
         assert_eq!(mc.msgs.len(), 0);
     }
+
+    #[test]
+    /// Test that messages awaiting validation are only gossiped about and served once validated.
+    fn test_pending_messages() {
+        let mut mc = MessageCache::new_default(3, 5);
+
+        let topic1_hash = Topic::new("topic1".into()).no_hash().clone();
+        let m = gen_testm(1, vec![topic1_hash.clone()]);
+        let id = (mc.msg_id)(&m);
+        mc.put_pending(m.clone());
+
+        assert!(mc.msgs.contains_key(&id));
+        assert!(mc.get_validated(&id).is_none());
+        assert!(mc.get_gossip_ids(&topic1_hash).is_empty());
+
+        assert_eq!(mc.validate(&id), Some(&m));
+        assert!(mc.validate(&id).is_none(), "A message is only validated once");
+        assert!(mc.get_validated(&id).is_some());
+        assert_eq!(mc.get_gossip_ids(&topic1_hash), vec![id.clone()]);
+        assert!(mc.remove_pending(&id).is_none());
+
+        let m = gen_testm(2, vec![topic1_hash.clone()]);
+        let id = (mc.msg_id)(&m);
+        mc.put_pending(m.clone());
+        assert_eq!(mc.remove_pending(&id), Some(m));
+        assert!(!mc.msgs.contains_key(&id));
+    }
 }
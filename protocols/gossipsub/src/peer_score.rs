@@ -0,0 +1,738 @@
+// Copyright 2020 Sigma Prime Pty Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Manages and stores the scoring logic of a particular peer on the gossipsub behaviour.
+
+use crate::protocol::MessageId;
+use crate::topic::TopicHash;
+use libp2p_core::PeerId;
+use log::{debug, trace};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
+use std::time::Duration;
+use wasm_timer::Instant;
+
+mod params;
+#[cfg(test)]
+mod tests;
+
+pub use params::{
+    score_parameter_decay, score_parameter_decay_with_base, PeerScoreParams, PeerScoreThresholds,
+    TopicScoreParams,
+};
+
+/// The time during which the delivery records of a message are kept.
+const TIME_CACHE_DURATION: Duration = Duration::from_secs(120);
+
+#[derive(Debug)]
+pub(crate) struct PeerScore {
+    /// The score parameters.
+    params: PeerScoreParams,
+    /// The stats per PeerId.
+    peer_stats: HashMap<PeerId, PeerStats>,
+    /// Tracking peers per IP.
+    peer_ips: HashMap<IpAddr, HashSet<PeerId>>,
+    /// Message delivery tracking. This is a time-cache of `DeliveryRecord`s.
+    deliveries: HashMap<MessageId, DeliveryRecord>,
+    /// The expiration times of the entries of `deliveries`, oldest first.
+    delivery_expirations: VecDeque<(Instant, MessageId)>,
+}
+
+/// General statistics for a given gossipsub peer.
+#[derive(Debug)]
+struct PeerStats {
+    /// Connection status of the peer.
+    status: ConnectionStatus,
+    /// Stats per topic.
+    topics: HashMap<TopicHash, TopicStats>,
+    /// IP tracking for individual peers.
+    known_ips: HashSet<IpAddr>,
+    /// Behaviour penalty that is applied to the peer, assigned by the behaviour.
+    behaviour_penalty: f64,
+    /// Application specific score. Can be manipulated by calling PeerScore::set_application_score
+    application_score: f64,
+}
+
+#[derive(Debug)]
+enum ConnectionStatus {
+    /// The peer is connected.
+    Connected,
+    /// The peer is disconnected
+    Disconnected {
+        /// Expiration time of the score state for disconnected peers.
+        expires: Instant,
+    },
+}
+
+impl Default for PeerStats {
+    fn default() -> Self {
+        PeerStats {
+            status: ConnectionStatus::Connected,
+            topics: HashMap::new(),
+            known_ips: HashSet::new(),
+            behaviour_penalty: 0f64,
+            application_score: 0f64,
+        }
+    }
+}
+
+/// Stats assigned to peer for each topic.
+#[derive(Debug, Default)]
+struct TopicStats {
+    mesh_status: MeshStatus,
+    /// Number of first message deliveries.
+    first_message_deliveries: f64,
+    /// True if the peer has been in the mesh for enough time to activate mesh message deliveries.
+    mesh_message_deliveries_active: bool,
+    /// Number of message deliveries from the mesh.
+    mesh_message_deliveries: f64,
+    /// Mesh rate failure penalty.
+    mesh_failure_penalty: f64,
+    /// Invalid message counter.
+    invalid_message_deliveries: f64,
+}
+
+impl TopicStats {
+    /// Returns true if the peer is in the `mesh`.
+    fn in_mesh(&self) -> bool {
+        matches!(self.mesh_status, MeshStatus::Active { .. })
+    }
+}
+
+/// Status defining a peer's inclusion in the mesh and associated parameters.
+#[derive(Debug, Default)]
+enum MeshStatus {
+    Active {
+        /// The time the peer was last GRAFTed;
+        graft_time: Instant,
+        /// The time the peer has been in the mesh.
+        mesh_time: Duration,
+    },
+    #[default]
+    InActive,
+}
+
+impl MeshStatus {
+    /// Initialises a new `Active` mesh status.
+    fn new_active() -> Self {
+        MeshStatus::Active {
+            graft_time: Instant::now(),
+            mesh_time: Duration::from_secs(0),
+        }
+    }
+}
+
+/// The validation status of a message, as far as scoring is concerned.
+#[derive(Debug)]
+enum DeliveryStatus {
+    /// We don't know (yet) if the message is valid.
+    Unknown,
+    /// The message is valid together with the validated time.
+    Valid(Instant),
+    /// The message is invalid.
+    Invalid,
+    /// Instructed by the validator to ignore the message.
+    Ignored,
+}
+
+#[derive(Debug)]
+struct DeliveryRecord {
+    status: DeliveryStatus,
+    first_seen: Instant,
+    /// The peers that delivered the message before its validation completed, or within the
+    /// delivery window after it.
+    peers: HashSet<PeerId>,
+}
+
+impl Default for DeliveryRecord {
+    fn default() -> Self {
+        DeliveryRecord {
+            status: DeliveryStatus::Unknown,
+            first_seen: Instant::now(),
+            peers: HashSet::new(),
+        }
+    }
+}
+
+impl PeerScore {
+    /// Creates a new `PeerScore` using the given parameters.
+    pub fn new(params: PeerScoreParams) -> Self {
+        PeerScore {
+            params,
+            peer_stats: HashMap::new(),
+            peer_ips: HashMap::new(),
+            deliveries: HashMap::new(),
+            delivery_expirations: VecDeque::new(),
+        }
+    }
+
+    /// Returns the score of a peer.
+    pub fn score(&self, peer_id: &PeerId) -> f64 {
+        let peer_stats = match self.peer_stats.get(peer_id) {
+            Some(v) => v,
+            None => return 0.0,
+        };
+        let mut score = 0.0;
+
+        // topic scores
+        for (topic, topic_stats) in peer_stats.topics.iter() {
+            // topic parameters
+            let topic_params = match self.params.topics.get(topic) {
+                Some(params) => params,
+                None => continue,
+            };
+
+            // we are tracking the topic
+            let mut topic_score = 0.0;
+
+            // P1: time in mesh
+            if let MeshStatus::Active { mesh_time, .. } = topic_stats.mesh_status {
+                let p1 = {
+                    let v = mesh_time.as_secs_f64()
+                        / topic_params.time_in_mesh_quantum.as_secs_f64();
+                    if v < topic_params.time_in_mesh_cap {
+                        v
+                    } else {
+                        topic_params.time_in_mesh_cap
+                    }
+                };
+                topic_score += p1 * topic_params.time_in_mesh_weight;
+            }
+
+            // P2: first message deliveries
+            let p2 = topic_stats.first_message_deliveries;
+            topic_score += p2 * topic_params.first_message_deliveries_weight;
+
+            // P3: mesh message deliveries
+            if topic_stats.mesh_message_deliveries_active
+                && topic_stats.mesh_message_deliveries
+                    < topic_params.mesh_message_deliveries_threshold
+            {
+                let deficit = topic_params.mesh_message_deliveries_threshold
+                    - topic_stats.mesh_message_deliveries;
+                let p3 = deficit * deficit;
+                topic_score += p3 * topic_params.mesh_message_deliveries_weight;
+            }
+
+            // P3b:
+            // NOTE: the weight of P3b is negative (validated in TopicScoreParams.validate), so this
+            // detracts.
+            let p3b = topic_stats.mesh_failure_penalty;
+            topic_score += p3b * topic_params.mesh_failure_penalty_weight;
+
+            // P4: invalid messages
+            // NOTE: the weight of P4 is negative (validated in TopicScoreParams.validate), so this
+            // detracts.
+            let p4 =
+                topic_stats.invalid_message_deliveries * topic_stats.invalid_message_deliveries;
+            topic_score += p4 * topic_params.invalid_message_deliveries_weight;
+
+            // update score, mixing with topic weight
+            score += topic_score * topic_params.topic_weight;
+        }
+
+        // apply the topic score cap, if any
+        if self.params.topic_score_cap > 0f64 && score > self.params.topic_score_cap {
+            score = self.params.topic_score_cap;
+        }
+
+        // P5: application-specific score
+        let p5 = peer_stats.application_score;
+        score += p5 * self.params.app_specific_weight;
+
+        // P6: IP collocation factor
+        for ip in peer_stats.known_ips.iter() {
+            if self.params.ip_colocation_factor_whitelist.contains(ip) {
+                continue;
+            }
+
+            // P6 has a cliff (ip_colocation_factor_threshold); it's only applied iff
+            // at least that many peers are connected to us from that source IP
+            // addr. It is quadratic, and the weight is negative (validated by
+            // peer_score_params.validate()).
+            if let Some(peers_in_ip) = self.peer_ips.get(ip).map(|peers| peers.len()) {
+                if (peers_in_ip as f64) > self.params.ip_colocation_factor_threshold {
+                    let surplus = (peers_in_ip as f64) - self.params.ip_colocation_factor_threshold;
+                    let p6 = surplus * surplus;
+                    score += p6 * self.params.ip_colocation_factor_weight;
+                }
+            }
+        }
+
+        // P7: behavioural pattern penalty
+        if peer_stats.behaviour_penalty > self.params.behaviour_penalty_threshold {
+            let excess = peer_stats.behaviour_penalty - self.params.behaviour_penalty_threshold;
+            let p7 = excess * excess;
+            score += p7 * self.params.behaviour_penalty_weight;
+        }
+        score
+    }
+
+    /// Adds a behavioural penalty to a peer.
+    pub fn add_penalty(&mut self, peer_id: &PeerId, count: usize) {
+        if let Some(peer_stats) = self.peer_stats.get_mut(peer_id) {
+            debug!(
+                "Behavioral penalty for peer {}, count = {}.",
+                peer_id, count
+            );
+            peer_stats.behaviour_penalty += count as f64;
+        }
+    }
+
+    /// Decays the counters of all peers and forgets the disconnected peers whose retention
+    /// period is over. Should be called every `decay_interval`.
+    pub fn refresh_scores(&mut self) {
+        let now = Instant::now();
+        let params = &self.params;
+        let peer_ips = &mut self.peer_ips;
+        self.peer_stats.retain(|peer_id, peer_stats| {
+            if let ConnectionStatus::Disconnected { expires } = peer_stats.status {
+                // has the retention period expired?
+                if now > expires {
+                    // yes, throw it away (but clean up the IP tracking first)
+                    remove_ips_for_peer(peer_stats, peer_ips, peer_id);
+                    return false;
+                }
+
+                // we don't decay retained scores, as the peer is not active.
+                // this way the peer cannot reset a negative score by simply disconnecting and
+                // reconnecting, unless the retention period has elapsed.
+                // similarly, a well behaved peer does not lose its score by getting disconnected.
+                return true;
+            }
+
+            for (topic, topic_stats) in peer_stats.topics.iter_mut() {
+                // the topic parameters
+                let topic_params = match params.topics.get(topic) {
+                    Some(params) => params,
+                    None => continue,
+                };
+
+                // decay counters
+                topic_stats.first_message_deliveries *=
+                    topic_params.first_message_deliveries_decay;
+                if topic_stats.first_message_deliveries < params.decay_to_zero {
+                    topic_stats.first_message_deliveries = 0.0;
+                }
+                topic_stats.mesh_message_deliveries *= topic_params.mesh_message_deliveries_decay;
+                if topic_stats.mesh_message_deliveries < params.decay_to_zero {
+                    topic_stats.mesh_message_deliveries = 0.0;
+                }
+                topic_stats.mesh_failure_penalty *= topic_params.mesh_failure_penalty_decay;
+                if topic_stats.mesh_failure_penalty < params.decay_to_zero {
+                    topic_stats.mesh_failure_penalty = 0.0;
+                }
+                topic_stats.invalid_message_deliveries *=
+                    topic_params.invalid_message_deliveries_decay;
+                if topic_stats.invalid_message_deliveries < params.decay_to_zero {
+                    topic_stats.invalid_message_deliveries = 0.0;
+                }
+                // update mesh time and activate mesh message delivery parameter if need be
+                if let MeshStatus::Active {
+                    ref mut mesh_time,
+                    ref mut graft_time,
+                } = topic_stats.mesh_status
+                {
+                    *mesh_time = now.duration_since(*graft_time);
+                    if *mesh_time > topic_params.mesh_message_deliveries_activation {
+                        topic_stats.mesh_message_deliveries_active = true;
+                    }
+                }
+            }
+
+            // decay P7 counter
+            peer_stats.behaviour_penalty *= params.behaviour_penalty_decay;
+            if peer_stats.behaviour_penalty < params.decay_to_zero {
+                peer_stats.behaviour_penalty = 0.0;
+            }
+            true
+        });
+
+        // forget the delivery records that expired
+        while let Some((expires, _)) = self.delivery_expirations.front() {
+            if *expires > now {
+                break;
+            }
+            if let Some((_, message_id)) = self.delivery_expirations.pop_front() {
+                self.deliveries.remove(&message_id);
+            }
+        }
+    }
+
+    /// Adds a connected peer to `PeerScore`, initialising with empty ips (ips get added later
+    /// through add_ip.
+    pub fn add_peer(&mut self, peer_id: PeerId) {
+        let peer_stats = self.peer_stats.entry(peer_id).or_default();
+
+        // mark the peer as connected
+        peer_stats.status = ConnectionStatus::Connected;
+    }
+
+    /// Adds a new ip to a peer, if the peer is not yet known creates a new peer_stats entry for it
+    pub fn add_ip(&mut self, peer_id: &PeerId, ip: IpAddr) {
+        trace!("Add ip for peer {}, ip: {}", peer_id, ip);
+        let peer_stats = self.peer_stats.entry(peer_id.clone()).or_default();
+
+        // Mark the peer as connected (currently the default is connected, but we don't want to
+        // rely on the default).
+        peer_stats.status = ConnectionStatus::Connected;
+
+        // Insert the ip
+        peer_stats.known_ips.insert(ip);
+        self.peer_ips
+            .entry(ip)
+            .or_default()
+            .insert(peer_id.clone());
+    }
+
+    /// Removes an ip from a peer
+    pub fn remove_ip(&mut self, peer_id: &PeerId, ip: &IpAddr) {
+        if let Some(peer_stats) = self.peer_stats.get_mut(peer_id) {
+            peer_stats.known_ips.remove(ip);
+            if let Some(peer_ids) = self.peer_ips.get_mut(ip) {
+                trace!("Remove ip for peer {}, ip: {}", peer_id, ip);
+                peer_ids.remove(peer_id);
+                if peer_ids.is_empty() {
+                    self.peer_ips.remove(ip);
+                }
+            } else {
+                trace!(
+                    "No entry in peer_ips for ip {} which should get removed for peer {}",
+                    ip,
+                    peer_id
+                );
+            }
+        } else {
+            trace!(
+                "No peer_stats for peer {} which should remove the ip {}",
+                peer_id,
+                ip
+            );
+        }
+    }
+
+    /// Removes a peer from the score table. This retains peer statistics if their score is
+    /// non-positive.
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        // we only retain non-positive scores of peers
+        if self.score(peer_id) > 0f64 {
+            if let Some(mut peer_stats) = self.peer_stats.remove(peer_id) {
+                remove_ips_for_peer(&mut peer_stats, &mut self.peer_ips, peer_id);
+            }
+            return;
+        }
+
+        // if the peer is retained (including it's score) the `first_message_delivery` counters
+        // are reset to 0 and mesh delivery penalties applied.
+        if let Some(peer_stats) = self.peer_stats.get_mut(peer_id) {
+            for (topic, topic_stats) in peer_stats.topics.iter_mut() {
+                topic_stats.first_message_deliveries = 0f64;
+
+                if let Some(threshold) = self
+                    .params
+                    .topics
+                    .get(topic)
+                    .map(|param| param.mesh_message_deliveries_threshold)
+                {
+                    if topic_stats.in_mesh()
+                        && topic_stats.mesh_message_deliveries_active
+                        && topic_stats.mesh_message_deliveries < threshold
+                    {
+                        let deficit = threshold - topic_stats.mesh_message_deliveries;
+                        topic_stats.mesh_failure_penalty += deficit * deficit;
+                    }
+                }
+
+                topic_stats.mesh_status = MeshStatus::InActive;
+                topic_stats.mesh_message_deliveries_active = false;
+            }
+
+            peer_stats.status = ConnectionStatus::Disconnected {
+                expires: Instant::now() + self.params.retain_score,
+            };
+        }
+    }
+
+    /// Handles scoring functionality as a peer GRAFTs to a topic.
+    pub fn graft(&mut self, peer_id: &PeerId, topic_hash: impl Into<TopicHash>) {
+        let topic_hash = topic_hash.into();
+        if !self.params.topics.contains_key(&topic_hash) {
+            return;
+        }
+        if let Some(peer_stats) = self.peer_stats.get_mut(peer_id) {
+            // if we are scoring the topic, update the mesh status.
+            let topic_stats = peer_stats.topics.entry(topic_hash).or_default();
+            topic_stats.mesh_status = MeshStatus::new_active();
+            topic_stats.mesh_message_deliveries_active = false;
+        }
+    }
+
+    /// Handles scoring functionality as a peer PRUNEs from a topic.
+    pub fn prune(&mut self, peer_id: &PeerId, topic_hash: TopicHash) {
+        let threshold = match self.params.topics.get(&topic_hash) {
+            Some(params) => params.mesh_message_deliveries_threshold,
+            None => return,
+        };
+        if let Some(topic_stats) = self
+            .peer_stats
+            .get_mut(peer_id)
+            .and_then(|stats| stats.topics.get_mut(&topic_hash))
+        {
+            // sticky mesh delivery rate failure penalty
+            if topic_stats.mesh_message_deliveries_active
+                && topic_stats.mesh_message_deliveries < threshold
+            {
+                let deficit = threshold - topic_stats.mesh_message_deliveries;
+                topic_stats.mesh_failure_penalty += deficit * deficit;
+            }
+            topic_stats.mesh_message_deliveries_active = false;
+            topic_stats.mesh_status = MeshStatus::InActive;
+        }
+    }
+
+    /// Records that a message was received for the first time and is pending validation.
+    pub fn validate_message(&mut self, _from: &PeerId, msg_id: &MessageId, _topics: &[TopicHash]) {
+        // adds an empty record with the message id
+        self.delivery_record(msg_id);
+    }
+
+    /// Records that a message was validated, crediting the peer that first delivered it and
+    /// the peers that delivered it in the meantime.
+    pub fn deliver_message(&mut self, from: &PeerId, msg_id: &MessageId, topics: &[TopicHash]) {
+        self.mark_first_message_delivery(from, topics);
+
+        let record = self.delivery_record(msg_id);
+
+        // this should be the first delivery trace
+        if !matches!(record.status, DeliveryStatus::Unknown) {
+            debug!(
+                "Unexpected delivery trace: Message from {} was first seen {}s ago and has a \
+                 delivery status",
+                from,
+                record.first_seen.elapsed().as_secs()
+            );
+            return;
+        }
+
+        // mark the message as valid and reward mesh peers that have already forwarded it to us
+        record.status = DeliveryStatus::Valid(Instant::now());
+        let peers = record.peers.iter().cloned().collect::<Vec<_>>();
+        for peer in peers.iter().filter(|peer| *peer != from) {
+            // this check is to make sure a peer can't send us a message twice and get a double
+            // count if it is a first delivery
+            self.mark_duplicate_message_delivery(peer, topics, None);
+        }
+    }
+
+    /// Records that a peer sent a message failing validation before it even reached the
+    /// application, e.g. because of an invalid signature. Only that peer is penalised.
+    pub fn reject_invalid_message(&mut self, from: &PeerId, topics: &[TopicHash]) {
+        self.mark_invalid_message_delivery(from, topics);
+    }
+
+    /// Records that a message was rejected by the application. The peers that delivered it are
+    /// penalised.
+    pub fn reject_message(&mut self, from: &PeerId, msg_id: &MessageId, topics: &[TopicHash]) {
+        let record = self.delivery_record(msg_id);
+
+        // Multiple peers can now reject the same message as we track which peers send us the
+        // message. If we have already updated the status, return.
+        if !matches!(record.status, DeliveryStatus::Unknown) {
+            return;
+        }
+
+        // mark the message as invalid and penalize peers that have already forwarded it.
+        record.status = DeliveryStatus::Invalid;
+        let peers = record.peers.drain().collect::<Vec<_>>();
+
+        self.mark_invalid_message_delivery(from, topics);
+        for peer_id in peers.iter() {
+            self.mark_invalid_message_delivery(peer_id, topics)
+        }
+    }
+
+    /// Records that the application chose to ignore a message, without penalising anyone.
+    pub fn ignore_message(&mut self, msg_id: &MessageId) {
+        let record = self.delivery_record(msg_id);
+        if matches!(record.status, DeliveryStatus::Unknown) {
+            record.status = DeliveryStatus::Ignored;
+            record.peers.clear();
+        }
+    }
+
+    /// Records that a message we had already received was delivered again by `from`.
+    pub fn duplicated_message(&mut self, from: &PeerId, msg_id: &MessageId, topics: &[TopicHash]) {
+        let record = self.delivery_record(msg_id);
+
+        if record.peers.contains(from) {
+            // we have already seen this duplicate!
+            return;
+        }
+
+        match record.status {
+            DeliveryStatus::Unknown => {
+                // the message is being validated; track the peer delivery and wait for
+                // the Deliver/Reject notification.
+                record.peers.insert(from.clone());
+            }
+            DeliveryStatus::Valid(validated) => {
+                // mark the peer delivery time to only count a duplicate delivery once.
+                record.peers.insert(from.clone());
+                self.mark_duplicate_message_delivery(from, topics, Some(validated));
+            }
+            DeliveryStatus::Invalid => {
+                // we no longer track delivery time
+                self.mark_invalid_message_delivery(from, topics);
+            }
+            DeliveryStatus::Ignored => {
+                // the message was ignored; do nothing (we don't know if it was valid)
+            }
+        }
+    }
+
+    /// Sets the application specific score for a peer. Returns true if the peer is
+    /// connected or if the score of the peer is not yet expired and false otherwise.
+    pub fn set_application_score(&mut self, peer_id: &PeerId, new_score: f64) -> bool {
+        if let Some(peer_stats) = self.peer_stats.get_mut(peer_id) {
+            peer_stats.application_score = new_score;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Sets scoring parameters for a topic.
+    pub fn set_topic_params(&mut self, topic_hash: TopicHash, params: TopicScoreParams) {
+        self.params.topics.insert(topic_hash, params);
+    }
+
+    /// Returns the delivery record of a message, creating it if it doesn't exist.
+    fn delivery_record(&mut self, msg_id: &MessageId) -> &mut DeliveryRecord {
+        let expirations = &mut self.delivery_expirations;
+        self.deliveries.entry(msg_id.clone()).or_insert_with(|| {
+            expirations.push_back((Instant::now() + TIME_CACHE_DURATION, msg_id.clone()));
+            DeliveryRecord::default()
+        })
+    }
+
+    /// Increments the "invalid message deliveries" counter for all scored topics the message
+    /// is published in.
+    fn mark_invalid_message_delivery(&mut self, peer_id: &PeerId, topics: &[TopicHash]) {
+        if let Some(peer_stats) = self.peer_stats.get_mut(peer_id) {
+            for topic_hash in topics {
+                if self.params.topics.contains_key(topic_hash) {
+                    debug!(
+                        "Peer {} delivered an invalid message in topic {} and gets penalized \
+                         for it",
+                        peer_id, topic_hash
+                    );
+                    peer_stats
+                        .topics
+                        .entry(topic_hash.clone())
+                        .or_default()
+                        .invalid_message_deliveries += 1f64;
+                }
+            }
+        }
+    }
+
+    /// Increments the "first message deliveries" counter for all scored topics the message is
+    /// published in, as well as the "mesh message deliveries" counter, if the peer is in the
+    /// mesh for the topic.
+    fn mark_first_message_delivery(&mut self, peer_id: &PeerId, topics: &[TopicHash]) {
+        if let Some(peer_stats) = self.peer_stats.get_mut(peer_id) {
+            for topic_hash in topics {
+                let topic_params = match self.params.topics.get(topic_hash) {
+                    Some(params) => params,
+                    None => continue,
+                };
+                let topic_stats = peer_stats.topics.entry(topic_hash.clone()).or_default();
+
+                let cap = topic_params.first_message_deliveries_cap;
+                topic_stats.first_message_deliveries =
+                    (topic_stats.first_message_deliveries + 1f64).min(cap);
+
+                if topic_stats.in_mesh() {
+                    let cap = topic_params.mesh_message_deliveries_cap;
+                    topic_stats.mesh_message_deliveries =
+                        (topic_stats.mesh_message_deliveries + 1f64).min(cap);
+                }
+            }
+        }
+    }
+
+    /// Increments the "mesh message deliveries" counter for messages we've seen before, as long
+    /// the message was received within the P3 window.
+    fn mark_duplicate_message_delivery(
+        &mut self,
+        peer_id: &PeerId,
+        topics: &[TopicHash],
+        validated_time: Option<Instant>,
+    ) {
+        if let Some(peer_stats) = self.peer_stats.get_mut(peer_id) {
+            let now = if validated_time.is_some() {
+                Some(Instant::now())
+            } else {
+                None
+            };
+            for topic_hash in topics {
+                let topic_params = match self.params.topics.get(topic_hash) {
+                    Some(params) => params,
+                    None => continue,
+                };
+                let topic_stats = match peer_stats.topics.get_mut(topic_hash) {
+                    Some(stats) if stats.in_mesh() => stats,
+                    _ => continue,
+                };
+
+                // check against the mesh delivery window -- if the validated time is passed as
+                // None, then the message was received before we finished validation and thus it
+                // falls within the mesh delivery window.
+                if let (Some(validated_time), Some(now)) = (validated_time, now) {
+                    if now.duration_since(validated_time)
+                        > topic_params.mesh_message_deliveries_window
+                    {
+                        continue;
+                    }
+                }
+
+                let cap = topic_params.mesh_message_deliveries_cap;
+                topic_stats.mesh_message_deliveries =
+                    (topic_stats.mesh_message_deliveries + 1f64).min(cap);
+            }
+        }
+    }
+}
+
+/// Removes all the IPs of a peer from the IP tracking.
+fn remove_ips_for_peer(
+    peer_stats: &mut PeerStats,
+    peer_ips: &mut HashMap<IpAddr, HashSet<PeerId>>,
+    peer_id: &PeerId,
+) {
+    for ip in peer_stats.known_ips.drain() {
+        if let Some(peer_set) = peer_ips.get_mut(&ip) {
+            peer_set.remove(peer_id);
+            if peer_set.is_empty() {
+                peer_ips.remove(&ip);
+            }
+        }
+    }
+}
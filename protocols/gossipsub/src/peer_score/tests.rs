@@ -0,0 +1,425 @@
+// Copyright 2020 Sigma Prime Pty Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+/// A collection of unit tests mostly ported from the go implementation.
+use super::*;
+
+use crate::{GossipsubMessage, Topic};
+
+// estimates a value within variance
+fn within_variance(value: f64, expected: f64, variance: f64) -> bool {
+    if expected >= 0.0 {
+        return value > expected * (1.0 - variance) && value < expected * (1.0 + variance);
+    }
+    value > expected * (1.0 + variance) && value < expected * (1.0 - variance)
+}
+
+// generates a random gossipsub message with sequence number i
+fn make_test_message(seq: u64, topic: &TopicHash) -> (MessageId, GossipsubMessage) {
+    let m = GossipsubMessage {
+        source: Some(PeerId::random()),
+        data: vec![12, 34, 56],
+        sequence_number: Some(seq),
+        topics: vec![topic.clone()],
+        signature: None,
+        key: None,
+    };
+    let id = MessageId(format!("{}{}", m.source.as_ref().unwrap().to_base58(), seq));
+    (id, m)
+}
+
+fn topic_hash() -> TopicHash {
+    Topic::new("test".into()).no_hash()
+}
+
+// peer score parameters scoring a single topic with `topic_params`, every other parameter
+// disabled
+fn params_with_topic(topic_params: TopicScoreParams) -> PeerScoreParams {
+    let mut params = PeerScoreParams {
+        app_specific_weight: 0.0,
+        ip_colocation_factor_weight: 0.0,
+        behaviour_penalty_weight: 0.0,
+        ..Default::default()
+    };
+    params.topics.insert(topic_hash(), topic_params);
+    params
+}
+
+// topic parameters with every component disabled
+fn disabled_topic_params() -> TopicScoreParams {
+    TopicScoreParams {
+        topic_weight: 1.0,
+        time_in_mesh_weight: 0.0,
+        first_message_deliveries_weight: 0.0,
+        mesh_message_deliveries_weight: 0.0,
+        mesh_failure_penalty_weight: 0.0,
+        invalid_message_deliveries_weight: 0.0,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_score_time_in_mesh() {
+    let topic_params = TopicScoreParams {
+        topic_weight: 0.5,
+        time_in_mesh_weight: 1.0,
+        time_in_mesh_quantum: Duration::from_millis(1),
+        time_in_mesh_cap: 3600.0,
+        ..disabled_topic_params()
+    };
+    let mut peer_score = PeerScore::new(params_with_topic(topic_params));
+
+    let peer_id = PeerId::random();
+    peer_score.add_peer(peer_id.clone());
+
+    // not in the mesh yet
+    assert_eq!(peer_score.score(&peer_id), 0.0);
+
+    peer_score.graft(&peer_id, topic_hash());
+    let elapsed = Duration::from_millis(200);
+    std::thread::sleep(elapsed);
+    peer_score.refresh_scores();
+
+    let score = peer_score.score(&peer_id);
+    let expected = 0.5 * 1.0 * elapsed.as_millis() as f64;
+    assert!(
+        score >= expected,
+        "The score: {} should be at least {}",
+        score,
+        expected
+    );
+}
+
+#[test]
+fn test_score_time_in_mesh_cap() {
+    let topic_params = TopicScoreParams {
+        topic_weight: 0.5,
+        time_in_mesh_weight: 1.0,
+        time_in_mesh_quantum: Duration::from_millis(1),
+        time_in_mesh_cap: 10.0,
+        ..disabled_topic_params()
+    };
+    let mut peer_score = PeerScore::new(params_with_topic(topic_params));
+
+    let peer_id = PeerId::random();
+    peer_score.add_peer(peer_id.clone());
+    peer_score.graft(&peer_id, topic_hash());
+    std::thread::sleep(Duration::from_millis(40));
+    peer_score.refresh_scores();
+
+    assert_eq!(peer_score.score(&peer_id), 0.5 * 10.0);
+}
+
+#[test]
+fn test_score_first_message_deliveries() {
+    let topic_params = TopicScoreParams {
+        topic_weight: 1.0,
+        first_message_deliveries_weight: 1.0,
+        first_message_deliveries_decay: 0.5,
+        first_message_deliveries_cap: 2000.0,
+        ..disabled_topic_params()
+    };
+    let mut peer_score = PeerScore::new(params_with_topic(topic_params));
+
+    let peer_id = PeerId::random();
+    peer_score.add_peer(peer_id.clone());
+    peer_score.graft(&peer_id, topic_hash());
+
+    // deliver a bunch of messages from the peer
+    let messages = 100;
+    for seq in 0..messages {
+        let (id, msg) = make_test_message(seq, &topic_hash());
+        peer_score.validate_message(&peer_id, &id, &msg.topics);
+        peer_score.deliver_message(&peer_id, &id, &msg.topics);
+    }
+
+    peer_score.refresh_scores();
+
+    let score = peer_score.score(&peer_id);
+    let expected = 1.0 * 1.0 * messages as f64 * 0.5;
+    assert_eq!(score, expected, "The score should be {}", expected);
+}
+
+#[test]
+fn test_score_first_message_deliveries_cap() {
+    let topic_params = TopicScoreParams {
+        topic_weight: 1.0,
+        first_message_deliveries_weight: 1.0,
+        first_message_deliveries_decay: 1.0, // test without decay
+        first_message_deliveries_cap: 50.0,
+        ..disabled_topic_params()
+    };
+    let mut peer_score = PeerScore::new(params_with_topic(topic_params));
+
+    let peer_id = PeerId::random();
+    peer_score.add_peer(peer_id.clone());
+    peer_score.graft(&peer_id, topic_hash());
+
+    for seq in 0..100 {
+        let (id, msg) = make_test_message(seq, &topic_hash());
+        peer_score.validate_message(&peer_id, &id, &msg.topics);
+        peer_score.deliver_message(&peer_id, &id, &msg.topics);
+    }
+
+    peer_score.refresh_scores();
+    assert_eq!(peer_score.score(&peer_id), 50.0);
+}
+
+#[test]
+fn test_score_mesh_message_deliveries() {
+    let topic_params = TopicScoreParams {
+        topic_weight: 1.0,
+        mesh_message_deliveries_weight: -1.0,
+        mesh_message_deliveries_activation: Duration::from_millis(100),
+        mesh_message_deliveries_window: Duration::from_millis(10),
+        mesh_message_deliveries_threshold: 20.0,
+        mesh_message_deliveries_cap: 100.0,
+        mesh_message_deliveries_decay: 1.0,
+        ..disabled_topic_params()
+    };
+    let mut peer_score = PeerScore::new(params_with_topic(topic_params));
+
+    // peer A always delivers the message first, peer B delivers within the window, peer C
+    // delivers after the window and gets penalised
+    let peer_id_a = PeerId::random();
+    let peer_id_b = PeerId::random();
+    let peer_id_c = PeerId::random();
+    let peers = vec![peer_id_a.clone(), peer_id_b.clone(), peer_id_c.clone()];
+    for peer_id in &peers {
+        peer_score.add_peer(peer_id.clone());
+        peer_score.graft(peer_id, topic_hash());
+    }
+
+    // assert that nobody has been penalized yet for not delivering messages before activation
+    // time
+    peer_score.refresh_scores();
+    for peer_id in &peers {
+        assert_eq!(peer_score.score(peer_id), 0.0);
+    }
+
+    // wait for the activation time to kick in
+    std::thread::sleep(Duration::from_millis(110));
+
+    for seq in 0..100 {
+        let (id, msg) = make_test_message(seq, &topic_hash());
+        peer_score.validate_message(&peer_id_a, &id, &msg.topics);
+        peer_score.deliver_message(&peer_id_a, &id, &msg.topics);
+        peer_score.duplicated_message(&peer_id_b, &id, &msg.topics);
+    }
+    std::thread::sleep(Duration::from_millis(20));
+    for seq in 0..100 {
+        let (id, msg) = make_test_message(seq, &topic_hash());
+        peer_score.duplicated_message(&peer_id_c, &id, &msg.topics);
+    }
+
+    peer_score.refresh_scores();
+    assert_eq!(peer_score.score(&peer_id_a), 0.0);
+    assert_eq!(peer_score.score(&peer_id_b), 0.0);
+    // C delivered no messages in the window, so it is penalised by the square of the threshold
+    assert_eq!(peer_score.score(&peer_id_c), -400.0);
+}
+
+#[test]
+fn test_score_mesh_failure_penalty() {
+    let topic_params = TopicScoreParams {
+        topic_weight: 1.0,
+        mesh_message_deliveries_weight: -1.0,
+        mesh_message_deliveries_activation: Duration::from_millis(0),
+        mesh_message_deliveries_threshold: 5.0,
+        mesh_message_deliveries_decay: 1.0,
+        mesh_failure_penalty_weight: -1.0,
+        mesh_failure_penalty_decay: 1.0,
+        ..disabled_topic_params()
+    };
+    let mut peer_score = PeerScore::new(params_with_topic(topic_params));
+
+    let peer_id_a = PeerId::random();
+    let peer_id_b = PeerId::random();
+    for peer_id in &[peer_id_a.clone(), peer_id_b.clone()] {
+        peer_score.add_peer(peer_id.clone());
+        peer_score.graft(peer_id, topic_hash());
+    }
+
+    for seq in 0..10 {
+        let (id, msg) = make_test_message(seq, &topic_hash());
+        peer_score.validate_message(&peer_id_a, &id, &msg.topics);
+        peer_score.deliver_message(&peer_id_a, &id, &msg.topics);
+    }
+
+    // activate the mesh message deliveries
+    std::thread::sleep(Duration::from_millis(5));
+    peer_score.refresh_scores();
+
+    assert_eq!(peer_score.score(&peer_id_a), 0.0);
+    assert_eq!(peer_score.score(&peer_id_b), -25.0);
+
+    // prune peer B; the penalty becomes sticky and is not reset by the mesh deliveries
+    peer_score.prune(&peer_id_b, topic_hash());
+    peer_score.refresh_scores();
+    assert_eq!(peer_score.score(&peer_id_b), -25.0);
+}
+
+#[test]
+fn test_score_invalid_message_deliveries() {
+    let topic_params = TopicScoreParams {
+        topic_weight: 1.0,
+        invalid_message_deliveries_weight: -1.0,
+        invalid_message_deliveries_decay: 1.0,
+        ..disabled_topic_params()
+    };
+    let mut peer_score = PeerScore::new(params_with_topic(topic_params));
+
+    let peer_id_a = PeerId::random();
+    let peer_id_b = PeerId::random();
+    peer_score.add_peer(peer_id_a.clone());
+    peer_score.add_peer(peer_id_b.clone());
+
+    for seq in 0..10 {
+        let (id, msg) = make_test_message(seq, &topic_hash());
+        peer_score.validate_message(&peer_id_a, &id, &msg.topics);
+        // B delivers the message while it is being validated, so it gets penalised as well
+        peer_score.duplicated_message(&peer_id_b, &id, &msg.topics);
+        peer_score.reject_message(&peer_id_a, &id, &msg.topics);
+    }
+
+    assert_eq!(peer_score.score(&peer_id_a), -100.0);
+    assert_eq!(peer_score.score(&peer_id_b), -100.0);
+}
+
+#[test]
+fn test_score_ignored_message() {
+    let topic_params = TopicScoreParams {
+        topic_weight: 1.0,
+        first_message_deliveries_weight: 1.0,
+        invalid_message_deliveries_weight: -1.0,
+        ..disabled_topic_params()
+    };
+    let mut peer_score = PeerScore::new(params_with_topic(topic_params));
+
+    let peer_id = PeerId::random();
+    peer_score.add_peer(peer_id.clone());
+
+    let (id, msg) = make_test_message(1, &topic_hash());
+    peer_score.validate_message(&peer_id, &id, &msg.topics);
+    peer_score.ignore_message(&id);
+    // an ignored message can't be rejected anymore
+    peer_score.reject_message(&peer_id, &id, &msg.topics);
+    peer_score.duplicated_message(&PeerId::random(), &id, &msg.topics);
+
+    assert_eq!(peer_score.score(&peer_id), 0.0);
+}
+
+#[test]
+fn test_score_application_score() {
+    let mut params = params_with_topic(disabled_topic_params());
+    params.app_specific_weight = 0.5;
+    let mut peer_score = PeerScore::new(params);
+
+    let peer_id = PeerId::random();
+    assert!(!peer_score.set_application_score(&peer_id, 1.0));
+    peer_score.add_peer(peer_id.clone());
+    assert!(peer_score.set_application_score(&peer_id, -4.0));
+
+    assert_eq!(peer_score.score(&peer_id), -2.0);
+}
+
+#[test]
+fn test_score_ip_colocation() {
+    let mut params = params_with_topic(disabled_topic_params());
+    params.ip_colocation_factor_weight = -1.0;
+    params.ip_colocation_factor_threshold = 1.0;
+    let whitelisted: IpAddr = "2.3.4.5".parse().unwrap();
+    params.ip_colocation_factor_whitelist.insert(whitelisted);
+    let mut peer_score = PeerScore::new(params);
+
+    let ip: IpAddr = "1.2.3.4".parse().unwrap();
+    let peers: Vec<PeerId> = (0..4).map(|_| PeerId::random()).collect();
+    for peer_id in &peers[..3] {
+        peer_score.add_peer(peer_id.clone());
+        peer_score.add_ip(peer_id, ip);
+    }
+    peer_score.add_peer(peers[3].clone());
+    peer_score.add_ip(&peers[3], whitelisted);
+
+    // 3 peers share the same ip, the surplus is 2
+    for peer_id in &peers[..3] {
+        assert_eq!(peer_score.score(peer_id), -4.0);
+    }
+    assert_eq!(peer_score.score(&peers[3]), 0.0);
+
+    peer_score.remove_ip(&peers[0], &ip);
+    assert_eq!(peer_score.score(&peers[0]), 0.0);
+    assert_eq!(peer_score.score(&peers[1]), -1.0);
+}
+
+#[test]
+fn test_score_behaviour_penalty() {
+    let mut params = params_with_topic(disabled_topic_params());
+    params.behaviour_penalty_weight = -1.0;
+    params.behaviour_penalty_decay = 0.99;
+    let mut peer_score = PeerScore::new(params);
+
+    let peer_id = PeerId::random();
+
+    // add a penalty to a non-existent peer
+    peer_score.add_penalty(&peer_id, 1);
+    assert_eq!(peer_score.score(&peer_id), 0.0);
+
+    peer_score.add_peer(peer_id.clone());
+    peer_score.add_penalty(&peer_id, 1);
+    assert_eq!(peer_score.score(&peer_id), -1.0);
+    peer_score.add_penalty(&peer_id, 1);
+    assert_eq!(peer_score.score(&peer_id), -4.0);
+
+    peer_score.refresh_scores();
+    assert!(within_variance(
+        peer_score.score(&peer_id),
+        -3.9204,
+        1e-6
+    ));
+}
+
+#[test]
+fn test_score_retention() {
+    let mut params = params_with_topic(disabled_topic_params());
+    params.app_specific_weight = 1.0;
+    params.retain_score = Duration::from_millis(50);
+    let mut peer_score = PeerScore::new(params);
+
+    let peer_id = PeerId::random();
+    peer_score.add_peer(peer_id.clone());
+    peer_score.set_application_score(&peer_id, -1000.0);
+
+    // the negative score is retained after disconnection
+    peer_score.remove_peer(&peer_id);
+    peer_score.refresh_scores();
+    assert_eq!(peer_score.score(&peer_id), -1000.0);
+
+    // and forgotten once the retention period expired
+    std::thread::sleep(Duration::from_millis(60));
+    peer_score.refresh_scores();
+    assert_eq!(peer_score.score(&peer_id), 0.0);
+
+    // positive scores are not retained at all
+    peer_score.add_peer(peer_id.clone());
+    peer_score.set_application_score(&peer_id, 1000.0);
+    peer_score.remove_peer(&peer_id);
+    assert_eq!(peer_score.score(&peer_id), 0.0);
+}
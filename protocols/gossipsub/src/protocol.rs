@@ -19,6 +19,7 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::behaviour::GossipsubRpc;
+use crate::config::ValidationMode;
 use crate::rpc_proto;
 use crate::topic::TopicHash;
 use byteorder::{BigEndian, ByteOrder};
@@ -27,9 +28,14 @@ use bytes::BytesMut;
 use futures::future;
 use futures::prelude::*;
 use futures_codec::{Decoder, Encoder, Framed};
-use libp2p_core::{InboundUpgrade, OutboundUpgrade, PeerId, UpgradeInfo};
+use libp2p_core::{
+    identity::{error::SigningError, Keypair},
+    multiaddr::multihash::{Code, Multihash},
+    InboundUpgrade, OutboundUpgrade, PeerId, PublicKey, UpgradeInfo,
+};
 use prost::Message as ProtobufMessage;
-use std::{borrow::Cow, fmt, io, iter, pin::Pin};
+use smallvec::SmallVec;
+use std::{borrow::Cow, fmt, io, pin::Pin};
 use unsigned_varint::codec;
 
 /// Implementation of the `ConnectionUpgrade` for the Gossipsub protocol.
@@ -39,10 +45,19 @@ pub struct ProtocolConfig {
     max_transmit_size: usize,
 }
 
+/// The protocol id of gossipsub v1.1.
+pub const GOSSIPSUB_1_1_0_PROTOCOL: &[u8] = b"/meshsub/1.1.0";
+
+/// The protocol id of gossipsub v1.0, which v1.1 nodes remain compatible with.
+pub const GOSSIPSUB_1_0_0_PROTOCOL: &[u8] = b"/meshsub/1.0.0";
+
+/// The prefix of the bytes covered by a message signature.
+const SIGNING_PREFIX: &[u8] = b"libp2p-pubsub:";
+
 impl Default for ProtocolConfig {
     fn default() -> Self {
         Self {
-            protocol_id: Cow::Borrowed(b"/meshsub/1.0.0"),
+            protocol_id: Cow::Borrowed(GOSSIPSUB_1_1_0_PROTOCOL),
             max_transmit_size: 2048,
         }
     }
@@ -64,10 +79,17 @@ impl ProtocolConfig {
 
 impl UpgradeInfo for ProtocolConfig {
     type Info = Cow<'static, [u8]>;
-    type InfoIter = iter::Once<Self::Info>;
+    type InfoIter = smallvec::IntoIter<[Self::Info; 2]>;
 
     fn protocol_info(&self) -> Self::InfoIter {
-        iter::once(self.protocol_id.clone())
+        let mut protocols = SmallVec::new();
+        protocols.push(self.protocol_id.clone());
+        // v1.1 is a backwards compatible extension of v1.0, so we keep accepting the older
+        // version when running with the default protocol id.
+        if self.protocol_id.as_ref() == GOSSIPSUB_1_1_0_PROTOCOL {
+            protocols.push(Cow::Borrowed(GOSSIPSUB_1_0_0_PROTOCOL));
+        }
+        protocols.into_iter()
     }
 }
 
@@ -123,16 +145,7 @@ impl Encoder for GossipsubCodec {
         let publish = item
             .messages
             .into_iter()
-            .map(|message| rpc_proto::Message {
-                from: Some(message.source.into_bytes()),
-                data: Some(message.data),
-                seqno: Some(message.sequence_number.to_be_bytes().to_vec()),
-                topic_ids: message
-                    .topics
-                    .into_iter()
-                    .map(TopicHash::into_string)
-                    .collect(),
-            })
+            .map(GossipsubMessage::into_protobuf)
             .collect::<Vec<_>>();
 
         // subscriptions
@@ -180,9 +193,13 @@ impl Encoder for GossipsubCodec {
                     };
                     control.graft.push(rpc_graft);
                 }
-                GossipsubControlAction::Prune { topic_hash } => {
+                GossipsubControlAction::Prune {
+                    topic_hash,
+                    backoff,
+                } => {
                     let rpc_prune = rpc_proto::ControlPrune {
                         topic_id: Some(topic_hash.into_string()),
+                        backoff,
                     };
                     control.prune.push(rpc_prune);
                 }
@@ -223,29 +240,36 @@ impl Decoder for GossipsubCodec {
 
         let mut messages = Vec::with_capacity(rpc.publish.len());
         for publish in rpc.publish.into_iter() {
+            // the source and sequence number are optional, but must be well-formed if present;
+            // whether their absence is acceptable is decided by the validation mode
+            let source = match publish.from {
+                Some(from) => Some(PeerId::from_bytes(from).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "Invalid Peer Id")
+                })?),
+                None => None,
+            };
             // ensure the sequence number is a u64
-            let seq_no = publish.seqno.ok_or_else(|| {
-                io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "sequence number was not provided",
-                )
-            })?;
-            if seq_no.len() != 8 {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "sequence number has an incorrect size",
-                ));
-            }
+            let sequence_number = match publish.seqno {
+                Some(seq_no) if seq_no.len() != 8 => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "sequence number has an incorrect size",
+                    ));
+                }
+                Some(seq_no) => Some(BigEndian::read_u64(&seq_no)),
+                None => None,
+            };
             messages.push(GossipsubMessage {
-                source: PeerId::from_bytes(publish.from.unwrap_or_default())
-                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid Peer Id"))?,
+                source,
                 data: publish.data.unwrap_or_default(),
-                sequence_number: BigEndian::read_u64(&seq_no),
+                sequence_number,
                 topics: publish
                     .topic_ids
                     .into_iter()
                     .map(TopicHash::from_raw)
                     .collect(),
+                signature: publish.signature,
+                key: publish.key,
             });
         }
 
@@ -291,6 +315,7 @@ impl Decoder for GossipsubCodec {
                 .into_iter()
                 .map(|prune| GossipsubControlAction::Prune {
                     topic_hash: TopicHash::from_raw(prune.topic_id.unwrap_or_default()),
+                    backoff: prune.backoff,
                 })
                 .collect();
 
@@ -338,19 +363,139 @@ impl Into<String> for MessageId {
 /// A message received by the gossipsub system.
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct GossipsubMessage {
-    /// Id of the peer that published this message.
-    pub source: PeerId,
+    /// Id of the peer that published this message, if the message is not anonymous.
+    pub source: Option<PeerId>,
 
     /// Content of the message. Its meaning is out of scope of this library.
     pub data: Vec<u8>,
 
-    /// A random sequence number.
-    pub sequence_number: u64,
+    /// A random sequence number, if the message is not anonymous.
+    pub sequence_number: Option<u64>,
 
     /// List of topics this message belongs to.
     ///
     /// Each message can belong to multiple topics at once.
     pub topics: Vec<TopicHash>,
+
+    /// The signature of the message by its source, if the message is signed.
+    pub signature: Option<Vec<u8>>,
+
+    /// The protobuf encoded public key of the source, if it can't be extracted from its peer id.
+    pub key: Option<Vec<u8>>,
+}
+
+impl GossipsubMessage {
+    /// Signs the message with the given keypair, which must be the one of the source.
+    pub(crate) fn sign(&mut self, keypair: &Keypair) -> Result<(), SigningError> {
+        self.signature = None;
+        self.key = None;
+        self.signature = Some(keypair.sign(&self.signed_bytes())?);
+
+        // the key only needs to be sent along if it is not inlined in the source peer id
+        let public_key = keypair.public();
+        if self.source.as_ref().and_then(public_key_from_peer_id).as_ref() != Some(&public_key) {
+            self.key = Some(public_key.into_protobuf_encoding());
+        }
+        Ok(())
+    }
+
+    /// Checks the source, sequence number and signature of the message against the given
+    /// validation mode. Returns the reason of the failure if the message is invalid.
+    pub(crate) fn validate(&self, mode: ValidationMode) -> Result<(), &'static str> {
+        match mode {
+            ValidationMode::Strict => {
+                if self.source.is_none() {
+                    return Err("missing source");
+                }
+                if self.sequence_number.is_none() {
+                    return Err("missing sequence number");
+                }
+                if self.signature.is_none() {
+                    return Err("missing signature");
+                }
+                self.verify_signature()
+            }
+            ValidationMode::Permissive => {
+                if self.signature.is_some() {
+                    self.verify_signature()
+                } else {
+                    Ok(())
+                }
+            }
+            ValidationMode::None => Ok(()),
+        }
+    }
+
+    /// Verifies that the signature of the message has been produced by its source.
+    fn verify_signature(&self) -> Result<(), &'static str> {
+        let source = self.source.as_ref().ok_or("signed message without source")?;
+        let signature = self.signature.as_ref().ok_or("missing signature")?;
+
+        let public_key = match &self.key {
+            Some(key) => {
+                let key = PublicKey::from_protobuf_encoding(key).map_err(|_| "invalid key")?;
+                if &key.clone().into_peer_id() != source {
+                    return Err("key does not match the source");
+                }
+                key
+            }
+            None => public_key_from_peer_id(source).ok_or("missing key")?,
+        };
+
+        if public_key.verify(&self.signed_bytes(), signature) {
+            Ok(())
+        } else {
+            Err("invalid signature")
+        }
+    }
+
+    /// Returns the bytes covered by the signature, i.e. the prefixed protobuf encoding of the
+    /// message without its signature and key.
+    fn signed_bytes(&self) -> Vec<u8> {
+        let message = rpc_proto::Message {
+            signature: None,
+            key: None,
+            ..self.clone().into_protobuf()
+        };
+        let mut buf = Vec::with_capacity(SIGNING_PREFIX.len() + message.encoded_len());
+        buf.extend_from_slice(SIGNING_PREFIX);
+        message
+            .encode(&mut buf)
+            .expect("Buffer has sufficient capacity");
+        buf
+    }
+
+    /// Returns the size of an RPC frame carrying only this message, which must not exceed the
+    /// maximum transmission size. The frames of RPCs carrying several messages add up.
+    pub(crate) fn encoded_len(&self) -> usize {
+        rpc_proto::Rpc {
+            subscriptions: Vec::new(),
+            publish: vec![self.clone().into_protobuf()],
+            control: None,
+        }
+        .encoded_len()
+    }
+
+    fn into_protobuf(self) -> rpc_proto::Message {
+        rpc_proto::Message {
+            from: self.source.map(PeerId::into_bytes),
+            data: Some(self.data),
+            seqno: self.sequence_number.map(|seq_no| seq_no.to_be_bytes().to_vec()),
+            topic_ids: self.topics.into_iter().map(TopicHash::into_string).collect(),
+            signature: self.signature,
+            key: self.key,
+        }
+    }
+}
+
+/// Extracts the public key inlined in a peer id, if any.
+fn public_key_from_peer_id(peer_id: &PeerId) -> Option<PublicKey> {
+    let multihash = Multihash::from_bytes(peer_id.as_bytes().to_vec()).ok()?;
+    if multihash.algorithm() == Code::Identity {
+        PublicKey::from_protobuf_encoding(multihash.digest()).ok()
+    } else {
+        None
+    }
 }
 
 impl fmt::Debug for GossipsubMessage {
@@ -360,6 +505,7 @@ impl fmt::Debug for GossipsubMessage {
             .field("source", &self.source)
             .field("sequence_number", &self.sequence_number)
             .field("topics", &self.topics)
+            .field("signature", &self.signature.as_ref().map(hex_fmt::HexFmt))
             .finish()
     }
 }
@@ -406,5 +552,7 @@ pub enum GossipsubControlAction {
     Prune {
         /// The mesh topic the peer should be removed from.
         topic_hash: TopicHash,
+        /// The time in seconds during which the pruned peer must not GRAFT again (gossipsub v1.1).
+        backoff: Option<u64>,
     },
 }
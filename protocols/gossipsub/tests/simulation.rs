@@ -0,0 +1,89 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_gossipsub::{Gossipsub, GossipsubConfigBuilder, GossipsubEvent, Topic};
+use libp2p_simulation::{LinkConfig, Simulation};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::time::Duration;
+
+/// Builds a simulation of `num_nodes` gossipsub nodes connected as a random
+/// tree over lossy links with the given seed.
+fn build_simulation(num_nodes: usize, seed: u64) -> Simulation<Gossipsub> {
+    let mut link = LinkConfig::default();
+    link.set_latency(Duration::from_millis(50))
+        .set_jitter(Duration::from_millis(25))
+        .set_packet_loss(0.05);
+    let mut simulation = Simulation::new(seed, link);
+
+    let config = GossipsubConfigBuilder::new()
+        .heartbeat_initial_delay(Duration::from_millis(50))
+        .heartbeat_interval(Duration::from_millis(50))
+        .build();
+    for _ in 0 .. num_nodes {
+        simulation.add_node(|keypair| Gossipsub::new(keypair.public().into_peer_id(), config.clone()));
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    for node in 1 .. num_nodes {
+        simulation.connect(node, rng.gen_range(0, node));
+    }
+    simulation
+}
+
+#[test]
+fn multi_hop_propagation_over_lossy_links() {
+    let _ = env_logger::try_init();
+
+    for seed in 0 .. 3 {
+        let num_nodes = 30;
+        let mut simulation = build_simulation(num_nodes, seed);
+
+        // Subscribe each node to the same topic.
+        let topic = Topic::new("test-net".into());
+        for node in 0 .. num_nodes {
+            simulation.swarm(node).subscribe(topic.clone());
+        }
+
+        // Wait for all nodes to learn about the subscriptions of their
+        // neighbours, i.e. twice per edge of the tree.
+        let mut subscribed = 0;
+        assert!(simulation.run_until(Duration::from_secs(60), |_, event| {
+            if let GossipsubEvent::Subscribed { .. } = event {
+                subscribed += 1;
+            }
+            subscribed == (num_nodes - 1) * 2
+        }), "seed {}: subscriptions did not propagate", seed);
+
+        // Publish a single message and wait for all other nodes to receive it.
+        let published_at = simulation.network().now();
+        simulation.swarm(0).publish(&topic, vec![1, 2, 3]).unwrap();
+        let mut received = vec![false; num_nodes];
+        assert!(simulation.run_until(Duration::from_secs(60), |node, event| {
+            if let GossipsubEvent::Message(_, _, message) = event {
+                assert_eq!(message.data, vec![1, 2, 3]);
+                received[node] = true;
+            }
+            received.iter().skip(1).all(|r| *r)
+        }), "seed {}: message did not reach all nodes", seed);
+
+        // The message has at least crossed one link.
+        assert!(simulation.network().now() - published_at >= Duration::from_millis(50));
+    }
+}
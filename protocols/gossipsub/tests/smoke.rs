@@ -198,7 +198,7 @@ fn multi_hop_propagation() {
         });
 
         // Publish a single message.
-        graph.nodes[0].1.publish(&topic, vec![1, 2, 3]);
+        graph.nodes[0].1.publish(&topic, vec![1, 2, 3]).unwrap();
 
         // Wait for all nodes to receive the published message.
         let mut received_msgs = 0;
@@ -18,12 +18,22 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::protocol::{RemoteInfo, IdentifyProtocolConfig, ReplySubstream};
+use crate::protocol::{
+    IdentifyProtocolConfig,
+    IdentifyPush,
+    InboundPush,
+    OutboundPush,
+    RemoteInfo,
+    ReplySubstream
+};
 use futures::prelude::*;
+use libp2p_core::either::{EitherError, EitherOutput};
 use libp2p_core::upgrade::{
+    EitherUpgrade,
     InboundUpgrade,
     OutboundUpgrade,
-    ReadOneError
+    ReadOneError,
+    SelectUpgrade
 };
 use libp2p_swarm::{
     NegotiatedSubstream,
@@ -39,8 +49,6 @@ use wasm_timer::Delay;
 
 /// Delay between the moment we connect and the first time we identify.
 const DELAY_TO_FIRST_ID: Duration = Duration::from_millis(500);
-/// After an identification succeeded, wait this long before the next time.
-const DELAY_TO_NEXT_ID: Duration = Duration::from_secs(5 * 60);
 /// After we failed to identify the remote, try again after the given delay.
 const TRY_AGAIN_ON_ERR: Duration = Duration::from_secs(60 * 60);
 
@@ -49,6 +57,9 @@ const TRY_AGAIN_ON_ERR: Duration = Duration::from_secs(60 * 60);
 /// Outbound requests are sent periodically. The handler performs expects
 /// at least one identification request to be answered by the remote before
 /// permitting the underlying connection to be closed.
+///
+/// Identifying information received via `/ipfs/id/push/1.0.0` is reported
+/// like the answer to an identification request.
 pub struct IdentifyHandler {
     /// Configuration for the protocol.
     config: IdentifyProtocolConfig,
@@ -59,6 +70,12 @@ pub struct IdentifyHandler {
     /// Future that fires when we need to identify the node again.
     next_id: Delay,
 
+    /// The delay between an identification and the next one.
+    interval: Duration,
+
+    /// Identifying information to push to the remote, if any.
+    pending_push: Option<IdentifyPush<OutboundPush>>,
+
     /// Whether the handler should keep the connection alive.
     keep_alive: KeepAlive,
 }
@@ -72,60 +89,102 @@ pub enum IdentifyHandlerEvent {
     Identify(ReplySubstream<NegotiatedSubstream>),
     /// Failed to identify the remote.
     IdentificationError(ProtocolsHandlerUpgrErr<ReadOneError>),
+    /// We pushed identifying information to the remote.
+    IdentificationPushed,
+    /// Failed to push identifying information to the remote.
+    IdentificationPushError(ProtocolsHandlerUpgrErr<ReadOneError>),
+}
+
+/// The purpose of an outbound substream of an `IdentifyHandler`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutboundKind {
+    /// Requesting identifying information from the remote.
+    Identify,
+    /// Pushing identifying information to the remote.
+    Push,
 }
 
 impl IdentifyHandler {
-    /// Creates a new `IdentifyHandler`.
-    pub fn new() -> Self {
+    /// Creates a new `IdentifyHandler` identifying the remote in the given interval.
+    pub fn new(interval: Duration) -> Self {
         IdentifyHandler {
             config: IdentifyProtocolConfig,
             events: SmallVec::new(),
             next_id: Delay::new(DELAY_TO_FIRST_ID),
+            interval,
+            pending_push: None,
             keep_alive: KeepAlive::Yes,
         }
     }
 }
 
 impl ProtocolsHandler for IdentifyHandler {
-    type InEvent = ();
+    type InEvent = IdentifyPush<OutboundPush>;
     type OutEvent = IdentifyHandlerEvent;
     type Error = ReadOneError;
-    type InboundProtocol = IdentifyProtocolConfig;
-    type OutboundProtocol = IdentifyProtocolConfig;
-    type OutboundOpenInfo = ();
+    type InboundProtocol = SelectUpgrade<IdentifyProtocolConfig, IdentifyPush<InboundPush>>;
+    type OutboundProtocol = EitherUpgrade<IdentifyProtocolConfig, IdentifyPush<OutboundPush>>;
+    type OutboundOpenInfo = OutboundKind;
 
     fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol> {
-        SubstreamProtocol::new(self.config.clone())
+        SubstreamProtocol::new(SelectUpgrade::new(self.config.clone(), IdentifyPush::inbound()))
     }
 
     fn inject_fully_negotiated_inbound(
         &mut self,
-        protocol: <Self::InboundProtocol as InboundUpgrade<NegotiatedSubstream>>::Output
+        output: <Self::InboundProtocol as InboundUpgrade<NegotiatedSubstream>>::Output
     ) {
-        self.events.push(IdentifyHandlerEvent::Identify(protocol))
+        match output {
+            EitherOutput::First(sender) =>
+                self.events.push(IdentifyHandlerEvent::Identify(sender)),
+            EitherOutput::Second(remote_info) =>
+                self.events.push(IdentifyHandlerEvent::Identified(remote_info)),
+        }
     }
 
     fn inject_fully_negotiated_outbound(
         &mut self,
-        protocol: <Self::OutboundProtocol as OutboundUpgrade<NegotiatedSubstream>>::Output,
+        output: <Self::OutboundProtocol as OutboundUpgrade<NegotiatedSubstream>>::Output,
         _info: Self::OutboundOpenInfo,
     ) {
-        self.events.push(IdentifyHandlerEvent::Identified(protocol));
-        self.keep_alive = KeepAlive::No;
+        match output {
+            EitherOutput::First(remote_info) => {
+                self.events.push(IdentifyHandlerEvent::Identified(remote_info));
+                self.keep_alive = KeepAlive::No;
+            }
+            EitherOutput::Second(()) =>
+                self.events.push(IdentifyHandlerEvent::IdentificationPushed),
+        }
     }
 
-    fn inject_event(&mut self, _: Self::InEvent) {}
+    fn inject_event(&mut self, push: Self::InEvent) {
+        self.pending_push = Some(push);
+    }
 
     fn inject_dial_upgrade_error(
         &mut self,
-        _info: Self::OutboundOpenInfo,
+        info: Self::OutboundOpenInfo,
         err: ProtocolsHandlerUpgrErr<
             <Self::OutboundProtocol as OutboundUpgrade<NegotiatedSubstream>>::Error
         >
     ) {
-        self.events.push(IdentifyHandlerEvent::IdentificationError(err));
-        self.keep_alive = KeepAlive::No;
-        self.next_id.reset(TRY_AGAIN_ON_ERR);
+        let err = match err {
+            ProtocolsHandlerUpgrErr::Timeout => ProtocolsHandlerUpgrErr::Timeout,
+            ProtocolsHandlerUpgrErr::Timer => ProtocolsHandlerUpgrErr::Timer,
+            ProtocolsHandlerUpgrErr::Upgrade(e) =>
+                ProtocolsHandlerUpgrErr::Upgrade(e.map_err(|e| match e {
+                    EitherError::A(e) | EitherError::B(e) => e,
+                })),
+        };
+        match info {
+            OutboundKind::Identify => {
+                self.events.push(IdentifyHandlerEvent::IdentificationError(err));
+                self.keep_alive = KeepAlive::No;
+                self.next_id.reset(TRY_AGAIN_ON_ERR);
+            }
+            OutboundKind::Push =>
+                self.events.push(IdentifyHandlerEvent::IdentificationPushError(err)),
+        }
     }
 
     fn connection_keep_alive(&self) -> KeepAlive {
@@ -146,14 +205,21 @@ impl ProtocolsHandler for IdentifyHandler {
             ));
         }
 
+        if let Some(push) = self.pending_push.take() {
+            return Poll::Ready(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                protocol: SubstreamProtocol::new(EitherUpgrade::B(push)),
+                info: OutboundKind::Push,
+            });
+        }
+
         // Poll the future that fires when we need to identify the node again.
         match Future::poll(Pin::new(&mut self.next_id), cx) {
             Poll::Pending => Poll::Pending,
             Poll::Ready(Ok(())) => {
-                self.next_id.reset(DELAY_TO_NEXT_ID);
+                self.next_id.reset(self.interval);
                 let ev = ProtocolsHandlerEvent::OutboundSubstreamRequest {
-                    protocol: SubstreamProtocol::new(self.config.clone()),
-                    info: (),
+                    protocol: SubstreamProtocol::new(EitherUpgrade::A(self.config.clone())),
+                    info: OutboundKind::Identify,
                 };
                 Poll::Ready(ev)
             }
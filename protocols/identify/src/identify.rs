@@ -19,7 +19,7 @@
 // DEALINGS IN THE SOFTWARE.
 
 use crate::handler::{IdentifyHandler, IdentifyHandlerEvent};
-use crate::protocol::{IdentifyInfo, ReplySubstream};
+use crate::protocol::{IdentifyInfo, IdentifyPush, OutboundPush, ReplySubstream};
 use futures::prelude::*;
 use libp2p_core::{
     ConnectedPoint,
@@ -33,7 +33,9 @@ use libp2p_swarm::{
     NegotiatedSubstream,
     NetworkBehaviour,
     NetworkBehaviourAction,
+    NotifyHandler,
     PollParameters,
+    ProtocolRegistry,
     ProtocolsHandler,
     ProtocolsHandlerUpgrErr
 };
@@ -42,7 +44,8 @@ use std::{
     io,
     pin::Pin,
     task::Context,
-    task::Poll
+    task::Poll,
+    time::Duration
 };
 
 /// Network behaviour that automatically identifies nodes periodically, returns information
@@ -54,12 +57,89 @@ pub struct Identify {
     agent_version: String,
     /// The public key of the local node. To report on the wire.
     local_public_key: PublicKey,
+    /// The configuration of the behaviour.
+    config: IdentifyConfig,
+    /// The listen addresses and protocols of the local node when last
+    /// checked for changes to push to remotes.
+    last_pushed: Option<(Vec<Multiaddr>, Vec<String>)>,
     /// For each peer we're connected to, the observed address to send back to it.
     observed_addresses: HashMap<PeerId, HashMap<ConnectionId, Multiaddr>>,
     /// Pending replies to send.
     pending_replies: VecDeque<Reply>,
     /// Pending events to be emitted when polled.
-    events: VecDeque<NetworkBehaviourAction<(), IdentifyEvent>>,
+    events: VecDeque<NetworkBehaviourAction<IdentifyPush<OutboundPush>, IdentifyEvent>>,
+}
+
+/// Configuration for the [`Identify`] behaviour.
+#[derive(Debug, Clone)]
+pub struct IdentifyConfig {
+    /// The interval in which remotes are identified.
+    interval: Duration,
+    /// Whether to push changes of the local listen addresses and
+    /// protocols to connected remotes.
+    push_updates: bool,
+    /// The predicate for the listen addresses to report to remotes.
+    listen_addr_filter: fn(&Multiaddr) -> bool,
+    /// The registry in which the protocols reported by remotes are recorded.
+    protocol_registry: Option<ProtocolRegistry>,
+}
+
+impl IdentifyConfig {
+    /// Creates a new `IdentifyConfig` with the following default settings:
+    ///
+    ///   * [`IdentifyConfig::with_interval`] 5 minutes
+    ///   * [`IdentifyConfig::with_push_updates`] true
+    ///   * [`IdentifyConfig::with_listen_addr_filter`] reporting all addresses
+    ///   * [`IdentifyConfig::with_protocol_registry`] none
+    pub fn new() -> Self {
+        IdentifyConfig {
+            interval: Duration::from_secs(5 * 60),
+            push_updates: true,
+            listen_addr_filter: |_| true,
+            protocol_registry: None,
+        }
+    }
+
+    /// Sets the interval in which remotes are identified again after
+    /// a successful identification.
+    pub fn with_interval(mut self, d: Duration) -> Self {
+        self.interval = d;
+        self
+    }
+
+    /// Sets whether changes of the listen addresses or the supported
+    /// protocols of the local node are pushed to connected remotes
+    /// using the `/ipfs/id/push/1.0.0` protocol.
+    pub fn with_push_updates(mut self, b: bool) -> Self {
+        self.push_updates = b;
+        self
+    }
+
+    /// Sets the predicate for the listen addresses to report to remotes,
+    /// which otherwise include all external and listen addresses of the
+    /// local node.
+    ///
+    /// For example, a public server may omit loopback and private addresses,
+    /// while a node only reachable via a relay may report nothing but its
+    /// relayed addresses.
+    pub fn with_listen_addr_filter(mut self, f: fn(&Multiaddr) -> bool) -> Self {
+        self.listen_addr_filter = f;
+        self
+    }
+
+    /// Sets the [`ProtocolRegistry`] in which the protocols reported by
+    /// remotes are recorded, which should be the registry of the `Swarm`
+    /// configured with `SwarmBuilder::protocol_registry`.
+    pub fn with_protocol_registry(mut self, registry: ProtocolRegistry) -> Self {
+        self.protocol_registry = Some(registry);
+        self
+    }
+}
+
+impl Default for IdentifyConfig {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// A pending reply to an inbound identification request.
@@ -80,15 +160,56 @@ enum Reply {
 impl Identify {
     /// Creates a new `Identify` network behaviour.
     pub fn new(protocol_version: String, agent_version: String, local_public_key: PublicKey) -> Self {
+        Identify::with_config(protocol_version, agent_version, local_public_key, IdentifyConfig::new())
+    }
+
+    /// Creates a new `Identify` network behaviour with the given configuration.
+    pub fn with_config(
+        protocol_version: String,
+        agent_version: String,
+        local_public_key: PublicKey,
+        config: IdentifyConfig
+    ) -> Self {
         Identify {
             protocol_version,
             agent_version,
             local_public_key,
+            config,
+            last_pushed: None,
             observed_addresses: HashMap::new(),
             pending_replies: VecDeque::new(),
             events: VecDeque::new(),
         }
     }
+
+    /// Builds the identifying information of the local node.
+    fn info(&self, listen_addrs: Vec<Multiaddr>, protocols: Vec<String>) -> IdentifyInfo {
+        IdentifyInfo {
+            public_key: self.local_public_key.clone(),
+            protocol_version: self.protocol_version.clone(),
+            agent_version: self.agent_version.clone(),
+            listen_addrs,
+            protocols,
+        }
+    }
+}
+
+/// Returns the addresses and the protocols of the local node to report to remotes.
+fn local_addrs_and_protocols(params: &mut impl PollParameters, filter: fn(&Multiaddr) -> bool)
+    -> (Vec<Multiaddr>, Vec<String>)
+{
+    // The protocol names can be bytes, but the identify protocol except UTF-8 strings.
+    // There's not much we can do to solve this conflict except strip non-UTF-8 characters.
+    let protocols = params
+        .supported_protocols()
+        .map(|p| String::from_utf8_lossy(&p).to_string())
+        .collect();
+
+    let mut listen_addrs: Vec<_> = params.external_addresses().collect();
+    listen_addrs.extend(params.listened_addresses());
+    listen_addrs.retain(filter);
+
+    (listen_addrs, protocols)
 }
 
 impl NetworkBehaviour for Identify {
@@ -96,7 +217,7 @@ impl NetworkBehaviour for Identify {
     type OutEvent = IdentifyEvent;
 
     fn new_handler(&mut self) -> Self::ProtocolsHandler {
-        IdentifyHandler::new()
+        IdentifyHandler::new(self.config.interval)
     }
 
     fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
@@ -133,17 +254,24 @@ impl NetworkBehaviour for Identify {
     ) {
         match event {
             IdentifyHandlerEvent::Identified(remote) => {
+                if let Some(registry) = &self.config.protocol_registry {
+                    registry.set_reported_protocols(&peer_id, remote.info.protocols.iter().cloned());
+                }
                 self.events.push_back(
                     NetworkBehaviourAction::GenerateEvent(
                         IdentifyEvent::Received {
-                            peer_id,
+                            peer_id: peer_id.clone(),
                             info: remote.info,
                             observed_addr: remote.observed_addr.clone(),
                         }));
-                self.events.push_back(
-                    NetworkBehaviourAction::ReportObservedAddr {
-                        address: remote.observed_addr,
-                    });
+                // Pushed information need not contain an observed address.
+                if remote.observed_addr.iter().next().is_some() {
+                    self.events.push_back(
+                        NetworkBehaviourAction::ReportObservedAddr {
+                            address: remote.observed_addr,
+                            peer_id,
+                        });
+                }
             }
             IdentifyHandlerEvent::Identify(sender) => {
                 let observed = self.observed_addresses.get(&peer_id)
@@ -157,7 +285,13 @@ impl NetworkBehaviour for Identify {
                         observed: observed.clone()
                     });
             }
-            IdentifyHandlerEvent::IdentificationError(error) => {
+            IdentifyHandlerEvent::IdentificationPushed => {
+                self.events.push_back(
+                    NetworkBehaviourAction::GenerateEvent(
+                        IdentifyEvent::Pushed { peer_id }));
+            }
+            IdentifyHandlerEvent::IdentificationError(error) |
+            IdentifyHandlerEvent::IdentificationPushError(error) => {
                 self.events.push_back(
                     NetworkBehaviourAction::GenerateEvent(
                         IdentifyEvent::Error { peer_id, error }));
@@ -175,20 +309,33 @@ impl NetworkBehaviour for Identify {
             Self::OutEvent,
         >,
     > {
+        // Push changes of the local addresses and protocols to all connections.
+        if self.config.push_updates {
+            let current = local_addrs_and_protocols(params, self.config.listen_addr_filter);
+            if self.last_pushed.as_ref() != Some(&current) {
+                if self.last_pushed.is_some() {
+                    let (listen_addrs, protocols) = current.clone();
+                    let info = self.info(listen_addrs, protocols);
+                    for (peer_id, connections) in &self.observed_addresses {
+                        for (connection, observed) in connections {
+                            self.events.push_back(NetworkBehaviourAction::NotifyHandler {
+                                peer_id: peer_id.clone(),
+                                handler: NotifyHandler::One(*connection),
+                                event: IdentifyPush::outbound(info.clone(), observed.clone()),
+                            });
+                        }
+                    }
+                }
+                self.last_pushed = Some(current);
+            }
+        }
+
         if let Some(event) = self.events.pop_front() {
             return Poll::Ready(event);
         }
 
         if let Some(r) = self.pending_replies.pop_front() {
-            // The protocol names can be bytes, but the identify protocol except UTF-8 strings.
-            // There's not much we can do to solve this conflict except strip non-UTF-8 characters.
-            let protocols: Vec<_> = params
-                .supported_protocols()
-                .map(|p| String::from_utf8_lossy(&p).to_string())
-                .collect();
-
-            let mut listen_addrs: Vec<_> = params.external_addresses().collect();
-            listen_addrs.extend(params.listened_addresses());
+            let (listen_addrs, protocols) = local_addrs_and_protocols(params, self.config.listen_addr_filter);
 
             let mut sending = 0;
             let to_send = self.pending_replies.len() + 1;
@@ -196,13 +343,7 @@ impl NetworkBehaviour for Identify {
             loop {
                 match reply {
                     Some(Reply::Queued { peer, io, observed }) => {
-                        let info = IdentifyInfo {
-                            public_key: self.local_public_key.clone(),
-                            protocol_version: self.protocol_version.clone(),
-                            agent_version: self.agent_version.clone(),
-                            listen_addrs: listen_addrs.clone(),
-                            protocols: protocols.clone(),
-                        };
+                        let info = self.info(listen_addrs.clone(), protocols.clone());
                         let io = Box::pin(io.send(info, &observed));
                         reply = Some(Reply::Sending { peer, io });
                     }
@@ -257,6 +398,12 @@ pub enum IdentifyEvent {
         /// The peer that the information has been sent to.
         peer_id: PeerId,
     },
+    /// Identifying information of the local node has been pushed to a peer,
+    /// following a change of the listen addresses or the protocols.
+    Pushed {
+        /// The peer that the information has been pushed to.
+        peer_id: PeerId,
+    },
     /// Error while attempting to identify the remote.
     Error {
         /// The peer with whom the error originated.
@@ -268,10 +415,12 @@ pub enum IdentifyEvent {
 
 #[cfg(test)]
 mod tests {
-    use crate::{Identify, IdentifyEvent};
+    use crate::{Identify, IdentifyConfig, IdentifyEvent};
     use futures::{prelude::*, pin_mut};
     use libp2p_core::{
         identity,
+        multiaddr::Protocol,
+        Multiaddr,
         PeerId,
         muxing::StreamMuxer,
         Transport,
@@ -279,9 +428,9 @@ mod tests {
     };
     use libp2p_tcp::TcpConfig;
     use libp2p_secio::SecioConfig;
-    use libp2p_swarm::{Swarm, SwarmEvent};
+    use libp2p_swarm::{ProtocolRegistry, Swarm, SwarmBuilder, SwarmEvent};
     use libp2p_mplex::MplexConfig;
-    use std::{fmt, io};
+    use std::{fmt, io, time::Duration};
 
     fn transport() -> (identity::PublicKey, impl Transport<
         Output = (PeerId, impl StreamMuxer<Substream = impl Send, OutboundSubstream = impl Send, Error = impl Into<io::Error>>),
@@ -363,4 +512,206 @@ mod tests {
             }
         })
     }
+
+    #[test]
+    fn identify_push() {
+        let (mut swarm1, pubkey1) = {
+            let (pubkey, transport) = transport();
+            let protocol = Identify::new("a".to_string(), "b".to_string(), pubkey.clone());
+            let swarm = Swarm::new(transport, protocol, pubkey.clone().into_peer_id());
+            (swarm, pubkey)
+        };
+
+        let (mut swarm2, pubkey2) = {
+            let (pubkey, transport) = transport();
+            let protocol = Identify::new("c".to_string(), "d".to_string(), pubkey.clone());
+            let swarm = Swarm::new(transport, protocol, pubkey.clone().into_peer_id());
+            (swarm, pubkey)
+        };
+
+        Swarm::listen_on(&mut swarm1, "/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+
+        let listen_addr = async_std::task::block_on(async {
+            loop {
+                let swarm1_fut = swarm1.next_event();
+                pin_mut!(swarm1_fut);
+                match swarm1_fut.await {
+                    SwarmEvent::NewListenAddr(addr) => return addr,
+                    _ => {}
+                }
+            }
+        });
+        Swarm::dial_addr(&mut swarm2, listen_addr).unwrap();
+
+        // Once connected, the first swarm listens on another address,
+        // which it pushes to the second swarm.
+        async_std::task::block_on(async move {
+            let mut pushed = false;
+            let mut received = false;
+            while !(pushed && received) {
+                let event = {
+                    let swarm1_fut = swarm1.next_event();
+                    pin_mut!(swarm1_fut);
+                    let swarm2_fut = swarm2.next();
+                    pin_mut!(swarm2_fut);
+                    match future::select(swarm1_fut, swarm2_fut).await {
+                        future::Either::Left((e, _)) => future::Either::Left(e),
+                        future::Either::Right((e, _)) => future::Either::Right(e),
+                    }
+                };
+
+                match event {
+                    future::Either::Left(SwarmEvent::ConnectionEstablished { .. }) => {
+                        Swarm::listen_on(&mut swarm1, "/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                            .unwrap();
+                    }
+                    future::Either::Left(SwarmEvent::Behaviour(IdentifyEvent::Pushed { peer_id })) => {
+                        assert_eq!(peer_id, pubkey2.clone().into_peer_id());
+                        pushed = true;
+                    }
+                    future::Either::Right(IdentifyEvent::Received { info, .. }) => {
+                        assert_eq!(info.public_key, pubkey1);
+                        if info.listen_addrs.len() == 2 {
+                            received = true;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn listen_addr_filter() {
+        fn not_loopback(addr: &Multiaddr) -> bool {
+            !addr.iter().any(|p| matches!(p, Protocol::Ip4(ip) if ip.is_loopback()))
+        }
+
+        let (mut swarm1, pubkey1) = {
+            let (pubkey, transport) = transport();
+            let config = IdentifyConfig::new().with_listen_addr_filter(not_loopback);
+            let protocol = Identify::with_config("a".to_string(), "b".to_string(), pubkey.clone(), config);
+            let swarm = Swarm::new(transport, protocol, pubkey.clone().into_peer_id());
+            (swarm, pubkey)
+        };
+
+        let (mut swarm2, pubkey2) = {
+            let (pubkey, transport) = transport();
+            let config = IdentifyConfig::new().with_listen_addr_filter(not_loopback);
+            let protocol = Identify::with_config("c".to_string(), "d".to_string(), pubkey.clone(), config);
+            let swarm = Swarm::new(transport, protocol, pubkey.clone().into_peer_id());
+            (swarm, pubkey)
+        };
+
+        Swarm::listen_on(&mut swarm1, "/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+        Swarm::listen_on(&mut swarm2, "/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+
+        let listen_addr = async_std::task::block_on(async {
+            let mut listen_addr = None;
+            let mut listening = false;
+            while listen_addr.is_none() || !listening {
+                let swarm1_fut = swarm1.next_event();
+                pin_mut!(swarm1_fut);
+                let swarm2_fut = swarm2.next_event();
+                pin_mut!(swarm2_fut);
+                match future::select(swarm1_fut, swarm2_fut).await.factor_second().0 {
+                    future::Either::Left(SwarmEvent::NewListenAddr(addr)) => listen_addr = Some(addr),
+                    future::Either::Right(SwarmEvent::NewListenAddr(_)) => listening = true,
+                    _ => {}
+                }
+            }
+            listen_addr.unwrap()
+        });
+        Swarm::dial_addr(&mut swarm2, listen_addr).unwrap();
+
+        // The only listen address of either swarm is filtered. As in
+        // `periodic_id_works`, either swarm may identify the other first.
+        let identified = async_std::future::timeout(Duration::from_secs(10), async move {
+            loop {
+                let swarm1_fut = swarm1.next();
+                pin_mut!(swarm1_fut);
+                let swarm2_fut = swarm2.next();
+                pin_mut!(swarm2_fut);
+
+                match future::select(swarm1_fut, swarm2_fut).await.factor_second().0 {
+                    future::Either::Left(IdentifyEvent::Received { info, .. }) => {
+                        assert_eq!(info.public_key, pubkey2);
+                        assert!(info.listen_addrs.is_empty());
+                        return;
+                    }
+                    future::Either::Right(IdentifyEvent::Received { info, .. }) => {
+                        assert_eq!(info.public_key, pubkey1);
+                        assert!(info.listen_addrs.is_empty());
+                        return;
+                    }
+                    _ => {}
+                }
+            }
+        });
+        async_std::task::block_on(identified).expect("the swarms identify each other");
+    }
+
+    #[test]
+    fn protocol_registry() {
+        let registry1 = ProtocolRegistry::new();
+        let mut swarm1 = {
+            let (pubkey, transport) = transport();
+            let config = IdentifyConfig::new().with_protocol_registry(registry1.clone());
+            let protocol = Identify::with_config("a".to_string(), "b".to_string(), pubkey.clone(), config);
+            SwarmBuilder::new(transport, protocol, pubkey.clone().into_peer_id())
+                .protocol_registry(registry1.clone())
+                .build()
+        };
+
+        let registry2 = ProtocolRegistry::new();
+        let mut swarm2 = {
+            let (pubkey, transport) = transport();
+            let config = IdentifyConfig::new().with_protocol_registry(registry2.clone());
+            let protocol = Identify::with_config("c".to_string(), "d".to_string(), pubkey.clone(), config);
+            SwarmBuilder::new(transport, protocol, pubkey.clone().into_peer_id())
+                .protocol_registry(registry2.clone())
+                .build()
+        };
+        let peer1 = Swarm::local_peer_id(&swarm1).clone();
+        let peer2 = Swarm::local_peer_id(&swarm2).clone();
+
+        Swarm::listen_on(&mut swarm1, "/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+
+        let listen_addr = async_std::task::block_on(async {
+            loop {
+                let swarm1_fut = swarm1.next_event();
+                pin_mut!(swarm1_fut);
+                if let SwarmEvent::NewListenAddr(addr) = swarm1_fut.await {
+                    return addr
+                }
+            }
+        });
+        Swarm::dial_addr(&mut swarm2, listen_addr).unwrap();
+
+        // The protocols reported by the remote are recorded, next to the
+        // identify protocol negotiated with it. As in `periodic_id_works`,
+        // either swarm may identify the other first.
+        let identified = async_std::future::timeout(Duration::from_secs(10), async {
+            loop {
+                let swarm1_fut = swarm1.next();
+                pin_mut!(swarm1_fut);
+                let swarm2_fut = swarm2.next();
+                pin_mut!(swarm2_fut);
+
+                let (registry, remote, info) = match future::select(swarm1_fut, swarm2_fut).await.factor_second().0 {
+                    future::Either::Left(IdentifyEvent::Received { info, .. }) => (&registry1, &peer2, info),
+                    future::Either::Right(IdentifyEvent::Received { info, .. }) => (&registry2, &peer1, info),
+                    _ => continue,
+                };
+                let mut reported = info.protocols;
+                reported.sort();
+                assert_eq!(registry.supported_protocols(remote), reported);
+                assert!(registry.supports(remote, "/ipfs/id/1.0.0"));
+                return;
+            }
+        });
+        async_std::task::block_on(identified).expect("the swarms identify each other");
+        assert_eq!(Swarm::supported_protocols(&swarm1, &peer2), registry1.supported_protocols(&peer2));
+        assert_eq!(Swarm::supported_protocols(&swarm2, &peer1), registry2.supported_protocols(&peer1));
+    }
 }
@@ -26,6 +26,10 @@
 //! At least one identification request is sent on a newly established
 //! connection, beyond which the behaviour does not keep connections alive.
 //!
+//! Changes of the listen addresses or the supported protocols of the local
+//! node are pushed to connected peers with the `/ipfs/id/push/1.0.0`
+//! protocol, unless disabled via [`IdentifyConfig::with_push_updates`].
+//!
 //! # Usage
 //!
 //! The [`Identify`] struct implements a `NetworkBehaviour` that negotiates
@@ -34,10 +38,11 @@
 //!
 //! [Identify]: https://github.com/libp2p/specs/tree/master/identify
 //! [`Identify`]: self::Identify
+//! [`IdentifyConfig::with_push_updates`]: self::IdentifyConfig::with_push_updates
 //! [`IdentifyEvent`]: self::IdentifyEvent
 //! [`IdentifyInfo`]: self::IdentifyEvent
 
-pub use self::identify::{Identify, IdentifyEvent};
+pub use self::identify::{Identify, IdentifyConfig, IdentifyEvent};
 pub use self::protocol::IdentifyInfo;
 
 mod handler;
@@ -34,6 +34,32 @@ use std::{fmt, io, iter, pin::Pin};
 #[derive(Debug, Clone)]
 pub struct IdentifyProtocolConfig;
 
+/// Upgrade for pushing identifying information to a remote, using the
+/// `/ipfs/id/push/1.0.0` protocol.
+#[derive(Debug, Clone)]
+pub struct IdentifyPush<T>(T);
+
+/// Marker for receiving identifying information pushed by a remote.
+#[derive(Debug, Clone)]
+pub struct InboundPush(());
+
+/// The identifying information to push to a remote, together with the
+/// address observed for the remote.
+#[derive(Debug, Clone)]
+pub struct OutboundPush(IdentifyInfo, Multiaddr);
+
+impl IdentifyPush<InboundPush> {
+    pub fn inbound() -> Self {
+        IdentifyPush(InboundPush(()))
+    }
+}
+
+impl IdentifyPush<OutboundPush> {
+    pub fn outbound(info: IdentifyInfo, observed_addr: Multiaddr) -> Self {
+        IdentifyPush(OutboundPush(info, observed_addr))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RemoteInfo {
     /// Information about the remote.
@@ -63,36 +89,70 @@ where
     ///
     /// Consumes the substream, returning a `ReplyFuture` that resolves
     /// when the reply has been sent on the underlying connection.
-    pub fn send(mut self, info: IdentifyInfo, observed_addr: &Multiaddr)
+    pub fn send(self, info: IdentifyInfo, observed_addr: &Multiaddr)
         -> impl Future<Output = Result<(), io::Error>>
     {
         debug!("Sending identify info to client");
-        trace!("Sending: {:?}", info);
-
-        let listen_addrs = info.listen_addrs
-            .into_iter()
-            .map(|addr| addr.to_vec())
-            .collect();
-
-        let pubkey_bytes = info.public_key.into_protobuf_encoding();
-
-        let message = structs_proto::Identify {
-            agent_version: Some(info.agent_version),
-            protocol_version: Some(info.protocol_version),
-            public_key: Some(pubkey_bytes),
-            listen_addrs: listen_addrs,
-            observed_addr: Some(observed_addr.to_vec()),
-            protocols: info.protocols
-        };
-
-        async move {
-            let mut bytes = Vec::with_capacity(message.encoded_len());
-            message.encode(&mut bytes).expect("Vec<u8> provides capacity as needed");
-            upgrade::write_one(&mut self.inner, &bytes).await
-        }
+        send(self.inner, info, observed_addr)
+    }
+}
+
+/// Sends the given identifying information on the substream.
+fn send<T>(mut io: T, info: IdentifyInfo, observed_addr: &Multiaddr)
+    -> impl Future<Output = Result<(), io::Error>>
+where
+    T: AsyncWrite + Unpin
+{
+    trace!("Sending: {:?}", info);
+
+    let listen_addrs = info.listen_addrs
+        .into_iter()
+        .map(|addr| addr.to_vec())
+        .collect();
+
+    let pubkey_bytes = info.public_key.into_protobuf_encoding();
+
+    let message = structs_proto::Identify {
+        agent_version: Some(info.agent_version),
+        protocol_version: Some(info.protocol_version),
+        public_key: Some(pubkey_bytes),
+        listen_addrs,
+        observed_addr: Some(observed_addr.to_vec()),
+        protocols: info.protocols
+    };
+
+    async move {
+        let mut bytes = Vec::with_capacity(message.encoded_len());
+        message.encode(&mut bytes).expect("Vec<u8> provides capacity as needed");
+        upgrade::write_one(&mut io, &bytes).await
     }
 }
 
+/// Receives identifying information on the substream.
+async fn recv<T>(mut socket: T) -> Result<RemoteInfo, upgrade::ReadOneError>
+where
+    T: AsyncRead + AsyncWrite + Unpin
+{
+    socket.close().await?;
+    let msg = upgrade::read_one(&mut socket, 4096).await?;
+    let (info, observed_addr) = match parse_proto_msg(msg) {
+        Ok(v) => v,
+        Err(err) => {
+            debug!("Failed to parse protobuf message; error = {:?}", err);
+            return Err(err.into())
+        }
+    };
+
+    trace!("Remote observes us as {:?}", observed_addr);
+    trace!("Information received: {:?}", info);
+
+    Ok(RemoteInfo {
+        info,
+        observed_addr,
+        _priv: ()
+    })
+}
+
 /// Information of a peer sent in `Identify` protocol responses.
 #[derive(Debug, Clone)]
 pub struct IdentifyInfo {
@@ -141,27 +201,45 @@ where
     type Error = upgrade::ReadOneError;
     type Future = Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + Send>>;
 
-    fn upgrade_outbound(self, mut socket: C, _: Self::Info) -> Self::Future {
-        Box::pin(async move {
-            socket.close().await?;
-            let msg = upgrade::read_one(&mut socket, 4096).await?;
-            let (info, observed_addr) = match parse_proto_msg(msg) {
-                Ok(v) => v,
-                Err(err) => {
-                    debug!("Failed to parse protobuf message; error = {:?}", err);
-                    return Err(err.into())
-                }
-            };
+    fn upgrade_outbound(self, socket: C, _: Self::Info) -> Self::Future {
+        Box::pin(recv(socket))
+    }
+}
+
+impl<T> UpgradeInfo for IdentifyPush<T> {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
 
-            trace!("Remote observes us as {:?}", observed_addr);
-            trace!("Information received: {:?}", info);
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(b"/ipfs/id/push/1.0.0")
+    }
+}
+
+impl<C> InboundUpgrade<C> for IdentifyPush<InboundPush>
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Output = RemoteInfo;
+    type Error = upgrade::ReadOneError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + Send>>;
+
+    fn upgrade_inbound(self, socket: C, _: Self::Info) -> Self::Future {
+        Box::pin(recv(socket))
+    }
+}
+
+impl<C> OutboundUpgrade<C> for IdentifyPush<OutboundPush>
+where
+    C: AsyncWrite + Unpin + Send + 'static,
+{
+    type Output = ();
+    type Error = upgrade::ReadOneError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + Send>>;
 
-            Ok(RemoteInfo {
-                info,
-                observed_addr: observed_addr.clone(),
-                _priv: ()
-            })
-        })
+    fn upgrade_outbound(self, socket: C, _: Self::Info) -> Self::Future {
+        debug!("Pushing identify info to remote");
+        let OutboundPush(info, observed_addr) = self.0;
+        Box::pin(send(socket, info, &observed_addr).map_err(Into::into))
     }
 }
 
@@ -31,6 +31,7 @@ use crate::protocol::{KademliaProtocolConfig, KadConnectionType, KadPeer};
 use crate::query::{Query, QueryId, QueryPool, QueryConfig, QueryPoolState};
 use crate::record::{self, store::{self, RecordStore}, Record, ProviderRecord};
 use fnv::{FnvHashMap, FnvHashSet};
+use futures::prelude::*;
 use libp2p_core::{ConnectedPoint, Multiaddr, PeerId, connection::ConnectionId};
 use libp2p_swarm::{
     DialPeerCondition,
@@ -48,7 +49,7 @@ use std::fmt;
 use std::num::NonZeroUsize;
 use std::task::{Context, Poll};
 use std::vec;
-use wasm_timer::Instant;
+use wasm_timer::{Delay, Instant};
 
 pub use crate::query::QueryStats;
 
@@ -86,6 +87,12 @@ pub struct Kademlia<TStore> {
     /// The TTL of provider records.
     provider_record_ttl: Option<Duration>,
 
+    /// The interval in which to bootstrap automatically, if any.
+    bootstrap_interval: Option<Duration>,
+
+    /// The timer for the next automatic bootstrap.
+    bootstrap_timer: Option<Delay>,
+
     /// How long to keep connections alive when they're idle.
     connection_idle_timeout: Duration,
 
@@ -94,6 +101,28 @@ pub struct Kademlia<TStore> {
 
     /// The record storage.
     store: TStore,
+
+    /// The current mode of operation.
+    mode: Mode,
+
+    /// Whether the mode is determined automatically from the confirmed
+    /// external addresses of the local node.
+    auto_mode: bool,
+}
+
+/// The modes of operation of the `Kademlia` behaviour.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// The local node only issues requests to other nodes and denies
+    /// inbound Kademlia requests. The Kademlia protocol is not advertised
+    /// to other peers.
+    ///
+    /// This is the appropriate mode for nodes that are not publicly
+    /// reachable, e.g. because they are behind a NAT.
+    Client,
+    /// The local node issues requests to other nodes and answers
+    /// inbound Kademlia requests, i.e. it serves as a DHT node.
+    Server,
 }
 
 /// The configurable strategies for the insertion of peers
@@ -135,6 +164,8 @@ pub struct KademliaConfig {
     provider_publication_interval: Option<Duration>,
     connection_idle_timeout: Duration,
     kbucket_inserts: KademliaBucketInserts,
+    mode: Option<Mode>,
+    bootstrap_interval: Option<Duration>,
 }
 
 impl Default for KademliaConfig {
@@ -150,6 +181,8 @@ impl Default for KademliaConfig {
             provider_record_ttl: Some(Duration::from_secs(24 * 60 * 60)),
             connection_idle_timeout: Duration::from_secs(10),
             kbucket_inserts: KademliaBucketInserts::OnConnected,
+            mode: Some(Mode::Server),
+            bootstrap_interval: Some(Duration::from_secs(5 * 60)),
         }
     }
 }
@@ -291,6 +324,20 @@ impl KademliaConfig {
         self
     }
 
+    /// Sets the interval in which the local node bootstraps automatically,
+    /// as if [`Kademlia::bootstrap`] was called, to keep the routing table
+    /// populated.
+    ///
+    /// An automatic bootstrap is skipped if no peers are known or if a
+    /// bootstrap is still in progress. Its progress is reported like that
+    /// of a manual bootstrap. The default is 5 minutes.
+    ///
+    /// `None` means that the local node never bootstraps automatically.
+    pub fn set_bootstrap_interval(&mut self, interval: Option<Duration>) -> &mut Self {
+        self.bootstrap_interval = interval;
+        self
+    }
+
     /// Sets the amount of time to keep connections alive when they're idle.
     pub fn set_connection_idle_timeout(&mut self, duration: Duration) -> &mut Self {
         self.connection_idle_timeout = duration;
@@ -311,6 +358,19 @@ impl KademliaConfig {
         self.kbucket_inserts = inserts;
         self
     }
+
+    /// Sets the [`Mode`] of operation.
+    ///
+    /// If `None`, the mode is determined automatically: the node operates
+    /// in [`Mode::Server`] as long as the `Swarm` has a confirmed external
+    /// address, e.g. one confirmed by AutoNAT, and in [`Mode::Client`]
+    /// otherwise. See also [`Kademlia::set_mode`].
+    ///
+    /// The default is `Some(Mode::Server)`.
+    pub fn set_mode(&mut self, mode: Option<Mode>) -> &mut Self {
+        self.mode = mode;
+        self
+    }
 }
 
 impl<TStore> Kademlia<TStore>
@@ -357,7 +417,11 @@ where
             put_record_job,
             record_ttl: config.record_ttl,
             provider_record_ttl: config.provider_record_ttl,
+            bootstrap_interval: config.bootstrap_interval,
+            bootstrap_timer: config.bootstrap_interval.map(Delay::new),
             connection_idle_timeout: config.connection_idle_timeout,
+            mode: config.mode.unwrap_or(Mode::Client),
+            auto_mode: config.mode.is_none(),
         }
     }
 
@@ -401,6 +465,46 @@ where
             })
     }
 
+    /// Returns the current [`Mode`] of operation.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Sets the [`Mode`] of operation.
+    ///
+    /// If `None`, the mode is henceforth determined automatically from the
+    /// confirmed external addresses of the local node, as described for
+    /// [`KademliaConfig::set_mode`].
+    ///
+    /// Changing the mode takes effect on existing connections as well and a
+    /// [`KademliaEvent::ModeChanged`] event is emitted.
+    pub fn set_mode(&mut self, mode: Option<Mode>) {
+        self.auto_mode = mode.is_none();
+        if let Some(mode) = mode {
+            self.switch_mode(mode);
+        }
+    }
+
+    /// Switches to the given mode, informing the handlers of all
+    /// connections and the `Swarm` about the change.
+    fn switch_mode(&mut self, mode: Mode) {
+        if self.mode == mode {
+            return
+        }
+        debug!("Switching to {:?} mode.", mode);
+        self.mode = mode;
+        for peer_id in &self.connected_peers {
+            self.queued_events.push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id: peer_id.clone(),
+                handler: NotifyHandler::All,
+                event: KademliaHandlerIn::AllowListening(mode == Mode::Server),
+            });
+        }
+        self.queued_events.push_back(NetworkBehaviourAction::UpdateSupportedProtocols);
+        self.queued_events.push_back(NetworkBehaviourAction::GenerateEvent(
+            KademliaEvent::ModeChanged { new_mode: mode }));
+    }
+
     /// Adds a known listen address of a peer participating in the DHT to the
     /// routing table.
     ///
@@ -670,6 +774,9 @@ where
     ///
     /// > **Note**: Bootstrapping requires at least one node of the DHT to be known.
     /// > See [`Kademlia::add_address`].
+    ///
+    /// Bootstrapping is also performed automatically in regular intervals,
+    /// see [`KademliaConfig::set_bootstrap_interval`].
     pub fn bootstrap(&mut self) -> Result<QueryId, NoKnownPeers> {
         let local_key = self.kbuckets.local_key().clone();
         let info = QueryInfo::Bootstrap {
@@ -912,6 +1019,7 @@ where
     {
         let query_id = q.id();
         log::trace!("Query {:?} finished.", query_id);
+        let timeout = q.timeout();
         let result = q.into_result();
         match result.inner.info {
             QueryInfo::Bootstrap { peer, remaining } => {
@@ -960,7 +1068,8 @@ where
                     };
                     let peers = self.kbuckets.closest_keys(&target);
                     let inner = QueryInner::new(info);
-                    self.queries.continue_iter_closest(query_id, target.clone(), peers, inner);
+                    self.queries.continue_iter_closest(query_id, target.clone(), peers, inner)
+                        .set_timeout(timeout);
                 }
 
                 Some(KademliaEvent::QueryResult {
@@ -1010,7 +1119,7 @@ where
                         get_closest_peers_stats: result.stats
                     }
                 });
-                self.queries.continue_fixed(query_id, result.peers, inner);
+                self.queries.continue_fixed(query_id, result.peers, inner).set_timeout(timeout);
                 None
             }
 
@@ -1089,7 +1198,7 @@ where
                     }
                 };
                 let inner = QueryInner::new(info);
-                self.queries.continue_fixed(query_id, result.peers, inner);
+                self.queries.continue_fixed(query_id, result.peers, inner).set_timeout(timeout);
                 None
             }
 
@@ -1136,6 +1245,7 @@ where
     fn query_timeout(&mut self, query: Query<QueryInner>) -> Option<KademliaEvent> {
         let query_id = query.id();
         log::trace!("Query {:?} timed out.", query_id);
+        let timeout = query.timeout();
         let result = query.into_result();
         match result.inner.info {
             QueryInfo::Bootstrap { peer, mut remaining } => {
@@ -1150,7 +1260,8 @@ where
                         };
                         let peers = self.kbuckets.closest_keys(&target);
                         let inner = QueryInner::new(info);
-                        self.queries.continue_iter_closest(query_id, target.clone(), peers, inner);
+                        self.queries.continue_iter_closest(query_id, target.clone(), peers, inner)
+                        .set_timeout(timeout);
                     }
                 }
 
@@ -1392,7 +1503,7 @@ where
     fn new_handler(&mut self) -> Self::ProtocolsHandler {
         KademliaHandler::new(KademliaHandlerConfig {
             protocol_config: self.protocol_config.clone(),
-            allow_listening: true,
+            allow_listening: self.mode == Mode::Server,
             idle_timeout: self.connection_idle_timeout,
         })
     }
@@ -1701,6 +1812,30 @@ where
     > {
         let now = Instant::now();
 
+        // Determine the mode from the confirmed external addresses, if automatic.
+        if self.auto_mode {
+            let mode = if parameters.external_addresses().len() > 0 {
+                Mode::Server
+            } else {
+                Mode::Client
+            };
+            self.switch_mode(mode);
+        }
+
+        // Bootstrap automatically, unless a bootstrap is still in progress.
+        if let Some(timer) = &mut self.bootstrap_timer {
+            if timer.poll_unpin(cx).is_ready() {
+                if let Some(interval) = self.bootstrap_interval {
+                    timer.reset(interval);
+                }
+                let in_progress = self.queries.iter().any(|q|
+                    matches!(q.inner.info, QueryInfo::Bootstrap { .. }));
+                if !in_progress && self.bootstrap().is_err() {
+                    debug!("Skipping automatic bootstrap: no known peers.");
+                }
+            }
+        }
+
         // Calculate the available capacity for queries triggered by background jobs.
         let mut jobs_query_capacity = JOBS_MAX_QUERIES.saturating_sub(self.queries.size());
 
@@ -1905,7 +2040,14 @@ pub enum KademliaEvent {
     PendingRoutablePeer {
         peer: PeerId,
         address: Multiaddr,
-    }
+    },
+
+    /// The [`Mode`] of operation of the local node has changed, either
+    /// explicitly via [`Kademlia::set_mode`] or automatically.
+    ModeChanged {
+        /// The new mode of operation.
+        new_mode: Mode,
+    },
 }
 
 /// The results of Kademlia queries.
@@ -2388,6 +2530,15 @@ impl<'a> QueryMut<'a> {
     pub fn finish(&mut self) {
         self.query.finish()
     }
+
+    /// Sets the timeout of the query, overriding the timeout configured
+    /// via [`KademliaConfig::set_query_timeout`].
+    ///
+    /// For a multi-phase query such as `put_record`, the timeout applies
+    /// to each phase, measured from the start of the phase.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.query.set_timeout(Some(timeout))
+    }
 }
 
 /// An immutable reference to a running query.
@@ -1099,3 +1099,112 @@ fn manual_bucket_inserts() {
         Poll::Pending
     }));
 }
+
+#[test]
+fn client_mode_denies_inbound_requests() {
+    let mut cfg = KademliaConfig::default();
+    cfg.set_mode(Some(Mode::Client));
+    let (client_addr, mut client) = build_node_with_config(cfg);
+    let (_, mut server) = build_node();
+    assert_eq!(client.mode(), Mode::Client);
+
+    // The server only knows the client, which denies the request.
+    let client_id = Swarm::local_peer_id(&client).clone();
+    server.add_address(&client_id, client_addr);
+    let search_target = PeerId::random();
+    server.get_closest_peers(search_target.clone());
+
+    block_on(poll_fn(move |ctx| {
+        loop {
+            match server.poll_next_unpin(ctx) {
+                Poll::Ready(Some(KademliaEvent::QueryResult {
+                    result: QueryResult::GetClosestPeers(Ok(ok)), ..
+                })) => {
+                    assert_eq!(&ok.key[..], search_target.as_bytes());
+                    assert_eq!(ok.peers.len(), 0);
+                    return Poll::Ready(());
+                }
+                // Ignore any other event.
+                Poll::Ready(Some(_)) => (),
+                e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                Poll::Pending => break,
+            }
+        }
+        while let Poll::Ready(Some(_)) = client.poll_next_unpin(ctx) {}
+        Poll::Pending
+    }))
+}
+
+#[test]
+fn automatic_mode_follows_external_addresses() {
+    let mut cfg = KademliaConfig::default();
+    cfg.set_mode(None);
+    let (_, mut swarm) = build_node_with_config(cfg);
+    assert_eq!(swarm.mode(), Mode::Client);
+
+    let external: Multiaddr = "/ip4/1.2.3.4/tcp/4001".parse().unwrap();
+    Swarm::add_external_address(&mut swarm, external.clone());
+
+    let mut removed = false;
+    block_on(poll_fn(move |ctx| {
+        loop {
+            match swarm.poll_next_unpin(ctx) {
+                Poll::Ready(Some(KademliaEvent::ModeChanged { new_mode: Mode::Server })) => {
+                    assert!(!removed);
+                    assert_eq!(swarm.mode(), Mode::Server);
+                    // Once the external address is gone, the node is a client again.
+                    assert!(Swarm::remove_external_address(&mut swarm, &external));
+                    removed = true;
+                }
+                Poll::Ready(Some(KademliaEvent::ModeChanged { new_mode: Mode::Client })) => {
+                    assert!(removed);
+                    assert_eq!(swarm.mode(), Mode::Client);
+                    return Poll::Ready(())
+                }
+                Poll::Ready(Some(_)) => (),
+                e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }))
+}
+
+#[test]
+fn periodic_bootstrap() {
+    let mut cfg = KademliaConfig::default();
+    cfg.set_bootstrap_interval(Some(Duration::from_millis(100)));
+    let mut swarms = build_connected_nodes_with_config(2, 1, cfg)
+        .into_iter()
+        .map(|(_a, s)| s)
+        .collect::<Vec<_>>();
+    let local_id = Swarm::local_peer_id(&swarms[0]).clone();
+
+    // Without any call to `Kademlia::bootstrap`, the first node
+    // completes bootstrapping repeatedly.
+    let mut completed = 0;
+    block_on(poll_fn(move |ctx| {
+        for swarm in swarms.iter_mut() {
+            loop {
+                match swarm.poll_next_unpin(ctx) {
+                    Poll::Ready(Some(KademliaEvent::QueryResult {
+                        result: QueryResult::Bootstrap(Ok(ok)), ..
+                    })) => {
+                        if Swarm::local_peer_id(swarm) != &local_id {
+                            continue
+                        }
+                        if ok.num_remaining == 0 {
+                            completed += 1;
+                            if completed == 2 {
+                                return Poll::Ready(())
+                            }
+                        }
+                    }
+                    Poll::Ready(Some(_)) => (),
+                    e @ Poll::Ready(_) => panic!("Unexpected return value: {:?}", e),
+                    Poll::Pending => break,
+                }
+            }
+        }
+        Poll::Pending
+    }))
+}
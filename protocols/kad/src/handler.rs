@@ -279,6 +279,13 @@ pub enum KademliaHandlerIn<TUserData> {
     /// for the query on the remote.
     Reset(KademliaRequestId),
 
+    /// Allows or denies inbound requests from now on, overriding
+    /// [`KademliaHandlerConfig::allow_listening`].
+    ///
+    /// Requests on inbound substreams that are still being negotiated
+    /// when inbound requests are denied are ignored.
+    AllowListening(bool),
+
     /// Request for the list of nodes whose IDs are the closest to `key`. The number of nodes
     /// returned is not specified, but should be around 20.
     FindNodeReq {
@@ -439,7 +446,12 @@ where
             EitherOutput::Second(p) => void::unreachable(p),
         };
 
-        debug_assert!(self.config.allow_listening);
+        // Inbound requests may have been denied while the substream was
+        // being negotiated.
+        if !self.config.allow_listening {
+            return
+        }
+
         let connec_unique_id = self.next_connec_unique_id;
         self.next_connec_unique_id.0 += 1;
         self.substreams
@@ -461,6 +473,9 @@ where
                     let _ = self.substreams.remove(pos).try_close(&mut cx);
                 }
             }
+            KademliaHandlerIn::AllowListening(allow) => {
+                self.config.allow_listening = allow;
+            }
             KademliaHandlerIn::FindNodeReq { key, user_data } => {
                 let msg = KadRequestMsg::FindNode { key };
                 self.substreams.push(SubstreamState::OutPendingOpen(msg, Some(user_data.clone())));
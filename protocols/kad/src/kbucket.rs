@@ -452,6 +452,13 @@ where
         self.num_entries() == 0
     }
 
+    /// Returns the index of the bucket, i.e. the base-2 logarithm of the
+    /// distances of its keys from the local key. The keys of the bucket
+    /// thus share a prefix of `255 - index` bits with the local key.
+    pub fn index(&self) -> usize {
+        self.index.get()
+    }
+
     /// Returns the number of entries in the bucket.
     pub fn num_entries(&self) -> usize {
         self.bucket.num_entries()
@@ -99,7 +99,7 @@ impl<TInner> QueryPool<TInner> {
     /// Continues an earlier query with a fixed set of peers, reusing
     /// the given query ID, which must be from a query that finished
     /// earlier.
-    pub fn continue_fixed<I>(&mut self, id: QueryId, peers: I, inner: TInner)
+    pub fn continue_fixed<I>(&mut self, id: QueryId, peers: I, inner: TInner) -> &mut Query<TInner>
     where
         I: IntoIterator<Item = PeerId>
     {
@@ -108,6 +108,7 @@ impl<TInner> QueryPool<TInner> {
         let peer_iter = QueryPeerIter::Fixed(FixedPeersIter::new(peers, parallelism));
         let query = Query::new(id, peer_iter, inner);
         self.queries.insert(id, query);
+        self.queries.get_mut(&id).expect("s.a.")
     }
 
     /// Adds a query to the pool that iterates towards the closest peers to the target.
@@ -123,6 +124,7 @@ impl<TInner> QueryPool<TInner> {
 
     /// Adds a query to the pool that iterates towards the closest peers to the target.
     pub fn continue_iter_closest<T, I>(&mut self, id: QueryId, target: T, peers: I, inner: TInner)
+        -> &mut Query<TInner>
     where
         T: Into<KeyBytes> + Clone,
         I: IntoIterator<Item = Key<PeerId>>
@@ -143,6 +145,7 @@ impl<TInner> QueryPool<TInner> {
 
         let query = Query::new(id, peer_iter, inner);
         self.queries.insert(id, query);
+        self.queries.get_mut(&id).expect("s.a.")
     }
 
     fn next_query_id(&mut self) -> QueryId {
@@ -181,7 +184,7 @@ impl<TInner> QueryPool<TInner> {
                 }
                 PeersIterState::Waiting(None) | PeersIterState::WaitingAtCapacity => {
                     let elapsed = now - query.stats.start.unwrap_or(now);
-                    if elapsed >= self.config.timeout {
+                    if elapsed >= query.timeout.unwrap_or(self.config.timeout) {
                         timeout = Some(query_id);
                         break
                     }
@@ -261,6 +264,8 @@ pub struct Query<TInner> {
     peer_iter: QueryPeerIter,
     /// Execution statistics of the query.
     stats: QueryStats,
+    /// The timeout of the query, overriding the timeout of the `QueryPool`.
+    timeout: Option<Duration>,
     /// The opaque inner query state.
     pub inner: TInner,
 }
@@ -275,7 +280,7 @@ enum QueryPeerIter {
 impl<TInner> Query<TInner> {
     /// Creates a new query without starting it.
     fn new(id: QueryId, peer_iter: QueryPeerIter, inner: TInner) -> Self {
-        Query { id, inner, peer_iter, stats: QueryStats::empty() }
+        Query { id, inner, peer_iter, stats: QueryStats::empty(), timeout: None }
     }
 
     /// Gets the unique ID of the query.
@@ -288,6 +293,16 @@ impl<TInner> Query<TInner> {
         &self.stats
     }
 
+    /// Gets the timeout of the query, if it overrides the timeout of the `QueryPool`.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Sets the timeout of the query, overriding the timeout of the `QueryPool`.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
     /// Informs the query that the attempt to contact `peer` failed.
     pub fn on_failure(&mut self, peer: &PeerId) {
         let updated = match &mut self.peer_iter {
@@ -496,3 +511,29 @@ impl QueryStats {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn query_timeout_overrides_pool_timeout() {
+        let mut pool = QueryPool::new(QueryConfig::default());
+        let id = pool.add_fixed(vec![PeerId::random()], ());
+        pool.get_mut(&id).unwrap().set_timeout(Some(Duration::from_secs(1)));
+
+        let now = Instant::now();
+        match pool.poll(now) {
+            QueryPoolState::Waiting(Some((query, _))) => assert_eq!(query.id(), id),
+            _ => panic!("Expected query to yield peer."),
+        }
+        match pool.poll(now + Duration::from_millis(500)) {
+            QueryPoolState::Waiting(None) => {}
+            _ => panic!("Expected query to be waiting for the peer."),
+        }
+        match pool.poll(now + Duration::from_secs(1)) {
+            QueryPoolState::Timeout(query) => assert_eq!(query.id(), id),
+            _ => panic!("Expected query to time out."),
+        }
+    }
+}
@@ -0,0 +1,546 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+use super::*;
+
+use crate::kbucket;
+use libp2p_core::PeerId;
+use prost::Message;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::{hash_set, HashSet};
+use std::fs;
+use std::iter;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+mod proto {
+    include!(concat!(env!("OUT_DIR"), "/disk_store.pb.rs"));
+}
+
+/// A `RecordStore` persisting records in a directory, so that they
+/// survive restarts and need not fit into memory.
+///
+/// Every (value-)record is stored in a file of the `records`
+/// subdirectory and the provider records of a key in a file of the
+/// `providers` subdirectory, each named after the SHA-256 hash of the
+/// key. Only the provider records of the local node are also kept in
+/// memory. Expiration times are persisted as wall-clock times.
+pub struct DiskStore {
+    /// The identity of the peer owning the store.
+    local_key: kbucket::Key<PeerId>,
+    /// The configuration of the store.
+    config: DiskStoreConfig,
+    /// The directory of the stored (regular) records.
+    records_dir: PathBuf,
+    /// The directory of the stored provider records.
+    providers_dir: PathBuf,
+    /// The directory for files being written, before they are
+    /// atomically moved into place.
+    tmp_dir: PathBuf,
+    /// The number of stored records.
+    num_records: usize,
+    /// The number of keys with stored provider records.
+    num_provider_keys: usize,
+    /// The set of all provider records for the node identified by `local_key`.
+    ///
+    /// Must be kept in sync with the stored provider records.
+    provided: HashSet<ProviderRecord>,
+}
+
+/// Configuration for a `DiskStore`.
+pub struct DiskStoreConfig {
+    /// The maximum number of records.
+    pub max_records: usize,
+    /// The maximum size of record values, in bytes.
+    pub max_value_bytes: usize,
+    /// The maximum number of providers stored for a key.
+    ///
+    /// This should match up with the chosen replication factor.
+    pub max_providers_per_key: usize,
+    /// The maximum number of keys with provider records.
+    pub max_provided_keys: usize,
+}
+
+impl Default for DiskStoreConfig {
+    fn default() -> Self {
+        Self {
+            max_records: 1024 * 1024,
+            max_value_bytes: 65 * 1024,
+            max_provided_keys: 1024 * 1024,
+            max_providers_per_key: K_VALUE.get(),
+        }
+    }
+}
+
+impl DiskStore {
+    /// Opens the `DiskStore` in the given directory with a default
+    /// configuration, creating the directory if it does not exist.
+    pub fn open(local_id: PeerId, path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::open_with_config(local_id, path, Default::default())
+    }
+
+    /// Opens the `DiskStore` in the given directory with the given
+    /// configuration, creating the directory if it does not exist.
+    pub fn open_with_config(local_id: PeerId, path: impl AsRef<Path>, config: DiskStoreConfig)
+        -> io::Result<Self>
+    {
+        let records_dir = path.as_ref().join("records");
+        let providers_dir = path.as_ref().join("providers");
+        let tmp_dir = path.as_ref().join("tmp");
+        fs::create_dir_all(&records_dir)?;
+        fs::create_dir_all(&providers_dir)?;
+        // Files left over from an interrupted write are incomplete.
+        if tmp_dir.exists() {
+            fs::remove_dir_all(&tmp_dir)?;
+        }
+        fs::create_dir_all(&tmp_dir)?;
+
+        let num_records = fs::read_dir(&records_dir)?.count();
+
+        let mut num_provider_keys = 0;
+        let mut provided = HashSet::new();
+        for entry in fs::read_dir(&providers_dir)? {
+            num_provider_keys += 1;
+            for p in read_providers(&entry?.path())? {
+                if p.provider == local_id {
+                    provided.insert(p);
+                }
+            }
+        }
+
+        Ok(DiskStore {
+            local_key: kbucket::Key::new(local_id),
+            config,
+            records_dir,
+            providers_dir,
+            tmp_dir,
+            num_records,
+            num_provider_keys,
+            provided,
+        })
+    }
+
+    /// Writes a file, replacing any previous file atomically.
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let tmp = self.tmp_dir.join(path.file_name().expect("named after a key"));
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Stores the provider records for a key, removing the
+    /// file if there are none.
+    fn write_providers(&self, key: &Key, providers: &[ProviderRecord]) -> io::Result<()> {
+        let path = file_path(&self.providers_dir, key);
+        if providers.is_empty() {
+            return fs::remove_file(path)
+        }
+        let proto = proto::Providers {
+            key: key.to_vec(),
+            providers: providers.iter().map(|p| proto::ProviderRecord {
+                provider: p.provider.clone().into_bytes(),
+                expires: to_millis(p.expires),
+            }).collect(),
+        };
+        self.write(&path, &encode(&proto))
+    }
+}
+
+impl<'a> RecordStore<'a> for DiskStore {
+    type RecordsIter = DiskRecordsIter<'a>;
+
+    type ProvidedIter = iter::Map<
+        hash_set::Iter<'a, ProviderRecord>,
+        fn(&'a ProviderRecord) -> Cow<'a, ProviderRecord>
+    >;
+
+    fn get(&'a self, k: &Key) -> Option<Cow<'a, Record>> {
+        match read_record(&file_path(&self.records_dir, k)) {
+            Ok(Some(r)) if &r.key == k => Some(Cow::Owned(r)),
+            Ok(_) => None,
+            Err(e) => {
+                log::warn!("Failed to read record {:?}: {}", k, e);
+                None
+            }
+        }
+    }
+
+    fn put(&'a mut self, r: Record) -> Result<()> {
+        if r.value.len() >= self.config.max_value_bytes {
+            return Err(Error::ValueTooLarge)
+        }
+
+        let path = file_path(&self.records_dir, &r.key);
+        let exists = path.exists();
+        if !exists && self.num_records >= self.config.max_records {
+            return Err(Error::MaxRecords)
+        }
+
+        let proto = proto::Record {
+            key: r.key.to_vec(),
+            value: r.value,
+            publisher: r.publisher.map(PeerId::into_bytes).unwrap_or_default(),
+            expires: to_millis(r.expires),
+        };
+        self.write(&path, &encode(&proto)).map_err(Error::Io)?;
+        if !exists {
+            self.num_records += 1;
+        }
+
+        Ok(())
+    }
+
+    fn remove(&'a mut self, k: &Key) {
+        match fs::remove_file(file_path(&self.records_dir, k)) {
+            Ok(()) => self.num_records -= 1,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("Failed to remove record {:?}: {}", k, e),
+        }
+    }
+
+    fn records(&'a self) -> Self::RecordsIter {
+        let entries = fs::read_dir(&self.records_dir)
+            .map_err(|e| log::warn!("Failed to read records: {}", e))
+            .ok();
+        DiskRecordsIter { entries, _marker: PhantomData }
+    }
+
+    fn add_provider(&'a mut self, record: ProviderRecord) -> Result<()> {
+        let path = file_path(&self.providers_dir, &record.key);
+        let mut providers = read_providers(&path).map_err(Error::Io)?;
+        let is_new_key = providers.is_empty();
+        if is_new_key && self.config.max_provided_keys == self.num_provider_keys {
+            return Err(Error::MaxProvidedKeys)
+        }
+
+        let local = self.local_key.preimage() == &record.provider;
+        if let Some(i) = providers.iter().position(|p| p.provider == record.provider) {
+            // In-place update of an existing provider record.
+            if local {
+                self.provided.replace(record.clone());
+            }
+            providers[i] = record.clone();
+        } else {
+            // It is a new provider record for that key.
+            let key = kbucket::Key::new(record.key.clone());
+            let provider = kbucket::Key::new(record.provider.clone());
+            let i = providers.iter().position(|p| {
+                let pk = kbucket::Key::new(p.provider.clone());
+                provider.distance(&key) < pk.distance(&key)
+            }).unwrap_or(providers.len());
+            if i >= self.config.max_providers_per_key {
+                // The distance of the new provider to the key is larger
+                // than the distance of any existing provider and there
+                // is no room left.
+                return Ok(())
+            }
+            if local {
+                self.provided.insert(record.clone());
+            }
+            providers.insert(i, record.clone());
+            // Remove the excess provider, if any.
+            if providers.len() > self.config.max_providers_per_key {
+                if let Some(p) = providers.pop() {
+                    self.provided.remove(&p);
+                }
+            }
+        }
+
+        self.write_providers(&record.key, &providers).map_err(Error::Io)?;
+        if is_new_key {
+            self.num_provider_keys += 1;
+        }
+        Ok(())
+    }
+
+    fn providers(&'a self, key: &Key) -> Vec<ProviderRecord> {
+        read_providers(&file_path(&self.providers_dir, key)).unwrap_or_else(|e| {
+            log::warn!("Failed to read providers of {:?}: {}", key, e);
+            Vec::new()
+        })
+    }
+
+    fn provided(&'a self) -> Self::ProvidedIter {
+        self.provided.iter().map(Cow::Borrowed)
+    }
+
+    fn remove_provider(&'a mut self, key: &Key, provider: &PeerId) {
+        let path = file_path(&self.providers_dir, key);
+        let mut providers = match read_providers(&path) {
+            Ok(providers) => providers,
+            Err(e) => {
+                log::warn!("Failed to read providers of {:?}: {}", key, e);
+                return
+            }
+        };
+        if let Some(i) = providers.iter().position(|p| &p.provider == provider) {
+            let p = providers.remove(i);
+            self.provided.remove(&p);
+            match self.write_providers(key, &providers) {
+                Ok(()) if providers.is_empty() => self.num_provider_keys -= 1,
+                Ok(()) => {}
+                Err(e) => log::warn!("Failed to remove provider of {:?}: {}", key, e),
+            }
+        }
+    }
+}
+
+/// An iterator over the records of a [`DiskStore`], reading
+/// each record as the iterator advances.
+pub struct DiskRecordsIter<'a> {
+    entries: Option<fs::ReadDir>,
+    _marker: PhantomData<&'a DiskStore>,
+}
+
+impl<'a> Iterator for DiskRecordsIter<'a> {
+    type Item = Cow<'a, Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entries = self.entries.as_mut()?;
+        for entry in entries {
+            match entry.and_then(|e| read_record(&e.path())) {
+                Ok(Some(r)) => return Some(Cow::Owned(r)),
+                // Removed concurrently.
+                Ok(None) => {}
+                Err(e) => log::warn!("Failed to read record: {}", e),
+            }
+        }
+        None
+    }
+}
+
+/// The path of the file storing the records of a key in `dir`.
+fn file_path(dir: &Path, key: &Key) -> PathBuf {
+    let hash = Sha256::digest(key.as_ref());
+    let name = hash.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    dir.join(name)
+}
+
+/// Reads a record, if the file exists.
+fn read_record(path: &Path) -> io::Result<Option<Record>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let proto = proto::Record::decode(&bytes[..]).map_err(invalid_data)?;
+    let publisher = if proto.publisher.is_empty() {
+        None
+    } else {
+        Some(PeerId::from_bytes(proto.publisher).map_err(|_| invalid_data("Invalid publisher"))?)
+    };
+    Ok(Some(Record {
+        key: Key::from(proto.key),
+        value: proto.value,
+        publisher,
+        expires: from_millis(proto.expires),
+    }))
+}
+
+/// Reads the provider records of a key, if the file exists.
+fn read_providers(path: &Path) -> io::Result<Vec<ProviderRecord>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let proto = proto::Providers::decode(&bytes[..]).map_err(invalid_data)?;
+    let key = Key::from(proto.key);
+    proto.providers.into_iter().map(|p| {
+        Ok(ProviderRecord {
+            key: key.clone(),
+            provider: PeerId::from_bytes(p.provider).map_err(|_| invalid_data("Invalid provider"))?,
+            expires: from_millis(p.expires),
+        })
+    }).collect()
+}
+
+fn encode(message: &impl Message) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(message.encoded_len());
+    message.encode(&mut buf).expect("Vec<u8> provides capacity as needed");
+    buf
+}
+
+/// Converts an expiration time to milliseconds since the UNIX epoch,
+/// with 0 meaning that there is no expiration.
+fn to_millis(expires: Option<Instant>) -> u64 {
+    let expires = match expires {
+        Some(t) => t,
+        None => return 0,
+    };
+    let now = Instant::now();
+    let time = if expires > now {
+        SystemTime::now() + (expires - now)
+    } else {
+        SystemTime::now() - (now - expires)
+    };
+    let millis = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+    millis.max(1)
+}
+
+/// Converts milliseconds since the UNIX epoch to an expiration time.
+///
+/// See [`to_millis`].
+fn from_millis(millis: u64) -> Option<Instant> {
+    if millis == 0 {
+        return None
+    }
+    let time = UNIX_EPOCH + Duration::from_millis(millis);
+    // An expiration time in the past is equivalent to one right now.
+    let remaining = time.duration_since(SystemTime::now()).unwrap_or_default();
+    Some(Instant::now() + remaining)
+}
+
+/// Creates an `io::Error` with `io::ErrorKind::InvalidData`.
+fn invalid_data<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use multihash::{wrap, Code};
+    use quickcheck::*;
+    use rand::Rng;
+
+    fn random_multihash() -> Multihash {
+        wrap(Code::Sha2_256, &rand::thread_rng().gen::<[u8; 32]>())
+    }
+
+    fn distance(r: &ProviderRecord) -> kbucket::Distance {
+        kbucket::Key::new(r.key.clone())
+            .distance(&kbucket::Key::new(r.provider.clone()))
+    }
+
+    /// Expiration times are persisted with a precision of milliseconds.
+    fn same_expiry(a: Option<Instant>, b: Option<Instant>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => {
+                let d = if a > b { a - b } else { b - a };
+                d < Duration::from_secs(1)
+            }
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+
+    #[test]
+    fn put_get_remove_record() {
+        fn prop(r: Record) {
+            let dir = tempfile::tempdir().unwrap();
+            let mut store = DiskStore::open(PeerId::random(), dir.path()).unwrap();
+            assert!(store.put(r.clone()).is_ok());
+            let stored = store.get(&r.key).unwrap().into_owned();
+            assert_eq!((&stored.key, &stored.value, &stored.publisher), (&r.key, &r.value, &r.publisher));
+            assert!(same_expiry(stored.expires, r.expires));
+            assert_eq!(store.records().count(), 1);
+            store.remove(&r.key);
+            assert!(store.get(&r.key).is_none());
+            assert_eq!(store.records().count(), 0);
+        }
+        quickcheck(prop as fn(_))
+    }
+
+    #[test]
+    fn add_get_remove_provider() {
+        fn prop(r: ProviderRecord) {
+            let dir = tempfile::tempdir().unwrap();
+            let mut store = DiskStore::open(PeerId::random(), dir.path()).unwrap();
+            assert!(store.add_provider(r.clone()).is_ok());
+            assert!(store.providers(&r.key).iter().any(|p| p.provider == r.provider));
+            store.remove_provider(&r.key, &r.provider);
+            assert!(store.providers(&r.key).is_empty());
+        }
+        quickcheck(prop as fn(_))
+    }
+
+    #[test]
+    fn providers_ordered_by_distance_to_key() {
+        fn prop(providers: Vec<kbucket::Key<PeerId>>) -> bool {
+            let dir = tempfile::tempdir().unwrap();
+            let mut store = DiskStore::open(PeerId::random(), dir.path()).unwrap();
+            let key = Key::from(random_multihash());
+
+            let mut records = providers.into_iter().map(|p| {
+                ProviderRecord::new(key.clone(), p.into_preimage())
+            }).collect::<Vec<_>>();
+
+            for r in &records {
+                assert!(store.add_provider(r.clone()).is_ok());
+            }
+
+            records.sort_by_key(distance);
+            records.truncate(store.config.max_providers_per_key);
+
+            records == store.providers(&key)
+        }
+
+        quickcheck(prop as fn(_) -> _)
+    }
+
+    #[test]
+    fn max_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = DiskStoreConfig { max_records: 1, .. Default::default() };
+        let mut store = DiskStore::open_with_config(PeerId::random(), dir.path(), config).unwrap();
+        let r1 = Record::new(random_multihash(), vec![1]);
+        let r2 = Record::new(random_multihash(), vec![2]);
+        assert!(store.put(r1.clone()).is_ok());
+        // Replacing a record is always possible.
+        assert!(store.put(r1).is_ok());
+        match store.put(r2) {
+            Err(Error::MaxRecords) => {}
+            _ => panic!("Unexpected result"),
+        }
+    }
+
+    #[test]
+    fn records_survive_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let id = PeerId::random();
+        let mut record = Record::new(random_multihash(), vec![1, 2, 3]);
+        record.expires = Some(Instant::now() + Duration::from_secs(60));
+        let provided = ProviderRecord::new(random_multihash(), id.clone());
+        let provider = ProviderRecord::new(provided.key.clone(), PeerId::random());
+
+        {
+            let mut store = DiskStore::open(id.clone(), dir.path()).unwrap();
+            store.put(record.clone()).unwrap();
+            store.add_provider(provided.clone()).unwrap();
+            store.add_provider(provider.clone()).unwrap();
+        }
+
+        let mut store = DiskStore::open(id, dir.path()).unwrap();
+        let stored = store.get(&record.key).unwrap().into_owned();
+        assert_eq!(stored.value, record.value);
+        assert!(same_expiry(stored.expires, record.expires));
+        assert_eq!(store.provided().count(), 1);
+        assert_eq!(store.providers(&provided.key).len(), 2);
+
+        store.remove_provider(&provided.key, &provided.provider);
+        store.remove_provider(&provider.key, &provider.provider);
+        assert_eq!(store.provided().count(), 0);
+        assert_eq!(store.num_provider_keys, 0);
+    }
+}
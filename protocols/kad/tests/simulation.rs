@@ -0,0 +1,117 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_kad::{Kademlia, KademliaEvent, QueryResult, store::MemoryStore};
+use libp2p_simulation::{LinkConfig, Simulation};
+use std::{collections::HashMap, time::Duration};
+
+type KadSimulation = Simulation<Kademlia<MemoryStore>>;
+
+/// Builds a simulation of `num_nodes` Kademlia nodes over lossy links, all
+/// of which only know the first node.
+fn build_simulation(num_nodes: usize, seed: u64) -> KadSimulation {
+    let mut link = LinkConfig::default();
+    link.set_latency(Duration::from_millis(20))
+        .set_jitter(Duration::from_millis(10))
+        .set_packet_loss(0.02);
+    let mut simulation = Simulation::new(seed, link);
+
+    for _ in 0 .. num_nodes {
+        simulation.add_node(|keypair| {
+            let peer_id = keypair.public().into_peer_id();
+            Kademlia::new(peer_id.clone(), MemoryStore::new(peer_id))
+        });
+    }
+
+    let (bootnode, bootnode_addr) = (simulation.peer_id(0).clone(), simulation.addr(0).clone());
+    for node in 1 .. num_nodes {
+        simulation.swarm(node).add_address(&bootnode, bootnode_addr.clone());
+    }
+    simulation
+}
+
+/// Runs the simulation like [`Simulation::run_until`], adding the address of
+/// every unroutable peer to the node that reported it, as identify would.
+fn run_until<F>(simulation: &mut KadSimulation, duration: Duration, mut f: F) -> bool
+where
+    F: FnMut(usize, KademliaEvent) -> bool,
+{
+    let deadline = simulation.network().now() + duration;
+    loop {
+        let mut unroutable = None;
+        let remaining = deadline.checked_sub(simulation.network().now()).unwrap_or_default();
+        let finished = simulation.run_until(remaining, |node, event| match event {
+            KademliaEvent::UnroutablePeer { peer } => {
+                unroutable = Some((node, peer));
+                true
+            }
+            event => f(node, event),
+        });
+        match unroutable {
+            Some((node, peer)) => {
+                let index = (0 .. simulation.len()).find(|i| simulation.peer_id(*i) == &peer).unwrap();
+                let addr = simulation.addr(index).clone();
+                simulation.swarm(node).add_address(&peer, addr);
+            }
+            None => return finished,
+        }
+    }
+}
+
+#[test]
+fn lookups_converge_after_bootstrap() {
+    let _ = env_logger::try_init();
+
+    for seed in 0 .. 4 {
+        let num_nodes = 25;
+        let mut simulation = build_simulation(num_nodes, seed);
+
+        // Bootstrap all nodes but the first concurrently.
+        for node in 1 .. num_nodes {
+            simulation.swarm(node).bootstrap().unwrap();
+        }
+        let mut bootstrapped = 0;
+        assert!(run_until(&mut simulation, Duration::from_secs(300), |_, event| {
+            if let KademliaEvent::QueryResult { result: QueryResult::Bootstrap(result), .. } = event {
+                if result.expect("bootstrap succeeds").num_remaining == 0 {
+                    bootstrapped += 1;
+                }
+            }
+            bootstrapped == num_nodes - 1
+        }), "seed {}: bootstrap did not finish", seed);
+
+        // Every node looks up another node, which is always among the closest
+        // peers to itself.
+        let mut lookups = HashMap::new();
+        for node in 0 .. num_nodes {
+            let target = simulation.peer_id((node + 1) % num_nodes).clone();
+            let id = simulation.swarm(node).get_closest_peers(target.clone());
+            lookups.insert((node, id), target);
+        }
+        assert!(run_until(&mut simulation, Duration::from_secs(300), |node, event| {
+            if let KademliaEvent::QueryResult { id, result: QueryResult::GetClosestPeers(result), .. } = event {
+                let target = lookups.remove(&(node, id)).expect("lookup has been started");
+                let ok = result.expect("lookup succeeds");
+                assert!(ok.peers.contains(&target), "seed {}: node {} did not find {}", seed, node, target);
+            }
+            lookups.is_empty()
+        }), "seed {}: lookups did not finish", seed);
+    }
+}
@@ -39,7 +39,82 @@ use smallvec::SmallVec;
 use std::{cmp, fmt, io, iter, mem, pin::Pin, time::Duration, task::Context, task::Poll};
 use wasm_timer::{Delay, Instant};
 
-const MDNS_RESPONSE_TTL: std::time::Duration = Duration::from_secs(5 * 60);
+/// Configuration for the `Mdns` behaviour.
+#[derive(Debug, Clone)]
+pub struct MdnsConfig {
+    ttl: Duration,
+    query_interval: Duration,
+    ipv6: bool,
+    responder: bool,
+}
+
+impl MdnsConfig {
+    /// Creates a new configuration with the default values.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the time-to-live of the records sent in responses to queries.
+    ///
+    /// Other peers forget the addresses of the local node after that time, unless they are
+    /// refreshed by a new response.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets the interval at which queries are sent on the network.
+    pub fn with_query_interval(mut self, interval: Duration) -> Self {
+        self.query_interval = interval;
+        self
+    }
+
+    /// Sets whether mDNS runs over IPv6 multicast (`ff02::fb`) instead of IPv4 multicast
+    /// (`224.0.0.251`).
+    pub fn with_ipv6(mut self, ipv6: bool) -> Self {
+        self.ipv6 = ipv6;
+        self
+    }
+
+    /// Sets whether queries from other peers are answered.
+    ///
+    /// When disabled, the local node discovers other peers without announcing itself.
+    pub fn with_responder(mut self, responder: bool) -> Self {
+        self.responder = responder;
+        self
+    }
+
+    /// Returns the time-to-live of the records sent in responses to queries.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Returns the interval at which queries are sent on the network.
+    pub fn query_interval(&self) -> Duration {
+        self.query_interval
+    }
+
+    /// Returns whether mDNS runs over IPv6 multicast.
+    pub fn ipv6(&self) -> bool {
+        self.ipv6
+    }
+
+    /// Returns whether queries from other peers are answered.
+    pub fn responder(&self) -> bool {
+        self.responder
+    }
+}
+
+impl Default for MdnsConfig {
+    fn default() -> Self {
+        MdnsConfig {
+            ttl: Duration::from_secs(5 * 60),
+            query_interval: Duration::from_secs(20),
+            ipv6: false,
+            responder: true,
+        }
+    }
+}
 
 /// A `NetworkBehaviour` for mDNS. Automatically discovers peers on the local network and adds
 /// them to the topology.
@@ -57,6 +132,9 @@ pub struct Mdns {
     ///
     /// `None` if `discovered_nodes` is empty.
     closest_expiration: Option<Delay>,
+
+    /// The configuration of the behaviour.
+    config: MdnsConfig,
 }
 
 /// `MdnsService::next` takes ownership of `self`, returning a future that resolves with both itself
@@ -92,10 +170,16 @@ impl fmt::Debug for MaybeBusyMdnsService {
 impl Mdns {
     /// Builds a new `Mdns` behaviour.
     pub fn new() -> io::Result<Mdns> {
+        Self::with_config(MdnsConfig::default())
+    }
+
+    /// Builds a new `Mdns` behaviour with the given configuration.
+    pub fn with_config(config: MdnsConfig) -> io::Result<Mdns> {
         Ok(Mdns {
-            service: MaybeBusyMdnsService::Free(MdnsService::new()?),
+            service: MaybeBusyMdnsService::Free(MdnsService::with_config(&config)?),
             discovered_nodes: SmallVec::new(),
             closest_expiration: None,
+            config,
         })
     }
 
@@ -270,6 +354,7 @@ impl NetworkBehaviour for Mdns {
             };
 
             match packet {
+                MdnsPacket::Query(_) | MdnsPacket::ServiceDiscovery(_) if !self.config.responder => {},
                 MdnsPacket::Query(query) => {
                     // MaybeBusyMdnsService should always be Free.
                     if let MaybeBusyMdnsService::Free(ref mut service) = self.service {
@@ -277,7 +362,7 @@ impl NetworkBehaviour for Mdns {
                             query.query_id(),
                             params.local_peer_id().clone(),
                             params.listened_addresses().into_iter(),
-                            MDNS_RESPONSE_TTL,
+                            self.config.ttl,
                         );
                         service.enqueue_response(resp.unwrap());
                     } else { debug_assert!(false); }
@@ -327,7 +412,7 @@ impl NetworkBehaviour for Mdns {
                     if let MaybeBusyMdnsService::Free(ref mut service) = self.service {
                         let resp = build_service_discovery_response(
                             disc.query_id(),
-                            MDNS_RESPONSE_TTL,
+                            self.config.ttl,
                         );
                         service.enqueue_response(resp);
                     } else { debug_assert!(false); }
@@ -353,6 +438,7 @@ impl fmt::Debug for Mdns {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt.debug_struct("Mdns")
             .field("service", &self.service)
+            .field("config", &self.config)
             .finish()
     }
 }
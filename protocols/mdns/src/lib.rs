@@ -35,7 +35,7 @@ const SERVICE_NAME: &[u8] = b"_p2p._udp.local";
 /// Hardcoded name of the service used for DNS-SD.
 const META_QUERY_SERVICE: &[u8] = b"_services._dns-sd._udp.local";
 
-pub use self::behaviour::{Mdns, MdnsEvent};
+pub use self::behaviour::{Mdns, MdnsConfig, MdnsEvent};
 pub use self::service::MdnsService;
 
 mod behaviour;
@@ -18,13 +18,13 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::{SERVICE_NAME, META_QUERY_SERVICE, dns};
+use crate::{MdnsConfig, SERVICE_NAME, META_QUERY_SERVICE, dns};
 use async_std::net::UdpSocket;
 use dns_parser::{Packet, RData};
 use either::Either::{Left, Right};
 use futures::{future, prelude::*};
 use libp2p_core::{multiaddr::{Multiaddr, Protocol}, PeerId};
-use std::{convert::TryFrom as _, fmt, io, net::Ipv4Addr, net::SocketAddr, str, time::{Duration, Instant}};
+use std::{convert::TryFrom as _, fmt, io, net::Ipv4Addr, net::Ipv6Addr, net::SocketAddr, str, time::{Duration, Instant}};
 use wasm_timer::Interval;
 use lazy_static::lazy_static;
 
@@ -35,6 +35,10 @@ lazy_static! {
         Ipv4Addr::new(224, 0, 0, 251),
         5353,
     ));
+    static ref IPV6_MDNS_MULTICAST_ADDRESS: SocketAddr = SocketAddr::from((
+        Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb),
+        5353,
+    ));
 }
 
 /// A running service that discovers libp2p peers and responds to other libp2p peers' queries on
@@ -107,6 +111,8 @@ pub struct MdnsService {
     socket: UdpSocket,
     /// Socket for sending queries on the network.
     query_socket: UdpSocket,
+    /// Multicast address that queries and responses are sent to.
+    multicast_addr: SocketAddr,
     /// Interval for sending queries.
     query_interval: Interval,
     /// Whether we send queries on the network at all.
@@ -124,16 +130,22 @@ pub struct MdnsService {
 impl MdnsService {
     /// Starts a new mDNS service.
     pub fn new() -> io::Result<MdnsService> {
-        Self::new_inner(false)
+        Self::new_inner(false, &MdnsConfig::default())
     }
 
     /// Same as `new`, but we don't automatically send queries on the network.
     pub fn silent() -> io::Result<MdnsService> {
-        Self::new_inner(true)
+        Self::new_inner(true, &MdnsConfig::default())
+    }
+
+    /// Starts a new mDNS service, using the query interval and IP version of the given
+    /// configuration.
+    pub fn with_config(config: &MdnsConfig) -> io::Result<MdnsService> {
+        Self::new_inner(false, config)
     }
 
     /// Starts a new mDNS service.
-    fn new_inner(silent: bool) -> io::Result<MdnsService> {
+    fn new_inner(silent: bool, config: &MdnsConfig) -> io::Result<MdnsService> {
         let socket = {
             #[cfg(unix)]
             fn platform_specific(s: &net2::UdpBuilder) -> io::Result<()> {
@@ -142,24 +154,43 @@ impl MdnsService {
             }
             #[cfg(not(unix))]
             fn platform_specific(_: &net2::UdpBuilder) -> io::Result<()> { Ok(()) }
-            let builder = net2::UdpBuilder::new_v4()?;
-            builder.reuse_address(true)?;
-            platform_specific(&builder)?;
-            builder.bind(("0.0.0.0", 5353))?
+            if config.ipv6() {
+                let builder = net2::UdpBuilder::new_v6()?;
+                builder.only_v6(true)?;
+                builder.reuse_address(true)?;
+                platform_specific(&builder)?;
+                builder.bind((Ipv6Addr::UNSPECIFIED, 5353))?
+            } else {
+                let builder = net2::UdpBuilder::new_v4()?;
+                builder.reuse_address(true)?;
+                platform_specific(&builder)?;
+                builder.bind(("0.0.0.0", 5353))?
+            }
         };
 
         let socket = UdpSocket::from(socket);
-        socket.set_multicast_loop_v4(true)?;
-        socket.set_multicast_ttl_v4(255)?;
-        // TODO: correct interfaces?
-        socket.join_multicast_v4(From::from([224, 0, 0, 251]), Ipv4Addr::UNSPECIFIED)?;
+        // Given that we pass an IP address to bind, which does not need to be resolved, we can
+        // use std::net::UdpSocket::bind, instead of its async counterpart from async-std.
+        let (query_socket, multicast_addr) = if config.ipv6() {
+            socket.set_multicast_loop_v6(true)?;
+            // TODO: correct interfaces?
+            socket.join_multicast_v6(&Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb), 0)?;
+            let query_socket = std::net::UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0u16))?;
+            (query_socket, *IPV6_MDNS_MULTICAST_ADDRESS)
+        } else {
+            socket.set_multicast_loop_v4(true)?;
+            socket.set_multicast_ttl_v4(255)?;
+            // TODO: correct interfaces?
+            socket.join_multicast_v4(From::from([224, 0, 0, 251]), Ipv4Addr::UNSPECIFIED)?;
+            let query_socket = std::net::UdpSocket::bind((Ipv4Addr::from([0u8, 0, 0, 0]), 0u16))?;
+            (query_socket, *IPV4_MDNS_MULTICAST_ADDRESS)
+        };
 
         Ok(MdnsService {
             socket,
-            // Given that we pass an IP address to bind, which does not need to be resolved, we can
-            // use std::net::UdpSocket::bind, instead of its async counterpart from async-std.
-            query_socket: std::net::UdpSocket::bind((Ipv4Addr::from([0u8, 0, 0, 0]), 0u16))?.into(),
-            query_interval: Interval::new_at(Instant::now(), Duration::from_secs(20)),
+            query_socket: query_socket.into(),
+            multicast_addr,
+            query_interval: Interval::new_at(Instant::now(), config.query_interval()),
             silent,
             recv_buffer: [0; 2048],
             send_buffers: Vec::new(),
@@ -199,7 +230,7 @@ impl MdnsService {
             while !self.send_buffers.is_empty() {
                 let to_send = self.send_buffers.remove(0);
 
-                match self.socket.send_to(&to_send, *IPV4_MDNS_MULTICAST_ADDRESS).await {
+                match self.socket.send_to(&to_send, self.multicast_addr).await {
                     Ok(bytes_written) => {
                         debug_assert_eq!(bytes_written, to_send.len());
                     }
@@ -216,7 +247,7 @@ impl MdnsService {
             while !self.query_send_buffers.is_empty() {
                 let to_send = self.query_send_buffers.remove(0);
 
-                match self.query_socket.send_to(&to_send, *IPV4_MDNS_MULTICAST_ADDRESS).await {
+                match self.query_socket.send_to(&to_send, self.multicast_addr).await {
                     Ok(bytes_written) => {
                         debug_assert_eq!(bytes_written, to_send.len());
                     }
@@ -270,6 +301,7 @@ impl fmt::Debug for MdnsService {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt.debug_struct("MdnsService")
             .field("silent", &self.silent)
+            .field("multicast_addr", &self.multicast_addr)
             .finish()
     }
 }
@@ -560,11 +592,11 @@ mod tests {
     use libp2p_core::{PeerId, multiaddr::multihash};
     use std::{io::{Error, ErrorKind}, time::Duration};
     use wasm_timer::ext::TryFutureExt;
-    use crate::service::{MdnsPacket, MdnsService};
+    use crate::{MdnsConfig, service::{MdnsPacket, MdnsService}};
 
-    fn discover(peer_id: PeerId) {
+    fn discover(peer_id: PeerId, config: MdnsConfig) {
         block_on(async {
-            let mut service = MdnsService::new().unwrap();
+            let mut service = MdnsService::with_config(&config).unwrap();
             loop {
                 let next = service.next().await;
                 service = next.0;
@@ -640,13 +672,18 @@ mod tests {
 
     #[test]
     fn discover_normal_peer_id() {
-        discover(PeerId::random())
+        discover(PeerId::random(), MdnsConfig::default())
+    }
+
+    #[test]
+    fn discover_ipv6() {
+        discover(PeerId::random(), MdnsConfig::new().with_ipv6(true))
     }
 
     #[test]
     fn discover_long_peer_id() {
         let max_value = String::from_utf8(vec![b'f'; 42]).unwrap();
         let hash = multihash::Identity::digest(max_value.as_ref());
-        discover(PeerId::from_multihash(hash).unwrap())
+        discover(PeerId::from_multihash(hash).unwrap(), MdnsConfig::default())
     }
 }
@@ -18,7 +18,7 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use libp2p_core::identity;
+use libp2p_core::{identity, PeerId};
 use snow::error::Error as SnowError;
 use std::{error::Error, fmt, io};
 
@@ -34,6 +34,10 @@ pub enum NoiseError {
     /// Authentication in a [`NoiseAuthenticated`](crate::NoiseAuthenticated)
     /// upgrade failed.
     AuthenticationFailed,
+    /// The remote authenticated as a peer that was rejected by the
+    /// verification of a [`NoiseAuthenticated`](crate::NoiseAuthenticated)
+    /// upgrade.
+    UnexpectedPeer(PeerId),
     /// A handshake payload is invalid.
     InvalidPayload(prost::DecodeError),
     /// A signature was required and could not be created.
@@ -50,6 +54,7 @@ impl fmt::Display for NoiseError {
             NoiseError::InvalidKey => f.write_str("invalid public key"),
             NoiseError::InvalidPayload(e) => write!(f, "{}", e),
             NoiseError::AuthenticationFailed => f.write_str("Authentication failed"),
+            NoiseError::UnexpectedPeer(p) => write!(f, "Unexpected remote peer: {}", p),
             NoiseError::SigningError(e) => write!(f, "{}", e),
             NoiseError::__Nonexhaustive => f.write_str("__Nonexhaustive")
         }
@@ -63,6 +68,7 @@ impl Error for NoiseError {
             NoiseError::Noise(_) => None, // TODO: `SnowError` should implement `Error`.
             NoiseError::InvalidKey => None,
             NoiseError::AuthenticationFailed => None,
+            NoiseError::UnexpectedPeer(_) => None,
             NoiseError::InvalidPayload(e) => Some(e),
             NoiseError::SigningError(e) => Some(e),
             NoiseError::__Nonexhaustive => None
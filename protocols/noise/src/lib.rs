@@ -68,7 +68,7 @@ pub use protocol::{x25519::X25519, x25519_spec::X25519Spec};
 
 use futures::prelude::*;
 use libp2p_core::{identity, PeerId, UpgradeInfo, InboundUpgrade, OutboundUpgrade};
-use std::pin::Pin;
+use std::{collections::HashSet, pin::Pin, sync::Arc};
 use zeroize::Zeroize;
 
 /// The protocol upgrade configuration.
@@ -84,7 +84,7 @@ impl<H, C: Zeroize, R> NoiseConfig<H, C, R> {
     /// Turn the `NoiseConfig` into an authenticated upgrade for use
     /// with a [`Network`](libp2p_core::Network).
     pub fn into_authenticated(self) -> NoiseAuthenticated<H, C, R> {
-        NoiseAuthenticated { config: self }
+        NoiseAuthenticated { config: self, verify_remote: None }
     }
 }
 
@@ -293,6 +293,9 @@ where
 
 // Authenticated Upgrades /////////////////////////////////////////////////////
 
+/// Verification of the `PeerId` of a remote, see `verify_remote`.
+type VerifyRemote = Arc<dyn Fn(&PeerId) -> bool + Send + Sync>;
+
 /// A `NoiseAuthenticated` transport upgrade that wraps around any
 /// `NoiseConfig` handshake and verifies that the remote identified with a
 /// [`RemoteIdentity::IdentityKey`], aborting otherwise.
@@ -305,7 +308,49 @@ where
 /// transport for use with a [`Network`](libp2p_core::Network).
 #[derive(Clone)]
 pub struct NoiseAuthenticated<P, C: Zeroize, R> {
-    config: NoiseConfig<P, C, R>
+    config: NoiseConfig<P, C, R>,
+    verify_remote: Option<VerifyRemote>
+}
+
+impl<P, C: Zeroize, R> NoiseAuthenticated<P, C, R> {
+    /// Only accept remotes for which the given function returns `true`.
+    ///
+    /// The function is called with the `PeerId` of the remote once its
+    /// identity has been authenticated, and the upgrade fails with
+    /// [`NoiseError::UnexpectedPeer`] if the remote is rejected.
+    pub fn verify_remote<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&PeerId) -> bool + Send + Sync + 'static
+    {
+        self.verify_remote = Some(Arc::new(f));
+        self
+    }
+
+    /// Only accept remotes authenticating as one of the given peers.
+    ///
+    /// This is a shorthand for [`NoiseAuthenticated::verify_remote`] with an allow-list.
+    pub fn expected_peers<I>(self, peers: I) -> Self
+    where
+        I: IntoIterator<Item = PeerId>
+    {
+        let peers = peers.into_iter().collect::<HashSet<_>>();
+        self.verify_remote(move |peer| peers.contains(peer))
+    }
+}
+
+/// Maps the remote identity to a `PeerId`, applying the optional verification.
+fn authenticate<C>(
+    remote: RemoteIdentity<C>,
+    verify_remote: Option<&(dyn Fn(&PeerId) -> bool + Send + Sync)>
+) -> Result<PeerId, NoiseError> {
+    let peer_id = match remote {
+        RemoteIdentity::IdentityKey(pk) => pk.into_peer_id(),
+        _ => return Err(NoiseError::AuthenticationFailed)
+    };
+    match verify_remote {
+        Some(verify) if !verify(&peer_id) => Err(NoiseError::UnexpectedPeer(peer_id)),
+        _ => Ok(peer_id)
+    }
 }
 
 impl<P, C: Zeroize, R> UpgradeInfo for NoiseAuthenticated<P, C, R>
@@ -335,11 +380,10 @@ where
     type Future = Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + Send>>;
 
     fn upgrade_inbound(self, socket: T, info: Self::Info) -> Self::Future {
+        let verify_remote = self.verify_remote;
         Box::pin(self.config.upgrade_inbound(socket, info)
-            .and_then(|(remote, io)| match remote {
-                RemoteIdentity::IdentityKey(pk) => future::ok((pk.into_peer_id(), io)),
-                _ => future::err(NoiseError::AuthenticationFailed)
-            }))
+            .and_then(move |(remote, io)| future::ready(
+                authenticate(remote, verify_remote.as_deref()).map(|peer_id| (peer_id, io)))))
     }
 }
 
@@ -358,10 +402,9 @@ where
     type Future = Pin<Box<dyn Future<Output = Result<Self::Output, Self::Error>> + Send>>;
 
     fn upgrade_outbound(self, socket: T, info: Self::Info) -> Self::Future {
+        let verify_remote = self.verify_remote;
         Box::pin(self.config.upgrade_outbound(socket, info)
-            .and_then(|(remote, io)| match remote {
-                RemoteIdentity::IdentityKey(pk) => future::ok((pk.into_peer_id(), io)),
-                _ => future::err(NoiseError::AuthenticationFailed)
-            }))
+            .and_then(move |(remote, io)| future::ready(
+                authenticate(remote, verify_remote.as_deref()).map(|peer_id| (peer_id, io)))))
     }
 }
@@ -103,7 +103,16 @@ pub trait Protocol<C> {
     where
         C: AsRef<[u8]>
     {
-        Ok(id_keys.sign(dh_pk.as_ref())?)
+        Ok(id_keys.sign(&Self::signed_message(dh_pk))?)
+    }
+
+    /// The message that is signed with the identity key in order to
+    /// authenticate the given static DH public key.
+    fn signed_message(dh_pk: &PublicKey<C>) -> Vec<u8>
+    where
+        C: AsRef<[u8]>
+    {
+        dh_pk.as_ref().to_vec()
     }
 }
 
@@ -173,6 +182,25 @@ impl<T: Zeroize> Keypair<T> {
 
         Ok(AuthenticKeypair { keypair: self, identity })
     }
+
+    /// Turn this DH keypair into a [`AuthenticKeypair`], like [`Keypair::into_authentic`],
+    /// but sign the DH public key with the given [`identity::Signer`], e.g. one
+    /// backed by a hardware security module.
+    pub async fn into_authentic_with_signer(self, signer: &dyn identity::Signer)
+        -> Result<AuthenticKeypair<T>, NoiseError>
+    where
+        T: AsRef<[u8]>,
+        T: Protocol<T>
+    {
+        let sig = signer.sign(&T::signed_message(&self.public)).await?;
+
+        let identity = KeypairIdentity {
+            public: signer.public(),
+            signature: Some(sig)
+        };
+
+        Ok(AuthenticKeypair { keypair: self, identity })
+    }
 }
 
 /// DH secret key.
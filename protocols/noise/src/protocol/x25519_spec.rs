@@ -131,8 +131,8 @@ impl Protocol<X25519Spec> for X25519Spec {
         })
     }
 
-    fn sign(id_keys: &identity::Keypair, dh_pk: &PublicKey<X25519Spec>) -> Result<Vec<u8>, NoiseError> {
-        Ok(id_keys.sign(&[STATIC_KEY_DOMAIN.as_bytes(), dh_pk.as_ref()].concat())?)
+    fn signed_message(dh_pk: &PublicKey<X25519Spec>) -> Vec<u8> {
+        [STATIC_KEY_DOMAIN.as_bytes(), dh_pk.as_ref()].concat()
     }
 }
 
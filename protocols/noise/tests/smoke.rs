@@ -22,7 +22,7 @@ use futures::{future::{self, Either}, prelude::*};
 use libp2p_core::identity;
 use libp2p_core::upgrade::{self, Negotiated, apply_inbound, apply_outbound};
 use libp2p_core::transport::{Transport, ListenerEvent};
-use libp2p_noise::{Keypair, X25519, X25519Spec, NoiseConfig, RemoteIdentity, NoiseError, NoiseOutput};
+use libp2p_noise::{Keypair, X25519, X25519Spec, XX, NoiseAuthenticated, NoiseConfig, RemoteIdentity, NoiseError, NoiseOutput};
 use libp2p_tcp::{TcpConfig, TcpTransStream};
 use log::info;
 use quickcheck::QuickCheck;
@@ -69,6 +69,57 @@ fn xx_spec() {
     QuickCheck::new().max_tests(30).quickcheck(prop as fn(Vec<Message>) -> bool)
 }
 
+#[test]
+fn xx_spec_signer() {
+    let _ = env_logger::try_init();
+
+    /// A signer that holds the identity key elsewhere, signing asynchronously.
+    struct ExternalSigner(identity::Keypair);
+
+    impl identity::Signer for ExternalSigner {
+        fn public(&self) -> identity::PublicKey {
+            self.0.public()
+        }
+
+        fn sign(&self, msg: &[u8]) -> future::BoxFuture<'static, Result<Vec<u8>, identity::error::SigningError>> {
+            let (tx, rx) = futures::channel::oneshot::channel();
+            let keypair = self.0.clone();
+            let msg = msg.to_vec();
+            std::thread::spawn(move || tx.send(keypair.sign(&msg)));
+            rx.map(|r| r.expect("signer thread sends result")).boxed()
+        }
+    }
+
+    fn prop(mut messages: Vec<Message>) -> bool {
+        messages.truncate(5);
+        let server_id = ExternalSigner(identity::Keypair::generate_ed25519());
+        let client_id = identity::Keypair::generate_ed25519();
+
+        let server_id_public = identity::Signer::public(&server_id);
+        let client_id_public = client_id.public();
+
+        let server_dh = futures::executor::block_on(
+            Keypair::<X25519Spec>::new().into_authentic_with_signer(&server_id)
+        ).unwrap();
+        let server_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                upgrade::apply(output, NoiseConfig::xx(server_dh), endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &client_id_public));
+
+        let client_dh = Keypair::<X25519Spec>::new().into_authentic(&client_id).unwrap();
+        let client_transport = TcpConfig::new()
+            .and_then(move |output, endpoint| {
+                upgrade::apply(output, NoiseConfig::xx(client_dh), endpoint, upgrade::Version::V1)
+            })
+            .and_then(move |out, _| expect_identity(out, &server_id_public));
+
+        run(server_transport, client_transport, messages);
+        true
+    }
+    QuickCheck::new().max_tests(10).quickcheck(prop as fn(Vec<Message>) -> bool)
+}
+
 #[test]
 fn xx() {
     let _ = env_logger::try_init();
@@ -100,6 +151,49 @@ fn xx() {
     QuickCheck::new().max_tests(30).quickcheck(prop as fn(Vec<Message>) -> bool)
 }
 
+#[test]
+fn xx_expected_peers() {
+    let _ = env_logger::try_init();
+    let server_id = identity::Keypair::generate_ed25519();
+    let client_id = identity::Keypair::generate_ed25519();
+    let client_peer_id = client_id.public().into_peer_id();
+    let other_peer_id = identity::Keypair::generate_ed25519().public().into_peer_id();
+
+    let handshake = |server: NoiseAuthenticated<XX, X25519, ()>| {
+        let client_dh = Keypair::<X25519>::new().into_authentic(&client_id).unwrap();
+        let client = NoiseConfig::xx(client_dh).into_authenticated();
+        futures::executor::block_on(async move {
+            let mut listener = TcpConfig::new()
+                .listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .unwrap();
+            let addr = listener.try_next().await.unwrap().unwrap().into_new_address().unwrap();
+            let server_fut = async {
+                let socket = listener.try_next().await.unwrap().unwrap()
+                    .into_upgrade().unwrap().0.await.unwrap();
+                upgrade::apply_inbound(socket, server).await
+            };
+            let client_fut = async {
+                let socket = TcpConfig::new().dial(addr).unwrap().await.unwrap();
+                let _ = upgrade::apply_outbound(socket, client, upgrade::Version::V1).await;
+            };
+            future::join(server_fut, client_fut).await.0.map(|(peer, _)| peer)
+        })
+    };
+
+    let server_dh = Keypair::<X25519>::new().into_authentic(&server_id).unwrap();
+    let server = NoiseConfig::xx(server_dh.clone()).into_authenticated()
+        .expected_peers(vec![client_peer_id.clone()]);
+    assert_eq!(handshake(server).unwrap(), client_peer_id);
+
+    let server = NoiseConfig::xx(server_dh).into_authenticated()
+        .expected_peers(vec![other_peer_id]);
+    match handshake(server) {
+        Err(upgrade::UpgradeError::Apply(NoiseError::UnexpectedPeer(p))) => assert_eq!(p, client_peer_id),
+        Err(e) => panic!("Unexpected error: {:?}", e),
+        Ok(p) => panic!("Unexpected success: {:?}", p),
+    }
+}
+
 #[test]
 fn ix() {
     let _ = env_logger::try_init();
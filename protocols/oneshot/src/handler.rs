@@ -0,0 +1,237 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::OneShotError;
+use crate::protocol::{InboundMessage, OutboundMessage};
+
+use futures::{channel::oneshot, future::BoxFuture, prelude::*, stream::FuturesUnordered};
+use libp2p_core::upgrade::{self, NegotiationError, ReadOneError, UpgradeError};
+use libp2p_swarm::{
+    NegotiatedSubstream,
+    SubstreamProtocol,
+    protocols_handler::{
+        KeepAlive,
+        ProtocolsHandler,
+        ProtocolsHandlerEvent,
+        ProtocolsHandlerUpgrErr,
+    }
+};
+use std::{collections::VecDeque, io, task::{Context, Poll}, time::Duration};
+use void::Void;
+use wasm_timer::{Instant, TryFutureExt};
+
+/// An outbound message to send on a new substream.
+#[derive(Debug, Clone)]
+pub struct OneShotRequest {
+    pub(crate) id: u64,
+    pub(crate) message: OutboundMessage,
+}
+
+/// An event emitted by a [`OneShotHandler`].
+#[derive(Debug)]
+pub enum OneShotHandlerEvent {
+    /// A message has been received.
+    Message {
+        /// The negotiated protocol.
+        protocol: String,
+        /// The received message.
+        data: Vec<u8>,
+        /// The sender through which a reply is sent. Dropping it closes
+        /// the substream without a reply.
+        responder: oneshot::Sender<Vec<u8>>,
+    },
+    /// An outbound message has been sent and the reply, if one is
+    /// expected, received, or sending it failed.
+    Outcome {
+        /// The ID of the [`OneShotRequest`].
+        id: u64,
+        /// The reply, if one is expected.
+        result: Result<Option<Vec<u8>>, OneShotError>,
+    },
+}
+
+/// A connection handler of the `OneShot` behaviour.
+#[doc(hidden)]
+pub struct OneShotHandler {
+    /// The supported inbound protocols.
+    inbound_protocols: Vec<String>,
+    /// The maximum size of a received message or reply.
+    max_message_size: usize,
+    /// The timeout for sending a message, including reading the reply,
+    /// and for replying to a received message.
+    substream_timeout: Duration,
+    /// The keep-alive timeout of idle connections.
+    keep_alive_timeout: Duration,
+    /// The current connection keep-alive.
+    keep_alive: KeepAlive,
+    /// Outbound messages waiting to be emitted as an `OutboundSubstreamRequest`.
+    outbound: VecDeque<OneShotRequest>,
+    /// The number of outbound substreams being opened or upgraded.
+    pending_outbound: usize,
+    /// Queue of events to emit in `poll()`.
+    pending_events: VecDeque<OneShotHandlerEvent>,
+    /// Inbound substreams waiting for or writing the reply.
+    replies: FuturesUnordered<BoxFuture<'static, ()>>,
+}
+
+impl OneShotHandler {
+    pub(crate) fn new(
+        inbound_protocols: Vec<String>,
+        max_message_size: usize,
+        substream_timeout: Duration,
+        keep_alive_timeout: Duration,
+    ) -> Self {
+        OneShotHandler {
+            inbound_protocols,
+            max_message_size,
+            substream_timeout,
+            keep_alive_timeout,
+            keep_alive: KeepAlive::Yes,
+            outbound: VecDeque::new(),
+            pending_outbound: 0,
+            pending_events: VecDeque::new(),
+            replies: FuturesUnordered::new(),
+        }
+    }
+}
+
+impl ProtocolsHandler for OneShotHandler {
+    type InEvent = OneShotRequest;
+    type OutEvent = OneShotHandlerEvent;
+    type Error = Void;
+    type InboundProtocol = InboundMessage;
+    type OutboundProtocol = OutboundMessage;
+    type OutboundOpenInfo = u64;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol> {
+        let proto = InboundMessage {
+            protocols: self.inbound_protocols.clone(),
+            max_size: self.max_message_size,
+        };
+        SubstreamProtocol::new(proto).with_timeout(self.substream_timeout)
+    }
+
+    fn inject_fully_negotiated_inbound(
+        &mut self,
+        (protocol, data, mut socket): (String, Vec<u8>, NegotiatedSubstream),
+    ) {
+        let (responder, reply) = oneshot::channel();
+        let write = async move {
+            match reply.await {
+                Ok(data) => upgrade::write_one(&mut socket, data).await,
+                // No reply is sent.
+                Err(oneshot::Canceled) => socket.close().await,
+            }
+        };
+        self.keep_alive = KeepAlive::Yes;
+        self.replies.push(write.timeout(self.substream_timeout)
+            .map(|result| if let Err(e) = result {
+                log::debug!("Failed to reply to one-shot message: {:?}", e);
+            })
+            .boxed());
+        self.pending_events.push_back(OneShotHandlerEvent::Message { protocol, data, responder });
+    }
+
+    fn inject_fully_negotiated_outbound(
+        &mut self,
+        reply: Option<Vec<u8>>,
+        id: u64,
+    ) {
+        self.pending_outbound -= 1;
+        self.pending_events.push_back(OneShotHandlerEvent::Outcome { id, result: Ok(reply) });
+    }
+
+    fn inject_event(&mut self, request: Self::InEvent) {
+        self.keep_alive = KeepAlive::Yes;
+        self.outbound.push_back(request);
+    }
+
+    fn inject_dial_upgrade_error(
+        &mut self,
+        id: u64,
+        error: ProtocolsHandlerUpgrErr<ReadOneError>,
+    ) {
+        self.pending_outbound -= 1;
+        let error = match error {
+            ProtocolsHandlerUpgrErr::Timeout => OneShotError::Timeout,
+            ProtocolsHandlerUpgrErr::Timer =>
+                OneShotError::Io(io::Error::new(io::ErrorKind::Other, "timer error")),
+            ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Select(NegotiationError::Failed)) =>
+                OneShotError::UnsupportedProtocol,
+            ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Select(NegotiationError::ProtocolError(e))) =>
+                OneShotError::Io(e.into()),
+            ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Apply(ReadOneError::Io(e))) =>
+                OneShotError::Io(e),
+            ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Apply(e)) =>
+                OneShotError::Io(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        self.pending_events.push_back(OneShotHandlerEvent::Outcome { id, result: Err(error) });
+    }
+
+    fn inject_listen_upgrade_error(
+        &mut self,
+        error: ProtocolsHandlerUpgrErr<ReadOneError>
+    ) {
+        // An inbound substream that fails to deliver a message is of no
+        // further concern, neither to the connection nor to the behaviour.
+        log::debug!("Failed to receive one-shot message: {:?}", error);
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        self.keep_alive
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<
+        ProtocolsHandlerEvent<OutboundMessage, u64, OneShotHandlerEvent, Void>,
+    > {
+        if let Some(event) = self.pending_events.pop_front() {
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(event))
+        }
+
+        // Make progress on replies.
+        while let Poll::Ready(Some(())) = self.replies.poll_next_unpin(cx) {}
+
+        if let Some(OneShotRequest { id, message }) = self.outbound.pop_front() {
+            self.pending_outbound += 1;
+            return Poll::Ready(
+                ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                    protocol: SubstreamProtocol::new(message)
+                        .with_timeout(self.substream_timeout),
+                    info: id,
+                },
+            )
+        }
+
+        if self.pending_outbound > 0 || !self.replies.is_empty() {
+            self.keep_alive = KeepAlive::Yes;
+        } else if let KeepAlive::Yes = self.keep_alive {
+            // An inbound message may still be in the process of being
+            // received, so the keep-alive timeout is preceded by the
+            // substream timeout.
+            let until = Instant::now() + self.substream_timeout + self.keep_alive_timeout;
+            self.keep_alive = KeepAlive::Until(until);
+        }
+
+        Poll::Pending
+    }
+}
@@ -0,0 +1,451 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! One-shot messages on dedicated substreams.
+//!
+//! The [`OneShot`] behaviour sends a single message to a peer on a new
+//! substream, negotiated with a protocol chosen per message, without the
+//! need for a dedicated `NetworkBehaviour` and `ProtocolsHandler` pair:
+//!
+//!   * [`OneShot::send`] writes the message and closes the substream.
+//!   * [`OneShot::request`] writes the message and reads a single reply.
+//!
+//! Both return a future that resolves once the message is sent or the
+//! reply received, respectively. A message and a reply are each written
+//! with an unsigned varint length prefix, after which the substream is
+//! closed for writing.
+//!
+//! Messages received on one of the protocols given to [`OneShot::new`] are
+//! reported as [`OneShotEvent::Message`], together with a [`Responder`]
+//! for sending a reply, if the protocol at hand expects one.
+//!
+//! > **Note**: The returned futures only make progress while the `Swarm`
+//! > containing the `OneShot` behaviour is polled.
+
+mod handler;
+mod protocol;
+
+pub use handler::{OneShotHandler, OneShotHandlerEvent, OneShotRequest};
+pub use protocol::{InboundMessage, OutboundMessage};
+
+use futures::{channel::oneshot, prelude::*};
+use libp2p_core::{ConnectedPoint, Multiaddr, PeerId, connection::ConnectionId};
+use libp2p_swarm::{
+    DialPeerCondition,
+    NetworkBehaviour,
+    NetworkBehaviourAction,
+    NotifyHandler,
+    PollParameters,
+};
+use smallvec::SmallVec;
+use std::{
+    collections::{HashMap, VecDeque},
+    error, fmt, io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// The configuration of a [`OneShot`] behaviour.
+#[derive(Debug, Clone)]
+pub struct OneShotConfig {
+    max_message_size: usize,
+    timeout: Duration,
+    connection_keep_alive: Duration,
+}
+
+impl Default for OneShotConfig {
+    fn default() -> Self {
+        OneShotConfig {
+            max_message_size: 1024 * 1024,
+            timeout: Duration::from_secs(10),
+            connection_keep_alive: Duration::from_secs(10),
+        }
+    }
+}
+
+impl OneShotConfig {
+    /// Sets the maximum size in bytes of a received message or reply.
+    pub fn set_max_message_size(&mut self, v: usize) -> &mut Self {
+        self.max_message_size = v;
+        self
+    }
+
+    /// Sets the timeout for sending a message, including reading its
+    /// reply, and for replying to a received message.
+    pub fn set_timeout(&mut self, v: Duration) -> &mut Self {
+        self.timeout = v;
+        self
+    }
+
+    /// Sets the keep-alive timeout of idle connections.
+    pub fn set_connection_keep_alive(&mut self, v: Duration) -> &mut Self {
+        self.connection_keep_alive = v;
+        self
+    }
+}
+
+/// The events emitted by the [`OneShot`] behaviour.
+#[derive(Debug)]
+pub enum OneShotEvent {
+    /// A message has been received.
+    Message {
+        /// The peer who sent the message.
+        peer: PeerId,
+        /// The protocol on which the message has been received.
+        protocol: String,
+        /// The received message.
+        data: Vec<u8>,
+        /// The responder through which a reply can be sent.
+        ///
+        /// Dropping the responder closes the substream without a reply,
+        /// which a peer expecting one receives as an empty reply.
+        responder: Responder,
+    },
+}
+
+/// Sends a reply to a received message.
+#[derive(Debug)]
+pub struct Responder {
+    sender: oneshot::Sender<Vec<u8>>,
+}
+
+impl Responder {
+    /// Sends a reply to the received message.
+    ///
+    /// If the substream is already closed, e.g. because the timeout for
+    /// replying elapsed, the reply is discarded.
+    pub fn respond(self, data: Vec<u8>) {
+        let _ = self.sender.send(data);
+    }
+}
+
+/// Possible failures of sending a message.
+#[derive(Debug)]
+pub enum OneShotError {
+    /// The peer could not be dialed.
+    DialFailure,
+    /// The connection was closed before the message was sent or the reply
+    /// received.
+    ConnectionClosed,
+    /// The message was not sent or the reply not received within the
+    /// configured timeout.
+    Timeout,
+    /// The peer does not support the protocol of the message.
+    UnsupportedProtocol,
+    /// An I/O error on the substream, e.g. a reply exceeding the maximum
+    /// message size.
+    Io(io::Error),
+}
+
+impl fmt::Display for OneShotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OneShotError::DialFailure => write!(f, "Failed to dial the peer"),
+            OneShotError::ConnectionClosed => write!(f, "Connection closed"),
+            OneShotError::Timeout => write!(f, "Timeout"),
+            OneShotError::UnsupportedProtocol => write!(f, "Protocol not supported by the peer"),
+            OneShotError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl error::Error for OneShotError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            OneShotError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The future returned by [`OneShot::send`], resolving once the message
+/// has been sent.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct SendFuture {
+    receiver: oneshot::Receiver<Result<Option<Vec<u8>>, OneShotError>>,
+}
+
+impl Future for SendFuture {
+    type Output = Result<(), OneShotError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.receiver.poll_unpin(cx) {
+            Poll::Ready(Ok(result)) => Poll::Ready(result.map(|_| ())),
+            Poll::Ready(Err(oneshot::Canceled)) => Poll::Ready(Err(OneShotError::ConnectionClosed)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// The future returned by [`OneShot::request`], resolving to the reply.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct RequestFuture {
+    receiver: oneshot::Receiver<Result<Option<Vec<u8>>, OneShotError>>,
+}
+
+impl Future for RequestFuture {
+    type Output = Result<Vec<u8>, OneShotError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.receiver.poll_unpin(cx) {
+            Poll::Ready(Ok(result)) => Poll::Ready(result.map(|reply|
+                reply.expect("A reply is read for every request."))),
+            Poll::Ready(Err(oneshot::Canceled)) => Poll::Ready(Err(OneShotError::ConnectionClosed)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// The sender through which the outcome of an outbound message is reported.
+type OutcomeSender = oneshot::Sender<Result<Option<Vec<u8>>, OneShotError>>;
+
+/// A `NetworkBehaviour` sending and receiving one-shot messages.
+pub struct OneShot {
+    /// The protocols on which messages are received.
+    inbound_protocols: Vec<String>,
+    /// The configuration of the behaviour.
+    config: OneShotConfig,
+    /// The ID of the next outbound message.
+    next_request_id: u64,
+    /// The currently established connections.
+    connected: HashMap<PeerId, SmallVec<[ConnectionId; 2]>>,
+    /// Messages to send to peers that are being dialed.
+    pending_requests: HashMap<PeerId, SmallVec<[OneShotRequest; 10]>>,
+    /// The senders of the outcomes of outbound messages, together with
+    /// the connection on which a message is sent, once assigned.
+    pending_outcomes: HashMap<u64, (Option<ConnectionId>, OutcomeSender)>,
+    /// The known addresses of peers, in addition to those reported by other
+    /// behaviours.
+    addresses: HashMap<PeerId, SmallVec<[Multiaddr; 6]>>,
+    /// Pending events to return from `poll`.
+    pending_events: VecDeque<NetworkBehaviourAction<OneShotRequest, OneShotEvent>>,
+}
+
+impl OneShot {
+    /// Creates a new `OneShot` behaviour, receiving messages on the given
+    /// protocols.
+    pub fn new<I, P>(inbound_protocols: I, config: OneShotConfig) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<String>,
+    {
+        OneShot {
+            inbound_protocols: inbound_protocols.into_iter().map(Into::into).collect(),
+            config,
+            next_request_id: 0,
+            connected: HashMap::new(),
+            pending_requests: HashMap::new(),
+            pending_outcomes: HashMap::new(),
+            addresses: HashMap::new(),
+            pending_events: VecDeque::new(),
+        }
+    }
+
+    /// Sends a message to a peer on a new substream with the given protocol,
+    /// closing the substream once the message is written.
+    ///
+    /// If the peer is currently not connected, a dialing attempt is
+    /// initiated and the message is sent as soon as a connection is
+    /// established.
+    ///
+    /// > **Note**: In order for such a dialing attempt to succeed,
+    /// > the `OneShot` behaviour must either be embedded in another
+    /// > `NetworkBehaviour` that provides peer and address discovery, or
+    /// > known addresses of peers must be managed via
+    /// > [`OneShot::add_address`] and [`OneShot::remove_address`].
+    pub fn send(&mut self, peer: &PeerId, protocol: impl Into<String>, data: Vec<u8>) -> SendFuture {
+        let receiver = self.send_message(peer, protocol.into(), data, false);
+        SendFuture { receiver }
+    }
+
+    /// Sends a message to a peer on a new substream with the given protocol,
+    /// like [`OneShot::send`], and reads a single reply from the substream.
+    pub fn request(&mut self, peer: &PeerId, protocol: impl Into<String>, data: Vec<u8>) -> RequestFuture {
+        let receiver = self.send_message(peer, protocol.into(), data, true);
+        RequestFuture { receiver }
+    }
+
+    /// Adds a known address for a peer that can be used for
+    /// dialing attempts by the `Swarm`.
+    ///
+    /// Addresses added in this way are only removed by `remove_address`.
+    pub fn add_address(&mut self, peer: &PeerId, address: Multiaddr) {
+        self.addresses.entry(peer.clone()).or_default().push(address);
+    }
+
+    /// Removes an address of a peer previously added via `add_address`.
+    pub fn remove_address(&mut self, peer: &PeerId, address: &Multiaddr) {
+        let mut last = false;
+        if let Some(addresses) = self.addresses.get_mut(peer) {
+            addresses.retain(|a| a != address);
+            last = addresses.is_empty();
+        }
+        if last {
+            self.addresses.remove(peer);
+        }
+    }
+
+    fn send_message(&mut self, peer: &PeerId, protocol: String, data: Vec<u8>, reply: bool)
+        -> oneshot::Receiver<Result<Option<Vec<u8>>, OneShotError>>
+    {
+        let (sender, receiver) = oneshot::channel();
+        let message = OutboundMessage {
+            protocol,
+            data,
+            reply,
+            max_size: self.config.max_message_size,
+        };
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        self.pending_outcomes.insert(id, (None, sender));
+        let request = OneShotRequest { id, message };
+
+        if let Some(request) = self.try_send_request(peer, request) {
+            self.pending_events.push_back(NetworkBehaviourAction::DialPeer {
+                peer_id: peer.clone(),
+                condition: DialPeerCondition::Disconnected,
+            });
+            self.pending_requests.entry(peer.clone()).or_default().push(request);
+        }
+
+        receiver
+    }
+
+    /// Tries to send a request by queueing an appropriate event to be
+    /// emitted to the `Swarm`. If the peer is not currently connected,
+    /// the given request is returned unchanged.
+    fn try_send_request(&mut self, peer: &PeerId, request: OneShotRequest) -> Option<OneShotRequest> {
+        if let Some(conn) = self.connected.get(peer).and_then(|c| c.first()) {
+            if let Some((c, _)) = self.pending_outcomes.get_mut(&request.id) {
+                *c = Some(*conn);
+            }
+            self.pending_events.push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id: peer.clone(),
+                handler: NotifyHandler::One(*conn),
+                event: request,
+            });
+            None
+        } else {
+            Some(request)
+        }
+    }
+}
+
+impl NetworkBehaviour for OneShot {
+    type ProtocolsHandler = OneShotHandler;
+    type OutEvent = OneShotEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        OneShotHandler::new(
+            self.inbound_protocols.clone(),
+            self.config.max_message_size,
+            self.config.timeout,
+            self.config.connection_keep_alive,
+        )
+    }
+
+    fn addresses_of_peer(&mut self, peer: &PeerId) -> Vec<Multiaddr> {
+        self.addresses.get(peer)
+            .map(|addresses| addresses.to_vec())
+            .unwrap_or_default()
+    }
+
+    fn inject_connected(&mut self, peer: &PeerId) {
+        if let Some(pending) = self.pending_requests.remove(peer) {
+            for request in pending {
+                let request = self.try_send_request(peer, request);
+                assert!(request.is_none());
+            }
+        }
+    }
+
+    fn inject_connection_established(&mut self, peer: &PeerId, conn: &ConnectionId, _: &ConnectedPoint) {
+        self.connected.entry(peer.clone()).or_default().push(*conn);
+    }
+
+    fn inject_connection_closed(&mut self, peer: &PeerId, conn: &ConnectionId, _: &ConnectedPoint) {
+        if let Some(connections) = self.connected.get_mut(peer) {
+            connections.retain(|c| c != conn);
+        }
+
+        // Any pending outcomes of messages sent over this connection
+        // must be considered failed.
+        let failed = self.pending_outcomes.iter()
+            .filter(|(_, (c, _))| c.as_ref() == Some(conn))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        for id in failed {
+            if let Some((_, sender)) = self.pending_outcomes.remove(&id) {
+                let _ = sender.send(Err(OneShotError::ConnectionClosed));
+            }
+        }
+    }
+
+    fn inject_disconnected(&mut self, peer: &PeerId) {
+        self.connected.remove(peer);
+    }
+
+    fn inject_dial_failure(&mut self, peer: &PeerId) {
+        if let Some(pending) = self.pending_requests.remove(peer) {
+            for request in pending {
+                if let Some((_, sender)) = self.pending_outcomes.remove(&request.id) {
+                    let _ = sender.send(Err(OneShotError::DialFailure));
+                }
+            }
+        }
+    }
+
+    fn inject_event(&mut self, peer: PeerId, _: ConnectionId, event: OneShotHandlerEvent) {
+        match event {
+            OneShotHandlerEvent::Message { protocol, data, responder } => {
+                let responder = Responder { sender: responder };
+                self.pending_events.push_back(NetworkBehaviourAction::GenerateEvent(
+                    OneShotEvent::Message { peer, protocol, data, responder }));
+            }
+            OneShotHandlerEvent::Outcome { id, result } => {
+                if let Some((_, sender)) = self.pending_outcomes.remove(&id) {
+                    let _ = sender.send(result);
+                }
+            }
+        }
+    }
+
+    fn poll(&mut self, _: &mut Context<'_>, _: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<OneShotRequest, OneShotEvent>>
+    {
+        if let Some(ev) = self.pending_events.pop_front() {
+            return Poll::Ready(ev);
+        } else if self.pending_events.capacity() > EMPTY_QUEUE_SHRINK_THRESHOLD {
+            self.pending_events.shrink_to_fit();
+        }
+
+        Poll::Pending
+    }
+}
+
+/// Internal threshold for when to shrink the capacity
+/// of empty queues. If the capacity of an empty queue
+/// exceeds this threshold, the associated memory is
+/// released.
+const EMPTY_QUEUE_SHRINK_THRESHOLD: usize = 100;
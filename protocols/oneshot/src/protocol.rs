@@ -0,0 +1,98 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The upgrades of the substreams on which one-shot messages are
+//! exchanged.
+//!
+//! A message and a reply are each sent with an unsigned varint length
+//! prefix, after which the sender closes its side of the substream.
+
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::upgrade::{self, InboundUpgrade, OutboundUpgrade, ReadOneError, UpgradeInfo};
+use libp2p_swarm::NegotiatedSubstream;
+use std::{iter, vec};
+
+/// The upgrade of an outbound substream, sending a message and
+/// optionally reading the reply.
+#[derive(Debug, Clone)]
+pub struct OutboundMessage {
+    pub(crate) protocol: String,
+    pub(crate) data: Vec<u8>,
+    pub(crate) reply: bool,
+    pub(crate) max_size: usize,
+}
+
+impl UpgradeInfo for OutboundMessage {
+    type Info = String;
+    type InfoIter = iter::Once<String>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(self.protocol.clone())
+    }
+}
+
+impl OutboundUpgrade<NegotiatedSubstream> for OutboundMessage {
+    type Output = Option<Vec<u8>>;
+    type Error = ReadOneError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, mut socket: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+        async move {
+            upgrade::write_one(&mut socket, self.data).await?;
+            if self.reply {
+                Ok(Some(upgrade::read_one(&mut socket, self.max_size).await?))
+            } else {
+                Ok(None)
+            }
+        }.boxed()
+    }
+}
+
+/// The upgrade of an inbound substream, reading a message.
+///
+/// The output is the negotiated protocol, the message and the substream
+/// on which a reply may be sent.
+#[derive(Debug, Clone)]
+pub struct InboundMessage {
+    pub(crate) protocols: Vec<String>,
+    pub(crate) max_size: usize,
+}
+
+impl UpgradeInfo for InboundMessage {
+    type Info = String;
+    type InfoIter = vec::IntoIter<String>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.protocols.clone().into_iter()
+    }
+}
+
+impl InboundUpgrade<NegotiatedSubstream> for InboundMessage {
+    type Output = (String, Vec<u8>, NegotiatedSubstream);
+    type Error = ReadOneError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, mut socket: NegotiatedSubstream, protocol: Self::Info) -> Self::Future {
+        async move {
+            let data = upgrade::read_one(&mut socket, self.max_size).await?;
+            Ok((protocol, data, socket))
+        }.boxed()
+    }
+}
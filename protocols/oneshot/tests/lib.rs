@@ -0,0 +1,123 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+use futures::{channel::mpsc, executor::block_on, future, prelude::*};
+use libp2p_core::{
+    identity,
+    multiaddr::{Multiaddr, Protocol},
+    muxing::StreamMuxerBox,
+    transport::{boxed::Boxed, MemoryTransport, Transport},
+    upgrade,
+    PeerId,
+};
+use libp2p_oneshot::{OneShot, OneShotConfig, OneShotError, OneShotEvent};
+use libp2p_plaintext::PlainText2Config;
+use libp2p_swarm::{Swarm, SwarmEvent};
+use libp2p_yamux as yamux;
+use std::{io, task::Poll};
+
+#[test]
+fn oneshot_messages() {
+    let (alice_id, trans) = mk_transport();
+    let protocols = vec!["/echo/1.0.0", "/note/1.0.0"];
+    let mut alice = Swarm::new(trans, OneShot::new(protocols, OneShotConfig::default()), alice_id.clone());
+    let alice_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    Swarm::listen_on(&mut alice, alice_addr.clone()).unwrap();
+    let (mut notes_tx, mut notes_rx) = mpsc::channel(1);
+    async_std::task::spawn(async move {
+        loop {
+            if let SwarmEvent::Behaviour(OneShotEvent::Message { protocol, mut data, responder, .. })
+                = alice.next_event().await
+            {
+                if protocol == "/echo/1.0.0" {
+                    data.reverse();
+                    responder.respond(data);
+                } else {
+                    notes_tx.send(data).await.unwrap();
+                }
+            }
+        }
+    });
+
+    let (bob_id, trans) = mk_transport();
+    let mut bob = Swarm::new(trans, OneShot::new(Vec::<String>::new(), OneShotConfig::default()), bob_id);
+    bob.add_address(&alice_id, alice_addr);
+
+    block_on(async {
+        let reply = bob.request(&alice_id, "/echo/1.0.0", b"ping".to_vec());
+        assert_eq!(drive(&mut bob, reply).await.unwrap(), b"gnip".to_vec());
+
+        let sent = bob.send(&alice_id, "/note/1.0.0", b"note".to_vec());
+        drive(&mut bob, sent).await.unwrap();
+        assert_eq!(notes_rx.next().await.unwrap(), b"note".to_vec());
+
+        let sent = bob.send(&alice_id, "/unknown/1.0.0", b"note".to_vec());
+        match drive(&mut bob, sent).await {
+            Err(OneShotError::UnsupportedProtocol) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        // Alice drops the responder of a note, closing the substream
+        // without a reply.
+        let reply = bob.request(&alice_id, "/note/1.0.0", b"note".to_vec());
+        assert!(drive(&mut bob, reply).await.unwrap().is_empty());
+        assert_eq!(notes_rx.next().await.unwrap(), b"note".to_vec());
+    });
+}
+
+#[test]
+fn dial_failure() {
+    let (bob_id, trans) = mk_transport();
+    let mut bob = Swarm::new(trans, OneShot::new(Vec::<String>::new(), OneShotConfig::default()), bob_id);
+    let unreachable: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    let peer = PeerId::random();
+    bob.add_address(&peer, unreachable);
+
+    block_on(async {
+        let sent = bob.send(&peer, "/note/1.0.0", b"note".to_vec());
+        match drive(&mut bob, sent).await {
+            Err(OneShotError::DialFailure) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    });
+}
+
+/// Polls the swarm until the future completes.
+async fn drive<F: Future + Unpin>(swarm: &mut Swarm<OneShot>, mut fut: F) -> F::Output {
+    future::poll_fn(|cx| {
+        if let Poll::Ready(output) = fut.poll_unpin(cx) {
+            return Poll::Ready(output)
+        }
+        while let Poll::Ready(Some(_)) = swarm.poll_next_unpin(cx) {}
+        Poll::Pending
+    }).await
+}
+
+fn mk_transport() -> (PeerId, Boxed<(PeerId, StreamMuxerBox), io::Error>) {
+    let id_keys = identity::Keypair::generate_ed25519();
+    let peer_id = id_keys.public().into_peer_id();
+    let transport = MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(PlainText2Config { local_public_key: id_keys.public() })
+        .multiplex(yamux::Config::default())
+        .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+        .boxed();
+    (peer_id, transport)
+}
@@ -0,0 +1,512 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The client role of the circuit relay v2 protocol.
+
+pub mod handler;
+pub mod transport;
+
+use crate::protocol::Limit;
+use futures::{channel::{mpsc, oneshot}, prelude::*};
+use handler::Handler;
+use libp2p_core::{connection::ConnectionId, multiaddr::Protocol, ConnectedPoint, Multiaddr, PeerId};
+use libp2p_swarm::{
+    DialPeerCondition,
+    NetworkBehaviour,
+    NetworkBehaviourAction,
+    NotifyHandler,
+    PollParameters,
+    ProtocolsHandlerUpgrErr,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    error,
+    io,
+    task::{Context, Poll},
+};
+use transport::{Connection, ToListenerMsg, TransportToBehaviourMsg};
+
+pub use transport::{ClientTransport, RelayListener};
+
+/// Event emitted by a [`Client`].
+#[derive(Debug)]
+pub enum ClientEvent {
+    /// A relay accepted our reservation.
+    ReservationReqAccepted { relay_peer_id: PeerId, renewal: bool, limit: Option<Limit> },
+    /// A relay did not accept our reservation.
+    ReservationReqFailed {
+        relay_peer_id: PeerId,
+        renewal: bool,
+        error: ProtocolsHandlerUpgrErr<io::Error>,
+    },
+    /// A relay connected us to the requested peer.
+    OutboundCircuitEstablished { relay_peer_id: PeerId, limit: Option<Limit> },
+    /// A relay did not connect us to the requested peer.
+    OutboundCircuitReqFailed {
+        relay_peer_id: PeerId,
+        error: ProtocolsHandlerUpgrErr<io::Error>,
+    },
+    /// A relay relayed a connection from `src_peer_id` to us.
+    InboundCircuitEstablished { src_peer_id: PeerId, limit: Option<Limit> },
+    /// A connection from `src_peer_id` relayed by a relay we do not hold a
+    /// reservation with was denied.
+    InboundCircuitReqDenied { src_peer_id: PeerId },
+}
+
+/// `NetworkBehaviour` acting as a circuit relay v2 client.
+///
+/// Carries out the requests issued by the [`ClientTransport`] it was created
+/// with, see [`Client::new_transport_and_behaviour`].
+pub struct Client {
+    from_transport: mpsc::UnboundedReceiver<TransportToBehaviourMsg>,
+    /// Established connections to relays, or any other peer.
+    connections: HashMap<PeerId, HashSet<ConnectionId>>,
+    /// Listeners of the transport, by relay.
+    listeners: HashMap<PeerId, mpsc::UnboundedSender<ToListenerMsg>>,
+    /// Relays to dial addresses not specifying a relay through and to make
+    /// automatic reservations on, with the address to reach them on.
+    relays: HashMap<PeerId, Multiaddr>,
+    /// Listener on `/p2p-circuit`, accepting the connections relayed by the
+    /// relays in `auto_reservations`.
+    auto_listener: Option<mpsc::UnboundedSender<ToListenerMsg>>,
+    /// Relays we requested or hold an automatic reservation on.
+    auto_reservations: HashSet<PeerId>,
+    /// Number of automatic reservations to hold while private.
+    max_auto_reservations: usize,
+    /// Whether the local node is not publicly reachable.
+    is_private: bool,
+    /// Dials of the transport waiting for the relay to establish a circuit.
+    pending_dials: HashMap<u64, oneshot::Sender<Result<Connection, io::Error>>>,
+    /// Requests waiting for a connection to the relay, together with the
+    /// address the relay is dialed on.
+    waiting_for_connection: HashMap<PeerId, (Multiaddr, Vec<handler::In>)>,
+    next_request_id: u64,
+    queued_actions: VecDeque<NetworkBehaviourAction<handler::In, ClientEvent>>,
+}
+
+impl Client {
+    /// Creates a [`ClientTransport`] together with the `Client` behaviour
+    /// carrying out its requests.
+    ///
+    /// The transport is to be combined with another transport able to reach
+    /// the relays, e.g. via [`Transport::or_transport`](libp2p_core::Transport::or_transport),
+    /// before the connection upgrades are applied.
+    pub fn new_transport_and_behaviour() -> (ClientTransport, Self) {
+        let (transport, from_transport) = ClientTransport::new();
+        let behaviour = Client {
+            from_transport,
+            connections: HashMap::new(),
+            listeners: HashMap::new(),
+            relays: HashMap::new(),
+            auto_listener: None,
+            auto_reservations: HashSet::new(),
+            max_auto_reservations: 2,
+            is_private: false,
+            pending_dials: HashMap::new(),
+            waiting_for_connection: HashMap::new(),
+            next_request_id: 0,
+            queued_actions: VecDeque::new(),
+        };
+        (transport, behaviour)
+    }
+
+    /// Adds a relay to establish circuits through when dialing an address of
+    /// the form `/p2p-circuit/p2p/<dst-id>`, which does not specify a relay.
+    ///
+    /// The relay is also a candidate for automatic reservations, see
+    /// [`Client::set_private`]. Candidates can be discovered e.g. from the
+    /// protocols a peer reports via identify, looking for
+    /// [`HOP_PROTOCOL_NAME`](crate::HOP_PROTOCOL_NAME), or from the providers
+    /// of a well-known Kademlia key.
+    ///
+    /// `addr` may be empty if the relay can be reached by its peer ID alone.
+    pub fn add_relay(&mut self, relay_peer_id: PeerId, addr: Multiaddr) {
+        self.relays.insert(relay_peer_id, addr);
+        self.update_auto_reservations();
+    }
+
+    /// Removes a relay previously added with [`Client::add_relay`],
+    /// cancelling an automatic reservation on it.
+    pub fn remove_relay(&mut self, relay_peer_id: &PeerId) {
+        self.relays.remove(relay_peer_id);
+        if self.auto_reservations.contains(relay_peer_id) {
+            self.cancel_auto_reservation(relay_peer_id);
+            self.update_auto_reservations();
+        }
+    }
+
+    /// Sets whether the local node is publicly unreachable, e.g. as reported
+    /// by AutoNAT.
+    ///
+    /// While private and listening on `/p2p-circuit`, reservations are made
+    /// automatically on up to [`Client::set_max_auto_reservations`] of the
+    /// relays added with [`Client::add_relay`]. The reservations are renewed
+    /// before they expire and their `<relay-addr>/p2p/<relay-id>/p2p-circuit`
+    /// addresses are reported as listen addresses of the `/p2p-circuit`
+    /// listener. Once public again, the reservations are cancelled.
+    pub fn set_private(&mut self, is_private: bool) {
+        self.is_private = is_private;
+        self.update_auto_reservations();
+    }
+
+    /// Sets the number of automatic reservations to hold while private.
+    ///
+    /// Defaults to 2.
+    pub fn set_max_auto_reservations(&mut self, max: usize) {
+        self.max_auto_reservations = max;
+        self.update_auto_reservations();
+    }
+
+    /// Makes or cancels automatic reservations until their number matches
+    /// the number wanted.
+    fn update_auto_reservations(&mut self) {
+        let wanted = match self.auto_listener {
+            Some(_) if self.is_private => self.max_auto_reservations,
+            _ => 0,
+        };
+
+        while self.auto_reservations.len() > wanted {
+            let relay_peer_id = self.auto_reservations.iter().next().cloned()
+                .expect("There is at least one reservation.");
+            self.cancel_auto_reservation(&relay_peer_id);
+        }
+
+        while self.auto_reservations.len() < wanted {
+            let is_candidate = |peer: &&PeerId| {
+                !self.auto_reservations.contains(*peer) && !self.listeners.contains_key(*peer)
+            };
+            // Prefer relays we are already connected to.
+            let candidate = self.relays.keys()
+                .filter(is_candidate)
+                .find(|peer| self.connections.contains_key(*peer))
+                .or_else(|| self.relays.keys().find(is_candidate))
+                .cloned();
+            let relay_peer_id = match candidate {
+                Some(peer) => peer,
+                None => break,
+            };
+            let relay_addr = self.relays[&relay_peer_id].clone();
+            self.auto_reservations.insert(relay_peer_id.clone());
+            self.request(relay_peer_id, relay_addr, handler::In::Reserve);
+        }
+    }
+
+    /// Cancels the automatic reservation on the relay.
+    fn cancel_auto_reservation(&mut self, relay_peer_id: &PeerId) {
+        self.auto_reservations.remove(relay_peer_id);
+        self.auto_reservation_lost(relay_peer_id);
+
+        if let Some((_, waiting)) = self.waiting_for_connection.get_mut(relay_peer_id) {
+            waiting.retain(|event| !matches!(event, handler::In::Reserve));
+        }
+        if self.connections.contains_key(relay_peer_id) {
+            self.queued_actions.push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id: relay_peer_id.clone(),
+                handler: NotifyHandler::All,
+                event: handler::In::CancelReservation,
+            });
+        }
+    }
+
+    /// Informs the `/p2p-circuit` listener that the automatic reservation on
+    /// the relay is gone.
+    fn auto_reservation_lost(&mut self, relay_peer_id: &PeerId) {
+        if let Some(listener) = &self.auto_listener {
+            let msg = ToListenerMsg::AutoReservationLost { relay_peer_id: relay_peer_id.clone() };
+            if listener.unbounded_send(msg).is_err() {
+                self.auto_listener = None;
+            }
+        }
+    }
+
+    /// Chooses a relay for a dial not specifying one.
+    ///
+    /// Relays we hold a reservation with are preferred, followed by added
+    /// relays we are connected to, followed by any other added relay.
+    fn select_relay(&self) -> Option<(PeerId, Multiaddr)> {
+        let address = |peer: &PeerId| self.relays.get(peer).cloned().unwrap_or_else(Multiaddr::empty);
+
+        if let Some(peer) = self.listeners.keys().find(|p| self.connections.contains_key(*p)) {
+            return Some((peer.clone(), address(peer)))
+        }
+        if let Some(peer) = self.relays.keys().find(|p| self.connections.contains_key(*p)) {
+            return Some((peer.clone(), address(peer)))
+        }
+        self.relays.iter().next().map(|(peer, addr)| (peer.clone(), addr.clone()))
+    }
+
+    /// Sends the request to a handler of the relay, dialing the relay first if
+    /// we are not connected to it.
+    fn request(&mut self, relay_peer_id: PeerId, relay_addr: Multiaddr, event: handler::In) {
+        if let Some(connection) = self.connections.get(&relay_peer_id).and_then(|c| c.iter().next()) {
+            self.queued_actions.push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id: relay_peer_id,
+                handler: NotifyHandler::One(*connection),
+                event,
+            });
+            return;
+        }
+
+        let (_, waiting) = self.waiting_for_connection
+            .entry(relay_peer_id.clone())
+            .or_insert_with(|| (relay_addr.clone(), Vec::new()));
+        waiting.push(event);
+        if waiting.len() > 1 {
+            // The relay is already being dialed.
+            return;
+        }
+
+        if relay_addr.iter().next().is_none() {
+            self.queued_actions.push_back(NetworkBehaviourAction::DialPeer {
+                peer_id: relay_peer_id,
+                condition: DialPeerCondition::Disconnected,
+            });
+        } else {
+            self.queued_actions.push_back(NetworkBehaviourAction::DialAddress { address: relay_addr });
+        }
+    }
+
+    /// Fails all requests waiting for a connection to the relay.
+    fn fail_waiting(&mut self, relay_peer_id: &PeerId, error: &dyn error::Error) {
+        let (_, waiting) = match self.waiting_for_connection.remove(relay_peer_id) {
+            Some(waiting) => waiting,
+            None => return,
+        };
+
+        for event in waiting {
+            let error = io::Error::new(io::ErrorKind::Other, error.to_string());
+            match event {
+                handler::In::Reserve => {
+                    if let Some(listener) = self.listeners.remove(relay_peer_id) {
+                        let _ = listener.unbounded_send(ToListenerMsg::Reservation(Err(error)));
+                    } else if self.auto_reservations.remove(relay_peer_id) {
+                        log::debug!("Failed to connect to relay {}: {}", relay_peer_id, error);
+                        self.relays.remove(relay_peer_id);
+                        self.auto_reservation_lost(relay_peer_id);
+                    }
+                }
+                handler::In::CancelReservation => {}
+                handler::In::EstablishCircuit { request_id, .. } => {
+                    if let Some(send_back) = self.pending_dials.remove(&request_id) {
+                        let _ = send_back.send(Err(error));
+                    }
+                }
+            }
+        }
+
+        self.update_auto_reservations();
+    }
+
+    fn on_transport_msg(&mut self, msg: TransportToBehaviourMsg) {
+        match msg {
+            TransportToBehaviourMsg::ListenReq { relay_peer_id, relay_addr, to_listener } => {
+                if self.auto_reservations.remove(&relay_peer_id) {
+                    // The reservation is taken over by the new listener.
+                    self.auto_reservation_lost(&relay_peer_id);
+                }
+                self.listeners.insert(relay_peer_id.clone(), to_listener);
+                self.request(relay_peer_id, relay_addr, handler::In::Reserve);
+                self.update_auto_reservations();
+            }
+            TransportToBehaviourMsg::AutoListenReq { to_listener } => {
+                for relay_peer_id in self.auto_reservations.clone() {
+                    self.cancel_auto_reservation(&relay_peer_id);
+                }
+                self.auto_listener = Some(to_listener);
+                self.update_auto_reservations();
+            }
+            TransportToBehaviourMsg::DialReq { relay_peer_id, relay_addr, dst_peer_id, send_back } => {
+                let (relay_peer_id, relay_addr) = match relay_peer_id {
+                    Some(relay_peer_id) => (relay_peer_id, relay_addr),
+                    None => match self.select_relay() {
+                        Some(relay) => relay,
+                        None => {
+                            let _ = send_back.send(Err(io::Error::new(io::ErrorKind::Other, "No relay to dial through")));
+                            return;
+                        }
+                    },
+                };
+                let request_id = self.next_request_id;
+                self.next_request_id += 1;
+                self.pending_dials.insert(request_id, send_back);
+                self.request(relay_peer_id, relay_addr, handler::In::EstablishCircuit { request_id, dst_peer_id });
+            }
+        }
+    }
+}
+
+impl NetworkBehaviour for Client {
+    type ProtocolsHandler = Handler;
+    type OutEvent = ClientEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        Handler::new()
+    }
+
+    fn addresses_of_peer(&mut self, peer: &PeerId) -> Vec<Multiaddr> {
+        self.waiting_for_connection.get(peer)
+            .filter(|(addr, _)| addr.iter().next().is_some())
+            .map(|(addr, _)| vec![addr.clone()])
+            .unwrap_or_default()
+    }
+
+    fn inject_connected(&mut self, _: &PeerId) {}
+
+    fn inject_disconnected(&mut self, _: &PeerId) {}
+
+    fn inject_connection_established(&mut self, peer: &PeerId, connection: &ConnectionId, _: &ConnectedPoint) {
+        self.connections.entry(peer.clone()).or_default().insert(*connection);
+
+        if let Some((_, waiting)) = self.waiting_for_connection.remove(peer) {
+            for event in waiting {
+                self.queued_actions.push_back(NetworkBehaviourAction::NotifyHandler {
+                    peer_id: peer.clone(),
+                    handler: NotifyHandler::One(*connection),
+                    event,
+                });
+            }
+        }
+    }
+
+    fn inject_connection_closed(&mut self, peer: &PeerId, connection: &ConnectionId, _: &ConnectedPoint) {
+        if let Some(connections) = self.connections.get_mut(peer) {
+            connections.remove(connection);
+            if connections.is_empty() {
+                self.connections.remove(peer);
+                if self.auto_reservations.remove(peer) {
+                    // The reservation is gone with the connection, make a new one.
+                    self.auto_reservation_lost(peer);
+                    self.update_auto_reservations();
+                }
+            }
+        }
+    }
+
+    fn inject_addr_reach_failure(&mut self, peer: Option<&PeerId>, addr: &Multiaddr, error: &dyn error::Error) {
+        let relay_peer_id = match peer {
+            Some(peer) => peer.clone(),
+            None => match self.waiting_for_connection.iter().find(|(_, (a, _))| a == addr) {
+                Some((peer, _)) => peer.clone(),
+                None => return,
+            },
+        };
+        self.fail_waiting(&relay_peer_id, error);
+    }
+
+    fn inject_dial_failure(&mut self, peer: &PeerId) {
+        let error = io::Error::new(io::ErrorKind::Other, "Failed to dial relay");
+        self.fail_waiting(peer, &error);
+    }
+
+    fn inject_event(&mut self, relay_peer_id: PeerId, _: ConnectionId, event: handler::Event) {
+        let event = match event {
+            handler::Event::ReservationReqAccepted { renewal, limit } => {
+                if let Some(listener) = self.listeners.get(&relay_peer_id) {
+                    if listener.unbounded_send(ToListenerMsg::Reservation(Ok(()))).is_err() {
+                        self.listeners.remove(&relay_peer_id);
+                    }
+                } else if self.auto_reservations.contains(&relay_peer_id) {
+                    if let Some(listener) = &self.auto_listener {
+                        let listen_addr = self.relays.get(&relay_peer_id)
+                            .cloned()
+                            .unwrap_or_else(Multiaddr::empty)
+                            .with(Protocol::P2p(relay_peer_id.clone().into()))
+                            .with(Protocol::P2pCircuit);
+                        let msg = ToListenerMsg::AutoReservationAccepted {
+                            relay_peer_id: relay_peer_id.clone(),
+                            listen_addr,
+                        };
+                        if listener.unbounded_send(msg).is_err() {
+                            self.auto_listener = None;
+                            self.update_auto_reservations();
+                        }
+                    }
+                }
+                ClientEvent::ReservationReqAccepted { relay_peer_id, renewal, limit }
+            }
+            handler::Event::ReservationReqFailed { renewal, error } => {
+                if let Some(listener) = self.listeners.remove(&relay_peer_id) {
+                    let e = io::Error::new(io::ErrorKind::Other, error.to_string());
+                    let _ = listener.unbounded_send(ToListenerMsg::Reservation(Err(e)));
+                } else if self.auto_reservations.remove(&relay_peer_id) {
+                    // Do not retry a relay refusing our reservation.
+                    self.relays.remove(&relay_peer_id);
+                    self.auto_reservation_lost(&relay_peer_id);
+                    self.update_auto_reservations();
+                }
+                ClientEvent::ReservationReqFailed { relay_peer_id, renewal, error }
+            }
+            handler::Event::OutboundCircuitEstablished { request_id, connection, limit } => {
+                if let Some(send_back) = self.pending_dials.remove(&request_id) {
+                    let _ = send_back.send(Ok(connection));
+                }
+                ClientEvent::OutboundCircuitEstablished { relay_peer_id, limit }
+            }
+            handler::Event::OutboundCircuitReqFailed { request_id, error } => {
+                if let Some(send_back) = self.pending_dials.remove(&request_id) {
+                    let e = io::Error::new(io::ErrorKind::Other, error.to_string());
+                    let _ = send_back.send(Err(e));
+                }
+                ClientEvent::OutboundCircuitReqFailed { relay_peer_id, error }
+            }
+            handler::Event::InboundCircuitEstablished { src_peer_id, connection, limit } => {
+                let msg = ToListenerMsg::IncomingRelayedConnection {
+                    connection: Box::new(connection),
+                    src_peer_id: src_peer_id.clone(),
+                    relay_peer_id: relay_peer_id.clone(),
+                };
+                if let Some(listener) = self.listeners.get(&relay_peer_id) {
+                    if listener.unbounded_send(msg).is_err() {
+                        self.listeners.remove(&relay_peer_id);
+                    }
+                } else if let (Some(listener), true) =
+                    (&self.auto_listener, self.auto_reservations.contains(&relay_peer_id))
+                {
+                    if listener.unbounded_send(msg).is_err() {
+                        self.auto_listener = None;
+                        self.update_auto_reservations();
+                    }
+                } else {
+                    log::debug!("Dropping circuit from {}: not listening via {}", src_peer_id, relay_peer_id);
+                }
+                ClientEvent::InboundCircuitEstablished { src_peer_id, limit }
+            }
+            handler::Event::InboundCircuitReqDenied { src_peer_id } => {
+                ClientEvent::InboundCircuitReqDenied { src_peer_id }
+            }
+        };
+
+        self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(event));
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>, _: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<handler::In, Self::OutEvent>>
+    {
+        while let Poll::Ready(Some(msg)) = self.from_transport.poll_next_unpin(cx) {
+            self.on_transport_msg(msg);
+        }
+
+        if let Some(action) = self.queued_actions.pop_front() {
+            return Poll::Ready(action);
+        }
+
+        Poll::Pending
+    }
+}
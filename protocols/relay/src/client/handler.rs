@@ -0,0 +1,261 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::client::transport::Connection;
+use crate::message_proto::Status;
+use crate::protocol::{inbound_stop, outbound_hop, Limit};
+use futures::{future::BoxFuture, prelude::*, stream::FuturesUnordered};
+use libp2p_core::PeerId;
+use libp2p_swarm::{
+    KeepAlive,
+    ProtocolsHandler,
+    ProtocolsHandlerEvent,
+    ProtocolsHandlerUpgrErr,
+    SubstreamProtocol,
+};
+use std::{collections::VecDeque, io, sync::Arc, task::{Context, Poll}, time::Duration};
+use wasm_timer::{Delay, Instant};
+
+/// Time a connection without reservation and circuits is kept alive.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Event sent from the [`Client`](super::Client) behaviour to a [`Handler`].
+#[derive(Debug, Clone)]
+pub enum In {
+    /// Make a reservation on the relay and keep renewing it.
+    Reserve,
+    /// Stop renewing the reservation on the relay.
+    CancelReservation,
+    /// Ask the relay to connect us to `dst_peer_id`.
+    EstablishCircuit { request_id: u64, dst_peer_id: PeerId },
+}
+
+/// Event produced by a [`Handler`].
+pub enum Event {
+    /// The relay accepted our reservation.
+    ReservationReqAccepted { renewal: bool, limit: Option<Limit> },
+    /// The relay did not accept our reservation.
+    ReservationReqFailed { renewal: bool, error: ProtocolsHandlerUpgrErr<io::Error> },
+    /// The relay connected us to the requested peer.
+    OutboundCircuitEstablished { request_id: u64, connection: Connection, limit: Option<Limit> },
+    /// The relay did not connect us to the requested peer.
+    OutboundCircuitReqFailed { request_id: u64, error: ProtocolsHandlerUpgrErr<io::Error> },
+    /// The relay relayed a connection from `src_peer_id` to us.
+    InboundCircuitEstablished { src_peer_id: PeerId, connection: Connection, limit: Option<Limit> },
+    /// A circuit from `src_peer_id` was denied, as we do not hold a reservation.
+    InboundCircuitReqDenied { src_peer_id: PeerId },
+}
+
+/// Information attached to an outbound `hop` substream request.
+#[derive(Debug)]
+pub enum OutboundOpenInfo {
+    Reserve { renewal: bool },
+    Connect { request_id: u64 },
+}
+
+/// State of our reservation on the relay.
+enum Reservation {
+    None,
+    /// A reservation request is in flight.
+    Requested,
+    /// The relay accepted the reservation, which is renewed once the timer fires.
+    Accepted { renewal_timeout: Delay },
+}
+
+/// Protocol handler of the relay client, sending `hop` requests to the relay
+/// and handling `stop` requests of the relay.
+pub struct Handler {
+    queued_events: VecDeque<ProtocolsHandlerEvent<outbound_hop::Upgrade, OutboundOpenInfo, Event, io::Error>>,
+    reservation: Reservation,
+    /// Answers to inbound circuit requests being sent.
+    inbound_circuits: FuturesUnordered<BoxFuture<'static, Option<Event>>>,
+    /// Shared with every [`Connection`] relayed over this connection.
+    circuits: Arc<()>,
+    keep_alive: KeepAlive,
+}
+
+impl Handler {
+    pub fn new() -> Self {
+        Handler {
+            queued_events: VecDeque::new(),
+            reservation: Reservation::None,
+            inbound_circuits: FuturesUnordered::new(),
+            circuits: Arc::new(()),
+            keep_alive: KeepAlive::Until(Instant::now() + IDLE_TIMEOUT),
+        }
+    }
+
+    fn request_reservation(&mut self, renewal: bool) {
+        self.reservation = Reservation::Requested;
+        self.queued_events.push_back(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+            protocol: SubstreamProtocol::new(outbound_hop::Upgrade::Reserve),
+            info: OutboundOpenInfo::Reserve { renewal },
+        });
+    }
+}
+
+impl Default for Handler {
+    fn default() -> Self {
+        Handler::new()
+    }
+}
+
+impl ProtocolsHandler for Handler {
+    type InEvent = In;
+    type OutEvent = Event;
+    type Error = io::Error;
+    type InboundProtocol = inbound_stop::Upgrade;
+    type OutboundProtocol = outbound_hop::Upgrade;
+    type OutboundOpenInfo = OutboundOpenInfo;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol> {
+        SubstreamProtocol::new(inbound_stop::Upgrade)
+    }
+
+    fn inject_fully_negotiated_inbound(&mut self, circuit: inbound_stop::Circuit) {
+        let src_peer_id = circuit.src_peer_id().clone();
+
+        if let Reservation::Accepted { .. } = self.reservation {
+            let limit = circuit.limit();
+            let guard = self.circuits.clone();
+            self.inbound_circuits.push(async move {
+                match circuit.accept().await {
+                    Ok(stream) => Some(Event::InboundCircuitEstablished {
+                        src_peer_id,
+                        connection: Connection::new(stream, guard),
+                        limit,
+                    }),
+                    Err(e) => {
+                        log::debug!("Failed to accept circuit from {}: {:?}", src_peer_id, e);
+                        None
+                    }
+                }
+            }.boxed());
+        } else {
+            self.inbound_circuits.push(async move {
+                if let Err(e) = circuit.deny(Status::NoReservation).await {
+                    log::debug!("Failed to deny circuit from {}: {:?}", src_peer_id, e);
+                }
+                Some(Event::InboundCircuitReqDenied { src_peer_id })
+            }.boxed());
+        }
+    }
+
+    fn inject_fully_negotiated_outbound(&mut self, output: outbound_hop::Output, info: OutboundOpenInfo) {
+        let event = match (output, info) {
+            (outbound_hop::Output::Reservation { .. }, OutboundOpenInfo::Reserve { .. })
+                if matches!(self.reservation, Reservation::None) =>
+            {
+                log::debug!("Reservation accepted after it was cancelled, not renewing it");
+                return;
+            }
+            (outbound_hop::Output::Reservation { expire_in, addrs, limit }, OutboundOpenInfo::Reserve { renewal }) => {
+                log::debug!("Reservation accepted, reachable via {:?}", addrs);
+                // Renew well before the reservation expires.
+                self.reservation = Reservation::Accepted {
+                    renewal_timeout: Delay::new(expire_in * 3 / 4),
+                };
+                Event::ReservationReqAccepted { renewal, limit }
+            }
+            (outbound_hop::Output::Circuit { substream, limit }, OutboundOpenInfo::Connect { request_id }) => {
+                Event::OutboundCircuitEstablished {
+                    request_id,
+                    connection: Connection::new(substream, self.circuits.clone()),
+                    limit,
+                }
+            }
+            _ => unreachable!("The upgrade output always matches the request it was opened for."),
+        };
+
+        self.queued_events.push_back(ProtocolsHandlerEvent::Custom(event));
+    }
+
+    fn inject_event(&mut self, event: In) {
+        match event {
+            In::Reserve => {
+                if let Reservation::Requested = self.reservation {
+                    return;
+                }
+                let renewal = matches!(self.reservation, Reservation::Accepted { .. });
+                self.request_reservation(renewal);
+            }
+            In::CancelReservation => self.reservation = Reservation::None,
+            In::EstablishCircuit { request_id, dst_peer_id } => {
+                self.queued_events.push_back(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                    protocol: SubstreamProtocol::new(outbound_hop::Upgrade::Connect { dst_peer_id }),
+                    info: OutboundOpenInfo::Connect { request_id },
+                });
+            }
+        }
+    }
+
+    fn inject_dial_upgrade_error(&mut self, info: OutboundOpenInfo, error: ProtocolsHandlerUpgrErr<io::Error>) {
+        let event = match info {
+            OutboundOpenInfo::Reserve { renewal } => {
+                self.reservation = Reservation::None;
+                Event::ReservationReqFailed { renewal, error }
+            }
+            OutboundOpenInfo::Connect { request_id } => {
+                Event::OutboundCircuitReqFailed { request_id, error }
+            }
+        };
+
+        self.queued_events.push_back(ProtocolsHandlerEvent::Custom(event));
+    }
+
+    fn inject_listen_upgrade_error(&mut self, error: ProtocolsHandlerUpgrErr<io::Error>) {
+        log::debug!("Failed to read inbound stop request: {:?}", error);
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        self.keep_alive
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<
+        ProtocolsHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::OutEvent, Self::Error>
+    > {
+        if let Reservation::Accepted { renewal_timeout } = &mut self.reservation {
+            if renewal_timeout.poll_unpin(cx).is_ready() {
+                self.request_reservation(true);
+            }
+        }
+
+        if let Some(event) = self.queued_events.pop_front() {
+            return Poll::Ready(event);
+        }
+
+        while let Poll::Ready(Some(event)) = self.inbound_circuits.poll_next_unpin(cx) {
+            if let Some(event) = event {
+                return Poll::Ready(ProtocolsHandlerEvent::Custom(event));
+            }
+        }
+
+        let active = !matches!(self.reservation, Reservation::None)
+            || !self.inbound_circuits.is_empty()
+            || Arc::strong_count(&self.circuits) > 1;
+        if active {
+            self.keep_alive = KeepAlive::Yes;
+        } else if self.keep_alive.is_yes() {
+            self.keep_alive = KeepAlive::Until(Instant::now() + IDLE_TIMEOUT);
+        }
+
+        Poll::Pending
+    }
+}
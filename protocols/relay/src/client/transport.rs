@@ -0,0 +1,328 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::{
+    channel::{mpsc, oneshot},
+    future::{self, BoxFuture},
+    prelude::*,
+    ready,
+};
+use libp2p_core::{
+    multiaddr::{Multiaddr, Protocol},
+    transport::{ListenerEvent, TransportError},
+    PeerId,
+    Transport,
+};
+use libp2p_swarm::NegotiatedSubstream;
+use std::{collections::HashMap, io, pin::Pin, sync::Arc, task::{Context, Poll}};
+
+/// A [`Transport`] listening and dialing via relays.
+///
+/// Only addresses containing `/p2p-circuit` are supported:
+///
+/// - Listening on `<relay-addr>/p2p/<relay-id>/p2p-circuit` makes a
+///   reservation on the relay and accepts connections relayed by it.
+/// - Dialing `<relay-addr>/p2p/<relay-id>/p2p-circuit/p2p/<dst-id>` asks the
+///   relay to connect us to the destination peer.
+/// - Listening on `/p2p-circuit` accepts connections relayed by the relays
+///   the [`Client`](super::Client) makes reservations on automatically, see
+///   [`Client::set_private`](super::Client::set_private).
+///
+/// The `<relay-addr>` part may be omitted if the [`Swarm`](libp2p_swarm::Swarm)
+/// is able to reach the relay by its peer ID alone. When dialing, the relay
+/// may be omitted altogether, i.e. `/p2p-circuit/p2p/<dst-id>`, in which case
+/// a relay we hold a reservation with or one added with
+/// [`Client::add_relay`](super::Client::add_relay) is used.
+///
+/// The transport only works together with the [`Client`](super::Client)
+/// behaviour it was created with.
+#[derive(Clone)]
+pub struct ClientTransport {
+    to_behaviour: mpsc::UnboundedSender<TransportToBehaviourMsg>,
+}
+
+impl ClientTransport {
+    pub(crate) fn new() -> (Self, mpsc::UnboundedReceiver<TransportToBehaviourMsg>) {
+        let (to_behaviour, from_transport) = mpsc::unbounded();
+        (ClientTransport { to_behaviour }, from_transport)
+    }
+}
+
+impl Transport for ClientTransport {
+    type Output = Connection;
+    type Error = io::Error;
+    type Listener = RelayListener;
+    type ListenerUpgrade = future::Ready<Result<Self::Output, Self::Error>>;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let (to_listener, from_behaviour) = mpsc::unbounded();
+        let msg = match parse_relayed_multiaddr(&addr)? {
+            RelayedMultiaddr { relay_peer_id: Some(relay_peer_id), relay_addr, dst_peer_id: None } =>
+                TransportToBehaviourMsg::ListenReq { relay_peer_id, relay_addr, to_listener },
+            RelayedMultiaddr { relay_peer_id: None, relay_addr, dst_peer_id: None }
+                if relay_addr.iter().next().is_none() =>
+                TransportToBehaviourMsg::AutoListenReq { to_listener },
+            _ => return Err(TransportError::MultiaddrNotSupported(addr)),
+        };
+
+        self.to_behaviour
+            .unbounded_send(msg)
+            .map_err(|_| TransportError::Other(behaviour_dropped()))?;
+
+        Ok(RelayListener {
+            listen_addr: addr,
+            from_behaviour,
+            is_announced: false,
+            auto_addrs: HashMap::new(),
+        })
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let (relay_peer_id, relay_addr, dst_peer_id) = match parse_relayed_multiaddr(&addr)? {
+            RelayedMultiaddr {
+                relay_peer_id: Some(relay_peer_id),
+                relay_addr,
+                dst_peer_id: Some(dst_peer_id),
+            } => (Some(relay_peer_id), relay_addr, dst_peer_id),
+            RelayedMultiaddr {
+                relay_peer_id: None,
+                relay_addr,
+                dst_peer_id: Some(dst_peer_id),
+            } if relay_addr.iter().next().is_none() => (None, relay_addr, dst_peer_id),
+            _ => return Err(TransportError::MultiaddrNotSupported(addr)),
+        };
+
+        let (send_back, rx) = oneshot::channel();
+        self.to_behaviour
+            .unbounded_send(TransportToBehaviourMsg::DialReq { relay_peer_id, relay_addr, dst_peer_id, send_back })
+            .map_err(|_| TransportError::Other(behaviour_dropped()))?;
+
+        Ok(async move { rx.await.map_err(|_| behaviour_dropped())? }.boxed())
+    }
+}
+
+fn behaviour_dropped() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "Relay client behaviour was dropped")
+}
+
+/// The components of an address containing `/p2p-circuit`.
+struct RelayedMultiaddr {
+    /// Address of the relay, without the trailing `/p2p/<relay-id>`.
+    relay_addr: Multiaddr,
+    relay_peer_id: Option<PeerId>,
+    dst_peer_id: Option<PeerId>,
+}
+
+fn parse_relayed_multiaddr(addr: &Multiaddr) -> Result<RelayedMultiaddr, TransportError<io::Error>> {
+    if !addr.iter().any(|p| p == Protocol::P2pCircuit) {
+        return Err(TransportError::MultiaddrNotSupported(addr.clone()));
+    }
+
+    let mut relayed = RelayedMultiaddr {
+        relay_addr: Multiaddr::empty(),
+        relay_peer_id: None,
+        dst_peer_id: None,
+    };
+    let mut before_circuit = true;
+
+    for protocol in addr.iter() {
+        match protocol {
+            Protocol::P2pCircuit if before_circuit => before_circuit = false,
+            Protocol::P2p(hash) if relayed.relay_peer_id.is_none() || !before_circuit => {
+                let peer_id = PeerId::from_multihash(hash)
+                    .map_err(|_| TransportError::MultiaddrNotSupported(addr.clone()))?;
+                if before_circuit {
+                    relayed.relay_peer_id = Some(peer_id);
+                } else if relayed.dst_peer_id.replace(peer_id).is_some() {
+                    return Err(TransportError::MultiaddrNotSupported(addr.clone()));
+                }
+            }
+            p if before_circuit && relayed.relay_peer_id.is_none() => relayed.relay_addr.push(p),
+            _ => return Err(TransportError::MultiaddrNotSupported(addr.clone())),
+        }
+    }
+
+    Ok(relayed)
+}
+
+/// Message sent from the [`ClientTransport`] to the [`Client`](super::Client) behaviour.
+pub(crate) enum TransportToBehaviourMsg {
+    /// Make a reservation on the relay and forward relayed connections to the listener.
+    ListenReq {
+        relay_peer_id: PeerId,
+        relay_addr: Multiaddr,
+        to_listener: mpsc::UnboundedSender<ToListenerMsg>,
+    },
+    /// Forward connections relayed by the relays reservations are made on
+    /// automatically to the listener.
+    AutoListenReq {
+        to_listener: mpsc::UnboundedSender<ToListenerMsg>,
+    },
+    /// Establish a circuit to `dst_peer_id` via the relay, or via any known
+    /// relay if `relay_peer_id` is `None`.
+    DialReq {
+        relay_peer_id: Option<PeerId>,
+        relay_addr: Multiaddr,
+        dst_peer_id: PeerId,
+        send_back: oneshot::Sender<Result<Connection, io::Error>>,
+    },
+}
+
+/// Message sent from the [`Client`](super::Client) behaviour to a [`RelayListener`].
+pub(crate) enum ToListenerMsg {
+    Reservation(Result<(), io::Error>),
+    /// An automatic reservation was accepted, making us reachable via `listen_addr`.
+    AutoReservationAccepted { relay_peer_id: PeerId, listen_addr: Multiaddr },
+    /// An automatic reservation was cancelled or could not be renewed.
+    AutoReservationLost { relay_peer_id: PeerId },
+    IncomingRelayedConnection { connection: Box<Connection>, src_peer_id: PeerId, relay_peer_id: PeerId },
+}
+
+/// Listener of a [`ClientTransport`], yielding the connections relayed by a
+/// single relay, or by the relays reservations are made on automatically if
+/// listening on `/p2p-circuit`.
+pub struct RelayListener {
+    listen_addr: Multiaddr,
+    from_behaviour: mpsc::UnboundedReceiver<ToListenerMsg>,
+    /// Whether the listen address has been reported, i.e. whether a first
+    /// reservation has been accepted.
+    is_announced: bool,
+    /// The reported listen addresses of the automatic reservations, by relay.
+    auto_addrs: HashMap<PeerId, Multiaddr>,
+}
+
+impl Stream for RelayListener {
+    type Item = Result<ListenerEvent<future::Ready<Result<Connection, io::Error>>, io::Error>, io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match ready!(self.from_behaviour.poll_next_unpin(cx)) {
+                Some(ToListenerMsg::Reservation(Ok(()))) => {
+                    if !self.is_announced {
+                        self.is_announced = true;
+                        return Poll::Ready(Some(Ok(ListenerEvent::NewAddress(self.listen_addr.clone()))));
+                    }
+                }
+                Some(ToListenerMsg::Reservation(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Some(ToListenerMsg::AutoReservationAccepted { relay_peer_id, listen_addr }) => {
+                    if self.auto_addrs.insert(relay_peer_id, listen_addr.clone()).is_none() {
+                        return Poll::Ready(Some(Ok(ListenerEvent::NewAddress(listen_addr))));
+                    }
+                }
+                Some(ToListenerMsg::AutoReservationLost { relay_peer_id }) => {
+                    if let Some(listen_addr) = self.auto_addrs.remove(&relay_peer_id) {
+                        return Poll::Ready(Some(Ok(ListenerEvent::AddressExpired(listen_addr))));
+                    }
+                }
+                Some(ToListenerMsg::IncomingRelayedConnection { connection, src_peer_id, relay_peer_id }) => {
+                    let local_addr = self.auto_addrs.get(&relay_peer_id)
+                        .cloned()
+                        .unwrap_or_else(|| self.listen_addr.clone());
+                    let remote_addr = local_addr.clone().with(Protocol::P2p(src_peer_id.into()));
+                    return Poll::Ready(Some(Ok(ListenerEvent::Upgrade {
+                        upgrade: future::ok(*connection),
+                        local_addr,
+                        remote_addr,
+                    })));
+                }
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+/// A connection relayed over a circuit.
+pub struct Connection {
+    stream: NegotiatedSubstream,
+    /// Keeps the connection to the relay alive while the circuit is in use.
+    _circuit: Arc<()>,
+}
+
+impl Connection {
+    pub(crate) fn new(stream: NegotiatedSubstream, circuit: Arc<()>) -> Self {
+        Connection { stream, _circuit: circuit }
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_listen_addr() {
+        let relay = PeerId::random();
+        let addr: Multiaddr = format!("/memory/1234/p2p/{}/p2p-circuit", relay).parse().unwrap();
+        let relayed = parse_relayed_multiaddr(&addr).ok().unwrap();
+        assert_eq!(relayed.relay_addr, "/memory/1234".parse().unwrap());
+        assert_eq!(relayed.relay_peer_id, Some(relay));
+        assert_eq!(relayed.dst_peer_id, None);
+    }
+
+    #[test]
+    fn parse_dial_addr() {
+        let relay = PeerId::random();
+        let dst = PeerId::random();
+        let addr: Multiaddr = format!("/p2p/{}/p2p-circuit/p2p/{}", relay, dst).parse().unwrap();
+        let relayed = parse_relayed_multiaddr(&addr).ok().unwrap();
+        assert_eq!(relayed.relay_addr, Multiaddr::empty());
+        assert_eq!(relayed.relay_peer_id, Some(relay));
+        assert_eq!(relayed.dst_peer_id, Some(dst));
+    }
+
+    #[test]
+    fn parse_dial_addr_without_relay() {
+        let dst = PeerId::random();
+        let addr: Multiaddr = format!("/p2p-circuit/p2p/{}", dst).parse().unwrap();
+        let relayed = parse_relayed_multiaddr(&addr).ok().unwrap();
+        assert_eq!(relayed.relay_addr, Multiaddr::empty());
+        assert_eq!(relayed.relay_peer_id, None);
+        assert_eq!(relayed.dst_peer_id, Some(dst));
+    }
+
+    #[test]
+    fn reject_non_relayed_addr() {
+        let addr: Multiaddr = "/memory/1234".parse().unwrap();
+        assert!(parse_relayed_multiaddr(&addr).is_err());
+
+        let addr: Multiaddr = "/memory/1234/p2p-circuit/memory/1234".parse().unwrap();
+        assert!(parse_relayed_multiaddr(&addr).is_err());
+    }
+}
@@ -0,0 +1,64 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Implementation of the [circuit relay v2
+//! protocol](https://github.com/libp2p/specs/blob/master/relay/circuit-v2.md).
+//!
+//! Circuit relays allow peers that cannot be dialed directly, e.g. because
+//! they are behind a NAT, to be reached through a publicly reachable relay.
+//!
+//! # Relay server
+//!
+//! The [`Relay`] network behaviour serves the `hop` protocol. Peers first
+//! reserve a slot on the relay, after which other peers can ask the relay to
+//! open a circuit to them. The relay then connects the two over the `stop`
+//! protocol and relays data between them. The number of reservations and
+//! circuits, as well as the duration and amount of data of each circuit, are
+//! limited according to the [`RelayConfig`].
+//!
+//! # Client
+//!
+//! [`client::Client::new_transport_and_behaviour`] creates a
+//! [`ClientTransport`](client::ClientTransport) together with the
+//! [`Client`](client::Client) network behaviour carrying out its requests.
+//!
+//! - Listening on `<relay-addr>/p2p/<relay-id>/p2p-circuit` makes a
+//!   reservation on the relay, renews it before it expires and yields the
+//!   connections relayed to us.
+//! - Dialing `<relay-addr>/p2p/<relay-id>/p2p-circuit/p2p/<dst-id>` asks the
+//!   relay to open a circuit to the destination.
+//! - Listening on `/p2p-circuit` makes reservations automatically on some of
+//!   the relays known to the [`Client`](client::Client) while the local node
+//!   is not publicly reachable, e.g. as reported by AutoNAT.
+//!
+//! The relayed connections are plain byte streams, which are to be upgraded
+//! with an encryption and multiplexing protocol like any other connection.
+
+pub mod client;
+mod protocol;
+pub mod relay;
+
+mod message_proto {
+    include!(concat!(env!("OUT_DIR"), "/message_v2.pb.rs"));
+}
+
+pub use message_proto::Status;
+pub use protocol::{Limit, HOP_PROTOCOL_NAME, STOP_PROTOCOL_NAME};
+pub use relay::{CircuitId, Relay, RelayConfig, RelayEvent};
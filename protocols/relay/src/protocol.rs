@@ -0,0 +1,116 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Wire protocol of circuit relay v2, split into its two sub-protocols.
+//!
+//! The `hop` protocol is spoken between a client and the relay, both to
+//! reserve a slot on the relay and to ask the relay to connect to another peer.
+//! The `stop` protocol is spoken between the relay and the destination of a
+//! circuit.
+
+use crate::message_proto;
+use libp2p_core::upgrade::{self, ReadOneError};
+use libp2p_swarm::NegotiatedSubstream;
+use std::{error, io, time::Duration};
+
+pub mod inbound_hop;
+pub mod inbound_stop;
+pub mod outbound_hop;
+pub mod outbound_stop;
+
+/// Protocol name of the `hop` protocol.
+pub const HOP_PROTOCOL_NAME: &[u8] = b"/libp2p/circuit/relay/0.2.0/hop";
+/// Protocol name of the `stop` protocol.
+pub const STOP_PROTOCOL_NAME: &[u8] = b"/libp2p/circuit/relay/0.2.0/stop";
+
+/// Maximum size of a single protocol message.
+const MAX_MESSAGE_SIZE: usize = 4096;
+
+/// Limits the relay applies to a circuit, as announced by the relay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit {
+    duration: Option<Duration>,
+    data_in_bytes: Option<u64>,
+}
+
+impl Limit {
+    /// Maximum duration of a circuit, if any.
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// Maximum number of bytes relayed in each direction of a circuit, if any.
+    pub fn data_in_bytes(&self) -> Option<u64> {
+        self.data_in_bytes
+    }
+}
+
+impl From<message_proto::Limit> for Limit {
+    fn from(limit: message_proto::Limit) -> Self {
+        Limit {
+            duration: limit.duration.map(|d| Duration::from_secs(d.into())),
+            data_in_bytes: limit.data,
+        }
+    }
+}
+
+/// Builds the wire representation of the limits of a circuit.
+fn limit(max_circuit_duration: Duration, max_circuit_bytes: u64) -> message_proto::Limit {
+    message_proto::Limit {
+        duration: Some(max_circuit_duration.as_secs().min(u64::from(u32::MAX)) as u32),
+        data: Some(max_circuit_bytes),
+    }
+}
+
+/// Writes a length-prefixed protobuf message to the substream.
+async fn send<M: prost::Message>(substream: &mut NegotiatedSubstream, message: M) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(message.encoded_len());
+    message.encode(&mut buf).expect("Vec<u8> provides capacity as needed");
+    upgrade::write_with_len_prefix(substream, buf).await
+}
+
+/// Reads a length-prefixed protobuf message from the substream.
+///
+/// Only the bytes of the message are consumed, any data following it is left
+/// on the substream.
+async fn recv<M: prost::Message + Default>(substream: &mut NegotiatedSubstream) -> io::Result<M> {
+    let buf = upgrade::read_one(substream, MAX_MESSAGE_SIZE).await
+        .map_err(|e| match e {
+            ReadOneError::Io(e) => e,
+            e => invalid_data(e),
+        })?;
+    if buf.is_empty() {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    M::decode(&buf[..]).map_err(invalid_data)
+}
+
+fn invalid_data(e: impl Into<Box<dyn error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Error returned when the remote answers a request with a non-`OK` status.
+fn denied(status: Option<i32>) -> io::Error {
+    let status = status.and_then(message_proto::Status::from_i32);
+    io::Error::new(
+        io::ErrorKind::ConnectionRefused,
+        format!("Request denied by remote with status {:?}", status),
+    )
+}
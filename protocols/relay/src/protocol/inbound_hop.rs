@@ -0,0 +1,182 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Relay side of the `hop` protocol.
+
+use crate::message_proto::{hop_message, HopMessage, Reservation, Status};
+use crate::protocol::{self, HOP_PROTOCOL_NAME};
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::{upgrade, Multiaddr, PeerId};
+use libp2p_swarm::NegotiatedSubstream;
+use std::{io, iter, time::Duration};
+use wasm_timer::{SystemTime, UNIX_EPOCH};
+
+/// Upgrade reading the initial `hop` message sent by a client.
+#[derive(Debug, Clone)]
+pub struct Upgrade {
+    pub reservation_duration: Duration,
+    pub max_circuit_duration: Duration,
+    pub max_circuit_bytes: u64,
+}
+
+impl upgrade::UpgradeInfo for Upgrade {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(HOP_PROTOCOL_NAME)
+    }
+}
+
+impl upgrade::InboundUpgrade<NegotiatedSubstream> for Upgrade {
+    type Output = Req;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, mut substream: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+        async move {
+            let HopMessage { r#type, peer, .. } = protocol::recv(&mut substream).await?;
+
+            match hop_message::Type::from_i32(r#type) {
+                Some(hop_message::Type::Reserve) => Ok(Req::Reserve(ReservationReq {
+                    substream,
+                    reservation_duration: self.reservation_duration,
+                    max_circuit_duration: self.max_circuit_duration,
+                    max_circuit_bytes: self.max_circuit_bytes,
+                })),
+                Some(hop_message::Type::Connect) => {
+                    let peer = peer.ok_or_else(|| protocol::invalid_data("Missing destination peer"))?;
+                    let dst = PeerId::from_bytes(peer.id)
+                        .map_err(|_| protocol::invalid_data("Invalid destination peer id"))?;
+                    Ok(Req::Connect(CircuitReq {
+                        dst,
+                        substream,
+                        max_circuit_duration: self.max_circuit_duration,
+                        max_circuit_bytes: self.max_circuit_bytes,
+                    }))
+                }
+                Some(hop_message::Type::Status) | None => {
+                    Err(protocol::invalid_data("Unexpected hop message type"))
+                }
+            }
+        }.boxed()
+    }
+}
+
+/// Request received on an inbound `hop` substream.
+pub enum Req {
+    /// The remote wants to reserve a slot on the relay.
+    Reserve(ReservationReq),
+    /// The remote wants the relay to connect it to another peer.
+    Connect(CircuitReq),
+}
+
+/// Pending reservation request of a client.
+pub struct ReservationReq {
+    substream: NegotiatedSubstream,
+    reservation_duration: Duration,
+    max_circuit_duration: Duration,
+    max_circuit_bytes: u64,
+}
+
+impl ReservationReq {
+    /// Accepts the reservation, announcing the given relay addresses to the client.
+    pub async fn accept(self, addrs: Vec<Multiaddr>) -> io::Result<()> {
+        let expire = (SystemTime::now() + self.reservation_duration)
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+            .as_secs();
+
+        let message = HopMessage {
+            r#type: hop_message::Type::Status.into(),
+            peer: None,
+            reservation: Some(Reservation {
+                expire,
+                addrs: addrs.into_iter().map(|a| a.to_vec()).collect(),
+                voucher: None,
+            }),
+            limit: Some(protocol::limit(self.max_circuit_duration, self.max_circuit_bytes)),
+            status: Some(Status::Ok.into()),
+        };
+
+        self.send(message).await
+    }
+
+    /// Denies the reservation with the given status.
+    pub async fn deny(self, status: Status) -> io::Result<()> {
+        let message = HopMessage {
+            r#type: hop_message::Type::Status.into(),
+            peer: None,
+            reservation: None,
+            limit: None,
+            status: Some(status.into()),
+        };
+
+        self.send(message).await
+    }
+
+    async fn send(mut self, message: HopMessage) -> io::Result<()> {
+        protocol::send(&mut self.substream, message).await?;
+        self.substream.close().await
+    }
+}
+
+/// Pending request of a client to be connected to another peer.
+pub struct CircuitReq {
+    dst: PeerId,
+    substream: NegotiatedSubstream,
+    max_circuit_duration: Duration,
+    max_circuit_bytes: u64,
+}
+
+impl CircuitReq {
+    /// The peer the client wants to be connected to.
+    pub fn dst(&self) -> &PeerId {
+        &self.dst
+    }
+
+    /// Accepts the request, returning the substream to relay data on.
+    pub async fn accept(mut self) -> io::Result<NegotiatedSubstream> {
+        let message = HopMessage {
+            r#type: hop_message::Type::Status.into(),
+            peer: None,
+            reservation: None,
+            limit: Some(protocol::limit(self.max_circuit_duration, self.max_circuit_bytes)),
+            status: Some(Status::Ok.into()),
+        };
+
+        protocol::send(&mut self.substream, message).await?;
+        Ok(self.substream)
+    }
+
+    /// Denies the request with the given status.
+    pub async fn deny(mut self, status: Status) -> io::Result<()> {
+        let message = HopMessage {
+            r#type: hop_message::Type::Status.into(),
+            peer: None,
+            reservation: None,
+            limit: None,
+            status: Some(status.into()),
+        };
+
+        protocol::send(&mut self.substream, message).await?;
+        self.substream.close().await
+    }
+}
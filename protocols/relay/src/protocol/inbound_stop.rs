@@ -0,0 +1,109 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Destination side of the `stop` protocol.
+
+use crate::message_proto::{stop_message, Status, StopMessage};
+use crate::protocol::{self, Limit, STOP_PROTOCOL_NAME};
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::{upgrade, PeerId};
+use libp2p_swarm::NegotiatedSubstream;
+use std::{io, iter};
+
+/// Upgrade reading the request of a relay to accept a relayed connection.
+#[derive(Debug, Clone)]
+pub struct Upgrade;
+
+impl upgrade::UpgradeInfo for Upgrade {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(STOP_PROTOCOL_NAME)
+    }
+}
+
+impl upgrade::InboundUpgrade<NegotiatedSubstream> for Upgrade {
+    type Output = Circuit;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, mut substream: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+        async move {
+            let StopMessage { r#type, peer, limit, .. } = protocol::recv(&mut substream).await?;
+
+            if stop_message::Type::from_i32(r#type) != Some(stop_message::Type::Connect) {
+                return Err(protocol::invalid_data("Unexpected stop message type"));
+            }
+
+            let peer = peer.ok_or_else(|| protocol::invalid_data("Missing source peer"))?;
+            let src_peer_id = PeerId::from_bytes(peer.id)
+                .map_err(|_| protocol::invalid_data("Invalid source peer id"))?;
+
+            Ok(Circuit {
+                substream,
+                src_peer_id,
+                limit: limit.map(Into::into),
+            })
+        }.boxed()
+    }
+}
+
+/// Pending request of a relay to accept a relayed connection.
+pub struct Circuit {
+    substream: NegotiatedSubstream,
+    src_peer_id: PeerId,
+    limit: Option<Limit>,
+}
+
+impl Circuit {
+    /// The peer the relayed connection originates from.
+    pub fn src_peer_id(&self) -> &PeerId {
+        &self.src_peer_id
+    }
+
+    /// The limits the relay applies to the circuit.
+    pub fn limit(&self) -> Option<Limit> {
+        self.limit
+    }
+
+    /// Accepts the circuit, returning the substream carrying the relayed connection.
+    pub async fn accept(mut self) -> io::Result<NegotiatedSubstream> {
+        self.send(Status::Ok).await?;
+        Ok(self.substream)
+    }
+
+    /// Denies the circuit with the given status.
+    pub async fn deny(mut self, status: Status) -> io::Result<()> {
+        self.send(status).await?;
+        self.substream.close().await
+    }
+
+    async fn send(&mut self, status: Status) -> io::Result<()> {
+        let message = StopMessage {
+            r#type: stop_message::Type::Status.into(),
+            peer: None,
+            limit: None,
+            status: Some(status.into()),
+        };
+
+        protocol::send(&mut self.substream, message).await
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Client side of the `hop` protocol.
+
+use crate::message_proto::{hop_message, HopMessage, Peer, Status};
+use crate::protocol::{self, Limit, HOP_PROTOCOL_NAME};
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::{upgrade, Multiaddr, PeerId};
+use libp2p_swarm::NegotiatedSubstream;
+use std::{convert::TryFrom, io, iter, time::Duration};
+use wasm_timer::{SystemTime, UNIX_EPOCH};
+
+/// Upgrade sending a request to a relay.
+#[derive(Debug, Clone)]
+pub enum Upgrade {
+    /// Reserve a slot on the relay, or renew an existing reservation.
+    Reserve,
+    /// Ask the relay to connect us to `dst_peer_id`.
+    Connect { dst_peer_id: PeerId },
+}
+
+/// Successful outcome of an [`Upgrade`].
+pub enum Output {
+    /// The relay accepted the reservation.
+    Reservation {
+        /// Time after which the reservation expires.
+        expire_in: Duration,
+        /// Addresses of the relay through which we can be reached.
+        addrs: Vec<Multiaddr>,
+        /// Limits applied to each circuit.
+        limit: Option<Limit>,
+    },
+    /// The relay established a circuit to the destination.
+    Circuit {
+        /// Substream carrying the relayed connection.
+        substream: NegotiatedSubstream,
+        /// Limits applied to the circuit.
+        limit: Option<Limit>,
+    },
+}
+
+impl upgrade::UpgradeInfo for Upgrade {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(HOP_PROTOCOL_NAME)
+    }
+}
+
+impl upgrade::OutboundUpgrade<NegotiatedSubstream> for Upgrade {
+    type Output = Output;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, mut substream: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+        let message = match &self {
+            Upgrade::Reserve => HopMessage {
+                r#type: hop_message::Type::Reserve.into(),
+                peer: None,
+                reservation: None,
+                limit: None,
+                status: None,
+            },
+            Upgrade::Connect { dst_peer_id } => HopMessage {
+                r#type: hop_message::Type::Connect.into(),
+                peer: Some(Peer {
+                    id: dst_peer_id.as_bytes().to_vec(),
+                    addrs: vec![],
+                }),
+                reservation: None,
+                limit: None,
+                status: None,
+            },
+        };
+
+        async move {
+            protocol::send(&mut substream, message).await?;
+            let HopMessage { r#type, reservation, limit, status, .. } =
+                protocol::recv(&mut substream).await?;
+
+            if hop_message::Type::from_i32(r#type) != Some(hop_message::Type::Status) {
+                return Err(protocol::invalid_data("Unexpected hop message type"));
+            }
+            if status != Some(Status::Ok.into()) {
+                return Err(protocol::denied(status));
+            }
+
+            let limit = limit.map(Into::into);
+
+            match self {
+                Upgrade::Reserve => {
+                    let reservation = reservation
+                        .ok_or_else(|| protocol::invalid_data("Missing reservation"))?;
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                    let expire_in = Duration::from_secs(reservation.expire)
+                        .checked_sub(now)
+                        .ok_or_else(|| protocol::invalid_data("Reservation expired"))?;
+                    let addrs = reservation.addrs.into_iter()
+                        .map(Multiaddr::try_from)
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(protocol::invalid_data)?;
+
+                    substream.close().await?;
+
+                    Ok(Output::Reservation { expire_in, addrs, limit })
+                }
+                Upgrade::Connect { .. } => Ok(Output::Circuit { substream, limit }),
+            }
+        }.boxed()
+    }
+}
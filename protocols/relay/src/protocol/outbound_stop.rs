@@ -0,0 +1,80 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Relay side of the `stop` protocol.
+
+use crate::message_proto::{stop_message, Peer, Status, StopMessage};
+use crate::protocol::{self, STOP_PROTOCOL_NAME};
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::{upgrade, PeerId};
+use libp2p_swarm::NegotiatedSubstream;
+use std::{io, iter, time::Duration};
+
+/// Upgrade asking the destination of a circuit to accept a relayed
+/// connection from `src_peer_id`.
+///
+/// On success, the substream is ready to relay data on.
+#[derive(Debug, Clone)]
+pub struct Upgrade {
+    pub src_peer_id: PeerId,
+    pub max_circuit_duration: Duration,
+    pub max_circuit_bytes: u64,
+}
+
+impl upgrade::UpgradeInfo for Upgrade {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(STOP_PROTOCOL_NAME)
+    }
+}
+
+impl upgrade::OutboundUpgrade<NegotiatedSubstream> for Upgrade {
+    type Output = NegotiatedSubstream;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, mut substream: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+        let message = StopMessage {
+            r#type: stop_message::Type::Connect.into(),
+            peer: Some(Peer {
+                id: self.src_peer_id.into_bytes(),
+                addrs: vec![],
+            }),
+            limit: Some(protocol::limit(self.max_circuit_duration, self.max_circuit_bytes)),
+            status: None,
+        };
+
+        async move {
+            protocol::send(&mut substream, message).await?;
+            let StopMessage { r#type, status, .. } = protocol::recv(&mut substream).await?;
+
+            if stop_message::Type::from_i32(r#type) != Some(stop_message::Type::Status) {
+                return Err(protocol::invalid_data("Unexpected stop message type"));
+            }
+            if status != Some(Status::Ok.into()) {
+                return Err(protocol::denied(status));
+            }
+
+            Ok(substream)
+        }.boxed()
+    }
+}
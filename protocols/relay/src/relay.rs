@@ -0,0 +1,471 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The relay server role of the circuit relay v2 protocol.
+
+pub mod handler;
+
+use crate::message_proto::Status;
+use crate::protocol::inbound_hop;
+use futures::{future::{self, BoxFuture, Either}, prelude::*, stream::FuturesUnordered};
+use handler::{CircuitGuard, Handler};
+use libp2p_core::{
+    connection::ConnectionId,
+    multiaddr::Protocol,
+    ConnectedPoint,
+    Multiaddr,
+    PeerId,
+};
+use libp2p_swarm::{
+    NegotiatedSubstream,
+    NetworkBehaviour,
+    NetworkBehaviourAction,
+    NotifyHandler,
+    PollParameters,
+    ProtocolsHandlerUpgrErr,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    task::{Context, Poll},
+    time::Duration,
+};
+use wasm_timer::Delay;
+
+/// Configuration of a [`Relay`].
+#[derive(Debug, Clone)]
+pub struct RelayConfig {
+    max_reservations: usize,
+    max_reservations_per_peer: usize,
+    reservation_duration: Duration,
+    max_circuits: usize,
+    max_circuits_per_peer: usize,
+    max_circuit_duration: Duration,
+    max_circuit_bytes: u64,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        RelayConfig {
+            max_reservations: 128,
+            max_reservations_per_peer: 4,
+            reservation_duration: Duration::from_secs(60 * 60),
+            max_circuits: 16,
+            max_circuits_per_peer: 4,
+            max_circuit_duration: Duration::from_secs(2 * 60),
+            max_circuit_bytes: 1 << 17, // 128 kibibyte
+        }
+    }
+}
+
+impl RelayConfig {
+    /// Sets the maximum number of reservations held by the relay.
+    pub fn set_max_reservations(&mut self, v: usize) -> &mut Self {
+        self.max_reservations = v;
+        self
+    }
+
+    /// Sets the maximum number of reservations a single peer can hold,
+    /// one per connection.
+    pub fn set_max_reservations_per_peer(&mut self, v: usize) -> &mut Self {
+        self.max_reservations_per_peer = v;
+        self
+    }
+
+    /// Sets the time after which a reservation expires unless renewed.
+    pub fn set_reservation_duration(&mut self, v: Duration) -> &mut Self {
+        self.reservation_duration = v;
+        self
+    }
+
+    /// Sets the maximum number of circuits relayed at the same time.
+    pub fn set_max_circuits(&mut self, v: usize) -> &mut Self {
+        self.max_circuits = v;
+        self
+    }
+
+    /// Sets the maximum number of circuits a single peer can have opened
+    /// at the same time.
+    pub fn set_max_circuits_per_peer(&mut self, v: usize) -> &mut Self {
+        self.max_circuits_per_peer = v;
+        self
+    }
+
+    /// Sets the time after which a circuit is closed.
+    pub fn set_max_circuit_duration(&mut self, v: Duration) -> &mut Self {
+        self.max_circuit_duration = v;
+        self
+    }
+
+    /// Sets the number of bytes relayed in each direction of a circuit
+    /// before the circuit is closed.
+    pub fn set_max_circuit_bytes(&mut self, v: u64) -> &mut Self {
+        self.max_circuit_bytes = v;
+        self
+    }
+}
+
+/// Identifier of a circuit relayed by a [`Relay`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CircuitId(u64);
+
+/// Event emitted by a [`Relay`].
+#[derive(Debug)]
+pub enum RelayEvent {
+    /// A reservation has been accepted.
+    ReservationReqAccepted { src_peer_id: PeerId, renewed: bool },
+    /// Sending the acceptance of a reservation failed.
+    ReservationReqAcceptFailed { src_peer_id: PeerId, error: io::Error },
+    /// A reservation has been denied.
+    ReservationReqDenied { src_peer_id: PeerId },
+    /// Sending the denial of a reservation failed.
+    ReservationReqDenyFailed { src_peer_id: PeerId, error: io::Error },
+    /// A reservation expired without being renewed.
+    ReservationTimedOut { src_peer_id: PeerId },
+    /// A circuit request has been denied.
+    CircuitReqDenied { src_peer_id: PeerId, dst_peer_id: PeerId },
+    /// The destination of a circuit request did not accept the circuit.
+    CircuitReqOutboundConnectFailed {
+        src_peer_id: PeerId,
+        dst_peer_id: PeerId,
+        error: ProtocolsHandlerUpgrErr<io::Error>,
+    },
+    /// A circuit has been accepted and is now relaying data.
+    CircuitReqAccepted { src_peer_id: PeerId, dst_peer_id: PeerId },
+    /// A circuit has been closed, either because one side closed it, because
+    /// it reached its limits, or because of an error.
+    CircuitClosed { src_peer_id: PeerId, dst_peer_id: PeerId, error: Option<io::Error> },
+}
+
+/// `NetworkBehaviour` acting as a circuit relay v2 server.
+///
+/// Peers can reserve a slot on the relay, after which other peers can ask
+/// the relay to connect them to the reserving peer. Both reservations and
+/// circuits are limited according to the [`RelayConfig`].
+pub struct Relay {
+    local_peer_id: PeerId,
+    config: RelayConfig,
+    /// Addresses the relay listens on, announced in reservations.
+    listen_addrs: Vec<Multiaddr>,
+    /// Confirmed external addresses of the relay, announced in reservations
+    /// ahead of the listen addresses.
+    external_addrs: Vec<Multiaddr>,
+    /// Connections over which peers hold a reservation.
+    reservations: HashMap<PeerId, HashSet<ConnectionId>>,
+    /// Circuits that are being established or are relaying data.
+    circuits: HashMap<CircuitId, Circuit>,
+    /// Circuit requests waiting for the destination to accept the circuit.
+    pending_circuit_reqs: HashMap<CircuitId, (inbound_hop::CircuitReq, CircuitGuard)>,
+    /// Circuits relaying data as well as denials of circuit requests being sent.
+    circuit_futures: FuturesUnordered<CircuitFuture>,
+    next_circuit_id: u64,
+    queued_actions: VecDeque<NetworkBehaviourAction<handler::In, RelayEvent>>,
+}
+
+/// Future relaying a circuit, or denying a circuit request in which case it
+/// yields `None`.
+type CircuitFuture = BoxFuture<'static, Option<(CircuitId, io::Result<()>)>>;
+
+struct Circuit {
+    src_peer_id: PeerId,
+    src_connection_id: ConnectionId,
+    dst_peer_id: PeerId,
+    dst_connection_id: ConnectionId,
+}
+
+impl Relay {
+    /// Creates a new `Relay` behaviour.
+    pub fn new(local_peer_id: PeerId, config: RelayConfig) -> Self {
+        Relay {
+            local_peer_id,
+            config,
+            listen_addrs: Vec::new(),
+            external_addrs: Vec::new(),
+            reservations: HashMap::new(),
+            circuits: HashMap::new(),
+            pending_circuit_reqs: HashMap::new(),
+            circuit_futures: FuturesUnordered::new(),
+            next_circuit_id: 0,
+            queued_actions: VecDeque::new(),
+        }
+    }
+
+    fn handle_reservation_req(&mut self, peer: PeerId, connection: ConnectionId, renewed: bool) {
+        let num_reservations: usize = self.reservations.values().map(HashSet::len).sum();
+        let num_peer_reservations = self.reservations.get(&peer).map_or(0, HashSet::len);
+
+        let event = if !renewed && (
+            num_reservations >= self.config.max_reservations
+                || num_peer_reservations >= self.config.max_reservations_per_peer
+        ) {
+            handler::In::DenyReservationReq { status: Status::ResourceLimitExceeded }
+        } else {
+            let local_peer_id = self.local_peer_id.clone();
+            let addrs = self.external_addrs.iter()
+                .chain(self.listen_addrs.iter())
+                .map(|a| a.clone().with(Protocol::P2p(local_peer_id.clone().into())))
+                .collect();
+            handler::In::AcceptReservationReq { addrs }
+        };
+
+        self.queued_actions.push_back(NetworkBehaviourAction::NotifyHandler {
+            peer_id: peer,
+            handler: NotifyHandler::One(connection),
+            event,
+        });
+    }
+
+    fn handle_circuit_req(
+        &mut self,
+        src_peer_id: PeerId,
+        src_connection_id: ConnectionId,
+        req: inbound_hop::CircuitReq,
+        guard: CircuitGuard,
+    ) {
+        let dst_peer_id = req.dst().clone();
+        let num_src_circuits = self.circuits.values()
+            .filter(|c| c.src_peer_id == src_peer_id)
+            .count();
+
+        let status = if self.circuits.len() >= self.config.max_circuits
+            || num_src_circuits >= self.config.max_circuits_per_peer
+        {
+            Status::ResourceLimitExceeded
+        } else if let Some(dst_connection_id) = self.reservations.get(&dst_peer_id)
+            .and_then(|connections| connections.iter().next())
+            .copied()
+        {
+            let circuit_id = CircuitId(self.next_circuit_id);
+            self.next_circuit_id += 1;
+            self.circuits.insert(circuit_id, Circuit {
+                src_peer_id: src_peer_id.clone(),
+                src_connection_id,
+                dst_peer_id: dst_peer_id.clone(),
+                dst_connection_id,
+            });
+            self.pending_circuit_reqs.insert(circuit_id, (req, guard));
+            self.queued_actions.push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id: dst_peer_id,
+                handler: NotifyHandler::One(dst_connection_id),
+                event: handler::In::NegotiateOutboundConnect { circuit_id, src_peer_id },
+            });
+            return;
+        } else {
+            Status::NoReservation
+        };
+
+        self.deny_circuit_req(req, guard, status);
+        self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+            RelayEvent::CircuitReqDenied { src_peer_id, dst_peer_id }
+        ));
+    }
+
+    fn deny_circuit_req(&mut self, req: inbound_hop::CircuitReq, guard: CircuitGuard, status: Status) {
+        self.circuit_futures.push(async move {
+            if let Err(e) = req.deny(status).await {
+                log::debug!("Failed to deny circuit request: {:?}", e);
+            }
+            drop(guard);
+            None
+        }.boxed());
+    }
+}
+
+impl NetworkBehaviour for Relay {
+    type ProtocolsHandler = Handler;
+    type OutEvent = RelayEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        Handler::new(handler::Config {
+            reservation_duration: self.config.reservation_duration,
+            max_circuit_duration: self.config.max_circuit_duration,
+            max_circuit_bytes: self.config.max_circuit_bytes,
+        })
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, _: &PeerId) {}
+
+    fn inject_disconnected(&mut self, _: &PeerId) {}
+
+    fn inject_connection_closed(&mut self, peer: &PeerId, connection: &ConnectionId, _: &ConnectedPoint) {
+        if let Some(connections) = self.reservations.get_mut(peer) {
+            connections.remove(connection);
+            if connections.is_empty() {
+                self.reservations.remove(peer);
+            }
+        }
+
+        // Circuits still being established over the closed connection are
+        // abandoned. Circuits already relaying data end on their own once the
+        // substreams of the connection fail.
+        let pending_circuit_reqs = &mut self.pending_circuit_reqs;
+        self.circuits.retain(|id, c| {
+            let closed = (&c.src_peer_id == peer && &c.src_connection_id == connection)
+                || (&c.dst_peer_id == peer && &c.dst_connection_id == connection);
+            !(closed && pending_circuit_reqs.remove(id).is_some())
+        });
+    }
+
+    fn inject_new_listen_addr(&mut self, addr: &Multiaddr) {
+        self.listen_addrs.push(addr.clone());
+    }
+
+    fn inject_expired_listen_addr(&mut self, addr: &Multiaddr) {
+        self.listen_addrs.retain(|a| a != addr);
+    }
+
+    fn inject_new_external_addr(&mut self, addr: &Multiaddr) {
+        if !self.external_addrs.contains(addr) {
+            self.external_addrs.push(addr.clone());
+        }
+    }
+
+    fn inject_event(&mut self, peer: PeerId, connection: ConnectionId, event: handler::Event) {
+        match event {
+            handler::Event::ReservationReqReceived { renewed } => {
+                self.handle_reservation_req(peer, connection, renewed);
+            }
+            handler::Event::ReservationReqAccepted { renewed } => {
+                self.reservations.entry(peer.clone()).or_default().insert(connection);
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    RelayEvent::ReservationReqAccepted { src_peer_id: peer, renewed }
+                ));
+            }
+            handler::Event::ReservationReqAcceptFailed { error } => {
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    RelayEvent::ReservationReqAcceptFailed { src_peer_id: peer, error }
+                ));
+            }
+            handler::Event::ReservationReqDenied => {
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    RelayEvent::ReservationReqDenied { src_peer_id: peer }
+                ));
+            }
+            handler::Event::ReservationReqDenyFailed { error } => {
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    RelayEvent::ReservationReqDenyFailed { src_peer_id: peer, error }
+                ));
+            }
+            handler::Event::ReservationTimedOut => {
+                if let Some(connections) = self.reservations.get_mut(&peer) {
+                    connections.remove(&connection);
+                    if connections.is_empty() {
+                        self.reservations.remove(&peer);
+                    }
+                }
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    RelayEvent::ReservationTimedOut { src_peer_id: peer }
+                ));
+            }
+            handler::Event::CircuitReqReceived { req, guard } => {
+                self.handle_circuit_req(peer, connection, req, guard);
+            }
+            handler::Event::OutboundConnectNegotiated { circuit_id, src_peer_id, dst_stream, guard } => {
+                let (req, src_guard) = match self.pending_circuit_reqs.remove(&circuit_id) {
+                    Some(pending) => pending,
+                    None => return,
+                };
+                let max_circuit_duration = self.config.max_circuit_duration;
+                let max_circuit_bytes = self.config.max_circuit_bytes;
+                self.circuit_futures.push(async move {
+                    let result = match req.accept().await {
+                        Ok(src_stream) => relay(
+                            src_stream,
+                            dst_stream,
+                            max_circuit_duration,
+                            max_circuit_bytes,
+                        ).await,
+                        Err(e) => Err(e),
+                    };
+                    drop((src_guard, guard));
+                    Some((circuit_id, result))
+                }.boxed());
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    RelayEvent::CircuitReqAccepted { src_peer_id, dst_peer_id: peer }
+                ));
+            }
+            handler::Event::OutboundConnectNegotiationFailed { circuit_id, src_peer_id, error } => {
+                self.circuits.remove(&circuit_id);
+                if let Some((req, guard)) = self.pending_circuit_reqs.remove(&circuit_id) {
+                    self.deny_circuit_req(req, guard, Status::ConnectionFailed);
+                }
+                self.queued_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    RelayEvent::CircuitReqOutboundConnectFailed { src_peer_id, dst_peer_id: peer, error }
+                ));
+            }
+        }
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>, _: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<handler::In, Self::OutEvent>>
+    {
+        if let Some(action) = self.queued_actions.pop_front() {
+            return Poll::Ready(action);
+        }
+
+        while let Poll::Ready(Some(outcome)) = self.circuit_futures.poll_next_unpin(cx) {
+            if let Some((circuit_id, result)) = outcome {
+                if let Some(circuit) = self.circuits.remove(&circuit_id) {
+                    return Poll::Ready(NetworkBehaviourAction::GenerateEvent(
+                        RelayEvent::CircuitClosed {
+                            src_peer_id: circuit.src_peer_id,
+                            dst_peer_id: circuit.dst_peer_id,
+                            error: result.err(),
+                        }
+                    ));
+                }
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+/// Relays data between the two substreams of a circuit until both sides
+/// closed their write half, or until the circuit reached its limits.
+async fn relay(
+    src: NegotiatedSubstream,
+    dst: NegotiatedSubstream,
+    max_duration: Duration,
+    max_bytes: u64,
+) -> io::Result<()> {
+    let (src_read, mut src_write) = src.split();
+    let (dst_read, mut dst_write) = dst.split();
+
+    let src_to_dst = async move {
+        futures::io::copy(src_read.take(max_bytes), &mut dst_write).await?;
+        dst_write.close().await
+    };
+    let dst_to_src = async move {
+        futures::io::copy(dst_read.take(max_bytes), &mut src_write).await?;
+        src_write.close().await
+    };
+    let transfer = future::try_join(src_to_dst, dst_to_src);
+
+    match future::select(transfer.boxed(), Delay::new(max_duration)).await {
+        Either::Left((result, _)) => result.map(|_| ()),
+        // The circuit reached its maximum duration.
+        Either::Right(_) => Ok(()),
+    }
+}
@@ -0,0 +1,277 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::message_proto::Status;
+use crate::protocol::{inbound_hop, outbound_stop};
+use crate::relay::CircuitId;
+use futures::{future::BoxFuture, prelude::*};
+use libp2p_core::{Multiaddr, PeerId};
+use libp2p_swarm::{
+    KeepAlive,
+    NegotiatedSubstream,
+    ProtocolsHandler,
+    ProtocolsHandlerEvent,
+    ProtocolsHandlerUpgrErr,
+    SubstreamProtocol,
+};
+use std::{collections::VecDeque, io, sync::Arc, task::{Context, Poll}, time::Duration};
+use wasm_timer::{Delay, Instant};
+
+/// Time a connection without reservation and circuits is kept alive.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Configuration of a [`Handler`].
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub reservation_duration: Duration,
+    pub max_circuit_duration: Duration,
+    pub max_circuit_bytes: u64,
+}
+
+/// Event sent from the [`Relay`](crate::Relay) behaviour to a [`Handler`].
+#[derive(Debug, Clone)]
+pub enum In {
+    /// Accept the pending reservation request of the remote.
+    AcceptReservationReq { addrs: Vec<Multiaddr> },
+    /// Deny the pending reservation request of the remote.
+    DenyReservationReq { status: Status },
+    /// Ask the remote to accept a circuit from `src_peer_id`.
+    NegotiateOutboundConnect { circuit_id: CircuitId, src_peer_id: PeerId },
+}
+
+/// Event produced by a [`Handler`].
+pub enum Event {
+    /// The remote asked for a reservation.
+    ReservationReqReceived { renewed: bool },
+    /// The reservation of the remote has been accepted.
+    ReservationReqAccepted { renewed: bool },
+    /// Sending the acceptance of the reservation failed.
+    ReservationReqAcceptFailed { error: io::Error },
+    /// The reservation of the remote has been denied.
+    ReservationReqDenied,
+    /// Sending the denial of the reservation failed.
+    ReservationReqDenyFailed { error: io::Error },
+    /// The reservation of the remote expired without being renewed.
+    ReservationTimedOut,
+    /// The remote asked to be connected to another peer.
+    CircuitReqReceived { req: inbound_hop::CircuitReq, guard: CircuitGuard },
+    /// The remote accepted a circuit from `src_peer_id`.
+    OutboundConnectNegotiated {
+        circuit_id: CircuitId,
+        src_peer_id: PeerId,
+        dst_stream: NegotiatedSubstream,
+        guard: CircuitGuard,
+    },
+    /// The remote did not accept a circuit from `src_peer_id`.
+    OutboundConnectNegotiationFailed {
+        circuit_id: CircuitId,
+        src_peer_id: PeerId,
+        error: ProtocolsHandlerUpgrErr<io::Error>,
+    },
+}
+
+/// Keeps the connection a circuit is relayed over alive for as long as it
+/// is not dropped.
+#[derive(Debug)]
+pub struct CircuitGuard {
+    _circuits: Arc<()>,
+}
+
+/// Pending answer to a reservation request.
+struct ReservationReqAnswer {
+    accepted: bool,
+    future: BoxFuture<'static, io::Result<()>>,
+}
+
+/// Protocol handler of the relay server, handling `hop` requests of the remote
+/// and `stop` requests to the remote.
+pub struct Handler {
+    config: Config,
+    queued_events: VecDeque<ProtocolsHandlerEvent<outbound_stop::Upgrade, (CircuitId, PeerId), Event, io::Error>>,
+    /// Reservation request waiting for a decision of the behaviour.
+    reservation_req: Option<inbound_hop::ReservationReq>,
+    reservation_req_answer: Option<ReservationReqAnswer>,
+    /// Expiration of the reservation of the remote, if any.
+    active_reservation: Option<Delay>,
+    /// Shared with every [`CircuitGuard`] of this connection.
+    circuits: Arc<()>,
+    keep_alive: KeepAlive,
+}
+
+impl Handler {
+    pub fn new(config: Config) -> Self {
+        Handler {
+            config,
+            queued_events: VecDeque::new(),
+            reservation_req: None,
+            reservation_req_answer: None,
+            active_reservation: None,
+            circuits: Arc::new(()),
+            keep_alive: KeepAlive::Until(Instant::now() + IDLE_TIMEOUT),
+        }
+    }
+
+    fn guard(&self) -> CircuitGuard {
+        CircuitGuard { _circuits: self.circuits.clone() }
+    }
+}
+
+impl ProtocolsHandler for Handler {
+    type InEvent = In;
+    type OutEvent = Event;
+    type Error = io::Error;
+    type InboundProtocol = inbound_hop::Upgrade;
+    type OutboundProtocol = outbound_stop::Upgrade;
+    type OutboundOpenInfo = (CircuitId, PeerId);
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol> {
+        SubstreamProtocol::new(inbound_hop::Upgrade {
+            reservation_duration: self.config.reservation_duration,
+            max_circuit_duration: self.config.max_circuit_duration,
+            max_circuit_bytes: self.config.max_circuit_bytes,
+        })
+    }
+
+    fn inject_fully_negotiated_inbound(&mut self, req: inbound_hop::Req) {
+        match req {
+            inbound_hop::Req::Reserve(req) => {
+                if self.reservation_req.replace(req).is_some() {
+                    log::debug!("Dropping previous reservation request in favour of new one.");
+                }
+                self.queued_events.push_back(ProtocolsHandlerEvent::Custom(
+                    Event::ReservationReqReceived { renewed: self.active_reservation.is_some() }
+                ));
+            }
+            inbound_hop::Req::Connect(req) => {
+                let guard = self.guard();
+                self.queued_events.push_back(ProtocolsHandlerEvent::Custom(
+                    Event::CircuitReqReceived { req, guard }
+                ));
+            }
+        }
+    }
+
+    fn inject_fully_negotiated_outbound(
+        &mut self,
+        dst_stream: NegotiatedSubstream,
+        (circuit_id, src_peer_id): Self::OutboundOpenInfo,
+    ) {
+        let guard = self.guard();
+        self.queued_events.push_back(ProtocolsHandlerEvent::Custom(
+            Event::OutboundConnectNegotiated { circuit_id, src_peer_id, dst_stream, guard }
+        ));
+    }
+
+    fn inject_event(&mut self, event: In) {
+        match event {
+            In::AcceptReservationReq { addrs } => {
+                if let Some(req) = self.reservation_req.take() {
+                    self.reservation_req_answer = Some(ReservationReqAnswer {
+                        accepted: true,
+                        future: req.accept(addrs).boxed(),
+                    });
+                }
+            }
+            In::DenyReservationReq { status } => {
+                if let Some(req) = self.reservation_req.take() {
+                    self.reservation_req_answer = Some(ReservationReqAnswer {
+                        accepted: false,
+                        future: req.deny(status).boxed(),
+                    });
+                }
+            }
+            In::NegotiateOutboundConnect { circuit_id, src_peer_id } => {
+                let upgrade = outbound_stop::Upgrade {
+                    src_peer_id: src_peer_id.clone(),
+                    max_circuit_duration: self.config.max_circuit_duration,
+                    max_circuit_bytes: self.config.max_circuit_bytes,
+                };
+                self.queued_events.push_back(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                    protocol: SubstreamProtocol::new(upgrade),
+                    info: (circuit_id, src_peer_id),
+                });
+            }
+        }
+    }
+
+    fn inject_dial_upgrade_error(
+        &mut self,
+        (circuit_id, src_peer_id): Self::OutboundOpenInfo,
+        error: ProtocolsHandlerUpgrErr<io::Error>,
+    ) {
+        self.queued_events.push_back(ProtocolsHandlerEvent::Custom(
+            Event::OutboundConnectNegotiationFailed { circuit_id, src_peer_id, error }
+        ));
+    }
+
+    fn inject_listen_upgrade_error(&mut self, error: ProtocolsHandlerUpgrErr<io::Error>) {
+        log::debug!("Failed to read inbound hop request: {:?}", error);
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        self.keep_alive
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<
+        ProtocolsHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::OutEvent, Self::Error>
+    > {
+        if let Some(event) = self.queued_events.pop_front() {
+            return Poll::Ready(event);
+        }
+
+        if let Some(answer) = self.reservation_req_answer.as_mut() {
+            if let Poll::Ready(result) = answer.future.poll_unpin(cx) {
+                let accepted = answer.accepted;
+                self.reservation_req_answer = None;
+                let event = match (accepted, result) {
+                    (true, Ok(())) => {
+                        let renewed = self.active_reservation
+                            .replace(Delay::new(self.config.reservation_duration))
+                            .is_some();
+                        Event::ReservationReqAccepted { renewed }
+                    }
+                    (true, Err(error)) => Event::ReservationReqAcceptFailed { error },
+                    (false, Ok(())) => Event::ReservationReqDenied,
+                    (false, Err(error)) => Event::ReservationReqDenyFailed { error },
+                };
+                return Poll::Ready(ProtocolsHandlerEvent::Custom(event));
+            }
+        }
+
+        if let Some(delay) = self.active_reservation.as_mut() {
+            if delay.poll_unpin(cx).is_ready() {
+                self.active_reservation = None;
+                return Poll::Ready(ProtocolsHandlerEvent::Custom(Event::ReservationTimedOut));
+            }
+        }
+
+        let active = self.active_reservation.is_some()
+            || self.reservation_req.is_some()
+            || self.reservation_req_answer.is_some()
+            || Arc::strong_count(&self.circuits) > 1;
+        if active {
+            self.keep_alive = KeepAlive::Yes;
+        } else if self.keep_alive.is_yes() {
+            self.keep_alive = KeepAlive::Until(Instant::now() + IDLE_TIMEOUT);
+        }
+
+        Poll::Pending
+    }
+}
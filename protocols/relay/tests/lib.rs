@@ -0,0 +1,351 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Integration tests for the circuit relay v2 client and server.
+
+use futures::{executor::block_on, prelude::*};
+use libp2p_core::{
+    identity,
+    multiaddr::{Multiaddr, Protocol},
+    muxing::StreamMuxerBox,
+    transport::{boxed::Boxed, MemoryTransport, Transport},
+    upgrade,
+    ConnectedPoint,
+    PeerId,
+};
+use libp2p_plaintext::PlainText2Config;
+use libp2p_relay::{
+    client::{Client, ClientEvent},
+    Relay,
+    RelayConfig,
+    RelayEvent,
+};
+use libp2p_swarm::{Swarm, SwarmEvent};
+use libp2p_yamux as yamux;
+use std::io;
+
+#[test]
+fn reservation_and_relayed_connection() {
+    let _ = env_logger::try_init();
+
+    let relay_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    let mut relay = build_relay(RelayConfig::default());
+    let relay_peer_id = Swarm::local_peer_id(&relay).clone();
+    Swarm::listen_on(&mut relay, relay_addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        loop {
+            relay.next().await;
+        }
+    });
+
+    let mut dst = build_client();
+    let dst_peer_id = Swarm::local_peer_id(&dst).clone();
+    let dst_listen_addr = relay_addr.clone()
+        .with(Protocol::P2p(relay_peer_id.clone().into()))
+        .with(Protocol::P2pCircuit);
+    Swarm::listen_on(&mut dst, dst_listen_addr.clone()).unwrap();
+    block_on(wait_for_reservation(&mut dst, dst_listen_addr.clone(), relay_peer_id.clone()));
+
+    let (mut tx, mut rx) = futures::channel::mpsc::channel(1);
+    async_std::task::spawn(async move {
+        loop {
+            if let SwarmEvent::Behaviour(ClientEvent::InboundCircuitEstablished { src_peer_id, .. }) =
+                dst.next_event().await
+            {
+                tx.send(src_peer_id).await.unwrap();
+            }
+        }
+    });
+
+    let mut src = build_client();
+    let src_peer_id = Swarm::local_peer_id(&src).clone();
+    let dst_addr = dst_listen_addr.with(Protocol::P2p(dst_peer_id.clone().into()));
+    Swarm::dial_addr(&mut src, dst_addr).unwrap();
+
+    block_on(async {
+        let mut circuit_established = false;
+        let mut connected = false;
+        while !(circuit_established && connected) {
+            match src.next_event().await {
+                SwarmEvent::Behaviour(ClientEvent::OutboundCircuitEstablished { relay_peer_id: peer, .. }) => {
+                    assert_eq!(peer, relay_peer_id);
+                    circuit_established = true;
+                }
+                SwarmEvent::ConnectionEstablished { peer_id, .. } if peer_id == dst_peer_id => {
+                    connected = true;
+                }
+                SwarmEvent::Behaviour(e) => panic!("Unexpected event: {:?}", e),
+                _ => {}
+            }
+        }
+
+        assert_eq!(rx.next().await, Some(src_peer_id));
+    });
+}
+
+#[test]
+fn dial_via_added_relay() {
+    let _ = env_logger::try_init();
+
+    let relay_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    let mut relay = build_relay(RelayConfig::default());
+    let relay_peer_id = Swarm::local_peer_id(&relay).clone();
+    Swarm::listen_on(&mut relay, relay_addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        loop {
+            relay.next().await;
+        }
+    });
+
+    let mut dst = build_client();
+    let dst_peer_id = Swarm::local_peer_id(&dst).clone();
+    let dst_listen_addr = relay_addr.clone()
+        .with(Protocol::P2p(relay_peer_id.clone().into()))
+        .with(Protocol::P2pCircuit);
+    Swarm::listen_on(&mut dst, dst_listen_addr.clone()).unwrap();
+    block_on(wait_for_reservation(&mut dst, dst_listen_addr, relay_peer_id.clone()));
+    async_std::task::spawn(async move {
+        loop {
+            dst.next_event().await;
+        }
+    });
+
+    let mut src = build_client();
+    src.add_relay(relay_peer_id.clone(), relay_addr);
+    let dst_addr = Multiaddr::empty()
+        .with(Protocol::P2pCircuit)
+        .with(Protocol::P2p(dst_peer_id.clone().into()));
+    Swarm::dial_addr(&mut src, dst_addr).unwrap();
+
+    block_on(async {
+        loop {
+            match src.next_event().await {
+                SwarmEvent::Behaviour(ClientEvent::OutboundCircuitEstablished { relay_peer_id: peer, .. }) => {
+                    assert_eq!(peer, relay_peer_id);
+                }
+                SwarmEvent::ConnectionEstablished { peer_id, .. } if peer_id == dst_peer_id => break,
+                SwarmEvent::Behaviour(e) => panic!("Unexpected event: {:?}", e),
+                _ => {}
+            }
+        }
+    });
+}
+
+#[test]
+fn auto_relay() {
+    let _ = env_logger::try_init();
+
+    let relay_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    let mut relay = build_relay(RelayConfig::default());
+    let relay_peer_id = Swarm::local_peer_id(&relay).clone();
+    Swarm::listen_on(&mut relay, relay_addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        loop {
+            relay.next().await;
+        }
+    });
+
+    let mut dst = build_client();
+    let dst_peer_id = Swarm::local_peer_id(&dst).clone();
+    Swarm::listen_on(&mut dst, Protocol::P2pCircuit.into()).unwrap();
+    dst.add_relay(relay_peer_id.clone(), relay_addr.clone());
+
+    dst.set_private(true);
+    let dst_listen_addr = relay_addr
+        .with(Protocol::P2p(relay_peer_id.clone().into()))
+        .with(Protocol::P2pCircuit);
+    block_on(wait_for_reservation(&mut dst, dst_listen_addr.clone(), relay_peer_id.clone()));
+
+    let mut src = build_client();
+    let dst_addr = dst_listen_addr.clone().with(Protocol::P2p(dst_peer_id.clone().into()));
+    Swarm::dial_addr(&mut src, dst_addr).unwrap();
+    async_std::task::spawn(async move {
+        loop {
+            src.next_event().await;
+        }
+    });
+
+    block_on(async {
+        loop {
+            match dst.next_event().await {
+                SwarmEvent::ConnectionEstablished { endpoint: ConnectedPoint::Listener { local_addr, .. }, .. } => {
+                    assert_eq!(local_addr, dst_listen_addr);
+                    break
+                }
+                SwarmEvent::Behaviour(ClientEvent::InboundCircuitEstablished { .. }) => {}
+                SwarmEvent::Behaviour(e) => panic!("Unexpected event: {:?}", e),
+                _ => {}
+            }
+        }
+
+        dst.set_private(false);
+        loop {
+            if let SwarmEvent::ExpiredListenAddr(addr) = dst.next_event().await {
+                assert_eq!(addr, dst_listen_addr);
+                break
+            }
+        }
+    });
+}
+
+#[test]
+fn circuit_to_peer_without_reservation_is_denied() {
+    let _ = env_logger::try_init();
+
+    let relay_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    let mut relay = build_relay(RelayConfig::default());
+    let relay_peer_id = Swarm::local_peer_id(&relay).clone();
+    Swarm::listen_on(&mut relay, relay_addr.clone()).unwrap();
+
+    let mut src = build_client();
+    let dst_addr = relay_addr
+        .with(Protocol::P2p(relay_peer_id.clone().into()))
+        .with(Protocol::P2pCircuit)
+        .with(Protocol::P2p(PeerId::random().into()));
+    Swarm::dial_addr(&mut src, dst_addr).unwrap();
+
+    block_on(async {
+        let mut relay_denied = false;
+        let mut client_failed = false;
+        while !(relay_denied && client_failed) {
+            future::select(
+                Box::pin(relay.next().map(|event| match event {
+                    RelayEvent::CircuitReqDenied { .. } => relay_denied = true,
+                    e => panic!("Unexpected event: {:?}", e),
+                })),
+                Box::pin(src.next_event().map(|event| match event {
+                    SwarmEvent::Behaviour(ClientEvent::OutboundCircuitReqFailed { relay_peer_id: peer, .. }) => {
+                        assert_eq!(peer, relay_peer_id);
+                        client_failed = true;
+                    }
+                    SwarmEvent::Behaviour(e) => panic!("Unexpected event: {:?}", e),
+                    _ => {}
+                })),
+            ).await;
+        }
+    });
+}
+
+#[test]
+fn reservation_over_limit_is_denied() {
+    let _ = env_logger::try_init();
+
+    let relay_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    let mut config = RelayConfig::default();
+    config.set_max_reservations(0);
+    let mut relay = build_relay(config);
+    let relay_peer_id = Swarm::local_peer_id(&relay).clone();
+    Swarm::listen_on(&mut relay, relay_addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        loop {
+            relay.next().await;
+        }
+    });
+
+    let mut client = build_client();
+    let listen_addr = relay_addr
+        .with(Protocol::P2p(relay_peer_id.clone().into()))
+        .with(Protocol::P2pCircuit);
+    Swarm::listen_on(&mut client, listen_addr).unwrap();
+
+    block_on(async {
+        let mut reservation_failed = false;
+        let mut listener_closed = false;
+        while !(reservation_failed && listener_closed) {
+            match client.next_event().await {
+                SwarmEvent::Behaviour(ClientEvent::ReservationReqFailed { relay_peer_id: peer, renewal, .. }) => {
+                    assert_eq!(peer, relay_peer_id);
+                    assert!(!renewal);
+                    reservation_failed = true;
+                }
+                SwarmEvent::ListenerClosed { reason, .. } => {
+                    assert!(reason.is_err());
+                    listener_closed = true;
+                }
+                SwarmEvent::Behaviour(e) => panic!("Unexpected event: {:?}", e),
+                _ => {}
+            }
+        }
+    });
+}
+
+async fn wait_for_reservation(client: &mut Swarm<Client>, listen_addr: Multiaddr, relay_peer_id: PeerId) {
+    let mut new_listen_addr = false;
+    let mut reservation_accepted = false;
+
+    while !(new_listen_addr && reservation_accepted) {
+        match client.next_event().await {
+            SwarmEvent::NewListenAddr(addr) => {
+                assert_eq!(addr, listen_addr);
+                new_listen_addr = true;
+            }
+            SwarmEvent::Behaviour(ClientEvent::ReservationReqAccepted { relay_peer_id: peer, renewal, .. }) => {
+                assert_eq!(peer, relay_peer_id);
+                assert!(!renewal);
+                reservation_accepted = true;
+            }
+            SwarmEvent::Behaviour(e) => panic!("Unexpected event: {:?}", e),
+            _ => {}
+        }
+    }
+}
+
+fn build_relay(config: RelayConfig) -> Swarm<Relay> {
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_public_key = local_key.public();
+    let local_peer_id = local_public_key.clone().into_peer_id();
+
+    let transport = upgrade_transport(MemoryTransport, local_public_key);
+
+    Swarm::new(transport, Relay::new(local_peer_id.clone(), config), local_peer_id)
+}
+
+fn build_client() -> Swarm<Client> {
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_public_key = local_key.public();
+    let local_peer_id = local_public_key.clone().into_peer_id();
+
+    let (relay_transport, behaviour) = Client::new_transport_and_behaviour();
+    let transport = upgrade_transport(
+        relay_transport.or_transport(MemoryTransport),
+        local_public_key,
+    );
+
+    Swarm::new(transport, behaviour, local_peer_id)
+}
+
+fn upgrade_transport<T>(transport: T, local_public_key: identity::PublicKey)
+    -> Boxed<(PeerId, StreamMuxerBox), io::Error>
+where
+    T: Transport + Clone + Send + Sync + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::Error: Send + Sync + 'static,
+    T::Dial: Send + 'static,
+    T::Listener: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+{
+    transport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(PlainText2Config { local_public_key })
+        .multiplex(yamux::Config::default())
+        .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+        .boxed()
+}
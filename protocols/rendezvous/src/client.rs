@@ -0,0 +1,408 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The rendezvous client role.
+
+use crate::codec::{
+    Cookie,
+    ErrorCode,
+    Namespace,
+    Registration,
+    RendezvousCodec,
+    RendezvousProtocol,
+    Request,
+    Response,
+    Ttl,
+};
+use futures::{future::BoxFuture, prelude::*, stream::FuturesUnordered};
+use libp2p_core::{connection::{ConnectedPoint, ConnectionId}, Multiaddr, PeerId};
+use libp2p_request_response::{
+    handler::RequestResponseHandler,
+    OutboundFailure,
+    ProtocolSupport,
+    RequestId,
+    RequestResponse,
+    RequestResponseConfig,
+    RequestResponseEvent,
+    RequestResponseMessage,
+};
+use libp2p_swarm::{NetworkBehaviour, NetworkBehaviourAction, PollParameters, ProtocolsHandler};
+use std::{
+    collections::{HashMap, VecDeque},
+    error,
+    fmt,
+    iter,
+    task::{Context, Poll},
+    time::Duration,
+};
+use wasm_timer::{Delay, Instant};
+
+/// The events emitted by the rendezvous [`Client`].
+#[derive(Debug)]
+pub enum ClientEvent {
+    /// We registered in a namespace of a rendezvous node, or renewed that
+    /// registration.
+    Registered {
+        rendezvous_node: PeerId,
+        namespace: Namespace,
+        /// The TTL granted by the rendezvous node, in seconds.
+        ttl: Ttl,
+    },
+    /// Registering in a namespace of a rendezvous node failed.
+    RegisterFailed {
+        rendezvous_node: PeerId,
+        namespace: Namespace,
+        error: ClientError,
+    },
+    /// A rendezvous node answered a discover request.
+    Discovered {
+        rendezvous_node: PeerId,
+        registrations: Vec<Registration>,
+        /// The cookie to pass to the next discover request, in order to only
+        /// discover the registrations made since.
+        cookie: Cookie,
+    },
+    /// A discover request failed.
+    DiscoverFailed {
+        rendezvous_node: PeerId,
+        namespace: Option<Namespace>,
+        error: ClientError,
+    },
+}
+
+/// The reasons a request of the [`Client`] may fail.
+#[derive(Debug)]
+pub enum ClientError {
+    /// There are no external addresses of the local node to register.
+    NoExternalAddresses,
+    /// The request could not be sent or no response has been received.
+    OutboundFailure(OutboundFailure),
+    /// The rendezvous node rejected the request.
+    Remote(ErrorCode),
+    /// The rendezvous node sent a response not matching the request.
+    UnexpectedResponse,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::NoExternalAddresses => write!(f, "No external addresses to register"),
+            ClientError::OutboundFailure(e) => write!(f, "Request failed: {:?}", e),
+            ClientError::Remote(e) => write!(f, "Rendezvous node rejected the request: {}", e),
+            ClientError::UnexpectedResponse => write!(f, "Unexpected response"),
+        }
+    }
+}
+
+impl error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ClientError::Remote(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A request of the client awaiting its response.
+enum PendingRequest {
+    Register(Namespace),
+    Unregister,
+    Discover(Option<Namespace>),
+}
+
+type InEvent = <RequestResponseHandler<RendezvousCodec> as ProtocolsHandler>::InEvent;
+
+/// `NetworkBehaviour` for the client role of the rendezvous protocol.
+///
+/// Registrations made via [`Client::register`] are renewed before they
+/// expire, until removed via [`Client::unregister`]. The addresses of peers
+/// found via [`Client::discover`] are returned by
+/// [`NetworkBehaviour::addresses_of_peer`] until their registration expires.
+///
+/// > **Note**: Requests to a rendezvous node that is not connected can only
+/// > be sent if its addresses are provided by another `NetworkBehaviour`
+/// > the `Client` is embedded with, or if it is dialed explicitly.
+pub struct Client {
+    /// The underlying request-response protocol.
+    inner: RequestResponse<RendezvousCodec>,
+    local_peer_id: PeerId,
+    /// The registrations to keep up, with the TTL to request.
+    registrations: HashMap<(PeerId, Namespace), Option<Ttl>>,
+    /// Registrations to send on the next poll, which provides the local
+    /// addresses.
+    pending_registrations: VecDeque<(PeerId, Namespace, Option<Ttl>)>,
+    /// Resolve when a registration is due for renewal.
+    renewals: FuturesUnordered<BoxFuture<'static, (PeerId, Namespace)>>,
+    /// The requests awaiting their response.
+    pending_requests: HashMap<RequestId, (PeerId, PendingRequest)>,
+    /// The addresses of discovered peers per namespace, with the expiry of
+    /// their registration.
+    discovered_peers: HashMap<PeerId, HashMap<Namespace, (Vec<Multiaddr>, Instant)>>,
+    /// Queue of events to return when polled.
+    pending_events: VecDeque<ClientEvent>,
+}
+
+impl Client {
+    /// Creates a new rendezvous `Client`.
+    pub fn new(local_peer_id: PeerId) -> Self {
+        let protocols = iter::once((RendezvousProtocol, ProtocolSupport::Outbound));
+        Client {
+            inner: RequestResponse::new(RendezvousCodec, protocols, RequestResponseConfig::default()),
+            local_peer_id,
+            registrations: HashMap::new(),
+            pending_registrations: VecDeque::new(),
+            renewals: FuturesUnordered::new(),
+            pending_requests: HashMap::new(),
+            discovered_peers: HashMap::new(),
+            pending_events: VecDeque::new(),
+        }
+    }
+
+    /// Registers the external addresses of the local node in a namespace of
+    /// a rendezvous node, with the given TTL in seconds or the default TTL of
+    /// the rendezvous node.
+    ///
+    /// The registration is renewed before it expires, until removed via
+    /// [`Client::unregister`].
+    pub fn register(&mut self, namespace: Namespace, rendezvous_node: PeerId, ttl: Option<Ttl>) {
+        self.registrations.insert((rendezvous_node.clone(), namespace.clone()), ttl);
+        self.pending_registrations.push_back((rendezvous_node, namespace, ttl));
+    }
+
+    /// Removes the registration of the local node from a namespace of a
+    /// rendezvous node.
+    pub fn unregister(&mut self, namespace: Namespace, rendezvous_node: PeerId) {
+        self.registrations.remove(&(rendezvous_node.clone(), namespace.clone()));
+        self.pending_registrations.retain(|(node, ns, _)| node != &rendezvous_node || ns != &namespace);
+        let request = Request::Unregister { namespace, peer_id: self.local_peer_id.clone() };
+        let request_id = self.inner.send_request(&rendezvous_node, request);
+        self.pending_requests.insert(request_id, (rendezvous_node, PendingRequest::Unregister));
+    }
+
+    /// Asks a rendezvous node for the peers registered in a namespace, or in
+    /// all namespaces if `None`.
+    ///
+    /// If a cookie of a previous [`ClientEvent::Discovered`] is given, only
+    /// the registrations made since are returned.
+    pub fn discover(
+        &mut self,
+        namespace: Option<Namespace>,
+        cookie: Option<Cookie>,
+        limit: Option<u64>,
+        rendezvous_node: PeerId,
+    ) {
+        let request = Request::Discover { namespace: namespace.clone(), limit, cookie };
+        let request_id = self.inner.send_request(&rendezvous_node, request);
+        self.pending_requests.insert(request_id, (rendezvous_node, PendingRequest::Discover(namespace)));
+    }
+
+    /// Sends the registrations queued since the last poll.
+    fn send_registrations(&mut self, params: &mut impl PollParameters) {
+        if self.pending_registrations.is_empty() {
+            return;
+        }
+
+        let addresses = params.external_addresses().collect::<Vec<_>>();
+        while let Some((rendezvous_node, namespace, ttl)) = self.pending_registrations.pop_front() {
+            if addresses.is_empty() {
+                self.registrations.remove(&(rendezvous_node.clone(), namespace.clone()));
+                self.pending_events.push_back(ClientEvent::RegisterFailed {
+                    rendezvous_node,
+                    namespace,
+                    error: ClientError::NoExternalAddresses,
+                });
+                continue;
+            }
+            let request = Request::Register {
+                namespace: namespace.clone(),
+                peer_id: self.local_peer_id.clone(),
+                addresses: addresses.clone(),
+                ttl,
+            };
+            let request_id = self.inner.send_request(&rendezvous_node, request);
+            self.pending_requests.insert(request_id, (rendezvous_node, PendingRequest::Register(namespace)));
+        }
+    }
+
+    fn handle_response(&mut self, request_id: RequestId, response: Response) {
+        let (rendezvous_node, request) = match self.pending_requests.remove(&request_id) {
+            Some(pending) => pending,
+            None => return,
+        };
+
+        let event = match (request, response) {
+            (PendingRequest::Register(namespace), Response::Register(Ok(ttl))) => {
+                let key = (rendezvous_node.clone(), namespace.clone());
+                if self.registrations.contains_key(&key) {
+                    // Renew once three quarters of the TTL have elapsed.
+                    let renew_in = Duration::from_secs(ttl) * 3 / 4;
+                    self.renewals.push(Delay::new(renew_in).map(move |_| key).boxed());
+                }
+                ClientEvent::Registered { rendezvous_node, namespace, ttl }
+            }
+            (PendingRequest::Register(namespace), response) => {
+                self.registrations.remove(&(rendezvous_node.clone(), namespace.clone()));
+                let error = match response {
+                    Response::Register(Err(code)) => ClientError::Remote(code),
+                    _ => ClientError::UnexpectedResponse,
+                };
+                ClientEvent::RegisterFailed { rendezvous_node, namespace, error }
+            }
+            (PendingRequest::Unregister, _) => return,
+            (PendingRequest::Discover(_), Response::Discover(Ok((registrations, cookie)))) => {
+                let now = Instant::now();
+                for registration in &registrations {
+                    let expires = now + Duration::from_secs(registration.ttl);
+                    self.discovered_peers
+                        .entry(registration.peer_id.clone())
+                        .or_default()
+                        .insert(registration.namespace.clone(), (registration.addresses.clone(), expires));
+                }
+                ClientEvent::Discovered { rendezvous_node, registrations, cookie }
+            }
+            (PendingRequest::Discover(namespace), response) => {
+                let error = match response {
+                    Response::Discover(Err(code)) => ClientError::Remote(code),
+                    _ => ClientError::UnexpectedResponse,
+                };
+                ClientEvent::DiscoverFailed { rendezvous_node, namespace, error }
+            }
+        };
+        self.pending_events.push_back(event);
+    }
+
+    fn handle_failure(&mut self, request_id: RequestId, error: OutboundFailure) {
+        let (rendezvous_node, request) = match self.pending_requests.remove(&request_id) {
+            Some(pending) => pending,
+            None => return,
+        };
+
+        let error = ClientError::OutboundFailure(error);
+        let event = match request {
+            PendingRequest::Register(namespace) => {
+                self.registrations.remove(&(rendezvous_node.clone(), namespace.clone()));
+                ClientEvent::RegisterFailed { rendezvous_node, namespace, error }
+            }
+            PendingRequest::Unregister => return,
+            PendingRequest::Discover(namespace) =>
+                ClientEvent::DiscoverFailed { rendezvous_node, namespace, error },
+        };
+        self.pending_events.push_back(event);
+    }
+}
+
+impl NetworkBehaviour for Client {
+    type ProtocolsHandler = RequestResponseHandler<RendezvousCodec>;
+    type OutEvent = ClientEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        self.inner.new_handler()
+    }
+
+    fn addresses_of_peer(&mut self, peer: &PeerId) -> Vec<Multiaddr> {
+        let mut addresses = self.inner.addresses_of_peer(peer);
+        if let Some(namespaces) = self.discovered_peers.get(peer) {
+            let now = Instant::now();
+            for (discovered, expires) in namespaces.values() {
+                if *expires > now {
+                    addresses.extend(discovered.iter().cloned());
+                }
+            }
+        }
+        addresses
+    }
+
+    fn inject_connected(&mut self, peer: &PeerId) {
+        self.inner.inject_connected(peer)
+    }
+
+    fn inject_disconnected(&mut self, peer: &PeerId) {
+        self.inner.inject_disconnected(peer)
+    }
+
+    fn inject_connection_established(&mut self, peer: &PeerId, conn: &ConnectionId, endpoint: &ConnectedPoint) {
+        self.inner.inject_connection_established(peer, conn, endpoint)
+    }
+
+    fn inject_connection_closed(&mut self, peer: &PeerId, conn: &ConnectionId, endpoint: &ConnectedPoint) {
+        self.inner.inject_connection_closed(peer, conn, endpoint)
+    }
+
+    fn inject_dial_failure(&mut self, peer: &PeerId) {
+        self.inner.inject_dial_failure(peer)
+    }
+
+    fn inject_event(
+        &mut self,
+        peer: PeerId,
+        conn: ConnectionId,
+        event: <Self::ProtocolsHandler as ProtocolsHandler>::OutEvent,
+    ) {
+        self.inner.inject_event(peer, conn, event)
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>, params: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<InEvent, ClientEvent>>
+    {
+        while let Poll::Ready(Some((rendezvous_node, namespace))) = self.renewals.poll_next_unpin(cx) {
+            if let Some(ttl) = self.registrations.get(&(rendezvous_node.clone(), namespace.clone())) {
+                self.pending_registrations.push_back((rendezvous_node, namespace, *ttl));
+            }
+        }
+
+        self.send_registrations(params);
+
+        loop {
+            if let Some(event) = self.pending_events.pop_front() {
+                return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event));
+            }
+
+            match self.inner.poll(cx, params) {
+                Poll::Ready(NetworkBehaviourAction::GenerateEvent(event)) => match event {
+                    RequestResponseEvent::Message {
+                        message: RequestResponseMessage::Response { request_id, response }, ..
+                    } => self.handle_response(request_id, response),
+                    RequestResponseEvent::OutboundFailure { request_id, error, .. } =>
+                        self.handle_failure(request_id, error),
+                    RequestResponseEvent::Message { .. } | RequestResponseEvent::InboundFailure { .. } => {
+                        // The client does not support inbound requests.
+                    }
+                },
+                Poll::Ready(NetworkBehaviourAction::DialAddress { address }) =>
+                    return Poll::Ready(NetworkBehaviourAction::DialAddress { address }),
+                Poll::Ready(NetworkBehaviourAction::DialPeer { peer_id, condition }) =>
+                    return Poll::Ready(NetworkBehaviourAction::DialPeer { peer_id, condition }),
+                Poll::Ready(NetworkBehaviourAction::NotifyHandler { peer_id, handler, event }) =>
+                    return Poll::Ready(NetworkBehaviourAction::NotifyHandler { peer_id, handler, event }),
+                Poll::Ready(NetworkBehaviourAction::ReportObservedAddr { address, peer_id }) =>
+                    return Poll::Ready(NetworkBehaviourAction::ReportObservedAddr { address, peer_id }),
+                Poll::Ready(NetworkBehaviourAction::AddExternalAddr { address }) =>
+                    return Poll::Ready(NetworkBehaviourAction::AddExternalAddr { address }),
+                Poll::Ready(NetworkBehaviourAction::ConfirmExternalAddr { address }) =>
+                    return Poll::Ready(NetworkBehaviourAction::ConfirmExternalAddr { address }),
+                Poll::Ready(NetworkBehaviourAction::RemoveExternalAddr { address }) =>
+                    return Poll::Ready(NetworkBehaviourAction::RemoveExternalAddr { address }),
+                Poll::Ready(NetworkBehaviourAction::UpdateSupportedProtocols) =>
+                    return Poll::Ready(NetworkBehaviourAction::UpdateSupportedProtocols),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
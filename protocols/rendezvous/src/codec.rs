@@ -0,0 +1,584 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The rendezvous wire protocol, carried over a `libp2p-request-response` codec.
+
+use crate::message_proto::{self, message::{MessageType, ResponseStatus}};
+use async_trait::async_trait;
+use futures::prelude::*;
+use libp2p_core::{
+    upgrade::{self, ReadOneError},
+    Multiaddr,
+    PeerId,
+    ProtocolName,
+};
+use libp2p_request_response::RequestResponseCodec;
+use prost::Message;
+use std::{convert::TryFrom, error, fmt, io};
+
+/// The protocol name used for negotiating rendezvous substreams.
+pub const PROTOCOL_NAME: &[u8] = b"/rendezvous/1.0.0";
+
+/// The default TTL of a registration, in seconds, if none is requested.
+pub const DEFAULT_TTL: Ttl = 60 * 60 * 2;
+
+/// The maximum length of a namespace, in bytes.
+pub const MAX_NAMESPACE: usize = 255;
+
+/// Maximum size of a rendezvous message.
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// The time-to-live of a registration, in seconds.
+pub type Ttl = u64;
+
+/// An application namespace peers register and discover each other in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Namespace(String);
+
+impl Namespace {
+    /// Creates a new namespace, failing if it exceeds [`MAX_NAMESPACE`] bytes.
+    pub fn new(value: String) -> Result<Self, NamespaceTooLong> {
+        if value.len() > MAX_NAMESPACE {
+            return Err(NamespaceTooLong);
+        }
+        Ok(Namespace(value))
+    }
+
+    /// Creates a namespace from a static string.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the namespace exceeds [`MAX_NAMESPACE`] bytes.
+    pub fn from_static(value: &'static str) -> Self {
+        Namespace::new(value.to_owned()).expect("Namespace too long")
+    }
+
+    /// Returns `true` if the namespace, as received from a remote, is within
+    /// [`MAX_NAMESPACE`] bytes.
+    pub(crate) fn is_valid(&self) -> bool {
+        self.0.len() <= MAX_NAMESPACE
+    }
+}
+
+impl AsRef<str> for Namespace {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Error returned by [`Namespace::new`] if the namespace is too long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NamespaceTooLong;
+
+impl fmt::Display for NamespaceTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Namespace exceeds {} bytes", MAX_NAMESPACE)
+    }
+}
+
+impl error::Error for NamespaceTooLong {}
+
+/// An opaque pagination cookie handed out by a rendezvous server.
+///
+/// Passing the cookie of a discover response to the next discover request
+/// for the same namespace only returns registrations made since.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cookie {
+    id: u64,
+    namespace: Option<Namespace>,
+}
+
+impl Cookie {
+    pub(crate) fn new(id: u64, namespace: Option<Namespace>) -> Self {
+        Cookie { id, namespace }
+    }
+
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the namespace the cookie is valid for, or `None` if it is
+    /// valid for discovering across all namespaces.
+    pub fn namespace(&self) -> Option<&Namespace> {
+        self.namespace.as_ref()
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        let mut bytes = self.id.to_be_bytes().to_vec();
+        if let Some(namespace) = self.namespace {
+            bytes.extend_from_slice(namespace.0.as_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> io::Result<Self> {
+        if bytes.len() < 8 {
+            return Err(invalid_data("Cookie too short"));
+        }
+        let mut id = [0; 8];
+        id.copy_from_slice(&bytes[..8]);
+        let namespace = if bytes.len() > 8 {
+            let namespace = String::from_utf8(bytes[8..].to_vec()).map_err(invalid_data)?;
+            Some(Namespace(namespace))
+        } else {
+            None
+        };
+        Ok(Cookie { id: u64::from_be_bytes(id), namespace })
+    }
+}
+
+/// A registration of a peer in a namespace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registration {
+    /// The namespace the peer is registered in.
+    pub namespace: Namespace,
+    /// The registered peer.
+    pub peer_id: PeerId,
+    /// The addresses of the registered peer.
+    pub addresses: Vec<Multiaddr>,
+    /// The time-to-live of the registration, in seconds.
+    pub ttl: Ttl,
+}
+
+/// The error codes of failed requests, as reported by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The namespace is invalid, e.g. too long.
+    InvalidNamespace,
+    /// The peer information of a registration is invalid.
+    InvalidPeerInfo,
+    /// The requested TTL is out of the accepted range.
+    InvalidTtl,
+    /// The cookie is invalid, e.g. issued for another namespace.
+    InvalidCookie,
+    /// The request is not authorized, e.g. registering another peer.
+    NotAuthorized,
+    /// The server failed for internal reasons.
+    InternalError,
+    /// The server is unavailable.
+    Unavailable,
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorCode::InvalidNamespace => write!(f, "Invalid namespace"),
+            ErrorCode::InvalidPeerInfo => write!(f, "Invalid peer info"),
+            ErrorCode::InvalidTtl => write!(f, "Invalid TTL"),
+            ErrorCode::InvalidCookie => write!(f, "Invalid cookie"),
+            ErrorCode::NotAuthorized => write!(f, "Not authorized"),
+            ErrorCode::InternalError => write!(f, "Internal error"),
+            ErrorCode::Unavailable => write!(f, "Unavailable"),
+        }
+    }
+}
+
+impl error::Error for ErrorCode {}
+
+impl From<ErrorCode> for ResponseStatus {
+    fn from(code: ErrorCode) -> Self {
+        match code {
+            ErrorCode::InvalidNamespace => ResponseStatus::EInvalidNamespace,
+            ErrorCode::InvalidPeerInfo => ResponseStatus::EInvalidPeerInfo,
+            ErrorCode::InvalidTtl => ResponseStatus::EInvalidTtl,
+            ErrorCode::InvalidCookie => ResponseStatus::EInvalidCookie,
+            ErrorCode::NotAuthorized => ResponseStatus::ENotAuthorized,
+            ErrorCode::InternalError => ResponseStatus::EInternalError,
+            ErrorCode::Unavailable => ResponseStatus::EUnavailable,
+        }
+    }
+}
+
+/// A request sent to a rendezvous server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    /// Registers the given peer in a namespace.
+    Register {
+        namespace: Namespace,
+        peer_id: PeerId,
+        addresses: Vec<Multiaddr>,
+        ttl: Option<Ttl>,
+    },
+    /// Removes the registration of the given peer from a namespace.
+    Unregister {
+        namespace: Namespace,
+        peer_id: PeerId,
+    },
+    /// Asks for the registrations in a namespace, or in all namespaces.
+    Discover {
+        namespace: Option<Namespace>,
+        limit: Option<u64>,
+        cookie: Option<Cookie>,
+    },
+}
+
+/// The response of a rendezvous server to a [`Request`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// The response to [`Request::Register`], carrying the granted TTL.
+    Register(Result<Ttl, ErrorCode>),
+    /// The (empty) response to [`Request::Unregister`].
+    Unregister,
+    /// The response to [`Request::Discover`].
+    Discover(Result<(Vec<Registration>, Cookie), ErrorCode>),
+}
+
+/// The rendezvous protocol, as negotiated by `libp2p-request-response`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RendezvousProtocol;
+
+impl ProtocolName for RendezvousProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        PROTOCOL_NAME
+    }
+}
+
+/// The codec reading and writing rendezvous requests and responses.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RendezvousCodec;
+
+#[async_trait]
+impl RequestResponseCodec for RendezvousCodec {
+    type Protocol = RendezvousProtocol;
+    type Request = Request;
+    type Response = Response;
+
+    async fn read_request<T>(&mut self, _: &RendezvousProtocol, io: &mut T)
+        -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send
+    {
+        let bytes = recv(io).await?;
+        if bytes.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        decode_request(&bytes)
+    }
+
+    async fn read_response<T>(&mut self, _: &RendezvousProtocol, io: &mut T)
+        -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send
+    {
+        let bytes = recv(io).await?;
+        // Unregistrations are not answered, the server just closes the
+        // substream.
+        if bytes.is_empty() {
+            return Ok(Response::Unregister);
+        }
+        decode_response(&bytes)
+    }
+
+    async fn write_request<T>(&mut self, _: &RendezvousProtocol, io: &mut T, req: Request)
+        -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send
+    {
+        upgrade::write_one(io, encode(request_to_message(req))).await
+    }
+
+    async fn write_response<T>(&mut self, _: &RendezvousProtocol, io: &mut T, res: Response)
+        -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send
+    {
+        match response_to_message(res) {
+            Some(msg) => upgrade::write_one(io, encode(msg)).await,
+            None => io.close().await,
+        }
+    }
+}
+
+fn request_to_message(request: Request) -> message_proto::Message {
+    let mut msg = message_proto::Message::default();
+    match request {
+        Request::Register { namespace, peer_id, addresses, ttl } => {
+            msg.r#type = Some(MessageType::Register as i32);
+            msg.register = Some(registration_to_message(namespace, peer_id, addresses, ttl));
+        }
+        Request::Unregister { namespace, peer_id } => {
+            msg.r#type = Some(MessageType::Unregister as i32);
+            msg.unregister = Some(message_proto::message::Unregister {
+                ns: Some(namespace.0),
+                id: Some(peer_id.into_bytes()),
+            });
+        }
+        Request::Discover { namespace, limit, cookie } => {
+            msg.r#type = Some(MessageType::Discover as i32);
+            msg.discover = Some(message_proto::message::Discover {
+                ns: namespace.map(|ns| ns.0),
+                limit,
+                cookie: cookie.map(Cookie::into_bytes),
+            });
+        }
+    }
+    msg
+}
+
+fn response_to_message(response: Response) -> Option<message_proto::Message> {
+    let mut msg = message_proto::Message::default();
+    match response {
+        Response::Register(result) => {
+            msg.r#type = Some(MessageType::RegisterResponse as i32);
+            msg.register_response = Some(match result {
+                Ok(ttl) => message_proto::message::RegisterResponse {
+                    status: Some(ResponseStatus::Ok as i32),
+                    status_text: None,
+                    ttl: Some(ttl),
+                },
+                Err(code) => message_proto::message::RegisterResponse {
+                    status: Some(ResponseStatus::from(code) as i32),
+                    status_text: Some(code.to_string()),
+                    ttl: None,
+                },
+            });
+        }
+        Response::Unregister => return None,
+        Response::Discover(result) => {
+            msg.r#type = Some(MessageType::DiscoverResponse as i32);
+            msg.discover_response = Some(match result {
+                Ok((registrations, cookie)) => message_proto::message::DiscoverResponse {
+                    registrations: registrations.into_iter()
+                        .map(|r| registration_to_message(r.namespace, r.peer_id, r.addresses, Some(r.ttl)))
+                        .collect(),
+                    cookie: Some(cookie.into_bytes()),
+                    status: Some(ResponseStatus::Ok as i32),
+                    status_text: None,
+                },
+                Err(code) => message_proto::message::DiscoverResponse {
+                    registrations: Vec::new(),
+                    cookie: None,
+                    status: Some(ResponseStatus::from(code) as i32),
+                    status_text: Some(code.to_string()),
+                },
+            });
+        }
+    }
+    Some(msg)
+}
+
+fn registration_to_message(namespace: Namespace, peer_id: PeerId, addresses: Vec<Multiaddr>, ttl: Option<Ttl>)
+    -> message_proto::message::Register
+{
+    message_proto::message::Register {
+        ns: Some(namespace.0),
+        peer: Some(message_proto::message::PeerInfo {
+            id: Some(peer_id.into_bytes()),
+            addrs: addresses.into_iter().map(|a| a.to_vec()).collect(),
+        }),
+        ttl,
+    }
+}
+
+fn decode_request(bytes: &[u8]) -> io::Result<Request> {
+    let msg = message_proto::Message::decode(bytes).map_err(invalid_data)?;
+    match msg.r#type.and_then(MessageType::from_i32) {
+        Some(MessageType::Register) => {
+            let register = msg.register.ok_or_else(|| invalid_data("REGISTER without content"))?;
+            let registration = registration_from_message(register)?;
+            Ok(Request::Register {
+                namespace: registration.namespace,
+                peer_id: registration.peer_id,
+                addresses: registration.addresses,
+                ttl: registration.ttl,
+            })
+        }
+        Some(MessageType::Unregister) => {
+            let unregister = msg.unregister.ok_or_else(|| invalid_data("UNREGISTER without content"))?;
+            Ok(Request::Unregister {
+                namespace: Namespace(unregister.ns.ok_or_else(|| invalid_data("UNREGISTER without namespace"))?),
+                peer_id: peer_id_from_bytes(unregister.id)?,
+            })
+        }
+        Some(MessageType::Discover) => {
+            let discover = msg.discover.ok_or_else(|| invalid_data("DISCOVER without content"))?;
+            Ok(Request::Discover {
+                namespace: discover.ns.map(Namespace),
+                limit: discover.limit,
+                cookie: discover.cookie.map(Cookie::from_bytes).transpose()?,
+            })
+        }
+        _ => Err(invalid_data("Unexpected message type")),
+    }
+}
+
+fn decode_response(bytes: &[u8]) -> io::Result<Response> {
+    let msg = message_proto::Message::decode(bytes).map_err(invalid_data)?;
+    match msg.r#type.and_then(MessageType::from_i32) {
+        Some(MessageType::RegisterResponse) => {
+            let response = msg.register_response
+                .ok_or_else(|| invalid_data("REGISTER_RESPONSE without content"))?;
+            let result = match status_from_message(response.status)? {
+                Ok(()) => Ok(response.ttl.ok_or_else(|| invalid_data("REGISTER_RESPONSE without TTL"))?),
+                Err(code) => Err(code),
+            };
+            Ok(Response::Register(result))
+        }
+        Some(MessageType::DiscoverResponse) => {
+            let response = msg.discover_response
+                .ok_or_else(|| invalid_data("DISCOVER_RESPONSE without content"))?;
+            let result = match status_from_message(response.status)? {
+                Ok(()) => {
+                    let registrations = response.registrations.into_iter()
+                        .map(|r| registration_from_message(r).map(|r| Registration {
+                            namespace: r.namespace,
+                            peer_id: r.peer_id,
+                            addresses: r.addresses,
+                            ttl: r.ttl.unwrap_or(DEFAULT_TTL),
+                        }))
+                        .collect::<io::Result<Vec<_>>>()?;
+                    let cookie = response.cookie
+                        .ok_or_else(|| invalid_data("DISCOVER_RESPONSE without cookie"))
+                        .and_then(Cookie::from_bytes)?;
+                    Ok((registrations, cookie))
+                }
+                Err(code) => Err(code),
+            };
+            Ok(Response::Discover(result))
+        }
+        _ => Err(invalid_data("Unexpected message type")),
+    }
+}
+
+/// A decoded registration whose TTL may not be set.
+struct DecodedRegistration {
+    namespace: Namespace,
+    peer_id: PeerId,
+    addresses: Vec<Multiaddr>,
+    ttl: Option<Ttl>,
+}
+
+fn registration_from_message(register: message_proto::message::Register) -> io::Result<DecodedRegistration> {
+    let namespace = Namespace(register.ns.ok_or_else(|| invalid_data("Registration without namespace"))?);
+    let peer = register.peer.ok_or_else(|| invalid_data("Registration without peer"))?;
+    let peer_id = peer_id_from_bytes(peer.id)?;
+    let addresses = peer.addrs.into_iter()
+        .filter_map(|a| Multiaddr::try_from(a).ok())
+        .collect();
+    Ok(DecodedRegistration { namespace, peer_id, addresses, ttl: register.ttl })
+}
+
+fn peer_id_from_bytes(bytes: Option<Vec<u8>>) -> io::Result<PeerId> {
+    bytes
+        .ok_or_else(|| invalid_data("Missing peer ID"))
+        .and_then(|id| PeerId::from_bytes(id).map_err(|_| invalid_data("Invalid peer ID")))
+}
+
+fn status_from_message(status: Option<i32>) -> io::Result<Result<(), ErrorCode>> {
+    let status = status
+        .and_then(ResponseStatus::from_i32)
+        .ok_or_else(|| invalid_data("Missing or invalid response status"))?;
+    Ok(match status {
+        ResponseStatus::Ok => Ok(()),
+        ResponseStatus::EInvalidNamespace => Err(ErrorCode::InvalidNamespace),
+        ResponseStatus::EInvalidPeerInfo => Err(ErrorCode::InvalidPeerInfo),
+        ResponseStatus::EInvalidTtl => Err(ErrorCode::InvalidTtl),
+        ResponseStatus::EInvalidCookie => Err(ErrorCode::InvalidCookie),
+        ResponseStatus::ENotAuthorized => Err(ErrorCode::NotAuthorized),
+        ResponseStatus::EInternalError => Err(ErrorCode::InternalError),
+        ResponseStatus::EUnavailable => Err(ErrorCode::Unavailable),
+    })
+}
+
+fn encode(msg: message_proto::Message) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(msg.encoded_len());
+    msg.encode(&mut bytes).expect("Vec<u8> provides capacity as needed");
+    bytes
+}
+
+/// Reads a single length-prefixed message, which is empty if the remote
+/// closed the substream without sending anything.
+async fn recv<T: AsyncRead + Unpin>(io: &mut T) -> io::Result<Vec<u8>> {
+    upgrade::read_one(io, MAX_MESSAGE_SIZE).await
+        .map_err(|e| match e {
+            ReadOneError::Io(e) => e,
+            e => invalid_data(e),
+        })
+}
+
+fn invalid_data<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_roundtrip() {
+        let requests = vec![
+            Request::Register {
+                namespace: Namespace::from_static("chat"),
+                peer_id: PeerId::random(),
+                addresses: vec!["/ip4/8.8.8.8/tcp/30333".parse().unwrap()],
+                ttl: Some(7200),
+            },
+            Request::Unregister {
+                namespace: Namespace::from_static("chat"),
+                peer_id: PeerId::random(),
+            },
+            Request::Discover {
+                namespace: None,
+                limit: Some(10),
+                cookie: Some(Cookie::new(42, Some(Namespace::from_static("chat")))),
+            },
+        ];
+        for request in requests {
+            let decoded = decode_request(&encode(request_to_message(request.clone()))).unwrap();
+            assert_eq!(decoded, request);
+        }
+    }
+
+    #[test]
+    fn response_roundtrip() {
+        let registration = Registration {
+            namespace: Namespace::from_static("chat"),
+            peer_id: PeerId::random(),
+            addresses: vec!["/memory/1234".parse().unwrap()],
+            ttl: 60,
+        };
+        let responses = vec![
+            Response::Register(Ok(60)),
+            Response::Register(Err(ErrorCode::InvalidTtl)),
+            Response::Discover(Ok((vec![registration], Cookie::new(1, None)))),
+            Response::Discover(Err(ErrorCode::InvalidCookie)),
+        ];
+        for response in responses {
+            let msg = response_to_message(response.clone()).unwrap();
+            assert_eq!(decode_response(&encode(msg)).unwrap(), response);
+        }
+        assert!(response_to_message(Response::Unregister).is_none());
+    }
+
+    #[test]
+    fn namespace_length_is_limited() {
+        assert!(Namespace::new("a".repeat(MAX_NAMESPACE)).is_ok());
+        assert_eq!(Namespace::new("a".repeat(MAX_NAMESPACE + 1)), Err(NamespaceTooLong));
+    }
+}
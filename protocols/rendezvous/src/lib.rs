@@ -0,0 +1,55 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Implementation of the [rendezvous](https://github.com/libp2p/specs/blob/master/rendezvous/README.md)
+//! protocol.
+//!
+//! Rendezvous lets peers find each other by application namespace through a
+//! well-known rendezvous node, without relying on a DHT.
+//!
+//! - The [`Server`] network behaviour keeps the registrations of peers until
+//!   their TTL elapses and answers discover requests. Discover responses
+//!   carry a [`Cookie`] which, passed to the next discover request, only
+//!   yields the registrations made since.
+//! - The [`Client`] network behaviour registers the external addresses of the
+//!   local node in namespaces of rendezvous nodes, renews these registrations
+//!   before they expire and discovers other peers.
+
+pub mod client;
+mod codec;
+pub mod server;
+
+mod message_proto {
+    include!(concat!(env!("OUT_DIR"), "/rendezvous.pb.rs"));
+}
+
+pub use client::{Client, ClientError, ClientEvent};
+pub use codec::{
+    Cookie,
+    ErrorCode,
+    Namespace,
+    NamespaceTooLong,
+    Registration,
+    Ttl,
+    DEFAULT_TTL,
+    MAX_NAMESPACE,
+    PROTOCOL_NAME,
+};
+pub use server::{Server, ServerConfig, ServerEvent};
@@ -0,0 +1,451 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The rendezvous server role.
+
+use crate::codec::{
+    Cookie,
+    ErrorCode,
+    Namespace,
+    Registration,
+    RendezvousCodec,
+    RendezvousProtocol,
+    Request,
+    Response,
+    Ttl,
+    DEFAULT_TTL,
+};
+use futures::{future::BoxFuture, prelude::*, stream::FuturesUnordered};
+use libp2p_core::{connection::{ConnectedPoint, ConnectionId}, Multiaddr, PeerId};
+use libp2p_request_response::{
+    handler::RequestResponseHandler,
+    ProtocolSupport,
+    RequestResponse,
+    RequestResponseConfig,
+    RequestResponseEvent,
+    RequestResponseMessage,
+    ResponseChannel,
+};
+use libp2p_swarm::{NetworkBehaviour, NetworkBehaviourAction, PollParameters, ProtocolsHandler};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    iter,
+    task::{Context, Poll},
+    time::Duration,
+};
+use wasm_timer::Delay;
+
+/// The configuration for a rendezvous [`Server`].
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    min_ttl: Ttl,
+    max_ttl: Ttl,
+    max_discover_limit: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            min_ttl: DEFAULT_TTL,
+            max_ttl: 60 * 60 * 72,
+            max_discover_limit: 1000,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Sets the minimum TTL of a registration, in seconds.
+    pub fn set_min_ttl(&mut self, v: Ttl) -> &mut Self {
+        self.min_ttl = v;
+        self
+    }
+
+    /// Sets the maximum TTL of a registration, in seconds.
+    pub fn set_max_ttl(&mut self, v: Ttl) -> &mut Self {
+        self.max_ttl = v;
+        self
+    }
+
+    /// Sets the maximum number of registrations returned by a single
+    /// discover request.
+    pub fn set_max_discover_limit(&mut self, v: u64) -> &mut Self {
+        self.max_discover_limit = v;
+        self
+    }
+}
+
+/// The events emitted by the rendezvous [`Server`].
+#[derive(Debug)]
+pub enum ServerEvent {
+    /// A peer registered in a namespace.
+    PeerRegistered {
+        peer: PeerId,
+        registration: Registration,
+    },
+    /// A registration request of a peer has been rejected.
+    PeerNotRegistered {
+        peer: PeerId,
+        namespace: Namespace,
+        error: ErrorCode,
+    },
+    /// A peer removed its registration from a namespace.
+    PeerUnregistered {
+        peer: PeerId,
+        namespace: Namespace,
+    },
+    /// A discover request has been served.
+    DiscoverServed {
+        enquirer: PeerId,
+        registrations: Vec<Registration>,
+    },
+    /// A discover request has been rejected.
+    DiscoverNotServed {
+        enquirer: PeerId,
+        error: ErrorCode,
+    },
+    /// A registration expired without being renewed.
+    RegistrationExpired(Registration),
+}
+
+type InEvent = <RequestResponseHandler<RendezvousCodec> as ProtocolsHandler>::InEvent;
+
+/// `NetworkBehaviour` serving the rendezvous protocol.
+///
+/// The server keeps the registrations of peers until they expire according
+/// to their TTL, unless renewed in the meantime, and answers discover
+/// requests. The cookie of a discover response allows a subsequent discover
+/// request to only return the registrations made since.
+pub struct Server {
+    /// The underlying request-response protocol.
+    inner: RequestResponse<RendezvousCodec>,
+    config: ServerConfig,
+    registrations: Registrations,
+    /// Queue of events to return when polled.
+    pending_events: VecDeque<ServerEvent>,
+}
+
+impl Server {
+    /// Creates a new rendezvous `Server` with the given configuration.
+    pub fn new(config: ServerConfig) -> Self {
+        let protocols = iter::once((RendezvousProtocol, ProtocolSupport::Inbound));
+        Server {
+            inner: RequestResponse::new(RendezvousCodec, protocols, RequestResponseConfig::default()),
+            config,
+            registrations: Registrations::default(),
+            pending_events: VecDeque::new(),
+        }
+    }
+
+    /// Returns the registrations currently held by the server.
+    pub fn registrations(&self) -> impl Iterator<Item = &Registration> {
+        self.registrations.registrations.values()
+    }
+
+    fn handle_request(&mut self, peer: PeerId, request: Request, channel: ResponseChannel<Response>) {
+        match request {
+            Request::Register { namespace, peer_id, addresses, ttl } => {
+                let ttl = ttl.unwrap_or(DEFAULT_TTL);
+                let result = if !namespace.is_valid() {
+                    Err(ErrorCode::InvalidNamespace)
+                } else if peer_id != peer {
+                    Err(ErrorCode::NotAuthorized)
+                } else if ttl < self.config.min_ttl || ttl > self.config.max_ttl {
+                    Err(ErrorCode::InvalidTtl)
+                } else {
+                    Ok(ttl)
+                };
+                self.inner.send_response(channel, Response::Register(result));
+                let event = match result {
+                    Ok(ttl) => {
+                        let registration = Registration { namespace, peer_id, addresses, ttl };
+                        self.registrations.add(registration.clone());
+                        ServerEvent::PeerRegistered { peer, registration }
+                    }
+                    Err(error) => ServerEvent::PeerNotRegistered { peer, namespace, error },
+                };
+                self.pending_events.push_back(event);
+            }
+            Request::Unregister { namespace, peer_id } => {
+                self.inner.send_response(channel, Response::Unregister);
+                if peer_id == peer && self.registrations.remove(&peer_id, &namespace).is_some() {
+                    self.pending_events.push_back(ServerEvent::PeerUnregistered { peer, namespace });
+                }
+            }
+            Request::Discover { namespace, limit, cookie } => {
+                let limit = limit.unwrap_or(self.config.max_discover_limit)
+                    .min(self.config.max_discover_limit);
+                let result = if namespace.as_ref().is_some_and(|ns| !ns.is_valid()) {
+                    Err(ErrorCode::InvalidNamespace)
+                } else {
+                    self.registrations.get(namespace, cookie, limit)
+                };
+                let event = match &result {
+                    Ok((registrations, _)) => ServerEvent::DiscoverServed {
+                        enquirer: peer,
+                        registrations: registrations.clone(),
+                    },
+                    Err(error) => ServerEvent::DiscoverNotServed { enquirer: peer, error: *error },
+                };
+                self.inner.send_response(channel, Response::Discover(result));
+                self.pending_events.push_back(event);
+            }
+        }
+    }
+}
+
+impl NetworkBehaviour for Server {
+    type ProtocolsHandler = RequestResponseHandler<RendezvousCodec>;
+    type OutEvent = ServerEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        self.inner.new_handler()
+    }
+
+    fn addresses_of_peer(&mut self, peer: &PeerId) -> Vec<Multiaddr> {
+        self.inner.addresses_of_peer(peer)
+    }
+
+    fn inject_connected(&mut self, peer: &PeerId) {
+        self.inner.inject_connected(peer)
+    }
+
+    fn inject_disconnected(&mut self, peer: &PeerId) {
+        self.inner.inject_disconnected(peer)
+    }
+
+    fn inject_connection_established(&mut self, peer: &PeerId, conn: &ConnectionId, endpoint: &ConnectedPoint) {
+        self.inner.inject_connection_established(peer, conn, endpoint)
+    }
+
+    fn inject_connection_closed(&mut self, peer: &PeerId, conn: &ConnectionId, endpoint: &ConnectedPoint) {
+        self.inner.inject_connection_closed(peer, conn, endpoint)
+    }
+
+    fn inject_dial_failure(&mut self, peer: &PeerId) {
+        self.inner.inject_dial_failure(peer)
+    }
+
+    fn inject_event(
+        &mut self,
+        peer: PeerId,
+        conn: ConnectionId,
+        event: <Self::ProtocolsHandler as ProtocolsHandler>::OutEvent,
+    ) {
+        self.inner.inject_event(peer, conn, event)
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>, params: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<InEvent, ServerEvent>>
+    {
+        loop {
+            if let Some(event) = self.pending_events.pop_front() {
+                return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event));
+            }
+
+            if let Poll::Ready(registration) = self.registrations.poll_expired(cx) {
+                return Poll::Ready(NetworkBehaviourAction::GenerateEvent(
+                    ServerEvent::RegistrationExpired(registration)));
+            }
+
+            match self.inner.poll(cx, params) {
+                Poll::Ready(NetworkBehaviourAction::GenerateEvent(event)) => match event {
+                    RequestResponseEvent::Message {
+                        peer,
+                        message: RequestResponseMessage::Request { request, channel },
+                    } => self.handle_request(peer, request, channel),
+                    RequestResponseEvent::Message { .. } | RequestResponseEvent::OutboundFailure { .. } => {
+                        // The server never sends requests.
+                    }
+                    RequestResponseEvent::InboundFailure { peer, error } => {
+                        log::debug!("Inbound rendezvous request of {:?} failed: {:?}", peer, error);
+                    }
+                },
+                Poll::Ready(NetworkBehaviourAction::DialAddress { address }) =>
+                    return Poll::Ready(NetworkBehaviourAction::DialAddress { address }),
+                Poll::Ready(NetworkBehaviourAction::DialPeer { peer_id, condition }) =>
+                    return Poll::Ready(NetworkBehaviourAction::DialPeer { peer_id, condition }),
+                Poll::Ready(NetworkBehaviourAction::NotifyHandler { peer_id, handler, event }) =>
+                    return Poll::Ready(NetworkBehaviourAction::NotifyHandler { peer_id, handler, event }),
+                Poll::Ready(NetworkBehaviourAction::ReportObservedAddr { address, peer_id }) =>
+                    return Poll::Ready(NetworkBehaviourAction::ReportObservedAddr { address, peer_id }),
+                Poll::Ready(NetworkBehaviourAction::AddExternalAddr { address }) =>
+                    return Poll::Ready(NetworkBehaviourAction::AddExternalAddr { address }),
+                Poll::Ready(NetworkBehaviourAction::ConfirmExternalAddr { address }) =>
+                    return Poll::Ready(NetworkBehaviourAction::ConfirmExternalAddr { address }),
+                Poll::Ready(NetworkBehaviourAction::RemoveExternalAddr { address }) =>
+                    return Poll::Ready(NetworkBehaviourAction::RemoveExternalAddr { address }),
+                Poll::Ready(NetworkBehaviourAction::UpdateSupportedProtocols) =>
+                    return Poll::Ready(NetworkBehaviourAction::UpdateSupportedProtocols),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// The store of registrations held by a [`Server`].
+///
+/// Every registration is assigned an ID from an increasing counter, also on
+/// renewal, which allows cookies to point at the last registration returned
+/// to a client.
+#[derive(Default)]
+struct Registrations {
+    /// The ID of the last registration added.
+    last_id: u64,
+    /// The registrations by ID, in the order they were added.
+    registrations: BTreeMap<u64, Registration>,
+    /// The ID of the registration of a peer in a namespace.
+    ids: HashMap<(PeerId, Namespace), u64>,
+    /// Resolve to the ID of a registration when its TTL elapses.
+    expiries: FuturesUnordered<BoxFuture<'static, u64>>,
+}
+
+impl Registrations {
+    /// Adds a registration, replacing any previous registration of the same
+    /// peer in the same namespace.
+    fn add(&mut self, registration: Registration) {
+        self.remove(&registration.peer_id, &registration.namespace);
+        self.last_id += 1;
+        let id = self.last_id;
+        let ttl = Duration::from_secs(registration.ttl);
+        self.expiries.push(Delay::new(ttl).map(move |_| id).boxed());
+        self.ids.insert((registration.peer_id.clone(), registration.namespace.clone()), id);
+        self.registrations.insert(id, registration);
+    }
+
+    /// Removes the registration of a peer in a namespace.
+    fn remove(&mut self, peer_id: &PeerId, namespace: &Namespace) -> Option<Registration> {
+        let id = self.ids.remove(&(peer_id.clone(), namespace.clone()))?;
+        self.registrations.remove(&id)
+    }
+
+    /// Returns up to `limit` registrations in the given namespace, or in all
+    /// namespaces, made after the given cookie, along with the cookie for the
+    /// next request.
+    fn get(&self, namespace: Option<Namespace>, cookie: Option<Cookie>, limit: u64)
+        -> Result<(Vec<Registration>, Cookie), ErrorCode>
+    {
+        let last_seen = match cookie {
+            Some(cookie) if cookie.namespace() != namespace.as_ref() => return Err(ErrorCode::InvalidCookie),
+            Some(cookie) => cookie.id(),
+            None => 0,
+        };
+
+        let mut last_id = last_seen;
+        let registrations = self.registrations
+            .range(last_seen + 1 ..)
+            .filter(|(_, r)| namespace.as_ref().is_none_or(|ns| &r.namespace == ns))
+            .take(limit as usize)
+            .map(|(id, r)| {
+                last_id = *id;
+                r.clone()
+            })
+            .collect();
+
+        Ok((registrations, Cookie::new(last_id, namespace)))
+    }
+
+    /// Polls for the next expired registration.
+    fn poll_expired(&mut self, cx: &mut Context<'_>) -> Poll<Registration> {
+        while let Poll::Ready(Some(id)) = self.expiries.poll_next_unpin(cx) {
+            // Registrations that have been renewed or removed in the meantime
+            // are no longer under this ID.
+            if let Some(registration) = self.registrations.remove(&id) {
+                self.ids.remove(&(registration.peer_id.clone(), registration.namespace.clone()));
+                return Poll::Ready(registration);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn registration(namespace: &'static str, ttl: Ttl) -> Registration {
+        Registration {
+            namespace: Namespace::from_static(namespace),
+            peer_id: PeerId::random(),
+            addresses: Vec::new(),
+            ttl,
+        }
+    }
+
+    #[test]
+    fn cookie_only_returns_new_registrations() {
+        let mut registrations = Registrations::default();
+        registrations.add(registration("foo", 60));
+        registrations.add(registration("bar", 60));
+
+        let (first, cookie) = registrations.get(None, None, 10).unwrap();
+        assert_eq!(first.len(), 2);
+        let (none, cookie) = registrations.get(None, Some(cookie), 10).unwrap();
+        assert!(none.is_empty());
+
+        let new = registration("foo", 60);
+        registrations.add(new.clone());
+        let (second, _) = registrations.get(None, Some(cookie), 10).unwrap();
+        assert_eq!(second, vec![new]);
+    }
+
+    #[test]
+    fn discover_is_limited_and_paginated() {
+        let mut registrations = Registrations::default();
+        for _ in 0 .. 5 {
+            registrations.add(registration("foo", 60));
+        }
+        registrations.add(registration("bar", 60));
+
+        let foo = Some(Namespace::from_static("foo"));
+        let (page, cookie) = registrations.get(foo.clone(), None, 3).unwrap();
+        assert_eq!(page.len(), 3);
+        let (page, cookie) = registrations.get(foo.clone(), Some(cookie), 3).unwrap();
+        assert_eq!(page.len(), 2);
+        assert_eq!(
+            registrations.get(Some(Namespace::from_static("bar")), Some(cookie), 3),
+            Err(ErrorCode::InvalidCookie)
+        );
+    }
+
+    #[test]
+    fn renewal_replaces_registration() {
+        let mut registrations = Registrations::default();
+        let first = registration("foo", 60);
+        registrations.add(first.clone());
+        let renewed = Registration { ttl: 120, ..first };
+        registrations.add(renewed.clone());
+
+        let (all, _) = registrations.get(None, None, 10).unwrap();
+        assert_eq!(all, vec![renewed]);
+    }
+
+    #[test]
+    fn registrations_expire() {
+        let mut registrations = Registrations::default();
+        let expiring = registration("foo", 0);
+        registrations.add(expiring.clone());
+        registrations.add(registration("foo", 60));
+
+        let expired = block_on(future::poll_fn(|cx| registrations.poll_expired(cx)));
+        assert_eq!(expired, expiring);
+        assert_eq!(registrations.get(None, None, 10).unwrap().0.len(), 1);
+    }
+}
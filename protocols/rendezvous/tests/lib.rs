@@ -0,0 +1,179 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Integration tests for the rendezvous client and server roles.
+
+use futures::executor::block_on;
+use libp2p_core::{
+    identity,
+    multiaddr::{Multiaddr, Protocol},
+    muxing::StreamMuxerBox,
+    transport::{boxed::Boxed, MemoryTransport, Transport},
+    upgrade,
+    PeerId,
+};
+use libp2p_plaintext::PlainText2Config;
+use libp2p_rendezvous::{
+    Client,
+    ClientError,
+    ClientEvent,
+    ErrorCode,
+    Namespace,
+    Server,
+    ServerConfig,
+};
+use libp2p_swarm::{NetworkBehaviour, Swarm, SwarmEvent};
+use libp2p_yamux as yamux;
+use std::io;
+
+#[test]
+fn register_and_discover() {
+    let _ = env_logger::try_init();
+
+    let (server_id, server_addr) = spawn_server(ServerConfig::default());
+    let namespace = Namespace::from_static("chat");
+
+    let mut alice = build_client(&server_addr);
+    let alice_id = Swarm::local_peer_id(&alice).clone();
+    let alice_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    Swarm::add_external_address(&mut alice, alice_addr.clone());
+    alice.register(namespace.clone(), server_id.clone(), None);
+    block_on(async {
+        match alice.next().await {
+            ClientEvent::Registered { rendezvous_node, namespace: ns, .. } => {
+                assert_eq!(rendezvous_node, server_id);
+                assert_eq!(ns, namespace);
+            }
+            e => panic!("Unexpected event: {:?}", e),
+        }
+    });
+
+    let mut bob = build_client(&server_addr);
+    bob.discover(Some(namespace.clone()), None, None, server_id.clone());
+    let cookie = block_on(async {
+        match bob.next().await {
+            ClientEvent::Discovered { registrations, cookie, .. } => {
+                assert_eq!(registrations.len(), 1);
+                assert_eq!(registrations[0].peer_id, alice_id);
+                assert_eq!(registrations[0].addresses, vec![alice_addr.clone()]);
+                cookie
+            }
+            e => panic!("Unexpected event: {:?}", e),
+        }
+    });
+    assert_eq!(bob.addresses_of_peer(&alice_id), vec![alice_addr]);
+
+    // Nothing has been registered since the cookie was issued.
+    bob.discover(Some(namespace), Some(cookie), None, server_id);
+    block_on(async {
+        match bob.next().await {
+            ClientEvent::Discovered { registrations, .. } => assert!(registrations.is_empty()),
+            e => panic!("Unexpected event: {:?}", e),
+        }
+    });
+}
+
+#[test]
+fn registration_is_renewed() {
+    let _ = env_logger::try_init();
+
+    let mut config = ServerConfig::default();
+    config.set_min_ttl(1);
+    let (server_id, server_addr) = spawn_server(config);
+
+    let mut alice = build_client(&server_addr);
+    Swarm::add_external_address(&mut alice, Protocol::Memory(rand::random::<u64>()).into());
+    alice.register(Namespace::from_static("chat"), server_id, Some(1));
+    block_on(async {
+        for _ in 0 .. 2 {
+            match alice.next().await {
+                ClientEvent::Registered { ttl, .. } => assert_eq!(ttl, 1),
+                e => panic!("Unexpected event: {:?}", e),
+            }
+        }
+    });
+}
+
+#[test]
+fn invalid_registrations_are_rejected() {
+    let _ = env_logger::try_init();
+
+    let (server_id, server_addr) = spawn_server(ServerConfig::default());
+
+    let mut alice = build_client(&server_addr);
+    alice.register(Namespace::from_static("chat"), server_id.clone(), None);
+    block_on(async {
+        match alice.next().await {
+            ClientEvent::RegisterFailed { error: ClientError::NoExternalAddresses, .. } => {}
+            e => panic!("Unexpected event: {:?}", e),
+        }
+    });
+
+    Swarm::add_external_address(&mut alice, Protocol::Memory(rand::random::<u64>()).into());
+    alice.register(Namespace::from_static("chat"), server_id, Some(1));
+    block_on(async {
+        match alice.next().await {
+            ClientEvent::RegisterFailed { error: ClientError::Remote(ErrorCode::InvalidTtl), .. } => {}
+            e => panic!("Unexpected event: {:?}", e),
+        }
+    });
+}
+
+/// Spawns a server swarm in the background, returning its peer ID and
+/// listen address.
+fn spawn_server(config: ServerConfig) -> (PeerId, Multiaddr) {
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_peer_id = local_key.public().into_peer_id();
+    let mut server = Swarm::new(build_transport(local_key), Server::new(config), local_peer_id.clone());
+    let server_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    Swarm::listen_on(&mut server, server_addr.clone()).unwrap();
+    async_std::task::spawn(async move {
+        loop {
+            server.next().await;
+        }
+    });
+    (local_peer_id, server_addr)
+}
+
+/// Builds a client swarm connected to the server.
+fn build_client(server_addr: &Multiaddr) -> Swarm<Client> {
+    let local_key = identity::Keypair::generate_ed25519();
+    let local_peer_id = local_key.public().into_peer_id();
+    let mut client = Swarm::new(build_transport(local_key), Client::new(local_peer_id.clone()), local_peer_id);
+    Swarm::dial_addr(&mut client, server_addr.clone()).unwrap();
+    block_on(async {
+        loop {
+            if let SwarmEvent::ConnectionEstablished { .. } = client.next_event().await {
+                break;
+            }
+        }
+    });
+    client
+}
+
+fn build_transport(local_key: identity::Keypair) -> Boxed<(PeerId, StreamMuxerBox), io::Error> {
+    MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(PlainText2Config { local_public_key: local_key.public() })
+        .multiplex(yamux::Config::default())
+        .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+        .boxed()
+}
@@ -18,12 +18,27 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+#[cfg(feature = "cbor")]
+pub mod cbor;
+#[cfg(feature = "json")]
+pub mod json;
+
 pub use libp2p_core::ProtocolName;
 
 use async_trait::async_trait;
 use futures::prelude::*;
 use std::io;
 
+/// The default maximum size in bytes of an inbound request of the
+/// `serde` based codecs.
+#[cfg(any(feature = "cbor", feature = "json"))]
+pub const DEFAULT_MAX_REQUEST_SIZE: usize = 1024 * 1024;
+
+/// The default maximum size in bytes of an inbound response of the
+/// `serde` based codecs.
+#[cfg(any(feature = "cbor", feature = "json"))]
+pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 10 * 1024 * 1024;
+
 /// A `RequestResponseCodec` defines the request and response types
 /// for a [`RequestResponse`](crate::RequestResponse) protocol or
 /// protocol family and how they are encoded / decoded on an I/O stream.
@@ -64,3 +79,12 @@ pub trait RequestResponseCodec {
     where
         T: AsyncWrite + Unpin + Send;
 }
+
+/// Converts the error of reading a length-prefixed message into an I/O error.
+#[cfg(any(feature = "cbor", feature = "json"))]
+fn read_error(e: libp2p_core::upgrade::ReadOneError) -> io::Error {
+    match e {
+        libp2p_core::upgrade::ReadOneError::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
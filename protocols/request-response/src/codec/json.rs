@@ -0,0 +1,176 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! A [`RequestResponseCodec`] for `serde` types encoded as JSON.
+
+use crate::codec::{RequestResponseCodec, ProtocolName, read_error};
+use async_trait::async_trait;
+use futures::prelude::*;
+use libp2p_core::upgrade::{read_one, write_with_len_prefix};
+use serde::{Serialize, de::DeserializeOwned};
+use std::{fmt, io, marker::PhantomData};
+
+/// A [`RequestResponseCodec`] that encodes requests and responses as JSON.
+///
+/// Every message is prefixed with its length as an unsigned varint.
+/// Inbound messages larger than the configured maximum size are rejected
+/// before they are read.
+pub struct JsonCodec<P, Req, Resp> {
+    max_request_size: usize,
+    max_response_size: usize,
+    _marker: PhantomData<(P, Req, Resp)>,
+}
+
+impl<P, Req, Resp> JsonCodec<P, Req, Resp> {
+    /// Creates a new codec with the default maximum message sizes.
+    pub fn new() -> Self {
+        JsonCodec {
+            max_request_size: super::DEFAULT_MAX_REQUEST_SIZE,
+            max_response_size: super::DEFAULT_MAX_RESPONSE_SIZE,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets the maximum size in bytes of an inbound request.
+    pub fn set_max_request_size(&mut self, v: usize) -> &mut Self {
+        self.max_request_size = v;
+        self
+    }
+
+    /// Sets the maximum size in bytes of an inbound response.
+    pub fn set_max_response_size(&mut self, v: usize) -> &mut Self {
+        self.max_response_size = v;
+        self
+    }
+}
+
+impl<P, Req, Resp> Default for JsonCodec<P, Req, Resp> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P, Req, Resp> Clone for JsonCodec<P, Req, Resp> {
+    fn clone(&self) -> Self {
+        JsonCodec {
+            max_request_size: self.max_request_size,
+            max_response_size: self.max_response_size,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<P, Req, Resp> fmt::Debug for JsonCodec<P, Req, Resp> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonCodec")
+            .field("max_request_size", &self.max_request_size)
+            .field("max_response_size", &self.max_response_size)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<P, Req, Resp> RequestResponseCodec for JsonCodec<P, Req, Resp>
+where
+    P: ProtocolName + Send + Sync + Clone,
+    Req: Serialize + DeserializeOwned + Send,
+    Resp: Serialize + DeserializeOwned + Send,
+{
+    type Protocol = P;
+    type Request = Req;
+    type Response = Resp;
+
+    async fn read_request<T>(&mut self, _: &P, io: &mut T) -> io::Result<Req>
+    where
+        T: AsyncRead + Unpin + Send
+    {
+        let bytes = read_one(io, self.max_request_size).await.map_err(read_error)?;
+        serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    async fn read_response<T>(&mut self, _: &P, io: &mut T) -> io::Result<Resp>
+    where
+        T: AsyncRead + Unpin + Send
+    {
+        let bytes = read_one(io, self.max_response_size).await.map_err(read_error)?;
+        serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    async fn write_request<T>(&mut self, _: &P, io: &mut T, req: Req) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send
+    {
+        let bytes = serde_json::to_vec(&req).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        write_with_len_prefix(io, bytes).await
+    }
+
+    async fn write_response<T>(&mut self, _: &P, io: &mut T, res: Resp) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send
+    {
+        let bytes = serde_json::to_vec(&res).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        write_with_len_prefix(io, bytes).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, io::Cursor};
+    use serde::Deserialize;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Req { key: String, values: Vec<u32> }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    enum Resp { Found(Vec<u8>), NotFound }
+
+    type Codec = JsonCodec<&'static str, Req, Resp>;
+
+    #[test]
+    fn roundtrip() {
+        let req = Req { key: "foo".into(), values: vec![1, 2, 3] };
+        let res = Resp::Found(vec![4, 5, 6]);
+        let mut codec = Codec::new();
+
+        let mut io = Cursor::new(Vec::new());
+        block_on(codec.write_request(&"/json", &mut io, req.clone())).unwrap();
+        block_on(codec.write_response(&"/json", &mut io, res.clone())).unwrap();
+
+        io.set_position(0);
+        assert_eq!(block_on(codec.read_request(&"/json", &mut io)).unwrap(), req);
+        assert_eq!(block_on(codec.read_response(&"/json", &mut io)).unwrap(), res);
+    }
+
+    #[test]
+    fn oversized_request_is_rejected() {
+        let req = Req { key: "foo".into(), values: (0 .. 100).collect() };
+        let mut codec = Codec::new();
+        codec.set_max_request_size(16);
+
+        let mut io = Cursor::new(Vec::new());
+        block_on(codec.write_request(&"/json", &mut io, req)).unwrap();
+
+        io.set_position(0);
+        let err = block_on(codec.read_request(&"/json", &mut io)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
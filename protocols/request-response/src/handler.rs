@@ -20,10 +20,16 @@
 
 mod protocol;
 
-use crate::{EMPTY_QUEUE_SHRINK_THRESHOLD, RequestId};
+use crate::{EMPTY_QUEUE_SHRINK_THRESHOLD, RequestId, ResponseStream};
 use crate::codec::RequestResponseCodec;
 
-pub use protocol::{RequestProtocol, ResponseProtocol, ProtocolSupport};
+pub use protocol::{
+    IncomingResponse,
+    OutgoingResponse,
+    ProtocolSupport,
+    RequestProtocol,
+    ResponseProtocol,
+};
 
 use futures::{
     channel::oneshot,
@@ -68,6 +74,8 @@ where
     /// The timeout for inbound and outbound substreams (i.e. request
     /// and response processing).
     substream_timeout: Duration,
+    /// Whether responses are streamed.
+    streaming: bool,
     /// The current connection keep-alive.
     keep_alive: KeepAlive,
     /// A pending fatal error that results in the connection being closed.
@@ -79,9 +87,12 @@ where
     /// Inbound upgrades waiting for the incoming request.
     inbound: FuturesUnordered<BoxFuture<'static,
         Result<
-            (TCodec::Request, oneshot::Sender<TCodec::Response>),
+            (TCodec::Request, oneshot::Sender<OutgoingResponse<TCodec::Response>>),
             oneshot::Canceled
         >>>,
+    /// Streamed responses that are being written or read. The latter
+    /// complete when the corresponding `ResponseStream` is dropped.
+    streams: FuturesUnordered<BoxFuture<'static, ()>>,
 }
 
 impl<TCodec> RequestResponseHandler<TCodec>
@@ -93,15 +104,18 @@ where
         codec: TCodec,
         keep_alive_timeout: Duration,
         substream_timeout: Duration,
+        streaming: bool,
     ) -> Self {
         Self {
             inbound_protocols,
             codec,
+            streaming,
             keep_alive: KeepAlive::Yes,
             keep_alive_timeout,
             substream_timeout,
             outbound: VecDeque::new(),
             inbound: FuturesUnordered::new(),
+            streams: FuturesUnordered::new(),
             pending_events: VecDeque::new(),
             pending_error: None,
         }
@@ -117,13 +131,18 @@ where
     /// An inbound request.
     Request {
         request: TCodec::Request,
-        sender: oneshot::Sender<TCodec::Response>
+        sender: oneshot::Sender<OutgoingResponse<TCodec::Response>>
     },
     /// An inbound response.
     Response {
         request_id: RequestId,
         response: TCodec::Response
     },
+    /// The start of an inbound streamed response.
+    ResponseStream {
+        request_id: RequestId,
+        stream: ResponseStream<TCodec::Response>
+    },
     /// An outbound upgrade (i.e. request) timed out.
     OutboundTimeout(RequestId),
     /// An outbound request failed to negotiate a mutually supported protocol.
@@ -165,6 +184,7 @@ where
             codec: self.codec.clone(),
             request_sender: rq_send,
             response_receiver: rs_recv,
+            streaming: self.streaming,
         };
 
         // The handler waits for the request to come in. It then emits
@@ -177,21 +197,35 @@ where
 
     fn inject_fully_negotiated_inbound(
         &mut self,
-        (): (),
+        write: Option<BoxFuture<'static, ()>>,
     ) {
-        // Nothing to do, as the response has already been sent
-        // as part of the upgrade.
+        // A single response has already been sent as part of the upgrade,
+        // whereas the chunks of a streamed response are written as they
+        // are produced.
+        if let Some(write) = write {
+            self.keep_alive = KeepAlive::Yes;
+            self.streams.push(write);
+        }
     }
 
     fn inject_fully_negotiated_outbound(
         &mut self,
-        response: TCodec::Response,
+        response: IncomingResponse<TCodec::Response>,
         request_id: RequestId,
     ) {
-        self.pending_events.push_back(
-            RequestResponseHandlerEvent::Response {
-                request_id, response
-            });
+        let event = match response {
+            IncomingResponse::Single(response) =>
+                RequestResponseHandlerEvent::Response { request_id, response },
+            IncomingResponse::Stream(chunks) => {
+                // Keep the connection alive until the stream is dropped.
+                let (guard, dropped) = oneshot::channel::<()>();
+                self.keep_alive = KeepAlive::Yes;
+                self.streams.push(dropped.map(|_| ()).boxed());
+                let stream = ResponseStream { chunks, _guard: guard };
+                RequestResponseHandlerEvent::ResponseStream { request_id, stream }
+            }
+        };
+        self.pending_events.push_back(event);
     }
 
     fn inject_event(&mut self, request: Self::InEvent) {
@@ -275,6 +309,9 @@ where
             self.pending_events.shrink_to_fit();
         }
 
+        // Make progress on streamed responses.
+        while let Poll::Ready(Some(())) = self.streams.poll_next_unpin(cx) {}
+
         // Check for inbound requests.
         while let Poll::Ready(Some(result)) = self.inbound.poll_next_unpin(cx) {
             match result {
@@ -312,7 +349,9 @@ where
             self.outbound.shrink_to_fit();
         }
 
-        if self.inbound.is_empty() {
+        if !self.streams.is_empty() {
+            self.keep_alive = KeepAlive::Yes;
+        } else if self.inbound.is_empty() {
             // No new inbound or outbound requests. However, we may just have
             // started the latest inbound or outbound upgrade(s), so make sure
             // the keep-alive timeout is preceded by the substream timeout.
@@ -27,9 +27,10 @@ use crate::RequestId;
 use crate::codec::RequestResponseCodec;
 
 use futures::{
-    channel::oneshot,
+    channel::{mpsc, oneshot},
     future::BoxFuture,
     prelude::*,
+    stream::{self, BoxStream},
 };
 use libp2p_core::{
     upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo},
@@ -69,6 +70,30 @@ impl ProtocolSupport {
     }
 }
 
+/// Marks that another response chunk follows on a streamed response.
+const CHUNK: u8 = 1;
+/// Marks the end of a streamed response.
+const END: u8 = 0;
+
+/// A response to send for an inbound request.
+#[doc(hidden)]
+pub enum OutgoingResponse<TResponse> {
+    /// A single response message.
+    Single(TResponse),
+    /// A sequence of response chunks, ending when the sender is dropped.
+    Stream(mpsc::Receiver<TResponse>),
+}
+
+/// A response received for an outbound request.
+#[doc(hidden)]
+pub enum IncomingResponse<TResponse> {
+    /// A single response message.
+    Single(TResponse),
+    /// A sequence of response chunks, read from the substream
+    /// as the stream is polled.
+    Stream(BoxStream<'static, io::Result<TResponse>>),
+}
+
 /// Response substream upgrade protocol.
 ///
 /// Receives a request and sends a response.
@@ -80,7 +105,8 @@ where
     pub(crate) codec: TCodec,
     pub(crate) protocols: SmallVec<[TCodec::Protocol; 2]>,
     pub(crate) request_sender: oneshot::Sender<TCodec::Request>,
-    pub(crate) response_receiver: oneshot::Receiver<TCodec::Response>
+    pub(crate) response_receiver: oneshot::Receiver<OutgoingResponse<TCodec::Response>>,
+    pub(crate) streaming: bool,
 }
 
 impl<TCodec> UpgradeInfo for ResponseProtocol<TCodec>
@@ -99,7 +125,9 @@ impl<TCodec> InboundUpgrade<NegotiatedSubstream> for ResponseProtocol<TCodec>
 where
     TCodec: RequestResponseCodec + Send + 'static,
 {
-    type Output = ();
+    /// A streamed response is written by the returned future,
+    /// independently of the upgrade timeout.
+    type Output = Option<BoxFuture<'static, ()>>;
     type Error = io::Error;
     type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
 
@@ -108,13 +136,26 @@ where
             let read = self.codec.read_request(&protocol, &mut io);
             let request = read.await?;
             if let Ok(()) = self.request_sender.send(request) {
-                if let Ok(response) = self.response_receiver.await {
-                    let write = self.codec.write_response(&protocol, &mut io, response);
-                    write.await?;
+                match self.response_receiver.await {
+                    Ok(OutgoingResponse::Single(response)) => {
+                        if self.streaming {
+                            io.write_all(&[CHUNK]).await?;
+                        }
+                        let write = self.codec.write_response(&protocol, &mut io, response);
+                        write.await?;
+                        if self.streaming {
+                            io.write_all(&[END]).await?;
+                        }
+                    }
+                    Ok(OutgoingResponse::Stream(chunks)) => {
+                        let write = write_chunks(self.codec, protocol, io, chunks);
+                        return Ok(Some(write.boxed()))
+                    }
+                    Err(oneshot::Canceled) => {}
                 }
             }
             io.close().await?;
-            Ok(())
+            Ok(None)
         }.boxed()
     }
 }
@@ -131,6 +172,7 @@ where
     pub(crate) protocols: SmallVec<[TCodec::Protocol; 2]>,
     pub(crate) request_id: RequestId,
     pub(crate) request: TCodec::Request,
+    pub(crate) streaming: bool,
 }
 
 impl<TCodec> UpgradeInfo for RequestProtocol<TCodec>
@@ -149,7 +191,7 @@ impl<TCodec> OutboundUpgrade<NegotiatedSubstream> for RequestProtocol<TCodec>
 where
     TCodec: RequestResponseCodec + Send + 'static,
 {
-    type Output = TCodec::Response;
+    type Output = IncomingResponse<TCodec::Response>;
     type Error = io::Error;
     type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
 
@@ -158,9 +200,67 @@ where
             let write = self.codec.write_request(&protocol, &mut io, self.request);
             write.await?;
             io.close().await?;
-            let read = self.codec.read_response(&protocol, &mut io);
-            let response = read.await?;
-            Ok(response)
+            if !self.streaming {
+                let read = self.codec.read_response(&protocol, &mut io);
+                let response = read.await?;
+                return Ok(IncomingResponse::Single(response))
+            }
+            // Only wait for the start of a streamed response as part of the
+            // upgrade, so that it is subject to the substream timeout. The
+            // chunks are read as the returned stream is polled.
+            let first = read_marker(&mut io).await?;
+            let chunks = stream::try_unfold(
+                (self.codec, protocol, io, Some(first)),
+                |(mut codec, protocol, mut io, marker)| async move {
+                    let more = match marker {
+                        Some(more) => more,
+                        None => read_marker(&mut io).await?,
+                    };
+                    if !more {
+                        return Ok(None)
+                    }
+                    let chunk = codec.read_response(&protocol, &mut io).await?;
+                    Ok(Some((chunk, (codec, protocol, io, None))))
+                });
+            Ok(IncomingResponse::Stream(chunks.boxed()))
         }.boxed()
     }
 }
+
+/// Writes the chunks of a streamed response, each preceded by a marker,
+/// until the sender of the chunks is dropped.
+///
+/// Errors end the stream, e.g. if the remote dropped the response stream,
+/// which is noticed by the local sender as the chunk receiver is dropped.
+async fn write_chunks<TCodec>(
+    mut codec: TCodec,
+    protocol: TCodec::Protocol,
+    mut io: NegotiatedSubstream,
+    mut chunks: mpsc::Receiver<TCodec::Response>,
+)
+where
+    TCodec: RequestResponseCodec,
+{
+    let write = async move {
+        while let Some(chunk) = chunks.next().await {
+            io.write_all(&[CHUNK]).await?;
+            codec.write_response(&protocol, &mut io, chunk).await?;
+            io.flush().await?;
+        }
+        io.write_all(&[END]).await?;
+        io.close().await
+    };
+    let _: io::Result<()> = write.await;
+}
+
+/// Reads the marker preceding a chunk of a streamed response, returning
+/// whether another chunk follows.
+async fn read_marker(io: &mut NegotiatedSubstream) -> io::Result<bool> {
+    let mut marker = [0];
+    io.read_exact(&mut marker).await?;
+    match marker[0] {
+        CHUNK => Ok(true),
+        END => Ok(false),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid response chunk marker")),
+    }
+}
@@ -39,6 +39,23 @@
 //! receiving a [`RequestResponseMessage::Request`] via
 //! [`RequestResponseEvent::Message`].
 //!
+//! ## Serde Codecs
+//!
+//! For simple protocols whose messages are `serde` types, the `cbor` and
+//! `json` features provide the ready-made codecs `CborCodec` and `JsonCodec`.
+//! Each message is sent with an unsigned varint length prefix and inbound
+//! messages are limited to a configurable maximum size.
+//!
+//! ## Streaming Responses
+//!
+//! Large responses can be streamed as a sequence of chunks by enabling
+//! [`RequestResponseConfig::set_streaming_responses`] on both peers.
+//! The responder then sends the chunks into the [`ResponseSink`] returned
+//! by [`RequestResponse::send_response_stream`] and the requester receives
+//! them from a [`ResponseStream`] via [`RequestResponseMessage::ResponseStream`].
+//! Each chunk is encoded with [`RequestResponseCodec::write_response`], which
+//! must therefore not close the substream.
+//!
 //! ## Protocol Families
 //!
 //! A single [`RequestResponse`] instance can be used with an entire
@@ -70,14 +87,23 @@
 
 pub mod codec;
 pub mod handler;
+pub mod throttled;
 
 pub use codec::{RequestResponseCodec, ProtocolName};
+#[cfg(feature = "cbor")]
+pub use codec::cbor::CborCodec;
+#[cfg(feature = "json")]
+pub use codec::json::JsonCodec;
 pub use handler::ProtocolSupport;
+pub use throttled::{Throttled, ThrottleConfig, ThrottleError};
 
 use futures::{
-    channel::oneshot,
+    channel::{mpsc, oneshot},
+    prelude::*,
+    stream::BoxStream,
 };
 use handler::{
+    OutgoingResponse,
     RequestProtocol,
     RequestResponseHandler,
     RequestResponseHandlerEvent,
@@ -98,6 +124,9 @@ use libp2p_swarm::{
 use smallvec::SmallVec;
 use std::{
     collections::{VecDeque, HashMap},
+    fmt,
+    io,
+    pin::Pin,
     time::Duration,
     task::{Context, Poll}
 };
@@ -123,6 +152,17 @@ pub enum RequestResponseMessage<TRequest, TResponse> {
         /// The response message.
         response: TResponse
     },
+    /// A streamed response, if streaming responses are enabled.
+    ///
+    /// See [`RequestResponseConfig::set_streaming_responses`].
+    ResponseStream {
+        /// The ID of the request that produced this response.
+        ///
+        /// See [`RequestResponse::send_request`].
+        request_id: RequestId,
+        /// The chunks of the response.
+        stream: ResponseStream<TResponse>
+    },
 }
 
 /// The events emitted by a [`RequestResponse`] protocol.
@@ -184,15 +224,27 @@ pub enum InboundFailure {
     Timeout,
     /// The local peer supports none of the requested protocols.
     UnsupportedProtocols,
+    /// The request was rejected by [`Throttled`] and dropped without
+    /// a response.
+    Throttled(ThrottleError),
 }
 
 /// A channel for sending a response to an inbound request.
 ///
 /// See [`RequestResponse::send_response`].
-#[derive(Debug)]
 pub struct ResponseChannel<TResponse> {
     peer: PeerId,
-    sender: oneshot::Sender<TResponse>,
+    sender: oneshot::Sender<OutgoingResponse<TResponse>>,
+    streaming: bool,
+}
+
+impl<TResponse> fmt::Debug for ResponseChannel<TResponse> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseChannel")
+            .field("peer", &self.peer)
+            .field("streaming", &self.streaming)
+            .finish()
+    }
 }
 
 impl<TResponse> ResponseChannel<TResponse> {
@@ -208,6 +260,70 @@ impl<TResponse> ResponseChannel<TResponse> {
     }
 }
 
+/// The chunks of a streamed response to an outbound request.
+///
+/// Chunks are only read from the connection as the stream is polled,
+/// so a slow consumer slows down the sender. Dropping the stream before
+/// it ends cancels the response.
+pub struct ResponseStream<TResponse> {
+    chunks: BoxStream<'static, io::Result<TResponse>>,
+    /// Keeps the connection alive until the stream is dropped.
+    _guard: oneshot::Sender<()>,
+}
+
+impl<TResponse> Stream for ResponseStream<TResponse> {
+    type Item = io::Result<TResponse>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.chunks.poll_next_unpin(cx)
+    }
+}
+
+impl<TResponse> fmt::Debug for ResponseStream<TResponse> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseStream").finish()
+    }
+}
+
+/// A sink for the chunks of a streamed response to an inbound request.
+///
+/// The response ends when the sink is closed or dropped. Sending blocks
+/// while the remote is not reading the response and fails once the
+/// remote has dropped the response stream.
+///
+/// See [`RequestResponse::send_response_stream`].
+#[derive(Debug)]
+pub struct ResponseSink<TResponse> {
+    sender: mpsc::Sender<TResponse>,
+}
+
+impl<TResponse> ResponseSink<TResponse> {
+    /// Checks whether the remote may still receive chunks.
+    pub fn is_open(&self) -> bool {
+        !self.sender.is_closed()
+    }
+}
+
+impl<TResponse> Sink<TResponse> for ResponseSink<TResponse> {
+    type Error = mpsc::SendError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.sender).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: TResponse) -> Result<(), Self::Error> {
+        Pin::new(&mut self.sender).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.sender).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.sender).poll_close(cx)
+    }
+}
+
 /// The (local) ID of an outgoing request.
 ///
 /// See [`RequestResponse::send_request`].
@@ -219,6 +335,7 @@ pub struct RequestId(u64);
 pub struct RequestResponseConfig {
     request_timeout: Duration,
     connection_keep_alive: Duration,
+    streaming_responses: bool,
 }
 
 impl Default for RequestResponseConfig {
@@ -226,6 +343,7 @@ impl Default for RequestResponseConfig {
         Self {
             connection_keep_alive: Duration::from_secs(10),
             request_timeout: Duration::from_secs(10),
+            streaming_responses: false,
         }
     }
 }
@@ -238,10 +356,25 @@ impl RequestResponseConfig {
     }
 
     /// Sets the timeout for inbound and outbound requests.
+    ///
+    /// For streamed responses, the timeout only applies until the
+    /// start of the response.
     pub fn set_request_timeout(&mut self, v: Duration) -> &mut Self {
         self.request_timeout = v;
         self
     }
+
+    /// Sets whether responses are streamed as a sequence of chunks.
+    ///
+    /// Streamed responses are framed differently on the wire, so the
+    /// remote must use the same setting. Responses are then received as
+    /// [`RequestResponseMessage::ResponseStream`] and may be sent with
+    /// [`RequestResponse::send_response_stream`]. Responses sent with
+    /// [`RequestResponse::send_response`] are streamed as a single chunk.
+    pub fn set_streaming_responses(&mut self, v: bool) -> &mut Self {
+        self.streaming_responses = v;
+        self
+    }
 }
 
 /// A request/response protocol for some message codec.
@@ -323,11 +456,18 @@ where
     /// > [`RequestResponse::remove_address`].
     pub fn send_request(&mut self, peer: &PeerId, request: TCodec::Request) -> RequestId {
         let request_id = self.next_request_id();
+        self.send_request_with_id(peer, request_id, request);
+        request_id
+    }
+
+    /// Initiates sending a request with a previously allocated ID.
+    fn send_request_with_id(&mut self, peer: &PeerId, request_id: RequestId, request: TCodec::Request) {
         let request = RequestProtocol {
             request_id,
             codec: self.codec.clone(),
             protocols: self.outbound_protocols.clone(),
             request,
+            streaming: self.config.streaming_responses,
         };
 
         if let Some(request) = self.try_send_request(peer, request) {
@@ -337,8 +477,6 @@ where
             });
             self.pending_requests.entry(peer.clone()).or_default().push(request);
         }
-
-        request_id
     }
 
     /// Initiates sending a response to an inbound request.
@@ -353,7 +491,25 @@ where
         // Fails only if the inbound upgrade timed out waiting for the response,
         // in which case the handler emits `RequestResponseHandlerEvent::InboundTimeout`
         // which in turn results in `RequestResponseEvent::InboundFailure`.
-        let _ = ch.sender.send(rs);
+        let _ = ch.sender.send(OutgoingResponse::Single(rs));
+    }
+
+    /// Initiates sending a streamed response to an inbound request,
+    /// returning the sink for the chunks of the response.
+    ///
+    /// The `ResponseChannel` is given back if streaming responses are not
+    /// enabled via [`RequestResponseConfig::set_streaming_responses`].
+    /// If the channel is already closed due to a timeout, the returned
+    /// sink is closed as well.
+    pub fn send_response_stream(&mut self, ch: ResponseChannel<TCodec::Response>)
+        -> Result<ResponseSink<TCodec::Response>, ResponseChannel<TCodec::Response>>
+    {
+        if !ch.streaming {
+            return Err(ch)
+        }
+        let (sender, receiver) = mpsc::channel(0);
+        let _ = ch.sender.send(OutgoingResponse::Stream(receiver));
+        Ok(ResponseSink { sender })
     }
 
     /// Adds a known address for a peer that can be used for
@@ -431,6 +587,7 @@ where
             self.codec.clone(),
             self.config.connection_keep_alive,
             self.config.request_timeout,
+            self.config.streaming_responses,
         )
     }
 
@@ -531,8 +688,16 @@ where
                     NetworkBehaviourAction::GenerateEvent(
                         RequestResponseEvent::Message { peer, message }));
             }
+            RequestResponseHandlerEvent::ResponseStream { request_id, stream } => {
+                self.pending_responses.remove(&request_id);
+                let message = RequestResponseMessage::ResponseStream { request_id, stream };
+                self.pending_events.push_back(
+                    NetworkBehaviourAction::GenerateEvent(
+                        RequestResponseEvent::Message { peer, message }));
+            }
             RequestResponseHandlerEvent::Request { request, sender } => {
-                let channel = ResponseChannel { peer: peer.clone(), sender };
+                let streaming = self.config.streaming_responses;
+                let channel = ResponseChannel { peer: peer.clone(), sender, streaming };
                 let message = RequestResponseMessage::Request { request, channel };
                 self.pending_events.push_back(
                     NetworkBehaviourAction::GenerateEvent(
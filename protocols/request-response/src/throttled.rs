@@ -0,0 +1,494 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+
+//! Limits on the number of concurrent requests of a [`RequestResponse`] protocol.
+//!
+//! [`Throttled`] wraps a [`RequestResponse`] behaviour and limits the number
+//! of requests in flight, per peer and in total, separately for inbound and
+//! outbound requests. Excess requests are queued until a request completes.
+//! Once the queue of a peer or the queue as a whole is full, further
+//! requests are rejected with a [`ThrottleError`].
+//!
+//! An outbound request is in flight until its response is received or
+//! the request failed. An inbound request is in flight from the moment
+//! it is emitted as a [`RequestResponseMessage::Request`] until a response
+//! is sent (or a streamed response started), the [`ResponseChannel`] is
+//! dropped or the request timed out. Rejected inbound requests are dropped
+//! without a response and reported as [`InboundFailure::Throttled`].
+
+use crate::{
+    InboundFailure,
+    RequestId,
+    RequestResponse,
+    RequestResponseCodec,
+    RequestResponseEvent,
+    RequestResponseMessage,
+    ResponseChannel,
+    ResponseSink,
+};
+use crate::handler::{OutgoingResponse, RequestProtocol, RequestResponseHandler, RequestResponseHandlerEvent};
+use futures::{channel::oneshot, prelude::*, stream::FuturesUnordered};
+use libp2p_core::{ConnectedPoint, Multiaddr, PeerId, connection::ConnectionId};
+use libp2p_swarm::{NetworkBehaviour, NetworkBehaviourAction, PollParameters};
+use std::{
+    collections::{HashMap, VecDeque},
+    error,
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// The limits of a [`Throttled`] behaviour.
+#[derive(Debug, Clone)]
+pub struct ThrottleConfig {
+    max_inbound_per_peer: usize,
+    max_inbound: usize,
+    max_outbound_per_peer: usize,
+    max_outbound: usize,
+    max_queued_per_peer: usize,
+    max_queued: usize,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        ThrottleConfig {
+            max_inbound_per_peer: 8,
+            max_inbound: 256,
+            max_outbound_per_peer: 8,
+            max_outbound: 256,
+            max_queued_per_peer: 32,
+            max_queued: 1024,
+        }
+    }
+}
+
+impl ThrottleConfig {
+    /// Sets the maximum number of inbound requests of a single peer in flight.
+    pub fn set_max_inbound_per_peer(&mut self, v: usize) -> &mut Self {
+        self.max_inbound_per_peer = v;
+        self
+    }
+
+    /// Sets the maximum number of inbound requests in flight.
+    pub fn set_max_inbound(&mut self, v: usize) -> &mut Self {
+        self.max_inbound = v;
+        self
+    }
+
+    /// Sets the maximum number of outbound requests to a single peer in flight.
+    pub fn set_max_outbound_per_peer(&mut self, v: usize) -> &mut Self {
+        self.max_outbound_per_peer = v;
+        self
+    }
+
+    /// Sets the maximum number of outbound requests in flight.
+    pub fn set_max_outbound(&mut self, v: usize) -> &mut Self {
+        self.max_outbound = v;
+        self
+    }
+
+    /// Sets the maximum number of queued requests of a single peer,
+    /// for inbound and outbound requests each.
+    pub fn set_max_queued_per_peer(&mut self, v: usize) -> &mut Self {
+        self.max_queued_per_peer = v;
+        self
+    }
+
+    /// Sets the maximum number of queued requests, for inbound
+    /// and outbound requests each.
+    pub fn set_max_queued(&mut self, v: usize) -> &mut Self {
+        self.max_queued = v;
+        self
+    }
+}
+
+/// The reason for rejecting a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottleError {
+    /// Too many requests of the peer are already queued.
+    PeerQueueFull,
+    /// Too many requests are already queued in total.
+    QueueFull,
+}
+
+impl fmt::Display for ThrottleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThrottleError::PeerQueueFull => write!(f, "Too many queued requests of the peer"),
+            ThrottleError::QueueFull => write!(f, "Too many queued requests"),
+        }
+    }
+}
+
+impl error::Error for ThrottleError {}
+
+/// A [`RequestResponse`] behaviour limiting the number of concurrent requests.
+pub struct Throttled<TCodec>
+where
+    TCodec: RequestResponseCodec,
+{
+    /// The wrapped behaviour.
+    behaviour: RequestResponse<TCodec>,
+    /// The configured limits.
+    config: ThrottleConfig,
+    /// Outbound requests in flight and the peers they are sent to.
+    outbound: HashMap<RequestId, PeerId>,
+    /// The number of outbound requests in flight.
+    outbound_count: Counter,
+    /// Outbound requests waiting to be sent.
+    outbound_queue: VecDeque<(PeerId, RequestId, TCodec::Request)>,
+    /// The number of queued outbound requests.
+    outbound_queued: Counter,
+    /// Inbound requests in flight, waiting for their response.
+    inbound: FuturesUnordered<PendingResponse<TCodec::Response>>,
+    /// The number of inbound requests in flight.
+    inbound_count: Counter,
+    /// Inbound requests waiting to be emitted.
+    inbound_queue: VecDeque<(PeerId, TCodec::Request, ResponseChannel<TCodec::Response>)>,
+    /// The number of queued inbound requests.
+    inbound_queued: Counter,
+    /// Pending events to return from `poll`.
+    pending_events: VecDeque<RequestResponseEvent<TCodec::Request, TCodec::Response>>,
+}
+
+impl<TCodec> Throttled<TCodec>
+where
+    TCodec: RequestResponseCodec + Clone,
+{
+    /// Wraps the given behaviour, limiting its requests according to `config`.
+    pub fn new(behaviour: RequestResponse<TCodec>, config: ThrottleConfig) -> Self {
+        Throttled {
+            behaviour,
+            config,
+            outbound: HashMap::new(),
+            outbound_count: Counter::default(),
+            outbound_queue: VecDeque::new(),
+            outbound_queued: Counter::default(),
+            inbound: FuturesUnordered::new(),
+            inbound_count: Counter::default(),
+            inbound_queue: VecDeque::new(),
+            inbound_queued: Counter::default(),
+            pending_events: VecDeque::new(),
+        }
+    }
+
+    /// Initiates sending a request, or queues it if too many requests
+    /// are in flight.
+    ///
+    /// See [`RequestResponse::send_request`].
+    pub fn send_request(&mut self, peer: &PeerId, request: TCodec::Request)
+        -> Result<RequestId, ThrottleError>
+    {
+        let cfg = &self.config;
+        let request_id = if self.outbound_queued.get(peer) == 0
+            && self.outbound_count.has_room(peer, cfg.max_outbound_per_peer, cfg.max_outbound)
+        {
+            let request_id = self.behaviour.next_request_id();
+            self.outbound_count.increment(peer);
+            self.outbound.insert(request_id, peer.clone());
+            self.behaviour.send_request_with_id(peer, request_id, request);
+            request_id
+        } else {
+            self.outbound_queued.check(peer, cfg.max_queued_per_peer, cfg.max_queued)?;
+            let request_id = self.behaviour.next_request_id();
+            self.outbound_queued.increment(peer);
+            self.outbound_queue.push_back((peer.clone(), request_id, request));
+            request_id
+        };
+        Ok(request_id)
+    }
+
+    /// Initiates sending a response to an inbound request.
+    ///
+    /// See [`RequestResponse::send_response`].
+    pub fn send_response(&mut self, ch: ResponseChannel<TCodec::Response>, rs: TCodec::Response) {
+        self.behaviour.send_response(ch, rs)
+    }
+
+    /// Initiates sending a streamed response to an inbound request.
+    ///
+    /// See [`RequestResponse::send_response_stream`].
+    pub fn send_response_stream(&mut self, ch: ResponseChannel<TCodec::Response>)
+        -> Result<ResponseSink<TCodec::Response>, ResponseChannel<TCodec::Response>>
+    {
+        self.behaviour.send_response_stream(ch)
+    }
+
+    /// Adds a known address for a peer.
+    ///
+    /// See [`RequestResponse::add_address`].
+    pub fn add_address(&mut self, peer: &PeerId, address: Multiaddr) {
+        self.behaviour.add_address(peer, address)
+    }
+
+    /// Removes an address of a peer previously added via `add_address`.
+    pub fn remove_address(&mut self, peer: &PeerId, address: &Multiaddr) {
+        self.behaviour.remove_address(peer, address)
+    }
+
+    /// Checks whether a peer is currently connected.
+    pub fn is_connected(&self, peer: &PeerId) -> bool {
+        self.behaviour.is_connected(peer)
+    }
+
+    /// Checks whether an outbound request initiated by
+    /// [`Throttled::send_request`] is still queued or waiting
+    /// for a response.
+    pub fn is_pending(&self, req_id: &RequestId) -> bool {
+        self.behaviour.is_pending(req_id)
+            || self.outbound_queue.iter().any(|(_, id, _)| id == req_id)
+    }
+
+    /// Handles an event of the wrapped behaviour, admitting inbound
+    /// requests and accounting for completed outbound requests.
+    fn on_event(&mut self, event: RequestResponseEvent<TCodec::Request, TCodec::Response>) {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message: RequestResponseMessage::Request { request, channel }
+            } => {
+                let cfg = &self.config;
+                if self.inbound_queued.get(&peer) == 0
+                    && self.inbound_count.has_room(&peer, cfg.max_inbound_per_peer, cfg.max_inbound)
+                {
+                    self.emit_request(peer, request, channel);
+                } else if let Err(e) = self.inbound_queued.check(&peer, cfg.max_queued_per_peer, cfg.max_queued) {
+                    drop(channel);
+                    self.pending_events.push_back(RequestResponseEvent::InboundFailure {
+                        peer,
+                        error: InboundFailure::Throttled(e),
+                    });
+                } else {
+                    self.inbound_queued.increment(&peer);
+                    self.inbound_queue.push_back((peer, request, channel));
+                }
+            }
+            RequestResponseEvent::Message { message: RequestResponseMessage::Response { request_id, .. }, .. }
+            | RequestResponseEvent::Message { message: RequestResponseMessage::ResponseStream { request_id, .. }, .. }
+            | RequestResponseEvent::OutboundFailure { request_id, .. } => {
+                if let Some(peer) = self.outbound.remove(&request_id) {
+                    self.outbound_count.decrement(&peer);
+                }
+                self.pending_events.push_back(event);
+            }
+            RequestResponseEvent::InboundFailure { .. } => {
+                self.pending_events.push_back(event);
+            }
+        }
+    }
+
+    /// Emits an inbound request, keeping track of its response.
+    fn emit_request(
+        &mut self,
+        peer: PeerId,
+        request: TCodec::Request,
+        channel: ResponseChannel<TCodec::Response>,
+    ) {
+        let (sender, receiver) = oneshot::channel();
+        self.inbound_count.increment(&peer);
+        self.inbound.push(PendingResponse {
+            peer: peer.clone(),
+            receiver,
+            sender: Some(channel.sender),
+        });
+        let channel = ResponseChannel { peer: channel.peer, sender, streaming: channel.streaming };
+        let message = RequestResponseMessage::Request { request, channel };
+        self.pending_events.push_back(RequestResponseEvent::Message { peer, message });
+    }
+
+    /// Sends queued outbound requests and emits queued inbound
+    /// requests, as far as the limits permit.
+    fn dequeue(&mut self) {
+        let (max_per_peer, max) = (self.config.max_outbound_per_peer, self.config.max_outbound);
+        while let Some(i) = self.outbound_queue.iter().position(|(peer, ..)|
+            self.outbound_count.has_room(peer, max_per_peer, max))
+        {
+            let (peer, request_id, request) = self.outbound_queue.remove(i).expect("valid index");
+            self.outbound_queued.decrement(&peer);
+            self.outbound_count.increment(&peer);
+            self.outbound.insert(request_id, peer.clone());
+            self.behaviour.send_request_with_id(&peer, request_id, request);
+        }
+
+        let (max_per_peer, max) = (self.config.max_inbound_per_peer, self.config.max_inbound);
+        while let Some(i) = self.inbound_queue.iter().position(|(peer, ..)|
+            self.inbound_count.has_room(peer, max_per_peer, max))
+        {
+            let (peer, request, channel) = self.inbound_queue.remove(i).expect("valid index");
+            self.inbound_queued.decrement(&peer);
+            // A request that timed out while queued is reported
+            // as an `InboundFailure` by the wrapped behaviour.
+            if channel.is_open() {
+                self.emit_request(peer, request, channel);
+            }
+        }
+    }
+}
+
+impl<TCodec> NetworkBehaviour for Throttled<TCodec>
+where
+    TCodec: RequestResponseCodec + Send + Clone + 'static,
+{
+    type ProtocolsHandler = RequestResponseHandler<TCodec>;
+    type OutEvent = RequestResponseEvent<TCodec::Request, TCodec::Response>;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        self.behaviour.new_handler()
+    }
+
+    fn addresses_of_peer(&mut self, peer: &PeerId) -> Vec<Multiaddr> {
+        self.behaviour.addresses_of_peer(peer)
+    }
+
+    fn inject_connected(&mut self, peer: &PeerId) {
+        self.behaviour.inject_connected(peer)
+    }
+
+    fn inject_connection_established(&mut self, peer: &PeerId, conn: &ConnectionId, endpoint: &ConnectedPoint) {
+        self.behaviour.inject_connection_established(peer, conn, endpoint)
+    }
+
+    fn inject_connection_closed(&mut self, peer: &PeerId, conn: &ConnectionId, endpoint: &ConnectedPoint) {
+        self.behaviour.inject_connection_closed(peer, conn, endpoint)
+    }
+
+    fn inject_disconnected(&mut self, peer: &PeerId) {
+        self.behaviour.inject_disconnected(peer)
+    }
+
+    fn inject_dial_failure(&mut self, peer: &PeerId) {
+        self.behaviour.inject_dial_failure(peer)
+    }
+
+    fn inject_event(
+        &mut self,
+        peer: PeerId,
+        conn: ConnectionId,
+        event: RequestResponseHandlerEvent<TCodec>,
+    ) {
+        self.behaviour.inject_event(peer, conn, event)
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>, params: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<
+            RequestProtocol<TCodec>,
+            RequestResponseEvent<TCodec::Request, TCodec::Response>
+        >>
+    {
+        loop {
+            while let Poll::Ready(Some(peer)) = self.inbound.poll_next_unpin(cx) {
+                self.inbound_count.decrement(&peer);
+            }
+
+            self.dequeue();
+
+            if let Some(event) = self.pending_events.pop_front() {
+                return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event))
+            }
+
+            match self.behaviour.poll(cx, params) {
+                Poll::Ready(NetworkBehaviourAction::GenerateEvent(event)) => self.on_event(event),
+                Poll::Ready(action) => return Poll::Ready(action),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Counts requests per peer and in total.
+#[derive(Debug, Default)]
+struct Counter {
+    per_peer: HashMap<PeerId, usize>,
+    total: usize,
+}
+
+impl Counter {
+    /// Returns the number of requests of the given peer.
+    fn get(&self, peer: &PeerId) -> usize {
+        self.per_peer.get(peer).copied().unwrap_or(0)
+    }
+
+    /// Checks whether another request of the given peer is below the limits.
+    fn has_room(&self, peer: &PeerId, max_per_peer: usize, max: usize) -> bool {
+        self.get(peer) < max_per_peer && self.total < max
+    }
+
+    /// Like `has_room`, but returns the limit that is reached.
+    fn check(&self, peer: &PeerId, max_per_peer: usize, max: usize) -> Result<(), ThrottleError> {
+        if self.get(peer) >= max_per_peer {
+            Err(ThrottleError::PeerQueueFull)
+        } else if self.total >= max {
+            Err(ThrottleError::QueueFull)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn increment(&mut self, peer: &PeerId) {
+        *self.per_peer.entry(peer.clone()).or_default() += 1;
+        self.total += 1;
+    }
+
+    fn decrement(&mut self, peer: &PeerId) {
+        if let Some(n) = self.per_peer.get_mut(peer) {
+            *n -= 1;
+            if *n == 0 {
+                self.per_peer.remove(peer);
+            }
+            self.total -= 1;
+        }
+    }
+}
+
+/// An inbound request emitted by [`Throttled`], waiting for its response.
+///
+/// Resolves to the peer who sent the request once the response is
+/// forwarded to the wrapped behaviour, the `ResponseChannel` is dropped
+/// or the wrapped behaviour no longer waits for the response.
+struct PendingResponse<TResponse> {
+    peer: PeerId,
+    /// Receives the response sent via the emitted `ResponseChannel`.
+    receiver: oneshot::Receiver<OutgoingResponse<TResponse>>,
+    /// The response channel of the wrapped behaviour.
+    sender: Option<oneshot::Sender<OutgoingResponse<TResponse>>>,
+}
+
+impl<TResponse> Future for PendingResponse<TResponse> {
+    type Output = PeerId;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<PeerId> {
+        let this = &mut *self;
+        match this.receiver.poll_unpin(cx) {
+            Poll::Ready(Ok(response)) => {
+                if let Some(sender) = this.sender.take() {
+                    let _ = sender.send(response);
+                }
+                return Poll::Ready(this.peer.clone())
+            }
+            Poll::Ready(Err(oneshot::Canceled)) => return Poll::Ready(this.peer.clone()),
+            Poll::Pending => {}
+        }
+        match this.sender.as_mut().map(|s| s.poll_canceled(cx)) {
+            Some(Poll::Pending) => Poll::Pending,
+            _ => Poll::Ready(this.peer.clone()),
+        }
+    }
+}
@@ -27,13 +27,13 @@ use libp2p_core::{
     identity,
     muxing::StreamMuxerBox,
     transport::{Transport, boxed::Boxed},
-    upgrade::{self, read_one, write_one}
+    upgrade::{self, read_one, write_with_len_prefix}
 };
 use libp2p_noise::{NoiseConfig, X25519Spec, Keypair};
 use libp2p_request_response::*;
 use libp2p_swarm::Swarm;
 use libp2p_tcp::TcpConfig;
-use futures::{prelude::*, channel::mpsc};
+use futures::{prelude::*, channel::{mpsc, oneshot}};
 use rand::{self, Rng};
 use std::{io, iter};
 
@@ -116,6 +116,273 @@ fn ping_protocol() {
     let () = async_std::task::block_on(peer2);
 }
 
+/// Exercises a ping protocol with streamed responses.
+#[test]
+fn ping_protocol_streaming() {
+    let num_pongs: u8 = rand::thread_rng().gen_range(1, 100);
+
+    let ping = Ping("ping".to_string().into_bytes());
+
+    let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
+    let mut cfg = RequestResponseConfig::default();
+    cfg.set_streaming_responses(true);
+
+    let (peer1_id, trans) = mk_transport();
+    let ping_proto1 = RequestResponse::new(PingCodec(), protocols.clone(), cfg.clone());
+    let mut swarm1 = Swarm::new(trans, ping_proto1, peer1_id.clone());
+
+    let (peer2_id, trans) = mk_transport();
+    let ping_proto2 = RequestResponse::new(PingCodec(), protocols, cfg);
+    let mut swarm2 = Swarm::new(trans, ping_proto2, peer2_id.clone());
+
+    let (mut tx, mut rx) = mpsc::channel::<Multiaddr>(1);
+
+    let addr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
+    Swarm::listen_on(&mut swarm1, addr).unwrap();
+
+    let expected_ping = ping.clone();
+
+    let peer1 = async move {
+        while swarm1.next().now_or_never().is_some() {}
+
+        let l = Swarm::listeners(&swarm1).next().unwrap();
+        tx.send(l.clone()).await.unwrap();
+
+        loop {
+            match swarm1.next().await {
+                RequestResponseEvent::Message {
+                    peer,
+                    message: RequestResponseMessage::Request { request, channel }
+                } => {
+                    assert_eq!(&request, &expected_ping);
+                    assert_eq!(&peer, &peer2_id);
+                    let mut sink = swarm1.send_response_stream(channel).unwrap();
+                    async_std::task::spawn(async move {
+                        for i in 0 .. num_pongs {
+                            sink.send(Pong(vec![i])).await.unwrap();
+                        }
+                    });
+                },
+                e => panic!("Peer1: Unexpected event: {:?}", e)
+            }
+        }
+    };
+
+    let peer2 = async move {
+        let addr = rx.next().await.unwrap();
+        swarm2.add_address(&peer1_id, addr.clone());
+        let req_id = swarm2.send_request(&peer1_id, ping.clone());
+
+        let stream = loop {
+            match swarm2.next().await {
+                RequestResponseEvent::Message {
+                    peer,
+                    message: RequestResponseMessage::ResponseStream { request_id, stream }
+                } => {
+                    assert_eq!(&peer, &peer1_id);
+                    assert_eq!(req_id, request_id);
+                    break stream
+                },
+                e => panic!("Peer2: Unexpected event: {:?}", e)
+            }
+        };
+
+        // The connection is driven by the swarm while the stream is consumed.
+        async_std::task::spawn(async move {
+            loop {
+                let e = swarm2.next().await;
+                panic!("Peer2: Unexpected event: {:?}", e)
+            }
+        });
+
+        let pongs = stream.try_collect::<Vec<_>>().await.unwrap();
+        let expected = (0 .. num_pongs).map(|i| Pong(vec![i])).collect::<Vec<_>>();
+        assert_eq!(pongs, expected);
+    };
+
+    async_std::task::spawn(Box::pin(peer1));
+    let () = async_std::task::block_on(peer2);
+}
+
+/// Dropping a response stream cancels the streamed response.
+#[test]
+fn ping_protocol_streaming_cancel() {
+    let ping = Ping("ping".to_string().into_bytes());
+
+    let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
+    let mut cfg = RequestResponseConfig::default();
+    cfg.set_streaming_responses(true);
+
+    let (peer1_id, trans) = mk_transport();
+    let ping_proto1 = RequestResponse::new(PingCodec(), protocols.clone(), cfg.clone());
+    let mut swarm1 = Swarm::new(trans, ping_proto1, peer1_id.clone());
+
+    let (peer2_id, trans) = mk_transport();
+    let ping_proto2 = RequestResponse::new(PingCodec(), protocols, cfg);
+    let mut swarm2 = Swarm::new(trans, ping_proto2, peer2_id.clone());
+
+    let (mut tx, mut rx) = mpsc::channel::<Multiaddr>(1);
+    let (done_tx, done_rx) = oneshot::channel::<()>();
+
+    let addr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
+    Swarm::listen_on(&mut swarm1, addr).unwrap();
+
+    let peer1 = async move {
+        while swarm1.next().now_or_never().is_some() {}
+
+        let l = Swarm::listeners(&swarm1).next().unwrap();
+        tx.send(l.clone()).await.unwrap();
+
+        let mut done_tx = Some(done_tx);
+        loop {
+            match swarm1.next().await {
+                RequestResponseEvent::Message {
+                    message: RequestResponseMessage::Request { channel, .. }, ..
+                } => {
+                    let mut sink = swarm1.send_response_stream(channel).unwrap();
+                    let done_tx = done_tx.take().unwrap();
+                    async_std::task::spawn(async move {
+                        // Sending blocks while the requester is not reading and
+                        // eventually fails once it dropped the response stream.
+                        while let Ok(()) = sink.send(Pong(vec![0; 1024])).await {}
+                        done_tx.send(()).unwrap();
+                    });
+                },
+                e => panic!("Peer1: Unexpected event: {:?}", e)
+            }
+        }
+    };
+
+    let peer2 = async move {
+        let addr = rx.next().await.unwrap();
+        swarm2.add_address(&peer1_id, addr.clone());
+        swarm2.send_request(&peer1_id, ping.clone());
+
+        let mut stream = loop {
+            match swarm2.next().await {
+                RequestResponseEvent::Message {
+                    message: RequestResponseMessage::ResponseStream { stream, .. }, ..
+                } => break stream,
+                e => panic!("Peer2: Unexpected event: {:?}", e)
+            }
+        };
+
+        async_std::task::spawn(async move {
+            loop {
+                let e = swarm2.next().await;
+                panic!("Peer2: Unexpected event: {:?}", e)
+            }
+        });
+
+        assert!(stream.next().await.unwrap().is_ok());
+        drop(stream);
+        done_rx.await.unwrap();
+    };
+
+    async_std::task::spawn(Box::pin(peer1));
+    let () = async_std::task::block_on(peer2);
+}
+
+/// Exercises the limits of a `Throttled` responder.
+#[test]
+fn ping_protocol_throttled() {
+    let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
+    let cfg = RequestResponseConfig::default();
+    let mut throttle_cfg = ThrottleConfig::default();
+    throttle_cfg.set_max_inbound_per_peer(1).set_max_queued_per_peer(1);
+
+    let (peer1_id, trans) = mk_transport();
+    let ping_proto1 = RequestResponse::new(PingCodec(), protocols.clone(), cfg.clone());
+    let mut swarm1 = Swarm::new(trans, Throttled::new(ping_proto1, throttle_cfg), peer1_id.clone());
+
+    let (peer2_id, trans) = mk_transport();
+    let ping_proto2 = RequestResponse::new(PingCodec(), protocols, cfg);
+    let mut swarm2 = Swarm::new(trans, Throttled::new(ping_proto2, ThrottleConfig::default()), peer2_id);
+
+    let (mut tx, mut rx) = mpsc::channel::<Multiaddr>(1);
+
+    let addr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
+    Swarm::listen_on(&mut swarm1, addr).unwrap();
+
+    let peer1 = async move {
+        while swarm1.next().now_or_never().is_some() {}
+
+        let l = Swarm::listeners(&swarm1).next().unwrap();
+        tx.send(l.clone()).await.unwrap();
+
+        // Of three concurrent requests, one is emitted, one is queued
+        // and one is rejected. The queued request is only emitted once
+        // the first is answered.
+        let mut pending = None;
+        let mut rejected = false;
+        let mut answered = 0;
+        while answered < 2 {
+            match swarm1.next().await {
+                RequestResponseEvent::Message {
+                    message: RequestResponseMessage::Request { channel, .. }, ..
+                } => {
+                    assert!(pending.is_none());
+                    pending = Some(channel);
+                },
+                RequestResponseEvent::InboundFailure {
+                    error: InboundFailure::Throttled(ThrottleError::PeerQueueFull), ..
+                } => {
+                    assert!(!rejected);
+                    rejected = true;
+                },
+                e => panic!("Peer1: Unexpected event: {:?}", e)
+            }
+            if rejected {
+                if let Some(channel) = pending.take() {
+                    swarm1.send_response(channel, Pong("pong".to_string().into_bytes()));
+                    answered += 1;
+                }
+            }
+        }
+    };
+
+    let peer2 = async move {
+        let addr = rx.next().await.unwrap();
+        swarm2.add_address(&peer1_id, addr.clone());
+        for _ in 0 .. 3 {
+            swarm2.send_request(&peer1_id, Ping("ping".to_string().into_bytes())).unwrap();
+        }
+        loop {
+            swarm2.next().await;
+        }
+    };
+
+    async_std::task::spawn(Box::pin(peer2));
+    let () = async_std::task::block_on(peer1);
+}
+
+/// Excess outbound requests of a `Throttled` requester are queued
+/// until the queue is full.
+#[test]
+fn throttled_outbound_queue_full() {
+    let protocols = iter::once((PingProtocol(), ProtocolSupport::Full));
+    let ping_proto = RequestResponse::new(PingCodec(), protocols, RequestResponseConfig::default());
+    let mut throttle_cfg = ThrottleConfig::default();
+    throttle_cfg.set_max_outbound_per_peer(1).set_max_queued_per_peer(1).set_max_queued(2);
+    let mut throttled = Throttled::new(ping_proto, throttle_cfg);
+
+    let peer1 = PeerId::random();
+    let peer2 = PeerId::random();
+    let ping = || Ping("ping".to_string().into_bytes());
+
+    let sent = throttled.send_request(&peer1, ping()).unwrap();
+    let queued = throttled.send_request(&peer1, ping()).unwrap();
+    assert!(throttled.is_pending(&queued));
+    assert_ne!(sent, queued);
+    assert_eq!(throttled.send_request(&peer1, ping()), Err(ThrottleError::PeerQueueFull));
+
+    throttled.send_request(&peer2, ping()).unwrap();
+    throttled.send_request(&peer2, ping()).unwrap();
+    let peer3 = PeerId::random();
+    throttled.send_request(&peer3, ping()).unwrap();
+    assert_eq!(throttled.send_request(&peer3, ping()), Err(ThrottleError::QueueFull));
+}
+
 fn mk_transport() -> (PeerId, Boxed<(PeerId, StreamMuxerBox), io::Error>) {
     let id_keys = identity::Keypair::generate_ed25519();
     let peer_id = id_keys.public().into_peer_id();
@@ -181,7 +448,7 @@ impl RequestResponseCodec for PingCodec {
     where
         T: AsyncWrite + Unpin + Send
     {
-        write_one(io, data).await
+        write_with_len_prefix(io, data).await
     }
 
     async fn write_response<T>(&mut self, _: &PingProtocol, io: &mut T, Pong(data): Pong)
@@ -189,7 +456,7 @@ impl RequestResponseCodec for PingCodec {
     where
         T: AsyncWrite + Unpin + Send
     {
-        write_one(io, data).await
+        write_with_len_prefix(io, data).await
     }
 }
 
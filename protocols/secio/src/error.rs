@@ -21,6 +21,7 @@
 //! Defines the `SecioError` enum that groups all possible errors in SECIO.
 
 use aes_ctr::stream_cipher::LoopError;
+use libp2p_core::PeerId;
 use std::error;
 use std::fmt;
 use std::io::Error as IoError;
@@ -70,6 +71,10 @@ pub enum SecioError {
     /// We received an invalid proposition from remote.
     InvalidProposition(&'static str),
 
+    /// The remote authenticated as a peer that was rejected by the
+    /// configured verification.
+    UnexpectedPeer(PeerId),
+
     #[doc(hidden)]
     __Nonexhaustive
 }
@@ -119,6 +124,8 @@ impl fmt::Display for SecioError {
                 f.write_str("The hashes of the message didn't match"),
             SecioError::InvalidProposition(msg) =>
                 write!(f, "invalid proposition: {}", msg),
+            SecioError::UnexpectedPeer(peer) =>
+                write!(f, "Unexpected remote peer: {}", peer),
             SecioError::__Nonexhaustive =>
                 f.write_str("__Nonexhaustive")
         }
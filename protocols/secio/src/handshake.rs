@@ -126,6 +126,14 @@ where
     trace!("received proposition from remote; pubkey = {:?}; nonce = {:?}",
         remote_public_key, remote_nonce);
 
+    if let Some(verify_remote) = &config.verify_remote {
+        let remote_peer_id = remote_public_key.clone().into_peer_id();
+        if !verify_remote(&remote_peer_id) {
+            debug!("rejecting unexpected remote {:?}", remote_peer_id);
+            return Err(SecioError::UnexpectedPeer(remote_peer_id));
+        }
+    }
+
     // In order to determine which protocols to use, we compute two hashes and choose
     // based on which hash is larger.
     let hashes_ordering = {
@@ -206,7 +214,7 @@ where
 
         Exchange {
             epubkey: Some(tmp_pub_key.clone()),
-            signature: match config.key.sign(&data_to_sign) {
+            signature: match config.key.sign(&data_to_sign).await {
                 Ok(sig) => Some(sig),
                 Err(_) => return Err(SecioError::SigningFailure)
             }
@@ -366,7 +374,7 @@ where D: ::hmac::digest::Input + ::hmac::digest::BlockInput +
 #[cfg(test)]
 mod tests {
     use super::{handshake, stretch_key};
-    use crate::{algo_support::Digest, codec::Hmac, SecioConfig};
+    use crate::{algo_support::Digest, codec::Hmac, error::SecioError, SecioConfig};
     use libp2p_core::identity;
     use futures::{prelude::*, channel::oneshot};
 
@@ -436,6 +444,36 @@ mod tests {
         });
     }
 
+    #[test]
+    fn handshake_rejects_unexpected_peer() {
+        let key1 = identity::Keypair::generate_ed25519();
+        let key2 = identity::Keypair::generate_ed25519();
+        let other = identity::Keypair::generate_ed25519().public().into_peer_id();
+        let key2_peer = key2.public().into_peer_id();
+        let config1 = SecioConfig::new(key1).expected_peers(vec![other]);
+        let config2 = SecioConfig::new(key2);
+
+        let (l_a_tx, l_a_rx) = oneshot::channel();
+
+        let listener = async_std::task::spawn(async move {
+            let listener = async_std::net::TcpListener::bind(&"127.0.0.1:0").await.unwrap();
+            l_a_tx.send(listener.local_addr().unwrap()).unwrap();
+            let connec = listener.accept().await.unwrap().0;
+            handshake(connec, config1).await.map(|_| ())
+        });
+
+        async_std::task::block_on(async move {
+            let listen_addr = l_a_rx.await.unwrap();
+            let connec = async_std::net::TcpStream::connect(&listen_addr).await.unwrap();
+            assert!(handshake(connec, config2).await.is_err());
+            match listener.await {
+                Err(SecioError::UnexpectedPeer(peer)) => assert_eq!(peer, key2_peer),
+                Err(e) => panic!("Unexpected error: {:?}", e),
+                Ok(()) => panic!("Unexpected success"),
+            }
+        });
+    }
+
     #[test]
     fn stretch() {
         let mut output = [0u8; 32];
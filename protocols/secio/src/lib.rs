@@ -62,7 +62,7 @@ use futures::prelude::*;
 use libp2p_core::{PeerId, PublicKey, identity, upgrade::{UpgradeInfo, InboundUpgrade, OutboundUpgrade}};
 use log::debug;
 use rw_stream_sink::RwStreamSink;
-use std::{io, iter, pin::Pin, task::Context, task::Poll};
+use std::{collections::HashSet, io, iter, pin::Pin, sync::Arc, task::Context, task::Poll};
 
 mod algo_support;
 mod codec;
@@ -78,27 +78,38 @@ pub use crate::algo_support::Digest;
 pub use crate::exchange::KeyAgreement;
 pub use crate::stream_cipher::Cipher;
 
+/// Verification of the `PeerId` of a remote, see `verify_remote`.
+type VerifyRemote = Arc<dyn Fn(&PeerId) -> bool + Send + Sync>;
+
 /// Implementation of the `ConnectionUpgrade` trait of `libp2p_core`. Automatically applies
 /// secio on any connection.
 #[derive(Clone)]
 pub struct SecioConfig {
-    /// Private and public keys of the local node.
-    pub(crate) key: identity::Keypair,
+    /// Signer with the identity key of the local node.
+    pub(crate) key: Arc<dyn identity::Signer>,
     pub(crate) agreements_prop: Option<String>,
     pub(crate) ciphers_prop: Option<String>,
     pub(crate) digests_prop: Option<String>,
-    pub(crate) max_frame_len: usize
+    pub(crate) max_frame_len: usize,
+    pub(crate) verify_remote: Option<VerifyRemote>
 }
 
 impl SecioConfig {
     /// Create a new `SecioConfig` with the given keypair.
     pub fn new(kp: identity::Keypair) -> Self {
+        SecioConfig::with_signer(Arc::new(kp))
+    }
+
+    /// Create a new `SecioConfig` which signs the handshake with the given
+    /// [`Signer`](identity::Signer), e.g. one backed by a hardware security module.
+    pub fn with_signer(signer: Arc<dyn identity::Signer>) -> Self {
         SecioConfig {
-            key: kp,
+            key: signer,
             agreements_prop: None,
             ciphers_prop: None,
             digests_prop: None,
-            max_frame_len: 8 * 1024 * 1024
+            max_frame_len: 8 * 1024 * 1024,
+            verify_remote: None
         }
     }
 
@@ -135,6 +146,30 @@ impl SecioConfig {
         self
     }
 
+    /// Only accept remotes for which the given function returns `true`.
+    ///
+    /// The function is called with the `PeerId` of the remote as soon as its
+    /// public key has been received, and the handshake fails with
+    /// [`SecioError::UnexpectedPeer`] if the remote is rejected.
+    pub fn verify_remote<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&PeerId) -> bool + Send + Sync + 'static
+    {
+        self.verify_remote = Some(Arc::new(f));
+        self
+    }
+
+    /// Only accept remotes authenticating as one of the given peers.
+    ///
+    /// This is a shorthand for [`SecioConfig::verify_remote`] with an allow-list.
+    pub fn expected_peers<I>(self, peers: I) -> Self
+    where
+        I: IntoIterator<Item = PeerId>
+    {
+        let peers = peers.into_iter().collect::<HashSet<_>>();
+        self.verify_remote(move |peer| peers.contains(peer))
+    }
+
     fn handshake<T>(self, socket: T) -> impl Future<Output = Result<(PeerId, SecioOutput<T>), SecioError>>
     where
         T: AsyncRead + AsyncWrite + Unpin + Send + 'static
@@ -0,0 +1,251 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::{OpenStreamError, Shared, Stream};
+use crate::protocol::StreamProtocol;
+
+use futures::task::AtomicWaker;
+use libp2p_core::upgrade::{NegotiationError, UpgradeError};
+use libp2p_swarm::{
+    NegotiatedSubstream,
+    SubstreamProtocol,
+    protocols_handler::{
+        KeepAlive,
+        ProtocolsHandler,
+        ProtocolsHandlerEvent,
+        ProtocolsHandlerUpgrErr,
+    }
+};
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}},
+    task::{Context, Poll},
+    time::Duration,
+};
+use void::Void;
+use wasm_timer::Instant;
+
+/// A request to open a stream on a connection.
+#[derive(Debug, Clone)]
+pub struct OpenStream {
+    pub(crate) id: u64,
+    pub(crate) protocol: String,
+}
+
+/// An event emitted by a [`StreamHandler`].
+#[derive(Debug)]
+pub enum StreamHandlerEvent {
+    /// An inbound stream has been negotiated.
+    Inbound {
+        /// The negotiated protocol.
+        protocol: String,
+        /// The stream.
+        stream: Stream,
+    },
+    /// An outbound stream has been negotiated, or opening it failed.
+    Outbound {
+        /// The ID of the [`OpenStream`] request.
+        id: u64,
+        /// The stream.
+        result: Result<Stream, OpenStreamError>,
+    },
+}
+
+/// Counts the streams of a connection that are alive, waking up the
+/// handler whenever one is dropped.
+#[derive(Debug, Default)]
+pub(crate) struct ActiveStreams {
+    count: AtomicUsize,
+    waker: AtomicWaker,
+}
+
+/// A stream counted in [`ActiveStreams`].
+#[derive(Debug)]
+pub(crate) struct ActiveStream(Arc<ActiveStreams>);
+
+impl ActiveStream {
+    fn new(streams: &Arc<ActiveStreams>) -> Self {
+        streams.count.fetch_add(1, Ordering::SeqCst);
+        ActiveStream(streams.clone())
+    }
+}
+
+impl Drop for ActiveStream {
+    fn drop(&mut self) {
+        self.0.count.fetch_sub(1, Ordering::SeqCst);
+        self.0.waker.wake();
+    }
+}
+
+/// A connection handler of the `StreamBehaviour`.
+#[doc(hidden)]
+pub struct StreamHandler {
+    /// The state shared with the behaviour and the controls, holding the
+    /// currently accepted protocols.
+    shared: Arc<Mutex<Shared>>,
+    /// The timeout for negotiating a protocol on a substream.
+    substream_timeout: Duration,
+    /// The keep-alive timeout of idle connections, i.e. of connections
+    /// without streams that are alive.
+    keep_alive_timeout: Duration,
+    /// The current connection keep-alive.
+    keep_alive: KeepAlive,
+    /// The streams of the connection that are alive.
+    streams: Arc<ActiveStreams>,
+    /// Requests waiting to be emitted as an `OutboundSubstreamRequest`.
+    outbound: VecDeque<OpenStream>,
+    /// The number of outbound substreams being opened or negotiated.
+    pending_outbound: usize,
+    /// Queue of events to emit in `poll()`.
+    pending_events: VecDeque<StreamHandlerEvent>,
+}
+
+impl StreamHandler {
+    pub(crate) fn new(
+        shared: Arc<Mutex<Shared>>,
+        substream_timeout: Duration,
+        keep_alive_timeout: Duration,
+    ) -> Self {
+        StreamHandler {
+            shared,
+            substream_timeout,
+            keep_alive_timeout,
+            keep_alive: KeepAlive::Yes,
+            streams: Arc::new(ActiveStreams::default()),
+            outbound: VecDeque::new(),
+            pending_outbound: 0,
+            pending_events: VecDeque::new(),
+        }
+    }
+
+    fn new_stream(&self, substream: NegotiatedSubstream) -> Stream {
+        Stream {
+            inner: substream,
+            _active: ActiveStream::new(&self.streams),
+        }
+    }
+}
+
+impl ProtocolsHandler for StreamHandler {
+    type InEvent = OpenStream;
+    type OutEvent = StreamHandlerEvent;
+    type Error = Void;
+    type InboundProtocol = StreamProtocol;
+    type OutboundProtocol = StreamProtocol;
+    type OutboundOpenInfo = u64;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol> {
+        let protocols = self.shared.lock().expect("not poisoned").accepted_protocols();
+        SubstreamProtocol::new(StreamProtocol { protocols }).with_timeout(self.substream_timeout)
+    }
+
+    fn inject_fully_negotiated_inbound(
+        &mut self,
+        (protocol, substream): (String, NegotiatedSubstream),
+    ) {
+        let stream = self.new_stream(substream);
+        self.pending_events.push_back(StreamHandlerEvent::Inbound { protocol, stream });
+    }
+
+    fn inject_fully_negotiated_outbound(
+        &mut self,
+        (_, substream): (String, NegotiatedSubstream),
+        id: u64,
+    ) {
+        self.pending_outbound -= 1;
+        let stream = self.new_stream(substream);
+        self.pending_events.push_back(StreamHandlerEvent::Outbound { id, result: Ok(stream) });
+    }
+
+    fn inject_event(&mut self, request: Self::InEvent) {
+        self.keep_alive = KeepAlive::Yes;
+        self.outbound.push_back(request);
+    }
+
+    fn inject_dial_upgrade_error(
+        &mut self,
+        id: u64,
+        error: ProtocolsHandlerUpgrErr<Void>,
+    ) {
+        self.pending_outbound -= 1;
+        let error = match error {
+            ProtocolsHandlerUpgrErr::Timeout => OpenStreamError::Timeout,
+            ProtocolsHandlerUpgrErr::Timer =>
+                OpenStreamError::Io(io::Error::new(io::ErrorKind::Other, "timer error")),
+            ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Select(NegotiationError::Failed)) =>
+                OpenStreamError::UnsupportedProtocol,
+            ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Select(NegotiationError::ProtocolError(e))) =>
+                OpenStreamError::Io(e.into()),
+            ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Apply(v)) => void::unreachable(v),
+        };
+        self.pending_events.push_back(StreamHandlerEvent::Outbound { id, result: Err(error) });
+    }
+
+    fn inject_listen_upgrade_error(
+        &mut self,
+        error: ProtocolsHandlerUpgrErr<Void>
+    ) {
+        log::debug!("Failed to negotiate inbound stream: {:?}", error);
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        self.keep_alive
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<
+        ProtocolsHandlerEvent<StreamProtocol, u64, StreamHandlerEvent, Void>,
+    > {
+        if let Some(event) = self.pending_events.pop_front() {
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(event))
+        }
+
+        if let Some(OpenStream { id, protocol }) = self.outbound.pop_front() {
+            self.pending_outbound += 1;
+            let upgrade = StreamProtocol { protocols: vec![protocol] };
+            return Poll::Ready(
+                ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                    protocol: SubstreamProtocol::new(upgrade)
+                        .with_timeout(self.substream_timeout),
+                    info: id,
+                },
+            )
+        }
+
+        // Get notified when a stream is dropped, to let the connection
+        // become idle.
+        self.streams.waker.register(cx.waker());
+
+        if self.pending_outbound > 0 || self.streams.count.load(Ordering::SeqCst) > 0 {
+            self.keep_alive = KeepAlive::Yes;
+        } else if let KeepAlive::Yes = self.keep_alive {
+            // An inbound stream may still be in the process of being
+            // negotiated, so the keep-alive timeout is preceded by the
+            // substream timeout.
+            let until = Instant::now() + self.substream_timeout + self.keep_alive_timeout;
+            self.keep_alive = KeepAlive::Until(until);
+        }
+
+        Poll::Pending
+    }
+}
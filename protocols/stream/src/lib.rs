@@ -0,0 +1,547 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Owned, negotiated streams to peers.
+//!
+//! The [`StreamBehaviour`] hands out streams to peers for use in imperative
+//! async code, as an alternative to implementing a `NetworkBehaviour` and
+//! `ProtocolsHandler` pair per protocol. Streams are requested and accepted
+//! through a [`StreamControl`], obtained from [`StreamBehaviour::new_control`],
+//! which can be cloned and moved to other tasks:
+//!
+//!   * [`StreamControl::open_stream`] opens a new stream to a peer with
+//!     the given protocol, dialing the peer if it is not connected.
+//!   * [`StreamControl::accept`] registers a protocol, returning the
+//!     [`IncomingStreams`] of that protocol opened by any peer.
+//!
+//! A [`Stream`] implements `AsyncRead` and `AsyncWrite`. The connection it
+//! belongs to is kept alive until the stream is dropped.
+//!
+//! > **Note**: Streams are only opened and accepted while the `Swarm`
+//! > containing the `StreamBehaviour` is polled.
+
+mod handler;
+mod protocol;
+
+pub use handler::{OpenStream, StreamHandler, StreamHandlerEvent};
+pub use protocol::StreamProtocol;
+
+use futures::{channel::{mpsc, oneshot}, prelude::*};
+use handler::ActiveStream;
+use libp2p_core::{ConnectedPoint, Multiaddr, PeerId, connection::ConnectionId};
+use libp2p_swarm::{
+    DialPeerCondition,
+    NegotiatedSubstream,
+    NetworkBehaviour,
+    NetworkBehaviourAction,
+    NotifyHandler,
+    PollParameters,
+};
+use smallvec::SmallVec;
+use std::{
+    collections::{HashMap, VecDeque},
+    error, fmt, io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use void::Void;
+
+/// The configuration of a [`StreamBehaviour`].
+#[derive(Debug, Clone)]
+pub struct StreamConfig {
+    timeout: Duration,
+    connection_keep_alive: Duration,
+    max_pending_inbound: usize,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        StreamConfig {
+            timeout: Duration::from_secs(10),
+            connection_keep_alive: Duration::from_secs(10),
+            max_pending_inbound: 16,
+        }
+    }
+}
+
+impl StreamConfig {
+    /// Sets the timeout for negotiating the protocol of a stream.
+    pub fn set_timeout(&mut self, v: Duration) -> &mut Self {
+        self.timeout = v;
+        self
+    }
+
+    /// Sets the keep-alive timeout of connections without streams.
+    pub fn set_connection_keep_alive(&mut self, v: Duration) -> &mut Self {
+        self.connection_keep_alive = v;
+        self
+    }
+
+    /// Sets the maximum number of inbound streams of a protocol that are
+    /// buffered for the [`IncomingStreams`]. Further inbound streams are
+    /// dropped until buffered streams are taken.
+    pub fn set_max_pending_inbound(&mut self, v: usize) -> &mut Self {
+        self.max_pending_inbound = v;
+        self
+    }
+}
+
+/// A negotiated stream to a peer.
+#[derive(Debug)]
+pub struct Stream {
+    inner: NegotiatedSubstream,
+    _active: ActiveStream,
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8])
+        -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8])
+        -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+/// Possible failures of opening a stream.
+#[derive(Debug)]
+pub enum OpenStreamError {
+    /// The peer could not be dialed.
+    DialFailure,
+    /// The connection was closed before the stream was negotiated.
+    ConnectionClosed,
+    /// The protocol was not negotiated within the configured timeout.
+    Timeout,
+    /// The peer does not support the protocol.
+    UnsupportedProtocol,
+    /// An I/O error while negotiating the protocol.
+    Io(io::Error),
+}
+
+impl fmt::Display for OpenStreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenStreamError::DialFailure => write!(f, "Failed to dial the peer"),
+            OpenStreamError::ConnectionClosed => write!(f, "Connection closed"),
+            OpenStreamError::Timeout => write!(f, "Timeout"),
+            OpenStreamError::UnsupportedProtocol => write!(f, "Protocol not supported by the peer"),
+            OpenStreamError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl error::Error for OpenStreamError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            OpenStreamError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The error returned by [`StreamControl::accept`] if the protocol is
+/// already accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlreadyRegistered;
+
+impl fmt::Display for AlreadyRegistered {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The protocol is already accepted")
+    }
+}
+
+impl error::Error for AlreadyRegistered {}
+
+/// The sender through which the outcome of opening a stream is reported.
+type OutcomeSender = oneshot::Sender<Result<Stream, OpenStreamError>>;
+
+/// A request to open a stream, made through a [`StreamControl`].
+struct OpenRequest {
+    peer: PeerId,
+    protocol: String,
+    sender: OutcomeSender,
+}
+
+/// The state shared between the [`StreamBehaviour`], its handlers and its
+/// controls.
+pub(crate) struct Shared {
+    /// The senders of inbound streams, by accepted protocol.
+    accepted: HashMap<String, mpsc::Sender<(PeerId, Stream)>>,
+    /// Whether the accepted protocols changed since the behaviour was last
+    /// polled.
+    protocols_changed: bool,
+    /// Requests to open streams.
+    requests: VecDeque<OpenRequest>,
+    /// The waker of the task polling the behaviour.
+    waker: Option<Waker>,
+}
+
+impl Shared {
+    /// Returns the accepted protocols, forgetting those whose
+    /// [`IncomingStreams`] have been dropped.
+    pub(crate) fn accepted_protocols(&mut self) -> Vec<String> {
+        let before = self.accepted.len();
+        self.accepted.retain(|_, sender| !sender.is_closed());
+        if self.accepted.len() != before {
+            self.protocols_changed = true;
+        }
+        self.accepted.keys().cloned().collect()
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Opens and accepts streams of a [`StreamBehaviour`].
+#[derive(Clone)]
+pub struct StreamControl {
+    shared: Arc<Mutex<Shared>>,
+    max_pending_inbound: usize,
+}
+
+impl fmt::Debug for StreamControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamControl").finish()
+    }
+}
+
+impl StreamControl {
+    /// Opens a new stream to a peer with the given protocol.
+    ///
+    /// If the peer is currently not connected, a dialing attempt is
+    /// initiated and the stream is opened as soon as a connection is
+    /// established.
+    ///
+    /// > **Note**: In order for such a dialing attempt to succeed,
+    /// > the `StreamBehaviour` must either be embedded in another
+    /// > `NetworkBehaviour` that provides peer and address discovery, or
+    /// > known addresses of peers must be managed via
+    /// > [`StreamBehaviour::add_address`] and [`StreamBehaviour::remove_address`].
+    pub fn open_stream(&self, peer: PeerId, protocol: impl Into<String>) -> OpenStreamFuture {
+        let (sender, receiver) = oneshot::channel();
+        let mut shared = self.shared.lock().expect("not poisoned");
+        shared.requests.push_back(OpenRequest { peer, protocol: protocol.into(), sender });
+        shared.wake();
+        OpenStreamFuture { receiver }
+    }
+
+    /// Accepts inbound streams of the given protocol.
+    ///
+    /// The protocol is accepted until the returned [`IncomingStreams`]
+    /// are dropped.
+    pub fn accept(&self, protocol: impl Into<String>) -> Result<IncomingStreams, AlreadyRegistered> {
+        let protocol = protocol.into();
+        let mut shared = self.shared.lock().expect("not poisoned");
+        if matches!(shared.accepted.get(&protocol), Some(s) if !s.is_closed()) {
+            return Err(AlreadyRegistered)
+        }
+        let (sender, receiver) = mpsc::channel(self.max_pending_inbound);
+        shared.accepted.insert(protocol, sender);
+        shared.protocols_changed = true;
+        shared.wake();
+        Ok(IncomingStreams { receiver })
+    }
+}
+
+/// The future returned by [`StreamControl::open_stream`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct OpenStreamFuture {
+    receiver: oneshot::Receiver<Result<Stream, OpenStreamError>>,
+}
+
+impl Future for OpenStreamFuture {
+    type Output = Result<Stream, OpenStreamError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.receiver.poll_unpin(cx) {
+            Poll::Ready(Ok(result)) => Poll::Ready(result),
+            Poll::Ready(Err(oneshot::Canceled)) => Poll::Ready(Err(OpenStreamError::ConnectionClosed)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// The inbound streams of an accepted protocol, together with the peers
+/// that opened them.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct IncomingStreams {
+    receiver: mpsc::Receiver<(PeerId, Stream)>,
+}
+
+impl futures::Stream for IncomingStreams {
+    type Item = (PeerId, Stream);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+/// A `NetworkBehaviour` handing out owned, negotiated streams to peers.
+pub struct StreamBehaviour {
+    /// The state shared with the handlers and controls.
+    shared: Arc<Mutex<Shared>>,
+    /// The configuration of the behaviour.
+    config: StreamConfig,
+    /// The ID of the next request to open a stream.
+    next_request_id: u64,
+    /// The currently established connections.
+    connected: HashMap<PeerId, SmallVec<[ConnectionId; 2]>>,
+    /// Requests to open streams to peers that are being dialed.
+    pending_requests: HashMap<PeerId, SmallVec<[OpenStream; 10]>>,
+    /// The senders of the outcomes of requests to open streams, together
+    /// with the connection on which a stream is opened, once assigned.
+    pending_outcomes: HashMap<u64, (Option<ConnectionId>, OutcomeSender)>,
+    /// The known addresses of peers, in addition to those reported by other
+    /// behaviours.
+    addresses: HashMap<PeerId, SmallVec<[Multiaddr; 6]>>,
+    /// Pending events to return from `poll`.
+    pending_events: VecDeque<NetworkBehaviourAction<OpenStream, Void>>,
+}
+
+impl StreamBehaviour {
+    /// Creates a new `StreamBehaviour`.
+    pub fn new(config: StreamConfig) -> Self {
+        StreamBehaviour {
+            shared: Arc::new(Mutex::new(Shared {
+                accepted: HashMap::new(),
+                protocols_changed: false,
+                requests: VecDeque::new(),
+                waker: None,
+            })),
+            config,
+            next_request_id: 0,
+            connected: HashMap::new(),
+            pending_requests: HashMap::new(),
+            pending_outcomes: HashMap::new(),
+            addresses: HashMap::new(),
+            pending_events: VecDeque::new(),
+        }
+    }
+
+    /// Returns a new [`StreamControl`] for opening and accepting streams.
+    pub fn new_control(&self) -> StreamControl {
+        StreamControl {
+            shared: self.shared.clone(),
+            max_pending_inbound: self.config.max_pending_inbound,
+        }
+    }
+
+    /// Adds a known address for a peer that can be used for
+    /// dialing attempts by the `Swarm`.
+    ///
+    /// Addresses added in this way are only removed by `remove_address`.
+    pub fn add_address(&mut self, peer: &PeerId, address: Multiaddr) {
+        self.addresses.entry(peer.clone()).or_default().push(address);
+    }
+
+    /// Removes an address of a peer previously added via `add_address`.
+    pub fn remove_address(&mut self, peer: &PeerId, address: &Multiaddr) {
+        let mut last = false;
+        if let Some(addresses) = self.addresses.get_mut(peer) {
+            addresses.retain(|a| a != address);
+            last = addresses.is_empty();
+        }
+        if last {
+            self.addresses.remove(peer);
+        }
+    }
+
+    fn open_stream(&mut self, OpenRequest { peer, protocol, sender }: OpenRequest) {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        self.pending_outcomes.insert(id, (None, sender));
+        let request = OpenStream { id, protocol };
+
+        if let Some(request) = self.try_send_request(&peer, request) {
+            self.pending_events.push_back(NetworkBehaviourAction::DialPeer {
+                peer_id: peer.clone(),
+                condition: DialPeerCondition::Disconnected,
+            });
+            self.pending_requests.entry(peer).or_default().push(request);
+        }
+    }
+
+    /// Tries to send a request by queueing an appropriate event to be
+    /// emitted to the `Swarm`. If the peer is not currently connected,
+    /// the given request is returned unchanged.
+    fn try_send_request(&mut self, peer: &PeerId, request: OpenStream) -> Option<OpenStream> {
+        if let Some(conn) = self.connected.get(peer).and_then(|c| c.first()) {
+            if let Some((c, _)) = self.pending_outcomes.get_mut(&request.id) {
+                *c = Some(*conn);
+            }
+            self.pending_events.push_back(NetworkBehaviourAction::NotifyHandler {
+                peer_id: peer.clone(),
+                handler: NotifyHandler::One(*conn),
+                event: request,
+            });
+            None
+        } else {
+            Some(request)
+        }
+    }
+}
+
+impl NetworkBehaviour for StreamBehaviour {
+    type ProtocolsHandler = StreamHandler;
+    type OutEvent = Void;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        StreamHandler::new(
+            self.shared.clone(),
+            self.config.timeout,
+            self.config.connection_keep_alive,
+        )
+    }
+
+    fn addresses_of_peer(&mut self, peer: &PeerId) -> Vec<Multiaddr> {
+        self.addresses.get(peer)
+            .map(|addresses| addresses.to_vec())
+            .unwrap_or_default()
+    }
+
+    fn inject_connected(&mut self, peer: &PeerId) {
+        if let Some(pending) = self.pending_requests.remove(peer) {
+            for request in pending {
+                let request = self.try_send_request(peer, request);
+                assert!(request.is_none());
+            }
+        }
+    }
+
+    fn inject_connection_established(&mut self, peer: &PeerId, conn: &ConnectionId, _: &ConnectedPoint) {
+        self.connected.entry(peer.clone()).or_default().push(*conn);
+    }
+
+    fn inject_connection_closed(&mut self, peer: &PeerId, conn: &ConnectionId, _: &ConnectedPoint) {
+        if let Some(connections) = self.connected.get_mut(peer) {
+            connections.retain(|c| c != conn);
+        }
+
+        // Any pending requests to open a stream on this connection
+        // must be considered failed.
+        let failed = self.pending_outcomes.iter()
+            .filter(|(_, (c, _))| c.as_ref() == Some(conn))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        for id in failed {
+            if let Some((_, sender)) = self.pending_outcomes.remove(&id) {
+                let _ = sender.send(Err(OpenStreamError::ConnectionClosed));
+            }
+        }
+    }
+
+    fn inject_disconnected(&mut self, peer: &PeerId) {
+        self.connected.remove(peer);
+    }
+
+    fn inject_dial_failure(&mut self, peer: &PeerId) {
+        if let Some(pending) = self.pending_requests.remove(peer) {
+            for request in pending {
+                if let Some((_, sender)) = self.pending_outcomes.remove(&request.id) {
+                    let _ = sender.send(Err(OpenStreamError::DialFailure));
+                }
+            }
+        }
+    }
+
+    fn inject_event(&mut self, peer: PeerId, _: ConnectionId, event: StreamHandlerEvent) {
+        match event {
+            StreamHandlerEvent::Inbound { protocol, stream } => {
+                let mut shared = self.shared.lock().expect("not poisoned");
+                let closed = match shared.accepted.get_mut(&protocol) {
+                    Some(sender) => match sender.try_send((peer, stream)) {
+                        Ok(()) => false,
+                        Err(e) if e.is_full() => {
+                            log::debug!("Dropping inbound stream of {}: too many pending streams.", protocol);
+                            false
+                        }
+                        Err(_) => true,
+                    },
+                    None => false,
+                };
+                if closed {
+                    shared.accepted.remove(&protocol);
+                    shared.protocols_changed = true;
+                }
+            }
+            StreamHandlerEvent::Outbound { id, result } => {
+                if let Some((_, sender)) = self.pending_outcomes.remove(&id) {
+                    let _ = sender.send(result);
+                }
+            }
+        }
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>, _: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<OpenStream, Void>>
+    {
+        let (requests, protocols_changed) = {
+            let mut shared = self.shared.lock().expect("not poisoned");
+            shared.waker = Some(cx.waker().clone());
+            let protocols_changed = shared.protocols_changed;
+            shared.protocols_changed = false;
+            (shared.requests.drain(..).collect::<Vec<_>>(), protocols_changed)
+        };
+
+        for request in requests {
+            self.open_stream(request);
+        }
+
+        if protocols_changed {
+            return Poll::Ready(NetworkBehaviourAction::UpdateSupportedProtocols);
+        }
+
+        if let Some(ev) = self.pending_events.pop_front() {
+            return Poll::Ready(ev);
+        } else if self.pending_events.capacity() > EMPTY_QUEUE_SHRINK_THRESHOLD {
+            self.pending_events.shrink_to_fit();
+        }
+
+        Poll::Pending
+    }
+}
+
+/// Internal threshold for when to shrink the capacity
+/// of empty queues. If the capacity of an empty queue
+/// exceeds this threshold, the associated memory is
+/// released.
+const EMPTY_QUEUE_SHRINK_THRESHOLD: usize = 100;
@@ -0,0 +1,63 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The upgrade of the substreams handed out as [`Stream`](crate::Stream)s.
+
+use futures::future;
+use libp2p_core::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p_swarm::NegotiatedSubstream;
+use std::vec;
+use void::Void;
+
+/// Negotiates one of the given protocols on a substream, without any
+/// further I/O.
+#[derive(Debug, Clone)]
+pub struct StreamProtocol {
+    pub(crate) protocols: Vec<String>,
+}
+
+impl UpgradeInfo for StreamProtocol {
+    type Info = String;
+    type InfoIter = vec::IntoIter<String>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.protocols.clone().into_iter()
+    }
+}
+
+impl InboundUpgrade<NegotiatedSubstream> for StreamProtocol {
+    type Output = (String, NegotiatedSubstream);
+    type Error = Void;
+    type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, socket: NegotiatedSubstream, protocol: Self::Info) -> Self::Future {
+        future::ok((protocol, socket))
+    }
+}
+
+impl OutboundUpgrade<NegotiatedSubstream> for StreamProtocol {
+    type Output = (String, NegotiatedSubstream);
+    type Error = Void;
+    type Future = future::Ready<Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, socket: NegotiatedSubstream, protocol: Self::Info) -> Self::Future {
+        future::ok((protocol, socket))
+    }
+}
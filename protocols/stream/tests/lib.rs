@@ -0,0 +1,122 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+use futures::{executor::block_on, prelude::*};
+use libp2p_core::{
+    identity,
+    multiaddr::{Multiaddr, Protocol},
+    muxing::StreamMuxerBox,
+    transport::{boxed::Boxed, MemoryTransport, Transport},
+    upgrade,
+    PeerId,
+};
+use libp2p_plaintext::PlainText2Config;
+use libp2p_stream::{AlreadyRegistered, OpenStreamError, StreamBehaviour, StreamConfig, StreamControl};
+use libp2p_swarm::Swarm;
+use libp2p_yamux as yamux;
+use std::io;
+
+#[test]
+fn open_and_accept_streams() {
+    let (alice_id, trans) = mk_transport();
+    let mut alice = Swarm::new(trans, StreamBehaviour::new(StreamConfig::default()), alice_id.clone());
+    let alice_addr: Multiaddr = Protocol::Memory(rand::random::<u64>()).into();
+    Swarm::listen_on(&mut alice, alice_addr.clone()).unwrap();
+    let alice_control = alice.new_control();
+    let mut incoming = alice_control.accept("/echo/1.0.0").unwrap();
+    assert_eq!(alice_control.accept("/echo/1.0.0").unwrap_err(), AlreadyRegistered);
+    spawn_swarm(alice);
+
+    // Alice echoes the data of every inbound stream.
+    async_std::task::spawn(async move {
+        while let Some((_, mut stream)) = incoming.next().await {
+            let mut data = Vec::new();
+            stream.read_to_end(&mut data).await.unwrap();
+            stream.write_all(&data).await.unwrap();
+            stream.close().await.unwrap();
+        }
+    });
+
+    let (bob_id, trans) = mk_transport();
+    let mut bob = Swarm::new(trans, StreamBehaviour::new(StreamConfig::default()), bob_id);
+    bob.add_address(&alice_id, alice_addr);
+    let bob_control = bob.new_control();
+    spawn_swarm(bob);
+
+    block_on(async {
+        for _ in 0 .. 2 {
+            let mut stream = bob_control.open_stream(alice_id.clone(), "/echo/1.0.0").await.unwrap();
+            stream.write_all(b"hello").await.unwrap();
+            stream.close().await.unwrap();
+            let mut data = Vec::new();
+            stream.read_to_end(&mut data).await.unwrap();
+            assert_eq!(data, b"hello".to_vec());
+        }
+
+        match bob_control.open_stream(alice_id.clone(), "/unknown/1.0.0").await {
+            Err(OpenStreamError::UnsupportedProtocol) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    });
+}
+
+#[test]
+fn accept_after_incoming_streams_dropped() {
+    let (local_id, trans) = mk_transport();
+    let control = Swarm::new(trans, StreamBehaviour::new(StreamConfig::default()), local_id).new_control();
+    let incoming = control.accept("/echo/1.0.0").unwrap();
+    drop(incoming);
+    assert!(control.accept("/echo/1.0.0").is_ok());
+}
+
+#[test]
+fn dial_failure() {
+    let (bob_id, trans) = mk_transport();
+    let mut bob = Swarm::new(trans, StreamBehaviour::new(StreamConfig::default()), bob_id);
+    let peer = PeerId::random();
+    bob.add_address(&peer, Protocol::Memory(rand::random::<u64>()).into());
+    let control: StreamControl = bob.new_control();
+    spawn_swarm(bob);
+
+    match block_on(control.open_stream(peer, "/echo/1.0.0")) {
+        Err(OpenStreamError::DialFailure) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+fn spawn_swarm(mut swarm: Swarm<StreamBehaviour>) {
+    async_std::task::spawn(async move {
+        loop {
+            swarm.next_event().await;
+        }
+    });
+}
+
+fn mk_transport() -> (PeerId, Boxed<(PeerId, StreamMuxerBox), io::Error>) {
+    let id_keys = identity::Keypair::generate_ed25519();
+    let peer_id = id_keys.public().into_peer_id();
+    let transport = MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(PlainText2Config { local_public_key: id_keys.public() })
+        .multiplex(yamux::Config::default())
+        .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+        .boxed();
+    (peer_id, transport)
+}
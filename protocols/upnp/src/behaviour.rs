@@ -0,0 +1,499 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::gateway::{Gateway, GatewayError, PortMapping, PortProtocol, is_routable};
+use futures::{future::BoxFuture, prelude::*, stream::FuturesUnordered};
+use libp2p_core::{
+    Multiaddr,
+    PeerId,
+    connection::ConnectionId,
+    multiaddr::Protocol,
+};
+use libp2p_swarm::{
+    NetworkBehaviour,
+    NetworkBehaviourAction,
+    PollParameters,
+    ProtocolsHandler,
+    protocols_handler::DummyProtocolsHandler
+};
+use log::{debug, warn};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{Ipv4Addr, SocketAddrV4},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use wasm_timer::{Delay, Instant};
+
+/// The configuration for an [`Upnp`] behaviour.
+#[derive(Debug, Clone)]
+pub struct UpnpConfig {
+    pub(crate) timeout: Duration,
+    pub(crate) lease_duration: Duration,
+    pub(crate) gateway: Option<Ipv4Addr>,
+}
+
+impl Default for UpnpConfig {
+    fn default() -> Self {
+        UpnpConfig {
+            timeout: Duration::from_secs(5),
+            lease_duration: Duration::from_secs(60 * 60),
+            gateway: None,
+        }
+    }
+}
+
+impl UpnpConfig {
+    /// Sets the timeout for the discovery of the gateway and for each
+    /// request to the gateway.
+    pub fn set_timeout(&mut self, v: Duration) -> &mut Self {
+        self.timeout = v;
+        self
+    }
+
+    /// Sets the requested lifetime of port mappings.
+    ///
+    /// Mappings are renewed after half of the lifetime granted by the gateway.
+    pub fn set_lease_duration(&mut self, v: Duration) -> &mut Self {
+        self.lease_duration = v;
+        self
+    }
+
+    /// Sets the address of the NAT-PMP gateway, which defaults to the
+    /// default gateway of the routing table on Linux.
+    pub fn set_gateway(&mut self, v: Ipv4Addr) -> &mut Self {
+        self.gateway = Some(v);
+        self
+    }
+}
+
+/// The events produced by the [`Upnp`] behaviour.
+#[derive(Debug)]
+pub enum UpnpEvent {
+    /// A port of a listener has been mapped on the gateway and the given
+    /// external address has been reported to the `Swarm`.
+    NewExternalAddr(Multiaddr),
+    /// The mapping of the given external address has been removed or could
+    /// not be renewed and the address has been removed from the `Swarm`.
+    ExpiredExternalAddr(Multiaddr),
+    /// No gateway supporting port mappings has been found in the local network.
+    GatewayNotFound(GatewayError),
+    /// The gateway is not connected to the public internet directly, e.g.
+    /// because it is itself behind a NAT, so its external addresses are not
+    /// reported.
+    NonRoutableGateway(Ipv4Addr),
+    /// Mapping the port of the given listen address failed.
+    MappingFailed {
+        /// The listen address whose port could not be mapped.
+        address: Multiaddr,
+        /// The error returned by the gateway.
+        error: GatewayError,
+    },
+}
+
+/// A `NetworkBehaviour` that maps the ports of the local listeners on the
+/// gateway of the local network via UPnP IGD or NAT-PMP.
+///
+/// The gateway is searched once the first listener with a private IPv4
+/// address is reported. The resulting external addresses are added to the
+/// candidates for external addresses of the `Swarm`, to be confirmed e.g. by
+/// AutoNAT, and mappings are renewed before they expire. Mappings are removed when the corresponding listen address expires.
+pub struct Upnp {
+    config: UpnpConfig,
+    gateway: GatewayState,
+    /// The mappings, keyed by listen address.
+    mappings: HashMap<Multiaddr, Mapping>,
+    /// Pending requests to map a port, with the listen address they are for.
+    requests: FuturesUnordered<BoxFuture<'static, (Multiaddr, Result<PortMapping, GatewayError>)>>,
+    /// Pending requests to remove a mapping.
+    removals: FuturesUnordered<BoxFuture<'static, ()>>,
+    /// Fires when the next mapping is to be renewed.
+    next_renewal: Option<Delay>,
+    /// Actions to report to the `Swarm`.
+    pending_actions: VecDeque<NetworkBehaviourAction<void::Void, UpnpEvent>>,
+}
+
+enum GatewayState {
+    /// The gateway has not been searched yet.
+    Idle,
+    Searching(BoxFuture<'static, Result<Gateway, GatewayError>>),
+    Available(Arc<Gateway>),
+    NotFound,
+}
+
+struct Mapping {
+    protocol: PortProtocol,
+    local: SocketAddrV4,
+    state: MappingState,
+}
+
+enum MappingState {
+    /// The mapping has not been requested yet.
+    Inactive,
+    /// The mapping has been requested.
+    Pending,
+    Active {
+        external: SocketAddrV4,
+        /// The external address reported to the `Swarm`, if the gateway is routable.
+        address: Option<Multiaddr>,
+        renew_at: Instant,
+        /// Whether a renewal has been requested.
+        renewing: bool,
+    },
+    Failed,
+}
+
+impl Upnp {
+    /// Creates a new `Upnp` behaviour with the given configuration.
+    pub fn new(config: UpnpConfig) -> Self {
+        Upnp {
+            config,
+            gateway: GatewayState::Idle,
+            mappings: HashMap::new(),
+            requests: FuturesUnordered::new(),
+            removals: FuturesUnordered::new(),
+            next_renewal: None,
+            pending_actions: VecDeque::new(),
+        }
+    }
+
+    /// Returns the external addresses of the active mappings.
+    pub fn external_addresses(&self) -> impl Iterator<Item = &Multiaddr> {
+        self.mappings.values().filter_map(|m| match &m.state {
+            MappingState::Active { address, .. } => address.as_ref(),
+            _ => None,
+        })
+    }
+
+    fn request_mapping(&mut self, gateway: &Arc<Gateway>, address: Multiaddr) {
+        let mapping = match self.mappings.get_mut(&address) {
+            Some(mapping) => mapping,
+            None => return,
+        };
+        let (gateway, config) = (gateway.clone(), self.config.clone());
+        let (protocol, local) = (mapping.protocol, mapping.local);
+        self.requests.push(async move {
+            let result = gateway.add_port_mapping(protocol, local, &config).await;
+            (address, result)
+        }.boxed());
+    }
+
+    fn remove_mapping(&mut self, mapping: Mapping) {
+        // A pending request is removed once it completes.
+        if let MappingState::Active { external, address, .. } = mapping.state {
+            if let Some(address) = address {
+                self.expire(address);
+            }
+            self.spawn_removal(mapping.protocol, mapping.local, external.port());
+        }
+    }
+
+    fn spawn_removal(&mut self, protocol: PortProtocol, local: SocketAddrV4, external_port: u16) {
+        let gateway = match &self.gateway {
+            GatewayState::Available(gateway) => gateway.clone(),
+            _ => return,
+        };
+        let timeout = self.config.timeout;
+        self.removals.push(async move {
+            if let Err(err) = gateway.remove_port_mapping(protocol, local, external_port, timeout).await {
+                debug!("Failed to remove port mapping of {}: {}", local, err);
+            }
+        }.boxed());
+    }
+
+    fn expire(&mut self, address: Multiaddr) {
+        self.pending_actions.push_back(NetworkBehaviourAction::RemoveExternalAddr { address: address.clone() });
+        self.pending_actions.push_back(NetworkBehaviourAction::GenerateEvent(UpnpEvent::ExpiredExternalAddr(address)));
+    }
+
+    fn on_mapping_result(&mut self, listen_addr: Multiaddr, result: Result<PortMapping, GatewayError>) {
+        let mapping = match self.mappings.get_mut(&listen_addr) {
+            Some(mapping) => mapping,
+            None => {
+                // The listen address expired in the meantime.
+                if let (Ok(result), Some((protocol, local))) = (result, mapping_target(&listen_addr)) {
+                    self.spawn_removal(protocol, local, result.external.port());
+                }
+                return
+            }
+        };
+
+        let previous = match &mapping.state {
+            MappingState::Active { external, address, .. } => Some((*external, address.clone())),
+            _ => None,
+        };
+
+        let result = match result {
+            Ok(result) => result,
+            Err(error) => {
+                mapping.state = MappingState::Failed;
+                if let Some((_, Some(address))) = previous {
+                    self.expire(address);
+                }
+                self.pending_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    UpnpEvent::MappingFailed { address: listen_addr, error }));
+                return
+            }
+        };
+
+        let renew_at = Instant::now() + result.lifetime / 2;
+        let unchanged = previous.as_ref().is_some_and(|(external, _)| *external == result.external);
+        if unchanged {
+            let address = previous.and_then(|(_, a)| a);
+            mapping.state = MappingState::Active { external: result.external, address, renew_at, renewing: false };
+            return
+        }
+
+        let address = if is_routable(*result.external.ip()) {
+            Some(external_address(&listen_addr, result.external))
+        } else {
+            None
+        };
+        mapping.state = MappingState::Active {
+            external: result.external,
+            address: address.clone(),
+            renew_at,
+            renewing: false,
+        };
+
+        if let Some((_, Some(previous))) = previous {
+            self.expire(previous);
+        }
+        match address {
+            Some(address) => {
+                self.pending_actions.push_back(NetworkBehaviourAction::AddExternalAddr { address: address.clone() });
+                self.pending_actions.push_back(NetworkBehaviourAction::GenerateEvent(UpnpEvent::NewExternalAddr(address)));
+            }
+            None => {
+                self.pending_actions.push_back(NetworkBehaviourAction::GenerateEvent(
+                    UpnpEvent::NonRoutableGateway(*result.external.ip())));
+            }
+        }
+    }
+
+    /// Requests the renewal of all mappings that are due and schedules the next renewal.
+    fn renew(&mut self, gateway: &Arc<Gateway>) {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        let mut next = None;
+        for (address, mapping) in self.mappings.iter_mut() {
+            if let MappingState::Active { renew_at, renewing, .. } = &mut mapping.state {
+                if *renewing {
+                    continue
+                }
+                if *renew_at <= now {
+                    *renewing = true;
+                    due.push(address.clone());
+                } else if next.is_none_or(|next| *renew_at < next) {
+                    next = Some(*renew_at);
+                }
+            }
+        }
+        for address in due {
+            self.request_mapping(gateway, address);
+        }
+        self.next_renewal = next.map(Delay::new_at);
+    }
+}
+
+impl Default for Upnp {
+    fn default() -> Self {
+        Upnp::new(UpnpConfig::default())
+    }
+}
+
+impl NetworkBehaviour for Upnp {
+    type ProtocolsHandler = DummyProtocolsHandler;
+    type OutEvent = UpnpEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        DummyProtocolsHandler::default()
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, _: &PeerId) {}
+
+    fn inject_disconnected(&mut self, _: &PeerId) {}
+
+    fn inject_event(
+        &mut self,
+        _: PeerId,
+        _: ConnectionId,
+        ev: <Self::ProtocolsHandler as ProtocolsHandler>::OutEvent,
+    ) {
+        void::unreachable(ev)
+    }
+
+    fn inject_new_listen_addr(&mut self, addr: &Multiaddr) {
+        let (protocol, local) = match mapping_target(addr) {
+            Some(target) => target,
+            None => return,
+        };
+        if self.mappings.contains_key(addr) {
+            return
+        }
+        self.mappings.insert(addr.clone(), Mapping { protocol, local, state: MappingState::Inactive });
+
+        if let GatewayState::Idle = self.gateway {
+            self.gateway = GatewayState::Searching(Gateway::search(self.config.clone()).boxed());
+        }
+    }
+
+    fn inject_expired_listen_addr(&mut self, addr: &Multiaddr) {
+        if let Some(mapping) = self.mappings.remove(addr) {
+            self.remove_mapping(mapping);
+        }
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        _: &mut impl PollParameters,
+    ) -> Poll<
+        NetworkBehaviourAction<
+            <Self::ProtocolsHandler as ProtocolsHandler>::InEvent,
+            Self::OutEvent,
+        >,
+    > {
+        loop {
+            if let Some(action) = self.pending_actions.pop_front() {
+                return Poll::Ready(action)
+            }
+
+            if let GatewayState::Searching(search) = &mut self.gateway {
+                match search.poll_unpin(cx) {
+                    Poll::Ready(Ok(gateway)) => {
+                        debug!("Found gateway {:?}", gateway);
+                        self.gateway = GatewayState::Available(Arc::new(gateway));
+                    }
+                    Poll::Ready(Err(err)) => {
+                        self.gateway = GatewayState::NotFound;
+                        return Poll::Ready(NetworkBehaviourAction::GenerateEvent(
+                            UpnpEvent::GatewayNotFound(err)))
+                    }
+                    Poll::Pending => {}
+                }
+            }
+
+            let gateway = match &self.gateway {
+                GatewayState::Available(gateway) => gateway.clone(),
+                _ => return Poll::Pending,
+            };
+
+            let inactive = self.mappings.iter_mut()
+                .filter(|(_, m)| matches!(m.state, MappingState::Inactive))
+                .map(|(a, m)| {
+                    m.state = MappingState::Pending;
+                    a.clone()
+                })
+                .collect::<Vec<_>>();
+            for address in inactive {
+                self.request_mapping(&gateway, address);
+            }
+
+            while let Poll::Ready(Some(())) = self.removals.poll_next_unpin(cx) {}
+
+            if let Poll::Ready(Some((address, result))) = self.requests.poll_next_unpin(cx) {
+                self.on_mapping_result(address, result);
+                self.renew(&gateway);
+                continue
+            }
+
+            if let Some(next_renewal) = &mut self.next_renewal {
+                match Future::poll(Pin::new(next_renewal), cx) {
+                    Poll::Ready(Ok(())) => {
+                        self.renew(&gateway);
+                        continue
+                    }
+                    Poll::Ready(Err(err)) => {
+                        warn!("timer has errored: {:?}", err);
+                        self.next_renewal = None;
+                    }
+                    Poll::Pending => {}
+                }
+            }
+
+            return Poll::Pending
+        }
+    }
+}
+
+/// Returns the protocol and local socket address of a listen address whose
+/// port can be mapped, i.e. a TCP or UDP address on a private IPv4 network.
+fn mapping_target(addr: &Multiaddr) -> Option<(PortProtocol, SocketAddrV4)> {
+    let mut iter = addr.iter();
+    let ip = match iter.next()? {
+        Protocol::Ip4(ip) if ip.is_private() => ip,
+        _ => return None,
+    };
+    let target = match iter.next()? {
+        Protocol::Tcp(port) => (PortProtocol::Tcp, SocketAddrV4::new(ip, port)),
+        Protocol::Udp(port) => (PortProtocol::Udp, SocketAddrV4::new(ip, port)),
+        _ => return None,
+    };
+    if iter.any(|p| matches!(p, Protocol::P2pCircuit)) {
+        return None
+    }
+    Some(target)
+}
+
+/// Returns the listen address with the IP address and port replaced by the
+/// external ones.
+fn external_address(listen_addr: &Multiaddr, external: SocketAddrV4) -> Multiaddr {
+    listen_addr.iter()
+        .enumerate()
+        .map(|(i, p)| match (i, p) {
+            (0, Protocol::Ip4(_)) => Protocol::Ip4(*external.ip()),
+            (1, Protocol::Tcp(_)) => Protocol::Tcp(external.port()),
+            (1, Protocol::Udp(_)) => Protocol::Udp(external.port()),
+            (_, p) => p,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapping_targets() {
+        let target = |a: &str| mapping_target(&a.parse().unwrap());
+        assert_eq!(target("/ip4/192.168.1.2/tcp/4001"), Some((PortProtocol::Tcp, "192.168.1.2:4001".parse().unwrap())));
+        assert_eq!(target("/ip4/10.0.0.2/udp/4001/quic"), Some((PortProtocol::Udp, "10.0.0.2:4001".parse().unwrap())));
+        assert_eq!(target("/ip4/127.0.0.1/tcp/4001"), None);
+        assert_eq!(target("/ip4/1.2.3.4/tcp/4001"), None);
+        assert_eq!(target("/ip6/::1/tcp/4001"), None);
+        assert_eq!(target("/ip4/192.168.1.2/tcp/4001/p2p-circuit"), None);
+    }
+
+    #[test]
+    fn external_addresses() {
+        let listen_addr = "/ip4/192.168.1.2/tcp/4001/ws".parse().unwrap();
+        assert_eq!(
+            external_address(&listen_addr, "1.2.3.4:4002".parse().unwrap()),
+            "/ip4/1.2.3.4/tcp/4002/ws".parse().unwrap(),
+        );
+    }
+}
@@ -0,0 +1,158 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Abstraction over the gateways supported for port mapping.
+
+use crate::{igd, natpmp, UpnpConfig};
+use log::debug;
+use std::{error, fmt, io, net::{Ipv4Addr, SocketAddrV4}, time::Duration};
+
+/// The transport protocol of a port mapping.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PortProtocol {
+    Tcp,
+    Udp,
+}
+
+/// A successfully established port mapping.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PortMapping {
+    /// The external address on the gateway.
+    pub external: SocketAddrV4,
+    /// The duration for which the gateway maintains the mapping.
+    pub lifetime: Duration,
+}
+
+/// A gateway of the local network on which ports can be mapped.
+#[derive(Debug)]
+pub enum Gateway {
+    /// A UPnP Internet Gateway Device.
+    Igd(igd::Gateway),
+    /// A NAT-PMP gateway.
+    NatPmp(natpmp::Gateway),
+}
+
+impl Gateway {
+    /// Searches the gateway of the local network.
+    ///
+    /// UPnP IGDs are searched first, falling back to NAT-PMP.
+    pub async fn search(config: UpnpConfig) -> Result<Gateway, GatewayError> {
+        let igd_err = match igd::Gateway::search(config.timeout).await {
+            Ok(gateway) => return Ok(Gateway::Igd(gateway)),
+            Err(err) => err,
+        };
+        debug!("No UPnP gateway found: {}", igd_err);
+
+        let address = match config.gateway.or_else(natpmp::default_gateway) {
+            Some(address) => address,
+            None => return Err(igd_err),
+        };
+        natpmp::Gateway::search(address, config.timeout).await.map(Gateway::NatPmp)
+    }
+
+    /// Maps the port of the given local address to an external port with
+    /// the same number.
+    pub async fn add_port_mapping(
+        &self,
+        protocol: PortProtocol,
+        local: SocketAddrV4,
+        config: &UpnpConfig,
+    ) -> Result<PortMapping, GatewayError> {
+        match self {
+            Gateway::Igd(gateway) =>
+                gateway.add_port_mapping(protocol, local, config.lease_duration, config.timeout).await,
+            Gateway::NatPmp(gateway) =>
+                gateway.add_port_mapping(protocol, local.port(), config.lease_duration, config.timeout).await,
+        }
+    }
+
+    /// Removes the mapping of the given local address.
+    pub async fn remove_port_mapping(
+        &self,
+        protocol: PortProtocol,
+        local: SocketAddrV4,
+        external_port: u16,
+        timeout: Duration,
+    ) -> Result<(), GatewayError> {
+        match self {
+            Gateway::Igd(gateway) =>
+                gateway.remove_port_mapping(protocol, external_port, timeout).await,
+            Gateway::NatPmp(gateway) =>
+                gateway.remove_port_mapping(protocol, local.port(), timeout).await,
+        }
+    }
+}
+
+/// Returns whether the given address is routable on the public internet.
+pub fn is_routable(ip: Ipv4Addr) -> bool {
+    !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified()
+        || ip.is_broadcast() || ip.is_documentation()
+        // Shared address space of carrier-grade NATs (RFC 6598).
+        || (ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64))
+}
+
+/// Error while communicating with a gateway.
+#[derive(Debug)]
+pub enum GatewayError {
+    /// An I/O error, including timeouts.
+    Io(io::Error),
+    /// The gateway sent an invalid or unexpected response.
+    InvalidResponse(&'static str),
+    /// The UPnP gateway rejected a request with the given error code.
+    Upnp(u16),
+    /// The NAT-PMP gateway rejected a request with the given result code.
+    NatPmp(u16),
+}
+
+impl From<io::Error> for GatewayError {
+    fn from(err: io::Error) -> Self {
+        GatewayError::Io(err)
+    }
+}
+
+impl fmt::Display for GatewayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GatewayError::Io(err) => write!(f, "I/O error: {}", err),
+            GatewayError::InvalidResponse(reason) => write!(f, "Invalid response: {}", reason),
+            GatewayError::Upnp(code) => write!(f, "UPnP error code {}", code),
+            GatewayError::NatPmp(code) => write!(f, "NAT-PMP result code {}", code),
+        }
+    }
+}
+
+impl error::Error for GatewayError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            GatewayError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Runs the future to completion or fails with an I/O error after the timeout.
+pub async fn timeout<F, T>(duration: Duration, future: F) -> Result<T, GatewayError>
+where
+    F: std::future::Future<Output = Result<T, GatewayError>>,
+{
+    async_std::future::timeout(duration, future)
+        .await
+        .map_err(|_| GatewayError::Io(io::ErrorKind::TimedOut.into()))?
+}
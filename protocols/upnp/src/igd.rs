@@ -0,0 +1,466 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! A minimal client for UPnP Internet Gateway Devices.
+//!
+//! Gateways are discovered via SSDP and controlled via SOAP requests to one
+//! of their `WANIPConnection` or `WANPPPConnection` services.
+
+use crate::gateway::{timeout, GatewayError, PortMapping, PortProtocol};
+use async_std::net::{TcpStream, UdpSocket};
+use futures::prelude::*;
+use log::debug;
+use std::{
+    fmt::Write as _,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    str,
+    time::Duration,
+};
+
+/// The multicast address of SSDP.
+const SSDP_ADDRESS: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(239, 255, 255, 250), 1900);
+
+/// The search request for Internet Gateway Devices.
+const SEARCH_REQUEST: &str = "M-SEARCH * HTTP/1.1\r\n\
+    HOST: 239.255.255.250:1900\r\n\
+    ST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n\
+    MAN: \"ssdp:discover\"\r\n\
+    MX: 2\r\n\r\n";
+
+/// The services supporting port mappings, in order of preference.
+const SERVICE_TYPES: &[&str] = &[
+    "urn:schemas-upnp-org:service:WANIPConnection:2",
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+
+/// Error code of gateways that only support permanent leases.
+const ONLY_PERMANENT_LEASES_SUPPORTED: u16 = 725;
+
+/// The description of the port mappings on the gateway.
+const DESCRIPTION: &str = "libp2p";
+
+/// A UPnP Internet Gateway Device.
+#[derive(Debug)]
+pub struct Gateway {
+    control_url: Url,
+    service_type: String,
+}
+
+impl Gateway {
+    /// Searches an Internet Gateway Device in the local network.
+    pub async fn search(duration: Duration) -> Result<Gateway, GatewayError> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.send_to(SEARCH_REQUEST.as_bytes(), SSDP_ADDRESS).await?;
+        timeout(duration, async {
+            let mut buf = [0; 1500];
+            loop {
+                let (n, from) = socket.recv_from(&mut buf).await?;
+                let location = match str::from_utf8(&buf[..n]).ok().and_then(parse_search_response) {
+                    Some(location) => location,
+                    None => continue,
+                };
+                match Gateway::from_location(location).await {
+                    Ok(gateway) => return Ok(gateway),
+                    Err(err) => debug!("Ignoring gateway {}: {}", from, err),
+                }
+            }
+        }).await
+    }
+
+    /// Creates a gateway from the location of its device description.
+    pub async fn from_location(location: &str) -> Result<Gateway, GatewayError> {
+        let location = Url::parse(location)?;
+        let (status, description) = http_request(&location, "GET", &[], "").await?;
+        if status != 200 {
+            return Err(GatewayError::InvalidResponse("unexpected HTTP status"))
+        }
+        let base = match element(&description, "URLBase") {
+            Some(base) => Url::parse(base.trim())?,
+            None => location,
+        };
+        let (service_type, control_url) = parse_description(&description)
+            .ok_or(GatewayError::InvalidResponse("no WAN connection service"))?;
+        Ok(Gateway {
+            control_url: base.join(&control_url)?,
+            service_type,
+        })
+    }
+
+    /// Maps the port of the given local address to the same external port.
+    pub async fn add_port_mapping(
+        &self,
+        protocol: PortProtocol,
+        local: SocketAddrV4,
+        lifetime: Duration,
+        duration: Duration,
+    ) -> Result<PortMapping, GatewayError> {
+        timeout(duration, async {
+            let lease = lifetime.as_secs();
+            match self.add_port_mapping_with_lease(protocol, local, lease).await {
+                Err(GatewayError::Upnp(ONLY_PERMANENT_LEASES_SUPPORTED)) => {
+                    // The mapping is refreshed nonetheless, in case it is
+                    // removed by the gateway, e.g. after a reboot.
+                    self.add_port_mapping_with_lease(protocol, local, 0).await?
+                }
+                result => result?,
+            }
+            let response = self.soap_request("GetExternalIPAddress", &[]).await?;
+            let ip = element(&response, "NewExternalIPAddress")
+                .and_then(|ip| ip.trim().parse().ok())
+                .ok_or(GatewayError::InvalidResponse("invalid external IP address"))?;
+            Ok(PortMapping {
+                external: SocketAddrV4::new(ip, local.port()),
+                lifetime,
+            })
+        }).await
+    }
+
+    async fn add_port_mapping_with_lease(&self, protocol: PortProtocol, local: SocketAddrV4, lease: u64)
+        -> Result<(), GatewayError>
+    {
+        self.soap_request("AddPortMapping", &[
+            ("NewRemoteHost", String::new()),
+            ("NewExternalPort", local.port().to_string()),
+            ("NewProtocol", protocol_name(protocol).to_string()),
+            ("NewInternalPort", local.port().to_string()),
+            ("NewInternalClient", local.ip().to_string()),
+            ("NewEnabled", "1".to_string()),
+            ("NewPortMappingDescription", DESCRIPTION.to_string()),
+            ("NewLeaseDuration", lease.to_string()),
+        ]).await?;
+        Ok(())
+    }
+
+    /// Removes the mapping of the given external port.
+    pub async fn remove_port_mapping(&self, protocol: PortProtocol, external_port: u16, duration: Duration)
+        -> Result<(), GatewayError>
+    {
+        timeout(duration, self.soap_request("DeletePortMapping", &[
+            ("NewRemoteHost", String::new()),
+            ("NewExternalPort", external_port.to_string()),
+            ("NewProtocol", protocol_name(protocol).to_string()),
+        ])).await?;
+        Ok(())
+    }
+
+    /// Invokes an action of the WAN connection service, returning the response body.
+    async fn soap_request(&self, action: &str, arguments: &[(&str, String)]) -> Result<String, GatewayError> {
+        let mut body = format!(
+            "<?xml version=\"1.0\"?>\
+            <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+            s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+            <s:Body><u:{} xmlns:u=\"{}\">",
+            action, self.service_type);
+        for (name, value) in arguments {
+            write!(body, "<{0}>{1}</{0}>", name, value).expect("writing to a String succeeds");
+        }
+        write!(body, "</u:{}></s:Body></s:Envelope>", action).expect("writing to a String succeeds");
+
+        let soap_action = format!("\"{}#{}\"", self.service_type, action);
+        let headers = [
+            ("Content-Type", "text/xml; charset=\"utf-8\""),
+            ("SOAPAction", soap_action.as_str()),
+        ];
+        let (status, response) = http_request(&self.control_url, "POST", &headers, &body).await?;
+        if status == 200 {
+            return Ok(response)
+        }
+        match element(&response, "errorCode").and_then(|code| code.trim().parse().ok()) {
+            Some(code) => Err(GatewayError::Upnp(code)),
+            None => Err(GatewayError::InvalidResponse("unexpected HTTP status")),
+        }
+    }
+}
+
+fn protocol_name(protocol: PortProtocol) -> &'static str {
+    match protocol {
+        PortProtocol::Tcp => "TCP",
+        PortProtocol::Udp => "UDP",
+    }
+}
+
+/// An HTTP URL whose host is an IP address, as used by gateways.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Url {
+    address: SocketAddr,
+    path: String,
+}
+
+impl Url {
+    fn parse(url: &str) -> Result<Url, GatewayError> {
+        let invalid = GatewayError::InvalidResponse("invalid URL");
+        let rest = url.strip_prefix("http://").ok_or(invalid)?;
+        let (host, path) = match rest.find('/') {
+            Some(pos) => (&rest[..pos], &rest[pos..]),
+            None => (rest, "/"),
+        };
+        let address = match host.parse() {
+            Ok(address) => address,
+            Err(_) => SocketAddr::new(host.parse().map_err(|_| GatewayError::InvalidResponse("invalid URL"))?, 80),
+        };
+        Ok(Url { address, path: path.to_string() })
+    }
+
+    /// Resolves a possibly relative URL against this one.
+    fn join(&self, url: &str) -> Result<Url, GatewayError> {
+        if url.starts_with("http://") {
+            Url::parse(url)
+        } else if url.starts_with('/') {
+            Ok(Url { address: self.address, path: url.to_string() })
+        } else {
+            Ok(Url { address: self.address, path: format!("/{}", url) })
+        }
+    }
+}
+
+/// Sends an HTTP/1.1 request, returning the status code and body of the response.
+async fn http_request(url: &Url, method: &str, headers: &[(&str, &str)], body: &str)
+    -> Result<(u16, String), GatewayError>
+{
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        method, url.path, url.address, body.len());
+    for (name, value) in headers {
+        write!(request, "{}: {}\r\n", name, value).expect("writing to a String succeeds");
+    }
+    request.push_str("\r\n");
+    request.push_str(body);
+
+    let mut stream = TcpStream::connect(url.address).await?;
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    parse_http_response(&response)
+}
+
+fn parse_http_response(response: &[u8]) -> Result<(u16, String), GatewayError> {
+    let invalid = GatewayError::InvalidResponse("invalid HTTP response");
+    let response = str::from_utf8(response).map_err(|_| GatewayError::InvalidResponse("invalid HTTP response"))?;
+    let pos = response.find("\r\n\r\n").ok_or(invalid)?;
+    let (head, body) = (&response[..pos], &response[pos + 4..]);
+    let mut lines = head.split("\r\n");
+    let status = lines.next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or(GatewayError::InvalidResponse("invalid HTTP status line"))?;
+    let chunked = lines
+        .filter_map(|line| header(line, "transfer-encoding"))
+        .any(|value| value.eq_ignore_ascii_case("chunked"));
+    let body = if chunked { decode_chunked(body)? } else { body.to_string() };
+    Ok((status, body))
+}
+
+/// Decodes a body with chunked transfer encoding.
+fn decode_chunked(mut body: &str) -> Result<String, GatewayError> {
+    let invalid = || GatewayError::InvalidResponse("invalid chunked encoding");
+    let mut decoded = String::new();
+    loop {
+        let pos = body.find("\r\n").ok_or_else(invalid)?;
+        let size = body[..pos].split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| invalid())?;
+        body = &body[pos + 2..];
+        if size == 0 {
+            return Ok(decoded)
+        }
+        decoded.push_str(body.get(..size).ok_or_else(invalid)?);
+        body = body.get(size..).and_then(|b| b.strip_prefix("\r\n")).ok_or_else(invalid)?;
+    }
+}
+
+/// Returns the value of a header line if it has the given lowercase name.
+fn header<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let pos = line.find(':')?;
+    if line[..pos].trim().eq_ignore_ascii_case(name) {
+        Some(line[pos + 1..].trim())
+    } else {
+        None
+    }
+}
+
+/// Extracts the location of the device description from an SSDP response.
+fn parse_search_response(response: &str) -> Option<&str> {
+    if !response.starts_with("HTTP/1.1 200") {
+        return None
+    }
+    response.split("\r\n").find_map(|line| header(line, "location"))
+}
+
+/// Extracts the type and control URL of the preferred WAN connection service
+/// from a device description.
+fn parse_description(description: &str) -> Option<(String, String)> {
+    let mut services = Vec::new();
+    let mut rest = description;
+    while let Some(service) = element(rest, "service") {
+        if let (Some(ty), Some(url)) = (element(service, "serviceType"), element(service, "controlURL")) {
+            services.push((ty.trim().to_string(), url.trim().replace("&amp;", "&")));
+        }
+        // Continue after the end of the current element.
+        let end = service.as_ptr() as usize - rest.as_ptr() as usize + service.len();
+        rest = &rest[end..];
+    }
+    SERVICE_TYPES.iter().find_map(|ty| services.iter().find(|(t, _)| t == ty).cloned())
+}
+
+/// Returns the content of the first XML element with the given name.
+///
+/// Namespace prefixes of the element are ignored.
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = xml;
+    loop {
+        let start = rest.find('<')? + 1;
+        rest = &rest[start..];
+        let tag_end = rest.find('>')?;
+        let tag = &rest[..tag_end];
+        let tag_name = tag.split_whitespace().next().unwrap_or_default();
+        let local_name = tag_name.rsplit(':').next().unwrap_or_default();
+        if local_name == name && !tag.ends_with('/') {
+            let content = &rest[tag_end + 1..];
+            let end = content.find(&format!("</{}>", tag_name))?;
+            return Some(&content[..end])
+        }
+        rest = &rest[tag_end..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::{net::TcpListener, task};
+
+    const DESCRIPTION: &str = "<?xml version=\"1.0\"?>\
+        <root xmlns=\"urn:schemas-upnp-org:device-1-0\"><device><serviceList>\
+        <service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>\
+        <controlURL>/ctl/L3F</controlURL></service>\
+        </serviceList><deviceList><device><deviceList><device><serviceList>\
+        <service><serviceType>urn:schemas-upnp-org:service:WANPPPConnection:1</serviceType>\
+        <controlURL>/ctl/PPP</controlURL></service>\
+        <service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>\
+        <controlURL>ctl/IPConn</controlURL></service>\
+        </serviceList></device></deviceList></device></deviceList></device></root>";
+
+    #[test]
+    fn search_response() {
+        let response = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=120\r\n\
+            Location: http://192.168.1.1:5000/rootDesc.xml\r\n\
+            ST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n\r\n";
+        assert_eq!(parse_search_response(response), Some("http://192.168.1.1:5000/rootDesc.xml"));
+    }
+
+    #[test]
+    fn description() {
+        assert_eq!(
+            parse_description(DESCRIPTION),
+            Some(("urn:schemas-upnp-org:service:WANIPConnection:1".to_string(), "ctl/IPConn".to_string())),
+        );
+    }
+
+    #[test]
+    fn urls() {
+        let base = Url::parse("http://192.168.1.1:5000/rootDesc.xml").unwrap();
+        assert_eq!(base.join("ctl/IPConn").unwrap().path, "/ctl/IPConn");
+        assert_eq!(base.join("http://192.168.1.1/ctl").unwrap(), Url {
+            address: "192.168.1.1:80".parse().unwrap(),
+            path: "/ctl".to_string(),
+        });
+        assert!(Url::parse("https://192.168.1.1/").is_err());
+    }
+
+    #[test]
+    fn chunked_response() {
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5\r\nhello\r\n6;ext=1\r\n world\r\n0\r\n\r\n";
+        assert_eq!(parse_http_response(response).unwrap(), (200, "hello world".to_string()));
+    }
+
+    #[test]
+    fn soap_fault() {
+        let fault = "<s:Envelope><s:Body><s:Fault><detail><UPnPError>\
+            <errorCode>718</errorCode><errorDescription>ConflictInMappingEntry</errorDescription>\
+            </UPnPError></detail></s:Fault></s:Body></s:Envelope>";
+        assert_eq!(element(fault, "errorCode"), Some("718"));
+        assert_eq!(element(fault, "Fault").map(|f| f.starts_with("<detail>")), Some(true));
+    }
+
+    /// Serves HTTP requests, answering each with the response returned by `respond`.
+    async fn serve(listener: TcpListener, respond: impl Fn(&str) -> (u16, String)) {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            // Read until the end of the body announced by `Content-Length`.
+            loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let request = str::from_utf8(&request).unwrap();
+                if let Some(pos) = request.find("\r\n\r\n") {
+                    let len = request.split("\r\n")
+                        .find_map(|line| header(line, "content-length"))
+                        .map_or(0, |len| len.parse().unwrap());
+                    if request.len() >= pos + 4 + len {
+                        break
+                    }
+                }
+            }
+            let (status, body) = respond(str::from_utf8(&request).unwrap());
+            let response = format!("HTTP/1.1 {} OK\r\nContent-Length: {}\r\n\r\n{}", status, body.len(), body);
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    }
+
+    #[test]
+    fn add_port_mapping() {
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let location = format!("http://{}/rootDesc.xml", listener.local_addr().unwrap());
+            task::spawn(serve(listener, |request| {
+                if request.starts_with("GET /rootDesc.xml ") {
+                    (200, DESCRIPTION.to_string())
+                } else if request.contains("SOAPAction: \"urn:schemas-upnp-org:service:WANIPConnection:1#AddPortMapping\"") {
+                    assert!(request.starts_with("POST /ctl/IPConn "));
+                    if request.contains("<NewLeaseDuration>3600</NewLeaseDuration>") {
+                        (500, "<errorCode>725</errorCode>".to_string())
+                    } else {
+                        assert!(request.contains("<NewInternalClient>192.168.1.2</NewInternalClient>"));
+                        assert!(request.contains("<NewLeaseDuration>0</NewLeaseDuration>"));
+                        (200, String::new())
+                    }
+                } else if request.contains("#GetExternalIPAddress\"") {
+                    (200, "<NewExternalIPAddress>1.2.3.4</NewExternalIPAddress>".to_string())
+                } else {
+                    (500, "<errorCode>401</errorCode>".to_string())
+                }
+            }));
+
+            let gateway = Gateway::from_location(&location).await.unwrap();
+            let local = "192.168.1.2:4001".parse().unwrap();
+            let lifetime = Duration::from_secs(3600);
+            let mapping = gateway.add_port_mapping(PortProtocol::Tcp, local, lifetime, Duration::from_secs(5))
+                .await
+                .unwrap();
+            assert_eq!(mapping, PortMapping { external: "1.2.3.4:4001".parse().unwrap(), lifetime });
+
+            match gateway.remove_port_mapping(PortProtocol::Tcp, 4001, Duration::from_secs(5)).await {
+                Err(GatewayError::Upnp(401)) => {}
+                other => panic!("unexpected result: {:?}", other),
+            }
+        })
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! A minimal NAT-PMP client, see [RFC 6886](https://tools.ietf.org/html/rfc6886).
+
+use crate::gateway::{timeout, GatewayError, PortMapping, PortProtocol};
+use async_std::net::UdpSocket;
+use std::{
+    convert::TryInto,
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    time::Duration,
+};
+
+/// The port on which NAT-PMP gateways listen.
+pub const PORT: u16 = 5351;
+
+/// The initial delay before a request is retransmitted, doubling on every attempt.
+const INITIAL_RETRANSMISSION_DELAY: Duration = Duration::from_millis(250);
+
+const OP_EXTERNAL_ADDRESS: u8 = 0;
+const OP_MAP_UDP: u8 = 1;
+const OP_MAP_TCP: u8 = 2;
+
+/// A NAT-PMP gateway.
+#[derive(Debug)]
+pub struct Gateway {
+    address: SocketAddr,
+}
+
+impl Gateway {
+    /// Checks that a NAT-PMP gateway is listening on the given address.
+    pub async fn search(address: Ipv4Addr, timeout: Duration) -> Result<Gateway, GatewayError> {
+        let gateway = Gateway::new(SocketAddrV4::new(address, PORT).into());
+        gateway.external_address(timeout).await?;
+        Ok(gateway)
+    }
+
+    fn new(address: SocketAddr) -> Self {
+        Gateway { address }
+    }
+
+    /// Requests the external address of the gateway.
+    pub async fn external_address(&self, timeout: Duration) -> Result<Ipv4Addr, GatewayError> {
+        let response = self.request(&[0, OP_EXTERNAL_ADDRESS], 12, timeout).await?;
+        Ok(Ipv4Addr::new(response[8], response[9], response[10], response[11]))
+    }
+
+    /// Maps the given local port to an external port, preferably with the same number.
+    pub async fn add_port_mapping(
+        &self,
+        protocol: PortProtocol,
+        port: u16,
+        lifetime: Duration,
+        timeout: Duration,
+    ) -> Result<PortMapping, GatewayError> {
+        let lifetime = lifetime.as_secs().try_into().unwrap_or(u32::MAX);
+        let response = self.request(&map_request(protocol, port, port, lifetime), 16, timeout).await?;
+        let external_port = u16::from_be_bytes([response[10], response[11]]);
+        let lifetime = u32::from_be_bytes(response[12..16].try_into().expect("4 bytes"));
+        let ip = self.external_address(timeout).await?;
+        Ok(PortMapping {
+            external: SocketAddrV4::new(ip, external_port),
+            lifetime: Duration::from_secs(u64::from(lifetime)),
+        })
+    }
+
+    /// Removes the mapping of the given local port.
+    pub async fn remove_port_mapping(
+        &self,
+        protocol: PortProtocol,
+        port: u16,
+        timeout: Duration,
+    ) -> Result<(), GatewayError> {
+        self.request(&map_request(protocol, port, 0, 0), 16, timeout).await?;
+        Ok(())
+    }
+
+    /// Sends a request, retransmitting it until a response of the expected
+    /// length arrives or the timeout expires.
+    async fn request(&self, request: &[u8], len: usize, duration: Duration)
+        -> Result<Vec<u8>, GatewayError>
+    {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.connect(self.address).await?;
+        timeout(duration, async {
+            let mut delay = INITIAL_RETRANSMISSION_DELAY;
+            let mut buf = [0; 16];
+            loop {
+                socket.send(request).await?;
+                let n = match async_std::io::timeout(delay, socket.recv(&mut buf)).await {
+                    Ok(n) => n,
+                    Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                        delay *= 2;
+                        continue
+                    }
+                    Err(err) => return Err(err.into()),
+                };
+                return parse_response(request[1], &buf[..n], len)
+            }
+        }).await
+    }
+}
+
+fn map_request(protocol: PortProtocol, internal: u16, external: u16, lifetime: u32) -> [u8; 12] {
+    let op = match protocol {
+        PortProtocol::Udp => OP_MAP_UDP,
+        PortProtocol::Tcp => OP_MAP_TCP,
+    };
+    let mut request = [0; 12];
+    request[1] = op;
+    request[4..6].copy_from_slice(&internal.to_be_bytes());
+    request[6..8].copy_from_slice(&external.to_be_bytes());
+    request[8..12].copy_from_slice(&lifetime.to_be_bytes());
+    request
+}
+
+/// Checks the header of a response to a request with the given opcode.
+fn parse_response(op: u8, response: &[u8], len: usize) -> Result<Vec<u8>, GatewayError> {
+    if response.len() < 4 || response[0] != 0 || response[1] != 128 + op {
+        return Err(GatewayError::InvalidResponse("unexpected NAT-PMP response header"))
+    }
+    match u16::from_be_bytes([response[2], response[3]]) {
+        0 if response.len() >= len => Ok(response.to_vec()),
+        0 => Err(GatewayError::InvalidResponse("truncated NAT-PMP response")),
+        code => Err(GatewayError::NatPmp(code)),
+    }
+}
+
+/// Returns the default IPv4 gateway from the routing table, if known.
+#[cfg(target_os = "linux")]
+pub fn default_gateway() -> Option<Ipv4Addr> {
+    let routes = std::fs::read_to_string("/proc/net/route").ok()?;
+    parse_routes(&routes)
+}
+
+/// Returns the default IPv4 gateway from the routing table, if known.
+#[cfg(not(target_os = "linux"))]
+pub fn default_gateway() -> Option<Ipv4Addr> {
+    None
+}
+
+/// Extracts the default gateway from the contents of `/proc/net/route`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_routes(routes: &str) -> Option<Ipv4Addr> {
+    routes.lines().skip(1).find_map(|line| {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if fields.get(1) != Some(&"00000000") {
+            return None
+        }
+        // The gateway is written in host byte order.
+        let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+        Some(Ipv4Addr::from(gateway.to_ne_bytes()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::task;
+
+    #[test]
+    fn add_port_mapping() {
+        task::block_on(async {
+            let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let gateway = Gateway::new(server.local_addr().unwrap());
+            task::spawn(async move {
+                let mut buf = [0; 16];
+                let mut dropped_first = false;
+                loop {
+                    let (n, from) = server.recv_from(&mut buf).await.unwrap();
+                    // Drop the first request to exercise retransmissions.
+                    if !dropped_first {
+                        dropped_first = true;
+                        continue
+                    }
+                    let response = match &buf[..n] {
+                        [0, OP_EXTERNAL_ADDRESS] => vec![0, 128, 0, 0, 0, 0, 0, 1, 1, 2, 3, 4],
+                        [0, OP_MAP_TCP, 0, 0, 0x0f, 0xa1, 0x0f, 0xa1, 0, 0, 0x0e, 0x10] =>
+                            vec![0, 130, 0, 0, 0, 0, 0, 1, 0x0f, 0xa1, 0x0f, 0xa2, 0, 0, 0x07, 0x08],
+                        [0, OP_MAP_UDP, ..] => vec![0, 129, 0, 2],
+                        other => panic!("unexpected request: {:?}", other),
+                    };
+                    server.send_to(&response, from).await.unwrap();
+                }
+            });
+
+            let timeout = Duration::from_secs(5);
+            let mapping = gateway.add_port_mapping(PortProtocol::Tcp, 4001, Duration::from_secs(3600), timeout)
+                .await
+                .unwrap();
+            assert_eq!(mapping, PortMapping {
+                external: "1.2.3.4:4002".parse().unwrap(),
+                lifetime: Duration::from_secs(1800),
+            });
+
+            match gateway.remove_port_mapping(PortProtocol::Udp, 4001, timeout).await {
+                Err(GatewayError::NatPmp(2)) => {}
+                other => panic!("unexpected result: {:?}", other),
+            }
+        })
+    }
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn routes() {
+        let routes = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\n\
+            eth0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\n\
+            eth0\t00000000\t0101A8C0\t0003\t0\t0\t0\t00000000\n";
+        assert_eq!(parse_routes(routes), Some(Ipv4Addr::new(192, 168, 1, 1)));
+    }
+}
@@ -18,12 +18,25 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::{Multiaddr, core::{Transport, transport::{ListenerEvent, TransportError}}};
+use crate::{
+    Multiaddr,
+    PeerId,
+    core::{
+        ConnectionInfo,
+        Endpoint,
+        Transport,
+        muxing::{StreamMuxer, StreamMuxerEvent},
+        transport::{ListenerEvent, TransportError},
+    },
+    multiaddr::Protocol,
+};
 
 use atomic::Atomic;
 use futures::{prelude::*, io::{IoSlice, IoSliceMut}, ready};
+use parking_lot::Mutex;
 use std::{
-    convert::TryFrom as _, io, pin::Pin, sync::{atomic::Ordering, Arc}, task::{Context, Poll}
+    collections::HashMap, convert::TryFrom as _, io, mem, pin::Pin, sync::{atomic::Ordering, Arc},
+    task::{Context, Poll}
 };
 
 /// Wraps around a `Transport` and counts the number of bytes that go through all the opened
@@ -74,6 +87,10 @@ where
             .dial(addr)
             .map(move |fut| BandwidthFuture { inner: fut, sinks })
     }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.inner.address_translation(listen, observed)
+    }
 }
 
 /// Wraps around a `Stream` that produces connections. Wraps each connection around a bandwidth
@@ -205,3 +222,471 @@ impl<TInner: AsyncWrite> AsyncWrite for BandwidthConnecLogging<TInner> {
         this.inner.poll_close(cx)
     }
 }
+
+/// Number of bytes received and sent.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Bandwidth {
+    /// Number of bytes received.
+    pub inbound: u64,
+    /// Number of bytes sent.
+    pub outbound: u64,
+}
+
+impl Bandwidth {
+    fn add(&mut self, direction: Direction, num_bytes: u64) {
+        match direction {
+            Direction::Inbound => self.inbound += num_bytes,
+            Direction::Outbound => self.outbound += num_bytes,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// Wraps around a `Transport` producing multiplexed connections and counts the
+/// number of bytes read from and written to the substreams of all connections,
+/// broken down by transport, negotiated protocol and remote peer.
+///
+/// In contrast to [`BandwidthLogging`], which counts the bytes of the raw
+/// connections, only the bytes of substreams are counted, excluding the
+/// overhead of encryption and multiplexing.
+#[derive(Clone)]
+pub struct SubstreamBandwidthLogging<TInner> {
+    inner: TInner,
+    sinks: Arc<SubstreamBandwidthSinks>,
+}
+
+impl<TInner> SubstreamBandwidthLogging<TInner> {
+    /// Creates a new [`SubstreamBandwidthLogging`] around the transport.
+    pub fn new(inner: TInner) -> (Self, Arc<SubstreamBandwidthSinks>) {
+        let sinks = Arc::new(SubstreamBandwidthSinks { stats: Mutex::new(Stats::default()) });
+        let trans = SubstreamBandwidthLogging { inner, sinks: sinks.clone() };
+        (trans, sinks)
+    }
+}
+
+impl<TInner, TConnInfo, TMuxer> Transport for SubstreamBandwidthLogging<TInner>
+where
+    TInner: Transport<Output = (TConnInfo, TMuxer)>,
+    TConnInfo: ConnectionInfo<PeerId = PeerId>,
+    TMuxer: StreamMuxer,
+{
+    type Output = (TConnInfo, BandwidthMuxer<TMuxer>);
+    type Error = TInner::Error;
+    type Listener = SubstreamBandwidthListener<TInner::Listener>;
+    type ListenerUpgrade = SubstreamBandwidthFuture<TInner::ListenerUpgrade>;
+    type Dial = SubstreamBandwidthFuture<TInner::Dial>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let sinks = self.sinks;
+        self.inner
+            .listen_on(addr)
+            .map(move |inner| SubstreamBandwidthListener { inner, sinks })
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let sinks = self.sinks;
+        let transport = transport_name(&addr);
+        self.inner
+            .dial(addr)
+            .map(move |inner| SubstreamBandwidthFuture { inner, sinks, transport })
+    }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.inner.address_translation(listen, observed)
+    }
+}
+
+/// Wraps around a `Stream` that produces connections. Wraps each connection
+/// upgrade into a [`SubstreamBandwidthFuture`].
+#[pin_project::pin_project]
+pub struct SubstreamBandwidthListener<TInner> {
+    #[pin]
+    inner: TInner,
+    sinks: Arc<SubstreamBandwidthSinks>,
+}
+
+impl<TInner, TUpgrade, TErr> Stream for SubstreamBandwidthListener<TInner>
+where
+    TInner: TryStream<Ok = ListenerEvent<TUpgrade, TErr>, Error = TErr>
+{
+    type Item = Result<ListenerEvent<SubstreamBandwidthFuture<TUpgrade>, TErr>, TErr>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        let event =
+            if let Some(event) = ready!(this.inner.try_poll_next(cx)?) {
+                event
+            } else {
+                return Poll::Ready(None)
+            };
+
+        let event = match event {
+            ListenerEvent::Upgrade { upgrade, local_addr, remote_addr } => {
+                let upgrade = SubstreamBandwidthFuture {
+                    inner: upgrade,
+                    sinks: this.sinks.clone(),
+                    transport: transport_name(&remote_addr),
+                };
+                ListenerEvent::Upgrade { upgrade, local_addr, remote_addr }
+            }
+            ListenerEvent::NewAddress(addr) => ListenerEvent::NewAddress(addr),
+            ListenerEvent::AddressExpired(addr) => ListenerEvent::AddressExpired(addr),
+            ListenerEvent::Error(error) => ListenerEvent::Error(error),
+        };
+
+        Poll::Ready(Some(Ok(event)))
+    }
+}
+
+/// Wraps around a `Future` that produces a multiplexed connection. Wraps the
+/// multiplexer into a [`BandwidthMuxer`].
+#[pin_project::pin_project]
+pub struct SubstreamBandwidthFuture<TInner> {
+    #[pin]
+    inner: TInner,
+    sinks: Arc<SubstreamBandwidthSinks>,
+    transport: String,
+}
+
+impl<TInner, TConnInfo, TMuxer> Future for SubstreamBandwidthFuture<TInner>
+where
+    TInner: TryFuture<Ok = (TConnInfo, TMuxer)>,
+    TConnInfo: ConnectionInfo<PeerId = PeerId>,
+{
+    type Output = Result<(TConnInfo, BandwidthMuxer<TMuxer>), TInner::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let (info, inner) = ready!(this.inner.try_poll(cx)?);
+        let muxer = BandwidthMuxer {
+            inner,
+            sinks: this.sinks.clone(),
+            transport: mem::take(this.transport),
+            peer: info.peer_id().clone(),
+        };
+        Poll::Ready(Ok((info, muxer)))
+    }
+}
+
+/// Allows obtaining the number of bytes transferred on the substreams of the
+/// connections created from a [`SubstreamBandwidthLogging`].
+///
+/// > **Note**: The values are by design subject to race conditions. They should
+/// >           only ever be used for statistics purposes, e.g. for computing
+/// >           rates by sampling them periodically.
+pub struct SubstreamBandwidthSinks {
+    stats: Mutex<Stats>,
+}
+
+#[derive(Default)]
+struct Stats {
+    total: Bandwidth,
+    by_transport: HashMap<String, Bandwidth>,
+    by_protocol: HashMap<String, Bandwidth>,
+    by_peer: HashMap<PeerId, Bandwidth>,
+}
+
+impl SubstreamBandwidthSinks {
+    /// Returns the number of bytes transferred on all substreams.
+    pub fn total(&self) -> Bandwidth {
+        self.stats.lock().total
+    }
+
+    /// Returns the number of bytes transferred per transport.
+    ///
+    /// A transport is identified by the protocols of the remote address of
+    /// a connection, without IP addresses, DNS names and peer IDs, e.g.
+    /// `tcp` or `tcp/ws`.
+    pub fn by_transport(&self) -> HashMap<String, Bandwidth> {
+        self.stats.lock().by_transport.clone()
+    }
+
+    /// Returns the number of bytes transferred per protocol negotiated on
+    /// the substreams.
+    ///
+    /// Bytes of substreams for which no protocol has been negotiated, e.g.
+    /// because the negotiation failed, are reported under `"unknown"`.
+    pub fn by_protocol(&self) -> HashMap<String, Bandwidth> {
+        self.stats.lock().by_protocol.clone()
+    }
+
+    /// Returns the number of bytes transferred with the given peer.
+    pub fn peer(&self, peer: &PeerId) -> Bandwidth {
+        self.stats.lock().by_peer.get(peer).copied().unwrap_or_default()
+    }
+
+    /// Returns the number of bytes transferred per remote peer.
+    pub fn by_peer(&self) -> HashMap<PeerId, Bandwidth> {
+        self.stats.lock().by_peer.clone()
+    }
+}
+
+/// Wraps around a `StreamMuxer` and counts the bytes read from and written to
+/// its substreams.
+pub struct BandwidthMuxer<TInner> {
+    inner: TInner,
+    sinks: Arc<SubstreamBandwidthSinks>,
+    transport: String,
+    peer: PeerId,
+}
+
+impl<TInner> BandwidthMuxer<TInner> {
+    /// Records the bytes transferred on a substream.
+    fn record<S>(&self, s: &mut BandwidthSubstream<S>, direction: Direction, data: &[u8]) {
+        let num_bytes = u64::try_from(data.len()).unwrap_or(u64::max_value());
+        let mut stats = self.sinks.stats.lock();
+        stats.total.add(direction, num_bytes);
+        stats.by_transport.entry(self.transport.clone()).or_default().add(direction, num_bytes);
+        stats.by_peer.entry(self.peer.clone()).or_default().add(direction, num_bytes);
+
+        if s.protocol.is_none() {
+            s.pending.add(direction, num_bytes);
+            // The protocol is confirmed by the listener of the substream.
+            let from_listener = match (s.endpoint, direction) {
+                (Endpoint::Dialer, Direction::Inbound) => true,
+                (Endpoint::Listener, Direction::Outbound) => true,
+                _ => false,
+            };
+            if from_listener {
+                if let Some(protocol) = s.negotiation.feed(data) {
+                    let pending = mem::take(&mut s.pending);
+                    let entry = stats.by_protocol.entry(protocol.clone()).or_default();
+                    entry.inbound += pending.inbound;
+                    entry.outbound += pending.outbound;
+                    s.protocol = Some(protocol);
+                }
+            }
+        } else if let Some(protocol) = &s.protocol {
+            stats.by_protocol.entry(protocol.clone()).or_default().add(direction, num_bytes);
+        }
+    }
+}
+
+/// A substream of a [`BandwidthMuxer`].
+pub struct BandwidthSubstream<TInner> {
+    inner: TInner,
+    endpoint: Endpoint,
+    negotiation: Negotiation,
+    /// The negotiated protocol, once known.
+    protocol: Option<String>,
+    /// The bytes transferred before the protocol is known.
+    pending: Bandwidth,
+}
+
+impl<TInner> BandwidthSubstream<TInner> {
+    fn new(inner: TInner, endpoint: Endpoint) -> Self {
+        BandwidthSubstream {
+            inner,
+            endpoint,
+            negotiation: Negotiation::default(),
+            protocol: None,
+            pending: Bandwidth::default(),
+        }
+    }
+}
+
+impl<TInner> StreamMuxer for BandwidthMuxer<TInner>
+where
+    TInner: StreamMuxer,
+{
+    type Substream = BandwidthSubstream<TInner::Substream>;
+    type OutboundSubstream = TInner::OutboundSubstream;
+    type Error = TInner::Error;
+
+    fn poll_event(&self, cx: &mut Context<'_>) -> Poll<Result<StreamMuxerEvent<Self::Substream>, Self::Error>> {
+        let event = match ready!(self.inner.poll_event(cx)) {
+            Ok(StreamMuxerEvent::InboundSubstream(inner)) =>
+                StreamMuxerEvent::InboundSubstream(BandwidthSubstream::new(inner, Endpoint::Listener)),
+            Ok(StreamMuxerEvent::AddressChange(addr)) => StreamMuxerEvent::AddressChange(addr),
+            Err(err) => return Poll::Ready(Err(err)),
+        };
+        Poll::Ready(Ok(event))
+    }
+
+    fn open_outbound(&self) -> Self::OutboundSubstream {
+        self.inner.open_outbound()
+    }
+
+    fn poll_outbound(&self, cx: &mut Context<'_>, s: &mut Self::OutboundSubstream)
+        -> Poll<Result<Self::Substream, Self::Error>>
+    {
+        let inner = ready!(self.inner.poll_outbound(cx, s))?;
+        Poll::Ready(Ok(BandwidthSubstream::new(inner, Endpoint::Dialer)))
+    }
+
+    fn destroy_outbound(&self, s: Self::OutboundSubstream) {
+        self.inner.destroy_outbound(s)
+    }
+
+    fn read_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream, buf: &mut [u8])
+        -> Poll<Result<usize, Self::Error>>
+    {
+        let num_bytes = ready!(self.inner.read_substream(cx, &mut s.inner, buf))?;
+        self.record(s, Direction::Inbound, &buf[..num_bytes]);
+        Poll::Ready(Ok(num_bytes))
+    }
+
+    fn write_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream, buf: &[u8])
+        -> Poll<Result<usize, Self::Error>>
+    {
+        let num_bytes = ready!(self.inner.write_substream(cx, &mut s.inner, buf))?;
+        self.record(s, Direction::Outbound, &buf[..num_bytes]);
+        Poll::Ready(Ok(num_bytes))
+    }
+
+    fn flush_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream)
+        -> Poll<Result<(), Self::Error>>
+    {
+        self.inner.flush_substream(cx, &mut s.inner)
+    }
+
+    fn shutdown_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream)
+        -> Poll<Result<(), Self::Error>>
+    {
+        self.inner.shutdown_substream(cx, &mut s.inner)
+    }
+
+    fn destroy_substream(&self, s: Self::Substream) {
+        if s.protocol.is_none() && s.pending != Bandwidth::default() {
+            let mut stats = self.sinks.stats.lock();
+            let entry = stats.by_protocol.entry("unknown".to_string()).or_default();
+            entry.inbound += s.pending.inbound;
+            entry.outbound += s.pending.outbound;
+        }
+        self.inner.destroy_substream(s.inner)
+    }
+
+    fn close(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.close(cx)
+    }
+
+    fn flush_all(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.flush_all(cx)
+    }
+}
+
+/// The maximum number of bytes buffered while looking for the negotiated protocol.
+const MAX_NEGOTIATION_LEN: usize = 1024;
+
+/// Extracts the protocol negotiated on a substream from the multistream-select
+/// messages sent by the listener of the substream.
+///
+/// The listener echoes the multistream-select header and then either rejects
+/// a proposed protocol with `na` or confirms it by echoing the protocol name.
+#[derive(Default)]
+struct Negotiation {
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl Negotiation {
+    /// Feeds data sent by the listener, returning the negotiated protocol once known.
+    ///
+    /// Returns `"unknown"` if the data does not look like a multistream-select negotiation.
+    fn feed(&mut self, data: &[u8]) -> Option<String> {
+        if self.done {
+            return None
+        }
+        self.buf.extend_from_slice(data);
+        loop {
+            // Messages are prefixed with their length as an unsigned varint.
+            let mut len = 0usize;
+            let mut prefix = None;
+            for (i, b) in self.buf.iter().enumerate().take(2) {
+                len |= usize::from(b & 0x7f) << (7 * i);
+                if b & 0x80 == 0 {
+                    prefix = Some(i + 1);
+                    break
+                }
+            }
+            let prefix = match prefix {
+                Some(prefix) => prefix,
+                None if self.buf.len() < 2 => return None,
+                None => return self.fail(),
+            };
+            if len == 0 || prefix + len > MAX_NEGOTIATION_LEN {
+                return self.fail()
+            }
+            if self.buf.len() < prefix + len {
+                return None
+            }
+            let msg = self.buf.drain(.. prefix + len).skip(prefix).collect::<Vec<_>>();
+            match msg.split_last() {
+                Some((b'\n', b"/multistream/1.0.0")) | Some((b'\n', b"na")) => continue,
+                Some((b'\n', name)) if name.starts_with(b"/") => {
+                    self.buf = Vec::new();
+                    self.done = true;
+                    return Some(String::from_utf8_lossy(name).into_owned())
+                }
+                _ => return self.fail(),
+            }
+        }
+    }
+
+    fn fail(&mut self) -> Option<String> {
+        self.buf = Vec::new();
+        self.done = true;
+        Some("unknown".to_string())
+    }
+}
+
+/// Returns the name of the transport of a connection with the given address,
+/// i.e. its protocols without IP addresses, DNS names and peer IDs.
+fn transport_name(addr: &Multiaddr) -> String {
+    let protocols = addr.iter()
+        .filter(|p| !matches!(p,
+            Protocol::Ip4(_) | Protocol::Ip6(_) | Protocol::Dns(_) |
+            Protocol::Dns4(_) | Protocol::Dns6(_) | Protocol::Dnsaddr(_) | Protocol::P2p(_)))
+        .map(|p| p.to_string().split('/').nth(1).unwrap_or_default().to_string())
+        .collect::<Vec<_>>();
+    if protocols.is_empty() {
+        "unknown".to_string()
+    } else {
+        protocols.join("/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(s: &str) -> Vec<u8> {
+        let mut msg = vec![s.len() as u8 + 1];
+        msg.extend_from_slice(s.as_bytes());
+        msg.push(b'\n');
+        msg
+    }
+
+    #[test]
+    fn negotiated_protocol() {
+        let mut negotiation = Negotiation::default();
+        let mut data = message("/multistream/1.0.0");
+        data.extend(message("na"));
+        data.extend(message("/ipfs/ping/1.0.0"));
+        data.extend(b"payload");
+        // Feed the data in small chunks.
+        let protocols = data.chunks(3).filter_map(|c| negotiation.feed(c)).collect::<Vec<_>>();
+        assert_eq!(protocols, vec!["/ipfs/ping/1.0.0".to_string()]);
+    }
+
+    #[test]
+    fn unknown_protocol() {
+        let mut negotiation = Negotiation::default();
+        assert_eq!(negotiation.feed(b"\x03abc"), Some("unknown".to_string()));
+        assert_eq!(negotiation.feed(b"more"), None);
+    }
+
+    #[test]
+    fn transport_names() {
+        let name = |a: &str| transport_name(&a.parse().unwrap());
+        assert_eq!(name("/ip4/127.0.0.1/tcp/4001"), "tcp");
+        assert_eq!(name("/dns4/example.com/tcp/443/wss"), "tcp/wss");
+        assert_eq!(name("/memory/1234"), "memory");
+    }
+}
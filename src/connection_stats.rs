@@ -0,0 +1,400 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Statistics of individual connections.
+//!
+//! The statistics of a connection are collected by a [`StatsMuxer`], obtained by
+//! wrapping the multiplexing upgrade of a transport into a [`StatsUpgrade`]. The
+//! transport output is then mapped with [`with_stats`], attaching the statistics
+//! to the connection information, such that they are available for all established
+//! connections of a `Swarm` through `Swarm::connections`:
+//!
+//! ```
+//! # #[cfg(all(not(any(target_os = "emscripten", target_os = "wasi", target_os = "unknown")), feature = "tcp", feature = "secio", feature = "mplex"))] {
+//! use libp2p::{connection_stats::{self, StatsUpgrade}, core::upgrade, identity};
+//! use libp2p::{Transport, mplex::MplexConfig, secio::SecioConfig, tcp::TcpConfig};
+//!
+//! let keys = identity::Keypair::generate_ed25519();
+//! let transport = TcpConfig::new()
+//!     .upgrade(upgrade::Version::V1)
+//!     .authenticate(upgrade::WithSecurityInfo::new(SecioConfig::new(keys)))
+//!     .multiplex(StatsUpgrade::new(MplexConfig::new()))
+//!     .map(connection_stats::with_stats);
+//! # }
+//! ```
+//!
+//! Only the bytes of substreams are counted, excluding the overhead of
+//! encryption and multiplexing.
+
+use crate::core::{
+    ConnectedPoint,
+    ConnectionInfo,
+    muxing::{StreamMuxer, StreamMuxerEvent},
+    upgrade::{InboundUpgrade, OutboundUpgrade, ProtocolName, UpgradeInfo},
+};
+use futures::{prelude::*, ready};
+use std::{
+    convert::TryFrom as _, pin::Pin, sync::{Arc, atomic::{AtomicU64, AtomicUsize, Ordering}},
+    task::{Context, Poll}, time::Duration
+};
+use parking_lot::Mutex;
+use wasm_timer::Instant;
+
+/// A snapshot of the statistics of a connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// The number of bytes read from the substreams of the connection.
+    pub bytes_inbound: u64,
+    /// The number of bytes written to the substreams of the connection.
+    pub bytes_outbound: u64,
+    /// The number of substreams opened on the connection, by either side.
+    pub substreams_opened: u64,
+    /// The number of substreams currently open on the connection.
+    pub substreams_active: usize,
+    /// The name of the negotiated security protocol, if known.
+    pub security_protocol: Option<String>,
+    /// The name of the negotiated multiplexing protocol.
+    pub muxer_protocol: String,
+    /// The time elapsed since the connection has been established.
+    pub age: Duration,
+    /// The time elapsed since data has last been read from or written to a substream.
+    pub idle: Duration,
+}
+
+/// The counters of a connection, shared between the [`StatsMuxer`] and the
+/// [`StatsInfo`] of the connection.
+#[derive(Debug)]
+struct Counters {
+    bytes_inbound: AtomicU64,
+    bytes_outbound: AtomicU64,
+    substreams_opened: AtomicU64,
+    substreams_active: AtomicUsize,
+    muxer_protocol: String,
+    established: Instant,
+    last_activity: Mutex<Instant>,
+}
+
+impl Counters {
+    fn new(muxer_protocol: String) -> Self {
+        let now = Instant::now();
+        Counters {
+            bytes_inbound: AtomicU64::new(0),
+            bytes_outbound: AtomicU64::new(0),
+            substreams_opened: AtomicU64::new(0),
+            substreams_active: AtomicUsize::new(0),
+            muxer_protocol,
+            established: now,
+            last_activity: Mutex::new(now),
+        }
+    }
+
+    fn record_bytes(&self, counter: &AtomicU64, num_bytes: usize) {
+        counter.fetch_add(u64::try_from(num_bytes).unwrap_or(u64::MAX), Ordering::Relaxed);
+        *self.last_activity.lock() = Instant::now();
+    }
+
+    fn record_substream(&self) {
+        self.substreams_opened.fetch_add(1, Ordering::Relaxed);
+        self.substreams_active.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Connection information with the statistics of the connection attached.
+///
+/// Obtained through [`with_stats`].
+#[derive(Debug, Clone)]
+pub struct StatsInfo<TInfo> {
+    info: TInfo,
+    counters: Arc<Counters>,
+}
+
+impl<TInfo> StatsInfo<TInfo> {
+    /// Returns the wrapped connection information.
+    pub fn info(&self) -> &TInfo {
+        &self.info
+    }
+}
+
+impl<TInfo: ConnectionInfo> StatsInfo<TInfo> {
+    /// Returns a snapshot of the current statistics of the connection.
+    pub fn stats(&self) -> ConnectionStats {
+        let now = Instant::now();
+        let last_activity = *self.counters.last_activity.lock();
+        ConnectionStats {
+            bytes_inbound: self.counters.bytes_inbound.load(Ordering::Relaxed),
+            bytes_outbound: self.counters.bytes_outbound.load(Ordering::Relaxed),
+            substreams_opened: self.counters.substreams_opened.load(Ordering::Relaxed),
+            substreams_active: self.counters.substreams_active.load(Ordering::Relaxed),
+            security_protocol: self.info.security_protocol()
+                .map(|p| String::from_utf8_lossy(p).into_owned()),
+            muxer_protocol: self.counters.muxer_protocol.clone(),
+            age: now.duration_since(self.counters.established),
+            idle: now.duration_since(last_activity),
+        }
+    }
+}
+
+impl<TInfo: ConnectionInfo> ConnectionInfo for StatsInfo<TInfo> {
+    type PeerId = TInfo::PeerId;
+
+    fn peer_id(&self) -> &Self::PeerId {
+        self.info.peer_id()
+    }
+
+    fn security_protocol(&self) -> Option<&[u8]> {
+        self.info.security_protocol()
+    }
+}
+
+/// Attaches the statistics collected by a [`StatsMuxer`] to the connection
+/// information, for use with `Transport::map`.
+pub fn with_stats<TInfo, TMuxer>((info, muxer): (TInfo, StatsMuxer<TMuxer>), _: ConnectedPoint)
+    -> (StatsInfo<TInfo>, StatsMuxer<TMuxer>)
+{
+    let info = StatsInfo { info, counters: muxer.counters.clone() };
+    (info, muxer)
+}
+
+/// Wraps around a multiplexing upgrade, wrapping the resulting multiplexer
+/// into a [`StatsMuxer`] that records the statistics of the connection.
+#[derive(Debug, Clone)]
+pub struct StatsUpgrade<TUpgrade> {
+    upgrade: TUpgrade,
+}
+
+impl<TUpgrade> StatsUpgrade<TUpgrade> {
+    /// Creates a new [`StatsUpgrade`] around the multiplexing upgrade.
+    pub fn new(upgrade: TUpgrade) -> Self {
+        StatsUpgrade { upgrade }
+    }
+}
+
+impl<TUpgrade> UpgradeInfo for StatsUpgrade<TUpgrade>
+where
+    TUpgrade: UpgradeInfo
+{
+    type Info = TUpgrade::Info;
+    type InfoIter = TUpgrade::InfoIter;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.upgrade.protocol_info()
+    }
+}
+
+impl<C, TUpgrade> InboundUpgrade<C> for StatsUpgrade<TUpgrade>
+where
+    TUpgrade: InboundUpgrade<C>,
+{
+    type Output = StatsMuxer<TUpgrade::Output>;
+    type Error = TUpgrade::Error;
+    type Future = StatsUpgradeFuture<TUpgrade::Future>;
+
+    fn upgrade_inbound(self, socket: C, info: Self::Info) -> Self::Future {
+        let protocol = String::from_utf8_lossy(info.protocol_name()).into_owned();
+        StatsUpgradeFuture { inner: self.upgrade.upgrade_inbound(socket, info), protocol }
+    }
+}
+
+impl<C, TUpgrade> OutboundUpgrade<C> for StatsUpgrade<TUpgrade>
+where
+    TUpgrade: OutboundUpgrade<C>,
+{
+    type Output = StatsMuxer<TUpgrade::Output>;
+    type Error = TUpgrade::Error;
+    type Future = StatsUpgradeFuture<TUpgrade::Future>;
+
+    fn upgrade_outbound(self, socket: C, info: Self::Info) -> Self::Future {
+        let protocol = String::from_utf8_lossy(info.protocol_name()).into_owned();
+        StatsUpgradeFuture { inner: self.upgrade.upgrade_outbound(socket, info), protocol }
+    }
+}
+
+/// Wraps around the `Future` of a multiplexing upgrade. Wraps the multiplexer
+/// into a [`StatsMuxer`].
+#[pin_project::pin_project]
+pub struct StatsUpgradeFuture<TInner> {
+    #[pin]
+    inner: TInner,
+    protocol: String,
+}
+
+impl<TInner: TryFuture> Future for StatsUpgradeFuture<TInner> {
+    type Output = Result<StatsMuxer<TInner::Ok>, TInner::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let inner = ready!(this.inner.try_poll(cx)?);
+        let counters = Arc::new(Counters::new(std::mem::take(this.protocol)));
+        Poll::Ready(Ok(StatsMuxer { inner, counters }))
+    }
+}
+
+/// Wraps around a `StreamMuxer` and records the statistics of the connection.
+pub struct StatsMuxer<TInner> {
+    inner: TInner,
+    counters: Arc<Counters>,
+}
+
+impl<TInner> StreamMuxer for StatsMuxer<TInner>
+where
+    TInner: StreamMuxer,
+{
+    type Substream = TInner::Substream;
+    type OutboundSubstream = TInner::OutboundSubstream;
+    type Error = TInner::Error;
+
+    fn poll_event(&self, cx: &mut Context<'_>) -> Poll<Result<StreamMuxerEvent<Self::Substream>, Self::Error>> {
+        let event = ready!(self.inner.poll_event(cx))?;
+        if let StreamMuxerEvent::InboundSubstream(_) = &event {
+            self.counters.record_substream();
+        }
+        Poll::Ready(Ok(event))
+    }
+
+    fn open_outbound(&self) -> Self::OutboundSubstream {
+        self.inner.open_outbound()
+    }
+
+    fn poll_outbound(&self, cx: &mut Context<'_>, s: &mut Self::OutboundSubstream)
+        -> Poll<Result<Self::Substream, Self::Error>>
+    {
+        let substream = ready!(self.inner.poll_outbound(cx, s))?;
+        self.counters.record_substream();
+        Poll::Ready(Ok(substream))
+    }
+
+    fn destroy_outbound(&self, s: Self::OutboundSubstream) {
+        self.inner.destroy_outbound(s)
+    }
+
+    fn read_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream, buf: &mut [u8])
+        -> Poll<Result<usize, Self::Error>>
+    {
+        let num_bytes = ready!(self.inner.read_substream(cx, s, buf))?;
+        self.counters.record_bytes(&self.counters.bytes_inbound, num_bytes);
+        Poll::Ready(Ok(num_bytes))
+    }
+
+    fn write_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream, buf: &[u8])
+        -> Poll<Result<usize, Self::Error>>
+    {
+        let num_bytes = ready!(self.inner.write_substream(cx, s, buf))?;
+        self.counters.record_bytes(&self.counters.bytes_outbound, num_bytes);
+        Poll::Ready(Ok(num_bytes))
+    }
+
+    fn flush_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream)
+        -> Poll<Result<(), Self::Error>>
+    {
+        self.inner.flush_substream(cx, s)
+    }
+
+    fn shutdown_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream)
+        -> Poll<Result<(), Self::Error>>
+    {
+        self.inner.shutdown_substream(cx, s)
+    }
+
+    fn destroy_substream(&self, s: Self::Substream) {
+        self.counters.substreams_active.fetch_sub(1, Ordering::Relaxed);
+        self.inner.destroy_substream(s)
+    }
+
+    fn close(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.close(cx)
+    }
+
+    fn flush_all(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.flush_all(cx)
+    }
+}
+
+#[cfg(all(test, feature = "mplex", feature = "plaintext"))]
+mod tests {
+    use super::*;
+    use crate::{Multiaddr, Transport, identity, mplex::MplexConfig, plaintext::PlainText2Config};
+    use crate::core::{muxing, transport::MemoryTransport, upgrade};
+
+    #[test]
+    fn connection_stats() {
+        let listener_key = identity::Keypair::generate_ed25519().public();
+        let listener_id = listener_key.clone().into_peer_id();
+        let listener_transport = MemoryTransport::default()
+            .upgrade(upgrade::Version::V1)
+            .authenticate(upgrade::WithSecurityInfo::new(PlainText2Config { local_public_key: listener_key }))
+            .multiplex(StatsUpgrade::new(MplexConfig::new()))
+            .map(with_stats);
+
+        let dialer_key = identity::Keypair::generate_ed25519().public();
+        let dialer_transport = MemoryTransport::default()
+            .upgrade(upgrade::Version::V1)
+            .authenticate(upgrade::WithSecurityInfo::new(PlainText2Config { local_public_key: dialer_key }))
+            .multiplex(StatsUpgrade::new(MplexConfig::new()))
+            .map(with_stats);
+
+        let addr: Multiaddr = "/memory/6354182".parse().unwrap();
+        let mut listener = listener_transport.listen_on(addr.clone()).unwrap();
+
+        async_std::task::spawn(async move {
+            let (upgrade, _) = loop {
+                if let Some(u) = listener.next().await.unwrap().unwrap().into_upgrade() {
+                    break u
+                }
+            };
+            let (_, muxer) = upgrade.await.unwrap();
+            let muxer = Arc::new(muxer);
+            let mut substream = loop {
+                let event = muxing::event_from_ref_and_wrap(muxer.clone()).await.unwrap();
+                if let Some(s) = event.into_inbound_substream() {
+                    break s
+                }
+            };
+            let mut buf = [0; 5];
+            substream.read_exact(&mut buf).await.unwrap();
+            substream.write_all(b"world!").await.unwrap();
+            substream.flush().await.unwrap();
+            substream.close().await.unwrap();
+        });
+
+        async_std::task::block_on(async move {
+            let (info, muxer) = dialer_transport.dial(addr).unwrap().await.unwrap();
+            assert_eq!(info.peer_id(), &listener_id);
+
+            let muxer = Arc::new(muxer);
+            let mut substream = muxing::outbound_from_ref_and_wrap(muxer.clone()).await.unwrap();
+            substream.write_all(b"hello").await.unwrap();
+            substream.flush().await.unwrap();
+            let mut buf = Vec::new();
+            substream.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(buf, b"world!");
+
+            let stats = info.stats();
+            assert_eq!(stats.bytes_outbound, 5);
+            assert_eq!(stats.bytes_inbound, 6);
+            assert_eq!(stats.substreams_opened, 1);
+            assert_eq!(stats.substreams_active, 1);
+            assert_eq!(stats.security_protocol.as_deref(), Some("/plaintext/2.0.0"));
+            assert_eq!(stats.muxer_protocol, "/mplex/6.7.0");
+
+            drop(substream);
+            assert_eq!(info.stats().substreams_active, 0);
+        });
+    }
+}
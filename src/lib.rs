@@ -163,8 +163,24 @@ pub use multiaddr;
 #[doc(inline)]
 pub use multihash;
 
+#[cfg(feature = "autonat")]
+#[cfg_attr(docsrs, doc(cfg(feature = "autonat")))]
+#[doc(inline)]
+pub use libp2p_autonat as autonat;
+#[cfg(feature = "bootstrap")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bootstrap")))]
+#[doc(inline)]
+pub use libp2p_bootstrap as bootstrap;
 #[doc(inline)]
 pub use libp2p_core as core;
+#[cfg(feature = "crawler")]
+#[cfg_attr(docsrs, doc(cfg(feature = "crawler")))]
+#[doc(inline)]
+pub use libp2p_crawler as crawler;
+#[cfg(feature = "dcutr")]
+#[cfg_attr(docsrs, doc(cfg(feature = "dcutr")))]
+#[doc(inline)]
+pub use libp2p_dcutr as dcutr;
 #[cfg(feature = "deflate")]
 #[cfg_attr(docsrs, doc(cfg(feature = "deflate")))]
 #[cfg(not(any(target_os = "emscripten", target_os = "wasi", target_os = "unknown")))]
@@ -179,10 +195,19 @@ pub use libp2p_dns as dns;
 #[cfg_attr(docsrs, doc(cfg(feature = "identify")))]
 #[doc(inline)]
 pub use libp2p_identify as identify;
+#[cfg(feature = "introspection")]
+#[cfg_attr(docsrs, doc(cfg(feature = "introspection")))]
+#[doc(inline)]
+pub use libp2p_introspection as introspection;
 #[cfg(feature = "kad")]
 #[cfg_attr(docsrs, doc(cfg(feature = "kad")))]
 #[doc(inline)]
 pub use libp2p_kad as kad;
+#[cfg(feature = "keystore")]
+#[cfg_attr(docsrs, doc(cfg(feature = "keystore")))]
+#[cfg(not(any(target_os = "emscripten", target_os = "wasi", target_os = "unknown")))]
+#[doc(inline)]
+pub use libp2p_keystore as keystore;
 #[cfg(feature = "floodsub")]
 #[cfg_attr(docsrs, doc(cfg(feature = "floodsub")))]
 #[doc(inline)]
@@ -200,10 +225,22 @@ pub use libp2p_mplex as mplex;
 #[cfg(not(any(target_os = "emscripten", target_os = "wasi", target_os = "unknown")))]
 #[doc(inline)]
 pub use libp2p_mdns as mdns;
+#[cfg(feature = "metrics")]
+#[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+#[doc(inline)]
+pub use libp2p_metrics as metrics;
 #[cfg(feature = "noise")]
 #[cfg_attr(docsrs, doc(cfg(feature = "noise")))]
 #[doc(inline)]
 pub use libp2p_noise as noise;
+#[cfg(feature = "oneshot")]
+#[cfg_attr(docsrs, doc(cfg(feature = "oneshot")))]
+#[doc(inline)]
+pub use libp2p_oneshot as oneshot;
+#[cfg(feature = "peer-store")]
+#[cfg_attr(docsrs, doc(cfg(feature = "peer-store")))]
+#[doc(inline)]
+pub use libp2p_peer_store as peer_store;
 #[cfg(feature = "ping")]
 #[cfg_attr(docsrs, doc(cfg(feature = "ping")))]
 #[doc(inline)]
@@ -212,10 +249,31 @@ pub use libp2p_ping as ping;
 #[cfg_attr(docsrs, doc(cfg(feature = "plaintext")))]
 #[doc(inline)]
 pub use libp2p_plaintext as plaintext;
+#[cfg(feature = "quic")]
+#[cfg_attr(docsrs, doc(cfg(feature = "quic")))]
+#[cfg(not(any(target_os = "emscripten", target_os = "wasi", target_os = "unknown")))]
+#[doc(inline)]
+pub use libp2p_quic as quic;
+#[cfg(feature = "relay")]
+#[cfg_attr(docsrs, doc(cfg(feature = "relay")))]
+#[doc(inline)]
+pub use libp2p_relay as relay;
+#[cfg(feature = "rendezvous")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rendezvous")))]
+#[doc(inline)]
+pub use libp2p_rendezvous as rendezvous;
 #[cfg(feature = "secio")]
 #[cfg_attr(docsrs, doc(cfg(feature = "secio")))]
 #[doc(inline)]
 pub use libp2p_secio as secio;
+#[cfg(feature = "socks5")]
+#[cfg_attr(docsrs, doc(cfg(feature = "socks5")))]
+#[doc(inline)]
+pub use libp2p_socks5 as socks5;
+#[cfg(feature = "stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "stream")))]
+#[doc(inline)]
+pub use libp2p_stream as stream;
 #[doc(inline)]
 pub use libp2p_swarm as swarm;
 #[cfg(any(feature = "tcp-async-std", feature = "tcp-tokio"))]
@@ -223,10 +281,20 @@ pub use libp2p_swarm as swarm;
 #[cfg(not(any(target_os = "emscripten", target_os = "wasi", target_os = "unknown")))]
 #[doc(inline)]
 pub use libp2p_tcp as tcp;
+#[cfg(feature = "tls")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
+#[cfg(not(any(target_os = "emscripten", target_os = "wasi", target_os = "unknown")))]
+#[doc(inline)]
+pub use libp2p_tls as tls;
 #[cfg(feature = "uds")]
 #[cfg_attr(docsrs, doc(cfg(feature = "uds")))]
 #[doc(inline)]
 pub use libp2p_uds as uds;
+#[cfg(feature = "upnp")]
+#[cfg_attr(docsrs, doc(cfg(feature = "upnp")))]
+#[cfg(not(any(target_os = "emscripten", target_os = "wasi", target_os = "unknown")))]
+#[doc(inline)]
+pub use libp2p_upnp as upnp;
 #[cfg(feature = "wasm-ext")]
 #[cfg_attr(docsrs, doc(cfg(feature = "wasm-ext")))]
 #[doc(inline)]
@@ -252,6 +320,7 @@ pub use libp2p_request_response as request_response;
 mod transport_ext;
 
 pub mod bandwidth;
+pub mod connection_stats;
 pub mod simple;
 
 pub use self::core::{
@@ -267,6 +336,34 @@ pub use self::simple::SimpleProtocol;
 pub use self::swarm::Swarm;
 pub use self::transport_ext::TransportExt;
 
+/// Builds a `Transport` for development and testing, supporting TCP/IP and
+/// WebSockets over TCP/IP with DNS resolution, noise as the encryption layer,
+/// and yamux or mplex as the multiplexing layer.
+///
+/// In contrast to [`build_development_transport`], the connections are
+/// secured with noise instead of secio.
+///
+/// > **Note**: This `Transport` is not suitable for production usage, as its implementation
+/// >           reserves the right to support additional protocols or remove deprecated protocols.
+#[cfg(all(not(any(target_os = "emscripten", target_os = "wasi", target_os = "unknown")), any(feature = "tcp-async-std", feature = "tcp-tokio"), feature = "dns", feature = "websocket", feature = "noise", feature = "mplex", feature = "yamux"))]
+#[cfg_attr(docsrs, doc(cfg(all(not(any(target_os = "emscripten", target_os = "wasi", target_os = "unknown")), any(feature = "tcp-async-std", feature = "tcp-tokio"), feature = "dns", feature = "websocket", feature = "noise", feature = "mplex", feature = "yamux"))))]
+pub fn development_transport(keypair: identity::Keypair)
+    -> std::io::Result<impl Transport<Output = (PeerId, impl core::muxing::StreamMuxer<OutboundSubstream = impl Send, Substream = impl Send, Error = impl Into<std::io::Error>> + Send + Sync), Error = impl std::error::Error + Send, Listener = impl Send, Dial = impl Send, ListenerUpgrade = impl Send> + Clone>
+{
+    let transport = build_tcp_ws_dns()?;
+
+    let noise_keys = noise::Keypair::<noise::X25519Spec>::new()
+        .into_authentic(&keypair)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+
+    Ok(transport
+        .upgrade(core::upgrade::Version::V1)
+        .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
+        .multiplex(core::upgrade::SelectUpgrade::new(yamux::Config::default(), mplex::MplexConfig::new()))
+        .map(|(peer, muxer), _| (peer, core::muxing::StreamMuxerBox::new(muxer)))
+        .timeout(std::time::Duration::from_secs(20)))
+}
+
 /// Builds a `Transport` that supports the most commonly-used protocols that libp2p supports.
 ///
 /// > **Note**: This `Transport` is not suitable for production usage, as its implementation
@@ -290,15 +387,7 @@ pub fn build_development_transport(keypair: identity::Keypair)
 pub fn build_tcp_ws_secio_mplex_yamux(keypair: identity::Keypair)
     -> std::io::Result<impl Transport<Output = (PeerId, impl core::muxing::StreamMuxer<OutboundSubstream = impl Send, Substream = impl Send, Error = impl Into<std::io::Error>> + Send + Sync), Error = impl std::error::Error + Send, Listener = impl Send, Dial = impl Send, ListenerUpgrade = impl Send> + Clone>
 {
-    let transport = {
-        #[cfg(feature = "tcp-async-std")]
-        let tcp = tcp::TcpConfig::new().nodelay(true);
-        #[cfg(feature = "tcp-tokio")]
-        let tcp = tcp::TokioTcpConfig::new().nodelay(true);
-        let transport = dns::DnsConfig::new(tcp)?;
-        let trans_clone = transport.clone();
-        transport.or_transport(websocket::WsConfig::new(trans_clone))
-    };
+    let transport = build_tcp_ws_dns()?;
 
     Ok(transport
         .upgrade(core::upgrade::Version::V1)
@@ -319,15 +408,7 @@ pub fn build_tcp_ws_secio_mplex_yamux(keypair: identity::Keypair)
 pub fn build_tcp_ws_pnet_secio_mplex_yamux(keypair: identity::Keypair, psk: PreSharedKey)
     -> std::io::Result<impl Transport<Output = (PeerId, impl core::muxing::StreamMuxer<OutboundSubstream = impl Send, Substream = impl Send, Error = impl Into<std::io::Error>> + Send + Sync), Error = impl std::error::Error + Send, Listener = impl Send, Dial = impl Send, ListenerUpgrade = impl Send> + Clone>
 {
-    let transport = {
-        #[cfg(feature = "tcp-async-std")]
-        let tcp = tcp::TcpConfig::new().nodelay(true);
-        #[cfg(feature = "tcp-tokio")]
-        let tcp = tcp::TokioTcpConfig::new().nodelay(true);
-        let transport = dns::DnsConfig::new(tcp)?;
-        let trans_clone = transport.clone();
-        transport.or_transport(websocket::WsConfig::new(trans_clone))
-    };
+    let transport = build_tcp_ws_dns()?;
 
     Ok(transport
         .and_then(move |socket, _| PnetConfig::new(psk).handshake(socket))
@@ -337,3 +418,18 @@ pub fn build_tcp_ws_pnet_secio_mplex_yamux(keypair: identity::Keypair, psk: PreS
         .map(|(peer, muxer), _| (peer, core::muxing::StreamMuxerBox::new(muxer)))
         .timeout(std::time::Duration::from_secs(20)))
 }
+
+/// Builds the TCP/IP and WebSockets over TCP/IP transport with DNS resolution that the
+/// development transports upgrade.
+#[cfg(all(not(any(target_os = "emscripten", target_os = "wasi", target_os = "unknown")), any(feature = "tcp-async-std", feature = "tcp-tokio"), feature = "websocket", feature = "mplex", feature = "yamux", any(feature = "secio", all(feature = "dns", feature = "noise"))))]
+fn build_tcp_ws_dns()
+    -> std::io::Result<impl Transport<Output = impl futures::io::AsyncRead + futures::io::AsyncWrite + Unpin + Send + 'static, Error = impl std::error::Error + Send + Sync + 'static, Listener = impl Send + 'static, Dial = impl Send + 'static, ListenerUpgrade = impl Send + 'static> + Clone + Send + 'static>
+{
+    #[cfg(feature = "tcp-async-std")]
+    let tcp = tcp::TcpConfig::new().nodelay(true);
+    #[cfg(feature = "tcp-tokio")]
+    let tcp = tcp::TokioTcpConfig::new().nodelay(true);
+    let transport = dns::DnsConfig::new(tcp)?;
+    let trans_clone = transport.clone();
+    Ok(transport.or_transport(websocket::WsConfig::new(trans_clone)))
+}
@@ -20,7 +20,10 @@
 
 //! Provides the `TransportExt` trait.
 
-use crate::{bandwidth::BandwidthLogging, bandwidth::BandwidthSinks, Transport};
+use crate::{
+    bandwidth::{BandwidthLogging, BandwidthSinks, SubstreamBandwidthLogging, SubstreamBandwidthSinks},
+    Transport
+};
 use std::sync::Arc;
 
 /// Trait automatically implemented on all objects that implement `Transport`. Provides some
@@ -38,6 +41,19 @@ pub trait TransportExt: Transport {
         BandwidthLogging::new(self)
     }
 
+    /// Adds a layer on a `Transport` producing multiplexed connections that logs the trafic
+    /// passing through the substreams of these connections.
+    ///
+    /// This method returns an `Arc<SubstreamBandwidthSinks>` that can be used to retreive the
+    /// number of bytes transferred, in total and broken down by transport, negotiated protocol
+    /// and remote peer.
+    fn with_substream_bandwidth_logging(self) -> (SubstreamBandwidthLogging<Self>, Arc<SubstreamBandwidthSinks>)
+    where
+        Self: Sized
+    {
+        SubstreamBandwidthLogging::new(self)
+    }
+
     // TODO: add methods to easily upgrade for secio/mplex/yamux
 }
 
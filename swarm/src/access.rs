@@ -0,0 +1,189 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The peers and addresses a `Swarm` refuses to be connected with.
+
+use ipnet::IpNet;
+use libp2p_core::{multiaddr::Protocol, ConnectedPoint, ConnectionGater, Multiaddr, PeerId};
+use std::{collections::HashSet, fmt, net::IpAddr, sync::Arc};
+
+/// The access rules of a `Swarm`, consisting of banned peers, blocked IP
+/// prefixes, an optional allow list of peers and an optional [`ConnectionGater`].
+#[derive(Default)]
+pub(crate) struct AccessControl {
+    /// Peers that are never connected with.
+    banned_peers: HashSet<PeerId>,
+    /// IP prefixes that are never connected with.
+    blocked_ip_prefixes: Vec<IpNet>,
+    /// The only peers connected with, if the allow list is enabled.
+    allowed_peers: HashSet<PeerId>,
+    /// Whether only `allowed_peers` are connected with.
+    allow_list_enabled: bool,
+    /// The gater consulted at every stage of a connection, if any.
+    gater: Option<Arc<dyn ConnectionGater>>,
+}
+
+impl fmt::Debug for AccessControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessControl")
+            .field("banned_peers", &self.banned_peers)
+            .field("blocked_ip_prefixes", &self.blocked_ip_prefixes)
+            .field("allowed_peers", &self.allowed_peers)
+            .field("allow_list_enabled", &self.allow_list_enabled)
+            .field("gater", &self.gater.is_some())
+            .finish()
+    }
+}
+
+impl AccessControl {
+    pub(crate) fn ban_peer(&mut self, peer_id: PeerId) {
+        self.banned_peers.insert(peer_id);
+    }
+
+    pub(crate) fn unban_peer(&mut self, peer_id: &PeerId) {
+        self.banned_peers.remove(peer_id);
+    }
+
+    pub(crate) fn block_ip_prefix(&mut self, prefix: IpNet) {
+        let prefix = prefix.trunc();
+        if !self.blocked_ip_prefixes.contains(&prefix) {
+            self.blocked_ip_prefixes.push(prefix);
+        }
+    }
+
+    pub(crate) fn unblock_ip_prefix(&mut self, prefix: &IpNet) {
+        let prefix = prefix.trunc();
+        self.blocked_ip_prefixes.retain(|p| p != &prefix);
+    }
+
+    pub(crate) fn allow_peer(&mut self, peer_id: PeerId) {
+        self.allowed_peers.insert(peer_id);
+    }
+
+    pub(crate) fn disallow_peer(&mut self, peer_id: &PeerId) {
+        self.allowed_peers.remove(peer_id);
+    }
+
+    pub(crate) fn set_allow_list_enabled(&mut self, enabled: bool) {
+        self.allow_list_enabled = enabled;
+    }
+
+    pub(crate) fn set_gater(&mut self, gater: Arc<dyn ConnectionGater>) {
+        self.gater = Some(gater);
+    }
+
+    /// Returns `true` if the gater allows dialing the given peer.
+    pub(crate) fn intercept_peer_dial(&self, peer_id: &PeerId) -> bool {
+        self.gater.as_ref().is_none_or(|g| g.intercept_peer_dial(peer_id))
+    }
+
+    /// Returns `true` if the gater allows dialing the given address.
+    pub(crate) fn intercept_addr_dial(&self, peer_id: Option<&PeerId>, addr: &Multiaddr) -> bool {
+        self.gater.as_ref().is_none_or(|g| g.intercept_addr_dial(peer_id, addr))
+    }
+
+    /// Returns `true` if the gater allows the incoming connection.
+    pub(crate) fn intercept_accept(&self, local_addr: &Multiaddr, send_back_addr: &Multiaddr) -> bool {
+        self.gater.as_ref().is_none_or(|g| g.intercept_accept(local_addr, send_back_addr))
+    }
+
+    /// Returns `true` if the gater allows the upgraded connection.
+    pub(crate) fn intercept_upgraded(&self, peer_id: &PeerId, endpoint: &ConnectedPoint) -> bool {
+        self.gater.as_ref().is_none_or(|g| g.intercept_upgraded(peer_id, endpoint))
+    }
+
+    /// Returns `true` if connections with the given peer are refused.
+    pub(crate) fn is_peer_denied(&self, peer_id: &PeerId) -> bool {
+        self.banned_peers.contains(peer_id)
+            || (self.allow_list_enabled && !self.allowed_peers.contains(peer_id))
+    }
+
+    /// Returns `true` if the given address is within a blocked IP prefix.
+    pub(crate) fn is_addr_blocked(&self, addr: &Multiaddr) -> bool {
+        if self.blocked_ip_prefixes.is_empty() {
+            return false;
+        }
+        addr.iter().any(|p| {
+            let ip = match p {
+                Protocol::Ip4(ip) => IpAddr::V4(ip),
+                Protocol::Ip6(ip) => IpAddr::V6(ip),
+                _ => return false,
+            };
+            self.blocked_ip_prefixes.iter().any(|prefix| prefix.contains(&ip))
+        })
+    }
+
+    /// Returns `true` if the remote address of the given endpoint is within
+    /// a blocked IP prefix.
+    pub(crate) fn is_endpoint_blocked(&self, endpoint: &ConnectedPoint) -> bool {
+        match endpoint {
+            ConnectedPoint::Dialer { address } => self.is_addr_blocked(address),
+            ConnectedPoint::Listener { send_back_addr, .. } => self.is_addr_blocked(send_back_addr),
+        }
+    }
+
+    /// Returns `true` if the given connection is to be closed, either
+    /// because of its peer or because of its remote address.
+    pub(crate) fn is_connection_denied(&self, peer_id: &PeerId, endpoint: &ConnectedPoint) -> bool {
+        self.is_peer_denied(peer_id) || self.is_endpoint_blocked(endpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocked_ip_prefixes() {
+        let mut access = AccessControl::default();
+        let addr: Multiaddr = "/ip4/10.1.2.3/tcp/4001".parse().unwrap();
+        assert!(!access.is_addr_blocked(&addr));
+
+        access.block_ip_prefix("10.1.0.0/16".parse().unwrap());
+        assert!(access.is_addr_blocked(&addr));
+        assert!(!access.is_addr_blocked(&"/ip4/10.2.0.1/tcp/4001".parse().unwrap()));
+        assert!(!access.is_addr_blocked(&"/memory/1".parse().unwrap()));
+
+        // Prefixes are compared after truncating the host bits.
+        access.unblock_ip_prefix(&"10.1.255.255/16".parse().unwrap());
+        assert!(!access.is_addr_blocked(&addr));
+    }
+
+    #[test]
+    fn allow_list() {
+        let mut access = AccessControl::default();
+        let peer = PeerId::random();
+        assert!(!access.is_peer_denied(&peer));
+
+        access.set_allow_list_enabled(true);
+        assert!(access.is_peer_denied(&peer));
+        access.allow_peer(peer.clone());
+        assert!(!access.is_peer_denied(&peer));
+
+        // Banning takes precedence over the allow list.
+        access.ban_peer(peer.clone());
+        assert!(access.is_peer_denied(&peer));
+        access.unban_peer(&peer);
+
+        access.set_allow_list_enabled(false);
+        access.disallow_peer(&peer);
+        assert!(!access.is_peer_denied(&peer));
+    }
+}
@@ -155,7 +155,8 @@ pub trait NetworkBehaviour: Send + 'static {
     fn inject_expired_listen_addr(&mut self, _addr: &Multiaddr) {
     }
 
-    /// Indicates to the behaviour that we have discovered a new external address for us.
+    /// Indicates to the behaviour that we have discovered a new external address for us,
+    /// i.e. that an external address has been confirmed.
     fn inject_new_external_addr(&mut self, _addr: &Multiaddr) {
     }
 
@@ -181,7 +182,8 @@ pub trait PollParameters {
     type SupportedProtocolsIter: ExactSizeIterator<Item = Vec<u8>>;
     /// Iterator returned by [`listened_addresses`](PollParameters::listened_addresses).
     type ListenedAddressesIter: ExactSizeIterator<Item = Multiaddr>;
-    /// Iterator returned by [`external_addresses`](PollParameters::external_addresses).
+    /// Iterator returned by [`external_addresses`](PollParameters::external_addresses)
+    /// and [`external_address_candidates`](PollParameters::external_address_candidates).
     type ExternalAddressesIter: ExactSizeIterator<Item = Multiaddr>;
 
     /// Returns the list of protocol the behaviour supports when a remote negotiates a protocol on
@@ -195,9 +197,14 @@ pub trait PollParameters {
     /// Returns the list of the addresses we're listening on.
     fn listened_addresses(&self) -> Self::ListenedAddressesIter;
 
-    /// Returns the list of the addresses nodes can use to reach us.
+    /// Returns the list of the addresses nodes can use to reach us, i.e. the
+    /// confirmed external addresses.
     fn external_addresses(&self) -> Self::ExternalAddressesIter;
 
+    /// Returns the list of candidates for external addresses that are not
+    /// confirmed yet, e.g. for verifying them.
+    fn external_address_candidates(&self) -> Self::ExternalAddressesIter;
+
     /// Returns the peer id of the local node.
     fn local_peer_id(&self) -> &PeerId;
 }
@@ -272,13 +279,56 @@ pub enum NetworkBehaviourAction<TInEvent, TOutEvent> {
     /// Informs the `Swarm` about a multi-address observed by a remote for
     /// the local node.
     ///
+    /// The address is added to the candidates for external addresses and is
+    /// confirmed once it has been reported by enough distinct peers, see
+    /// [`SwarmBuilder::external_address_confirmations`](crate::SwarmBuilder::external_address_confirmations).
+    ///
     /// It is advisable to issue `ReportObservedAddr` actions at a fixed frequency
     /// per node. This way address information will be more accurate over time
     /// and individual outliers carry less weight.
     ReportObservedAddr {
         /// The observed address of the local node.
         address: Multiaddr,
+        /// The peer that observed the address.
+        peer_id: PeerId,
+    },
+
+    /// Informs the `Swarm` about an address of the local node that is likely
+    /// to be reachable by other nodes, e.g. because a port mapping has been
+    /// established for it on the gateway of the local network.
+    ///
+    /// Contrary to [`NetworkBehaviourAction::ReportObservedAddr`], the address
+    /// is added to the candidates for external addresses as is, without
+    /// translation. It is only advertised once confirmed.
+    AddExternalAddr {
+        /// The external address of the local node.
+        address: Multiaddr,
     },
+
+    /// Informs the `Swarm` that an address of the local node has been verified
+    /// to be reachable by other nodes, e.g. by AutoNAT, making it a confirmed
+    /// external address.
+    ConfirmExternalAddr {
+        /// The external address of the local node.
+        address: Multiaddr,
+    },
+
+    /// Informs the `Swarm` that an address previously reported via
+    /// [`NetworkBehaviourAction::AddExternalAddr`] or confirmed via
+    /// [`NetworkBehaviourAction::ConfirmExternalAddr`] is no longer reachable.
+    RemoveExternalAddr {
+        /// The expired external address of the local node.
+        address: Multiaddr,
+    },
+
+    /// Instructs the `Swarm` to recompute the protocols supported by the
+    /// local node, as returned by [`PollParameters::supported_protocols`].
+    ///
+    /// The protocols are obtained from the inbound protocol of a new
+    /// [`ProtocolsHandler`](NetworkBehaviour::ProtocolsHandler). Behaviours
+    /// emit this action after a change that affects the protocols their
+    /// handlers accept, e.g. Kademlia switching to client mode.
+    UpdateSupportedProtocols,
 }
 
 /// The options w.r.t. which connection handlers to notify of an event.
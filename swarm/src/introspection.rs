@@ -0,0 +1,53 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Snapshots of the state of a `Swarm` for debugging.
+
+use libp2p_core::{ConnectedPoint, Multiaddr, PeerId, connection::ConnectionId, network::NetworkInfo};
+
+/// A snapshot of the state of a `Swarm`, obtained from
+/// [`Swarm::state`](crate::Swarm::state).
+#[derive(Debug, Clone)]
+pub struct SwarmState {
+    /// The peer ID of the local node.
+    pub local_peer_id: PeerId,
+    /// The addresses the node is listening on.
+    pub listeners: Vec<Multiaddr>,
+    /// The confirmed external addresses of the node.
+    pub external_addresses: Vec<Multiaddr>,
+    /// The numbers of peers and connections.
+    pub network_info: NetworkInfo,
+    /// The established connections.
+    pub connections: Vec<ConnectionState>,
+}
+
+/// The state of an established connection in a [`SwarmState`].
+#[derive(Debug, Clone)]
+pub struct ConnectionState {
+    /// The ID of the connection.
+    pub id: ConnectionId,
+    /// The peer ID of the remote.
+    pub peer_id: PeerId,
+    /// The endpoint of the connection.
+    pub endpoint: ConnectedPoint,
+    /// The number of substreams negotiated on the connection since it was
+    /// established per protocol, in lexicographic order of the protocols.
+    pub substreams: Vec<(String, u64)>,
+}
@@ -53,7 +53,11 @@
 //! are supported, when to open a new outbound substream, etc.
 //!
 
+mod access;
 mod behaviour;
+mod introspection;
+mod memory_limits;
+mod protocol_registry;
 mod registry;
 mod upgrade;
 
@@ -80,7 +84,12 @@ pub use protocols_handler::{
     OneShotHandlerConfig,
     SubstreamProtocol
 };
+pub use introspection::{ConnectionState, SwarmState};
+pub use ipnet::IpNet;
+pub use memory_limits::MemoryConnectionLimits;
+pub use protocol_registry::ProtocolRegistry;
 
+use access::AccessControl;
 use protocols_handler::{
     NodeHandlerWrapperBuilder,
     NodeHandlerWrapperError,
@@ -91,12 +100,14 @@ use futures::{
     stream::FusedStream,
 };
 use libp2p_core::{
+    ConnectionGater,
     Executor,
     Transport,
     Multiaddr,
     Negotiated,
     PeerId,
     connection::{
+        CloseReason,
         ConnectionError,
         ConnectionId,
         ConnectionInfo,
@@ -121,10 +132,10 @@ use libp2p_core::{
 };
 use registry::{Addresses, AddressIntoIter};
 use smallvec::SmallVec;
-use std::{error, fmt, hash::Hash, io, ops::{Deref, DerefMut}, pin::Pin, task::{Context, Poll}};
-use std::collections::HashSet;
-use std::num::{NonZeroU32, NonZeroUsize};
+use std::{collections::VecDeque, error, fmt, hash::Hash, io, ops::{Deref, DerefMut}, pin::Pin, sync::Arc, task::{Context, Poll}, time::Duration};
+use std::num::{NonZeroU8, NonZeroU32, NonZeroUsize};
 use upgrade::UpgradeInfoSend as _;
+use wasm_timer::Delay;
 
 /// Contains the state of the network, plus the way it should behave.
 pub type Swarm<TBehaviour, TConnInfo = PeerId> = ExpandedSwarm<
@@ -184,7 +195,10 @@ pub enum SwarmEvent<TBvEv, THandleErr> {
     /// An error happened on a connection during its initial handshake.
     ///
     /// This can include, for example, an error during the handshake of the encryption layer, or
-    /// the connection unexpectedly closed.
+    /// the connection unexpectedly closed. Incoming connections that are dropped right away
+    /// because of the [limit](SwarmBuilder::incoming_connection_limit) of incoming connections
+    /// are reported with this event as well, without a preceding
+    /// [`IncomingConnection`](SwarmEvent::IncomingConnection) event.
     IncomingConnectionError {
         /// Local connection address.
         /// This address has been earlier reported with a [`NewListenAddr`](SwarmEvent::NewListenAddr)
@@ -195,7 +209,18 @@ pub enum SwarmEvent<TBvEv, THandleErr> {
         /// The error that happened.
         error: PendingConnectionError<io::Error>,
     },
-    /// We connected to a peer, but we immediately closed the connection because that peer is banned.
+    /// A new connection arrived on a listener from a blocked IP prefix, or was
+    /// denied by the [`ConnectionGater`](libp2p_core::ConnectionGater), and has
+    /// been dropped.
+    BlockedIncomingConnection {
+        /// Local connection address.
+        local_addr: Multiaddr,
+        /// Address used to send back data to the remote.
+        send_back_addr: Multiaddr,
+    },
+    /// We connected to a peer, but we immediately closed the connection because that peer is
+    /// banned, not on the allow list, connected from a blocked IP prefix, or the connection
+    /// was denied by the [`ConnectionGater`](libp2p_core::ConnectionGater).
     BannedPeer {
         /// Identity of the banned peer.
         peer_id: PeerId,
@@ -207,6 +232,10 @@ pub enum SwarmEvent<TBvEv, THandleErr> {
         /// `PeerId` that we were trying to reach.
         peer_id: PeerId,
         /// Address that we failed to reach.
+        ///
+        /// For addresses dialed concurrently, this is the first address and the
+        /// error is a [`PendingConnectionError::ConcurrentDial`] with the error
+        /// for each address.
         address: Multiaddr,
         /// Error that has been encountered.
         error: PendingConnectionError<io::Error>,
@@ -246,8 +275,17 @@ pub enum SwarmEvent<TBvEv, THandleErr> {
     /// A [`ConnectionEstablished`](SwarmEvent::ConnectionEstablished)
     /// event is reported if the dialing attempt succeeds, otherwise a
     /// [`UnreachableAddr`](SwarmEvent::UnreachableAddr) event is reported
-    /// with `attempts_remaining` equal to 0.
+    /// with `attempts_remaining` equal to 0, unless the dialing attempt
+    /// is aborted.
     Dialing(PeerId),
+    /// A connection attempt has been aborted with
+    /// [`ExpandedSwarm::abort_dial`] or [`ExpandedSwarm::abort_dial_attempt`].
+    DialAborted {
+        /// `PeerId` that we were trying to reach, if known.
+        peer_id: Option<PeerId>,
+        /// Address of the aborted connection attempt.
+        address: Multiaddr,
+    },
 }
 
 /// Contains the state of the network, plus the way it should behave.
@@ -279,13 +317,32 @@ where
     /// similar mechanisms.
     external_addrs: Addresses,
 
-    /// List of nodes for which we deny any incoming connection.
-    banned_peers: HashSet<PeerId>,
+    /// Banned peers, blocked IP prefixes and the allow list.
+    access: AccessControl,
+
+    /// The protocols supported by connected peers.
+    protocols: ProtocolRegistry,
+
+    /// Whether [`ExpandedSwarm::close`] has been called, in which case
+    /// dialing requests and incoming connections are ignored.
+    closing: bool,
+
+    /// How long [`ExpandedSwarm::close`] waits for connections to be
+    /// closed by their handlers.
+    close_timeout: Duration,
 
     /// Pending event to be delivered to connection handlers
     /// (or dropped if the peer disconnected) before the `behaviour`
     /// can be polled again.
-    pending_event: Option<(PeerId, PendingNotifyHandler, TInEvent)>
+    pending_event: Option<(PeerId, PendingNotifyHandler, TInEvent)>,
+
+    /// Connection attempts aborted with [`ExpandedSwarm::abort_dial_attempt`]
+    /// that have yet to be reported as [`SwarmEvent::DialAborted`].
+    aborted_dials: VecDeque<(Option<PeerId>, Multiaddr)>,
+
+    /// Connections closed by the local node that have yet to be reported
+    /// as [`SwarmEvent::ConnectionClosed`].
+    closed_connections: VecDeque<(PeerId, ConnectedPoint, u32, CloseReason)>,
 }
 
 impl<TBehaviour, TInEvent, TOutEvent, THandler, TConnInfo> Deref for
@@ -368,31 +425,49 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
 
     /// Tries to dial the given address.
     ///
-    /// Returns an error if the address is not supported.
-    pub fn dial_addr(me: &mut Self, addr: Multiaddr) -> Result<(), ConnectionLimit> {
+    /// Returns the ID of the connection attempt, which can be aborted with
+    /// [`ExpandedSwarm::abort_dial_attempt`], or an error if the address is
+    /// within a blocked IP prefix, is denied by the
+    /// [`ConnectionGater`](libp2p_core::ConnectionGater) or if the connection
+    /// limit has been reached.
+    pub fn dial_addr(me: &mut Self, addr: Multiaddr) -> Result<ConnectionId, DialError> {
+        if me.access.is_addr_blocked(&addr) {
+            return Err(DialError::Blocked)
+        }
+        if !me.access.intercept_addr_dial(None, &addr) {
+            return Err(DialError::Denied)
+        }
         let handler = me.behaviour.new_handler();
-        me.network.dial(&addr, handler.into_node_handler_builder()).map(|_id| ())
+        me.network.dial(&addr, handler.into_node_handler_builder().with_protocol_registry(me.protocols.clone()))
+            .map_err(DialError::ConnectionLimit)
     }
 
     /// Tries to initiate a dialing attempt to the given peer.
     ///
-    /// If a new dialing attempt has been initiated, `Ok(true)` is returned.
-    ///
-    /// If no new dialing attempt has been initiated, meaning there is an ongoing
-    /// dialing attempt or `addresses_of_peer` reports no addresses, `Ok(false)`
-    /// is returned.
-    pub fn dial(me: &mut Self, peer_id: &PeerId) -> Result<(), DialError> {
+    /// If a new dialing attempt has been initiated, the connection ID of its
+    /// first connection attempt is returned. Otherwise, e.g. if the peer is
+    /// banned or `addresses_of_peer` reports no addresses, an error is
+    /// returned and the behaviour is informed with `inject_dial_failure`.
+    pub fn dial(me: &mut Self, peer_id: &PeerId) -> Result<ConnectionId, DialError> {
         let self_listening = &me.listened_addrs;
+        let access = &me.access;
         let mut addrs = me.behaviour.addresses_of_peer(peer_id)
             .into_iter()
-            .filter(|a| !self_listening.contains(a));
+            .filter(|a| !self_listening.contains(a) && !access.is_addr_blocked(a))
+            .filter(|a| access.intercept_addr_dial(Some(peer_id), a));
 
         let result =
-            if let Some(first) = addrs.next() {
-                let handler = me.behaviour.new_handler().into_node_handler_builder();
+            if access.is_peer_denied(peer_id) {
+                Err(DialError::Banned)
+            } else if !access.intercept_peer_dial(peer_id) {
+                Err(DialError::Denied)
+            } else if let Some(first) = addrs.next() {
+                let handler = me.behaviour.new_handler()
+                    .into_node_handler_builder()
+                    .with_protocol_registry(me.protocols.clone());
                 me.network.peer(peer_id.clone())
                     .dial(first, addrs, handler)
-                    .map(|_| ())
+                    .map(|(id, _)| id)
                     .map_err(DialError::ConnectionLimit)
             } else {
                 Err(DialError::NoAddresses)
@@ -408,30 +483,95 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
         result
     }
 
+    /// Aborts all ongoing dialing attempts to the given peer, dropping the
+    /// pending connections and the addresses that have not been tried yet.
+    ///
+    /// A [`SwarmEvent::DialAborted`] is reported for every aborted connection
+    /// attempt and the behaviour is informed with `inject_dial_failure`,
+    /// unless there is an established connection to the peer.
+    ///
+    /// Returns `true` if there was an ongoing dialing attempt to the peer.
+    pub fn abort_dial(me: &mut Self, peer_id: &PeerId) -> bool {
+        let attempts = me.network.dialing_attempts(peer_id).collect::<SmallVec<[_; 10]>>();
+        for id in &attempts {
+            ExpandedSwarm::abort_dial_attempt(me, *id);
+        }
+        !attempts.is_empty()
+    }
+
+    /// Aborts the connection attempt with the given ID, as returned by
+    /// [`ExpandedSwarm::dial`] or [`ExpandedSwarm::dial_addr`] or as the
+    /// ID of the current connection attempt of a dialing attempt, in which
+    /// case the remaining addresses of the dialing attempt are not tried.
+    ///
+    /// A [`SwarmEvent::DialAborted`] is reported for the aborted connection
+    /// attempt. If it was the last dialing attempt to a known peer, the
+    /// behaviour is informed with `inject_dial_failure`, unless there is an
+    /// established connection to the peer.
+    ///
+    /// Returns `true` if there was a pending outgoing connection with the
+    /// given ID.
+    pub fn abort_dial_attempt(me: &mut Self, id: ConnectionId) -> bool {
+        let (peer_id, address) = match me.network.abort_dial(id) {
+            Some(aborted) => aborted,
+            None => return false,
+        };
+        log::debug!("Aborted connection attempt to {:?} via {:?}.", peer_id, address);
+        if let Some(peer_id) = &peer_id {
+            if me.network.is_disconnected(peer_id) {
+                me.behaviour.inject_dial_failure(peer_id);
+            }
+        }
+        me.aborted_dials.push_back((peer_id, address));
+        true
+    }
+
     /// Returns an iterator that produces the list of addresses we're listening on.
     pub fn listeners(me: &Self) -> impl Iterator<Item = &Multiaddr> {
         me.network.listen_addrs()
     }
 
     /// Returns an iterator that produces the list of addresses that other nodes can use to reach
-    /// us.
+    /// us, i.e. the confirmed external addresses.
     pub fn external_addresses(me: &Self) -> impl Iterator<Item = &Multiaddr> {
         me.external_addrs.iter()
     }
 
+    /// Returns an iterator over the candidates for external addresses that are not confirmed yet.
+    pub fn external_address_candidates(me: &Self) -> impl Iterator<Item = &Multiaddr> {
+        me.external_addrs.candidates()
+    }
+
     /// Returns the peer ID of the swarm passed as parameter.
     pub fn local_peer_id(me: &Self) -> &PeerId {
         &me.network.local_peer_id()
     }
 
-    /// Adds an external address.
+    /// Adds a confirmed external address.
     ///
     /// An external address is an address we are listening on but that accounts for things such as
     /// NAT traversal.
     pub fn add_external_address(me: &mut Self, addr: Multiaddr) {
+        if me.external_addrs.confirm(addr.clone()) {
+            me.behaviour.inject_new_external_addr(&addr);
+        }
+    }
+
+    /// Adds a candidate for an external address.
+    ///
+    /// The address is only advertised once it is confirmed, by being reported by enough distinct
+    /// peers or through [`NetworkBehaviourAction::ConfirmExternalAddr`].
+    pub fn add_external_address_candidate(me: &mut Self, addr: Multiaddr) {
         me.external_addrs.add(addr)
     }
 
+    /// Removes an external address, regardless of its score.
+    ///
+    /// Returns `true` if the address was known.
+    pub fn remove_external_address(me: &mut Self, addr: &Multiaddr) -> bool {
+        me.external_addrs.remove(addr)
+    }
+
     /// Returns the connection info for an arbitrary connection with the peer, or `None`
     /// if there is no connection to that peer.
     // TODO: should take &self instead of &mut self, but the API in network requires &mut
@@ -443,20 +583,180 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
         }
     }
 
+    /// Returns the protocols known to be supported by a peer, i.e. those
+    /// negotiated on its connections or reported by the peer, in
+    /// lexicographic order.
+    ///
+    /// See [`ProtocolRegistry`] for details.
+    pub fn supported_protocols(me: &Self, peer_id: &PeerId) -> Vec<String> {
+        me.protocols.supported_protocols(peer_id)
+    }
+
+    /// Returns the ID, the connected endpoint and the connection information of
+    /// every established connection, e.g. for displaying a table of connections.
+    pub fn connections(me: &mut Self) -> Vec<(ConnectionId, ConnectedPoint, TConnInfo)> {
+        let peers = me.network.connected_peers().cloned().collect::<Vec<_>>();
+        let mut connections = Vec::new();
+        for peer in peers {
+            if let Some(mut peer) = me.network.peer(peer).into_connected() {
+                let mut iter = peer.connections();
+                while let Some(c) = iter.next() {
+                    connections.push((c.id(), c.endpoint().clone(), c.info().clone()));
+                }
+            }
+        }
+        connections
+    }
+
+    /// Takes a snapshot of the state of the `Swarm`, i.e. its listeners,
+    /// external addresses and established connections with the substreams
+    /// negotiated on them, e.g. for displaying it in a debugging UI.
+    pub fn state(me: &mut Self) -> SwarmState {
+        let connections = Swarm::connections(me)
+            .into_iter()
+            .map(|(id, endpoint, info)| {
+                let peer_id = info.peer_id().clone();
+                let substreams = me.protocols.negotiated_substreams(&peer_id, &endpoint);
+                ConnectionState { id, peer_id, endpoint, substreams }
+            })
+            .collect();
+        SwarmState {
+            local_peer_id: Swarm::local_peer_id(me).clone(),
+            listeners: Swarm::listeners(me).cloned().collect(),
+            external_addresses: Swarm::external_addresses(me).cloned().collect(),
+            network_info: Swarm::network_info(me),
+            connections,
+        }
+    }
+
     /// Bans a peer by its peer ID.
     ///
     /// Any incoming connection and any dialing attempt will immediately be rejected.
     /// This function has no effect is the peer is already banned.
     pub fn ban_peer_id(me: &mut Self, peer_id: PeerId) {
-        me.banned_peers.insert(peer_id.clone());
-        if let Some(c) = me.network.peer(peer_id).into_connected() {
-            c.disconnect();
-        }
+        me.access.ban_peer(peer_id.clone());
+        Self::close_connections(me, &peer_id, CloseReason::Banned, |_, _| true);
     }
 
     /// Unbans a peer.
     pub fn unban_peer_id(me: &mut Self, peer_id: PeerId) {
-        me.banned_peers.remove(&peer_id);
+        me.access.unban_peer(&peer_id);
+    }
+
+    /// Blocks an IP prefix.
+    ///
+    /// Existing connections with a remote address within the prefix are closed.
+    /// Incoming connections from and dialing attempts to addresses within the
+    /// prefix are rejected.
+    pub fn block_ip_prefix(me: &mut Self, prefix: IpNet) {
+        me.access.block_ip_prefix(prefix);
+        let peers = me.network.connected_peers().cloned().collect::<Vec<_>>();
+        for peer_id in peers {
+            Self::close_connections(me, &peer_id, CloseReason::Denied, |access, endpoint| {
+                access.is_endpoint_blocked(endpoint)
+            });
+        }
+    }
+
+    /// Unblocks an IP prefix previously blocked with [`ExpandedSwarm::block_ip_prefix`].
+    pub fn unblock_ip_prefix(me: &mut Self, prefix: &IpNet) {
+        me.access.unblock_ip_prefix(prefix);
+    }
+
+    /// Adds a peer to the allow list.
+    ///
+    /// The allow list only has an effect if enabled with
+    /// [`ExpandedSwarm::enable_allow_list`].
+    pub fn allow_peer_id(me: &mut Self, peer_id: PeerId) {
+        me.access.allow_peer(peer_id);
+    }
+
+    /// Removes a peer from the allow list.
+    ///
+    /// If the allow list is enabled, the peer is disconnected.
+    pub fn disallow_peer_id(me: &mut Self, peer_id: PeerId) {
+        me.access.disallow_peer(&peer_id);
+        if me.access.is_peer_denied(&peer_id) {
+            Self::close_connections(me, &peer_id, CloseReason::Denied, |_, _| true);
+        }
+    }
+
+    /// Enables the allow list, such that only connections with peers added
+    /// through [`ExpandedSwarm::allow_peer_id`] are kept.
+    ///
+    /// Peers not on the allow list are disconnected.
+    pub fn enable_allow_list(me: &mut Self) {
+        me.access.set_allow_list_enabled(true);
+        let denied = me.network.connected_peers()
+            .filter(|p| me.access.is_peer_denied(p))
+            .cloned()
+            .collect::<Vec<_>>();
+        for peer_id in denied {
+            Self::close_connections(me, &peer_id, CloseReason::Denied, |_, _| true);
+        }
+    }
+
+    /// Disables the allow list, such that all peers that are not banned may connect.
+    pub fn disable_allow_list(me: &mut Self) {
+        me.access.set_allow_list_enabled(false);
+    }
+
+    /// Closes all established connections to a peer for the given reason.
+    ///
+    /// Every closed connection is reported to the `NetworkBehaviour` and as a
+    /// [`SwarmEvent::ConnectionClosed`] with a [`ConnectionError::Closed`]
+    /// cause carrying the `reason`.
+    ///
+    /// > **Note**: The reason is only reported locally. None of the supported
+    /// >           stream multiplexers can transmit it to the remote, which only
+    /// >           observes the connection being closed. For instance, yamux
+    /// >           closes the connection with a `GoAway` frame without a reason.
+    ///
+    /// Returns `false` if there was no established connection to the peer.
+    pub fn disconnect_peer_id(me: &mut Self, peer_id: &PeerId, reason: CloseReason) -> bool {
+        Self::close_connections(me, peer_id, reason, |_, _| true) > 0
+    }
+
+    /// Closes the established connections to a peer for which the given
+    /// filter returns `true`, informing the `NetworkBehaviour` and queueing
+    /// [`SwarmEvent::ConnectionClosed`] events for them.
+    ///
+    /// Returns the number of closed connections.
+    fn close_connections<F>(me: &mut Self, peer_id: &PeerId, reason: CloseReason, mut filter: F) -> usize
+    where
+        F: FnMut(&AccessControl, &ConnectedPoint) -> bool
+    {
+        let access = &me.access;
+        let (closed, remaining) = match me.network.peer(peer_id.clone()).into_connected() {
+            Some(mut peer) => {
+                let ids = peer.connections().into_ids().collect::<Vec<_>>();
+                let mut closed = Vec::new();
+                for id in ids {
+                    if let Some(c) = peer.connection(id) {
+                        if filter(access, c.endpoint()) {
+                            let endpoint = c.endpoint().clone();
+                            c.close();
+                            closed.push((id, endpoint));
+                        }
+                    }
+                }
+                (closed, peer.num_connections())
+            }
+            None => return 0,
+        };
+
+        let num_closed = closed.len();
+        for (i, (id, endpoint)) in closed.into_iter().enumerate() {
+            log::debug!("Connection {:?} to {:?} closed: {}", id, peer_id, reason);
+            let num_established = (remaining + num_closed - i - 1) as u32;
+            me.behaviour.inject_connection_closed(peer_id, &id, &endpoint);
+            if num_established == 0 {
+                me.behaviour.inject_disconnected(peer_id);
+                me.protocols.remove_reported(peer_id);
+            }
+            me.closed_connections.push_back((peer_id.clone(), endpoint, num_established, reason.clone()));
+        }
+        num_closed
     }
 
     /// Returns the next event that happens in the `Swarm`.
@@ -466,6 +766,16 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
         future::poll_fn(move |cx| ExpandedSwarm::poll_next_event(Pin::new(self), cx)).await
     }
 
+    /// Returns a stream of all events that happen in the `Swarm`, as
+    /// returned by [`ExpandedSwarm::next_event`].
+    ///
+    /// A failed dialing attempt to a peer is reported as an
+    /// [`UnreachableAddr`](SwarmEvent::UnreachableAddr) event with
+    /// `attempts_remaining` equal to 0.
+    pub fn events(&mut self) -> impl Stream<Item = SwarmEvent<TBehaviour::OutEvent, THandleErr>> + '_ {
+        stream::poll_fn(move |cx| ExpandedSwarm::poll_next_event(Pin::new(&mut *self), cx).map(Some))
+    }
+
     /// Returns the next event produced by the [`NetworkBehaviour`].
     pub async fn next(&mut self) -> TBehaviour::OutEvent {
         future::poll_fn(move |cx| {
@@ -478,6 +788,47 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
         }).await
     }
 
+    /// Gracefully shuts down the `Swarm`.
+    ///
+    /// All listeners are removed and, from then on, dialing requests of the
+    /// [`NetworkBehaviour`] and incoming connections are ignored. The `Swarm`
+    /// then waits for the established connections to be closed by their
+    /// [`ProtocolsHandler`]s, i.e. once these no longer keep them alive,
+    /// and closes the remaining connections once the timeout configured
+    /// with [`SwarmBuilder::close_timeout`] has elapsed.
+    ///
+    /// Resolves once the background tasks of all connections have
+    /// terminated. Events occurring in the meantime are still delivered
+    /// to the [`NetworkBehaviour`] but are otherwise discarded.
+    pub async fn close(&mut self) {
+        self.closing = true;
+        self.network.remove_all_listeners();
+        for addr in self.listened_addrs.drain(..) {
+            self.behaviour.inject_expired_listen_addr(&addr);
+        }
+
+        let mut timeout = Delay::new(self.close_timeout);
+        future::poll_fn(|cx| {
+            loop {
+                if self.network.num_connections_established() == 0
+                    && self.network.num_connections_pending() == 0
+                {
+                    return Poll::Ready(())
+                }
+                if timeout.poll_unpin(cx).is_ready() {
+                    return Poll::Ready(())
+                }
+                futures::ready!(ExpandedSwarm::poll_next_event(Pin::new(&mut *self), cx));
+            }
+        }).await;
+
+        self.network.disconnect_all();
+        future::poll_fn(|cx| {
+            while ExpandedSwarm::poll_next_event(Pin::new(&mut *self), cx).is_ready() {}
+            self.network.poll_terminated(cx)
+        }).await
+    }
+
     /// Internal function used by everything event-related.
     ///
     /// Polls the `Swarm` for the next event.
@@ -488,6 +839,19 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
         // across a `Deref`.
         let this = &mut *self;
 
+        if let Some((peer_id, address)) = this.aborted_dials.pop_front() {
+            return Poll::Ready(SwarmEvent::DialAborted { peer_id, address })
+        }
+
+        if let Some((peer_id, endpoint, num_established, reason)) = this.closed_connections.pop_front() {
+            return Poll::Ready(SwarmEvent::ConnectionClosed {
+                peer_id,
+                endpoint,
+                num_established,
+                cause: ConnectionError::Closed(reason),
+            })
+        }
+
         loop {
             let mut network_not_ready = false;
 
@@ -507,11 +871,12 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                 Poll::Ready(NetworkEvent::ConnectionEstablished { connection, num_established }) => {
                     let peer_id = connection.peer_id().clone();
                     let endpoint = connection.endpoint().clone();
-                    if this.banned_peers.contains(&peer_id) {
-                        this.network.peer(peer_id.clone())
-                            .into_connected()
-                            .expect("the Network just notified us that we were connected; QED")
-                            .disconnect();
+                    if this.access.is_connection_denied(&peer_id, &endpoint)
+                        || !this.access.intercept_upgraded(&peer_id, &endpoint)
+                    {
+                        // Only close the new connection. Other connections to the
+                        // peer may have been established through permitted addresses.
+                        connection.close();
                         return Poll::Ready(SwarmEvent::BannedPeer {
                             peer_id,
                             endpoint,
@@ -536,6 +901,7 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                     this.behaviour.inject_connection_closed(info.peer_id(), &id, &endpoint);
                     if num_established == 0 {
                         this.behaviour.inject_disconnected(info.peer_id());
+                        this.protocols.remove_reported(info.peer_id());
                     }
                     return Poll::Ready(SwarmEvent::ConnectionClosed {
                         peer_id: info.peer_id().clone(),
@@ -545,11 +911,31 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                     });
                 },
                 Poll::Ready(NetworkEvent::IncomingConnection(incoming)) => {
-                    let handler = this.behaviour.new_handler();
                     let local_addr = incoming.local_addr().clone();
                     let send_back_addr = incoming.send_back_addr().clone();
-                    if let Err(e) = incoming.accept(handler.into_node_handler_builder()) {
-                        log::warn!("Incoming connection rejected: {:?}", e);
+                    if this.closing {
+                        log::debug!("Incoming connection from {} dropped while closing.", send_back_addr);
+                        continue
+                    }
+                    if this.access.is_addr_blocked(&send_back_addr)
+                        || !this.access.intercept_accept(&local_addr, &send_back_addr)
+                    {
+                        log::debug!("Incoming connection from blocked address {} dropped.", send_back_addr);
+                        return Poll::Ready(SwarmEvent::BlockedIncomingConnection {
+                            local_addr,
+                            send_back_addr,
+                        });
+                    }
+                    let handler = this.behaviour.new_handler();
+                    let handler = handler.into_node_handler_builder()
+                        .with_protocol_registry(this.protocols.clone());
+                    if let Err(e) = incoming.accept(handler) {
+                        log::debug!("Incoming connection from {} rejected: {:?}", send_back_addr, e);
+                        return Poll::Ready(SwarmEvent::IncomingConnectionError {
+                            local_addr,
+                            send_back_addr,
+                            error: PendingConnectionError::ConnectionLimit(e),
+                        });
                     }
                     return Poll::Ready(SwarmEvent::IncomingConnection {
                         local_addr,
@@ -602,7 +988,13 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                     log::debug!(
                         "Connection attempt to {:?} via {:?} failed with {:?}. Attempts remaining: {}.",
                         peer_id, multiaddr, error, attempts_remaining);
-                    this.behaviour.inject_addr_reach_failure(Some(&peer_id), &multiaddr, &error);
+                    if let PendingConnectionError::ConcurrentDial(errors) = &error {
+                        for (address, error) in errors {
+                            this.behaviour.inject_addr_reach_failure(Some(&peer_id), address, error);
+                        }
+                    } else {
+                        this.behaviour.inject_addr_reach_failure(Some(&peer_id), &multiaddr, &error);
+                    }
                     if attempts_remaining == 0 {
                         this.behaviour.inject_dial_failure(&peer_id);
                     }
@@ -676,10 +1068,15 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                     return Poll::Ready(SwarmEvent::Behaviour(event))
                 },
                 Poll::Ready(NetworkBehaviourAction::DialAddress { address }) => {
-                    let _ = ExpandedSwarm::dial_addr(&mut *this, address);
+                    if !this.closing {
+                        let _ = ExpandedSwarm::dial_addr(&mut *this, address);
+                    }
                 },
                 Poll::Ready(NetworkBehaviourAction::DialPeer { peer_id, condition }) => {
-                    if this.banned_peers.contains(&peer_id) {
+                    if this.closing
+                        || this.access.is_peer_denied(&peer_id)
+                        || !this.access.intercept_peer_dial(&peer_id)
+                    {
                         this.behaviour.inject_dial_failure(&peer_id);
                     } else {
                         let condition_matched = match condition {
@@ -687,6 +1084,7 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                                 if this.network.is_disconnected(&peer_id) => true,
                             DialPeerCondition::NotDialing
                                 if !this.network.is_dialing(&peer_id) => true,
+                            DialPeerCondition::Always => true,
                             _ => false
                         };
                         if condition_matched {
@@ -743,14 +1141,27 @@ where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
                         }
                     }
                 },
-                Poll::Ready(NetworkBehaviourAction::ReportObservedAddr { address }) => {
+                Poll::Ready(NetworkBehaviourAction::ReportObservedAddr { address, peer_id }) => {
                     for addr in this.network.address_translation(&address) {
-                        if this.external_addrs.iter().all(|a| *a != addr) {
+                        if this.external_addrs.report(addr.clone(), peer_id.clone()) {
                             this.behaviour.inject_new_external_addr(&addr);
                         }
-                        this.external_addrs.add(addr);
                     }
                 },
+                Poll::Ready(NetworkBehaviourAction::AddExternalAddr { address }) => {
+                    this.external_addrs.add(address);
+                },
+                Poll::Ready(NetworkBehaviourAction::ConfirmExternalAddr { address }) => {
+                    if this.external_addrs.confirm(address.clone()) {
+                        this.behaviour.inject_new_external_addr(&address);
+                    }
+                },
+                Poll::Ready(NetworkBehaviourAction::RemoveExternalAddr { address }) => {
+                    this.external_addrs.remove(&address);
+                },
+                Poll::Ready(NetworkBehaviourAction::UpdateSupportedProtocols) => {
+                    this.supported_protocols = supported_protocols(&mut this.behaviour);
+                },
             }
         }
     }
@@ -896,6 +1307,21 @@ where
     None
 }
 
+/// Computes the protocols supported by the local node from the inbound
+/// protocol of a new connection handler of the given behaviour.
+fn supported_protocols<TBehaviour>(behaviour: &mut TBehaviour) -> SmallVec<[Vec<u8>; 16]>
+where
+    TBehaviour: NetworkBehaviour,
+{
+    behaviour
+        .new_handler()
+        .inbound_protocol()
+        .protocol_info()
+        .into_iter()
+        .map(|info| info.protocol_name().to_vec())
+        .collect()
+}
+
 impl<TBehaviour, TInEvent, TOutEvent, THandler, TConnInfo> Stream for
     ExpandedSwarm<TBehaviour, TInEvent, TOutEvent, THandler, TConnInfo>
 where TBehaviour: NetworkBehaviour<ProtocolsHandler = THandler>,
@@ -958,6 +1384,10 @@ impl<'a> PollParameters for SwarmPollParameters<'a> {
         self.external_addrs.clone().into_iter()
     }
 
+    fn external_address_candidates(&self) -> Self::ExternalAddressesIter {
+        self.external_addrs.clone().into_candidates()
+    }
+
     fn local_peer_id(&self) -> &PeerId {
         self.local_peer_id
     }
@@ -970,6 +1400,10 @@ pub struct SwarmBuilder<TBehaviour, TConnInfo> {
     transport: BoxTransport<(TConnInfo, StreamMuxerBox), io::Error>,
     behaviour: TBehaviour,
     network_config: NetworkConfig,
+    close_timeout: Duration,
+    connection_gater: Option<Arc<dyn ConnectionGater>>,
+    external_address_confirmations: NonZeroUsize,
+    protocols: ProtocolRegistry,
 }
 
 impl<TBehaviour, TConnInfo> SwarmBuilder<TBehaviour, TConnInfo>
@@ -1001,6 +1435,10 @@ where TBehaviour: NetworkBehaviour,
             transport,
             behaviour,
             network_config: Default::default(),
+            close_timeout: Duration::from_secs(10),
+            connection_gater: None,
+            external_address_confirmations: NonZeroUsize::new(2).expect("2 > 0"),
+            protocols: ProtocolRegistry::new(),
         }
     }
 
@@ -1027,6 +1465,16 @@ where TBehaviour: NetworkBehaviour,
         self
     }
 
+    /// Configures the number of distinct peers that need to report an observed
+    /// address of the local node via [`NetworkBehaviourAction::ReportObservedAddr`]
+    /// for it to become a confirmed external address.
+    ///
+    /// Only confirmed external addresses are advertised. Defaults to 2.
+    pub fn external_address_confirmations(mut self, n: NonZeroUsize) -> Self {
+        self.external_address_confirmations = n;
+        self
+    }
+
     /// Configures the number of extra events from the [`ProtocolsHandler`] in
     /// destination to the [`NetworkBehaviour`] that can be buffered before
     /// the [`ProtocolsHandler`] has to go to sleep.
@@ -1056,12 +1504,28 @@ where TBehaviour: NetworkBehaviour,
     }
 
     /// Configures a limit for the number of simultaneous incoming
-    /// connection attempts.
+    /// connection attempts, i.e. incoming connections whose handshakes are
+    /// still in progress.
+    ///
+    /// Incoming connections beyond this limit are dropped right away,
+    /// resulting in a [`SwarmEvent::IncomingConnectionError`] with a
+    /// [`PendingConnectionError::ConnectionLimit`] error.
     pub fn incoming_connection_limit(mut self, n: usize) -> Self {
         self.network_config.set_incoming_limit(n);
         self
     }
 
+    /// Configures the time within which the handshakes of an incoming
+    /// connection must complete.
+    ///
+    /// Incoming connections that take longer are dropped, resulting in a
+    /// [`SwarmEvent::IncomingConnectionError`] with a
+    /// [`PendingConnectionError::Timeout`] error.
+    pub fn incoming_connection_timeout(mut self, timeout: Duration) -> Self {
+        self.network_config.set_incoming_timeout(timeout);
+        self
+    }
+
     /// Configures a limit for the number of simultaneous outgoing
     /// connection attempts.
     pub fn outgoing_connection_limit(mut self, n: usize) -> Self {
@@ -1069,6 +1533,32 @@ where TBehaviour: NetworkBehaviour,
         self
     }
 
+    /// Configures a limit for the number of simultaneous
+    /// established connections, across all peers.
+    ///
+    /// Connections established beyond this limit are closed right away,
+    /// resulting in a [`PendingConnectionError::ConnectionLimit`] error.
+    pub fn connection_limit(mut self, n: usize) -> Self {
+        self.network_config.set_established_limit(n);
+        self
+    }
+
+    /// Configures the number of addresses dialed concurrently when
+    /// dialing a peer for which [`NetworkBehaviour::addresses_of_peer`]
+    /// reports more than one address.
+    ///
+    /// The first connection established is kept and the remaining dials
+    /// are cancelled. If all addresses fail, a single
+    /// [`UnreachableAddr`](SwarmEvent::UnreachableAddr) event with a
+    /// [`PendingConnectionError::ConcurrentDial`] error listing the error
+    /// for each address is reported.
+    ///
+    /// Defaults to 1, i.e. the addresses are dialed one after the other.
+    pub fn dial_concurrency_factor(mut self, factor: NonZeroU8) -> Self {
+        self.network_config.set_dial_concurrency_factor(factor);
+        self
+    }
+
     /// Configures a limit for the number of simultaneous
     /// established connections per peer.
     pub fn peer_connection_limit(mut self, n: usize) -> Self {
@@ -1076,15 +1566,47 @@ where TBehaviour: NetworkBehaviour,
         self
     }
 
+    /// Configures how long [`ExpandedSwarm::close`] waits for the
+    /// connections to be closed by their [`ProtocolsHandler`]s before
+    /// closing the remaining connections.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn close_timeout(mut self, timeout: Duration) -> Self {
+        self.close_timeout = timeout;
+        self
+    }
+
+    /// Configures a [`ConnectionGater`] that is consulted before dialing,
+    /// on incoming connections and once connections are fully upgraded.
+    ///
+    /// To also consult the gater once the remote of a connection is
+    /// authenticated, the transport must be upgraded with
+    /// [`Builder::gate`](libp2p_core::transport::upgrade::Builder::gate)
+    /// using the same gater.
+    pub fn connection_gater(mut self, gater: Arc<dyn ConnectionGater>) -> Self {
+        self.connection_gater = Some(gater);
+        self
+    }
+
+    /// Configures the [`ProtocolRegistry`] in which the protocols negotiated
+    /// with connected peers are recorded, e.g. to share it with a
+    /// [`NetworkBehaviour`]. By default, the `Swarm` creates its own registry.
+    pub fn protocol_registry(mut self, registry: ProtocolRegistry) -> Self {
+        self.protocols = registry;
+        self
+    }
+
     /// Builds a `Swarm` with the current configuration.
     pub fn build(mut self) -> Swarm<TBehaviour, TConnInfo> {
-        let supported_protocols = self.behaviour
-            .new_handler()
-            .inbound_protocol()
-            .protocol_info()
-            .into_iter()
-            .map(|info| info.protocol_name().to_vec())
-            .collect();
+        let mut access = AccessControl::default();
+        if let Some(gater) = self.connection_gater.take() {
+            access.set_gater(gater);
+        }
+
+        let mut external_addrs = Addresses::default();
+        external_addrs.set_confirmations(self.external_address_confirmations);
+
+        let supported_protocols = supported_protocols(&mut self.behaviour);
 
         let mut network_cfg = self.network_config;
 
@@ -1114,9 +1636,14 @@ where TBehaviour: NetworkBehaviour,
             behaviour: self.behaviour,
             supported_protocols,
             listened_addrs: SmallVec::new(),
-            external_addrs: Addresses::default(),
-            banned_peers: HashSet::new(),
-            pending_event: None
+            external_addrs,
+            access,
+            protocols: self.protocols,
+            closing: false,
+            close_timeout: self.close_timeout,
+            pending_event: None,
+            aborted_dials: VecDeque::new(),
+            closed_connections: VecDeque::new(),
         }
     }
 }
@@ -1129,14 +1656,23 @@ pub enum DialError {
     ConnectionLimit(ConnectionLimit),
     /// [`NetworkBehaviour::addresses_of_peer`] returned no addresses
     /// for the peer to dial.
-    NoAddresses
+    NoAddresses,
+    /// The peer is banned or not on the enabled allow list.
+    Banned,
+    /// The address to dial is within a blocked IP prefix.
+    Blocked,
+    /// The [`ConnectionGater`](libp2p_core::ConnectionGater) denied the dial.
+    Denied,
 }
 
 impl fmt::Display for DialError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             DialError::ConnectionLimit(err) => write!(f, "Dial error: {}", err),
-            DialError::NoAddresses => write!(f, "Dial error: no addresses for peer.")
+            DialError::NoAddresses => write!(f, "Dial error: no addresses for peer."),
+            DialError::Banned => write!(f, "Dial error: peer is banned."),
+            DialError::Blocked => write!(f, "Dial error: address is blocked."),
+            DialError::Denied => write!(f, "Dial error: denied by the connection gater."),
         }
     }
 }
@@ -1145,7 +1681,10 @@ impl error::Error for DialError {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self {
             DialError::ConnectionLimit(err) => Some(err),
-            DialError::NoAddresses => None
+            DialError::NoAddresses => None,
+            DialError::Banned => None,
+            DialError::Blocked => None,
+            DialError::Denied => None,
         }
     }
 }
@@ -1189,14 +1728,35 @@ impl NetworkBehaviour for DummyBehaviour {
 
 #[cfg(test)]
 mod tests {
-    use crate::{DummyBehaviour, SwarmBuilder};
+    use crate::{
+        DialError,
+        DummyBehaviour,
+        ExpandedSwarm,
+        NetworkBehaviour,
+        NetworkBehaviourAction,
+        PollParameters,
+        Swarm,
+        SwarmBuilder,
+        SwarmEvent,
+    };
+    use crate::protocols_handler::{IntoProtocolsHandler, OneShotHandler, ProtocolsHandlerUpgrErr, SubstreamProtocol};
+    use futures::{executor::block_on, future};
     use libp2p_core::{
+        ConnectedPoint,
+        ConnectionGater,
+        Multiaddr,
         PeerId,
         PublicKey,
+        connection::{CloseReason, ConnectionError, ConnectionId, PendingConnectionError},
         identity,
-        transport::dummy::{DummyStream, DummyTransport}
+        multiaddr::Protocol,
+        transport::{Transport, MemoryTransport, dummy::{DummyStream, DummyTransport}},
+        upgrade::{self, DeniedUpgrade},
     };
-    use libp2p_mplex::Multiplex;
+    use libp2p_mplex::{Multiplex, MplexConfig};
+    use libp2p_plaintext::PlainText2Config;
+    use libp2p_tcp::TcpConfig;
+    use std::{pin::Pin, sync::{Arc, atomic::{AtomicBool, Ordering}}, task::{Context, Poll}, time::Duration};
 
     fn get_random_id() -> PublicKey {
         identity::Keypair::generate_ed25519().public()
@@ -1220,4 +1780,341 @@ mod tests {
         let swarm = SwarmBuilder::new(transport, DummyBehaviour {}, id.into()).build();
         assert!(swarm.network.incoming_limit().is_none())
     }
+
+    #[test]
+    fn test_dial_denied() {
+        let id = get_random_id();
+        let transport = DummyTransport::<(PeerId, Multiplex<DummyStream>)>::new();
+        let mut swarm = SwarmBuilder::new(transport, DummyBehaviour {}, id.into()).build();
+
+        Swarm::block_ip_prefix(&mut swarm, "127.0.0.0/8".parse().unwrap());
+        let addr = "/ip4/127.0.0.1/tcp/4001".parse().unwrap();
+        assert!(matches!(Swarm::dial_addr(&mut swarm, addr), Err(DialError::Blocked)));
+
+        let peer = PeerId::random();
+        Swarm::ban_peer_id(&mut swarm, peer.clone());
+        assert!(matches!(Swarm::dial(&mut swarm, &peer), Err(DialError::Banned)));
+        Swarm::unban_peer_id(&mut swarm, peer.clone());
+
+        Swarm::enable_allow_list(&mut swarm);
+        assert!(matches!(Swarm::dial(&mut swarm, &peer), Err(DialError::Banned)));
+        Swarm::allow_peer_id(&mut swarm, peer.clone());
+        assert!(matches!(Swarm::dial(&mut swarm, &peer), Err(DialError::NoAddresses)));
+    }
+
+    fn memory_swarm_builder() -> SwarmBuilder<DummyBehaviour, PeerId> {
+        let local_public_key = get_random_id();
+        let local_peer_id = local_public_key.clone().into_peer_id();
+        let transport = MemoryTransport::default()
+            .upgrade(upgrade::Version::V1)
+            .authenticate(PlainText2Config { local_public_key })
+            .multiplex(MplexConfig::new());
+        SwarmBuilder::new(transport, DummyBehaviour {}, local_peer_id)
+            .close_timeout(Duration::from_secs(1))
+    }
+
+    fn new_memory_swarm() -> Swarm<DummyBehaviour> {
+        memory_swarm_builder().build()
+    }
+
+    fn listen(swarm: &mut Swarm<DummyBehaviour>) -> Multiaddr {
+        let addr = Protocol::Memory(rand::random::<u64>()).into();
+        Swarm::listen_on(swarm, addr).unwrap();
+        block_on(async {
+            loop {
+                if let SwarmEvent::NewListenAddr(addr) = swarm.next_event().await {
+                    break addr
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_close() {
+        let mut swarm1 = new_memory_swarm();
+        let mut swarm2 = new_memory_swarm();
+        let addr = listen(&mut swarm1);
+
+        Swarm::dial_addr(&mut swarm2, addr).unwrap();
+        wait_for(&mut swarm1, &mut swarm2, |e| matches!(e, SwarmEvent::ConnectionEstablished { .. }));
+
+        let driver = async move {
+            loop {
+                swarm2.next_event().await;
+            }
+        };
+        block_on(future::select(Box::pin(swarm1.close()), Box::pin(driver)));
+
+        assert_eq!(Swarm::listeners(&swarm1).count(), 0);
+        assert_eq!(swarm1.network.listen_addrs().count(), 0);
+        assert_eq!(swarm1.network.num_connections_established(), 0);
+        assert_eq!(swarm1.network.num_connections_pending(), 0);
+    }
+
+    #[test]
+    fn test_connections() {
+        let mut swarm1 = new_memory_swarm();
+        let mut swarm2 = new_memory_swarm();
+        let addr = listen(&mut swarm1);
+        assert!(Swarm::connections(&mut swarm1).is_empty());
+
+        Swarm::dial_addr(&mut swarm2, addr).unwrap();
+        wait_for(&mut swarm1, &mut swarm2, |e| matches!(e, SwarmEvent::ConnectionEstablished { .. }));
+
+        let connections = Swarm::connections(&mut swarm1);
+        assert_eq!(connections.len(), 1);
+        let (_, endpoint, peer) = &connections[0];
+        assert!(endpoint.is_listener());
+        assert_eq!(peer, Swarm::local_peer_id(&swarm2));
+    }
+
+    #[test]
+    fn test_abort_dial() {
+        let mut swarm1 = new_memory_swarm();
+        let mut swarm2 = new_memory_swarm();
+        // `swarm1` is not polled anymore, hence the connection upgrade of
+        // dialing attempts to it never completes.
+        let addr = listen(&mut swarm1);
+        let peer1 = Swarm::local_peer_id(&swarm1).clone();
+
+        let id = Swarm::dial_addr(&mut swarm2, addr.clone()).unwrap();
+        assert!(Swarm::abort_dial_attempt(&mut swarm2, id));
+        assert!(!Swarm::abort_dial_attempt(&mut swarm2, id));
+        assert_eq!(swarm2.network.num_connections_pending(), 0);
+        match block_on(swarm2.next_event()) {
+            SwarmEvent::DialAborted { peer_id: None, address } => assert_eq!(address, addr),
+            e => panic!("Unexpected event: {:?}", e),
+        }
+
+        let handler = swarm2.behaviour.new_handler().into_node_handler_builder();
+        let other = Protocol::Memory(rand::random::<u64>()).into();
+        swarm2.network.peer(peer1.clone()).dial(addr.clone(), vec![other], handler).unwrap();
+        assert!(Swarm::abort_dial(&mut swarm2, &peer1));
+        assert!(!Swarm::abort_dial(&mut swarm2, &peer1));
+        assert!(swarm2.network.is_disconnected(&peer1));
+        match block_on(swarm2.next_event()) {
+            SwarmEvent::DialAborted { peer_id, address } => {
+                assert_eq!(peer_id, Some(peer1));
+                assert_eq!(address, addr);
+            }
+            e => panic!("Unexpected event: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_disconnect_peer_id() {
+        let mut swarm1 = new_memory_swarm();
+        let mut swarm2 = new_memory_swarm();
+        let addr = listen(&mut swarm1);
+        let peer2 = Swarm::local_peer_id(&swarm2).clone();
+        let reason = CloseReason::Application("shutting down".into());
+
+        Swarm::dial_addr(&mut swarm2, addr).unwrap();
+        wait_for(&mut swarm1, &mut swarm2, |e| matches!(e, SwarmEvent::ConnectionEstablished { .. }));
+
+        assert!(Swarm::disconnect_peer_id(&mut swarm1, &peer2, reason.clone()));
+        assert!(!Swarm::disconnect_peer_id(&mut swarm1, &peer2, reason.clone()));
+        assert!(swarm1.network.is_disconnected(&peer2));
+        match block_on(swarm1.next_event()) {
+            SwarmEvent::ConnectionClosed { peer_id, endpoint, num_established, cause } => {
+                assert_eq!(peer_id, peer2);
+                assert!(endpoint.is_listener());
+                assert_eq!(num_established, 0);
+                assert!(matches!(cause, ConnectionError::Closed(r) if r == reason));
+            }
+            e => panic!("Unexpected event: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_incoming_connection_limit_and_timeout() {
+        let mut swarm = memory_swarm_builder()
+            .incoming_connection_limit(1)
+            .incoming_connection_timeout(Duration::from_millis(100))
+            .build();
+        let addr = listen(&mut swarm);
+
+        // Connections on which the handshakes never start.
+        let _conn1 = block_on(MemoryTransport.dial(addr.clone()).unwrap()).unwrap();
+        let _conn2 = block_on(MemoryTransport.dial(addr).unwrap()).unwrap();
+
+        block_on(async {
+            match swarm.next_event().await {
+                SwarmEvent::IncomingConnection { .. } => {}
+                e => panic!("Unexpected event: {:?}", e),
+            }
+            match swarm.next_event().await {
+                SwarmEvent::IncomingConnectionError { error: PendingConnectionError::ConnectionLimit(_), .. } => {}
+                e => panic!("Unexpected event: {:?}", e),
+            }
+            match swarm.next_event().await {
+                SwarmEvent::IncomingConnectionError { error: PendingConnectionError::Timeout, .. } => {}
+                e => panic!("Unexpected event: {:?}", e),
+            }
+        });
+        assert_eq!(swarm.network.num_connections_pending(), 0);
+    }
+
+    /// Polls both swarms until the first one emits an event matching `f`.
+    fn wait_for(
+        swarm1: &mut Swarm<DummyBehaviour>,
+        swarm2: &mut Swarm<DummyBehaviour>,
+        f: impl Fn(&SwarmEvent<void::Void, void::Void>) -> bool,
+    ) {
+        block_on(future::poll_fn(|cx| {
+            while ExpandedSwarm::poll_next_event(Pin::new(&mut *swarm2), cx).is_ready() {}
+            loop {
+                match ExpandedSwarm::poll_next_event(Pin::new(&mut *swarm1), cx) {
+                    Poll::Ready(event) if f(&event) => return Poll::Ready(()),
+                    Poll::Ready(_) => {}
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+        }))
+    }
+
+    #[derive(Default)]
+    struct TestGater {
+        deny_dial: AtomicBool,
+        deny_accept: AtomicBool,
+        deny_upgraded: AtomicBool,
+    }
+
+    impl ConnectionGater for TestGater {
+        fn intercept_peer_dial(&self, _: &PeerId) -> bool {
+            !self.deny_dial.load(Ordering::SeqCst)
+        }
+
+        fn intercept_addr_dial(&self, _: Option<&PeerId>, _: &Multiaddr) -> bool {
+            !self.deny_dial.load(Ordering::SeqCst)
+        }
+
+        fn intercept_accept(&self, _: &Multiaddr, _: &Multiaddr) -> bool {
+            !self.deny_accept.load(Ordering::SeqCst)
+        }
+
+        fn intercept_upgraded(&self, _: &PeerId, _: &ConnectedPoint) -> bool {
+            !self.deny_upgraded.load(Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn test_connection_gater() {
+        let gater = Arc::new(TestGater::default());
+        let mut swarm1 = memory_swarm_builder().connection_gater(gater.clone()).build();
+        let mut swarm2 = new_memory_swarm();
+        let addr = listen(&mut swarm1);
+
+        gater.deny_dial.store(true, Ordering::SeqCst);
+        let peer = PeerId::random();
+        assert!(matches!(Swarm::dial(&mut swarm1, &peer), Err(DialError::Denied)));
+        assert!(matches!(Swarm::dial_addr(&mut swarm1, addr.clone()), Err(DialError::Denied)));
+        gater.deny_dial.store(false, Ordering::SeqCst);
+
+        gater.deny_accept.store(true, Ordering::SeqCst);
+        Swarm::dial_addr(&mut swarm2, addr.clone()).unwrap();
+        wait_for(&mut swarm1, &mut swarm2, |e| matches!(e, SwarmEvent::BlockedIncomingConnection { .. }));
+        gater.deny_accept.store(false, Ordering::SeqCst);
+
+        gater.deny_upgraded.store(true, Ordering::SeqCst);
+        Swarm::dial_addr(&mut swarm2, addr).unwrap();
+        wait_for(&mut swarm1, &mut swarm2, |e| matches!(e, SwarmEvent::BannedPeer { .. }));
+    }
+
+    /// A behaviour keeping its connections alive, since [`DummyBehaviour`]
+    /// closes them as soon as they are idle.
+    struct KeepAliveBehaviour;
+
+    impl NetworkBehaviour for KeepAliveBehaviour {
+        type ProtocolsHandler = OneShotHandler<DeniedUpgrade, DeniedUpgrade, void::Void>;
+        type OutEvent = void::Void;
+
+        fn new_handler(&mut self) -> Self::ProtocolsHandler {
+            OneShotHandler::new(SubstreamProtocol::new(DeniedUpgrade), Default::default())
+        }
+
+        fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+            Vec::new()
+        }
+
+        fn inject_connected(&mut self, _: &PeerId) {}
+
+        fn inject_disconnected(&mut self, _: &PeerId) {}
+
+        fn inject_event(&mut self, _: PeerId, _: ConnectionId, event: void::Void) {
+            void::unreachable(event)
+        }
+
+        fn poll(&mut self, _: &mut Context<'_>, _: &mut impl PollParameters)
+            -> Poll<NetworkBehaviourAction<DeniedUpgrade, void::Void>>
+        {
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn test_denied_connection_keeps_other_connections() {
+        fn new_swarm() -> Swarm<KeepAliveBehaviour> {
+            let local_public_key = get_random_id();
+            let local_peer_id = local_public_key.clone().into_peer_id();
+            let transport = MemoryTransport
+                .or_transport(TcpConfig::new())
+                .upgrade(upgrade::Version::V1)
+                .authenticate(PlainText2Config { local_public_key })
+                .multiplex(MplexConfig::new());
+            SwarmBuilder::new(transport, KeepAliveBehaviour, local_peer_id).build()
+        }
+
+        /// Polls both swarms until the first one emits an event matching `f`,
+        /// failing if it reports a closed connection.
+        fn wait_for(
+            swarm1: &mut Swarm<KeepAliveBehaviour>,
+            swarm2: &mut Swarm<KeepAliveBehaviour>,
+            f: impl Fn(&SwarmEvent<void::Void, ProtocolsHandlerUpgrErr<void::Void>>) -> bool,
+        ) {
+            block_on(future::poll_fn(|cx| {
+                while ExpandedSwarm::poll_next_event(Pin::new(&mut *swarm2), cx).is_ready() {}
+                loop {
+                    match ExpandedSwarm::poll_next_event(Pin::new(&mut *swarm1), cx) {
+                        Poll::Ready(SwarmEvent::ConnectionClosed { cause, .. }) =>
+                            panic!("Unexpected closed connection: {:?}", cause),
+                        Poll::Ready(event) if f(&event) => return Poll::Ready(()),
+                        Poll::Ready(_) => {}
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+            }))
+        }
+
+        let mut swarm1 = new_swarm();
+        let mut swarm2 = new_swarm();
+        let peer2 = Swarm::local_peer_id(&swarm2).clone();
+        Swarm::listen_on(&mut swarm1, Protocol::Memory(rand::random::<u64>()).into()).unwrap();
+        Swarm::listen_on(&mut swarm2, "/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+        let (memory_addr, tcp_addr) = block_on(async {
+            let memory_addr = loop {
+                if let SwarmEvent::NewListenAddr(addr) = swarm1.next_event().await {
+                    break addr
+                }
+            };
+            let tcp_addr = loop {
+                if let SwarmEvent::NewListenAddr(addr) = swarm2.next_event().await {
+                    break addr
+                }
+            };
+            (memory_addr, tcp_addr)
+        });
+
+        Swarm::dial_addr(&mut swarm2, memory_addr).unwrap();
+        wait_for(&mut swarm1, &mut swarm2, |e| matches!(e, SwarmEvent::ConnectionEstablished { .. }));
+
+        // The prefix is blocked while the second connection is being established.
+        Swarm::dial_addr(&mut swarm1, tcp_addr).unwrap();
+        Swarm::block_ip_prefix(&mut swarm1, "127.0.0.0/8".parse().unwrap());
+        wait_for(&mut swarm1, &mut swarm2, |e| matches!(e, SwarmEvent::BannedPeer { .. }));
+
+        let mut peer = swarm1.network.peer(peer2).into_connected().unwrap();
+        assert_eq!(peer.num_connections(), 1);
+        assert!(peer.some_connection().endpoint().is_listener());
+    }
 }
@@ -0,0 +1,214 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Refusing new connections while the process uses too much memory.
+
+use libp2p_core::{ConnectionGater, Multiaddr, PeerId};
+use std::{sync::Mutex, time::Duration};
+use wasm_timer::Instant;
+
+/// A [`ConnectionGater`] denying new incoming and outgoing connections while
+/// the memory used by the process exceeds an absolute or a relative
+/// threshold, such that a heavily loaded node degrades gracefully instead
+/// of running out of memory.
+///
+/// The memory usage is the resident set size of the process, relative to
+/// the total physical memory for [`MemoryConnectionLimits::with_max_percentage`].
+/// Connections are denied as soon as any of the configured thresholds is
+/// exceeded.
+/// It is currently only known on Linux. On other platforms, no connections
+/// are denied.
+///
+/// Established connections are not affected.
+#[derive(Debug)]
+pub struct MemoryConnectionLimits {
+    max_bytes: Option<u64>,
+    max_percentage: Option<f64>,
+    refresh_interval: Duration,
+    /// The last time the memory usage was obtained, if ever, and whether it
+    /// exceeded the thresholds.
+    last: Mutex<Option<(Instant, bool)>>,
+}
+
+impl MemoryConnectionLimits {
+    /// Creates a `MemoryConnectionLimits` without thresholds, i.e. denying
+    /// no connections until a threshold is configured.
+    pub fn new() -> Self {
+        MemoryConnectionLimits {
+            max_bytes: None,
+            max_percentage: None,
+            refresh_interval: Duration::from_secs(1),
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Denies new connections while the process uses more than `max_bytes`
+    /// of memory.
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Denies new connections while the process uses more than the given
+    /// percentage of the total physical memory.
+    ///
+    /// # Panics
+    ///
+    /// If the percentage is not within `0.0..=100.0`.
+    pub fn with_max_percentage(mut self, percentage: f64) -> Self {
+        assert!((0.0..=100.0).contains(&percentage), "percentage must be within 0 and 100");
+        self.max_percentage = Some(percentage);
+        self
+    }
+
+    /// Sets the interval at which the memory usage is obtained anew. In
+    /// between, the decision based on the last memory usage is reused.
+    ///
+    /// Defaults to 1 second.
+    pub fn with_refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    /// Returns whether the memory usage of the process currently exceeds
+    /// the configured thresholds.
+    pub fn is_exceeded(&self) -> bool {
+        if self.max_bytes.is_none() && self.max_percentage.is_none() {
+            return false
+        }
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        match *last {
+            Some((at, exceeded)) if now.duration_since(at) < self.refresh_interval => exceeded,
+            _ => {
+                let exceeded = self.check(memory_usage());
+                if exceeded {
+                    log::debug!("Memory usage exceeds the connection limits.");
+                }
+                *last = Some((now, exceeded));
+                exceeded
+            }
+        }
+    }
+
+    /// Checks the memory usage, given as the used and the total bytes,
+    /// against the thresholds.
+    fn check(&self, usage: Option<(u64, u64)>) -> bool {
+        let (used, total) = match usage {
+            Some(usage) => usage,
+            None => return false,
+        };
+        if let Some(max) = self.max_bytes {
+            if used > max {
+                return true
+            }
+        }
+        if let Some(max) = self.max_percentage {
+            if total > 0 && used as f64 * 100.0 / total as f64 > max {
+                return true
+            }
+        }
+        false
+    }
+}
+
+impl Default for MemoryConnectionLimits {
+    fn default() -> Self {
+        MemoryConnectionLimits::new()
+    }
+}
+
+impl ConnectionGater for MemoryConnectionLimits {
+    fn intercept_peer_dial(&self, _: &PeerId) -> bool {
+        !self.is_exceeded()
+    }
+
+    fn intercept_addr_dial(&self, _: Option<&PeerId>, _: &Multiaddr) -> bool {
+        !self.is_exceeded()
+    }
+
+    fn intercept_accept(&self, _: &Multiaddr, _: &Multiaddr) -> bool {
+        !self.is_exceeded()
+    }
+}
+
+/// Returns the resident set size of the process and the total physical
+/// memory in bytes.
+#[cfg(target_os = "linux")]
+fn memory_usage() -> Option<(u64, u64)> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    Some((parse_kb(&status, "VmRSS:")?, parse_kb(&meminfo, "MemTotal:")?))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn memory_usage() -> Option<(u64, u64)> {
+    None
+}
+
+/// Parses a line of the form `<key> <value> kB` of a file in `/proc`,
+/// returning the value in bytes.
+#[cfg(any(target_os = "linux", test))]
+fn parse_kb(contents: &str, key: &str) -> Option<u64> {
+    let line = contents.lines().find(|l| l.starts_with(key))?;
+    let kb = line[key.len()..].trim().trim_end_matches("kB").trim().parse::<u64>().ok()?;
+    kb.checked_mul(1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_proc_files() {
+        let status = "Name:\tcat\nVmPeak:\t    8060 kB\nVmRSS:\t     948 kB\n";
+        assert_eq!(parse_kb(status, "VmRSS:"), Some(948 * 1024));
+        assert_eq!(parse_kb(status, "VmSwap:"), None);
+    }
+
+    #[test]
+    fn thresholds() {
+        let limits = MemoryConnectionLimits::new().with_max_bytes(1000);
+        assert!(!limits.check(Some((1000, 4000))));
+        assert!(limits.check(Some((1001, 4000))));
+        assert!(!limits.check(None));
+
+        let limits = MemoryConnectionLimits::new().with_max_percentage(25.0);
+        assert!(!limits.check(Some((1000, 4000))));
+        assert!(limits.check(Some((1001, 4000))));
+
+        let limits = limits.with_max_bytes(500);
+        assert!(limits.check(Some((501, 4000))));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn deny_connections() {
+        let addr = "/memory/1".parse().unwrap();
+        let limits = MemoryConnectionLimits::new().with_max_bytes(1);
+        assert!(!limits.intercept_accept(&addr, &addr));
+        assert!(!limits.intercept_peer_dial(&PeerId::random()));
+
+        let limits = MemoryConnectionLimits::new().with_max_percentage(100.0);
+        assert!(limits.intercept_accept(&addr, &addr));
+        assert!(MemoryConnectionLimits::new().intercept_accept(&addr, &addr));
+        assert!(limits.intercept_addr_dial(None, &addr));
+    }
+}
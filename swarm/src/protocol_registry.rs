@@ -0,0 +1,297 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Tracking the protocols supported by connected peers.
+
+use libp2p_core::{ConnectedPoint, PeerId, upgrade::{InboundUpgrade, OutboundUpgrade, ProtocolName, UpgradeInfo}};
+use std::{collections::{HashMap, HashSet}, fmt, sync::{Arc, Mutex}};
+
+/// The protocols known to be supported by connected peers.
+///
+/// A protocol is known to be supported by a peer if it has been successfully
+/// negotiated on a substream of any connection to the peer, or if the peer
+/// reported it, e.g. through the identify protocol (see
+/// [`ProtocolRegistry::set_reported_protocols`]). The protocols negotiated
+/// on a connection are forgotten once the connection is closed, the reported
+/// protocols once the peer is disconnected.
+///
+/// The registry of a `Swarm` is shared by all clones of a `ProtocolRegistry`
+/// passed to [`SwarmBuilder::protocol_registry`](crate::SwarmBuilder::protocol_registry),
+/// such that a `NetworkBehaviour` holding a clone can avoid proposing
+/// protocols that a remote does not support.
+#[derive(Clone, Default)]
+pub struct ProtocolRegistry {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    peers: HashMap<PeerId, PeerProtocols>,
+    /// The key of the next connection added to the registry.
+    next_connection: u64,
+}
+
+#[derive(Default)]
+struct PeerProtocols {
+    /// The protocols negotiated on each connection to the peer.
+    connections: HashMap<u64, ConnectionSubstreams>,
+    /// The protocols reported by the peer.
+    reported: HashSet<String>,
+}
+
+/// The substreams negotiated on a connection.
+struct ConnectionSubstreams {
+    endpoint: ConnectedPoint,
+    /// The number of substreams negotiated per protocol.
+    protocols: HashMap<String, u64>,
+}
+
+impl PeerProtocols {
+    fn is_empty(&self) -> bool {
+        self.connections.is_empty() && self.reported.is_empty()
+    }
+}
+
+impl ProtocolRegistry {
+    /// Creates an empty `ProtocolRegistry`.
+    pub fn new() -> Self {
+        ProtocolRegistry::default()
+    }
+
+    /// Returns the protocols known to be supported by a peer, in
+    /// lexicographic order.
+    pub fn supported_protocols(&self, peer: &PeerId) -> Vec<String> {
+        let inner = self.inner.lock().expect("not poisoned");
+        let mut protocols = match inner.peers.get(peer) {
+            Some(p) => p.connections.values()
+                .flat_map(|c| c.protocols.keys())
+                .chain(p.reported.iter())
+                .cloned()
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+        protocols.sort();
+        protocols
+    }
+
+    /// Checks whether a protocol is known to be supported by a peer.
+    pub fn supports(&self, peer: &PeerId, protocol: &str) -> bool {
+        let inner = self.inner.lock().expect("not poisoned");
+        match inner.peers.get(peer) {
+            Some(p) => p.reported.contains(protocol)
+                || p.connections.values().any(|c| c.protocols.contains_key(protocol)),
+            None => false,
+        }
+    }
+
+    /// Sets the protocols reported by a peer, replacing those reported earlier.
+    pub fn set_reported_protocols<I>(&self, peer: &PeerId, protocols: I)
+    where
+        I: IntoIterator<Item = String>
+    {
+        let mut inner = self.inner.lock().expect("not poisoned");
+        let entry = inner.peers.entry(peer.clone()).or_default();
+        entry.reported = protocols.into_iter().collect();
+        if entry.is_empty() {
+            inner.peers.remove(peer);
+        }
+    }
+
+    /// Forgets the protocols reported by a peer, e.g. once it is disconnected.
+    pub(crate) fn remove_reported(&self, peer: &PeerId) {
+        let mut inner = self.inner.lock().expect("not poisoned");
+        if let Some(entry) = inner.peers.get_mut(peer) {
+            entry.reported.clear();
+            if entry.is_empty() {
+                inner.peers.remove(peer);
+            }
+        }
+    }
+
+    /// Returns the number of substreams negotiated per protocol on the
+    /// connections to a peer with the given endpoint, in lexicographic order
+    /// of the protocols.
+    pub(crate) fn negotiated_substreams(&self, peer: &PeerId, endpoint: &ConnectedPoint) -> Vec<(String, u64)> {
+        let inner = self.inner.lock().expect("not poisoned");
+        let mut substreams = HashMap::<String, u64>::new();
+        // Connections are only distinguished by their endpoint, which is
+        // unique among the connections to a peer but for rare exceptions.
+        let connections = inner.peers.get(peer)
+            .into_iter()
+            .flat_map(|p| p.connections.values())
+            .filter(|c| &c.endpoint == endpoint);
+        for c in connections {
+            for (protocol, n) in &c.protocols {
+                *substreams.entry(protocol.clone()).or_default() += n;
+            }
+        }
+        let mut substreams = substreams.into_iter().collect::<Vec<_>>();
+        substreams.sort();
+        substreams
+    }
+
+    /// Adds a connection to a peer, returning the handle through which the
+    /// protocols negotiated on the connection are recorded.
+    pub(crate) fn add_connection(&self, peer: &PeerId, endpoint: &ConnectedPoint) -> ConnectionProtocols {
+        let mut inner = self.inner.lock().expect("not poisoned");
+        let id = inner.next_connection;
+        inner.next_connection += 1;
+        ConnectionProtocols {
+            inner: Arc::new(ConnectionEntry {
+                registry: self.clone(),
+                peer: peer.clone(),
+                endpoint: endpoint.clone(),
+                id,
+            })
+        }
+    }
+}
+
+impl fmt::Debug for ProtocolRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.inner.lock().expect("not poisoned");
+        f.debug_struct("ProtocolRegistry")
+            .field("peers", &inner.peers.len())
+            .finish()
+    }
+}
+
+/// Records the protocols negotiated on a connection in a [`ProtocolRegistry`].
+///
+/// The protocols are removed from the registry once the handle and all its
+/// clones are dropped.
+#[derive(Clone)]
+pub(crate) struct ConnectionProtocols {
+    inner: Arc<ConnectionEntry>,
+}
+
+struct ConnectionEntry {
+    registry: ProtocolRegistry,
+    peer: PeerId,
+    endpoint: ConnectedPoint,
+    id: u64,
+}
+
+impl ConnectionProtocols {
+    /// Records a substream negotiated on the connection.
+    pub(crate) fn record(&self, protocol: &[u8]) {
+        // Protocol names can be bytes, but are reported as UTF-8 strings
+        // e.g. by the identify protocol.
+        let protocol = String::from_utf8_lossy(protocol).to_string();
+        let entry = &self.inner;
+        let mut inner = entry.registry.inner.lock().expect("not poisoned");
+        let connection = inner.peers.entry(entry.peer.clone())
+            .or_default()
+            .connections.entry(entry.id)
+            .or_insert_with(|| ConnectionSubstreams {
+                endpoint: entry.endpoint.clone(),
+                protocols: HashMap::new(),
+            });
+        *connection.protocols.entry(protocol).or_default() += 1;
+    }
+}
+
+impl Drop for ConnectionEntry {
+    fn drop(&mut self) {
+        let mut inner = self.registry.inner.lock().expect("not poisoned");
+        if let Some(entry) = inner.peers.get_mut(&self.peer) {
+            entry.connections.remove(&self.id);
+            if entry.is_empty() {
+                inner.peers.remove(&self.peer);
+            }
+        }
+    }
+}
+
+/// Wraps around an upgrade, recording the negotiated protocol in
+/// [`ConnectionProtocols`] before applying the upgrade.
+pub(crate) struct RecordProtocol<T> {
+    pub(crate) upgrade: T,
+    pub(crate) protocols: ConnectionProtocols,
+}
+
+impl<T: UpgradeInfo> UpgradeInfo for RecordProtocol<T> {
+    type Info = T::Info;
+    type InfoIter = T::InfoIter;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.upgrade.protocol_info()
+    }
+}
+
+impl<C, T: InboundUpgrade<C>> InboundUpgrade<C> for RecordProtocol<T> {
+    type Output = T::Output;
+    type Error = T::Error;
+    type Future = T::Future;
+
+    fn upgrade_inbound(self, socket: C, info: Self::Info) -> Self::Future {
+        self.protocols.record(info.protocol_name());
+        self.upgrade.upgrade_inbound(socket, info)
+    }
+}
+
+impl<C, T: OutboundUpgrade<C>> OutboundUpgrade<C> for RecordProtocol<T> {
+    type Output = T::Output;
+    type Error = T::Error;
+    type Future = T::Future;
+
+    fn upgrade_outbound(self, socket: C, info: Self::Info) -> Self::Future {
+        self.protocols.record(info.protocol_name());
+        self.upgrade.upgrade_outbound(socket, info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiated_and_reported_protocols() {
+        let registry = ProtocolRegistry::new();
+        let peer = PeerId::random();
+        assert!(registry.supported_protocols(&peer).is_empty());
+
+        let endpoint1 = ConnectedPoint::Dialer { address: "/memory/1".parse().unwrap() };
+        let endpoint2 = ConnectedPoint::Dialer { address: "/memory/2".parse().unwrap() };
+        let conn1 = registry.add_connection(&peer, &endpoint1);
+        let conn2 = registry.add_connection(&peer, &endpoint2);
+        conn1.record(b"/foo/1.0.0");
+        conn2.record(b"/bar/1.0.0");
+        conn2.record(b"/foo/1.0.0");
+        conn2.record(b"/foo/1.0.0");
+        assert_eq!(registry.negotiated_substreams(&peer, &endpoint2),
+            vec![("/bar/1.0.0".to_string(), 1), ("/foo/1.0.0".to_string(), 2)]);
+        registry.set_reported_protocols(&peer, vec!["/baz/1.0.0".to_string()]);
+        assert_eq!(registry.supported_protocols(&peer), vec!["/bar/1.0.0", "/baz/1.0.0", "/foo/1.0.0"]);
+        assert!(registry.supports(&peer, "/bar/1.0.0"));
+        assert!(!registry.supports(&PeerId::random(), "/bar/1.0.0"));
+
+        drop(conn2);
+        assert_eq!(registry.supported_protocols(&peer), vec!["/baz/1.0.0", "/foo/1.0.0"]);
+
+        registry.remove_reported(&peer);
+        assert_eq!(registry.supported_protocols(&peer), vec!["/foo/1.0.0"]);
+
+        drop(conn1);
+        assert!(registry.inner.lock().unwrap().peers.is_empty());
+    }
+}
@@ -18,6 +18,7 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+use crate::protocol_registry::{ConnectionProtocols, ProtocolRegistry, RecordProtocol};
 use crate::upgrade::SendWrapper;
 use crate::protocols_handler::{
     KeepAlive,
@@ -50,6 +51,8 @@ use wasm_timer::{Delay, Instant};
 pub struct NodeHandlerWrapperBuilder<TIntoProtoHandler> {
     /// The underlying handler.
     handler: TIntoProtoHandler,
+    /// The registry of the protocols negotiated on the connection.
+    protocols: ProtocolRegistry,
 }
 
 impl<TIntoProtoHandler> NodeHandlerWrapperBuilder<TIntoProtoHandler>
@@ -60,8 +63,15 @@ where
     pub(crate) fn new(handler: TIntoProtoHandler) -> Self {
         NodeHandlerWrapperBuilder {
             handler,
+            protocols: ProtocolRegistry::new(),
         }
     }
+
+    /// Sets the registry in which the protocols negotiated on the connection are recorded.
+    pub(crate) fn with_protocol_registry(mut self, protocols: ProtocolRegistry) -> Self {
+        self.protocols = protocols;
+        self
+    }
 }
 
 impl<TIntoProtoHandler, TProtoHandler, TConnInfo> IntoConnectionHandler<TConnInfo>
@@ -81,6 +91,7 @@ where
             queued_dial_upgrades: Vec::new(),
             unique_dial_upgrade_id: 0,
             shutdown: Shutdown::None,
+            protocols: self.protocols.add_connection(connected.peer_id(), &connected.endpoint),
         }
     }
 }
@@ -95,13 +106,15 @@ where
     /// The underlying handler.
     handler: TProtoHandler,
     /// Futures that upgrade incoming substreams.
-    negotiating_in:
-        Vec<(InboundUpgradeApply<Substream<StreamMuxerBox>, SendWrapper<TProtoHandler::InboundProtocol>>, Delay)>,
+    negotiating_in: Vec<(
+        InboundUpgradeApply<Substream<StreamMuxerBox>, RecordProtocol<SendWrapper<TProtoHandler::InboundProtocol>>>,
+        Delay,
+    )>,
     /// Futures that upgrade outgoing substreams. The first element of the tuple is the userdata
     /// to pass back once successfully opened.
     negotiating_out: Vec<(
         TProtoHandler::OutboundOpenInfo,
-        OutboundUpgradeApply<Substream<StreamMuxerBox>, SendWrapper<TProtoHandler::OutboundProtocol>>,
+        OutboundUpgradeApply<Substream<StreamMuxerBox>, RecordProtocol<SendWrapper<TProtoHandler::OutboundProtocol>>>,
         Delay,
     )>,
     /// For each outbound substream request, how to upgrade it. The first element of the tuple
@@ -111,6 +124,8 @@ where
     unique_dial_upgrade_id: u64,
     /// The currently planned connection & handler shutdown.
     shutdown: Shutdown,
+    /// Records the protocols negotiated on the connection.
+    protocols: ConnectionProtocols,
 }
 
 /// The options for a planned connection & handler shutdown.
@@ -192,7 +207,11 @@ where
             SubstreamEndpoint::Listener => {
                 let protocol = self.handler.listen_protocol();
                 let timeout = protocol.timeout().clone();
-                let upgrade = upgrade::apply_inbound(substream, SendWrapper(protocol.into_upgrade().1));
+                let upgrade = RecordProtocol {
+                    upgrade: SendWrapper(protocol.into_upgrade().1),
+                    protocols: self.protocols.clone(),
+                };
+                let upgrade = upgrade::apply_inbound(substream, upgrade);
                 let timeout = Delay::new(timeout);
                 self.negotiating_in.push((upgrade, timeout));
             }
@@ -210,6 +229,7 @@ where
                 };
 
                 let (_, (version, upgrade)) = self.queued_dial_upgrades.remove(pos);
+                let upgrade = RecordProtocol { upgrade, protocols: self.protocols.clone() };
                 let upgrade = upgrade::apply_outbound(substream, upgrade, version);
                 let timeout = Delay::new(timeout);
                 self.negotiating_out.push((user_data, upgrade, timeout));
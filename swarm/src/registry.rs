@@ -18,7 +18,7 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use libp2p_core::Multiaddr;
+use libp2p_core::{Multiaddr, PeerId};
 use smallvec::SmallVec;
 use std::{collections::VecDeque, num::NonZeroUsize};
 
@@ -27,12 +27,18 @@ use std::{collections::VecDeque, num::NonZeroUsize};
 /// Every address has an associated score and iterating over addresses will return them
 /// in order from highest to lowest. When reaching the limit, addresses with the lowest
 /// score will be dropped first.
+///
+/// Addresses start out as candidates and are only returned by [`Addresses::iter`] once
+/// they are confirmed, either explicitly or by having been reported by a number of
+/// distinct peers. Confirmed addresses are never dropped because of their score.
 #[derive(Debug, Clone)]
 pub struct Addresses {
     /// The ranked sequence of addresses.
     registry: SmallVec<[Record; 8]>,
     /// Number of historical reports. Similar to `reports.capacity()`.
     limit: NonZeroUsize,
+    /// Number of distinct peers that need to report an address for it to be confirmed.
+    confirmations: NonZeroUsize,
     /// Queue of last reports. Every new report is added to the queue. If the queue reaches its
     /// capacity, we also pop the first element.
     reports: VecDeque<Multiaddr>,
@@ -42,7 +48,16 @@ pub struct Addresses {
 #[derive(Clone, Debug, PartialEq, Eq)]
 struct Record {
     score: u32,
-    addr: Multiaddr
+    addr: Multiaddr,
+    confirmed: bool,
+    // The distinct peers that reported the address, up to the number of confirmations.
+    reporters: SmallVec<[PeerId; 2]>,
+}
+
+impl Record {
+    fn new(addr: Multiaddr) -> Self {
+        Record { score: 1, addr, confirmed: false, reporters: SmallVec::new() }
+    }
 }
 
 impl Default for Addresses {
@@ -57,15 +72,58 @@ impl Addresses {
         Addresses {
             registry: SmallVec::new(),
             limit,
+            confirmations: NonZeroUsize::new(2).expect("2 > 0"),
             reports: VecDeque::with_capacity(limit.get()),
         }
     }
 
-    /// Add a [`Multiaddr`] to the collection.
+    /// Sets the number of distinct peers that need to report an address via
+    /// [`Addresses::report`] for it to be confirmed.
+    pub fn set_confirmations(&mut self, confirmations: NonZeroUsize) {
+        self.confirmations = confirmations;
+    }
+
+    /// Add a [`Multiaddr`] to the collection, as a candidate unless it is already confirmed.
     ///
     /// Adding an existing address is interpreted as additional
     /// confirmation and thus increases its score.
     pub fn add(&mut self, a: Multiaddr) {
+        self.add_record(a);
+    }
+
+    /// Add a [`Multiaddr`] reported by the given peer, like [`Addresses::add`].
+    ///
+    /// The address is confirmed once it has been reported by the configured number of
+    /// distinct peers. Returns `true` if the address was confirmed by this report.
+    pub fn report(&mut self, a: Multiaddr, reporter: PeerId) -> bool {
+        let confirmations = self.confirmations.get();
+        let record = self.add_record(a);
+        if record.confirmed {
+            return false
+        }
+        if !record.reporters.contains(&reporter) {
+            record.reporters.push(reporter);
+        }
+        if record.reporters.len() >= confirmations {
+            record.confirmed = true;
+            record.reporters = SmallVec::new();
+            return true
+        }
+        false
+    }
+
+    /// Add a [`Multiaddr`] like [`Addresses::add`] and confirm it.
+    ///
+    /// Returns `true` if the address was not confirmed before.
+    pub fn confirm(&mut self, a: Multiaddr) -> bool {
+        let record = self.add_record(a);
+        let was_confirmed = record.confirmed;
+        record.confirmed = true;
+        record.reporters = SmallVec::new();
+        !was_confirmed
+    }
+
+    fn add_record(&mut self, a: Multiaddr) -> &mut Record {
 
         let oldest = if self.reports.len() == self.limit.get() {
             self.reports.pop_front()
@@ -80,37 +138,66 @@ impl Addresses {
             }
         }
 
-        // Remove addresses that have a score of 0.
-        while self.registry.last().map(|e| e.score == 0).unwrap_or(false) {
-            self.registry.pop();
-        }
+        // Remove candidates that have a score of 0.
+        self.registry.retain(|e| e.score > 0 || e.confirmed);
 
         self.reports.push_back(a.clone());
 
-        for r in &mut self.registry {
-            if r.addr == a {
-                r.score = r.score.saturating_add(1);
-                isort(&mut self.registry);
-                return
-            }
+        if let Some(pos) = self.registry.iter().position(|r| r.addr == a) {
+            self.registry[pos].score = self.registry[pos].score.saturating_add(1);
+            let pos = isort_one(&mut self.registry, pos);
+            return &mut self.registry[pos]
         }
 
-        let r = Record { score: 1, addr: a };
-        self.registry.push(r)
+        self.registry.push(Record::new(a));
+        let pos = self.registry.len() - 1;
+        let pos = isort_one(&mut self.registry, pos);
+        &mut self.registry[pos]
     }
 
-    /// Return an iterator over all [`Multiaddr`] values.
+    /// Remove a [`Multiaddr`] from the collection, regardless of its score.
+    ///
+    /// Returns `true` if the address was present.
+    pub fn remove(&mut self, a: &Multiaddr) -> bool {
+        self.reports.retain(|r| r != a);
+        if let Some(pos) = self.registry.iter().position(|r| r.addr == *a) {
+            self.registry.remove(pos);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Return an iterator over all confirmed [`Multiaddr`] values.
     ///
     /// The iteration is ordered by descending score.
     pub fn iter(&self) -> AddressIter<'_> {
-        AddressIter { items: &self.registry, offset: 0 }
+        AddressIter { items: &self.registry, offset: 0, confirmed: true }
+    }
+
+    /// Return an iterator over all [`Multiaddr`] values that are not confirmed yet.
+    ///
+    /// The iteration is ordered by descending score.
+    pub fn candidates(&self) -> AddressIter<'_> {
+        AddressIter { items: &self.registry, offset: 0, confirmed: false }
     }
 
-    /// Return an iterator over all [`Multiaddr`] values.
+    /// Return an iterator over all confirmed [`Multiaddr`] values.
     ///
     /// The iteration is ordered by descending score.
     pub fn into_iter(self) -> AddressIntoIter {
-        AddressIntoIter { items: self.registry }
+        let mut items = self.registry;
+        items.retain(|r| r.confirmed);
+        AddressIntoIter { items }
+    }
+
+    /// Return an iterator over all [`Multiaddr`] values that are not confirmed yet.
+    ///
+    /// The iteration is ordered by descending score.
+    pub fn into_candidates(self) -> AddressIntoIter {
+        let mut items = self.registry;
+        items.retain(|r| !r.confirmed);
+        AddressIntoIter { items }
     }
 }
 
@@ -118,23 +205,27 @@ impl Addresses {
 #[derive(Clone)]
 pub struct AddressIter<'a> {
     items: &'a [Record],
-    offset: usize
+    offset: usize,
+    // Whether to yield the confirmed addresses or the candidates.
+    confirmed: bool,
 }
 
 impl<'a> Iterator for AddressIter<'a> {
     type Item = &'a Multiaddr;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.offset == self.items.len() {
-            return None
+        while self.offset < self.items.len() {
+            let item = &self.items[self.offset];
+            self.offset += 1;
+            if item.confirmed == self.confirmed {
+                return Some(&item.addr)
+            }
         }
-        let item = &self.items[self.offset];
-        self.offset += 1;
-        Some(&item.addr)
+        None
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let n = self.items.len() - self.offset;
+        let n = self.items[self.offset ..].iter().filter(|r| r.confirmed == self.confirmed).count();
         (n, Some(n))
     }
 }
@@ -166,6 +257,16 @@ impl Iterator for AddressIntoIter {
 
 impl ExactSizeIterator for AddressIntoIter {}
 
+// Moves the element at `pos`, whose score has increased, towards the front
+// to keep the slice sorted. Returns the new position of the element.
+fn isort_one(xs: &mut [Record], mut pos: usize) -> usize {
+    while pos > 0 && xs[pos].score > xs[pos - 1].score {
+        xs.swap(pos, pos - 1);
+        pos -= 1;
+    }
+    pos
+}
+
 // Reverse insertion sort.
 fn isort(xs: &mut [Record]) {
     for i in 1 .. xs.len() {
@@ -180,7 +281,7 @@ fn isort(xs: &mut [Record]) {
 
 #[cfg(test)]
 mod tests {
-    use libp2p_core::multiaddr::{Multiaddr, Protocol};
+    use libp2p_core::{multiaddr::{Multiaddr, Protocol}, PeerId};
     use quickcheck::{Arbitrary, Gen, QuickCheck};
     use rand::Rng;
     use std::num::NonZeroUsize;
@@ -190,7 +291,7 @@ mod tests {
     fn isort_sorts() {
         fn property(xs: Vec<u32>) -> bool {
             let mut xs = xs.into_iter()
-                .map(|s| Record { score: s, addr: Multiaddr::empty() })
+                .map(|s| Record { score: s, ..Record::new(Multiaddr::empty()) })
                 .collect::<Vec<_>>();
 
             isort(&mut xs);
@@ -211,7 +312,7 @@ mod tests {
         // Add an address a single time.
         let single: Multiaddr = "/tcp/2108".parse().unwrap();
         addresses.add(single.clone());
-        assert!(addresses.iter().find(|a| **a == single).is_some());
+        assert!(addresses.candidates().find(|a| **a == single).is_some());
 
         // Then fill `addresses` with random stuff.
         let other: Multiaddr = "/tcp/120".parse().unwrap();
@@ -220,7 +321,56 @@ mod tests {
         }
 
         // Check that `single` disappeared from the list.
-        assert!(addresses.iter().find(|a| **a == single).is_none());
+        assert!(addresses.candidates().find(|a| **a == single).is_none());
+    }
+
+    #[test]
+    fn removed_address_disappears() {
+        let mut addresses = Addresses::default();
+        let a: Multiaddr = "/tcp/2108".parse().unwrap();
+        let b: Multiaddr = "/tcp/120".parse().unwrap();
+        addresses.add(a.clone());
+        addresses.add(a.clone());
+        addresses.add(b.clone());
+
+        assert!(addresses.remove(&a));
+        assert!(!addresses.remove(&a));
+        assert_eq!(addresses.candidates().collect::<Vec<_>>(), vec![&b]);
+        assert!(addresses.reports.iter().all(|r| *r != a));
+    }
+
+    #[test]
+    fn reports_of_distinct_peers_confirm() {
+        let mut addresses = Addresses::default();
+        let a: Multiaddr = "/tcp/2108".parse().unwrap();
+        let peer = PeerId::random();
+
+        assert!(!addresses.report(a.clone(), peer.clone()));
+        assert!(!addresses.report(a.clone(), peer));
+        assert_eq!(addresses.iter().count(), 0);
+        assert_eq!(addresses.candidates().collect::<Vec<_>>(), vec![&a]);
+
+        assert!(addresses.report(a.clone(), PeerId::random()));
+        assert_eq!(addresses.iter().collect::<Vec<_>>(), vec![&a]);
+        assert_eq!(addresses.candidates().count(), 0);
+        assert!(!addresses.report(a, PeerId::random()));
+    }
+
+    #[test]
+    fn confirmed_addresses_do_not_disappear() {
+        let mut addresses = Addresses::new(NonZeroUsize::new(10).unwrap());
+        let confirmed: Multiaddr = "/tcp/2108".parse().unwrap();
+        assert!(addresses.confirm(confirmed.clone()));
+        assert!(!addresses.confirm(confirmed.clone()));
+
+        let other: Multiaddr = "/tcp/120".parse().unwrap();
+        for _ in 0 .. 100 {
+            addresses.add(other.clone());
+        }
+
+        assert_eq!(addresses.iter().collect::<Vec<_>>(), vec![&confirmed]);
+        assert_eq!(addresses.clone().into_iter().collect::<Vec<_>>(), vec![confirmed]);
+        assert_eq!(addresses.into_candidates().collect::<Vec<_>>(), vec![other]);
     }
 
     #[test]
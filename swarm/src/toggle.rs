@@ -18,7 +18,7 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::{NetworkBehaviour, NetworkBehaviourAction, NetworkBehaviourEventProcess, PollParameters};
+use crate::{NetworkBehaviour, NetworkBehaviourAction, NetworkBehaviourEventProcess, NotifyHandler, PollParameters};
 use crate::upgrade::{SendWrapper, InboundUpgradeSend, OutboundUpgradeSend};
 use crate::protocols_handler::{
     KeepAlive,
@@ -37,13 +37,24 @@ use libp2p_core::{
     either::EitherOutput,
     upgrade::{DeniedUpgrade, EitherUpgrade}
 };
-use std::{error, task::Context, task::Poll};
+use std::{collections::{HashSet, VecDeque}, error, task::Context, task::Poll};
 
 /// Implementation of `NetworkBehaviour` that can be either in the disabled or enabled state.
 ///
-/// The state can only be chosen at initialization.
+/// The state can only be chosen at initialization. The inbound protocols of an
+/// enabled behaviour can however be disabled and re-enabled at any time with
+/// [`Toggle::set_listening`].
 pub struct Toggle<TBehaviour> {
     inner: Option<TBehaviour>,
+    /// Whether the inbound protocols of the inner behaviour are accepted.
+    listening: bool,
+    /// The peers the local node is connected to.
+    connected_peers: HashSet<PeerId>,
+    /// The peers whose handlers are yet to be informed of a change of `listening`.
+    pending_notifications: VecDeque<PeerId>,
+    /// Whether the `Swarm` is yet to update the supported protocols
+    /// following a change of `listening`.
+    pending_protocols_update: bool,
 }
 
 impl<TBehaviour> Toggle<TBehaviour> {
@@ -51,11 +62,40 @@ impl<TBehaviour> Toggle<TBehaviour> {
     pub fn is_enabled(&self) -> bool {
         self.inner.is_some()
     }
+
+    /// Returns `true` if the inbound protocols of the behaviour are accepted.
+    pub fn is_listening(&self) -> bool {
+        self.listening
+    }
+
+    /// Enables or disables the inbound protocols of the behaviour, e.g. to
+    /// only accept a protocol once the local node is ready to serve it.
+    ///
+    /// The change takes effect on existing connections as well and updates
+    /// the protocols supported by the local node, which is pushed to connected
+    /// peers if identify is configured to do so. Inbound protocols are accepted
+    /// by default.
+    pub fn set_listening(&mut self, listening: bool) {
+        if self.listening == listening {
+            return
+        }
+        self.listening = listening;
+        if self.inner.is_some() {
+            self.pending_notifications = self.connected_peers.iter().cloned().collect();
+            self.pending_protocols_update = true;
+        }
+    }
 }
 
 impl<TBehaviour> From<Option<TBehaviour>> for Toggle<TBehaviour> {
     fn from(inner: Option<TBehaviour>) -> Self {
-        Toggle { inner }
+        Toggle {
+            inner,
+            listening: true,
+            connected_peers: HashSet::new(),
+            pending_notifications: VecDeque::new(),
+            pending_protocols_update: false,
+        }
     }
 }
 
@@ -68,7 +108,8 @@ where
 
     fn new_handler(&mut self) -> Self::ProtocolsHandler {
         ToggleIntoProtoHandler {
-            inner: self.inner.as_mut().map(|i| i.new_handler())
+            inner: self.inner.as_mut().map(|i| i.new_handler()),
+            listening: self.listening,
         }
     }
 
@@ -77,12 +118,14 @@ where
     }
 
     fn inject_connected(&mut self, peer_id: &PeerId) {
+        self.connected_peers.insert(peer_id.clone());
         if let Some(inner) = self.inner.as_mut() {
             inner.inject_connected(peer_id)
         }
     }
 
     fn inject_disconnected(&mut self, peer_id: &PeerId) {
+        self.connected_peers.remove(peer_id);
         if let Some(inner) = self.inner.as_mut() {
             inner.inject_disconnected(peer_id)
         }
@@ -144,11 +187,48 @@ where
     fn poll(&mut self, cx: &mut Context<'_>, params: &mut impl PollParameters)
         -> Poll<NetworkBehaviourAction<<<Self::ProtocolsHandler as IntoProtocolsHandler>::Handler as ProtocolsHandler>::InEvent, Self::OutEvent>>
     {
-        if let Some(inner) = self.inner.as_mut() {
-            inner.poll(cx, params)
-        } else {
-            Poll::Pending
+        if let Some(peer_id) = self.pending_notifications.pop_front() {
+            return Poll::Ready(NetworkBehaviourAction::NotifyHandler {
+                peer_id,
+                handler: NotifyHandler::All,
+                event: ToggleProtoHandlerIn::SetListening(self.listening),
+            })
         }
+
+        if self.pending_protocols_update {
+            self.pending_protocols_update = false;
+            return Poll::Ready(NetworkBehaviourAction::UpdateSupportedProtocols)
+        }
+
+        let inner = match self.inner.as_mut() {
+            Some(inner) => inner,
+            None => return Poll::Pending,
+        };
+
+        Poll::Ready(match futures::ready!(inner.poll(cx, params)) {
+            NetworkBehaviourAction::GenerateEvent(event) =>
+                NetworkBehaviourAction::GenerateEvent(event),
+            NetworkBehaviourAction::DialAddress { address } =>
+                NetworkBehaviourAction::DialAddress { address },
+            NetworkBehaviourAction::DialPeer { peer_id, condition } =>
+                NetworkBehaviourAction::DialPeer { peer_id, condition },
+            NetworkBehaviourAction::NotifyHandler { peer_id, handler, event } =>
+                NetworkBehaviourAction::NotifyHandler {
+                    peer_id,
+                    handler,
+                    event: ToggleProtoHandlerIn::Inner(event),
+                },
+            NetworkBehaviourAction::ReportObservedAddr { address, peer_id } =>
+                NetworkBehaviourAction::ReportObservedAddr { address, peer_id },
+            NetworkBehaviourAction::AddExternalAddr { address } =>
+                NetworkBehaviourAction::AddExternalAddr { address },
+            NetworkBehaviourAction::ConfirmExternalAddr { address } =>
+                NetworkBehaviourAction::ConfirmExternalAddr { address },
+            NetworkBehaviourAction::RemoveExternalAddr { address } =>
+                NetworkBehaviourAction::RemoveExternalAddr { address },
+            NetworkBehaviourAction::UpdateSupportedProtocols =>
+                NetworkBehaviourAction::UpdateSupportedProtocols,
+        })
     }
 }
 
@@ -166,6 +246,7 @@ where
 /// Implementation of `IntoProtocolsHandler` that can be in the disabled state.
 pub struct ToggleIntoProtoHandler<TInner> {
     inner: Option<TInner>,
+    listening: bool,
 }
 
 impl<TInner> IntoProtocolsHandler for ToggleIntoProtoHandler<TInner>
@@ -176,15 +257,15 @@ where
 
     fn into_handler(self, remote_peer_id: &PeerId, connected_point: &ConnectedPoint) -> Self::Handler {
         ToggleProtoHandler {
-            inner: self.inner.map(|h| h.into_handler(remote_peer_id, connected_point))
+            inner: self.inner.map(|h| h.into_handler(remote_peer_id, connected_point)),
+            listening: self.listening,
         }
     }
 
     fn inbound_protocol(&self) -> <Self::Handler as ProtocolsHandler>::InboundProtocol {
-        if let Some(inner) = self.inner.as_ref() {
-            EitherUpgrade::A(SendWrapper(inner.inbound_protocol()))
-        } else {
-            EitherUpgrade::B(SendWrapper(DeniedUpgrade))
+        match self.inner.as_ref() {
+            Some(inner) if self.listening => EitherUpgrade::A(SendWrapper(inner.inbound_protocol())),
+            _ => EitherUpgrade::B(SendWrapper(DeniedUpgrade)),
         }
     }
 }
@@ -192,13 +273,27 @@ where
 /// Implementation of `ProtocolsHandler` that can be in the disabled state.
 pub struct ToggleProtoHandler<TInner> {
     inner: Option<TInner>,
+    /// Whether the inbound protocols of the inner handler are accepted.
+    listening: bool,
+}
+
+/// Event sent to a [`ToggleProtoHandler`].
+#[derive(Debug, Clone)]
+pub enum ToggleProtoHandlerIn<TInner> {
+    /// An event for the inner handler.
+    Inner(TInner),
+    /// Enables or disables the inbound protocols of the inner handler.
+    ///
+    /// Inbound substreams that are still being negotiated when the inbound
+    /// protocols are disabled are nevertheless passed to the inner handler.
+    SetListening(bool),
 }
 
 impl<TInner> ProtocolsHandler for ToggleProtoHandler<TInner>
 where
     TInner: ProtocolsHandler,
 {
-    type InEvent = TInner::InEvent;
+    type InEvent = ToggleProtoHandlerIn<TInner::InEvent>;
     type OutEvent = TInner::OutEvent;
     type Error = TInner::Error;
     type InboundProtocol = EitherUpgrade<SendWrapper<TInner::InboundProtocol>, SendWrapper<DeniedUpgrade>>;
@@ -206,10 +301,10 @@ where
     type OutboundOpenInfo = TInner::OutboundOpenInfo;
 
     fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol> {
-        if let Some(inner) = self.inner.as_ref() {
-            inner.listen_protocol().map_upgrade(|u| EitherUpgrade::A(SendWrapper(u)))
-        } else {
-            SubstreamProtocol::new(EitherUpgrade::B(SendWrapper(DeniedUpgrade)))
+        match self.inner.as_ref() {
+            Some(inner) if self.listening =>
+                inner.listen_protocol().map_upgrade(|u| EitherUpgrade::A(SendWrapper(u))),
+            _ => SubstreamProtocol::new(EitherUpgrade::B(SendWrapper(DeniedUpgrade))),
         }
     }
 
@@ -236,8 +331,12 @@ where
     }
 
     fn inject_event(&mut self, event: Self::InEvent) {
-        self.inner.as_mut().expect("Can't receive events if disabled; QED")
-            .inject_event(event)
+        match event {
+            ToggleProtoHandlerIn::Inner(event) =>
+                self.inner.as_mut().expect("Can't receive events if disabled; QED")
+                    .inject_event(event),
+            ToggleProtoHandlerIn::SetListening(listening) => self.listening = listening,
+        }
     }
 
     fn inject_dial_upgrade_error(&mut self, info: Self::OutboundOpenInfo, err: ProtocolsHandlerUpgrErr<<Self::OutboundProtocol as OutboundUpgradeSend>::Error>) {
@@ -263,3 +362,77 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DummyBehaviour;
+    use crate::protocols_handler::DummyProtocolsHandler;
+    use futures::{executor::block_on, future};
+
+    struct Params(PeerId);
+
+    impl PollParameters for Params {
+        type SupportedProtocolsIter = std::vec::IntoIter<Vec<u8>>;
+        type ListenedAddressesIter = std::vec::IntoIter<Multiaddr>;
+        type ExternalAddressesIter = std::vec::IntoIter<Multiaddr>;
+
+        fn supported_protocols(&self) -> Self::SupportedProtocolsIter {
+            Vec::new().into_iter()
+        }
+
+        fn listened_addresses(&self) -> Self::ListenedAddressesIter {
+            Vec::new().into_iter()
+        }
+
+        fn external_addresses(&self) -> Self::ExternalAddressesIter {
+            Vec::new().into_iter()
+        }
+
+        fn external_address_candidates(&self) -> Self::ExternalAddressesIter {
+            Vec::new().into_iter()
+        }
+
+        fn local_peer_id(&self) -> &PeerId {
+            &self.0
+        }
+    }
+
+    #[test]
+    fn set_listening() {
+        let mut toggle = Toggle::from(Some(DummyBehaviour::default()));
+        let peer = PeerId::random();
+        toggle.inject_connected(&peer);
+        assert!(toggle.is_listening());
+
+        toggle.set_listening(false);
+        assert!(!toggle.is_listening());
+        assert!(matches!(toggle.new_handler().inbound_protocol(), EitherUpgrade::B(_)));
+
+        let mut params = Params(PeerId::random());
+        let mut poll = || block_on(future::poll_fn(|cx| Poll::Ready(toggle.poll(cx, &mut params))));
+        match poll() {
+            Poll::Ready(NetworkBehaviourAction::NotifyHandler {
+                peer_id,
+                handler: NotifyHandler::All,
+                event: ToggleProtoHandlerIn::SetListening(false),
+            }) => assert_eq!(peer_id, peer),
+            _ => panic!("Expected the handlers to be notified."),
+        }
+        assert!(matches!(poll(), Poll::Ready(NetworkBehaviourAction::UpdateSupportedProtocols)));
+        assert!(poll().is_pending());
+    }
+
+    #[test]
+    fn handler_set_listening() {
+        let endpoint = ConnectedPoint::Dialer { address: Multiaddr::empty() };
+        let mut handler = ToggleIntoProtoHandler {
+            inner: Some(DummyProtocolsHandler::default()),
+            listening: true,
+        }.into_handler(&PeerId::random(), &endpoint);
+        assert!(matches!(handler.listen_protocol().upgrade(), EitherUpgrade::A(_)));
+
+        handler.inject_event(ToggleProtoHandlerIn::SetListening(false));
+        assert!(matches!(handler.listen_protocol().upgrade(), EitherUpgrade::B(_)));
+    }
+}
@@ -20,8 +20,8 @@
 
 //! # libp2p-dns
 //!
-//! This crate provides the type `DnsConfig` that allows one to resolve the `/dns4/` and `/dns6/`
-//! components of multiaddresses.
+//! This crate provides the type `DnsConfig` that allows one to resolve the `/dns/`, `/dns4/`,
+//! `/dns6/` and `/dnsaddr/` components of multiaddresses.
 //!
 //! ## Usage
 //!
@@ -30,31 +30,56 @@
 //!
 //! Whenever we want to dial an address through the `DnsConfig` and that address contains a
 //! `/dns/`, `/dns4/`, or `/dns6/` component, a DNS resolve will be performed and the component
-//! will be replaced with `/ip4/` and/or `/ip6/` components.
+//! will be replaced with `/ip4/` and/or `/ip6/` components. If the name resolves to multiple IP
+//! addresses, they are dialed one after the other until one of the attempts succeeds.
+//!
+//! With the `dnsaddr` feature enabled, a `/dnsaddr/` component is resolved by looking up the
+//! `dnsaddr` TXT records of `_dnsaddr.<name>`, each of which contains a full multiaddress. Only
+//! the records ending with the components following `/dnsaddr/<name>` (typically
+//! `/p2p/<peer-id>`) are dialed. The resolver used for these lookups runs on its own tokio 0.2
+//! runtime, which is why the feature is disabled by default.
 //!
 
-use futures::{prelude::*, channel::oneshot, future::BoxFuture};
+use futures::{prelude::*, channel::oneshot, executor::ThreadPool, future::BoxFuture};
+#[cfg(feature = "dnsaddr")]
+use trust_dns_resolver::Resolver;
 use libp2p_core::{
     Transport,
     multiaddr::{Protocol, Multiaddr},
     transport::{TransportError, ListenerEvent}
 };
 use log::{error, debug, trace};
-use std::{error, fmt, io, net::ToSocketAddrs};
+use std::{collections::VecDeque, error, fmt, io, net::ToSocketAddrs};
+#[cfg(feature = "dnsaddr")]
+use std::sync::Arc;
+
+/// Maximum number of nested `/dnsaddr` lookups performed when resolving an address.
+#[cfg(feature = "dnsaddr")]
+const MAX_DNSADDR_DEPTH: usize = 4;
 
 /// Represents the configuration for a DNS transport capability of libp2p.
 ///
 /// This struct implements the `Transport` trait and holds an underlying transport. Any call to
-/// `dial` with a multiaddr that contains `/dns/`, `/dns4/`, or `/dns6/` will be first be resolved,
-/// then passed to the underlying transport.
+/// `dial` with a multiaddr that contains `/dns/`, `/dns4/`, `/dns6/` or, with the `dnsaddr`
+/// feature, `/dnsaddr/` will be first be resolved, then passed to the underlying transport.
 ///
 /// Listening is unaffected.
 #[derive(Clone)]
 pub struct DnsConfig<T> {
     /// Underlying transport to use once the DNS addresses have been resolved.
     inner: T,
+    /// Resolver of the DNS components of the dialed addresses.
+    resolver: DnsResolver,
+}
+
+/// Performs the DNS queries of a `DnsConfig`.
+#[derive(Clone)]
+struct DnsResolver {
     /// Pool of threads to use when resolving DNS addresses.
-    thread_pool: futures::executor::ThreadPool,
+    thread_pool: ThreadPool,
+    /// Resolver for the TXT records of `/dnsaddr` components.
+    #[cfg(feature = "dnsaddr")]
+    txt_resolver: Arc<Resolver>,
 }
 
 impl<T> DnsConfig<T> {
@@ -64,8 +89,11 @@ impl<T> DnsConfig<T> {
     }
 
     /// Same as `new`, but allows specifying a number of threads for the resolving.
+    ///
+    /// With the `dnsaddr` feature, this fails if the DNS configuration of the system can't be
+    /// read.
     pub fn with_resolve_threads(inner: T, num_threads: usize) -> Result<DnsConfig<T>, io::Error> {
-        let thread_pool = futures::executor::ThreadPool::builder()
+        let thread_pool = ThreadPool::builder()
             .pool_size(num_threads)
             .name_prefix("libp2p-dns-")
             .create()?;
@@ -74,7 +102,11 @@ impl<T> DnsConfig<T> {
 
         Ok(DnsConfig {
             inner,
-            thread_pool,
+            resolver: DnsResolver {
+                thread_pool,
+                #[cfg(feature = "dnsaddr")]
+                txt_resolver: Arc::new(Resolver::from_system_conf()?),
+            },
         })
     }
 }
@@ -90,7 +122,7 @@ where
 
 impl<T> Transport for DnsConfig<T>
 where
-    T: Transport + Send + 'static,
+    T: Transport + Clone + Send + 'static,
     T::Error: Send,
     T::Dial: Send
 {
@@ -123,12 +155,7 @@ where
     fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
         // As an optimization, we immediately pass through if no component of the address contain
         // a DNS protocol.
-        let contains_dns = addr.iter().any(|cmp| match cmp {
-            Protocol::Dns(_) => true,
-            Protocol::Dns4(_) => true,
-            Protocol::Dns6(_) => true,
-            _ => false,
-        });
+        let contains_dns = addr.iter().any(|cmp| is_dns(&cmp));
 
         if !contains_dns {
             trace!("Pass-through address without DNS: {}", addr);
@@ -138,70 +165,169 @@ where
         }
 
         trace!("Dialing address with DNS: {}", addr);
-        let resolve_futs = addr.iter()
-            .map(|cmp| match cmp {
-                Protocol::Dns(ref name) | Protocol::Dns4(ref name) | Protocol::Dns6(ref name) => {
-                    let name = name.to_string();
-                    let to_resolve = format!("{}:0", name);
-                    let (tx, rx) = oneshot::channel();
-                    self.thread_pool.spawn_ok(async {
-                        let to_resolve = to_resolve;
-                        let _ = tx.send(match to_resolve[..].to_socket_addrs() {
-                            Ok(list) => Ok(list.map(|s| s.ip()).collect::<Vec<_>>()),
-                            Err(e) => Err(e),
-                        });
-                    });
-
-                    let (dns4, dns6) = match cmp {
-                        Protocol::Dns(_) => (true, true),
-                        Protocol::Dns4(_) => (true, false),
-                        Protocol::Dns6(_) => (false, true),
-                        _ => unreachable!(),
-                    };
-
-                    async move {
-                        let list = rx.await
-                            .map_err(|_| {
-                                error!("DNS resolver crashed");
-                                DnsErr::ResolveFail(name.clone())
-                            })?
-                            .map_err(|err| DnsErr::ResolveError {
-                                domain_name: name.clone(),
-                                error: err,
-                            })?;
-
-                        list.into_iter()
-                            .filter_map(|addr| {
-                                if (dns4 && addr.is_ipv4()) || (dns6 && addr.is_ipv6()) {
-                                    Some(Protocol::from(addr))
-                                } else {
-                                    None
-                                }
-                            })
-                            .next()
-                            .ok_or_else(|| DnsErr::ResolveFail(name))
-                    }.left_future()
-                },
-                cmp => future::ready(Ok(cmp.acquire())).right_future()
-            })
-            .collect::<stream::FuturesOrdered<_>>();
-
-        let future = resolve_futs.collect::<Vec<_>>()
-            .then(move |outcome| async move {
-                let outcome = outcome.into_iter().collect::<Result<Vec<_>, _>>()?;
-                let outcome = outcome.into_iter().collect::<Multiaddr>();
-                debug!("DNS resolution outcome: {} => {}", addr, outcome);
-
-                match self.inner.dial(outcome) {
-                    Ok(d) => d.await.map_err(DnsErr::Underlying),
-                    Err(TransportError::MultiaddrNotSupported(_addr)) =>
-                        Err(DnsErr::MultiaddrNotSupported),
-                    Err(TransportError::Other(err)) => Err(DnsErr::Underlying(err))
+        let future = async move {
+            let outcome = resolve(&self.resolver, addr.clone()).await?;
+            debug!("DNS resolution outcome: {} => {:?}", addr, outcome);
+
+            // Try the resolved addresses one after the other, and report the error of the last
+            // attempt if none of them succeeds.
+            let mut last_err = None;
+            for resolved in outcome {
+                match self.inner.clone().dial(resolved.clone()) {
+                    Ok(d) => match d.await {
+                        Ok(output) => return Ok(output),
+                        Err(err) => {
+                            debug!("Dialing {} failed: {:?}", resolved, err);
+                            last_err = Some(DnsErr::Underlying(err));
+                        }
+                    },
+                    Err(TransportError::MultiaddrNotSupported(_addr)) => {
+                        if last_err.is_none() {
+                            last_err = Some(DnsErr::MultiaddrNotSupported);
+                        }
+                    }
+                    Err(TransportError::Other(err)) => last_err = Some(DnsErr::Underlying(err)),
                 }
-            });
+            }
+
+            Err(last_err.unwrap_or_else(|| DnsErr::ResolveFail(addr.to_string())))
+        };
 
         Ok(future.boxed().right_future())
     }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.inner.address_translation(listen, observed)
+    }
+}
+
+/// Returns true if the given component has to be resolved through DNS.
+fn is_dns(cmp: &Protocol<'_>) -> bool {
+    matches!(cmp, Protocol::Dns(_) | Protocol::Dns4(_) | Protocol::Dns6(_))
+        || (cfg!(feature = "dnsaddr") && matches!(cmp, Protocol::Dnsaddr(_)))
+}
+
+/// Resolves all the DNS components of `addr`, returning every address that the DNS records
+/// point to, in the order in which they were returned by the resolver.
+async fn resolve<TErr>(resolver: &DnsResolver, addr: Multiaddr)
+    -> Result<Vec<Multiaddr>, DnsErr<TErr>>
+{
+    let mut resolved = Vec::new();
+    let mut unresolved = VecDeque::new();
+    unresolved.push_back((addr, 0));
+
+    while let Some((addr, depth)) = unresolved.pop_front() {
+        let (index, cmp) = match addr.iter().enumerate().find(|(_, cmp)| is_dns(cmp)) {
+            Some((index, cmp)) => (index, cmp.acquire()),
+            None => {
+                resolved.push(addr);
+                continue
+            }
+        };
+
+        match cmp {
+            #[cfg(feature = "dnsaddr")]
+            Protocol::Dnsaddr(name) => {
+                if depth >= MAX_DNSADDR_DEPTH {
+                    debug!("Too many nested /dnsaddr lookups, ignoring {}", addr);
+                    continue
+                }
+                let name = name.into_owned();
+                let records = spawn_blocking(&resolver.thread_pool, &name, {
+                    let txt_resolver = resolver.txt_resolver.clone();
+                    let name = name.clone();
+                    move || lookup_dnsaddr(&txt_resolver, &name)
+                }).await?;
+                // Only keep the records that end with the components following the `/dnsaddr`,
+                // typically the `/p2p` component identifying the peer to dial.
+                let suffix = addr.iter().skip(index + 1).collect::<Vec<_>>();
+                for record in records {
+                    if ends_with(&record, &suffix) {
+                        unresolved.push_back((record, depth + 1));
+                    }
+                }
+            }
+            Protocol::Dns(ref name) | Protocol::Dns4(ref name) | Protocol::Dns6(ref name) => {
+                let (dns4, dns6) = match cmp {
+                    Protocol::Dns(_) => (true, true),
+                    Protocol::Dns4(_) => (true, false),
+                    _ => (false, true),
+                };
+                let name = name.to_string();
+                let ips = spawn_blocking(&resolver.thread_pool, &name, {
+                    let to_resolve = format!("{}:0", name);
+                    move || to_resolve[..].to_socket_addrs()
+                        .map(|list| list.map(|s| s.ip()).collect::<Vec<_>>())
+                }).await?;
+                for ip in ips {
+                    if (dns4 && ip.is_ipv4()) || (dns6 && ip.is_ipv6()) {
+                        let replaced = addr.replace(index, |_| Some(Protocol::from(ip)))
+                            .expect("`index` is the position of an existing component; qed");
+                        unresolved.push_back((replaced, depth));
+                    }
+                }
+            }
+            _ => unreachable!("`is_dns` only matches DNS components; qed"),
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Runs a blocking DNS query for `name` on the thread pool.
+async fn spawn_blocking<T, F, TErr>(thread_pool: &ThreadPool, name: &str, query: F)
+    -> Result<T, DnsErr<TErr>>
+where
+    F: FnOnce() -> Result<T, io::Error> + Send + 'static,
+    T: Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    thread_pool.spawn_ok(async move {
+        let _ = tx.send(query());
+    });
+
+    rx.await
+        .map_err(|_| {
+            error!("DNS resolver crashed");
+            DnsErr::ResolveFail(name.to_owned())
+        })?
+        .map_err(|err| DnsErr::ResolveError {
+            domain_name: name.to_owned(),
+            error: err,
+        })
+}
+
+/// Looks up the `dnsaddr` TXT records of `_dnsaddr.<name>` and parses the multiaddresses they
+/// contain. Records that aren't valid multiaddresses are ignored.
+#[cfg(feature = "dnsaddr")]
+fn lookup_dnsaddr(resolver: &Resolver, name: &str) -> Result<Vec<Multiaddr>, io::Error> {
+    let lookup = resolver.txt_lookup(&format!("_dnsaddr.{}", name))
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+
+    Ok(lookup.iter()
+        .flat_map(|txt| txt.txt_data().iter())
+        .filter_map(|data| parse_dnsaddr_txt(data))
+        .collect())
+}
+
+/// Parses the content of a `dnsaddr` TXT record, which is of the form `dnsaddr=<multiaddr>`.
+#[cfg(feature = "dnsaddr")]
+fn parse_dnsaddr_txt(data: &[u8]) -> Option<Multiaddr> {
+    let data = std::str::from_utf8(data).ok()?;
+    match data.strip_prefix("dnsaddr=")?.parse() {
+        Ok(addr) => Some(addr),
+        Err(err) => {
+            debug!("Invalid multiaddr in dnsaddr record {:?}: {:?}", data, err);
+            None
+        }
+    }
+}
+
+/// Returns true if the components of `addr` end with `suffix`.
+#[cfg(feature = "dnsaddr")]
+fn ends_with(addr: &Multiaddr, suffix: &[Protocol<'_>]) -> bool {
+    let components = addr.iter().collect::<Vec<_>>();
+    components.len() >= suffix.len() && components[components.len() - suffix.len()..] == *suffix
 }
 
 /// Error that can be generated by the DNS layer.
@@ -315,4 +441,28 @@ mod tests {
                 .unwrap();
         });
     }
+
+    #[test]
+    #[cfg(feature = "dnsaddr")]
+    fn dnsaddr_records() {
+        use super::{ends_with, parse_dnsaddr_txt};
+
+        let record: Multiaddr = "/ip4/147.75.83.83/tcp/4001/p2p/QmNnooDu7bfjPFoTZYxMNLWUQJyrVwtbZg5gBMjTezGAJN"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            parse_dnsaddr_txt(format!("dnsaddr={}", record).as_bytes()),
+            Some(record.clone())
+        );
+        assert_eq!(parse_dnsaddr_txt(b"dnsaddr=not-a-multiaddr"), None);
+        assert_eq!(parse_dnsaddr_txt(format!("{}", record).as_bytes()), None);
+
+        let peer: Multiaddr = "/p2p/QmNnooDu7bfjPFoTZYxMNLWUQJyrVwtbZg5gBMjTezGAJN".parse().unwrap();
+        let other: Multiaddr = "/p2p/QmQCU2EcMqAqQPR2i9bChDtGNJchTbq5TbXJJ16u19uLTa".parse().unwrap();
+        assert!(ends_with(&record, &peer.iter().collect::<Vec<_>>()));
+        assert!(!ends_with(&record, &other.iter().collect::<Vec<_>>()));
+        assert!(ends_with(&record, &[]));
+        assert!(!ends_with(&peer, &record.iter().collect::<Vec<_>>()));
+    }
 }
@@ -0,0 +1,53 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_tls::certificate;
+use std::io;
+
+/// Error that can happen on a QUIC connection or while setting one up.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// An I/O error, e.g. while binding a UDP socket or on a substream.
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// The TLS certificate could not be generated.
+    #[error("Failed to generate the TLS certificate: {0}")]
+    Certificate(#[from] certificate::GenError),
+    /// A connection could not be initiated.
+    #[error("Failed to initiate a connection: {0}")]
+    Connect(#[from] quinn::ConnectError),
+    /// The QUIC connection failed or has been closed.
+    #[error("Connection error: {0}")]
+    Connection(#[from] quinn::ConnectionError),
+    /// The remote did not present a libp2p certificate from which
+    /// its `PeerId` could be derived.
+    #[error("The remote did not authenticate with a libp2p certificate")]
+    UnknownRemotePeer,
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        match err {
+            Error::Io(e) => e,
+            Error::Connection(e) => e.into(),
+            e => io::Error::new(io::ErrorKind::Other, e)
+        }
+    }
+}
@@ -0,0 +1,321 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Implementation of the libp2p `Transport` trait for QUIC.
+//!
+//! QUIC provides encryption and stream multiplexing natively. Contrary to other
+//! transports, the output of [`QuicConfig`] is therefore not upgraded any further:
+//! connections are authenticated during the TLS 1.3 handshake of QUIC, following
+//! the [libp2p TLS specification](https://github.com/libp2p/specs/blob/master/tls/tls.md),
+//! and the transport directly yields the [`PeerId`] of the remote together
+//! with a [`QuicMuxer`] exposing QUIC streams as substreams.
+//!
+//! # Usage
+//!
+//! Addresses have the form `/ip4/127.0.0.1/udp/4001/quic`. The connections are driven by
+//! the `async-std` runtime.
+//!
+//! ```
+//! use libp2p_core::{identity, Transport};
+//! use libp2p_quic::QuicConfig;
+//!
+//! let keypair = identity::Keypair::generate_ed25519();
+//! let transport = QuicConfig::new(&keypair).unwrap();
+//! # let _ = transport.listen_on("/ip4/127.0.0.1/udp/0/quic".parse().unwrap());
+//! ```
+
+mod error;
+mod muxer;
+
+pub use error::Error;
+pub use muxer::{OutboundSubstream, QuicMuxer, Substream};
+
+use futures::{future::BoxFuture, prelude::*, stream::BoxStream};
+use get_if_addrs::get_if_addrs;
+use libp2p_core::{
+    PeerId,
+    Transport,
+    address_translation,
+    identity,
+    multiaddr::{Protocol, Multiaddr},
+    transport::{ListenerEvent, TransportError}
+};
+use log::debug;
+use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+use rustls::pki_types::CertificateDer;
+use std::{
+    convert::TryFrom,
+    iter::{self, FromIterator},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration
+};
+
+/// The configuration of a QUIC transport.
+#[derive(Debug, Clone)]
+pub struct QuicConfig {
+    /// TLS configuration used when dialing.
+    client_tls_config: Arc<rustls::ClientConfig>,
+    /// TLS configuration used when listening.
+    server_tls_config: Arc<rustls::ServerConfig>,
+    /// Time after which an idle connection is closed.
+    max_idle_timeout: Duration,
+    /// Interval at which keep-alive packets are sent, if any.
+    keep_alive_interval: Option<Duration>,
+    /// Maximum number of substreams the remote may open concurrently.
+    max_concurrent_streams: u32,
+}
+
+impl QuicConfig {
+    /// Creates a new configuration authenticating the local node with the given keypair.
+    pub fn new(keypair: &identity::Keypair) -> Result<Self, Error> {
+        Ok(QuicConfig {
+            client_tls_config: Arc::new(libp2p_tls::make_client_config(keypair)?),
+            server_tls_config: Arc::new(libp2p_tls::make_server_config(keypair)?),
+            max_idle_timeout: Duration::from_secs(30),
+            keep_alive_interval: Some(Duration::from_secs(10)),
+            max_concurrent_streams: 256,
+        })
+    }
+
+    /// Sets the time after which an idle connection is closed.
+    pub fn max_idle_timeout(mut self, value: Duration) -> Self {
+        self.max_idle_timeout = value;
+        self
+    }
+
+    /// Sets the interval at which keep-alive packets are sent, or disables them.
+    pub fn keep_alive_interval(mut self, value: Option<Duration>) -> Self {
+        self.keep_alive_interval = value;
+        self
+    }
+
+    /// Sets the maximum number of substreams the remote may have open concurrently.
+    pub fn max_concurrent_streams(mut self, value: u32) -> Self {
+        self.max_concurrent_streams = value;
+        self
+    }
+
+    fn transport_config(&self) -> Arc<quinn::TransportConfig> {
+        let mut config = quinn::TransportConfig::default();
+        // libp2p only uses bidirectional streams.
+        config.max_concurrent_uni_streams(From::from(0u32));
+        config.max_concurrent_bidi_streams(From::from(self.max_concurrent_streams));
+        config.max_idle_timeout(quinn::IdleTimeout::try_from(self.max_idle_timeout).ok());
+        config.keep_alive_interval(self.keep_alive_interval);
+        Arc::new(config)
+    }
+
+    fn client_config(&self) -> quinn::ClientConfig {
+        let crypto = QuicClientConfig::try_from(self.client_tls_config.clone())
+            .expect("TLS 1.3 with an initial cipher suite is configured; qed");
+        let mut config = quinn::ClientConfig::new(Arc::new(crypto));
+        config.transport_config(self.transport_config());
+        config
+    }
+
+    fn server_config(&self) -> quinn::ServerConfig {
+        let crypto = QuicServerConfig::try_from(self.server_tls_config.clone())
+            .expect("TLS 1.3 with an initial cipher suite is configured; qed");
+        let mut config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+        config.transport_config(self.transport_config());
+        config
+    }
+}
+
+impl Transport for QuicConfig {
+    type Output = (PeerId, QuicMuxer);
+    type Error = Error;
+    type Listener = BoxStream<'static, Result<ListenerEvent<Self::ListenerUpgrade, Self::Error>, Self::Error>>;
+    type ListenerUpgrade = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let socket_addr = if let Some(socket_addr) = multiaddr_to_socketaddr(&addr) {
+            socket_addr
+        } else {
+            return Err(TransportError::MultiaddrNotSupported(addr))
+        };
+
+        let endpoint = quinn::Endpoint::server(self.server_config(), socket_addr)
+            .map_err(|e| TransportError::Other(Error::Io(e)))?;
+        let local_addr = endpoint.local_addr().map_err(|e| TransportError::Other(Error::Io(e)))?;
+
+        // If the IP address is a wildcard, report every matching interface address.
+        let addresses = if local_addr.ip().is_unspecified() {
+            let addrs = host_addresses(local_addr)
+                .map_err(|e| TransportError::Other(Error::Io(e)))?;
+            debug!("Listening on {:?}", addrs);
+            addrs
+        } else {
+            let ma = socketaddr_to_multiaddr(&local_addr);
+            debug!("Listening on {:?}", ma);
+            vec![ma]
+        };
+
+        let new_addresses = stream::iter(addresses)
+            .map(|a| Ok(ListenerEvent::NewAddress(a)));
+
+        let upgrades = stream::unfold(endpoint, move |endpoint| async move {
+            let incoming = endpoint.accept().await?;
+            let remote_addr = socketaddr_to_multiaddr(&incoming.remote_address());
+            let local_addr = match incoming.local_ip() {
+                Some(ip) => socketaddr_to_multiaddr(&SocketAddr::new(ip, local_addr.port())),
+                None => socketaddr_to_multiaddr(&local_addr)
+            };
+            debug!("Incoming connection from {} at {}", remote_addr, local_addr);
+            let upgrade = async move {
+                let connection = incoming.accept()?.await?;
+                upgrade(connection)
+            };
+            let event = ListenerEvent::Upgrade {
+                upgrade: upgrade.boxed(),
+                local_addr,
+                remote_addr
+            };
+            Some((Ok(event), endpoint))
+        });
+
+        Ok(new_addresses.chain(upgrades).boxed())
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let socket_addr = if let Some(socket_addr) = multiaddr_to_socketaddr(&addr) {
+            if socket_addr.port() == 0 || socket_addr.ip().is_unspecified() {
+                debug!("Instantly refusing dialing {}, as it is invalid", addr);
+                return Err(TransportError::Other(Error::Io(std::io::ErrorKind::ConnectionRefused.into())))
+            }
+            socket_addr
+        } else {
+            return Err(TransportError::MultiaddrNotSupported(addr))
+        };
+
+        let bind_addr = match socket_addr {
+            SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+            SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+        };
+
+        debug!("Dialing {}", addr);
+        let client_config = self.client_config();
+
+        Ok(async move {
+            let endpoint = quinn::Endpoint::client(bind_addr)?;
+            // The server name is irrelevant, as the remote is authenticated
+            // by its libp2p certificate.
+            let connection = endpoint.connect_with(client_config, socket_addr, "l")?.await?;
+            upgrade(connection)
+        }.boxed())
+    }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        // Outbound connections are dialed from an ephemeral port.
+        multiaddr_to_socketaddr(listen)?;
+        multiaddr_to_socketaddr(observed)?;
+        address_translation(listen, observed)
+    }
+}
+
+/// Turns an established QUIC connection into the output of the transport.
+fn upgrade(connection: quinn::Connection) -> Result<(PeerId, QuicMuxer), Error> {
+    let peer_id = connection.peer_identity()
+        .and_then(|identity| identity.downcast::<Vec<CertificateDer<'static>>>().ok())
+        .and_then(|certificates| libp2p_tls::peer_id_from_certificates(&certificates))
+        .ok_or(Error::UnknownRemotePeer)?;
+    Ok((peer_id, QuicMuxer::new(connection)))
+}
+
+/// Tries to turn a QUIC multiaddress into a UDP [`SocketAddr`]. Returns `None` if the format
+/// of the multiaddr is wrong.
+fn multiaddr_to_socketaddr(addr: &Multiaddr) -> Option<SocketAddr> {
+    let mut iter = addr.iter();
+    let proto1 = iter.next()?;
+    let proto2 = iter.next()?;
+    let proto3 = iter.next()?;
+
+    if iter.next().is_some() {
+        return None
+    }
+
+    match (proto1, proto2, proto3) {
+        (Protocol::Ip4(ip), Protocol::Udp(port), Protocol::Quic) => Some(SocketAddr::new(ip.into(), port)),
+        (Protocol::Ip6(ip), Protocol::Udp(port), Protocol::Quic) => Some(SocketAddr::new(ip.into(), port)),
+        _ => None,
+    }
+}
+
+/// Turns an IP address and port into the corresponding QUIC multiaddr.
+fn socketaddr_to_multiaddr(socket_addr: &SocketAddr) -> Multiaddr {
+    let proto = match socket_addr.ip() {
+        IpAddr::V4(ip) => Protocol::Ip4(ip),
+        IpAddr::V6(ip) => Protocol::Ip6(ip)
+    };
+    let it = iter::once(proto)
+        .chain(iter::once(Protocol::Udp(socket_addr.port())))
+        .chain(iter::once(Protocol::Quic));
+    Multiaddr::from_iter(it)
+}
+
+/// Collects all local host addresses of the same IP version as `local_addr`,
+/// using its port as listen port.
+fn host_addresses(local_addr: SocketAddr) -> std::io::Result<Vec<Multiaddr>> {
+    Ok(get_if_addrs()?
+        .into_iter()
+        .map(|iface| iface.ip())
+        .filter(|ip| ip.is_ipv4() == local_addr.is_ipv4())
+        .map(|ip| socketaddr_to_multiaddr(&SocketAddr::new(ip, local_addr.port())))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multiaddr_to_udp_conversion() {
+        assert!(
+            multiaddr_to_socketaddr(&"/ip4/127.0.0.1/udp/1234".parse::<Multiaddr>().unwrap())
+                .is_none()
+        );
+
+        assert_eq!(
+            multiaddr_to_socketaddr(&"/ip4/127.0.0.1/udp/12345/quic".parse::<Multiaddr>().unwrap()),
+            Some(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 12345))
+        );
+        assert_eq!(
+            multiaddr_to_socketaddr(&"/ip6/::1/udp/12345/quic".parse::<Multiaddr>().unwrap()),
+            Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 12345))
+        );
+        assert!(
+            multiaddr_to_socketaddr(&"/ip4/127.0.0.1/tcp/12345/quic".parse::<Multiaddr>().unwrap())
+                .is_none()
+        );
+        assert!(
+            multiaddr_to_socketaddr(&"/ip4/127.0.0.1/udp/12345/quic/ws".parse::<Multiaddr>().unwrap())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn socketaddr_to_multiaddr_roundtrip() {
+        let addr: Multiaddr = "/ip4/127.0.0.1/udp/4001/quic".parse().unwrap();
+        let socket_addr = multiaddr_to_socketaddr(&addr).unwrap();
+        assert_eq!(socketaddr_to_multiaddr(&socket_addr), addr);
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use crate::error::Error;
+use futures::{future::BoxFuture, prelude::*, ready};
+use libp2p_core::muxing::{StreamMuxer, StreamMuxerEvent};
+use parking_lot::Mutex;
+use std::{fmt, pin::Pin, task::{Context, Poll}};
+
+/// Future yielding the send and receive halves of a bidirectional QUIC stream.
+type StreamFuture = BoxFuture<'static, Result<(quinn::SendStream, quinn::RecvStream), quinn::ConnectionError>>;
+
+/// State for a single opened QUIC connection.
+///
+/// Every bidirectional QUIC stream is exposed as a substream. QUIC streams are
+/// natively multiplexed and encrypted, hence no further upgrades are applied.
+///
+/// This implementation doesn't report connection migrations, i.e. no
+/// [`StreamMuxerEvent::AddressChange`] event is ever emitted.
+pub struct QuicMuxer {
+    connection: quinn::Connection,
+    /// Future accepting the next inbound substream, if any is pending.
+    incoming: Mutex<Option<StreamFuture>>,
+}
+
+impl QuicMuxer {
+    pub(crate) fn new(connection: quinn::Connection) -> Self {
+        QuicMuxer {
+            connection,
+            incoming: Mutex::new(None),
+        }
+    }
+}
+
+impl fmt::Debug for QuicMuxer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QuicMuxer")
+            .field("remote_address", &self.connection.remote_address())
+            .finish()
+    }
+}
+
+/// A QUIC substream, i.e. a bidirectional QUIC stream.
+#[derive(Debug)]
+pub struct Substream {
+    send: quinn::SendStream,
+    recv: quinn::RecvStream,
+}
+
+/// A substream being opened, see [`StreamMuxer::open_outbound`].
+pub struct OutboundSubstream(StreamFuture);
+
+impl fmt::Debug for OutboundSubstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OutboundSubstream")
+    }
+}
+
+impl StreamMuxer for QuicMuxer {
+    type Substream = Substream;
+    type OutboundSubstream = OutboundSubstream;
+    type Error = Error;
+
+    fn poll_event(&self, cx: &mut Context<'_>) -> Poll<Result<StreamMuxerEvent<Self::Substream>, Self::Error>> {
+        let mut incoming = self.incoming.lock();
+        let connection = self.connection.clone();
+        let future = incoming.get_or_insert_with(|| {
+            async move { connection.accept_bi().await }.boxed()
+        });
+        let result = ready!(future.poll_unpin(cx));
+        *incoming = None;
+        let (send, recv) = result?;
+        Poll::Ready(Ok(StreamMuxerEvent::InboundSubstream(Substream { send, recv })))
+    }
+
+    fn open_outbound(&self) -> Self::OutboundSubstream {
+        let connection = self.connection.clone();
+        OutboundSubstream(async move { connection.open_bi().await }.boxed())
+    }
+
+    fn poll_outbound(&self, cx: &mut Context<'_>, s: &mut Self::OutboundSubstream)
+        -> Poll<Result<Self::Substream, Self::Error>>
+    {
+        let (send, recv) = ready!(s.0.poll_unpin(cx))?;
+        Poll::Ready(Ok(Substream { send, recv }))
+    }
+
+    fn destroy_outbound(&self, _: Self::OutboundSubstream) {
+    }
+
+    fn read_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream, buf: &mut [u8])
+        -> Poll<Result<usize, Self::Error>>
+    {
+        AsyncRead::poll_read(Pin::new(&mut s.recv), cx, buf).map_err(Error::Io)
+    }
+
+    fn write_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream, buf: &[u8])
+        -> Poll<Result<usize, Self::Error>>
+    {
+        AsyncWrite::poll_write(Pin::new(&mut s.send), cx, buf).map_err(Error::Io)
+    }
+
+    fn flush_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream)
+        -> Poll<Result<(), Self::Error>>
+    {
+        AsyncWrite::poll_flush(Pin::new(&mut s.send), cx).map_err(Error::Io)
+    }
+
+    fn shutdown_substream(&self, cx: &mut Context<'_>, s: &mut Self::Substream)
+        -> Poll<Result<(), Self::Error>>
+    {
+        AsyncWrite::poll_close(Pin::new(&mut s.send), cx).map_err(Error::Io)
+    }
+
+    fn destroy_substream(&self, _: Self::Substream) {
+    }
+
+    fn close(&self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.connection.close(From::from(0u32), &[]);
+        Poll::Ready(Ok(()))
+    }
+
+    fn flush_all(&self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use libp2p_core::{identity, muxing, Transport};
+use libp2p_quic::QuicConfig;
+use futures::{channel::oneshot, prelude::*};
+use std::sync::Arc;
+
+#[test]
+fn dialer_to_listener_outbound() {
+    // Simulate a dialer writing to a substream opened by the listener.
+
+    let listener_keys = identity::Keypair::generate_ed25519();
+    let listener_id = listener_keys.public().into_peer_id();
+    let dialer_keys = identity::Keypair::generate_ed25519();
+    let dialer_id = dialer_keys.public().into_peer_id();
+
+    let (tx, rx) = oneshot::channel();
+
+    let bg_thread = async_std::task::spawn(async move {
+        let transport = QuicConfig::new(&listener_keys).unwrap();
+
+        let mut listener = transport
+            .listen_on("/ip4/127.0.0.1/udp/0/quic".parse().unwrap())
+            .unwrap();
+
+        let addr = listener.next().await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        tx.send(addr).unwrap();
+
+        let (peer_id, muxer) = listener
+            .next().await
+            .unwrap()
+            .unwrap()
+            .into_upgrade().unwrap().0.await.unwrap();
+        assert_eq!(peer_id, dialer_id);
+
+        let muxer = Arc::new(muxer);
+        let mut outbound = muxing::outbound_from_ref_and_wrap(muxer.clone()).await.unwrap();
+        outbound.write_all(b"ping").await.unwrap();
+        outbound.flush().await.unwrap();
+
+        let mut buf = Vec::new();
+        outbound.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hello world");
+    });
+
+    async_std::task::block_on(async {
+        let transport = QuicConfig::new(&dialer_keys).unwrap();
+
+        let (peer_id, muxer) = transport.dial(rx.await.unwrap()).unwrap().await.unwrap();
+        assert_eq!(peer_id, listener_id);
+
+        let muxer = Arc::new(muxer);
+        let mut inbound = loop {
+            if let Some(s) = muxing::event_from_ref_and_wrap(muxer.clone()).await.unwrap()
+                .into_inbound_substream() {
+                break s;
+            }
+        };
+
+        let mut buf = [0; 4];
+        inbound.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        inbound.write_all(b"hello world").await.unwrap();
+        inbound.close().await.unwrap();
+
+        bg_thread.await;
+    });
+}
+
+#[test]
+fn listen_on_wildcard_reports_interfaces() {
+    async_std::task::block_on(async {
+        let keys = identity::Keypair::generate_ed25519();
+        let mut listener = QuicConfig::new(&keys).unwrap()
+            .listen_on("/ip4/0.0.0.0/udp/0/quic".parse().unwrap())
+            .unwrap();
+
+        let addr = listener.next().await
+            .expect("some event")
+            .expect("no error")
+            .into_new_address()
+            .expect("listen address");
+
+        assert!(!addr.to_string().contains("0.0.0.0"));
+        assert!(!addr.to_string().contains("/udp/0/"));
+    });
+}
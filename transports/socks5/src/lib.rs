@@ -0,0 +1,552 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! # libp2p-socks5
+//!
+//! This crate provides the type `Socks5Config`, which routes the dials of an underlying transport
+//! through a [SOCKS5](https://tools.ietf.org/html/rfc1928) proxy, such as the one exposed by Tor.
+//!
+//! ## Usage
+//!
+//! Create a `Socks5Config` that wraps a transport able to reach the proxy, typically a
+//! `TcpConfig`, and pass it the address of the proxy. Dialing `/ip4/`, `/ip6/`, `/dns/`, `/dns4/`
+//! or `/dns6/` addresses followed by a `/tcp/` component, or `/onion/` and `/onion3/` addresses,
+//! opens a connection to the proxy and asks it to connect to the target.
+//!
+//! Host names are passed to the proxy as is, so that it resolves them itself. For this reason the
+//! `Socks5Config` should *not* be wrapped inside a `DnsConfig`, otherwise names would be resolved
+//! locally and `.onion` addresses could not be reached.
+//!
+//! Listening is passed through to the underlying transport, as SOCKS5 proxies can't accept
+//! incoming connections on our behalf.
+//!
+//! ```
+//! use libp2p_core::{Multiaddr, transport::memory::MemoryTransport};
+//! use libp2p_socks5::Socks5Config;
+//!
+//! let proxy: Multiaddr = "/memory/9050".parse().unwrap();
+//! let transport = Socks5Config::new(MemoryTransport, proxy)
+//!     .with_credentials("user", "password");
+//! ```
+
+use futures::{prelude::*, future::BoxFuture};
+use libp2p_core::{
+    Transport,
+    multiaddr::{Protocol, Multiaddr},
+    transport::{TransportError, ListenerEvent}
+};
+use log::debug;
+use std::{error, fmt, io, net::IpAddr};
+
+/// Version byte of the SOCKS5 protocol.
+const SOCKS_VERSION: u8 = 0x05;
+/// Version byte of the username/password sub-negotiation (RFC 1929).
+const AUTH_VERSION: u8 = 0x01;
+/// No authentication required.
+const METHOD_NO_AUTH: u8 = 0x00;
+/// Username/password authentication.
+const METHOD_USERNAME_PASSWORD: u8 = 0x02;
+/// Sent by the proxy if none of the proposed methods is acceptable.
+const METHOD_NO_ACCEPTABLE: u8 = 0xff;
+/// The `CONNECT` command.
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// Represents the configuration for a SOCKS5 proxy in front of a transport.
+///
+/// This struct implements the `Transport` trait and holds an underlying transport that is used
+/// to reach the proxy. All calls to `dial` are routed through the proxy, while calls to
+/// `listen_on` are passed through to the underlying transport.
+#[derive(Clone)]
+pub struct Socks5Config<T> {
+    /// Underlying transport used to connect to the proxy.
+    inner: T,
+    /// Address of the proxy.
+    proxy: Multiaddr,
+    /// Username and password to authenticate with, if any.
+    credentials: Option<(String, String)>,
+}
+
+impl<T> Socks5Config<T> {
+    /// Creates a new configuration that dials through the SOCKS5 proxy at `proxy`, which is
+    /// reached using `inner`.
+    pub fn new(inner: T, proxy: Multiaddr) -> Self {
+        Socks5Config {
+            inner,
+            proxy,
+            credentials: None,
+        }
+    }
+
+    /// Authenticates with the proxy using the given username and password.
+    ///
+    /// Both must be at most 255 bytes long, otherwise dialing fails.
+    pub fn with_credentials(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+}
+
+impl<T> fmt::Debug for Socks5Config<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Socks5Config")
+            .field("inner", &self.inner)
+            .field("proxy", &self.proxy)
+            .finish()
+    }
+}
+
+impl<T> Transport for Socks5Config<T>
+where
+    T: Transport + Send + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send,
+    T::Error: Send,
+    T::Dial: Send,
+{
+    type Output = T::Output;
+    type Error = Socks5Error<T::Error>;
+    type Listener = stream::MapErr<
+        stream::MapOk<T::Listener,
+            fn(ListenerEvent<T::ListenerUpgrade, T::Error>) -> ListenerEvent<Self::ListenerUpgrade, Self::Error>>,
+        fn(T::Error) -> Self::Error>;
+    type ListenerUpgrade = future::MapErr<T::ListenerUpgrade, fn(T::Error) -> Self::Error>;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        let listener = self.inner.listen_on(addr).map_err(|err| err.map(Socks5Error::Underlying))?;
+        let listener = listener
+            .map_ok::<_, fn(_) -> _>(|event| {
+                event
+                    .map(|upgr| {
+                        upgr.map_err::<_, fn(_) -> _>(Socks5Error::Underlying)
+                    })
+                    .map_err(Socks5Error::Underlying)
+            })
+            .map_err::<_, fn(_) -> _>(Socks5Error::Underlying);
+        Ok(listener)
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let target = match multiaddr_to_target(&addr) {
+            Some(target) => target,
+            None => return Err(TransportError::MultiaddrNotSupported(addr)),
+        };
+
+        debug!("Dialing {} through SOCKS5 proxy {}", addr, self.proxy);
+        let credentials = self.credentials;
+        let dial = match self.inner.dial(self.proxy) {
+            Ok(dial) => dial,
+            Err(TransportError::MultiaddrNotSupported(_)) =>
+                return Err(TransportError::MultiaddrNotSupported(addr)),
+            Err(TransportError::Other(err)) =>
+                return Err(TransportError::Other(Socks5Error::Underlying(err))),
+        };
+
+        Ok(async move {
+            let mut stream = dial.await.map_err(Socks5Error::Underlying)?;
+            handshake(&mut stream, &target, credentials.as_ref()).await?;
+            Ok(stream)
+        }.boxed())
+    }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.inner.address_translation(listen, observed)
+    }
+}
+
+/// Destination of a connection, as sent to the proxy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    /// An IP address and port.
+    Ip(IpAddr, u16),
+    /// A host name to be resolved by the proxy, and a port.
+    Domain(String, u16),
+}
+
+/// Turns a `Multiaddr` into the target to ask the proxy to connect to.
+///
+/// Returns `None` if the address isn't a TCP address or an onion address.
+fn multiaddr_to_target(addr: &Multiaddr) -> Option<Target> {
+    let mut iter = addr.iter();
+    let target = match iter.next()? {
+        Protocol::Ip4(ip) => match iter.next()? {
+            Protocol::Tcp(port) => Target::Ip(ip.into(), port),
+            _ => return None,
+        },
+        Protocol::Ip6(ip) => match iter.next()? {
+            Protocol::Tcp(port) => Target::Ip(ip.into(), port),
+            _ => return None,
+        },
+        Protocol::Dns(name) | Protocol::Dns4(name) | Protocol::Dns6(name) => match iter.next()? {
+            Protocol::Tcp(port) => Target::Domain(name.into_owned(), port),
+            _ => return None,
+        },
+        Protocol::Onion(hash, port) => Target::Domain(onion_host(&hash[..]), port),
+        Protocol::Onion3(addr) => Target::Domain(onion_host(&addr.hash()[..]), addr.port()),
+        _ => return None,
+    };
+
+    if iter.next().is_some() {
+        return None;
+    }
+
+    match &target {
+        Target::Domain(name, _) if name.len() > usize::from(u8::MAX) => None,
+        _ => Some(target),
+    }
+}
+
+/// Returns the `.onion` host name corresponding to the given onion address hash.
+fn onion_host(hash: &[u8]) -> String {
+    let mut host = data_encoding::BASE32_NOPAD.encode(hash).to_lowercase();
+    host.push_str(".onion");
+    host
+}
+
+/// Performs the SOCKS5 handshake on `stream`, asking the proxy to connect to `target`.
+async fn handshake<S, TErr>(
+    stream: &mut S,
+    target: &Target,
+    credentials: Option<&(String, String)>
+) -> Result<(), Socks5Error<TErr>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // Method selection.
+    let method = if credentials.is_some() { METHOD_USERNAME_PASSWORD } else { METHOD_NO_AUTH };
+    stream.write_all(&[SOCKS_VERSION, 1, method]).await?;
+    stream.flush().await?;
+
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(Socks5Error::InvalidReply);
+    }
+    if reply[1] == METHOD_NO_ACCEPTABLE {
+        return Err(Socks5Error::NoAcceptableAuthMethod);
+    }
+    if reply[1] != method {
+        return Err(Socks5Error::InvalidReply);
+    }
+
+    // Username/password authentication.
+    if let Some((username, password)) = credentials {
+        let mut request = vec![AUTH_VERSION];
+        for field in &[username, password] {
+            if field.len() > usize::from(u8::MAX) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "SOCKS5 credentials too long").into());
+            }
+            request.push(field.len() as u8);
+            request.extend_from_slice(field.as_bytes());
+        }
+        stream.write_all(&request).await?;
+        stream.flush().await?;
+
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != AUTH_VERSION {
+            return Err(Socks5Error::InvalidReply);
+        }
+        if reply[1] != 0 {
+            return Err(Socks5Error::AuthenticationFailed);
+        }
+    }
+
+    // Connection request.
+    let mut request = vec![SOCKS_VERSION, CMD_CONNECT, 0];
+    let port = match target {
+        Target::Ip(IpAddr::V4(ip), port) => {
+            request.push(ATYP_IPV4);
+            request.extend_from_slice(&ip.octets());
+            port
+        }
+        Target::Ip(IpAddr::V6(ip), port) => {
+            request.push(ATYP_IPV6);
+            request.extend_from_slice(&ip.octets());
+            port
+        }
+        Target::Domain(name, port) => {
+            request.push(ATYP_DOMAIN);
+            request.push(name.len() as u8);
+            request.extend_from_slice(name.as_bytes());
+            port
+        }
+    };
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+    stream.flush().await?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(Socks5Error::InvalidReply);
+    }
+    if reply[1] != 0 {
+        return Err(Socks5Error::ConnectFailed(reply[1]));
+    }
+
+    // Skip the address the proxy bound to, which we have no use for.
+    let addr_len = match reply[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => {
+            let mut len = [0; 1];
+            stream.read_exact(&mut len).await?;
+            usize::from(len[0])
+        }
+        _ => return Err(Socks5Error::InvalidReply),
+    };
+    let mut bound = vec![0; addr_len + 2];
+    stream.read_exact(&mut bound).await?;
+
+    Ok(())
+}
+
+/// Error that can be generated by the SOCKS5 layer.
+#[derive(Debug)]
+pub enum Socks5Error<TErr> {
+    /// Error in the underlying transport layer.
+    Underlying(TErr),
+    /// I/O error while talking to the proxy.
+    Io(io::Error),
+    /// The proxy sent a message that doesn't follow the SOCKS5 protocol.
+    InvalidReply,
+    /// The proxy doesn't accept the authentication method we proposed.
+    NoAcceptableAuthMethod,
+    /// The proxy rejected our username and password.
+    AuthenticationFailed,
+    /// The proxy failed to connect to the target. Contains the reply code sent by the proxy.
+    ConnectFailed(u8),
+}
+
+impl<TErr> From<io::Error> for Socks5Error<TErr> {
+    fn from(err: io::Error) -> Self {
+        Socks5Error::Io(err)
+    }
+}
+
+impl<TErr> fmt::Display for Socks5Error<TErr>
+where TErr: fmt::Display
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Socks5Error::Underlying(err) => write!(f, "{}", err),
+            Socks5Error::Io(err) => write!(f, "I/O error with the SOCKS5 proxy: {}", err),
+            Socks5Error::InvalidReply => write!(f, "Invalid reply from the SOCKS5 proxy"),
+            Socks5Error::NoAcceptableAuthMethod =>
+                write!(f, "SOCKS5 proxy doesn't accept the proposed authentication method"),
+            Socks5Error::AuthenticationFailed => write!(f, "SOCKS5 authentication failed"),
+            Socks5Error::ConnectFailed(code) => {
+                let reason = match code {
+                    0x01 => "general failure",
+                    0x02 => "connection not allowed by ruleset",
+                    0x03 => "network unreachable",
+                    0x04 => "host unreachable",
+                    0x05 => "connection refused",
+                    0x06 => "TTL expired",
+                    0x07 => "command not supported",
+                    0x08 => "address type not supported",
+                    _ => "unknown error",
+                };
+                write!(f, "SOCKS5 proxy failed to connect: {} ({:#04x})", reason, code)
+            }
+        }
+    }
+}
+
+impl<TErr> error::Error for Socks5Error<TErr>
+where TErr: error::Error + 'static
+{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Socks5Error::Underlying(err) => Some(err),
+            Socks5Error::Io(err) => Some(err),
+            Socks5Error::InvalidReply => None,
+            Socks5Error::NoAcceptableAuthMethod => None,
+            Socks5Error::AuthenticationFailed => None,
+            Socks5Error::ConnectFailed(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{multiaddr_to_target, Socks5Config, Socks5Error, Target};
+    use futures::prelude::*;
+    use libp2p_core::{
+        Transport,
+        multiaddr::{Multiaddr, multiaddr},
+        transport::{ListenerEvent, memory::MemoryTransport},
+    };
+    use rand::{thread_rng, Rng};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn multiaddr_to_target_conversion() {
+        let parse = |addr: &str| multiaddr_to_target(&addr.parse::<Multiaddr>().unwrap());
+
+        assert_eq!(
+            parse("/ip4/127.0.0.1/tcp/4001"),
+            Some(Target::Ip(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 4001))
+        );
+        assert_eq!(
+            parse("/ip6/::1/tcp/4001"),
+            Some(Target::Ip(IpAddr::V6(Ipv6Addr::LOCALHOST), 4001))
+        );
+        assert_eq!(
+            parse("/dns4/example.com/tcp/443"),
+            Some(Target::Domain("example.com".into(), 443))
+        );
+        assert_eq!(
+            parse("/onion3/vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd:1234"),
+            Some(Target::Domain("vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd.onion".into(), 1234))
+        );
+        assert_eq!(parse("/ip4/127.0.0.1/udp/4001"), None);
+        assert_eq!(parse("/ip4/127.0.0.1"), None);
+        assert_eq!(parse("/ip4/127.0.0.1/tcp/4001/ws"), None);
+        assert_eq!(parse("/memory/1234"), None);
+    }
+
+    /// Spawns a minimal SOCKS5 proxy on a memory address, which accepts a single connection,
+    /// checks the handshake against `expected` and then echoes everything it receives.
+    fn spawn_proxy(expected_auth: Option<(&'static [u8], &'static [u8])>, expected_request: Vec<u8>) -> Multiaddr {
+        let mut listener = MemoryTransport
+            .listen_on(multiaddr![Memory(thread_rng().gen::<u64>())])
+            .unwrap();
+        let addr = match listener.next().now_or_never() {
+            Some(Some(Ok(ListenerEvent::NewAddress(a)))) => a,
+            _ => panic!("MemoryTransport not listening on an address!"),
+        };
+
+        async_std::task::spawn(async move {
+            let (upgrade, _) = listener.next().await.unwrap().unwrap().into_upgrade().unwrap();
+            let mut conn = upgrade.await.unwrap();
+
+            let mut greeting = [0; 3];
+            conn.read_exact(&mut greeting).await.unwrap();
+            match expected_auth {
+                None => {
+                    assert_eq!(greeting, [5, 1, 0]);
+                    conn.write_all(&[5, 0]).await.unwrap();
+                }
+                Some((username, password)) => {
+                    assert_eq!(greeting, [5, 1, 2]);
+                    conn.write_all(&[5, 2]).await.unwrap();
+                    let mut expected = vec![1, username.len() as u8];
+                    expected.extend_from_slice(username);
+                    expected.push(password.len() as u8);
+                    expected.extend_from_slice(password);
+                    let mut auth = vec![0; expected.len()];
+                    conn.read_exact(&mut auth).await.unwrap();
+                    assert_eq!(auth, expected);
+                    conn.write_all(&[1, 0]).await.unwrap();
+                }
+            }
+
+            let mut request = vec![0; expected_request.len()];
+            conn.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, expected_request);
+            conn.write_all(&[5, 0, 0, 1, 10, 0, 0, 1, 0x1f, 0x90]).await.unwrap();
+
+            let mut buf = [0; 5];
+            conn.read_exact(&mut buf).await.unwrap();
+            conn.write_all(&buf).await.unwrap();
+        });
+
+        addr
+    }
+
+    #[test]
+    fn dial_domain_through_proxy() {
+        let mut expected = vec![5, 1, 0, 3, 11];
+        expected.extend_from_slice(b"example.com");
+        expected.extend_from_slice(&443u16.to_be_bytes());
+        let proxy = spawn_proxy(None, expected);
+
+        async_std::task::block_on(async move {
+            let mut conn = Socks5Config::new(MemoryTransport, proxy)
+                .dial("/dns/example.com/tcp/443".parse().unwrap())
+                .unwrap()
+                .await
+                .unwrap();
+            conn.write_all(b"hello").await.unwrap();
+            let mut buf = [0; 5];
+            conn.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+        });
+    }
+
+    #[test]
+    fn dial_ip_with_credentials() {
+        let expected = vec![5, 1, 0, 1, 192, 168, 1, 2, 0x0f, 0xa1];
+        let proxy = spawn_proxy(Some((b"user", b"secret")), expected);
+
+        async_std::task::block_on(async move {
+            let mut conn = Socks5Config::new(MemoryTransport, proxy)
+                .with_credentials("user", "secret")
+                .dial("/ip4/192.168.1.2/tcp/4001".parse().unwrap())
+                .unwrap()
+                .await
+                .unwrap();
+            conn.write_all(b"hello").await.unwrap();
+            let mut buf = [0; 5];
+            conn.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+        });
+    }
+
+    #[test]
+    fn connect_failure_is_reported() {
+        let mut listener = MemoryTransport
+            .listen_on(multiaddr![Memory(thread_rng().gen::<u64>())])
+            .unwrap();
+        let proxy = match listener.next().now_or_never() {
+            Some(Some(Ok(ListenerEvent::NewAddress(a)))) => a,
+            _ => panic!("MemoryTransport not listening on an address!"),
+        };
+
+        async_std::task::spawn(async move {
+            let (upgrade, _) = listener.next().await.unwrap().unwrap().into_upgrade().unwrap();
+            let mut conn = upgrade.await.unwrap();
+            let mut buf = [0; 3];
+            conn.read_exact(&mut buf).await.unwrap();
+            conn.write_all(&[5, 0]).await.unwrap();
+            let mut buf = [0; 10];
+            conn.read_exact(&mut buf).await.unwrap();
+            conn.write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0]).await.unwrap();
+        });
+
+        async_std::task::block_on(async move {
+            let result = Socks5Config::new(MemoryTransport, proxy)
+                .dial("/ip4/127.0.0.1/tcp/80".parse().unwrap())
+                .unwrap()
+                .await;
+            match result {
+                Err(Socks5Error::ConnectFailed(5)) => {}
+                _ => panic!("Unexpected dial result"),
+            }
+        });
+    }
+}
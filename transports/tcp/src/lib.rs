@@ -35,31 +35,37 @@ use get_if_addrs::{IfAddr, get_if_addrs};
 use ipnet::{IpNet, Ipv4Net, Ipv6Net};
 use libp2p_core::{
     Transport,
+    address_translation,
     multiaddr::{Protocol, Multiaddr},
     transport::{ListenerEvent, TransportError}
 };
-use log::{debug, trace};
+use tracing::{Instrument, debug, trace};
 use socket2::{Socket, Domain, Type};
 use std::{
-    collections::VecDeque,
+    collections::{HashSet, VecDeque},
     convert::TryFrom,
     io,
     iter::{self, FromIterator},
-    net::{IpAddr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     pin::Pin,
+    sync::{Arc, Mutex},
     task::{Context, Poll},
     time::Duration
 };
 
+/// How often listeners on all interfaces check the host interfaces for new
+/// and expired addresses.
+const IF_WATCH_INTERVAL: Duration = Duration::from_secs(10);
+
 macro_rules! codegen {
-    ($feature_name:expr, $tcp_config:ident, $tcp_trans_stream:ident, $tcp_listen_stream:ident, $apply_config:ident, $tcp_stream:ty, $tcp_listener:ty) => {
+    ($feature_name:expr, $tcp_config:ident, $tcp_trans_stream:ident, $tcp_listen_stream:ident, $apply_config:ident, $connect:ident, $tcp_stream:ty, $tcp_listener:ty) => {
 
 /// Represents the configuration for a TCP/IP transport capability for libp2p.
 ///
 /// The TCP sockets created by libp2p will need to be progressed by running the futures and streams
 /// obtained by libp2p through the tokio reactor.
 #[cfg_attr(docsrs, doc(cfg(feature = $feature_name)))]
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct $tcp_config {
     /// How long a listener should sleep after receiving an error, before trying again.
     sleep_on_error: Duration,
@@ -67,6 +73,22 @@ pub struct $tcp_config {
     ttl: Option<u32>,
     /// `TCP_NODELAY` to set for opened sockets, or `None` to keep default.
     nodelay: Option<bool>,
+    /// `SO_KEEPALIVE` idle time to set for opened sockets, or `None` to keep default.
+    keepalive: Option<Duration>,
+    /// `SO_SNDBUF` to set for opened sockets, or `None` to keep default.
+    send_buffer_size: Option<usize>,
+    /// `SO_RCVBUF` to set for opened sockets, or `None` to keep default.
+    recv_buffer_size: Option<usize>,
+    /// Size of the queue of pending incoming connections of a listener.
+    backlog: u32,
+    /// Whether dialing sockets are bound to the port of a listener.
+    port_reuse: PortReuse,
+}
+
+impl Default for $tcp_config {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl $tcp_config {
@@ -76,6 +98,11 @@ impl $tcp_config {
             sleep_on_error: Duration::from_millis(100),
             ttl: None,
             nodelay: None,
+            keepalive: None,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            backlog: 1024,
+            port_reuse: PortReuse::Disabled,
         }
     }
 
@@ -90,6 +117,81 @@ impl $tcp_config {
         self.nodelay = Some(value);
         self
     }
+
+    /// Enables `SO_KEEPALIVE` on opened sockets, sending the first keepalive
+    /// probe after the connection has been idle for the given duration.
+    pub fn keepalive(mut self, value: Duration) -> Self {
+        self.keepalive = Some(value);
+        self
+    }
+
+    /// Sets the size of the send buffer (`SO_SNDBUF`) of opened sockets.
+    pub fn send_buffer_size(mut self, value: usize) -> Self {
+        self.send_buffer_size = Some(value);
+        self
+    }
+
+    /// Sets the size of the receive buffer (`SO_RCVBUF`) of opened sockets.
+    pub fn recv_buffer_size(mut self, value: usize) -> Self {
+        self.recv_buffer_size = Some(value);
+        self
+    }
+
+    /// Sets the maximum number of pending incoming connections of a listener.
+    ///
+    /// Defaults to 1024.
+    pub fn listen_backlog(mut self, value: u32) -> Self {
+        self.backlog = value;
+        self
+    }
+
+    /// Enables or disables port reuse.
+    ///
+    /// With port reuse enabled, listening sockets are created with `SO_REUSEPORT`
+    /// (where available) and outgoing connections are bound to the port of one
+    /// of the listeners created from this configuration or a clone of it, so
+    /// that remotes observe the listen port as the source port of our dials.
+    /// This is needed for TCP hole punching.
+    ///
+    /// Disabled by default.
+    pub fn port_reuse(mut self, value: bool) -> Self {
+        self.port_reuse = if value {
+            PortReuse::Enabled { listen_addrs: Default::default() }
+        } else {
+            PortReuse::Disabled
+        };
+        self
+    }
+
+    /// Creates a new, unbound socket for the given address and applies the
+    /// configured socket options to it.
+    ///
+    /// Sockets accepted by a listener inherit the options of the listening socket.
+    fn create_socket(&self, socket_addr: &SocketAddr) -> io::Result<Socket> {
+        let socket = if socket_addr.is_ipv4() {
+            Socket::new(Domain::ipv4(), Type::stream(), Some(socket2::Protocol::tcp()))?
+        } else {
+            let s = Socket::new(Domain::ipv6(), Type::stream(), Some(socket2::Protocol::tcp()))?;
+            s.set_only_v6(true)?;
+            s
+        };
+        if let Some(ttl) = self.ttl {
+            socket.set_ttl(ttl)?;
+        }
+        if let Some(nodelay) = self.nodelay {
+            socket.set_nodelay(nodelay)?;
+        }
+        if let Some(keepalive) = self.keepalive {
+            socket.set_keepalive(Some(keepalive))?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        Ok(socket)
+    }
 }
 
 impl Transport for $tcp_config {
@@ -110,24 +212,22 @@ impl Transport for $tcp_config {
         async fn do_listen(cfg: $tcp_config, socket_addr: SocketAddr)
             -> Result<impl Stream<Item = Result<ListenerEvent<Ready<Result<$tcp_trans_stream, io::Error>>, io::Error>, io::Error>>, io::Error>
         {
-            let socket = if socket_addr.is_ipv4() {
-                Socket::new(Domain::ipv4(), Type::stream(), Some(socket2::Protocol::tcp()))?
-            } else {
-                let s = Socket::new(Domain::ipv6(), Type::stream(), Some(socket2::Protocol::tcp()))?;
-                s.set_only_v6(true)?;
-                s
-            };
+            let socket = cfg.create_socket(&socket_addr)?;
             if cfg!(target_family = "unix") {
                 socket.set_reuse_address(true)?;
             }
+            if let PortReuse::Enabled { .. } = cfg.port_reuse {
+                set_reuse_port(&socket)?;
+            }
             socket.bind(&socket_addr.into())?;
-            socket.listen(1024)?; // we may want to make this configurable
+            socket.listen(i32::try_from(cfg.backlog).unwrap_or(i32::MAX))?;
 
             let listener = <$tcp_listener>::try_from(socket.into_tcp_listener())
                 .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
 
             let local_addr = listener.local_addr()?;
             let port = local_addr.port();
+            cfg.port_reuse.register(local_addr);
 
             // Determine all our listen addresses which is either a single local IP address
             // or (if a wildcard IP address was used) the addresses of all our interfaces,
@@ -161,11 +261,18 @@ impl Transport for $tcp_config {
                 }
             };
 
+            let if_watch = match addrs {
+                Addresses::One(_) => None,
+                Addresses::Many(_) => Some(Delay::new(IF_WATCH_INTERVAL)),
+            };
+
             let listen_stream = $tcp_listen_stream {
                 stream: listener,
                 pause: None,
+                if_watch,
                 pause_duration: cfg.sleep_on_error,
                 port,
+                local_addr,
                 addrs,
                 pending,
                 config: cfg
@@ -192,12 +299,41 @@ impl Transport for $tcp_config {
         debug!("Dialing {}", addr);
 
         async fn do_dial(cfg: $tcp_config, socket_addr: SocketAddr) -> Result<$tcp_trans_stream, io::Error> {
-            let stream = <$tcp_stream>::connect(&socket_addr).await?;
-            $apply_config(&cfg, &stream)?;
+            let socket = cfg.create_socket(&socket_addr)?;
+            if let Some(local_addr) = cfg.port_reuse.local_dial_addr(&socket_addr.ip()) {
+                trace!("Binding dial socket to {}", local_addr);
+                socket.set_reuse_address(true)?;
+                set_reuse_port(&socket)?;
+                socket.bind(&local_addr.into())?;
+            }
+            let stream = $connect(socket, socket_addr).await?;
             Ok($tcp_trans_stream { inner: stream })
         }
 
-        Ok(Box::pin(do_dial(self, socket_addr)))
+        Ok(Box::pin(async move {
+            // The span is created once the dial is polled, to be nested in
+            // the span of the connection being established.
+            let span = tracing::debug_span!("tcp_connect", address = %addr);
+            let result = do_dial(self, socket_addr).instrument(span.clone()).await;
+            span.in_scope(|| match &result {
+                Ok(_) => debug!("Connected"),
+                Err(e) => debug!("Failed to connect: {}", e),
+            });
+            result
+        }))
+    }
+
+    /// With port reuse enabled, outbound connections are dialed from the port of a
+    /// listener, thus the observed address is taken as is. Otherwise, the IP address
+    /// of the observed address is combined with the port of the listen address.
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        if multiaddr_to_socketaddr(listen).is_err() || multiaddr_to_socketaddr(observed).is_err() {
+            return None
+        }
+        match self.port_reuse {
+            PortReuse::Disabled => address_translation(listen, observed),
+            PortReuse::Enabled { .. } => Some(observed.clone()),
+        }
     }
 }
 
@@ -208,10 +344,14 @@ pub struct $tcp_listen_stream {
     stream: $tcp_listener,
     /// The current pause if any.
     pause: Option<Delay>,
+    /// Timer for the next check of the host interfaces, if listening on all interfaces.
+    if_watch: Option<Delay>,
     /// How long to pause after an error.
     pause_duration: Duration,
     /// The port which we use as our listen port in listener event addresses.
     port: u16,
+    /// The local address the listening socket is bound to.
+    local_addr: SocketAddr,
     /// The set of known addresses.
     addrs: Addresses,
     /// Temporary buffer of listener events.
@@ -232,8 +372,33 @@ impl $tcp_listen_stream {
                 let _ = pause.await;
             }
 
+            // Wait for an incoming connection, or the next check of the host
+            // interfaces if we listen on all of them.
+            let accept = match self.if_watch.as_mut() {
+                Some(if_watch) => match future::select(Box::pin(self.stream.accept()), if_watch).await {
+                    future::Either::Left((accept, _)) => Some(accept),
+                    future::Either::Right(((), _)) => None
+                },
+                None => Some(self.stream.accept().await)
+            };
+
+            let accept = match accept {
+                Some(accept) => accept,
+                None => {
+                    if let Some(if_watch) = self.if_watch.as_mut() {
+                        if_watch.reset(IF_WATCH_INTERVAL);
+                    }
+                    if let Addresses::Many(ref mut addrs) = self.addrs {
+                        if let Err(err) = update_host_addresses(self.port, addrs, &mut self.pending) {
+                            return (Ok(ListenerEvent::Error(err)), self);
+                        }
+                    }
+                    continue
+                }
+            };
+
             // TODO: do we get the peer_addr at the same time?
-            let (sock, _) = match self.stream.accept().await {
+            let (sock, _) = match accept {
                 Ok(s) => s,
                 Err(e) => {
                     debug!("error accepting incoming connection: {}", e);
@@ -289,6 +454,12 @@ impl $tcp_listen_stream {
     }
 }
 
+impl Drop for $tcp_listen_stream {
+    fn drop(&mut self) {
+        self.config.port_reuse.unregister(&self.local_addr);
+    }
+}
+
 /// Wraps around a `TcpStream` and adds logging for important events.
 #[cfg_attr(docsrs, doc(cfg(feature = $feature_name)))]
 #[derive(Debug)]
@@ -323,10 +494,86 @@ fn $apply_config(config: &$tcp_config, socket: &$tcp_stream) -> Result<(), io::E
 }
 
 #[cfg(feature = "async-std")]
-codegen!("async-std", TcpConfig, TcpTransStream, TcpListenStream, apply_config_async_std, async_std::net::TcpStream, async_std::net::TcpListener);
+codegen!("async-std", TcpConfig, TcpTransStream, TcpListenStream, apply_config_async_std, connect_async_std, async_std::net::TcpStream, async_std::net::TcpListener);
+
+#[cfg(feature = "tokio")]
+codegen!("tokio", TokioTcpConfig, TokioTcpTransStream, TokioTcpListenStream, apply_config_tokio, connect_tokio, tokio::net::TcpStream, tokio::net::TcpListener);
+
+/// Port reuse configuration of a TCP transport.
+#[derive(Debug, Clone)]
+enum PortReuse {
+    /// Dialing sockets are bound to an ephemeral port.
+    Disabled,
+    /// Dialing sockets are bound to the port of one of these listen addresses.
+    Enabled { listen_addrs: Arc<Mutex<HashSet<SocketAddr>>> },
+}
+
+impl PortReuse {
+    /// Registers the address of a new listener.
+    fn register(&self, addr: SocketAddr) {
+        if let PortReuse::Enabled { listen_addrs } = self {
+            listen_addrs.lock().expect("lock is not poisoned").insert(addr);
+        }
+    }
+
+    /// Unregisters the address of a closed listener.
+    fn unregister(&self, addr: &SocketAddr) {
+        if let PortReuse::Enabled { listen_addrs } = self {
+            listen_addrs.lock().expect("lock is not poisoned").remove(addr);
+        }
+    }
+
+    /// Selects the local address to bind a socket dialing `remote_ip` to.
+    ///
+    /// Only listeners of the same IP version and the same loopback-ness as
+    /// the remote are considered. Returns `None` if port reuse is disabled or
+    /// no matching listener exists, in which case an ephemeral port is used.
+    fn local_dial_addr(&self, remote_ip: &IpAddr) -> Option<SocketAddr> {
+        if let PortReuse::Enabled { listen_addrs } = self {
+            let listen_addrs = listen_addrs.lock().expect("lock is not poisoned");
+            for addr in listen_addrs.iter() {
+                if addr.is_ipv4() == remote_ip.is_ipv4() && addr.ip().is_loopback() == remote_ip.is_loopback() {
+                    let ip = if addr.is_ipv4() {
+                        IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+                    } else {
+                        IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+                    };
+                    return Some(SocketAddr::new(ip, addr.port()))
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Sets `SO_REUSEPORT` on platforms which support it.
+fn set_reuse_port(socket: &Socket) -> io::Result<()> {
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    socket.set_reuse_port(true)?;
+    #[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+    let _ = socket;
+    Ok(())
+}
+
+/// Connects an unbound socket to the given address.
+///
+/// async-std offers no way to connect a socket that has been created by
+/// us, hence we connect on its blocking thread pool instead.
+#[cfg(feature = "async-std")]
+async fn connect_async_std(socket: Socket, addr: SocketAddr) -> Result<async_std::net::TcpStream, io::Error> {
+    let stream = async_std::task::spawn_blocking(move || {
+        socket.connect(&addr.into())?;
+        socket.set_nonblocking(true)?;
+        Ok::<_, io::Error>(socket.into_tcp_stream())
+    }).await?;
+    Ok(async_std::net::TcpStream::from(stream))
+}
 
+/// Connects an unbound socket to the given address.
 #[cfg(feature = "tokio")]
-codegen!("tokio", TokioTcpConfig, TokioTcpTransStream, TokioTcpListenStream, apply_config_tokio, tokio::net::TcpStream, tokio::net::TcpListener);
+async fn connect_tokio(socket: Socket, addr: SocketAddr) -> Result<tokio::net::TcpStream, io::Error> {
+    tokio::net::TcpStream::connect_std(socket.into_tcp_stream(), &addr).await
+}
 
 #[cfg(feature = "async-std")]
 impl AsyncRead for TcpTransStream {
@@ -457,8 +704,28 @@ fn check_for_interface_changes<T>(
     // The local IP address of this socket is new to us.
     // We check for changes in the set of host addresses and report new
     // and expired addresses.
-    //
-    // TODO: We do not detect expired addresses unless there is a new address.
+    update_host_addresses(listen_port, listen_addrs, pending)?;
+
+    // We should now be able to find the local address, if not something
+    // is seriously wrong and we report an error.
+    if listen_addrs.iter()
+        .find(|(ip, net, _)| ip == &socket_addr.ip() || net.contains(&socket_addr.ip()))
+        .is_none()
+    {
+        let msg = format!("{} does not match any listen address", socket_addr.ip());
+        return Err(io::Error::new(io::ErrorKind::Other, msg))
+    }
+
+    Ok(())
+}
+
+// Replace the listen addresses with the current host addresses and
+// report new and expired listen addresses.
+fn update_host_addresses<T>(
+    listen_port: u16,
+    listen_addrs: &mut Vec<(IpAddr, IpNet, Multiaddr)>,
+    pending: &mut Buffer<T>
+) -> Result<(), io::Error> {
     let old_listen_addrs = std::mem::replace(listen_addrs, host_addresses(listen_port)?);
 
     // Check for addresses no longer in use.
@@ -477,25 +744,16 @@ fn check_for_interface_changes<T>(
         }
     }
 
-    // We should now be able to find the local address, if not something
-    // is seriously wrong and we report an error.
-    if listen_addrs.iter()
-        .find(|(ip, net, _)| ip == &socket_addr.ip() || net.contains(&socket_addr.ip()))
-        .is_none()
-    {
-        let msg = format!("{} does not match any listen address", socket_addr.ip());
-        return Err(io::Error::new(io::ErrorKind::Other, msg))
-    }
-
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use futures::prelude::*;
+    use ipnet::IpNet;
     use libp2p_core::{Transport, multiaddr::{Multiaddr, Protocol}, transport::ListenerEvent};
-    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-    use super::multiaddr_to_socketaddr;
+    use std::{collections::VecDeque, net::{IpAddr, Ipv4Addr, SocketAddr}, time::Duration};
+    use super::{Buffer, ip_to_multiaddr, multiaddr_to_socketaddr, update_host_addresses};
     #[cfg(feature = "async-std")]
     use super::TcpConfig;
 
@@ -543,6 +801,44 @@ mod tests {
         test("/ip6/::1/tcp/0".parse().unwrap());
     }
 
+    #[test]
+    #[cfg(feature = "async-std")]
+    fn address_translation() {
+        let listen: Multiaddr = "/ip4/192.168.1.2/tcp/4001".parse().unwrap();
+        let observed: Multiaddr = "/ip4/203.0.113.7/tcp/53211".parse().unwrap();
+
+        let transport = TcpConfig::new();
+        assert_eq!(
+            transport.address_translation(&listen, &observed),
+            Some("/ip4/203.0.113.7/tcp/4001".parse().unwrap())
+        );
+
+        let transport = TcpConfig::new().port_reuse(true);
+        assert_eq!(transport.address_translation(&listen, &observed), Some(observed.clone()));
+
+        let quic: Multiaddr = "/ip4/203.0.113.7/udp/53211/quic".parse().unwrap();
+        assert_eq!(transport.address_translation(&listen, &quic), None);
+        let memory: Multiaddr = "/memory/1234".parse().unwrap();
+        assert_eq!(transport.address_translation(&memory, &observed), None);
+    }
+
+    #[test]
+    fn host_address_changes() {
+        let stale_ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1));
+        let stale = (stale_ip, IpNet::from(stale_ip), ip_to_multiaddr(stale_ip, 1234));
+        let mut addrs = vec![stale.clone()];
+        let mut pending: Buffer<()> = VecDeque::new();
+
+        update_host_addresses(1234, &mut addrs, &mut pending).unwrap();
+
+        let events = pending.into_iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert!(matches!(&events[0], ListenerEvent::AddressExpired(a) if a == &stale.2));
+        assert_eq!(events.len(), addrs.len() + 1);
+        for ((_, _, ma), event) in addrs.iter().zip(&events[1..]) {
+            assert!(matches!(event, ListenerEvent::NewAddress(a) if a == ma));
+        }
+    }
+
     #[test]
     fn multiaddr_to_tcp_conversion() {
         use std::net::Ipv6Addr;
@@ -638,6 +934,77 @@ mod tests {
         test("/ip6/::1/tcp/0".parse().unwrap());
     }
 
+    #[test]
+    #[cfg(feature = "async-std")]
+    fn socket_options() {
+        let tcp = TcpConfig::new()
+            .nodelay(true)
+            .ttl(32)
+            .keepalive(Duration::from_secs(30))
+            .send_buffer_size(64 * 1024)
+            .recv_buffer_size(64 * 1024)
+            .listen_backlog(16);
+
+        let mut listener = tcp.clone().listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+
+        async_std::task::block_on(async move {
+            let addr = listener.next().await.unwrap().unwrap().into_new_address().unwrap();
+
+            let server = async move {
+                let upgrade = listener.next().await.unwrap().unwrap().into_upgrade().unwrap().0;
+                let mut socket = upgrade.await.unwrap();
+                socket.write_all(&[1, 2, 3]).await.unwrap();
+            };
+
+            let client = async move {
+                let mut socket = tcp.dial(addr).unwrap().await.unwrap();
+                assert_eq!(socket.inner.ttl().unwrap(), 32);
+                assert!(socket.inner.nodelay().unwrap());
+                let mut buf = [0u8; 3];
+                socket.read_exact(&mut buf).await.unwrap();
+                assert_eq!(buf, [1, 2, 3]);
+            };
+
+            future::join(server, client).await;
+        });
+    }
+
+    #[test]
+    #[cfg(all(feature = "async-std", unix))]
+    fn port_reuse() {
+        let tcp = TcpConfig::new().port_reuse(true);
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
+
+        let mut listener1 = tcp.clone().listen_on(addr.clone()).unwrap();
+        let mut listener2 = TcpConfig::new().listen_on(addr).unwrap();
+
+        async_std::task::block_on(async move {
+            let addr1 = listener1.next().await.unwrap().unwrap().into_new_address().unwrap();
+            let addr2 = listener2.next().await.unwrap().unwrap().into_new_address().unwrap();
+
+            let server = async move {
+                listener2.next().await.unwrap().unwrap().into_upgrade().unwrap().1
+            };
+            let client = tcp.dial(addr2).unwrap();
+
+            let (remote_addr, socket) = future::join(server, client).await;
+            socket.unwrap();
+            assert_eq!(remote_addr, addr1);
+            drop(listener1);
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "async-std")]
+    fn dial_error() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = ip_to_multiaddr(listener.local_addr().unwrap().ip(), listener.local_addr().unwrap().port());
+        drop(listener);
+
+        let dial = TcpConfig::new().dial(addr).unwrap();
+        assert!(async_std::task::block_on(dial).is_err());
+    }
+
     #[test]
     #[cfg(feature = "async-std")]
     fn replace_port_0_in_returned_multiaddr_ipv4() {
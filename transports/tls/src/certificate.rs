@@ -0,0 +1,307 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! X.509 certificate handling as described in the
+//! [libp2p TLS specification](https://github.com/libp2p/specs/blob/master/tls/tls.md).
+//!
+//! Every connection uses a fresh self-signed certificate whose key is unrelated to the
+//! node's identity. The identity is bound to the certificate through a custom extension
+//! containing the host's public key and a signature over the certificate's public key.
+
+use libp2p_core::{PeerId, identity};
+use x509_parser::prelude::*;
+
+/// The libp2p Public Key Extension is a X.509 extension
+/// with the Object Identifier 1.3.6.1.4.1.53594.1.1,
+/// allocated by IANA to the libp2p project at Protocol Labs.
+const P2P_EXT_OID: [u64; 9] = [1, 3, 6, 1, 4, 1, 53594, 1, 1];
+
+/// The peer signs the concatenation of the string `libp2p-tls-handshake:`
+/// and the public key that it used to generate the certificate carrying
+/// the libp2p Public Key Extension, using its private host key.
+const P2P_SIGNING_PREFIX: [u8; 21] = *b"libp2p-tls-handshake:";
+
+/// The signature algorithm of the certificate key.
+///
+/// The certificate key is ephemeral and only used for the TLS handshake, hence
+/// we always use ECDSA on P-256, which every libp2p implementation supports.
+static P2P_SIGNATURE_ALGORITHM: &rcgen::SignatureAlgorithm = &rcgen::PKCS_ECDSA_P256_SHA256;
+
+/// Generates a self-signed TLS certificate that includes a libp2p-specific
+/// certificate extension containing the public key of the given keypair.
+pub fn generate(
+    identity_keypair: &identity::Keypair,
+) -> Result<(rustls::pki_types::CertificateDer<'static>, rustls::pki_types::PrivateKeyDer<'static>), GenError> {
+    let certificate_keypair = rcgen::KeyPair::generate_for(P2P_SIGNATURE_ALGORITHM)?;
+    let signature = identity_keypair.sign(&signed_message(&certificate_keypair))
+        .map_err(|e| GenError::Signing(e.to_string()))?;
+    self_signed(identity_keypair.public(), signature, certificate_keypair)
+}
+
+/// Like [`generate`], but signs the libp2p-specific certificate extension
+/// with the given [`identity::Signer`], e.g. one backed by a hardware security module.
+pub async fn generate_with_signer(
+    signer: &dyn identity::Signer,
+) -> Result<(rustls::pki_types::CertificateDer<'static>, rustls::pki_types::PrivateKeyDer<'static>), GenError> {
+    let certificate_keypair = rcgen::KeyPair::generate_for(P2P_SIGNATURE_ALGORITHM)?;
+    let signature = signer.sign(&signed_message(&certificate_keypair)).await
+        .map_err(|e| GenError::Signing(e.to_string()))?;
+    self_signed(signer.public(), signature, certificate_keypair)
+}
+
+/// Creates the self-signed certificate for `certificate_keypair`, carrying the
+/// libp2p-specific extension with the identity public key and its signature.
+fn self_signed(
+    identity_public: identity::PublicKey,
+    signature: Vec<u8>,
+    // Keypair used to sign the certificate.
+    // SHOULD NOT be related to the host's key.
+    // Endpoints MAY generate a new key and certificate
+    // for every connection attempt, or they MAY reuse the same key
+    // and certificate for multiple connections.
+    certificate_keypair: rcgen::KeyPair,
+) -> Result<(rustls::pki_types::CertificateDer<'static>, rustls::pki_types::PrivateKeyDer<'static>), GenError> {
+    let rustls_key = rustls::pki_types::PrivateKeyDer::from(
+        rustls::pki_types::PrivatePkcs8KeyDer::from(certificate_keypair.serialize_der()),
+    );
+
+    let certificate = {
+        let mut params = rcgen::CertificateParams::new(vec![])?;
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        params.custom_extensions.push(make_libp2p_extension(identity_public, signature));
+        params.self_signed(&certificate_keypair)?
+    };
+
+    Ok((certificate.der().clone(), rustls_key))
+}
+
+/// An X.509 certificate with a libp2p-specific extension
+/// is used to secure libp2p connections.
+pub struct P2pCertificate<'a> {
+    certificate: X509Certificate<'a>,
+    /// The public key of the remote's identity, as carried by the libp2p extension.
+    public_key: identity::PublicKey,
+}
+
+impl P2pCertificate<'_> {
+    /// The [`PeerId`] of the remote peer.
+    pub fn peer_id(&self) -> PeerId {
+        self.public_key.clone().into_peer_id()
+    }
+
+    /// Verify the `signature` of the `message` signed by the private key corresponding to the
+    /// public key stored in the certificate.
+    pub fn verify_signature(
+        &self,
+        signature_scheme: rustls::SignatureScheme,
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), VerificationError> {
+        use ring::signature;
+        use rustls::SignatureScheme::*;
+
+        let algorithm: &dyn signature::VerificationAlgorithm = match signature_scheme {
+            ECDSA_NISTP256_SHA256 => &signature::ECDSA_P256_SHA256_ASN1,
+            ECDSA_NISTP384_SHA384 => &signature::ECDSA_P384_SHA384_ASN1,
+            ED25519 => &signature::ED25519,
+            RSA_PSS_SHA256 => &signature::RSA_PSS_2048_8192_SHA256,
+            RSA_PSS_SHA384 => &signature::RSA_PSS_2048_8192_SHA384,
+            RSA_PSS_SHA512 => &signature::RSA_PSS_2048_8192_SHA512,
+            _ => return Err(VerificationError::UnsupportedSchema(signature_scheme))
+        };
+
+        let spki = &self.certificate.tbs_certificate.subject_pki;
+        signature::UnparsedPublicKey::new(algorithm, &spki.subject_public_key.data)
+            .verify(message, signature)
+            .map_err(|_| VerificationError::InvalidSignature)
+    }
+}
+
+/// Attempts to parse the provided bytes as a [`P2pCertificate`].
+///
+/// For this to succeed, the certificate must contain the specified extension,
+/// be currently valid, be correctly self-signed and carry a valid signature of
+/// the certificate key by the embedded identity key.
+pub fn parse<'a>(
+    certificate: &'a rustls::pki_types::CertificateDer<'_>,
+) -> Result<P2pCertificate<'a>, ParseError> {
+    let certificate = X509Certificate::from_der(certificate.as_ref())
+        .map(|(_rest, x509)| x509)
+        .map_err(|e| ParseError::Malformed(e.to_string()))?;
+
+    if !certificate.validity().is_valid() {
+        return Err(ParseError::Expired)
+    }
+
+    // The certificate MUST be self-signed.
+    certificate.verify_signature(None).map_err(|_| ParseError::InvalidSelfSignature)?;
+
+    let p2p_ext_oid = x509_parser::der_parser::oid::Oid::from(&P2P_EXT_OID)
+        .expect("This is a valid OID of p2p extension; qed");
+
+    let mut libp2p_extension = None;
+
+    for ext in certificate.extensions() {
+        if ext.oid == p2p_ext_oid {
+            if libp2p_extension.is_some() {
+                // The extension was already parsed.
+                return Err(ParseError::DuplicateExtension)
+            }
+            libp2p_extension = Some(parse_libp2p_extension(ext.value)?);
+            continue
+        }
+
+        if ext.critical {
+            // Endpoints MUST abort the connection attempt if the certificate
+            // contains critical extensions that the endpoint does not understand.
+            return Err(ParseError::UnknownCriticalExtension)
+        }
+    }
+
+    let (public_key, signature) = libp2p_extension.ok_or(ParseError::MissingExtension)?;
+
+    // The signature is over the concatenation of the prefix and the
+    // DER-encoded SubjectPublicKeyInfo of the certificate.
+    let mut msg = Vec::with_capacity(P2P_SIGNING_PREFIX.len() + certificate.public_key().raw.len());
+    msg.extend(P2P_SIGNING_PREFIX.iter());
+    msg.extend(certificate.public_key().raw);
+
+    if !public_key.verify(&msg, &signature) {
+        return Err(ParseError::InvalidExtensionSignature)
+    }
+
+    Ok(P2pCertificate { certificate, public_key })
+}
+
+/// Decode the DER-encoded `SignedKey` of the libp2p extension:
+///
+/// ```text
+/// SignedKey ::= SEQUENCE {
+///    publicKey OCTET STRING,
+///    signature OCTET STRING
+/// }
+/// ```
+fn parse_libp2p_extension(der: &[u8]) -> Result<(identity::PublicKey, Vec<u8>), ParseError> {
+    let (public_key, signature) = yasna::decode_der::<(Vec<u8>, Vec<u8>)>(der)
+        .map_err(|_| ParseError::MalformedExtension)?;
+    let public_key = identity::PublicKey::from_protobuf_encoding(&public_key)
+        .map_err(|_| ParseError::MalformedExtension)?;
+    Ok((public_key, signature))
+}
+
+/// The message signed with the identity key to bind `certificate_keypair` to it.
+fn signed_message(certificate_keypair: &rcgen::KeyPair) -> Vec<u8> {
+    let mut msg = vec![];
+    msg.extend(P2P_SIGNING_PREFIX.iter());
+    msg.extend(certificate_keypair.public_key_der());
+    msg
+}
+
+/// Create the libp2p Public Key Extension carrying the identity public key and
+/// its `signature` over the [`signed_message`] of the certificate keypair.
+fn make_libp2p_extension(
+    identity_public: identity::PublicKey,
+    signature: Vec<u8>,
+) -> rcgen::CustomExtension {
+    let extension_content = {
+        let serialized_pubkey = identity_public.into_protobuf_encoding();
+        yasna::encode_der(&(serialized_pubkey, signature))
+    };
+
+    // This extension MAY be marked critical.
+    let mut ext = rcgen::CustomExtension::from_oid_content(&P2P_EXT_OID, extension_content);
+    ext.set_criticality(true);
+
+    ext
+}
+
+/// Error while generating a certificate.
+#[derive(Debug, thiserror::Error)]
+pub enum GenError {
+    #[error("Failed to generate certificate: {0}")]
+    Certificate(#[from] rcgen::Error),
+    #[error("Failed to sign certificate key with the identity key: {0}")]
+    Signing(String),
+}
+
+/// Error while parsing a certificate.
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+    #[error("Malformed certificate: {0}")]
+    Malformed(String),
+    #[error("Certificate is not valid at the current time")]
+    Expired,
+    #[error("Certificate is not correctly self-signed")]
+    InvalidSelfSignature,
+    #[error("Certificate contains the libp2p extension more than once")]
+    DuplicateExtension,
+    #[error("Certificate contains an unknown critical extension")]
+    UnknownCriticalExtension,
+    #[error("Certificate does not contain the libp2p extension")]
+    MissingExtension,
+    #[error("Malformed libp2p extension")]
+    MalformedExtension,
+    #[error("Invalid signature in the libp2p extension")]
+    InvalidExtensionSignature,
+}
+
+/// Error while verifying a handshake signature.
+#[derive(Debug, thiserror::Error)]
+pub enum VerificationError {
+    #[error("Unsupported signature scheme: {0:?}")]
+    UnsupportedSchema(rustls::SignatureScheme),
+    #[error("Invalid signature")]
+    InvalidSignature,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanity_check() {
+        let keypair = identity::Keypair::generate_ed25519();
+
+        let (cert, _) = generate(&keypair).unwrap();
+        let parsed_cert = parse(&cert).unwrap();
+
+        assert_eq!(parsed_cert.peer_id(), keypair.public().into_peer_id());
+    }
+
+    #[test]
+    fn secp256k1_identity() {
+        let keypair = identity::Keypair::generate_secp256k1();
+
+        let (cert, _) = generate(&keypair).unwrap();
+        let parsed_cert = parse(&cert).unwrap();
+
+        assert_eq!(parsed_cert.peer_id(), keypair.public().into_peer_id());
+    }
+
+    #[test]
+    fn ecdsa_identity_with_signer() {
+        let keypair = identity::Keypair::generate_ecdsa();
+
+        let (cert, _) = futures::executor::block_on(generate_with_signer(&keypair)).unwrap();
+        let parsed_cert = parse(&cert).unwrap();
+
+        assert_eq!(parsed_cert.peer_id(), keypair.public().into_peer_id());
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! Implementation of the [libp2p TLS handshake](https://github.com/libp2p/specs/blob/master/tls/tls.md).
+//!
+//! Each side generates a self-signed X.509 certificate binding its libp2p identity key, through
+//! a custom certificate extension, to the key used for the TLS 1.3 handshake. The certificate of
+//! the remote is verified the same way, which yields its authenticated `PeerId`.
+//!
+//! [`TlsConfig`] is a connection upgrade for stream-oriented transports such as TCP, while
+//! [`make_client_config`] and [`make_server_config`] provide the `rustls` configurations for
+//! transports performing the TLS handshake themselves, such as QUIC.
+//!
+//! ```
+//! use libp2p_core::identity;
+//! use libp2p_tls::TlsConfig;
+//!
+//! let keypair = identity::Keypair::generate_ed25519();
+//! let tls = TlsConfig::new(&keypair).unwrap();
+//! ```
+
+pub mod certificate;
+mod upgrade;
+mod verifier;
+
+pub use upgrade::{TlsConfig, TlsError};
+pub use futures_rustls::TlsStream;
+
+use libp2p_core::{PeerId, identity};
+use rustls::pki_types::CertificateDer;
+use std::sync::Arc;
+
+/// The ALPN protocol identifier of libp2p connections secured with TLS.
+const P2P_ALPN: [u8; 6] = *b"libp2p";
+
+const PROTOCOL_VERSIONS: &[&rustls::SupportedProtocolVersion] = &[&rustls::version::TLS13];
+
+/// Create a TLS client configuration for libp2p.
+pub fn make_client_config(
+    keypair: &identity::Keypair,
+) -> Result<rustls::ClientConfig, certificate::GenError> {
+    Ok(client_config(make_cert_resolver(certificate::generate(keypair)?)))
+}
+
+fn client_config(cert_resolver: Arc<rustls::sign::SingleCertAndKey>) -> rustls::ClientConfig {
+    let mut crypto = rustls::ClientConfig::builder_with_provider(provider())
+        .with_protocol_versions(PROTOCOL_VERSIONS)
+        .expect("Cipher suites and kx groups are configured; qed")
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier::Libp2pCertificateVerifier::new()))
+        .with_client_cert_resolver(cert_resolver);
+    crypto.alpn_protocols = vec![P2P_ALPN.to_vec()];
+
+    crypto
+}
+
+/// Create a TLS server configuration for libp2p.
+pub fn make_server_config(
+    keypair: &identity::Keypair,
+) -> Result<rustls::ServerConfig, certificate::GenError> {
+    Ok(server_config(make_cert_resolver(certificate::generate(keypair)?)))
+}
+
+fn server_config(cert_resolver: Arc<rustls::sign::SingleCertAndKey>) -> rustls::ServerConfig {
+    let mut crypto = rustls::ServerConfig::builder_with_provider(provider())
+        .with_protocol_versions(PROTOCOL_VERSIONS)
+        .expect("Cipher suites and kx groups are configured; qed")
+        .with_client_cert_verifier(Arc::new(verifier::Libp2pCertificateVerifier::new()))
+        .with_cert_resolver(cert_resolver);
+    crypto.alpn_protocols = vec![P2P_ALPN.to_vec()];
+
+    crypto
+}
+
+/// Extract the [`PeerId`] of the remote from the certificates it presented
+/// during a TLS handshake performed with one of the configurations above.
+pub fn peer_id_from_certificates(certificates: &[CertificateDer<'_>]) -> Option<PeerId> {
+    let end_entity = certificates.first()?;
+    // The certificate was already verified during the handshake.
+    certificate::parse(end_entity).ok().map(|c| c.peer_id())
+}
+
+/// Always present the given certificate, as generated by [`certificate::generate`], to the remote.
+///
+/// `rustls` refuses certificates with unknown critical extensions when they are
+/// configured via `with_single_cert`, hence the certified key is built manually.
+fn make_cert_resolver(
+    (certificate, private_key): (CertificateDer<'static>, rustls::pki_types::PrivateKeyDer<'static>),
+) -> Arc<rustls::sign::SingleCertAndKey> {
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&private_key)
+        .expect("The certificate key is an ECDSA P-256 key in PKCS#8 format; qed");
+    let certified_key = rustls::sign::CertifiedKey::new(vec![certificate], signing_key);
+    Arc::new(certified_key.into())
+}
+
+fn provider() -> Arc<rustls::crypto::CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
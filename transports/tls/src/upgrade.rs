@@ -0,0 +1,121 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+//! The TLS connection upgrade.
+
+use crate::{certificate, client_config, make_cert_resolver, make_client_config, make_server_config, peer_id_from_certificates, server_config};
+use futures::{future::BoxFuture, prelude::*};
+use futures_rustls::{TlsAcceptor, TlsConnector, TlsStream};
+use libp2p_core::{PeerId, identity, upgrade::{UpgradeInfo, InboundUpgrade, OutboundUpgrade}};
+use rustls::pki_types::ServerName;
+use std::{convert::TryFrom, io, iter, sync::Arc};
+
+/// Upgrade that secures a connection with TLS 1.3 and authenticates the remote by its
+/// libp2p identity.
+///
+/// On success, the upgrade yields the [`PeerId`] of the remote together with the
+/// encrypted stream.
+#[derive(Clone)]
+pub struct TlsConfig {
+    client: Arc<rustls::ClientConfig>,
+    server: Arc<rustls::ServerConfig>,
+}
+
+impl TlsConfig {
+    /// Creates a new configuration, generating a certificate for `keypair`.
+    pub fn new(keypair: &identity::Keypair) -> Result<Self, certificate::GenError> {
+        Ok(TlsConfig {
+            client: Arc::new(make_client_config(keypair)?),
+            server: Arc::new(make_server_config(keypair)?),
+        })
+    }
+
+    /// Creates a new configuration, generating a certificate whose libp2p
+    /// extension is signed by `signer`, e.g. one backed by a hardware security module.
+    pub async fn with_signer(signer: &dyn identity::Signer) -> Result<Self, certificate::GenError> {
+        let cert_resolver = make_cert_resolver(certificate::generate_with_signer(signer).await?);
+        Ok(TlsConfig {
+            client: Arc::new(client_config(cert_resolver.clone())),
+            server: Arc::new(server_config(cert_resolver)),
+        })
+    }
+}
+
+impl UpgradeInfo for TlsConfig {
+    type Info = &'static [u8];
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(b"/tls/1.0.0")
+    }
+}
+
+impl<C> InboundUpgrade<C> for TlsConfig
+where
+    C: AsyncRead + AsyncWrite + Send + Unpin + 'static
+{
+    type Output = (PeerId, TlsStream<C>);
+    type Error = TlsError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, socket: C, _: Self::Info) -> Self::Future {
+        async move {
+            let stream = TlsAcceptor::from(self.server).accept(socket).await?;
+            let peer_id = stream.get_ref().1.peer_certificates()
+                .and_then(peer_id_from_certificates)
+                .ok_or(TlsError::UnknownRemotePeer)?;
+            Ok((peer_id, stream.into()))
+        }.boxed()
+    }
+}
+
+impl<C> OutboundUpgrade<C> for TlsConfig
+where
+    C: AsyncRead + AsyncWrite + Send + Unpin + 'static
+{
+    type Output = (PeerId, TlsStream<C>);
+    type Error = TlsError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, socket: C, _: Self::Info) -> Self::Future {
+        async move {
+            // The server name is not used by libp2p, the remote is authenticated through
+            // its certificate extension instead.
+            let name = ServerName::try_from("l").expect("\"l\" is a valid DNS name; qed");
+            let stream = TlsConnector::from(self.client).connect(name, socket).await?;
+            let peer_id = stream.get_ref().1.peer_certificates()
+                .and_then(peer_id_from_certificates)
+                .ok_or(TlsError::UnknownRemotePeer)?;
+            Ok((peer_id, stream.into()))
+        }.boxed()
+    }
+}
+
+/// Error that can happen during the TLS upgrade.
+#[derive(Debug, thiserror::Error)]
+pub enum TlsError {
+    /// The TLS handshake failed, including because the certificate of the remote is invalid.
+    #[error("TLS handshake failed: {0}")]
+    Io(#[from] io::Error),
+    /// The remote did not present a libp2p certificate from which
+    /// its `PeerId` could be derived.
+    #[error("The remote did not authenticate with a libp2p certificate")]
+    UnknownRemotePeer,
+}
@@ -0,0 +1,124 @@
+// Copyright 2020 Parity Technologies (UK) Ltd.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a
+// copy of this software and associated documentation files (the "Software"),
+// to deal in the Software without restriction, including without limitation
+// the rights to use, copy, modify, merge, publish, distribute, sublicense,
+// and/or sell copies of the Software, and to permit persons to whom the
+// Software is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS
+// OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+// FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+// DEALINGS IN THE SOFTWARE.
+
+use futures::prelude::*;
+use libp2p_core::{
+    Transport,
+    identity,
+    multiaddr::multiaddr,
+    transport::ListenerEvent,
+    upgrade::{self, InboundUpgrade, OutboundUpgrade},
+};
+use libp2p_tcp::TcpConfig;
+use libp2p_tls::TlsConfig;
+
+#[test]
+fn tcp_handshake_authenticates_both_sides() {
+    let server_key = identity::Keypair::generate_ed25519();
+    let client_key = identity::Keypair::generate_secp256k1();
+    let server_id = server_key.public().into_peer_id();
+    let client_id = client_key.public().into_peer_id();
+
+    let server_config = TlsConfig::new(&server_key).unwrap();
+    let client_config = TlsConfig::new(&client_key).unwrap();
+
+    let mut listener = TcpConfig::new()
+        .listen_on(multiaddr![Ip4([127, 0, 0, 1]), Tcp(0u16)])
+        .unwrap();
+
+    async_std::task::block_on(async move {
+        let addr = match listener.next().await.unwrap().unwrap() {
+            ListenerEvent::NewAddress(addr) => addr,
+            _ => panic!("Expected a new listen address"),
+        };
+
+        let server = async_std::task::spawn(async move {
+            let (upgrade, _) = listener.next().await.unwrap().unwrap().into_upgrade().unwrap();
+            let socket = upgrade.await.unwrap();
+            let (peer_id, mut stream) = server_config
+                .upgrade_inbound(socket, b"/tls/1.0.0")
+                .await
+                .unwrap();
+            assert_eq!(peer_id, client_id);
+
+            let mut buf = [0; 5];
+            stream.read_exact(&mut buf).await.unwrap();
+            stream.write_all(&buf).await.unwrap();
+            stream.flush().await.unwrap();
+        });
+
+        let socket = TcpConfig::new().dial(addr).unwrap().await.unwrap();
+        let (peer_id, mut stream) = client_config
+            .upgrade_outbound(socket, b"/tls/1.0.0")
+            .await
+            .unwrap();
+        assert_eq!(peer_id, server_id);
+
+        stream.write_all(b"hello").await.unwrap();
+        stream.flush().await.unwrap();
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        server.await;
+    });
+}
+
+#[test]
+fn negotiated_through_multistream_select() {
+    let server_key = identity::Keypair::generate_ed25519();
+    let client_key = identity::Keypair::generate_ed25519();
+    let server_id = server_key.public().into_peer_id();
+    let client_id = client_key.public().into_peer_id();
+
+    let server_config = TlsConfig::new(&server_key).unwrap();
+    let client_config = TlsConfig::new(&client_key).unwrap();
+
+    let mut listener = TcpConfig::new()
+        .listen_on(multiaddr![Ip4([127, 0, 0, 1]), Tcp(0u16)])
+        .unwrap();
+
+    async_std::task::block_on(async move {
+        let addr = match listener.next().await.unwrap().unwrap() {
+            ListenerEvent::NewAddress(addr) => addr,
+            _ => panic!("Expected a new listen address"),
+        };
+
+        let server = async_std::task::spawn(async move {
+            let (upgrade, _) = listener.next().await.unwrap().unwrap().into_upgrade().unwrap();
+            let socket = upgrade.await.unwrap();
+            let (peer_id, mut stream) = upgrade::apply_inbound(socket, server_config).await.unwrap();
+            assert_eq!(peer_id, client_id);
+            stream.write_all(b"ping").await.unwrap();
+            stream.flush().await.unwrap();
+        });
+
+        let socket = TcpConfig::new().dial(addr).unwrap().await.unwrap();
+        let (peer_id, mut stream) = upgrade::apply_outbound(socket, client_config, upgrade::Version::V1)
+            .await
+            .unwrap();
+        assert_eq!(peer_id, server_id);
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        server.await;
+    });
+}
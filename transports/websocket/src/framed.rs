@@ -262,6 +262,20 @@ where
 
         Ok(Box::pin(future))
     }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        let mut inner_listen = listen.clone();
+        let proto = match inner_listen.pop()? {
+            p@Protocol::Ws(_) | p@Protocol::Wss(_) => p,
+            _ => return None
+        };
+        let mut inner_observed = observed.clone();
+        match inner_observed.pop()? {
+            Protocol::Ws(_) | Protocol::Wss(_) => {}
+            _ => return None
+        }
+        self.transport.address_translation(&inner_listen, &inner_observed).map(|a| a.with(proto))
+    }
 }
 
 impl<T> WsConfig<T>
@@ -113,6 +113,15 @@ impl Builder {
         Ok(self)
     }
 
+    /// Remove all trust anchors, including the default web PKI roots.
+    ///
+    /// Combined with [`Builder::add_trust`] this allows dialing `/wss`
+    /// addresses using a custom root store only.
+    pub fn clear_trust(&mut self) -> &mut Self {
+        self.client.root_store = rustls::RootCertStore::empty();
+        self
+    }
+
     /// Finish configuration.
     pub fn finish(self) -> Config {
         Config {